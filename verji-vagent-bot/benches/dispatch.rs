@@ -0,0 +1,70 @@
+//! Dispatching synthetic messages through a three-responder chain
+//!
+//! ```sh
+//! cargo bench --features testing --bench dispatch
+//! ```
+//!
+//! Measures the pipeline itself (middleware-free manager, `should_handle`
+//! checks, context clones), not the agent: most messages match no responder,
+//! as in a busy room where the agent is the only thing answering.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::collections::HashMap;
+use std::sync::Arc;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::{EchoResponder, PingPongResponder, ShortcutResponder};
+use verji_vagent_bot::testing::ResponderTestHarness;
+
+const MESSAGES: usize = 10_000;
+
+/// Mostly chatter, with some commands and shortcuts mixed in
+fn synthetic_messages() -> Vec<Arc<str>> {
+    (0..MESSAGES)
+        .map(|i| match i % 10 {
+            0 => "!ping".into(),
+            1 => format!("!echo message number {}", i).into(),
+            2 => format!("/SQL select {} from events", i).into(),
+            _ => format!(
+                "Message {} about the quarterly report, and whether the numbers in \
+                 the second table still match what finance sent over last week",
+                i
+            )
+            .into(),
+        })
+        .collect()
+}
+
+fn dispatch(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    // The harness owns the store directory, so it lives as long as the benchmark
+    let (manager, _harness, base) = runtime.block_on(async {
+        let manager = ResponderManager::new();
+        manager.register(Arc::new(PingPongResponder::new()));
+        manager.register(Arc::new(EchoResponder::new()));
+        manager.register(Arc::new(ShortcutResponder::new(HashMap::from([(
+            "/sql".to_string(),
+            "Write a SQL query for: {}".to_string(),
+        )]))));
+        let harness = ResponderTestHarness::new().expect("harness");
+        let base = harness.context("").await.expect("context");
+        (manager, harness, base)
+    });
+    let messages = synthetic_messages();
+
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+    group.sample_size(10);
+    group.bench_function("10k messages, three responders", |b| {
+        b.to_async(&runtime).iter(|| async {
+            for body in &messages {
+                let mut context = base.clone();
+                context.message_body = Arc::clone(body);
+                black_box(manager.dispatch(&context).await.expect("dispatch"));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
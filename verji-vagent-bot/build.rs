@@ -0,0 +1,32 @@
+use std::process::Command;
+
+fn main() {
+    // Commit shown in the startup summary; GIT_COMMIT overrides it for builds
+    // without a checkout (e.g. Docker contexts without .git)
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.is_empty())
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+                .map(|commit| commit.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=VAGENT_GIT_COMMIT={}", commit);
+
+    // The gRPC client is optional; plain builds don't need protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/vagent_graph.proto");
+        tonic_build::configure()
+            .build_server(false)
+            .compile_protos(&["proto/vagent_graph.proto"], &["proto"])
+            .expect("Failed to compile proto/vagent_graph.proto");
+    }
+}
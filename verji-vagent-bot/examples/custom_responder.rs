@@ -0,0 +1,63 @@
+//! Runs the bot with an extra responder, the way an embedding service would
+//!
+//! ```sh
+//! cargo run --example custom_responder
+//! ```
+//!
+//! Configuration is read from the environment (and `.env`) like the binary's.
+//! Send `!roll` or `!roll 20` in a room with the bot.
+
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use verji_vagent_bot::{
+    async_trait, BotBuilder, BotConfig, Responder, ResponderContext, ResponderResult,
+};
+
+/// Rolls a die with the given number of sides (`!roll [sides]`)
+struct DiceResponder;
+
+#[async_trait]
+impl Responder for DiceResponder {
+    fn name(&self) -> &str {
+        "DiceResponder"
+    }
+
+    fn priority(&self) -> i32 {
+        90
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        context.message_body.trim_start().starts_with("!roll")
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let sides = context
+            .message_body
+            .split_whitespace()
+            .nth(1)
+            .and_then(|sides| sides.parse::<u64>().ok())
+            .filter(|sides| *sides > 0)
+            .unwrap_or(6);
+        // Good enough for a demo; not a source of randomness for anything else
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos() as u64;
+        Ok(ResponderResult::Handled(Some(format!(
+            "🎲 {} (d{})",
+            nanos % sides + 1,
+            sides
+        ))))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter("verji_vagent_bot=info")
+        .init();
+    dotenvy::dotenv().ok();
+
+    BotBuilder::new(BotConfig::from_env())
+        .responder(Arc::new(DiceResponder))
+        .run()
+        .await
+}
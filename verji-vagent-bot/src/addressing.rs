@@ -0,0 +1,82 @@
+//! Whether a message is addressed to the bot
+//!
+//! In rooms with reply mode `mentions` the bot only answers messages meant
+//! for it: commands, mentions, direct chats, answers to its pending
+//! questions, replies to its messages and messages in threads started off
+//! one of them. The last two count as implicit mentions.
+
+use matrix_sdk::ruma::EventId;
+use tracing::warn;
+
+use crate::room::RoomHandle;
+use crate::room_config::ReplyMode;
+use crate::sent_events::SentEventRegistry;
+
+/// What is known about a message when deciding whether to answer it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Addressing {
+    pub command: bool,
+    /// Names the bot or mentions it in `m.mentions`
+    pub mentioned: bool,
+    /// Room with only the sender and the bot
+    pub direct_chat: bool,
+    /// The sender owes the bot an answer (pending HITL question)
+    pub awaiting_answer: bool,
+    /// Reply to one of the bot's messages
+    pub reply_to_bot: bool,
+    /// In a thread whose root the bot sent
+    pub in_bot_thread: bool,
+}
+
+impl Addressing {
+    /// Mentioned outright, or implicitly by replying in the bot's conversation
+    pub fn is_mention(&self) -> bool {
+        self.mentioned || self.reply_to_bot || self.in_bot_thread
+    }
+
+    /// Whether responders should see the message in a room with `mode`
+    pub fn should_process(&self, mode: ReplyMode) -> bool {
+        match mode {
+            ReplyMode::All => true,
+            ReplyMode::Mentions => {
+                self.command || self.is_mention() || self.direct_chat || self.awaiting_answer
+            }
+        }
+    }
+}
+
+/// Whether the body names the bot, by user ID or as "vagent"
+pub fn mentions_by_name(body: &str, bot_user_id: &str) -> bool {
+    (!bot_user_id.is_empty() && body.contains(bot_user_id)) || contains_ignore_ascii_case(body, "vagent")
+}
+
+/// `haystack.to_lowercase().contains(needle)` for a lowercase ASCII needle, without the copy
+fn contains_ignore_ascii_case(haystack: &str, needle: &str) -> bool {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Whether the bot sent `event_id`
+///
+/// Asks the sent event registry first; events older than its retention are
+/// fetched from the homeserver and their sender checked.
+pub async fn sent_by_bot(
+    room: &dyn RoomHandle,
+    sent_events: &SentEventRegistry,
+    event_id: &EventId,
+    bot_user_id: &str,
+) -> bool {
+    if sent_events.lookup(event_id).await.is_some() {
+        return true;
+    }
+    match room.fetch_event(event_id).await {
+        Ok(Some(entry)) => entry.sender == bot_user_id,
+        Ok(None) => false,
+        Err(e) => {
+            warn!("Failed to fetch {} to check its sender: {:#}", event_id, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Posting operational notices to `ADMIN_ROOM`
+
+use matrix_sdk::{ruma::events::room::message::RoomMessageEventContent, Client};
+use tracing::warn;
+
+use crate::runtime::Runtime;
+use crate::shadow::RoomWrite;
+
+/// Post `markdown` as a notice to `admin_room`; returns whether it was sent
+///
+/// `what` names the notice in logs. Failures are only logged, since notices
+/// are often about the very thing that is broken.
+pub async fn post(
+    runtime: &Runtime,
+    client: &Client,
+    admin_room: &str,
+    what: &str,
+    markdown: &str,
+) -> bool {
+    let room = runtime
+        .aliases
+        .resolver(client)
+        .resolve_room(admin_room)
+        .await
+        .ok()
+        .and_then(|room_id| client.get_room(&room_id));
+    let Some(room) = room else {
+        warn!("ADMIN_ROOM {} is not a joined room", admin_room);
+        return false;
+    };
+    let write = RoomWrite::new(room.room_id(), "message").body(markdown);
+    if runtime.shadow.suppress(write).is_some() {
+        return true;
+    }
+    let content = RoomMessageEventContent::notice_markdown(markdown);
+    match runtime
+        .pacer
+        .send(what, || room.send(content.clone()))
+        .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Failed to post the {} to {}: {}", what, admin_room, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Requests to vagent-graph, for everything in the bot that calls it
+//!
+//! [`AgentService`] owns the transport connection: it connects lazily, pauses
+//! reconnecting for `GRAPH_RECONNECT_COOLDOWN_SECS` after a failed attempt
+//! (the circuit breaker), keeps the connection alive and bounds every request
+//! by `AGENT_TIMEOUT_SECS`. [`AgentService::ask`] turns a request into a
+//! stream of [`AgentEvent`]s; what to do with them (relaying progress to a
+//! room, caching, phrasing failures) is up to the caller.
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, StreamExt};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::capabilities::Capabilities;
+use crate::config;
+use crate::error::BotError;
+use crate::metrics::Metrics;
+use crate::redis_client::{GraphMessage, GraphMessageType, GraphRequest, RequestKind};
+use crate::runtime::Runtime;
+use crate::transport::{GraphStream, GraphTimeout, GraphTransport, TransportConfig};
+
+/// A keepalive PING slower than this counts as a dead connection
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection attempts are paused for `GRAPH_RECONNECT_COOLDOWN_SECS` after one fails
+#[derive(Debug, thiserror::Error)]
+#[error("Not reconnecting to vagent-graph yet, the last attempt failed")]
+pub struct CircuitOpen;
+
+/// What happened to a request, in order
+#[derive(Debug)]
+pub enum AgentEvent {
+    /// The graph has the request
+    Submitted,
+    /// An intermediate update
+    Progress(GraphMessage),
+    /// The message that ends the request: an answer, a HITL question or a
+    /// graph error. Always the last event.
+    Finished(GraphMessage),
+    /// No answer: connecting, sending or receiving failed, or the timeout ran
+    /// out. Always the last event.
+    Failed(anyhow::Error),
+}
+
+/// How one request is asked
+#[derive(Debug, Clone, Default)]
+pub struct AskOptions {
+    /// Instead of `AGENT_TIMEOUT_SECS`
+    pub timeout: Option<Duration>,
+}
+
+type TransportSlot = Arc<Mutex<Option<Box<dyn GraphTransport>>>>;
+
+/// The shared connection to vagent-graph
+pub struct AgentService {
+    inner: Arc<Connection>,
+    timeout: Duration,
+    metrics: Arc<Metrics>,
+    /// Added to every request (see `capabilities`)
+    capabilities: Arc<Capabilities>,
+}
+
+struct Connection {
+    transport: TransportSlot,
+    config: TransportConfig,
+    /// Pause after a failed connection attempt (zero = retry on every request)
+    reconnect_cooldown: Duration,
+    /// No connection attempts before this time
+    reconnect_after: std::sync::Mutex<Option<Instant>>,
+}
+
+impl AgentService {
+    /// Talk to vagent-graph through `transport_config` (e.g. a mock in tests)
+    pub fn new(transport_config: TransportConfig) -> Self {
+        Self {
+            inner: Arc::new(Connection {
+                transport: Arc::new(Mutex::new(None)),
+                config: transport_config,
+                reconnect_cooldown: Duration::from_secs(config::env_u64(
+                    "GRAPH_RECONNECT_COOLDOWN_SECS",
+                    10,
+                )),
+                reconnect_after: std::sync::Mutex::new(None),
+            }),
+            timeout: Duration::from_secs(config::env_u64("AGENT_TIMEOUT_SECS", 60)),
+            metrics: Arc::default(),
+            capabilities: Arc::default(),
+        }
+    }
+
+    /// Record metrics in, and send the capabilities of, the bot's runtime
+    pub fn with_runtime(mut self, runtime: &Runtime) -> Self {
+        self.metrics = Arc::clone(&runtime.metrics);
+        self.capabilities = Arc::clone(&runtime.capabilities);
+        self
+    }
+
+    /// Where requests are counted and timed
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(
+            TransportConfig::from_env().context(BotError::Config)?,
+        ))
+    }
+
+    /// How long a request may take (`AGENT_TIMEOUT_SECS`)
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Connect if not connected yet; fails with [`CircuitOpen`] during the cooldown
+    pub async fn connect(&self) -> Result<()> {
+        self.inner.ensure_connected().await
+    }
+
+    /// Send `request` and follow it to its end
+    ///
+    /// The stream ends after [`AgentEvent::Finished`] or [`AgentEvent::Failed`].
+    /// The connection is only held while the request is submitted, so answers
+    /// to several requests stream at the same time and cancels aren't held up
+    /// by them; dropping the stream gives the request up. Messages are read
+    /// from the transport only as the stream is polled, so a slow consumer
+    /// holds the graph's messages back instead of piling them up here; the
+    /// timeout keeps running meanwhile.
+    pub fn ask(
+        &self,
+        request: GraphRequest,
+        options: AskOptions,
+    ) -> BoxStream<'static, AgentEvent> {
+        let timeout = options.timeout.unwrap_or(self.timeout);
+        let request = self.with_capabilities(request);
+        let connection = Arc::clone(&self.inner);
+        let metrics = Arc::clone(&self.metrics);
+        let start: BoxFuture<'static, Result<(Active, &'static str)>> = Box::pin(async move {
+            connection.ensure_connected().await?;
+            let mut transport = connection.transport.lock().await;
+            let client = transport.as_mut().context("Graph connection was dropped")?;
+            let name = client.name();
+            let stream = client.submit(&request, timeout).await?;
+            Ok((
+                Active {
+                    stream,
+                    deadline: tokio::time::Instant::now() + timeout,
+                    metrics,
+                },
+                name,
+            ))
+        });
+
+        let started = Instant::now();
+        futures::stream::unfold(Ask::Starting(start), move |state| async move {
+            let (event, next) = match state {
+                Ask::Starting(start) => match start.await {
+                    Ok((active, name)) => (AgentEvent::Submitted, Ask::Active(active, name)),
+                    Err(e) => (AgentEvent::Failed(e), Ask::Done),
+                },
+                Ask::Active(mut active, name) => match active.next().await {
+                    Ok(message) if message.message_type == GraphMessageType::Progress => {
+                        info!("📊 Progress: {}", message.content);
+                        (AgentEvent::Progress(message), Ask::Active(active, name))
+                    }
+                    Ok(message) => {
+                        observe(&active.metrics, name, "finished", started);
+                        (AgentEvent::Finished(message), Ask::Done)
+                    }
+                    Err(e) => {
+                        observe(&active.metrics, name, "failed", started);
+                        let e = e.context("Failed to get response from vagent-graph");
+                        (AgentEvent::Failed(e), Ask::Done)
+                    }
+                },
+                Ask::Done => return None,
+            };
+            Some((event, next))
+        })
+        .boxed()
+    }
+
+    /// Send a one-off request (e.g. a command) and wait for its final message
+    ///
+    /// Progress updates are dropped.
+    pub async fn run_command(&self, request: GraphRequest) -> Result<GraphMessage> {
+        let mut events = self.ask(request, AskOptions::default());
+        while let Some(event) = events.next().await {
+            match event {
+                AgentEvent::Submitted | AgentEvent::Progress(_) => {}
+                AgentEvent::Finished(message) => return Ok(message),
+                AgentEvent::Failed(e) => return Err(e),
+            }
+        }
+        bail!("The request ended without an answer")
+    }
+
+    /// Send a request vagent-graph sends nothing back for
+    pub async fn publish(&self, request: &GraphRequest) -> Result<()> {
+        self.inner.ensure_connected().await?;
+
+        let request = self.with_capabilities(request.clone());
+        let mut transport = self.inner.transport.lock().await;
+        let client = transport.as_mut().context("Graph connection was dropped")?;
+        client.cancel(&request).await
+    }
+
+    /// Tell vagent-graph to abandon a paused HITL execution
+    pub async fn cancel_hitl(&self, request_id: &str, room_id: &str, user_id: &str) -> Result<()> {
+        let request = GraphRequest::new(
+            RequestKind::HitlCancel,
+            request_id.to_string(),
+            String::new(),
+            room_id.to_string(),
+            user_id.to_string(),
+        );
+        self.publish(&request).await
+    }
+
+    /// Tell vagent-graph to stop working on a query nobody will receive the answer to
+    pub async fn cancel(&self, request: &GraphRequest) -> Result<()> {
+        let cancel = GraphRequest::new(
+            RequestKind::Cancel,
+            request.request_id.clone(),
+            String::new(),
+            request.metadata.room_id.clone(),
+            request.metadata.user_id.clone(),
+        );
+        self.publish(&cancel).await
+    }
+
+    /// Tell vagent-graph to purge the session memory of a user's sessions
+    pub async fn notify_erasure(&self, user_id: &str, rooms: &[String]) -> Result<()> {
+        let payload = serde_json::json!({ "user_id": user_id, "rooms": rooms });
+        let request =
+            GraphRequest::command("erase_user", payload, String::new(), user_id.to_string());
+        let response = self.run_command(request).await?;
+        if response.message_type == GraphMessageType::Error {
+            bail!("vagent-graph rejected the erasure: {}", response.content);
+        }
+        Ok(())
+    }
+
+    /// Connect if needed and ping vagent-graph once, for status reports
+    ///
+    /// Returns the transport name and the ping's round trip.
+    pub async fn check_connection(&self) -> Result<(&'static str, Duration)> {
+        self.inner.ensure_connected().await?;
+
+        let mut transport = self.inner.transport.lock().await;
+        let client = transport.as_mut().context("Graph connection was dropped")?;
+        let name = client.name();
+        let latency = tokio::time::timeout(KEEPALIVE_TIMEOUT, client.ping())
+            .await
+            .with_context(|| format!("{} ping timed out", name))??;
+        Ok((name, latency))
+    }
+
+    /// Probe the graph connection every `interval` so dead connections are
+    /// replaced before a user query runs into them
+    ///
+    /// Probes are skipped while a request is being submitted (the connection
+    /// is evidently in use). With Redis, pubsub connections are opened per
+    /// request, so only the command connection needs keeping alive. Abort the
+    /// returned handle on shutdown.
+    pub fn spawn_keepalive(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let connection = Arc::clone(&self.inner);
+        let metrics = Arc::clone(&self.metrics);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await; // First tick fires immediately
+            loop {
+                ticker.tick().await;
+                connection.probe(&metrics).await;
+            }
+        })
+    }
+
+    /// `request` with the bot's capabilities, unless it carries its own
+    fn with_capabilities(&self, mut request: GraphRequest) -> GraphRequest {
+        if request.bot_capabilities.is_none() {
+            request.bot_capabilities = self.capabilities.get().cloned();
+        }
+        request
+    }
+}
+
+impl Connection {
+    /// Ensure the graph transport is connected (lazy initialization)
+    async fn ensure_connected(&self) -> Result<()> {
+        let mut transport = self.transport.lock().await;
+
+        if transport.is_none() {
+            let paused = self
+                .reconnect_after
+                .lock()
+                .unwrap()
+                .is_some_and(|after| Instant::now() < after);
+            if paused {
+                return Err(CircuitOpen.into());
+            }
+            info!("Initializing connection to vagent-graph");
+            match self.config.connect().await {
+                Ok(client) => {
+                    info!("✅ Connected to vagent-graph via {}", client.name());
+                    *transport = Some(client);
+                    *self.reconnect_after.lock().unwrap() = None;
+                }
+                Err(e) => {
+                    warn!("Failed to connect to vagent-graph: {:#}", e);
+                    if !self.reconnect_cooldown.is_zero() {
+                        *self.reconnect_after.lock().unwrap() =
+                            Some(Instant::now() + self.reconnect_cooldown);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn probe(&self, metrics: &Metrics) {
+        let Ok(mut transport) = self.transport.try_lock() else {
+            return;
+        };
+        let Some(client) = transport.as_mut() else {
+            // Never connected or dropped by a failed probe; connect now
+            drop(transport);
+            let _ = self.ensure_connected().await;
+            return;
+        };
+
+        let name = client.name();
+        match tokio::time::timeout(KEEPALIVE_TIMEOUT, client.ping()).await {
+            Ok(Ok(latency)) => {
+                metrics.observe_ms(
+                    "graph_ping_ms",
+                    &[("transport", name)],
+                    latency.as_millis() as u64,
+                );
+                return;
+            }
+            Ok(Err(e)) => warn!("💔 {} keepalive failed, reconnecting: {:#}", name, e),
+            Err(_) => warn!("💔 {} keepalive timed out, reconnecting", name),
+        }
+        metrics.increment("graph_ping_failures_total", &[("transport", name)]);
+        *transport = None;
+        drop(transport);
+        let _ = self.ensure_connected().await;
+    }
+}
+
+/// Where an `ask` stream is
+enum Ask {
+    Starting(BoxFuture<'static, Result<(Active, &'static str)>>),
+    Active(Active, &'static str),
+    Done,
+}
+
+/// A submitted request, streaming its answer
+struct Active {
+    stream: GraphStream,
+    deadline: tokio::time::Instant,
+    /// Where the request's latency is recorded
+    metrics: Arc<Metrics>,
+}
+
+impl Active {
+    async fn next(&mut self) -> Result<GraphMessage> {
+        match tokio::time::timeout_at(self.deadline, self.stream.next()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => Err(anyhow!("Response stream ended before the final message")),
+            Err(_) => Err(GraphTimeout.into()),
+        }
+    }
+}
+
+fn observe(metrics: &Metrics, transport: &'static str, outcome: &'static str, started: Instant) {
+    metrics.observe_ms(
+        "graph_request_ms",
+        &[("transport", transport), ("outcome", outcome)],
+        started.elapsed().as_millis() as u64,
+    );
+}
@@ -0,0 +1,280 @@
+//! Room alias resolution, cached for every part of the bot that takes aliases
+//!
+//! Room lists in the configuration (`ALLOWED_ROOMS`, `RESPONDER_ROOMS`,
+//! `WEBHOOK_ROOMS`, `SYSTEM_ROOMS`, `ADMIN_ROOM`), webhook targets and the
+//! `send` subcommand accept `#alias:server` as well as room IDs. Resolutions
+//! are kept for `ALIAS_CACHE_TTL_SECS`, unknown aliases for
+//! `ALIAS_NEGATIVE_TTL_SECS`; a room's `m.room.canonical_alias` change drops
+//! what was cached for it. Config lists are resolved together at startup, so
+//! synchronous checks like allowlists only ever read the cache.
+
+use anyhow::{Context, Result};
+use futures::future::{join_all, BoxFuture};
+use matrix_sdk::ruma::api::client::error::ErrorKind;
+use matrix_sdk::ruma::{OwnedRoomAliasId, OwnedRoomId, RoomAliasId, RoomId};
+use matrix_sdk::Client;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::clock::{Clock, SystemClock};
+use crate::config;
+
+/// The alias does not exist (on its server, as far as the homeserver knows)
+#[derive(Debug, thiserror::Error)]
+#[error("Room alias {0} does not exist")]
+pub struct UnknownAlias(pub String);
+
+/// Pause between background refreshes of an expired alias that keep failing
+const REFRESH_RETRY: Duration = Duration::from_secs(60);
+
+/// Asks the homeserver for an alias's room; Ok(None) = no such alias
+type ResolveFn =
+    dyn Fn(OwnedRoomAliasId) -> BoxFuture<'static, Result<Option<OwnedRoomId>>> + Send + Sync;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    /// None = the alias is unknown
+    room_id: Option<OwnedRoomId>,
+    expires_ms: u64,
+}
+
+/// Cached alias -> room ID lookups
+pub struct AliasResolver {
+    resolve: Box<ResolveFn>,
+    ttl: Duration,
+    negative_ttl: Duration,
+    clock: Arc<dyn Clock>,
+    cache: Mutex<HashMap<String, Entry>>,
+}
+
+impl AliasResolver {
+    /// Resolve aliases with `resolve` (e.g. a stub in tests)
+    pub fn new<F, Fut>(resolve: F, ttl: Duration, negative_ttl: Duration) -> Self
+    where
+        F: Fn(OwnedRoomAliasId) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Option<OwnedRoomId>>> + Send + 'static,
+    {
+        Self {
+            resolve: Box::new(move |alias| Box::pin(resolve(alias))),
+            ttl,
+            negative_ttl,
+            clock: Arc::new(SystemClock),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolve through the homeserver, with the TTLs from the environment
+    pub fn for_client(client: Client) -> Self {
+        Self::new(
+            move |alias: OwnedRoomAliasId| {
+                let client = client.clone();
+                async move {
+                    match client.resolve_room_alias(&alias).await {
+                        Ok(response) => Ok(Some(response.room_id)),
+                        Err(e)
+                            if matches!(e.client_api_error_kind(), Some(ErrorKind::NotFound)) =>
+                        {
+                            Ok(None)
+                        }
+                        Err(e) => Err(e).with_context(|| format!("Failed to resolve {}", alias)),
+                    }
+                }
+            },
+            Duration::from_secs(config::env_u64("ALIAS_CACHE_TTL_SECS", 3600)),
+            Duration::from_secs(config::env_u64("ALIAS_NEGATIVE_TTL_SECS", 300)),
+        )
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The alias's room, from the cache while it is fresh
+    ///
+    /// Fails with [`UnknownAlias`] for aliases that don't exist; other
+    /// failures (homeserver unreachable) are not cached.
+    pub async fn resolve(&self, alias: &RoomAliasId) -> Result<OwnedRoomId> {
+        let now = self.clock.now_ms();
+        let cached = self.cache.lock().unwrap().get(alias.as_str()).cloned();
+        let room_id = match cached.filter(|entry| entry.expires_ms > now) {
+            Some(entry) => entry.room_id,
+            None => self.fetch(alias).await?,
+        };
+        room_id.ok_or_else(|| UnknownAlias(alias.to_string()).into())
+    }
+
+    /// Ask the homeserver and cache the answer
+    async fn fetch(&self, alias: &RoomAliasId) -> Result<Option<OwnedRoomId>> {
+        let room_id = (self.resolve)(alias.to_owned()).await?;
+        let ttl = if room_id.is_some() {
+            self.ttl
+        } else {
+            self.negative_ttl
+        };
+        debug!("🏷️ Resolved {} to {:?}", alias, room_id);
+        self.cache.lock().unwrap().insert(
+            alias.to_string(),
+            Entry {
+                room_id: room_id.clone(),
+                expires_ms: self.clock.now_ms() + ttl.as_millis() as u64,
+            },
+        );
+        Ok(room_id)
+    }
+
+    /// A room ID as is, or an alias resolved
+    pub async fn resolve_room(&self, room: &str) -> Result<OwnedRoomId> {
+        if let Ok(room_id) = RoomId::parse(room) {
+            return Ok(room_id);
+        }
+        let alias = RoomAliasId::parse(room)
+            .with_context(|| format!("{} is not a room ID or alias", room))?;
+        self.resolve(&alias).await
+    }
+
+    /// Resolve every alias among `rooms` at once; fails with all that didn't resolve
+    pub async fn resolve_all<'a>(&self, rooms: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let aliases: BTreeSet<&str> = rooms
+            .into_iter()
+            .filter(|room| room.starts_with('#'))
+            .collect();
+        if aliases.is_empty() {
+            return Ok(());
+        }
+
+        let results = join_all(aliases.iter().map(|alias| self.resolve_room(alias))).await;
+        let failures: Vec<String> = aliases
+            .iter()
+            .zip(results)
+            .filter_map(|(alias, result)| result.err().map(|e| format!("{} ({:#})", alias, e)))
+            .collect();
+        info!(
+            "🏷️ Resolved {} of {} configured room aliases",
+            aliases.len() - failures.len(),
+            aliases.len()
+        );
+        if !failures.is_empty() {
+            anyhow::bail!(
+                "{} configured room alias(es) could not be resolved: {}",
+                failures.len(),
+                failures.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The cached room of an alias, without asking the homeserver
+    ///
+    /// Expired entries are still answered and refreshed in the background,
+    /// at most once per `REFRESH_RETRY` while the homeserver doesn't answer.
+    pub fn cached(self: &Arc<Self>, alias: &str) -> Option<OwnedRoomId> {
+        let now = self.clock.now_ms();
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.get_mut(alias)?;
+        if entry.expires_ms <= now {
+            if let (Ok(runtime), Ok(parsed)) = (
+                tokio::runtime::Handle::try_current(),
+                RoomAliasId::parse(alias),
+            ) {
+                entry.expires_ms = now + REFRESH_RETRY.as_millis() as u64;
+                let resolver = Arc::clone(self);
+                runtime.spawn(async move {
+                    if let Err(e) = resolver.fetch(&parsed).await {
+                        warn!("Failed to refresh room alias {}: {:#}", parsed, e);
+                    }
+                });
+            }
+        }
+        entry.room_id.clone()
+    }
+
+    /// Whether a room list entry (room ID or alias) names `room_id`
+    pub fn matches(self: &Arc<Self>, entry: &str, room_id: &str) -> bool {
+        entry == room_id
+            || (entry.starts_with('#')
+                && self
+                    .cached(entry)
+                    .is_some_and(|cached| cached.as_str() == room_id))
+    }
+
+    /// Resolve again what is cached for a room and for its new `aliases`
+    ///
+    /// Called when the room's `m.room.canonical_alias` changes; an alias
+    /// moved to another room or removed stops matching right away.
+    pub async fn on_aliases_changed(&self, room_id: &RoomId, aliases: &[String]) {
+        let stale: Vec<String> = {
+            let mut cache = self.cache.lock().unwrap();
+            let stale: Vec<String> = cache
+                .iter()
+                .filter(|(alias, entry)| {
+                    entry.room_id.as_deref() == Some(room_id) || aliases.contains(alias)
+                })
+                .map(|(alias, _)| alias.clone())
+                .collect();
+            for alias in &stale {
+                cache.remove(alias);
+            }
+            stale
+        };
+        if stale.is_empty() {
+            return;
+        }
+        debug!(
+            "🏷️ Aliases of {} changed, resolving {} again",
+            room_id,
+            stale.len()
+        );
+        for alias in stale {
+            let Ok(alias) = RoomAliasId::parse(&alias) else {
+                continue;
+            };
+            if let Err(e) = self.fetch(&alias).await {
+                warn!("Failed to resolve room alias {} again: {:#}", alias, e);
+            }
+        }
+    }
+}
+
+/// A bot's resolver, created for its client on first use
+///
+/// Until then room list entries only match room IDs.
+#[derive(Default)]
+pub struct Aliases(OnceLock<Arc<AliasResolver>>);
+
+impl Aliases {
+    /// The resolver, created for `client` on first use
+    pub fn resolver(&self, client: &Client) -> Arc<AliasResolver> {
+        Arc::clone(
+            self.0
+                .get_or_init(|| Arc::new(AliasResolver::for_client(client.clone()))),
+        )
+    }
+
+    /// Whether a room list entry names `room_id`; aliases only match once resolved
+    pub fn matches(&self, entry: &str, room_id: &str) -> bool {
+        match self.0.get() {
+            Some(resolver) => resolver.matches(entry, room_id),
+            None => entry == room_id,
+        }
+    }
+}
+
+/// Aliases of a room from its `m.room.canonical_alias` content
+pub fn aliases_in(content: &serde_json::Value) -> Vec<String> {
+    let alias = content.get("alias").and_then(|alias| alias.as_str());
+    let alt_aliases = content
+        .get("alt_aliases")
+        .and_then(|aliases| aliases.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|alias| alias.as_str());
+    alias
+        .into_iter()
+        .chain(alt_aliases)
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,191 @@
+//! Read-only mode for archived rooms
+//!
+//! A room counts as archived when one of the configured markers is present:
+//! a topic starting with `ARCHIVE_TOPIC_PREFIX`, an `ARCHIVE_STATE_EVENT`
+//! state event, or the bot's own `ARCHIVE_TAG` room tag. The bot keeps
+//! observing such rooms but answers nothing, except for one notice the first
+//! time someone mentions it there. Markers are re-read when the topic, the
+//! state event or the bot's tags change, so archiving needs no restart.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::room::RoomHandle;
+use crate::room_config::RoomConfigStore;
+
+pub const TOPIC_EVENT_TYPE: &str = "m.room.topic";
+pub const TAG_EVENT_TYPE: &str = "m.tag";
+
+/// Which markers archive a room; empty settings turn a marker off
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivePolicy {
+    /// Topic prefix, matched case-insensitively (`ARCHIVE_TOPIC_PREFIX`)
+    pub topic_prefix: Option<String>,
+    /// State event type whose presence archives the room (`ARCHIVE_STATE_EVENT`)
+    pub state_event: Option<String>,
+    /// Room tag on the bot's account (`ARCHIVE_TAG`)
+    pub tag: Option<String>,
+}
+
+impl Default for ArchivePolicy {
+    fn default() -> Self {
+        Self {
+            topic_prefix: Some("[ARCHIVED]".to_string()),
+            state_event: Some("no.verji.vagent.archived".to_string()),
+            tag: None,
+        }
+    }
+}
+
+impl ArchivePolicy {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let setting = |name: &str, default: Option<String>| match std::env::var(name) {
+            Ok(value) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+            Err(_) => default,
+        };
+        Self {
+            topic_prefix: setting("ARCHIVE_TOPIC_PREFIX", defaults.topic_prefix),
+            state_event: setting("ARCHIVE_STATE_EVENT", defaults.state_event),
+            tag: setting("ARCHIVE_TAG", defaults.tag),
+        }
+    }
+
+    /// Whether a change to this state event type can archive or unarchive a room
+    pub fn watches_state(&self, event_type: &str) -> bool {
+        (event_type == TOPIC_EVENT_TYPE && self.topic_prefix.is_some())
+            || self.state_event.as_deref() == Some(event_type)
+    }
+}
+
+/// The marker that archived a room
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveTrigger {
+    /// The topic starts with this prefix
+    Topic(String),
+    /// This state event is set
+    StateEvent(String),
+    /// The bot tagged the room with this tag
+    Tag(String),
+}
+
+impl ArchiveTrigger {
+    /// i18n key and argument describing the trigger, for `!roominfo`
+    pub fn describe(&self) -> (&'static str, &str) {
+        match self {
+            ArchiveTrigger::Topic(prefix) => ("archive.trigger_topic", prefix),
+            ArchiveTrigger::StateEvent(event_type) => ("archive.trigger_state", event_type),
+            ArchiveTrigger::Tag(tag) => ("archive.trigger_tag", tag),
+        }
+    }
+}
+
+/// Whether a state event's content counts as set: not empty (redacted) and not `archived: false`
+fn state_marker_set(content: &Value) -> bool {
+    let non_empty = content
+        .as_object()
+        .is_some_and(|content| !content.is_empty());
+    non_empty && content.get("archived").and_then(Value::as_bool) != Some(false)
+}
+
+/// Cached archive state of each room
+pub struct ArchiveWatch {
+    policy: ArchivePolicy,
+    /// Room ID -> trigger (None = not archived)
+    cache: Mutex<HashMap<String, Option<ArchiveTrigger>>>,
+}
+
+impl ArchiveWatch {
+    pub fn new(policy: ArchivePolicy) -> Self {
+        Self {
+            policy,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> &ArchivePolicy {
+        &self.policy
+    }
+
+    /// What archived the room, if it is; read failures count as not archived and are retried
+    pub async fn check(&self, room: &dyn RoomHandle) -> Option<ArchiveTrigger> {
+        let room_id = room.room_id().to_string();
+        if let Some(state) = self.cache.lock().unwrap().get(&room_id) {
+            return state.clone();
+        }
+
+        let state = match self.read(room).await {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to read the archive markers of {}: {:#}", room_id, e);
+                return None;
+            }
+        };
+        self.cache.lock().unwrap().insert(room_id, state.clone());
+        state
+    }
+
+    async fn read(&self, room: &dyn RoomHandle) -> anyhow::Result<Option<ArchiveTrigger>> {
+        if let Some(prefix) = &self.policy.topic_prefix {
+            let topic = room.state_event(TOPIC_EVENT_TYPE, "").await?;
+            let topic = topic
+                .as_ref()
+                .and_then(|content| content.get("topic"))
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            if topic
+                .trim_start()
+                .to_lowercase()
+                .starts_with(&prefix.to_lowercase())
+            {
+                return Ok(Some(ArchiveTrigger::Topic(prefix.clone())));
+            }
+        }
+        if let Some(event_type) = &self.policy.state_event {
+            if room
+                .state_event(event_type, "")
+                .await?
+                .is_some_and(|content| state_marker_set(&content))
+            {
+                return Ok(Some(ArchiveTrigger::StateEvent(event_type.clone())));
+            }
+        }
+        if let Some(tag) = &self.policy.tag {
+            let tags = room.room_account_data(TAG_EVENT_TYPE).await?;
+            if tags
+                .as_ref()
+                .and_then(|tags| tags.get("tags"))
+                .is_some_and(|tags| tags.get(tag).is_some())
+            {
+                return Ok(Some(ArchiveTrigger::Tag(tag.clone())));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Re-read the markers after a relevant change and log when the room's state flips
+    ///
+    /// Unarchiving clears the room's notice flag, so the notice is posted
+    /// again if the room is archived once more.
+    pub async fn on_change(&self, room: &dyn RoomHandle, room_configs: &RoomConfigStore) {
+        let room_id = room.room_id().to_string();
+        let before = self.cache.lock().unwrap().remove(&room_id).flatten();
+        let after = self.check(room).await;
+        match (&before, &after) {
+            (None, Some(trigger)) => info!("🗄️ {} is archived ({:?})", room_id, trigger),
+            (Some(_), None) => info!("🗄️ {} is no longer archived", room_id),
+            _ => return,
+        }
+
+        if after.is_none() && room_configs.get(room).await.archived_notice_sent {
+            if let Err(e) = room_configs
+                .update(room, |config| config.archived_notice_sent = false)
+                .await
+            {
+                warn!("Failed to reset the archive notice of {}: {:#}", room_id, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,1993 @@
+//! Wiring of the Matrix client, the responder pipeline and the sync loop
+//!
+//! [`BotBuilder`] is what the `verji-vagent-bot` binary runs; services that
+//! embed the pipeline use it the same way, adding their own responders.
+
+use anyhow::{Context, Result};
+use matrix_sdk::{
+    config::SyncSettings,
+    room::Room as MatrixRoom,
+    ruma::{
+        events::{
+            reaction::OriginalSyncReactionEvent,
+            room::member::{MembershipState, OriginalSyncRoomMemberEvent},
+            room::message::{MessageType, OriginalSyncRoomMessageEvent, Relation},
+            room::redaction::OriginalSyncRoomRedactionEvent,
+            sticker::OriginalSyncStickerEvent,
+            tag::TagEvent,
+            AnySyncStateEvent, AnySyncTimelineEvent,
+        },
+        serde::Raw,
+        EventId, OwnedEventId,
+    },
+    Client,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::addressing::{self, mentions_by_name, Addressing};
+use crate::agent_service::AgentService;
+use crate::alias::AliasResolver;
+use crate::archive::ArchiveWatch;
+use crate::capabilities::BotCapabilities;
+use crate::coalesce::Coalescer;
+use crate::config::{self, BotConfig};
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::crash::CrashReporter;
+use crate::custom_events::{self, Treatment};
+use crate::decorators::{Cooldown, DailyQuota, IntentFilter, RateLimited, RoomBudget};
+use crate::erasure::Erasure;
+use crate::error::BotError;
+use crate::feedback::FeedbackStore;
+use crate::follow_up::{self, FollowUpScheduler};
+use crate::hitl::{PendingHitl, HITL_SLOT};
+use crate::incoming::{self, Incoming, StickerAction};
+use crate::intent::IntentFilterMode;
+use crate::kill_switch::{self, KillSwitch};
+use crate::maintenance::MaintenanceMode;
+use crate::middlewares::{
+    AccessControlMiddleware, ArchiveMiddleware, KillSwitchMiddleware, MaintenanceMiddleware,
+    RateLimitMiddleware,
+};
+use crate::observers;
+use crate::outbox::Outbox;
+use crate::pager;
+use crate::policy::PolicyList;
+use crate::preferences::PreferenceStore;
+use crate::quota::QuotaStore;
+use crate::replay::{FailedRequests, Replayer};
+use crate::responder::{OutgoingMessage, Responder, ResponderContext};
+use crate::responder_manager::{ErrorPolicy, ResponderManager, TimeoutPolicy};
+use crate::responders::{
+    AdminResponder, AgentSelectResponder, EchoResponder, ExportResponder, HelpResponder,
+    HistoryResponder, PinResponder, PingPongResponder, PrefsResponder, PromptResponder,
+    QuotaResponder, RoomInfoResponder, ShortcutResponder, StatsResponder, StatusResponder,
+    SummaryResponder, VerjiAgentResponder, WhoamiResponder,
+};
+use crate::retraction::ProcessingDelay;
+use crate::room::{BotRoom, RoomHandle};
+use crate::room_config::{ReplyMode, ResponseFormat, RoomConfig, RoomConfigStore};
+use crate::runtime::Runtime;
+use crate::sent_events::{SentEvent, SentEventRegistry, SentKind};
+use crate::stats::UsageStats;
+use crate::still_working::{OutputActivity, StillWorking};
+use crate::system_rooms::{SystemRoomKind, SystemRooms};
+use crate::tenant::TenantResolver;
+use crate::{
+    alias, client, command, crash, dispatcher, encryption, i18n, key_import, mentions,
+    outbound_webhook, retry, self_test, send_queue, shadow, startup, startup_announce,
+    still_working, store, store_health, sync, system_rooms, warmup, webhook,
+};
+
+/// Runs the bot: logs in, registers the responders and syncs until Ctrl+C
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use verji_vagent_bot::{BotBuilder, BotConfig};
+///
+/// BotBuilder::new(BotConfig::from_env()).run().await
+/// # }
+/// ```
+#[must_use]
+pub struct BotBuilder {
+    config: Arc<BotConfig>,
+    clear_store: bool,
+    reset_encryption: bool,
+    responders: Vec<Arc<dyn Responder>>,
+}
+
+impl BotBuilder {
+    /// Matrix credentials come from `MATRIX_HOMESERVER`, `MATRIX_USER` and
+    /// `MATRIX_PASSWORD`, everything else from `config`
+    pub fn new(config: BotConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            clear_store: false,
+            reset_encryption: false,
+            responders: Vec::new(),
+        }
+    }
+
+    /// Delete the store before logging in (`--clear-store`)
+    pub fn clear_store(mut self, clear_store: bool) -> Self {
+        self.clear_store = clear_store;
+        self
+    }
+
+    /// Create fresh encryption keys (`--reset-encryption`, DESTRUCTIVE)
+    pub fn reset_encryption(mut self, reset_encryption: bool) -> Self {
+        self.reset_encryption = reset_encryption;
+        self
+    }
+
+    /// Register a responder next to the built-in ones
+    ///
+    /// It gets the same treatment as they do: room scope from
+    /// `RESPONDER_ROOMS`, quota from `RESPONDER_QUOTAS` and cooldown from
+    /// `RESPONDER_COOLDOWNS`, looked up by its name.
+    pub fn responder(mut self, responder: Arc<dyn Responder>) -> Self {
+        self.responders.push(responder);
+        self
+    }
+
+    /// Run until Ctrl+C or a fatal sync error, then shut down cleanly
+    pub async fn run(self) -> Result<()> {
+        run(self).await
+    }
+}
+
+/// Log in, wire everything up and sync
+async fn run(bot: BotBuilder) -> Result<()> {
+    let BotBuilder {
+        config,
+        clear_store,
+        reset_encryption,
+        responders: extra_responders,
+    } = bot;
+
+    let runtime = Runtime::new(load_catalog(&config)?);
+    info!("🤖 Starting Verji vAgent Bot with Pluggable Responder Pattern");
+    info!("Version: {}", startup_announce::version());
+    if reset_encryption {
+        warn_before_encryption_reset();
+    }
+
+    let credentials = Credentials::from_env()?;
+    let store = prepare_store(&config, &credentials, &runtime, clear_store).await?;
+    let (client, session_source) = login(&config, &credentials, &store, clear_store).await?;
+
+    if let Some(admin_room) = &config.admin_room {
+        store
+            .crashes
+            .set_alert_target(client.clone(), admin_room.clone(), runtime.clone());
+    }
+    // Aliases in the room settings are resolved once up front, so allowlists
+    // match them from the cache
+    let aliases = runtime.aliases.resolver(&client);
+    if let Err(e) = aliases.resolve_all(config.configured_rooms()).await {
+        warn!("⚠️  {:#}", e);
+    }
+    store_health::spawn_monitor(
+        runtime.clone(),
+        client.clone(),
+        store.path.clone(),
+        config.store_min_free_bytes,
+        config.store_check_interval,
+        config.admin_room.clone(),
+        store.problem.clone(),
+    );
+    prepare_encryption(&client, &store, reset_encryption, &credentials.password).await?;
+
+    let stores = open_stores(&config, &store.path, &runtime)?;
+    let (manager, guards) = build_manager(&config, &stores);
+    let responder_manager = Arc::new(manager);
+    let agent = Arc::new(
+        VerjiAgentResponder::new(&runtime)?
+            .with_outbound_webhooks(
+                outbound_webhook::OutboundWebhooks::from_env(Arc::clone(&runtime.metrics))
+                    .context(BotError::Config)?,
+            )
+            .with_failure_journal(Arc::clone(&stores.failed_requests)),
+    );
+    register_responders(
+        &config,
+        &runtime,
+        &responder_manager,
+        &stores,
+        &guards,
+        &agent,
+        extra_responders,
+    );
+    info!("✅ Registered {} responders", responder_manager.count());
+    runtime
+        .capabilities
+        .init(BotCapabilities::new(responder_manager.list_responders()));
+
+    // Find dead Redis connections before a user query does
+    let keepalive = (!config.redis_keepalive.is_zero())
+        .then(|| agent.service().spawn_keepalive(config.redis_keepalive));
+
+    // Expire conversation state
+    stores
+        .conversations
+        .spawn_sweep_task(std::time::Duration::from_secs(60), Vec::new());
+
+    let services = Services::new(&config, &runtime, &stores);
+    register_event_handlers(
+        &client,
+        &services,
+        &responder_manager,
+        &stores,
+        &guards,
+        &aliases,
+        &agent,
+    );
+    encryption::log_encryption_changes(&client);
+    info!("📨 Event handlers registered");
+
+    // Initial sync: full state only for new logins (fresh or cleared store),
+    // restored sessions catch up incrementally from their sync token
+    let new_login = session_source == "new_login";
+    let initial_sync = sync::initial_sync(
+        &client,
+        new_login,
+        std::time::Duration::from_secs(10),
+        &runtime.metrics,
+    );
+    match initial_sync.await {
+        Ok(()) if new_login => {
+            encryption::log_encryption_status(&client, "after initial sync").await;
+        }
+        Ok(()) => {}
+        Err(e) => {
+            if !runtime.store_health.report_error("initial sync", &e) {
+                warn!("⚠️  {:#}", e);
+            }
+        }
+    }
+
+    start_background_tasks(
+        &client, &services, &store, &stores, &guards, &agent, new_login,
+    )
+    .await;
+
+    if let (true, Some(admin_room)) = (config.startup_announce, &config.admin_room) {
+        startup_announce::announce(
+            &runtime,
+            &client,
+            admin_room,
+            session_source,
+            store.previous_crash.as_ref(),
+            &agent,
+            &responder_manager.list_responders(),
+        )
+        .await;
+    }
+
+    // A daily canary question checks the bot can still answer
+    if let Some(test) = config.self_test.clone() {
+        self_test::spawn(
+            runtime.clone(),
+            client.clone(),
+            Arc::clone(agent.service()),
+            test,
+            config.admin_room.clone(),
+        );
+    }
+
+    // Let monitoring and CI post into rooms; stopped together with the sync loop
+    let webhook = match &config.webhook {
+        Some(webhook_config) => Some(
+            webhook::WebhookServer::start(
+                runtime.clone(),
+                client.clone(),
+                webhook_config.clone(),
+                Arc::clone(&stores.stats),
+                config
+                    .maintenance_not_ready
+                    .then(|| Arc::clone(&stores.maintenance)),
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    info!("🔄 Starting main sync loop...");
+    info!("Bot is now running and ready to respond");
+    runtime.startup.serving();
+    let result = serve(&client, &runtime).await;
+
+    if let Some(keepalive) = keepalive {
+        keepalive.abort();
+    }
+    if let Some(webhook) = webhook {
+        webhook.shutdown().await;
+    }
+    shut_down(&runtime, &stores, &services.coalescer).await;
+
+    result
+}
+
+/// Load the message catalog; inconsistent translations abort startup
+fn load_catalog(config: &BotConfig) -> Result<i18n::Catalog> {
+    let catalog = i18n::Catalog::load(config.i18n_file.as_deref()).context(BotError::Config)?;
+    info!(
+        "🌐 Message languages: {:?} (default: {})",
+        catalog.languages(),
+        config.locale
+    );
+    Ok(catalog)
+}
+
+/// Give the operator a chance to abort `--reset-encryption`
+fn warn_before_encryption_reset() {
+    warn!("⚠️  ⚠️  ⚠️  DESTRUCTIVE MODE: --reset-encryption enabled ⚠️  ⚠️  ⚠️");
+    warn!("This will create FRESH encryption keys!");
+    warn!("Old encrypted messages may become UNREADABLE!");
+    warn!("Waiting 3 seconds... Press Ctrl+C to abort.");
+    std::thread::sleep(std::time::Duration::from_secs(3));
+    warn!("Proceeding with encryption reset...");
+}
+
+/// Matrix credentials, from `MATRIX_HOMESERVER`, `MATRIX_USER` and
+/// `MATRIX_PASSWORD`
+struct Credentials {
+    homeserver: String,
+    username: String,
+    /// Also the passphrase of the store
+    password: String,
+}
+
+impl Credentials {
+    fn from_env() -> Result<Self> {
+        Ok(Self {
+            homeserver: config::env_required("MATRIX_HOMESERVER")?,
+            username: config::env_required("MATRIX_USER")?,
+            password: config::env_required("MATRIX_PASSWORD")?,
+        })
+    }
+}
+
+/// The store directory, locked for as long as the bot runs
+struct StoreDir {
+    path: PathBuf,
+    _lock: store::StoreLock,
+    /// Records panics, and alerts on them once the client is up
+    crashes: Arc<CrashReporter>,
+    /// The crash the last run ended with, not yet reported
+    previous_crash: Option<crash::PreviousCrash>,
+    /// Why the store is unhealthy, alerted on once the client is up
+    problem: Option<String>,
+}
+
+/// Create and lock the store, and set up what writes to it on its own:
+/// crash reports and the shadow log
+async fn prepare_store(
+    config: &BotConfig,
+    credentials: &Credentials,
+    runtime: &Runtime,
+    clear_store: bool,
+) -> Result<StoreDir> {
+    let path = config.store_path.clone();
+    info!("Configuration:");
+    info!("  Homeserver: {}", credentials.homeserver);
+    info!("  Username: {}", credentials.username);
+    info!("  Store path: {}", path.display());
+
+    if clear_store {
+        client::clear_store(&path).await?;
+    }
+    if !path.exists() {
+        info!("Creating store directory: {}", path.display());
+        std::fs::create_dir_all(&path).context("Failed to create store directory")?;
+    }
+
+    // Keep `store maintain` and other bot processes away while we run
+    let lock = store::StoreLock::acquire(&path)?;
+
+    // Panics are written to the store, and reported on the next start
+    let crashes = CrashReporter::install(&path)?;
+    let previous_crash = crash::take_unreported(&path);
+
+    // Shadow mode runs everything but the room writes, which go to a file
+    let shadow_log = config
+        .shadow_log
+        .clone()
+        .or_else(|| config.shadow_mode.then(|| path.join("shadow.jsonl")));
+    runtime
+        .shadow
+        .install(config.shadow_mode, shadow_log.as_deref())?;
+    if let Some(crash) = &previous_crash {
+        warn!(
+            "💥 Recovered from a crash at {} ({} so far)",
+            crash.at.to_rfc3339(),
+            crash.total
+        );
+    }
+
+    // A full or read-only store is alerted on once the client is up, not fatal
+    let problem = match store_health::check(&path, config.store_min_free_bytes) {
+        Ok(_) => None,
+        Err(e) => {
+            error!("💾 Store check failed: {:#}", e);
+            Some(format!("{:#}", e))
+        }
+    };
+
+    Ok(StoreDir {
+        path,
+        _lock: lock,
+        crashes,
+        previous_crash,
+        problem,
+    })
+}
+
+/// Restore the saved session or log in fresh; the second value says which
+async fn login(
+    config: &BotConfig,
+    credentials: &Credentials,
+    store: &StoreDir,
+    clear_store: bool,
+) -> Result<(Client, &'static str)> {
+    info!("🔌 Connecting to homeserver: {}", credentials.homeserver);
+    let session_file = store.path.join("session.json");
+    let (client, session_source) = if session_file.exists() && !clear_store {
+        client::restore_or_login(
+            &session_file,
+            &credentials.homeserver,
+            &credentials.username,
+            &credentials.password,
+            &store.path,
+            &credentials.password,
+            &config.device_display_name,
+        )
+        .await?
+    } else {
+        client::fresh_login(
+            &credentials.homeserver,
+            &credentials.username,
+            &credentials.password,
+            &store.path.to_string_lossy(),
+            &store.path,
+            &credentials.password,
+            &session_file,
+            &config.device_display_name,
+        )
+        .await?
+    };
+
+    info!("📊 Session Status:");
+    info!("  Source: {}", session_source);
+    if let Some(user_id) = client.user_id() {
+        info!("  User ID: {}", user_id);
+    }
+    if let Some(device_id) = client.device_id() {
+        info!("  Device ID: {}", device_id);
+    }
+    info!("  Device name: {}", config.device_display_name);
+
+    // Fresh logins already got the name; restored devices may predate a change
+    if session_source == "restored" {
+        if let Err(e) =
+            client::update_device_display_name(&client, &config.device_display_name).await
+        {
+            warn!("⚠️  Failed to update the device display name: {:#}", e);
+        }
+    }
+    Ok((client, session_source))
+}
+
+/// Setup/reset encryption if explicitly requested; an operator asked for it,
+/// so the bot doesn't answer with the old keys in the meantime
+async fn prepare_encryption(
+    client: &Client,
+    store: &StoreDir,
+    reset: bool,
+    password: &str,
+) -> Result<()> {
+    if !reset {
+        encryption::log_encryption_status(client, "before sync").await;
+        return Ok(());
+    }
+    info!("🔐 Resetting encryption as requested");
+    encryption::setup_encryption(client, &store.path, true, password).await?;
+
+    // Perform initial sync after encryption reset to stabilize SDK state
+    info!("🔄 Performing initial sync after encryption reset...");
+    let initial_sync_settings = SyncSettings::default().timeout(std::time::Duration::from_secs(30));
+    match client.sync_once(initial_sync_settings).await {
+        Ok(_) => {
+            info!("✅ Initial sync after reset completed");
+            encryption::log_encryption_status(client, "after reset sync").await;
+        }
+        Err(e) => {
+            warn!("⚠️  Initial sync after reset failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// What the bot keeps in the store directory
+struct Stores {
+    stats: Arc<UsageStats>,
+    quotas: Arc<QuotaStore>,
+    maintenance: Arc<MaintenanceMode>,
+    conversations: Arc<ConversationStore>,
+    follow_ups: Arc<FollowUpScheduler>,
+    failed_requests: Arc<FailedRequests>,
+    preferences: Arc<PreferenceStore>,
+    feedback: Arc<FeedbackStore>,
+    sent_events: Arc<SentEventRegistry>,
+    outbox: Option<Arc<Outbox>>,
+}
+
+/// Open the stores and start their periodic flushes
+fn open_stores(config: &BotConfig, store_path: &Path, runtime: &Runtime) -> Result<Stores> {
+    // Load usage statistics and start the periodic flush
+    let stats = Arc::new(
+        UsageStats::open(store_path, config.history_max_age)?
+            .with_metrics(Arc::clone(&runtime.metrics)),
+    );
+    stats.spawn_flush_task(config.stats_flush_interval);
+
+    // Per-responder quotas survive restarts, flushed alongside the stats
+    let quotas = Arc::new(QuotaStore::open(store_path)?);
+    quotas.spawn_flush_task(config.stats_flush_interval);
+
+    if !config.store_maintenance_interval.is_zero() {
+        store::spawn_maintenance_task(store_path.to_path_buf(), config.store_maintenance_interval);
+    }
+
+    // Survives restarts so a deployment window isn't ended by a bot restart
+    let maintenance = Arc::new(MaintenanceMode::open(
+        store_path,
+        config.maintenance_mode,
+        config.maintenance_message.clone(),
+    )?);
+
+    // Shared per-conversation state for multi-step features
+    let conversations = Arc::new(if config.conversation_persist {
+        ConversationStore::open(store_path, config.conversation_max_entries)?
+    } else {
+        ConversationStore::in_memory(config.conversation_max_entries)
+    });
+
+    // Reminders and timeouts that must survive restarts
+    let follow_ups = Arc::new(
+        FollowUpScheduler::open(store_path, config.follow_ups_per_room_max)?
+            .with_metrics(Arc::clone(&runtime.metrics)),
+    );
+
+    // Queries that fail during a graph outage are kept for `!admin replay-failed`
+    let failed_requests = Arc::new(FailedRequests::open(store_path, config.replay_max_age)?);
+    // `!prefs`, read from memory for every message
+    let preferences = Arc::new(PreferenceStore::open(store_path)?);
+    // 👍/👎 on agent answers, for `!admin feedbackstats`
+    let feedback = Arc::new(FeedbackStore::open(store_path)?);
+    // What the bot sent and for which request, to match reactions to answers
+    let sent_events = Arc::new(SentEventRegistry::open(
+        store_path,
+        config.sent_events_max,
+        config.sent_events_max_age,
+    )?);
+    // Final answers whose send failed, delivered once the homeserver is back
+    let outbox = if config.outbox_max_age.is_zero() {
+        None
+    } else {
+        Some(Arc::new(Outbox::open(
+            store_path,
+            config.outbox_max_age,
+            config.outbox_retry,
+            config.outbox_retry_max,
+            &config.locale,
+            runtime.clone(),
+        )?))
+    };
+
+    Ok(Stores {
+        stats,
+        quotas,
+        maintenance,
+        conversations,
+        follow_ups,
+        failed_requests,
+        preferences,
+        feedback,
+        sent_events,
+        outbox,
+    })
+}
+
+/// Room state the middlewares enforce and state events change
+struct RoomGuards {
+    /// Bans shared through the policy room, loaded after the initial sync
+    policy: Option<Arc<PolicyList>>,
+    /// Rooms whose moderators switched the bot off
+    kill_switch: Arc<KillSwitch>,
+    /// Archived rooms are read-only; observers still see their messages
+    archive: Arc<ArchiveWatch>,
+}
+
+/// The responder manager with its middlewares, observers and policies, and
+/// no responders yet
+fn build_manager(config: &BotConfig, stores: &Stores) -> (ResponderManager, RoomGuards) {
+    let mut manager = ResponderManager::new();
+    let guards = RoomGuards {
+        policy: config
+            .policy_room
+            .as_deref()
+            .map(|room| Arc::new(PolicyList::new(room))),
+        kill_switch: Arc::new(KillSwitch::new()),
+        archive: Arc::new(ArchiveWatch::new(config.archive.clone())),
+    };
+
+    // Middleware order matters: access control drops before rate limits count
+    manager.add_middleware(Arc::new(
+        AccessControlMiddleware::new(config).with_policy(guards.policy.clone()),
+    ));
+    manager.add_middleware(Arc::new(KillSwitchMiddleware::new(Arc::clone(
+        &guards.kill_switch,
+    ))));
+    manager.add_middleware(Arc::new(ArchiveMiddleware::new(Arc::clone(
+        &guards.archive,
+    ))));
+    manager.add_middleware(Arc::new(MaintenanceMiddleware::new(Arc::clone(
+        &stores.maintenance,
+    ))));
+    manager.add_middleware(Arc::new(RateLimitMiddleware::new(
+        config.rate_limit_per_minute,
+    )));
+
+    for observer in observers::standard() {
+        manager.add_observer(observer);
+    }
+
+    manager.set_timeout_policy(
+        TimeoutPolicy::parse(&config.responder_timeout_policy),
+        config.responder_timeout_reply.clone(),
+    );
+    manager.set_error_policy(
+        ErrorPolicy::parse(&config.responder_error_policy),
+        config.responder_error_reply.clone(),
+    );
+    manager.set_trace_max(config.routing_trace_max);
+
+    (manager, guards)
+}
+
+/// Register the built-in responders and the embedder's, each wrapped in the
+/// quota, budget, cooldown and intent filter its configuration asks for
+fn register_responders(
+    config: &BotConfig,
+    runtime: &Runtime,
+    responder_manager: &Arc<ResponderManager>,
+    stores: &Stores,
+    guards: &RoomGuards,
+    agent: &Arc<VerjiAgentResponder>,
+    extra_responders: Vec<Arc<dyn Responder>>,
+) {
+    let erasure = Arc::new(Erasure::new(
+        Arc::clone(&stores.stats),
+        Arc::clone(&stores.quotas),
+        Arc::clone(&stores.conversations),
+        Arc::clone(&stores.follow_ups),
+        Arc::clone(&stores.failed_requests),
+        Arc::clone(&stores.feedback),
+        Arc::clone(&stores.preferences),
+        Arc::clone(agent),
+    ));
+    let replayer = Arc::new(Replayer::new(
+        Arc::clone(&stores.failed_requests),
+        Arc::clone(agent),
+        Arc::clone(&stores.sent_events),
+        config.replay_interval,
+        runtime.clone(),
+    ));
+
+    // Register responders (priority order: PingPong/Echo=100, Admin=95, Help/Stats/History/Prompt/Agent/Summary=90, Shortcut=50, VerjiAgent=10)
+    info!("📝 Registering responders...");
+    // Room scopes come from RESPONDER_ROOMS (unlisted responders are active
+    // everywhere), quotas from RESPONDER_QUOTAS and cooldowns from RESPONDER_COOLDOWNS.
+    // Commands that query the agent use the agent's quota unless they have their own,
+    // and count towards the per-user daily limit (DAILY_QUOTA) and the room's monthly
+    // budget (MONTHLY_BUDGET) like the agent does.
+    const AGENT: &str = "VerjiAgentResponder";
+    const AGENT_COMMANDS: &[&str] = &["SummaryResponder"];
+    let quotas = &stores.quotas;
+    let register = |responder: Arc<dyn Responder>| {
+        let scope = config.responder_scope(responder.name());
+        let responder: Arc<dyn Responder> = match config.responder_quotas.get(responder.name()) {
+            Some(quota) => Arc::new(RateLimited::new(
+                responder,
+                quota.clone(),
+                Arc::clone(quotas),
+            )),
+            None => match config.responder_quotas.get(AGENT) {
+                Some(quota) if AGENT_COMMANDS.contains(&responder.name()) => Arc::new(
+                    RateLimited::new(responder, quota.clone(), Arc::clone(quotas))
+                        .sharing_quota_of(AGENT),
+                ),
+                _ => responder,
+            },
+        };
+        let responder: Arc<dyn Responder> =
+            if responder.name() == AGENT || AGENT_COMMANDS.contains(&responder.name()) {
+                Arc::new(RoomBudget::new(DailyQuota::new(responder)))
+            } else {
+                responder
+            };
+        let responder: Arc<dyn Responder> = match config.responder_cooldowns.get(responder.name()) {
+            Some(cooldown) => Arc::new(Cooldown::new(responder, *cooldown)),
+            None => responder,
+        };
+        // Outermost, so dropped acknowledgements use up no quota or cooldown
+        let responder: Arc<dyn Responder> =
+            if responder.name() == AGENT && config.intent_filter != IntentFilterMode::Off {
+                Arc::new(IntentFilter::new(
+                    responder,
+                    config.intent_filter,
+                    config.intent_filter_reaction.clone(),
+                    Arc::clone(agent.service()),
+                ))
+            } else {
+                responder
+            };
+        responder_manager.register_scoped(responder, scope);
+    };
+    register(Arc::new(PingPongResponder::new()));
+    register(Arc::new(EchoResponder::new()));
+    register(Arc::new(AdminResponder::new(
+        Arc::downgrade(responder_manager),
+        Arc::clone(&stores.maintenance),
+        erasure,
+        replayer,
+        Arc::clone(&stores.feedback),
+        stores.outbox.clone(),
+    )));
+    register(Arc::new(HelpResponder::new()));
+    register(Arc::new(StatsResponder::new()));
+    register(Arc::new(HistoryResponder::new()));
+    register(Arc::new(ExportResponder::new()));
+    register(Arc::new(PinResponder::new()));
+    register(Arc::new(QuotaResponder::new()));
+    register(Arc::new(PromptResponder::new()));
+    register(Arc::new(AgentSelectResponder::new()));
+    register(Arc::new(PrefsResponder::new()));
+    register(Arc::new(WhoamiResponder::new()));
+    register(Arc::new(RoomInfoResponder::new(
+        Arc::clone(&guards.archive),
+        Arc::clone(&guards.kill_switch),
+    )));
+    register(Arc::new(StatusResponder::new(Arc::clone(
+        &stores.maintenance,
+    ))));
+    if !config.prompt_shortcuts.is_empty() {
+        register(Arc::new(ShortcutResponder::new(
+            config.prompt_shortcuts.clone(),
+        )));
+    }
+    register(Arc::new(SummaryResponder::new(Arc::clone(agent))));
+    register(Arc::clone(agent) as Arc<dyn Responder>);
+    for responder in extra_responders {
+        register(responder);
+    }
+}
+
+/// Hook the bot into the client's event stream, one handler per event kind
+fn register_event_handlers(
+    client: &Client,
+    services: &Services,
+    responder_manager: &Arc<ResponderManager>,
+    stores: &Stores,
+    guards: &RoomGuards,
+    aliases: &Arc<AliasResolver>,
+    agent: &VerjiAgentResponder,
+) {
+    on_state_events(client, services, guards, aliases);
+    on_tags(client, services, guards);
+    on_reactions(client, services, responder_manager, stores);
+    on_redactions(client, services);
+    on_own_membership(client, services, agent.service());
+    on_departures(client, services);
+    on_stickers(client, services, responder_manager);
+    if !services.config.custom_events.is_empty() {
+        on_custom_events(client, services, responder_manager);
+    }
+    on_messages(client, services, responder_manager);
+}
+
+/// A changed tenant state event takes effect with the room's next query;
+/// policy room rules, the kill switch and archive markers apply right away
+fn on_state_events(
+    client: &Client,
+    services: &Services,
+    guards: &RoomGuards,
+    aliases: &Arc<AliasResolver>,
+) {
+    let tenants = Arc::clone(&services.tenants);
+    let policy = guards.policy.clone();
+    let kill_switch = Arc::clone(&guards.kill_switch);
+    let archive = Arc::clone(&guards.archive);
+    let room_configs = Arc::clone(&services.room_configs);
+    let config = Arc::clone(&services.config);
+    let runtime = services.runtime.clone();
+    let aliases = Arc::clone(aliases);
+    client.add_event_handler(move |event: Raw<AnySyncStateEvent>, room: MatrixRoom| {
+        let room = BotRoom::new(room, runtime.clone());
+        let tenants = Arc::clone(&tenants);
+        let aliases = Arc::clone(&aliases);
+        let policy = policy.clone();
+        let kill_switch = Arc::clone(&kill_switch);
+        let archive = Arc::clone(&archive);
+        let room_configs = Arc::clone(&room_configs);
+        let config = Arc::clone(&config);
+        async move {
+            let event_type = event.get_field::<String>("type").ok().flatten();
+            if event_type.as_deref() == Some(tenants.event_type.as_str()) {
+                tenants.invalidate(room.room_id().as_str());
+            }
+            if event_type.as_deref() == Some(kill_switch::DISABLED_EVENT_TYPE) {
+                let content = event
+                    .get_field::<serde_json::Value>("content")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                kill_switch
+                    .on_state_event(&room, &content, &room_configs, &config, room.runtime())
+                    .await;
+            }
+            if event_type
+                .as_deref()
+                .is_some_and(|event_type| archive.policy().watches_state(event_type))
+            {
+                archive.on_change(&room, &room_configs).await;
+            }
+            if event_type.as_deref() == Some("m.room.canonical_alias") {
+                let content = event
+                    .get_field::<serde_json::Value>("content")
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default();
+                aliases
+                    .on_aliases_changed(room.room_id(), &alias::aliases_in(&content))
+                    .await;
+            }
+            let policy = policy.filter(|policy| policy.is_policy_room(room.room_id().as_str()));
+            if let (Some(policy), Ok(json)) = (policy, event.deserialize_as::<serde_json::Value>())
+            {
+                policy.on_state_event(&json);
+            }
+        }
+    });
+}
+
+/// The bot's own room tags mark server-notice rooms and can archive a room
+/// (ARCHIVE_TAG)
+fn on_tags(client: &Client, services: &Services, guards: &RoomGuards) {
+    let archive = Arc::clone(&guards.archive);
+    let room_configs = Arc::clone(&services.room_configs);
+    let system_rooms = Arc::clone(&services.system_rooms);
+    let runtime = services.runtime.clone();
+    client.add_event_handler(move |_event: TagEvent, room: MatrixRoom| {
+        let room = BotRoom::new(room, runtime.clone());
+        let archive = Arc::clone(&archive);
+        let room_configs = Arc::clone(&room_configs);
+        let system_rooms = Arc::clone(&system_rooms);
+        async move {
+            system_rooms.invalidate(room.room_id().as_str());
+            if archive.policy().tag.is_some() {
+                archive.on_change(&room, &room_configs).await;
+            }
+        }
+    });
+}
+
+/// Reactions to the bot's answers are kept as feedback on them; 🔁 on an
+/// error reply retries the failed query, ▶️/◀️ turn paged output
+fn on_reactions(
+    client: &Client,
+    services: &Services,
+    responder_manager: &Arc<ResponderManager>,
+    stores: &Stores,
+) {
+    let services = services.clone();
+    let responder_manager = Arc::clone(responder_manager);
+    let feedback = Arc::clone(&stores.feedback);
+    let failed_requests = Arc::clone(&stores.failed_requests);
+    client.add_event_handler(
+        move |event: OriginalSyncReactionEvent, room: MatrixRoom, client: Client| {
+            let room = BotRoom::new(room, services.runtime.clone());
+            let services = services.clone();
+            let responder_manager = Arc::clone(&responder_manager);
+            let feedback = Arc::clone(&feedback);
+            let failed_requests = Arc::clone(&failed_requests);
+            async move {
+                if client.user_id() == Some(&*event.sender) {
+                    return;
+                }
+                let annotation = &event.content.relates_to;
+                if retry::is_retry_reaction(&annotation.key) {
+                    let target = annotation.event_id.clone();
+                    let sender = event.sender.to_string();
+                    let retried = handle_retry(
+                        room,
+                        &target,
+                        &sender,
+                        responder_manager,
+                        client,
+                        services,
+                        &failed_requests,
+                    )
+                    .await;
+                    if let Err(e) = retried {
+                        error!("Error handling retry: {}", e);
+                    }
+                    return;
+                }
+                let paged = pager::on_reaction(
+                    &room,
+                    &services.runtime.catalog,
+                    &services.conversations,
+                    &services.sent_events,
+                    event.sender.as_str(),
+                    &annotation.event_id,
+                    &annotation.key,
+                )
+                .await;
+                match paged {
+                    Ok(true) => return,
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to turn a page in {}: {:#}", room.room_id(), e),
+                }
+                if let Err(e) = feedback
+                    .on_reaction(
+                        room.room_id().as_str(),
+                        &services.conversations,
+                        &services.sent_events,
+                        event.sender.as_str(),
+                        &annotation.event_id,
+                        &annotation.key,
+                    )
+                    .await
+                {
+                    warn!("Failed to record feedback in {}: {:#}", room.room_id(), e);
+                }
+            }
+        },
+    );
+}
+
+/// Redacted bot messages no longer count as sent; redacted user messages
+/// still waiting for their processing delay are dropped
+fn on_redactions(client: &Client, services: &Services) {
+    let sent_events = Arc::clone(&services.sent_events);
+    let processing_delay = Arc::clone(&services.processing_delay);
+    client.add_event_handler(move |event: OriginalSyncRoomRedactionEvent| {
+        let sent_events = Arc::clone(&sent_events);
+        let processing_delay = Arc::clone(&processing_delay);
+        async move {
+            // Room version 11 moved `redacts` into the content
+            let Some(redacts) = event.content.redacts.as_ref().or(event.redacts.as_ref()) else {
+                return;
+            };
+            processing_delay.on_redaction(redacts);
+            if let Some(sent) = sent_events.forget(redacts).await {
+                info!(
+                    "🗑️  Bot {} message {} (request {}) redacted by {}",
+                    sent.kind.as_str(),
+                    redacts,
+                    sent.request_id.as_deref().unwrap_or("-"),
+                    event.sender
+                );
+            }
+        }
+    });
+}
+
+/// The bot leaving, or being kicked or banned, ends its work in the room;
+/// joining again starts over
+fn on_own_membership(client: &Client, services: &Services, agent: &Arc<AgentService>) {
+    let services = services.clone();
+    let agent = Arc::clone(agent);
+    client.add_event_handler(
+        move |event: OriginalSyncRoomMemberEvent, room: MatrixRoom, client: Client| {
+            let services = services.clone();
+            let agent = Arc::clone(&agent);
+            async move {
+                if client.user_id() != Some(&*event.state_key) {
+                    return;
+                }
+                let action = match event.content.membership {
+                    MembershipState::Join | MembershipState::Invite => {
+                        services.runtime.memberships.joined(room.room_id().as_str());
+                        return;
+                    }
+                    MembershipState::Leave if event.sender == event.state_key => "left",
+                    MembershipState::Leave => "was kicked from",
+                    MembershipState::Ban => "was banned from",
+                    _ => return,
+                };
+                info!("🚪 Bot {} {} (by {})", action, room.room_id(), event.sender);
+                leave_room(room.room_id().as_str(), &services, &agent).await;
+            }
+        },
+    );
+}
+
+/// Sensitive rooms drop the bot's room key when someone leaves or is banned
+fn on_departures(client: &Client, services: &Services) {
+    let room_configs = Arc::clone(&services.room_configs);
+    let runtime = services.runtime.clone();
+    client.add_event_handler(
+        move |event: OriginalSyncRoomMemberEvent, room: MatrixRoom, client: Client| {
+            let room = BotRoom::new(room, runtime.clone());
+            let room_configs = Arc::clone(&room_configs);
+            async move {
+                let action = match event.content.membership {
+                    MembershipState::Leave if event.sender == event.state_key => "left",
+                    MembershipState::Leave => "was removed",
+                    MembershipState::Ban => "was banned",
+                    _ => return,
+                };
+                let was_member = event.unsigned.prev_content.as_ref().is_some_and(|prev| {
+                    matches!(
+                        prev.membership,
+                        MembershipState::Join | MembershipState::Invite
+                    )
+                });
+                if !was_member || client.user_id() == Some(&*event.state_key) {
+                    return;
+                }
+                if !room_configs.get(&room).await.sensitive {
+                    return;
+                }
+                let reason = format!("{} {} ({})", event.state_key, action, event.event_id);
+                if let Err(e) = room.runtime().key_rotations.rotate(&room, &reason).await {
+                    error!(
+                        "Failed to rotate the room key of {}: {:#}",
+                        room.room_id(),
+                        e
+                    );
+                }
+            }
+        },
+    );
+}
+
+fn on_stickers(client: &Client, services: &Services, responder_manager: &Arc<ResponderManager>) {
+    let responder_manager = Arc::clone(responder_manager);
+    let services = services.clone();
+    client.add_event_handler(
+        move |event: OriginalSyncStickerEvent, room: MatrixRoom, client: Client| {
+            let responder_manager = Arc::clone(&responder_manager);
+            let services = services.clone();
+            async move {
+                if let Err(e) =
+                    handle_sticker(event, room, responder_manager, client, services).await
+                {
+                    error!("Error handling sticker: {}", e);
+                }
+            }
+        },
+    );
+}
+
+/// Custom event types and msgtypes listed in CUSTOM_EVENTS
+fn on_custom_events(
+    client: &Client,
+    services: &Services,
+    responder_manager: &Arc<ResponderManager>,
+) {
+    let responder_manager = Arc::clone(responder_manager);
+    let services = services.clone();
+    client.add_event_handler(
+        move |event: Raw<AnySyncTimelineEvent>, room: MatrixRoom, client: Client| {
+            let responder_manager = Arc::clone(&responder_manager);
+            let services = services.clone();
+            async move {
+                if let Err(e) =
+                    handle_custom_event(event, room, responder_manager, client, services).await
+                {
+                    error!("Error handling custom event: {}", e);
+                }
+            }
+        },
+    );
+}
+
+fn on_messages(client: &Client, services: &Services, responder_manager: &Arc<ResponderManager>) {
+    let responder_manager = Arc::clone(responder_manager);
+    let services = services.clone();
+    client.add_event_handler(
+        move |event: OriginalSyncRoomMessageEvent, room: MatrixRoom, client: Client| {
+            let responder_manager = Arc::clone(&responder_manager);
+            let services = services.clone();
+            async move {
+                if let Err(e) =
+                    handle_message(event, room, responder_manager, client, services).await
+                {
+                    error!("Error handling message: {}", e);
+                }
+            }
+        },
+    );
+}
+
+/// What runs once the initial sync is done: encryption backups of a new
+/// login, key import, policy bans, warmup, follow-ups and the outbox
+async fn start_background_tasks(
+    client: &Client,
+    services: &Services,
+    store: &StoreDir,
+    stores: &Stores,
+    guards: &RoomGuards,
+    agent: &Arc<VerjiAgentResponder>,
+    new_login: bool,
+) {
+    let config = &services.config;
+    let runtime = &services.runtime;
+
+    // Backups of a new login aren't needed to answer; only sends to encrypted
+    // rooms wait for them
+    if new_login {
+        let setup_client = client.clone();
+        let setup_store_path = store.path.clone();
+        startup::spawn_encryption_setup(
+            runtime.clone(),
+            client.clone(),
+            config.admin_room.clone(),
+            async move { encryption::setup_backup_only(&setup_client, &setup_store_path).await },
+        );
+    }
+
+    if let Some(import) = &config.room_keys_import {
+        key_import::import_on_startup(client, &store.path, import).await;
+    }
+
+    if let Some(policy) = &guards.policy {
+        if let Err(e) = policy.join_and_load(client, &runtime.aliases).await {
+            warn!("⚠️  Policy room bans are not applied: {:#}", e);
+        }
+    }
+
+    warmup::run(
+        runtime,
+        client,
+        config.warmup_rooms,
+        &config.allowed_rooms,
+        config.warmup_interval,
+        config.warmup_budget,
+    )
+    .await;
+
+    // Follow-ups that fell due while the bot was down run now
+    stores.follow_ups.spawn_runner(
+        runtime.clone(),
+        client.clone(),
+        Arc::clone(agent),
+        Arc::clone(&stores.conversations),
+        follow_up::RUN_INTERVAL,
+    );
+
+    // Answers a room refuses go to the asker's direct chat
+    if config.fallback_dm {
+        runtime
+            .fallback_dm
+            .init(client, config.admin_room.clone(), &config.locale);
+    }
+
+    // Answers left undelivered before a restart go out first
+    if let Some(outbox) = &stores.outbox {
+        outbox.spawn_delivery_task(
+            client.clone(),
+            Arc::clone(&stores.sent_events),
+            config.outbox_retry,
+        );
+    }
+}
+
+/// Continuous incremental syncing, until it fails or Ctrl+C
+async fn serve(client: &Client, runtime: &Runtime) -> Result<()> {
+    tokio::select! {
+        result = sync::run(client, &runtime.metrics) => match result {
+            Ok(_) => {
+                info!("Sync completed normally");
+                Ok(())
+            }
+            Err(e) => {
+                if !runtime.store_health.report_error("sync", &e) {
+                    error!("Sync loop failed: {:#}", e);
+                }
+                Err(e)
+            }
+        },
+        _ = tokio::signal::ctrl_c() => {
+            info!("🛑 Shutdown requested");
+            Ok(())
+        }
+    }
+}
+
+/// Let queued work finish and persist what is only in memory
+async fn shut_down(runtime: &Runtime, stores: &Stores, coalescer: &Coalescer) {
+    // Hand buffered message bursts to their handlers instead of dropping them
+    coalescer.flush().await;
+
+    // Let answers already queued for rooms go out
+    let send_queues = &runtime.send_queues;
+    if !send_queues.drain(send_queue::SHUTDOWN_DRAIN_TIMEOUT).await {
+        warn!(
+            "Shutting down with {} room send(s) still queued",
+            send_queues.depth()
+        );
+    }
+
+    // Persist counters collected since the last periodic flush
+    if let Err(e) = stores.stats.flush().await {
+        warn!("Failed to flush usage statistics on shutdown: {}", e);
+    }
+    if let Err(e) = stores.quotas.flush().await {
+        warn!("Failed to flush quotas on shutdown: {}", e);
+    }
+    // Conversation state is written behind; let the writer catch up
+    if let Err(e) = stores.conversations.flush().await {
+        warn!("Failed to flush conversation state on shutdown: {}", e);
+    }
+}
+
+/// Shared services handed to every message handler
+#[derive(Clone)]
+struct Services {
+    config: Arc<BotConfig>,
+    runtime: Runtime,
+    stats: Arc<UsageStats>,
+    conversations: Arc<ConversationStore>,
+    follow_ups: Arc<FollowUpScheduler>,
+    preferences: Arc<PreferenceStore>,
+    room_configs: Arc<RoomConfigStore>,
+    tenants: Arc<TenantResolver>,
+    coalescer: Arc<Coalescer>,
+    sent_events: Arc<SentEventRegistry>,
+    processing_delay: Arc<ProcessingDelay>,
+    outbox: Option<Arc<Outbox>>,
+    system_rooms: Arc<SystemRooms>,
+}
+
+impl Services {
+    fn new(config: &Arc<BotConfig>, runtime: &Runtime, stores: &Stores) -> Self {
+        Self {
+            config: Arc::clone(config),
+            runtime: runtime.clone(),
+            stats: Arc::clone(&stores.stats),
+            conversations: Arc::clone(&stores.conversations),
+            follow_ups: Arc::clone(&stores.follow_ups),
+            preferences: Arc::clone(&stores.preferences),
+            room_configs: Arc::new(RoomConfigStore::new()),
+            tenants: Arc::new(TenantResolver::from_env()),
+            // Quick consecutive messages from one user are handled as one query
+            coalescer: Arc::new(Coalescer::new(config.message_coalesce)),
+            sent_events: Arc::clone(&stores.sent_events),
+            processing_delay: Arc::new(ProcessingDelay::new(config.processing_delay)),
+            outbox: stores.outbox.clone(),
+            system_rooms: Arc::new(SystemRooms::new(
+                config.system_rooms.clone(),
+                Arc::clone(&runtime.aliases),
+            )),
+        }
+    }
+}
+
+/// Give up the work of a room the bot left and clear what it kept for it
+///
+/// Dispatches still running there are abandoned and their graph requests
+/// cancelled (see `membership`); pending HITL questions are cancelled at the
+/// graph, conversation state and follow-ups deleted and cached settings
+/// dropped, so a later invite starts from a clean slate.
+async fn leave_room(room_id: &str, services: &Services, agent: &AgentService) {
+    let cancelled = services.runtime.memberships.left(room_id);
+
+    let removed = services.conversations.remove_room(room_id).await;
+    let mut questions = 0;
+    for (key, value) in &removed {
+        if key.slot != HITL_SLOT {
+            continue;
+        }
+        let Some(pending) = PendingHitl::from_value(value.clone()) else {
+            continue;
+        };
+        questions += 1;
+        if let Err(e) = agent
+            .cancel_hitl(&pending.request_id, room_id, &key.user_id)
+            .await
+        {
+            warn!(
+                "Failed to cancel HITL request {}: {:#}",
+                pending.request_id, e
+            );
+        }
+    }
+    let follow_ups = services
+        .follow_ups
+        .cancel_room(room_id)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to drop the follow-ups of {}: {:#}", room_id, e);
+            0
+        });
+    services.room_configs.forget(room_id);
+    services.tenants.invalidate(room_id);
+    services.system_rooms.invalidate(room_id);
+    services.runtime.processing.remove_room(room_id);
+    services.runtime.send_queues.remove_room(room_id);
+
+    info!(
+        "🧹 Cleared {}: {} graph request(s) and {} HITL question(s) cancelled, {} state entries and {} follow-up(s) removed",
+        room_id,
+        cancelled,
+        questions,
+        removed.len(),
+        follow_ups
+    );
+}
+
+/// Drop the quoted `> ` lines older clients put in front of a reply's body
+fn strip_reply_fallback(body: &str) -> String {
+    if !body.starts_with("> ") {
+        return body.to_string();
+    }
+    body.lines()
+        .skip_while(|line| line.starts_with('>'))
+        .skip_while(|line| line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A message the responders may answer, from a text, emote or sticker event
+struct IncomingMessage {
+    sender: String,
+    event_id: OwnedEventId,
+    body: String,
+    in_reply_to: Option<OwnedEventId>,
+    thread_id: Option<String>,
+    /// The bot is in the event's `m.mentions`
+    mentions_bot: bool,
+    /// Addressed to other users, so not merged with quick follow-ups
+    mentions_others: bool,
+    /// Had a formatted body (see `RESPONSE_FORMAT`)
+    formatted: bool,
+}
+
+/// Handle incoming message by routing through responder manager
+async fn handle_message(
+    event: OriginalSyncRoomMessageEvent,
+    room: MatrixRoom,
+    responder_manager: Arc<ResponderManager>,
+    client: Client,
+    services: Services,
+) -> Result<()> {
+    let room = BotRoom::new(room, services.runtime.clone());
+    let sender = event.sender.to_string();
+
+    // Ignore bot's own messages
+    let bot_user_id = client.user_id().map(|u| u.to_string()).unwrap_or_default();
+    if sender == bot_user_id {
+        return Ok(());
+    }
+
+    // Nothing is answered in system rooms; server notices go to the operators
+    if let Some(system) = services.system_rooms.check(&room).await {
+        if let (SystemRoomKind::ServerNotices, Some(admin_room)) =
+            (system.kind, &services.config.admin_room)
+        {
+            system_rooms::forward_notice(
+                &services.runtime,
+                &client,
+                admin_room,
+                &sender,
+                event.content.body(),
+            )
+            .await;
+        }
+        return Ok(());
+    }
+
+    let in_reply_to = match &event.content.relates_to {
+        Some(Relation::Reply { in_reply_to }) => Some(in_reply_to.event_id.clone()),
+        Some(Relation::Thread(thread)) if !thread.is_falling_back => thread
+            .in_reply_to
+            .as_ref()
+            .map(|reply| reply.event_id.clone()),
+        _ => None,
+    };
+    let mentions = event.content.mentions.as_ref();
+    let mentions_bot = mentions.is_some_and(|mentions| {
+        mentions
+            .user_ids
+            .iter()
+            .any(|user| client.user_id() == Some(&**user))
+    });
+    let mentions_others = mentions.is_some_and(|mentions| {
+        mentions
+            .user_ids
+            .iter()
+            .any(|user| client.user_id() != Some(&**user))
+    });
+
+    let formatted = match &event.content.msgtype {
+        MessageType::Text(text) => text.formatted.is_some(),
+        MessageType::Emote(emote) => emote.formatted.is_some(),
+        _ => false,
+    };
+    let body = match incoming::classify(event.content.msgtype, mentions_bot, &bot_user_id) {
+        Incoming::Text(body) => body,
+        Incoming::Emote(body) => {
+            let name = sender_name(&room, &sender).await;
+            info!("🎭 Handling emote from {} as a query", sender);
+            incoming::emote_query(&name, &body)
+        }
+        Incoming::UnaddressedEmote => {
+            info!("🎭 Ignoring emote from {} (bot not mentioned)", sender);
+            return Ok(());
+        }
+        Incoming::Notice => {
+            debug!("Ignoring notice from {}", sender);
+            return Ok(());
+        }
+        Incoming::Other(msgtype) => {
+            debug!("Ignoring {} message from {}", msgtype, sender);
+            return Ok(());
+        }
+    };
+    let body = if in_reply_to.is_some() {
+        strip_reply_fallback(&body)
+    } else {
+        body
+    };
+
+    let thread_id = match &event.content.relates_to {
+        Some(Relation::Thread(thread)) => Some(thread.event_id.to_string()),
+        _ => None,
+    };
+    let message = IncomingMessage {
+        sender,
+        event_id: event.event_id,
+        body,
+        in_reply_to,
+        thread_id,
+        mentions_bot,
+        mentions_others,
+        formatted,
+    };
+    process_message(message, room, responder_manager, client, services).await
+}
+
+/// Handle a sticker as the room's sticker mode says (`!admin stickers`)
+async fn handle_sticker(
+    event: OriginalSyncStickerEvent,
+    room: MatrixRoom,
+    responder_manager: Arc<ResponderManager>,
+    client: Client,
+    services: Services,
+) -> Result<()> {
+    let room = BotRoom::new(room, services.runtime.clone());
+    let sender = event.sender.to_string();
+    if client.user_id() == Some(&*event.sender) {
+        return Ok(());
+    }
+
+    let room_mode = services.room_configs.get(&room).await.sticker_mode;
+    match incoming::sticker_action(
+        room_mode,
+        services.config.sticker_mode,
+        &services.config.sticker_ack,
+    ) {
+        StickerAction::Ignore => {
+            debug!("Ignoring sticker from {} (sticker mode: ignore)", sender);
+            Ok(())
+        }
+        StickerAction::React(key) => {
+            info!("🖼️  Acknowledging sticker from {}", sender);
+            let reaction = OutgoingMessage::Reaction(key);
+            dispatcher::send_all(
+                &services.runtime,
+                &room,
+                &event.event_id,
+                None,
+                None,
+                vec![reaction],
+                &services.sent_events,
+                None,
+            )
+            .await;
+            Ok(())
+        }
+        StickerAction::Forward => {
+            info!("🖼️  Forwarding sticker from {} to the responders", sender);
+            let name = sender_name(&room, &sender).await;
+            let message = IncomingMessage {
+                body: incoming::sticker_query(&name, &event.content.body),
+                sender,
+                event_id: event.event_id,
+                in_reply_to: None,
+                thread_id: None,
+                mentions_bot: false,
+                mentions_others: false,
+                // Clients that send stickers render formatting
+                formatted: true,
+            };
+            process_message(message, room, responder_manager, client, services).await
+        }
+    }
+}
+
+/// Ignore, log or forward an event as CUSTOM_EVENTS says
+async fn handle_custom_event(
+    event: Raw<AnySyncTimelineEvent>,
+    room: MatrixRoom,
+    responder_manager: Arc<ResponderManager>,
+    client: Client,
+    services: Services,
+) -> Result<()> {
+    let room = BotRoom::new(room, services.runtime.clone());
+    // The content can have any shape, so the event is only read as JSON
+    let Ok(event) = event.deserialize_as::<serde_json::Value>() else {
+        return Ok(());
+    };
+    let Some((custom_type, treatment)) =
+        custom_events::classify(&event, &services.config.custom_events)
+    else {
+        return Ok(());
+    };
+    let sender = event
+        .get("sender")
+        .and_then(|sender| sender.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if sender.is_empty()
+        || client
+            .user_id()
+            .is_some_and(|user_id| user_id.as_str() == sender)
+    {
+        return Ok(());
+    }
+
+    match treatment {
+        Treatment::Ignore => Ok(()),
+        Treatment::Log => {
+            info!(
+                "📦 Custom event {} from {} in {} (content keys: {:?})",
+                custom_type,
+                sender,
+                room.room_id(),
+                custom_events::content_keys(&event)
+            );
+            Ok(())
+        }
+        Treatment::ForwardToGraph => {
+            let event_id = event
+                .get("event_id")
+                .and_then(|event_id| event_id.as_str())
+                .and_then(|event_id| EventId::parse(event_id).ok())
+                .context("Custom event has no valid event ID")?;
+            info!(
+                "📦 Forwarding custom event {} from {} to the graph",
+                custom_type, sender
+            );
+            let room_config = services.room_configs.get(&room).await;
+            let query = Query {
+                sender,
+                event_id,
+                in_reply_to: None,
+                thread_id: custom_events::thread_id(&event),
+                body: String::new(),
+                // Forwarding is opted into per type, so it's always meant for the bot
+                is_direct_mention: true,
+                // No text to go by
+                formatted: true,
+                retry_attempt: 0,
+                custom_event: Some(custom_events::payload(&custom_type, &event)),
+            };
+            dispatch_query(
+                query,
+                room,
+                room_config,
+                responder_manager,
+                client,
+                services,
+            )
+            .await
+        }
+    }
+}
+
+/// Whether the answer starts with the asker's name (`ADDRESS_BY_NAME`)
+///
+/// Only in group rooms' main timeline: in DMs and threads it's obvious who is
+/// being answered.
+fn addresses_by_name(context: &ResponderContext) -> bool {
+    context.config.address_by_name
+        && context.thread_id.is_none()
+        && context.room.member_count() > 2
+        && context.prefs().address_by_name()
+}
+
+/// Display name of a room member, falling back to their user ID
+async fn sender_name(room: &BotRoom, sender: &str) -> String {
+    RoomHandle::member_display_name(room, sender)
+        .await
+        .unwrap_or_else(|| sender.to_string())
+}
+
+/// What decides whether the bot answers a message in `reply_mode`
+///
+/// With reply mode `all` every message is answered, so only the cheap checks
+/// are made; threads and replies are looked up for `mentions` only.
+async fn message_addressing(
+    room: &BotRoom,
+    services: &Services,
+    reply_mode: ReplyMode,
+    message: &IncomingMessage,
+    bot_user_id: &str,
+) -> Addressing {
+    let mut addressing = Addressing {
+        command: message
+            .body
+            .trim_start()
+            .starts_with(command::COMMAND_PREFIX),
+        mentioned: message.mentions_bot || mentions_by_name(&message.body, bot_user_id),
+        direct_chat: RoomHandle::member_count(room) <= 2,
+        ..Addressing::default()
+    };
+    if reply_mode == ReplyMode::All || addressing.should_process(reply_mode) {
+        return addressing;
+    }
+
+    let room_id = room.room_id().as_str();
+    let mut hitl_key = ConversationKey::new(room_id, &message.sender, HITL_SLOT);
+    if let Some(thread_id) = &message.thread_id {
+        hitl_key = hitl_key.in_thread(thread_id);
+    }
+    addressing.awaiting_answer = services.conversations.get(&hitl_key).await.is_some();
+
+    if let Some(root) = message
+        .thread_id
+        .as_deref()
+        .and_then(|root| EventId::parse(root).ok())
+    {
+        addressing.in_bot_thread =
+            addressing::sent_by_bot(room, &services.sent_events, &root, bot_user_id).await;
+    }
+    if let Some(reply_to) = &message.in_reply_to {
+        addressing.reply_to_bot =
+            addressing::sent_by_bot(room, &services.sent_events, reply_to, bot_user_id).await;
+    }
+    addressing
+}
+
+/// Re-submit a failed agent query whose asker reacted to the error with 🔁
+async fn handle_retry(
+    room: BotRoom,
+    target: &EventId,
+    reactor: &str,
+    responder_manager: Arc<ResponderManager>,
+    client: Client,
+    services: Services,
+    failed_requests: &FailedRequests,
+) -> Result<()> {
+    let Some(SentEvent {
+        kind: SentKind::Error,
+        request_id: Some(request_id),
+        ..
+    }) = services.sent_events.lookup(target).await
+    else {
+        return Ok(());
+    };
+    let room_id = room.room_id().to_string();
+    let Some(failed) = retry::take(&services.conversations, &room_id, reactor, &request_id).await
+    else {
+        debug!(
+            "Ignoring 🔁 from {} on {}: not the asker, already retried or expired",
+            reactor, target
+        );
+        return Ok(());
+    };
+
+    // The retry replaces the journaled query, so `!admin replay-failed` won't answer it twice
+    if let Err(e) = failed_requests.remove(&request_id).await {
+        warn!(
+            "Failed to drop retried request {} from the journal: {:#}",
+            request_id, e
+        );
+    }
+    let attempt = failed.attempts + 1;
+    info!(
+        "🔁 {} retries request {} (retry {}/{})",
+        reactor, request_id, attempt, services.config.retry_max
+    );
+
+    let room_config = services.room_configs.get(&room).await;
+    let query = Query {
+        sender: reactor.to_string(),
+        event_id: failed.event_id,
+        in_reply_to: failed.in_reply_to,
+        thread_id: failed.thread_id,
+        body: failed.body,
+        is_direct_mention: failed.is_direct_mention,
+        // The journal doesn't keep the question's format
+        formatted: true,
+        retry_attempt: attempt,
+        custom_event: None,
+    };
+    dispatch_query(
+        query,
+        room,
+        room_config,
+        responder_manager,
+        client,
+        services,
+    )
+    .await
+}
+
+/// Coalesce, build the responder context and dispatch
+async fn process_message(
+    message: IncomingMessage,
+    room: BotRoom,
+    responder_manager: Arc<ResponderManager>,
+    client: Client,
+    services: Services,
+) -> Result<()> {
+    let bot_user_id = client.user_id().map(|u| u.to_string()).unwrap_or_default();
+    let room_config = services.room_configs.get(&room).await;
+    let reply_mode = room_config.reply_mode.unwrap_or(services.config.reply_mode);
+    let addressing = message_addressing(&room, &services, reply_mode, &message, &bot_user_id).await;
+    if !addressing.should_process(reply_mode) {
+        debug!(
+            "Ignoring message from {} (not addressed to the bot)",
+            message.sender
+        );
+        return Ok(());
+    }
+
+    let IncomingMessage {
+        sender,
+        event_id,
+        body: message_body,
+        in_reply_to,
+        thread_id,
+        mentions_others,
+        formatted,
+        ..
+    } = message;
+
+    // Merge quick follow-ups into one query; commands and messages addressed
+    // to other users are handled on their own
+    let (message_body, event_id, event_ids) = if addressing.command || mentions_others {
+        let event_ids = vec![event_id.clone()];
+        (message_body, event_id, event_ids)
+    } else {
+        let key = Coalescer::key(room.room_id().as_str(), thread_id.as_deref(), &sender);
+        match services.coalescer.submit(key, message_body, event_id).await {
+            Some(burst) => {
+                if burst.messages > 1 {
+                    info!("🧩 Coalesced {} messages from {}", burst.messages, sender);
+                }
+                (burst.body, burst.event_id, burst.event_ids)
+            }
+            // Merged into a burst another handler is waiting on
+            None => return Ok(()),
+        }
+    };
+
+    // Give the sender a moment to delete what they sent
+    if services.processing_delay.applies(
+        addressing.command,
+        addressing.direct_chat,
+        event_ids.len(),
+    ) && !services.processing_delay.wait(&event_ids).await
+    {
+        info!(
+            "↩️  {} retracted their message before it was handled",
+            sender
+        );
+        return Ok(());
+    }
+
+    // Detect if bot was mentioned, outright or by replying in its conversation
+    let is_direct_mention =
+        addressing.is_mention() || mentions_by_name(&message_body, &bot_user_id);
+
+    info!("📨 Received message: {}", message_body);
+
+    let query = Query {
+        sender,
+        event_id,
+        in_reply_to,
+        thread_id,
+        body: message_body,
+        is_direct_mention,
+        formatted,
+        retry_attempt: 0,
+        custom_event: None,
+    };
+    dispatch_query(
+        query,
+        room,
+        room_config,
+        responder_manager,
+        client,
+        services,
+    )
+    .await
+}
+
+/// A message that passed gating and coalescing, ready for the responders
+struct Query {
+    sender: String,
+    /// The (last) triggering event, which replies are sent to
+    event_id: OwnedEventId,
+    in_reply_to: Option<OwnedEventId>,
+    thread_id: Option<String>,
+    body: String,
+    is_direct_mention: bool,
+    /// The question had a formatted body
+    formatted: bool,
+    /// Times the query was re-submitted with 🔁
+    retry_attempt: u32,
+    /// Payload of a forwarded custom event, which has no text
+    custom_event: Option<serde_json::Value>,
+}
+
+/// Build the responder context, dispatch and send the output
+async fn dispatch_query(
+    query: Query,
+    room: BotRoom,
+    room_config: RoomConfig,
+    responder_manager: Arc<ResponderManager>,
+    client: Client,
+    services: Services,
+) -> Result<()> {
+    if services.system_rooms.check(&room).await.is_some() {
+        debug!("Not dispatching in system room {}", room.room_id());
+        return Ok(());
+    }
+    let event_id = query.event_id;
+    let room: Arc<dyn RoomHandle> = Arc::new(room);
+    let registered_responders =
+        responder_manager.active_in(room.as_ref(), &services.runtime.aliases);
+
+    // Looked up per message, so a name change shows on the next answer
+    let sender_display_name = room.member_display_name(&query.sender).await;
+    let identity = services.config.identities.canonical(&query.sender);
+    let context = ResponderContext {
+        client: client.clone(),
+        room,
+        event_id: event_id.clone(),
+        in_reply_to: query.in_reply_to,
+        sender: query.sender.into(),
+        sender_display_name,
+        identity: identity.into(),
+        thread_id: query.thread_id,
+        message_body: query.body.into(),
+        is_direct_mention: query.is_direct_mention,
+        message_formatted: query.formatted,
+        registered_responders,
+        config: services.config,
+        stats: services.stats,
+        conversations: services.conversations,
+        follow_ups: services.follow_ups,
+        preferences: services.preferences,
+        annotations: HashMap::new(),
+        room_config,
+        room_configs: services.room_configs,
+        tenants: services.tenants,
+        sent_events: services.sent_events,
+        retry_attempt: query.retry_attempt,
+        output: Arc::new(OutputActivity::new()),
+        observers: Arc::clone(responder_manager.observers()),
+        custom_event: query.custom_event,
+        runtime: services.runtime,
+    };
+
+    // Slow requests that show nothing get a notice, taken down with the answer
+    let wants_notice =
+        !context.config.still_working_after.is_zero() && context.prefs().delay_notices();
+    let still_working = wants_notice.then(|| {
+        let text = i18n::t(&context, "still_working", &[]);
+        let message = if context.config.still_working_notice {
+            OutgoingMessage::Notice(text)
+        } else {
+            OutgoingMessage::Text(text)
+        };
+        StillWorking::start(
+            Arc::clone(&context.runtime.send_queues),
+            Arc::clone(&context.room),
+            event_id.clone(),
+            context.thread_id.clone(),
+            Arc::clone(&context.output),
+            context.config.still_working_after,
+            message,
+        )
+    });
+
+    // Process through responder manager
+    let result = shadow::scope(event_id.clone(), responder_manager.dispatch(&context)).await;
+    let notice = match still_working {
+        Some(still_working) => still_working.stop().await,
+        None => None,
+    };
+    let mut messages = match result {
+        Ok(messages) => messages,
+        Err(e) => {
+            if let Some(notice) = &notice {
+                still_working::remove(context.room.as_ref(), notice).await;
+            }
+            return Err(e);
+        }
+    };
+    let format = context.config.response_format.resolve(
+        context.room_config.response_format,
+        context.message_formatted,
+    );
+    if format == ResponseFormat::Plain {
+        messages = messages
+            .into_iter()
+            .map(OutgoingMessage::into_plain)
+            .collect();
+    }
+    // Mention-only rooms are busy; the answer notifies whoever asked
+    let reply_mode = context
+        .room_config
+        .reply_mode
+        .unwrap_or(context.config.reply_mode);
+    if reply_mode == ReplyMode::Mentions || addresses_by_name(&context) {
+        mentions::address(&mut messages, &context.sender);
+    }
+    if !messages.is_empty() {
+        // Spawn the send operation in a separate task to avoid potential recursion issues
+        // when encryption state has been reset
+        let room = Arc::clone(&context.room);
+        let sent_events = Arc::clone(&context.sent_events);
+        let thread_id = context.thread_id.clone();
+        let sender = context.sender.clone();
+        let profile = context.config.profile_pipeline;
+        let outbox = services.outbox;
+        let runtime = context.runtime.clone();
+        tokio::spawn(shadow::scope(event_id.clone(), async move {
+            let started = std::time::Instant::now();
+            let thread_id = thread_id.as_deref();
+            dispatcher::send_all(
+                &runtime,
+                room.as_ref(),
+                &event_id,
+                thread_id,
+                Some(sender.as_ref()),
+                messages,
+                &sent_events,
+                outbox.as_deref(),
+            )
+            .await;
+            if let Some(notice) = &notice {
+                still_working::remove(room.as_ref(), notice).await;
+            }
+            if profile {
+                let elapsed = started.elapsed().as_millis() as u64;
+                runtime.metrics.observe_ms(
+                    "pipeline_stage_ms",
+                    &[("stage", "matrix_send")],
+                    elapsed,
+                );
+                info!("⏱️  Matrix send took {}ms", elapsed);
+            }
+        }));
+    } else if let Some(notice) = &notice {
+        still_working::remove(context.room.as_ref(), notice).await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! What this bot deployment can do, sent to vagent-graph with every request
+//!
+//! The graph uses it to tailor HITL questions and answer formats (e.g. offer
+//! a file instead of a long table). Computed once at startup, after the
+//! responders are registered; responders disabled later are still listed.
+
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tracing::warn;
+
+use crate::responder_manager::ResponderInfo;
+
+/// Longest answer body the bot sends in one message
+///
+/// Markdown answers carry the body twice (plain and HTML) within the
+/// homeserver's 64 KiB event limit.
+pub const MAX_MESSAGE_BYTES: usize = 32 * 1024;
+
+/// A registered responder as the graph sees it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponderCapability {
+    pub name: String,
+    pub priority: i32,
+}
+
+/// The `bot_capabilities` object of a graph request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BotCapabilities {
+    /// Files can be posted to the room
+    pub attachments: bool,
+    /// Sent messages can be edited in place (progress steps)
+    pub edits: bool,
+    /// Reactions can be added to the user's message
+    pub reactions: bool,
+    /// Matrix polls can be started
+    pub polls: bool,
+    /// An answer can be delivered in several parts while the graph runs
+    pub partial_responses: bool,
+    /// HITL requests can carry a form (see `hitl::Form`)
+    pub hitl_forms: bool,
+    pub max_message_bytes: usize,
+    /// Registered responders, in dispatch order
+    pub responders: Vec<ResponderCapability>,
+}
+
+impl BotCapabilities {
+    pub fn new(responders: Vec<ResponderInfo>) -> Self {
+        Self {
+            attachments: true,
+            edits: true,
+            reactions: true,
+            polls: false,
+            partial_responses: false,
+            hitl_forms: true,
+            max_message_bytes: MAX_MESSAGE_BYTES,
+            responders: responders
+                .into_iter()
+                .map(|info| ResponderCapability {
+                    name: info.name,
+                    priority: info.priority,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The capabilities of a bot, set once its responders are registered
+#[derive(Default)]
+pub struct Capabilities(OnceLock<BotCapabilities>);
+
+impl Capabilities {
+    /// Install the capabilities computed at startup
+    pub fn init(&self, capabilities: BotCapabilities) {
+        if self.0.set(capabilities).is_err() {
+            warn!("Bot capabilities already initialized");
+        }
+    }
+
+    /// The capabilities, if `init` ran (not in one-off CLI commands)
+    pub fn get(&self) -> Option<&BotCapabilities> {
+        self.0.get()
+    }
+}
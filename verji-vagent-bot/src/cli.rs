@@ -0,0 +1,371 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Subcommand;
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
+use matrix_sdk::ruma::OwnedRoomOrAliasId;
+use matrix_sdk::{Client, RoomState};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::alias::AliasResolver;
+use crate::client;
+use crate::config::{self, BotConfig};
+use crate::conversation::ConversationStore;
+use crate::erasure::Erasure;
+use crate::error::BotError;
+use crate::feedback::FeedbackStore;
+use crate::follow_up::FollowUpScheduler;
+use crate::i18n::Catalog;
+use crate::preferences::PreferenceStore;
+use crate::quota::QuotaStore;
+use crate::replay::{self, FailedRequests, Replayer};
+use crate::responders::VerjiAgentResponder;
+use crate::runtime::Runtime;
+use crate::sent_events::SentEventRegistry;
+use crate::shadow;
+use crate::stats::UsageStats;
+use crate::store::{self, StoreLock};
+
+/// One-shot maintenance subcommands (only `replay` and `send` log in, and they don't keep syncing)
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Usage statistics utilities
+    Stats {
+        #[command(subcommand)]
+        action: StatsCommand,
+    },
+    /// Answer feedback utilities
+    Feedback {
+        #[command(subcommand)]
+        action: FeedbackCommand,
+    },
+    /// Store directory utilities
+    Store {
+        #[command(subcommand)]
+        action: StoreCommand,
+    },
+    /// Delete everything stored about a user and ask vagent-graph to purge
+    /// their session memory (bot must be stopped; `!admin erase` works while it runs)
+    EraseUser {
+        /// Matrix ID of the user, e.g. @alice:example.org
+        user_id: String,
+        /// Only count what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Send journaled failed agent queries again and post the answers (bot must be stopped)
+    Replay {
+        /// Only requests that failed within this period, e.g. 30m, 6h, 2d
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// Send a message to a room as the bot, print its event ID and exit (bot must be stopped)
+    Send {
+        /// Room ID or alias, e.g. #ops:example.org
+        #[arg(long)]
+        room: String,
+        /// Message text, or `-` to read it from stdin
+        #[arg(long)]
+        message: String,
+        /// Render the message as Markdown
+        #[arg(long)]
+        markdown: bool,
+        /// Send as a notice (`m.notice`), formatted with --markdown
+        #[arg(long)]
+        notice: bool,
+    },
+    /// Compare the answers recorded in two shadow logs (SHADOW_LOG), by triggering event
+    ShadowDiff {
+        /// Shadow log of one run, usually the live bot
+        a: PathBuf,
+        /// Shadow log of the other run, usually the shadow deployment
+        b: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StatsCommand {
+    /// Export per-room/per-user usage counters as CSV
+    Export {
+        /// Output file (defaults to stdout)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FeedbackCommand {
+    /// Export raw 👍/👎 records as CSV (user IDs hashed with EXPORT_ANONYMIZE=true)
+    Export {
+        /// Only records from this day on (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        since: Option<String>,
+        /// Output file (defaults to stdout)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StoreCommand {
+    /// Prune expired bot data and VACUUM every sqlite database (bot must be stopped)
+    Maintain,
+}
+
+/// Run a subcommand and return
+pub async fn run(command: Command, config: &BotConfig) -> Result<()> {
+    match command {
+        Command::Stats {
+            action: StatsCommand::Export { out },
+        } => export_stats(config, out).await,
+        Command::Feedback {
+            action: FeedbackCommand::Export { since, out },
+        } => export_feedback(config, since, out),
+        Command::Store {
+            action: StoreCommand::Maintain,
+        } => maintain_store(config).await,
+        Command::EraseUser { user_id, dry_run } => erase_user(config, &user_id, dry_run).await,
+        Command::Replay { since } => replay_failed(config, since).await,
+        Command::Send {
+            room,
+            message,
+            markdown,
+            notice,
+        } => send_message(config, &room, &message, markdown, notice).await,
+        Command::ShadowDiff { a, b } => shadow_diff(&a, &b),
+    }
+}
+
+/// Log in with the saved session; callers catch up with a sync of their own
+async fn restore_session(config: &BotConfig) -> Result<Client> {
+    let session_file = config.store_path.join("session.json");
+    if !session_file.exists() {
+        return Err(anyhow!(
+            "No saved session in {:?}; start the bot once first",
+            config.store_path
+        ))
+        .context(BotError::Config);
+    }
+    let homeserver = config::env_required("MATRIX_HOMESERVER")?;
+    let username = config::env_required("MATRIX_USER")?;
+    let password = config::env_required("MATRIX_PASSWORD")?;
+    let (client, _) = client::restore_or_login(
+        &session_file,
+        &homeserver,
+        &username,
+        &password,
+        &config.store_path,
+        &password,
+    )
+    .await?;
+    Ok(client)
+}
+
+/// The message text of `--message`, read from `stdin` if it is `-`
+pub fn message_body(message: &str, mut stdin: impl Read) -> Result<String> {
+    let body = if message == "-" {
+        let mut body = String::new();
+        stdin
+            .read_to_string(&mut body)
+            .context("Failed to read the message from stdin")?;
+        body.trim_end().to_string()
+    } else {
+        message.to_string()
+    };
+    if body.trim().is_empty() {
+        return Err(anyhow!("The message is empty")).context(BotError::Config);
+    }
+    Ok(body)
+}
+
+/// Content of a sent message; `--markdown --notice` is a formatted notice
+pub fn message_content(body: &str, markdown: bool, notice: bool) -> RoomMessageEventContent {
+    match (markdown, notice) {
+        (true, true) => RoomMessageEventContent::notice_markdown(body),
+        (true, false) => RoomMessageEventContent::text_markdown(body),
+        (false, true) => RoomMessageEventContent::notice_plain(body),
+        (false, false) => RoomMessageEventContent::text_plain(body),
+    }
+}
+
+async fn send_message(
+    config: &BotConfig,
+    room: &str,
+    message: &str,
+    markdown: bool,
+    notice: bool,
+) -> Result<()> {
+    let body = message_body(message, std::io::stdin())?;
+    let id_or_alias = OwnedRoomOrAliasId::try_from(room)
+        .with_context(|| format!("{} is not a room ID or alias", room))
+        .context(BotError::Config)?;
+    let _lock = StoreLock::acquire(&config.store_path)?;
+
+    let client = restore_session(config).await?;
+    // The catch-up brings the room list and the members' devices up to date,
+    // so the message can be encrypted for everyone; nothing is waited for
+    tokio::time::timeout(
+        Duration::from_secs(60),
+        client.sync_once(SyncSettings::default().timeout(Duration::ZERO)),
+    )
+    .await
+    .context("Initial sync timed out")?
+    .context("Initial sync failed")?;
+    let room_id = AliasResolver::for_client(client.clone())
+        .resolve_room(id_or_alias.as_str())
+        .await?;
+    let matrix_room = client
+        .get_room(&room_id)
+        .filter(|matrix_room| matrix_room.state() == RoomState::Joined)
+        .with_context(|| format!("The bot has not joined {}", room))?;
+
+    let content = message_content(&body, markdown, notice);
+    let event_id = Runtime::default()
+        .pacer
+        .send("message", || matrix_room.send(content.clone()))
+        .await
+        .with_context(|| format!("Failed to send to {}", room))?
+        .event_id;
+    info!("📣 Sent {} to {}", event_id, room_id);
+    println!("{}", event_id);
+    Ok(())
+}
+
+async fn replay_failed(config: &BotConfig, since: Option<String>) -> Result<()> {
+    let since = match since {
+        Some(since) => Some(
+            replay::parse_since(&since)
+                .with_context(|| format!("Invalid --since {:?} (e.g. 30m, 6h, 2d)", since))?,
+        ),
+        None => None,
+    };
+    let _lock = StoreLock::acquire(&config.store_path)?;
+
+    // Room list and encryption state must be current before answers go out
+    let client = restore_session(config).await?;
+    client
+        .sync_once(SyncSettings::default().timeout(Duration::from_secs(30)))
+        .await?;
+
+    let runtime =
+        Runtime::new(Catalog::load(config.i18n_file.as_deref()).context(BotError::Config)?);
+    let replayer = Replayer::new(
+        Arc::new(FailedRequests::open(
+            &config.store_path,
+            config.replay_max_age,
+        )?),
+        Arc::new(VerjiAgentResponder::new(&runtime)?),
+        Arc::new(SentEventRegistry::open(
+            &config.store_path,
+            config.sent_events_max,
+            config.sent_events_max_age,
+        )?),
+        config.replay_interval,
+        runtime,
+    );
+    let summary = replayer.run(&client, since).await?;
+    println!("{}", summary.render());
+    Ok(())
+}
+
+async fn erase_user(config: &BotConfig, user_id: &str, dry_run: bool) -> Result<()> {
+    let _lock = StoreLock::acquire(&config.store_path)?;
+
+    let erasure = Erasure::new(
+        Arc::new(UsageStats::open(
+            &config.store_path,
+            config.history_max_age,
+        )?),
+        Arc::new(QuotaStore::open(&config.store_path)?),
+        Arc::new(ConversationStore::open(
+            &config.store_path,
+            config.conversation_max_entries,
+        )?),
+        Arc::new(FollowUpScheduler::open(
+            &config.store_path,
+            config.follow_ups_per_room_max,
+        )?),
+        Arc::new(FailedRequests::open(
+            &config.store_path,
+            config.replay_max_age,
+        )?),
+        Arc::new(FeedbackStore::open(&config.store_path)?),
+        Arc::new(PreferenceStore::open(&config.store_path)?),
+        Arc::new(VerjiAgentResponder::new(&Runtime::default())?),
+    );
+    let identity = config.identities.canonical(user_id);
+    let report = erasure.erase(user_id, &identity, dry_run).await?;
+    print!("{}", report.render());
+    Ok(())
+}
+
+async fn maintain_store(config: &BotConfig) -> Result<()> {
+    let _lock = StoreLock::acquire(&config.store_path)?;
+
+    // Opening the bot's stores prunes their expired rows
+    UsageStats::open(&config.store_path, config.history_max_age)?;
+    QuotaStore::open(&config.store_path)?;
+    ConversationStore::open(&config.store_path, config.conversation_max_entries)?;
+
+    info!("🧹 Vacuuming databases in {:?}", config.store_path);
+    let store_path = config.store_path.clone();
+    let reports = tokio::task::spawn_blocking(move || store::vacuum(&store_path)).await??;
+    print!("{}", store::render_report(&reports));
+    Ok(())
+}
+
+fn export_feedback(config: &BotConfig, since: Option<String>, out: Option<PathBuf>) -> Result<()> {
+    let since = match since {
+        Some(since) => chrono::NaiveDate::parse_from_str(&since, "%Y-%m-%d")
+            .with_context(|| format!("Invalid --since {:?} (expected YYYY-MM-DD)", since))?
+            .and_hms_opt(0, 0, 0)
+            .map_or(0, |start| start.and_utc().timestamp().max(0) as u64),
+        None => 0,
+    };
+    let feedback = FeedbackStore::open(&config.store_path)?;
+
+    match out {
+        Some(path) => {
+            let mut file = std::fs::File::create(&path)
+                .with_context(|| format!("Failed to create {:?}", path))?;
+            feedback.export_csv(&mut file, since, config.export_anonymize)?;
+            info!("✅ Feedback exported to {:?}", path);
+        }
+        None => {
+            let stdout = std::io::stdout();
+            feedback.export_csv(&mut stdout.lock(), since, config.export_anonymize)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn export_stats(config: &BotConfig, out: Option<PathBuf>) -> Result<()> {
+    let stats = UsageStats::open(&config.store_path, config.history_max_age)?;
+
+    match out {
+        Some(path) => {
+            let mut file = std::fs::File::create(&path)
+                .with_context(|| format!("Failed to create {:?}", path))?;
+            stats.export_csv(&mut file)?;
+            info!("✅ Usage statistics exported to {:?}", path);
+        }
+        None => {
+            let stdout = std::io::stdout();
+            stats.export_csv(&mut stdout.lock())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the triggers two shadow logs answered differently, and a count of
+/// the ones they agree on
+fn shadow_diff(a: &Path, b: &Path) -> Result<()> {
+    let diff = shadow::diff(&shadow::read_log(a)?, &shadow::read_log(b)?);
+    print!("{}", diff.render());
+    Ok(())
+}
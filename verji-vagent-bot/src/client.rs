@@ -1,10 +1,18 @@
 use anyhow::{Context, Result};
 use matrix_sdk::{encryption::EncryptionSettings, Client};
 use std::path::PathBuf;
+use std::time::Duration;
 use tracing::{info, warn};
 
+use crate::error::BotError;
 use crate::session;
 
+/// Device display name used when `MATRIX_DEVICE_DISPLAY_NAME` is unset
+pub const DEFAULT_DEVICE_DISPLAY_NAME: &str = "Verji vAgent Bot";
+
+/// Login attempts while the homeserver is rate limiting or unreachable
+const LOGIN_ATTEMPTS: u32 = 3;
+
 /// Build a new Matrix client with encryption settings
 pub async fn build_client(
     homeserver: &str,
@@ -33,6 +41,7 @@ pub async fn restore_or_login(
     password: &str,
     store_path_buf: &PathBuf,
     store_passphrase: &str,
+    device_display_name: &str,
 ) -> Result<(Client, &'static str)> {
     info!("📁 Found saved session file, attempting to restore...");
 
@@ -48,10 +57,13 @@ pub async fn restore_or_login(
                 build_client(&full_session.client_session.homeserver, store_path_buf, store_passphrase).await?;
 
             // Restore the session
-            client
-                .restore_session(full_session.user_session)
-                .await
-                .context("Failed to restore session")?;
+            if let Err(e) = client.restore_session(full_session.user_session).await {
+                let e = anyhow::Error::new(e);
+                if BotError::classify(&e) == Some(BotError::CryptoStoreMismatch) {
+                    warn!("⚠️  The store in {:?} wasn't created for the saved session's device", store_path_buf);
+                }
+                return Err(e.context("Failed to restore session"));
+            }
 
             info!("✅ Session restored successfully");
             Ok((client, "restored"))
@@ -69,6 +81,7 @@ pub async fn restore_or_login(
                 store_path_buf,
                 store_passphrase,
                 session_file,
+                device_display_name,
             )
             .await
         }
@@ -84,6 +97,7 @@ pub async fn fresh_login(
     store_path_buf: &PathBuf,
     store_passphrase: &str,
     session_file: &PathBuf,
+    device_display_name: &str,
 ) -> Result<(Client, &'static str)> {
     info!("📝 Performing fresh login");
 
@@ -92,12 +106,25 @@ pub async fn fresh_login(
 
     // Login
     info!("🔐 Logging in as: {}", username);
-    client
+    let mut attempt = 1;
+    while let Err(e) = client
         .matrix_auth()
         .login_username(username, password)
-        .initial_device_display_name("Verji vAgent Bot")
+        .initial_device_display_name(device_display_name)
         .await
-        .context("Failed to login")?;
+    {
+        let e = anyhow::Error::new(e);
+        let wait = match BotError::classify(&e) {
+            Some(class) if class.is_transient() && attempt < LOGIN_ATTEMPTS => match class {
+                BotError::RateLimited { retry_after: Some(retry_after) } => retry_after,
+                _ => Duration::from_secs(2u64.pow(attempt)),
+            },
+            _ => return Err(e.context("Failed to login")),
+        };
+        warn!("⚠️  Login failed ({:#}), retrying in {}s", e, wait.as_secs());
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
 
     info!("✅ Successfully logged in");
     if let Some(user_id) = client.user_id() {
@@ -113,6 +140,35 @@ pub async fn fresh_login(
     Ok((client, "new_login"))
 }
 
+/// Rename the bot's device if the server has a different display name for it
+///
+/// Returns whether the name was changed.
+pub async fn update_device_display_name(client: &Client, display_name: &str) -> Result<bool> {
+    let device_id = client.device_id().context("Client has no device ID")?;
+    let devices = client.devices().await.context("Failed to list devices")?;
+    let current = devices
+        .devices
+        .into_iter()
+        .find(|device| device.device_id == device_id)
+        .and_then(|device| device.display_name);
+
+    if current.as_deref() == Some(display_name) {
+        return Ok(false);
+    }
+
+    client
+        .rename_device(device_id, display_name)
+        .await
+        .context("Failed to rename device")?;
+    info!(
+        "🏷️  Device {} renamed from {:?} to {:?}",
+        device_id,
+        current.unwrap_or_default(),
+        display_name
+    );
+    Ok(true)
+}
+
 /// Clear the store directory with retry logic for Windows
 pub async fn clear_store(store_path: &PathBuf) -> Result<()> {
     if !store_path.exists() {
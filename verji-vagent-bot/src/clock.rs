@@ -0,0 +1,17 @@
+/// Source of wall-clock time, replaceable in tests
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the unix epoch
+    fn now_ms(&self) -> u64;
+}
+
+/// The real system clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+}
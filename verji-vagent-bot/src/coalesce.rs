@@ -0,0 +1,153 @@
+//! Merging of quick consecutive messages from one user into a single query
+//!
+//! People often type a question across several messages ("hey", "can you
+//! check", "why invoice 1234 failed?"). The first message of a burst waits
+//! for the window; messages arriving meanwhile are appended to it and
+//! handled as one, newline-joined.
+
+use matrix_sdk::ruma::OwnedEventId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::conversation::ConversationKey;
+
+/// A burst never waits longer than this many windows in total
+const MAX_WINDOWS: u32 = 3;
+
+/// Slot naming coalescing buffers in their (room, thread, user) key
+const SLOT: &str = "coalesce";
+
+struct Pending {
+    bodies: Vec<String>,
+    /// Messages of the burst, oldest first
+    event_ids: Vec<OwnedEventId>,
+    /// Latest message of the burst; replies go to it
+    event_id: OwnedEventId,
+    deadline: Instant,
+    /// Deadline never moves past this
+    hard_deadline: Instant,
+}
+
+/// A burst of messages ready to be handled
+pub struct Burst {
+    pub body: String,
+    pub event_id: OwnedEventId,
+    /// Every message merged into the burst, oldest first
+    pub event_ids: Vec<OwnedEventId>,
+    pub messages: usize,
+}
+
+/// Per-(room, thread, user) debounce buffers
+pub struct Coalescer {
+    window: Duration,
+    pending: Mutex<HashMap<ConversationKey, Pending>>,
+    /// Wakes every waiting burst early (shutdown)
+    flush: Notify,
+}
+
+impl Coalescer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Mutex::new(HashMap::new()),
+            flush: Notify::new(),
+        }
+    }
+
+    /// Buffer key for a sender in a room (and thread)
+    pub fn key(room_id: &str, thread_id: Option<&str>, sender: &str) -> ConversationKey {
+        let key = ConversationKey::new(room_id, sender, SLOT);
+        match thread_id {
+            Some(thread_id) => key.in_thread(thread_id),
+            None => key,
+        }
+    }
+
+    /// Add a message to its sender's burst
+    ///
+    /// Returns the whole burst to the caller that started it once the window
+    /// closes without new messages; later callers get `None` because their
+    /// message was merged. Each new message restarts the window.
+    pub async fn submit(
+        &self,
+        key: ConversationKey,
+        body: String,
+        event_id: OwnedEventId,
+    ) -> Option<Burst> {
+        if self.window.is_zero() {
+            return Some(Burst {
+                body,
+                event_ids: vec![event_id.clone()],
+                event_id,
+                messages: 1,
+            });
+        }
+
+        let now = Instant::now();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(burst) = pending.get_mut(&key) {
+                burst.bodies.push(body);
+                burst.event_ids.push(event_id.clone());
+                burst.event_id = event_id;
+                burst.deadline = (now + self.window).min(burst.hard_deadline);
+                debug!("🧩 Merged message into pending burst of {}", key.user_id);
+                return None;
+            }
+            pending.insert(
+                key.clone(),
+                Pending {
+                    bodies: vec![body],
+                    event_ids: vec![event_id.clone()],
+                    event_id,
+                    deadline: now + self.window,
+                    hard_deadline: now + self.window * MAX_WINDOWS,
+                },
+            );
+        }
+
+        loop {
+            let deadline = self.deadline(&key)?;
+            if Instant::now() >= deadline {
+                break;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.flush.notified() => break,
+            }
+        }
+
+        let burst = self.pending.lock().unwrap().remove(&key)?;
+        Some(Burst {
+            messages: burst.bodies.len(),
+            body: burst.bodies.join("\n"),
+            event_id: burst.event_id,
+            event_ids: burst.event_ids,
+        })
+    }
+
+    fn deadline(&self, key: &ConversationKey) -> Option<Instant> {
+        self.pending
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|burst| burst.deadline)
+    }
+
+    fn has_pending(&self) -> bool {
+        !self.pending.lock().unwrap().is_empty()
+    }
+
+    /// Release every waiting burst now and wait until they were picked up
+    pub async fn flush(&self) {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while self.has_pending() && Instant::now() < deadline {
+            self.flush.notify_waiters();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
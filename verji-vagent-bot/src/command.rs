@@ -0,0 +1,206 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::i18n::t;
+use crate::responder::{Responder, ResponderContext, ResponderResult, Route};
+
+/// Prefix that marks an explicit bot command
+pub const COMMAND_PREFIX: char = '!';
+
+/// Declarative description of a command: names, arity and access
+pub struct CommandSpec {
+    /// Command name and aliases, matched case-insensitively against the first word
+    /// (e.g. `&["!ping", "ping"]`)
+    pub names: &'static [&'static str],
+    /// Minimum number of arguments after the command name
+    pub min_args: usize,
+    /// Maximum number of arguments, `None` for unlimited
+    pub max_args: Option<usize>,
+    /// Restrict the command to `ADMIN_USERS`
+    pub admin_only: bool,
+    /// Usage line shown when the arguments do not fit
+    pub usage: &'static str,
+}
+
+impl CommandSpec {
+    fn accepts(&self, arg_count: usize) -> bool {
+        arg_count >= self.min_args && !self.max_args.is_some_and(|max| arg_count > max)
+    }
+}
+
+/// Helper layer for command-style responders
+///
+/// Implementors declare a [`CommandSpec`] and receive tokenized arguments in
+/// `run`; `should_handle`, argument parsing, arity checks and admin checks are
+/// provided by the blanket [`Responder`] implementation below.
+///
+/// Names with the `!` prefix always claim the message and reply with usage
+/// when the arguments do not fit. Bare aliases (`ping`, `help`) only match when
+/// the arity fits, so "help me write an email" still reaches the agent.
+#[async_trait]
+pub trait CommandResponder: Send + Sync {
+    /// Returns the name of this responder
+    fn name(&self) -> &str;
+
+    /// Returns the priority of this responder (higher = checked first)
+    /// Default priority is 90, the usual slot for commands
+    fn priority(&self) -> i32 {
+        90
+    }
+
+    /// Maximum time `run()` may take, see [`Responder::timeout`]
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Names, arity and access of this command
+    fn spec(&self) -> CommandSpec;
+
+    /// Execute the command with its parsed arguments
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult>;
+}
+
+/// Split a message into its command word and the remaining argument text
+fn split_command(message: &str) -> (&str, &str) {
+    let message = message.trim();
+    match message.split_once(char::is_whitespace) {
+        Some((command, rest)) => (command, rest.trim_start()),
+        None => (message, ""),
+    }
+}
+
+/// Split command arguments into words
+///
+/// Whitespace separates arguments; double quotes (ASCII or typographic “…”)
+/// group words into one argument, and `""` yields an empty argument. Inside
+/// and outside quotes `\"` and `\\` are escapes; any other backslash is kept
+/// literally. Apostrophes are ordinary characters so "don't" needs no quoting.
+pub fn tokenize(input: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Whether `current` holds an argument, so that `""` produces an empty one
+    let mut in_arg = false;
+    let mut in_quotes = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(&next) if next == '"' || next == '\\' => {
+                    current.push(next);
+                    chars.next();
+                    in_arg = true;
+                }
+                _ => {
+                    current.push('\\');
+                    in_arg = true;
+                }
+            },
+            '"' | '“' | '”' => {
+                in_quotes = !in_quotes;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        bail!("Unterminated quote in arguments");
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+#[async_trait]
+impl<T: CommandResponder> Responder for T {
+    fn name(&self) -> &str {
+        CommandResponder::name(self)
+    }
+
+    fn priority(&self) -> i32 {
+        CommandResponder::priority(self)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        CommandResponder::timeout(self)
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.route(context).await == Route::Accept
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        let spec = self.spec();
+        let (command, rest) = split_command(&context.message_body);
+        let Some(name) = spec
+            .names
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(command))
+        else {
+            return Route::decline("not_a_command");
+        };
+
+        if name.starts_with(COMMAND_PREFIX) {
+            return Route::Accept;
+        }
+        // Bare aliases only match well-formed invocations
+        if tokenize(rest).is_ok_and(|args| spec.accepts(args.len())) {
+            Route::Accept
+        } else {
+            Route::decline("alias_arguments_mismatch")
+        }
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let spec = self.spec();
+
+        if spec.admin_only && !context.sender_is_admin() {
+            warn!(
+                "⛔ Non-admin {} tried to run admin command {}",
+                context.sender,
+                CommandResponder::name(self)
+            );
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "command.admin_only",
+                &[],
+            ))));
+        }
+
+        let (_, rest) = split_command(&context.message_body);
+        let args = match tokenize(rest) {
+            Ok(args) => args,
+            Err(e) => {
+                let error = e.to_string();
+                return Ok(ResponderResult::Handled(Some(t(
+                    context,
+                    "command.bad_arguments",
+                    &[("error", &error), ("usage", spec.usage)],
+                ))));
+            }
+        };
+
+        if !spec.accepts(args.len()) {
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "command.usage",
+                &[("usage", spec.usage)],
+            ))));
+        }
+
+        self.run(context, args).await
+    }
+}
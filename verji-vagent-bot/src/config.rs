@@ -0,0 +1,461 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::archive::ArchivePolicy;
+use crate::client;
+use crate::custom_events::{self, Treatment};
+use crate::error::BotError;
+use crate::i18n::CannedReply;
+use crate::identity::IdentityRules;
+use crate::intent::IntentFilterMode;
+use crate::key_import::RoomKeysImport;
+use crate::quota::{DailyQuotaPolicy, Quota, RoomBudgetPolicy};
+use crate::redact::Redactor;
+use crate::room::RoomScope;
+use crate::room_config::{ReplyMode, ResponseFormat, StickerMode};
+use crate::self_test::SelfTest;
+use crate::system_rooms::SystemRoomPolicy;
+use crate::warmup::WarmupRooms;
+use crate::webhook::WebhookConfig;
+
+/// Runtime configuration shared by the dispatcher and responders
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct BotConfig {
+    /// Directory holding the Matrix store, session file and bot databases
+    pub store_path: PathBuf,
+    /// Display name of the bot's device, set at login and kept in sync at startup
+    pub device_display_name: String,
+    /// Default language for the bot's own replies (rooms can override it)
+    pub locale: String,
+    /// Optional JSON file extending or overriding the built-in message catalog
+    pub i18n_file: Option<PathBuf>,
+    /// User IDs allowed to run `!admin` commands
+    pub admin_users: Vec<String>,
+    /// How often in-memory usage counters are flushed to disk
+    pub stats_flush_interval: Duration,
+    /// How long agent interactions are kept for `!history`
+    pub history_max_age: Duration,
+    /// How often a running bot vacuums its store databases (0 = never)
+    pub store_maintenance_interval: Duration,
+    /// How often the store directory is checked for writability and free space
+    pub store_check_interval: Duration,
+    /// Free space below which the store counts as unhealthy
+    pub store_min_free_bytes: u64,
+    /// Room the bot posts operational alerts to (None = log only)
+    pub admin_room: Option<String>,
+    /// Post a startup summary to the admin room (STARTUP_ANNOUNCE)
+    pub startup_announce: bool,
+    /// Element room key export imported once at startup
+    pub room_keys_import: Option<RoomKeysImport>,
+    /// Daily canary question to vagent-graph (None = no self-test)
+    pub self_test: Option<SelfTest>,
+    /// Messages from one user within this window are merged into one query (0 = off)
+    pub message_coalesce: Duration,
+    /// Extra wait after coalescing in which deleting a message cancels it
+    pub processing_delay: Duration,
+    /// Quiet time after which a "still working" notice is posted (zero = never)
+    pub still_working_after: Duration,
+    /// Whether that notice is an `m.notice` rather than `m.text`
+    pub still_working_notice: bool,
+    /// Rooms the bot answers in (empty = all rooms)
+    pub allowed_rooms: Vec<String>,
+    /// Users the bot answers (empty = all users)
+    pub allowed_users: Vec<String>,
+    /// Users the bot never answers
+    pub denied_users: Vec<String>,
+    /// Room ID or alias of a shared ban list (m.policy.rule.* state events)
+    pub policy_room: Option<String>,
+    /// Messages per user per minute before rate limiting kicks in (0, the default, = unlimited)
+    pub rate_limit_per_minute: usize,
+    /// Whether the chain continues or aborts after a responder timeout
+    pub responder_timeout_policy: String,
+    /// Reply sent when a responder times out
+    pub responder_timeout_reply: CannedReply,
+    /// Whether the chain continues, replies or aborts after a responder error/panic
+    pub responder_error_policy: String,
+    /// Reply sent with the "reply" error policy
+    pub responder_error_reply: CannedReply,
+    /// Routing traces kept in memory for `!admin trace` (0 = none)
+    pub routing_trace_max: usize,
+    /// Rooms (IDs or aliases) each responder is limited to, keyed by responder name
+    pub responder_rooms: HashMap<String, Vec<String>>,
+    /// Minimum time between firings of a responder in the same room, keyed by responder name
+    pub responder_cooldowns: HashMap<String, Duration>,
+    /// Per-responder usage quotas, keyed by responder name
+    pub responder_quotas: HashMap<String, Quota>,
+    /// Prompt shortcuts (`/sql` -> template with `{}` for the rest of the message)
+    pub prompt_shortcuts: HashMap<String, String>,
+    /// Agent graphs rooms can choose with `!agent`, name -> description
+    pub agents: BTreeMap<String, String>,
+    /// Agent used by rooms that haven't chosen one (None = the graph's default)
+    pub default_agent: Option<String>,
+    /// Unanswered HITL questions are repeated to the user after this long
+    pub hitl_reminder_after: Duration,
+    /// Unanswered HITL questions are cancelled after this long
+    pub hitl_timeout: Duration,
+    /// Pending follow-ups (reminders, timeouts) allowed per room
+    pub follow_ups_per_room_max: usize,
+    /// Failed agent requests older than this are dropped instead of replayed
+    pub replay_max_age: Duration,
+    /// Pause between two replayed requests
+    pub replay_interval: Duration,
+    /// Undelivered answers older than this are abandoned (zero = no outbox)
+    pub outbox_max_age: Duration,
+    /// First wait before an undelivered answer is sent again
+    pub outbox_retry: Duration,
+    /// Longest wait between two delivery attempts
+    pub outbox_retry_max: Duration,
+    /// Answers a room refuses go to the asker's direct chat
+    pub fallback_dm: bool,
+    /// Encrypted rooms whose members are loaded right after the initial sync
+    pub warmup_rooms: WarmupRooms,
+    /// Pause between two warmed-up rooms
+    pub warmup_interval: Duration,
+    /// Longest the warm-up may hold back the startup
+    pub warmup_budget: Duration,
+    /// Replace Matrix IDs with hashes in CSV exports of feedback
+    pub export_anonymize: bool,
+    /// Sent events kept for correlating reactions and redactions
+    pub sent_events_max: usize,
+    /// Sent events older than this are forgotten
+    pub sent_events_max_age: Duration,
+    /// How long paged output can be turned with ▶️/◀️
+    pub pager_ttl: Duration,
+    /// Which messages the bot answers in rooms without their own reply mode
+    pub reply_mode: ReplyMode,
+    /// Start answers in group rooms with a pill for whoever asked, unless
+    /// they turned it off with `!prefs address off`
+    pub address_by_name: bool,
+    /// Show the bot typing in a room while any of its messages is being handled
+    pub typing_indicator: bool,
+    /// Plain or formatted answers in rooms without their own format
+    pub response_format: ResponseFormat,
+    /// What to do with stickers in rooms without their own sticker mode
+    pub sticker_mode: StickerMode,
+    /// Reaction used by the `ack` sticker mode
+    pub sticker_ack: String,
+    /// Custom event type or msgtype -> what to do with it (CUSTOM_EVENTS)
+    pub custom_events: HashMap<String, Treatment>,
+    /// Recent room messages sent to the agent as context (0 = none)
+    pub context_messages: usize,
+    /// Token budget for the room context plus the triggering message
+    pub context_token_budget: usize,
+    /// Retries with less context after the graph refuses a query as too large (0 = none)
+    pub context_retries: usize,
+    /// How often the Redis connection is probed with PING (0 = never)
+    pub redis_keepalive: Duration,
+    /// Maps bridged MXIDs to one identity per person (SENDER_IDENTITY_RULES)
+    pub identities: IdentityRules,
+    /// Keeps acknowledgements like "ok" or "thanks" from the agent (INTENT_FILTER)
+    pub intent_filter: IntentFilterMode,
+    /// Reaction to acknowledgements the filter drops (None = no reaction)
+    pub intent_filter_reaction: Option<String>,
+    /// Masks personal data in text sent to the agent (None = `REDACT_PII` off)
+    pub redactor: Option<Arc<Redactor>>,
+    /// Quote the user's message in agent error replies
+    pub error_echo_message: bool,
+    /// Add the request ID to agent error replies, for support
+    pub error_show_reference: bool,
+    /// Times a failed agent query can be retried with a 🔁 reaction (0 = off)
+    pub retry_max: u32,
+    /// Random request IDs instead of ones derived from the event ID
+    pub random_request_ids: bool,
+    /// Record per-stage timings of agent requests in logs and metrics
+    pub profile_pipeline: bool,
+    /// Append the timings to agent answers shown to admins
+    pub profile_footer: bool,
+    /// Inbound webhook listener (None = `WEBHOOK_ADDR` unset)
+    pub webhook: Option<WebhookConfig>,
+    /// Maximum conversation state entries kept before LRU eviction
+    pub conversation_max_entries: usize,
+    /// Whether conversation state survives restarts (stored in the bot database)
+    pub conversation_persist: bool,
+    /// Per-user agent queries per day
+    pub daily_quota: DailyQuotaPolicy,
+    /// Monthly spend per room on agent answers (MONTHLY_BUDGET)
+    pub room_budget: RoomBudgetPolicy,
+    /// Append the tokens and cost of agent answers shown to admins
+    pub usage_footer: bool,
+    /// Markers that make a room archived and the bot read-only in it
+    pub archive: ArchivePolicy,
+    /// How server-notice and other system rooms are recognized
+    pub system_rooms: SystemRoomPolicy,
+    /// Start in maintenance mode regardless of the stored state
+    pub maintenance_mode: bool,
+    /// Notice for maintenance mode set at boot (None = the stored or default one)
+    pub maintenance_message: Option<String>,
+    /// `GET /ready` answers 503 while maintenance mode is on
+    pub maintenance_not_ready: bool,
+    /// Record room writes instead of sending them (SHADOW_MODE)
+    pub shadow_mode: bool,
+    /// Where room writes are recorded (None = `shadow.jsonl` in the store in
+    /// shadow mode, nowhere otherwise)
+    pub shadow_log: Option<PathBuf>,
+}
+
+impl BotConfig {
+    /// Load configuration from environment variables
+    pub fn from_env() -> Self {
+        let store_path =
+            std::env::var("MATRIX_STORE_PATH").unwrap_or_else(|_| "./matrix_store".to_string());
+
+        Self {
+            store_path: PathBuf::from(store_path),
+            device_display_name: std::env::var("MATRIX_DEVICE_DISPLAY_NAME")
+                .ok()
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or_else(|| client::DEFAULT_DEVICE_DISPLAY_NAME.to_string()),
+            locale: std::env::var("LOCALE").unwrap_or_else(|_| "en".to_string()),
+            i18n_file: std::env::var("I18N_FILE").ok().map(PathBuf::from),
+            admin_users: env_list("ADMIN_USERS"),
+            stats_flush_interval: Duration::from_secs(env_u64("STATS_FLUSH_SECS", 60)),
+            history_max_age: Duration::from_secs(env_u64("HISTORY_MAX_AGE_DAYS", 90) * 24 * 3600),
+            store_maintenance_interval: Duration::from_secs(
+                env_u64("STORE_MAINTENANCE_INTERVAL_HOURS", 0) * 3600,
+            ),
+            store_check_interval: Duration::from_secs(env_u64("STORE_CHECK_INTERVAL_SECS", 60)),
+            store_min_free_bytes: env_u64("STORE_MIN_FREE_MB", 100) * 1024 * 1024,
+            admin_room: std::env::var("ADMIN_ROOM")
+                .ok()
+                .filter(|room| !room.is_empty()),
+            startup_announce: env_bool("STARTUP_ANNOUNCE", true),
+            room_keys_import: RoomKeysImport::from_env(),
+            self_test: SelfTest::from_env(),
+            message_coalesce: Duration::from_millis(env_u64("MESSAGE_COALESCE_MS", 0)),
+            processing_delay: Duration::from_millis(env_u64("PROCESSING_DELAY_MS", 0)),
+            still_working_after: Duration::from_secs(env_u64("STILL_WORKING_SECS", 20)),
+            still_working_notice: std::env::var("STILL_WORKING_MSGTYPE")
+                .map(|msgtype| !msgtype.trim().eq_ignore_ascii_case("text"))
+                .unwrap_or(true),
+            allowed_rooms: env_list("ALLOWED_ROOMS"),
+            allowed_users: env_list("ALLOWED_USERS"),
+            denied_users: env_list("DENIED_USERS"),
+            policy_room: std::env::var("POLICY_ROOM")
+                .ok()
+                .filter(|room| !room.is_empty()),
+            rate_limit_per_minute: env_u64("RATE_LIMIT_PER_MINUTE", 0) as usize,
+            responder_timeout_policy: std::env::var("RESPONDER_TIMEOUT_POLICY")
+                .unwrap_or_else(|_| "continue".to_string()),
+            responder_timeout_reply: CannedReply::from_env("RESPONDER_TIMEOUT_REPLY"),
+            responder_error_policy: std::env::var("RESPONDER_ERROR_POLICY")
+                .unwrap_or_else(|_| "continue".to_string()),
+            responder_error_reply: CannedReply::from_env("RESPONDER_ERROR_REPLY"),
+            routing_trace_max: env_u64("ROUTING_TRACE_MAX", 100) as usize,
+            responder_rooms: env_map("RESPONDER_ROOMS")
+                .into_iter()
+                .map(|(responder, rooms)| {
+                    let rooms = rooms
+                        .split(',')
+                        .map(|room| room.trim().to_string())
+                        .filter(|room| !room.is_empty())
+                        .collect();
+                    (responder, rooms)
+                })
+                .collect(),
+            responder_cooldowns: env_map("RESPONDER_COOLDOWNS")
+                .into_iter()
+                .filter_map(|(responder, secs)| {
+                    let secs: u64 = secs.parse().ok()?;
+                    Some((responder, Duration::from_secs(secs)))
+                })
+                .collect(),
+            responder_quotas: env_map("RESPONDER_QUOTAS")
+                .into_iter()
+                .filter_map(|(responder, quota)| Some((responder, Quota::parse(&quota)?)))
+                .collect(),
+            prompt_shortcuts: env_map("PROMPT_SHORTCUTS"),
+            agents: env_map("AGENTS").into_iter().collect(),
+            default_agent: std::env::var("DEFAULT_AGENT")
+                .ok()
+                .filter(|agent| !agent.is_empty()),
+            hitl_reminder_after: Duration::from_secs(env_u64("HITL_REMINDER_SECS", 900)),
+            hitl_timeout: Duration::from_secs(env_u64("HITL_TIMEOUT_SECS", 3600)),
+            follow_ups_per_room_max: env_u64("FOLLOW_UPS_PER_ROOM_MAX", 50) as usize,
+            replay_max_age: Duration::from_secs(env_u64("REPLAY_MAX_AGE_HOURS", 24) * 3600),
+            replay_interval: Duration::from_millis(env_u64("REPLAY_INTERVAL_MS", 2000)),
+            outbox_max_age: Duration::from_secs(env_u64("OUTBOX_MAX_AGE_HOURS", 6) * 3600),
+            outbox_retry: Duration::from_secs(env_u64("OUTBOX_RETRY_SECS", 30)),
+            outbox_retry_max: Duration::from_secs(env_u64("OUTBOX_RETRY_MAX_SECS", 900)),
+            fallback_dm: env_bool("FALLBACK_DM", false),
+            warmup_rooms: std::env::var("WARMUP_ROOMS")
+                .ok()
+                .and_then(|rooms| WarmupRooms::parse(&rooms))
+                .unwrap_or_default(),
+            warmup_interval: Duration::from_millis(env_u64("WARMUP_INTERVAL_MS", 500)),
+            warmup_budget: Duration::from_secs(env_u64("WARMUP_BUDGET_SECS", 30)),
+            export_anonymize: env_bool("EXPORT_ANONYMIZE", false),
+            sent_events_max: env_u64("SENT_EVENTS_MAX", 10_000) as usize,
+            sent_events_max_age: Duration::from_secs(
+                env_u64("SENT_EVENTS_MAX_AGE_HOURS", 168) * 3600,
+            ),
+            pager_ttl: Duration::from_secs(env_u64("PAGER_TTL_SECS", 3600)),
+            reply_mode: std::env::var("REPLY_MODE")
+                .ok()
+                .and_then(|mode| ReplyMode::parse(&mode))
+                .unwrap_or_default(),
+            address_by_name: env_bool("ADDRESS_BY_NAME", false),
+            typing_indicator: env_bool("TYPING_INDICATOR", false),
+            response_format: std::env::var("RESPONSE_FORMAT")
+                .ok()
+                .and_then(|format| ResponseFormat::parse(&format))
+                .unwrap_or_default(),
+            sticker_mode: std::env::var("STICKER_MODE")
+                .ok()
+                .and_then(|mode| StickerMode::parse(&mode))
+                .unwrap_or_default(),
+            custom_events: custom_events::parse_treatments(env_map("CUSTOM_EVENTS")),
+            sticker_ack: std::env::var("STICKER_ACK")
+                .ok()
+                .filter(|ack| !ack.trim().is_empty())
+                .unwrap_or_else(|| "👀".to_string()),
+            context_messages: env_u64("CONTEXT_MESSAGES", 20) as usize,
+            context_token_budget: env_u64("CONTEXT_TOKEN_BUDGET", 4000) as usize,
+            context_retries: env_u64("CONTEXT_RETRIES", 2) as usize,
+            redis_keepalive: Duration::from_secs(env_u64("REDIS_KEEPALIVE_SECS", 30)),
+            identities: IdentityRules::from_env(),
+            intent_filter: std::env::var("INTENT_FILTER")
+                .ok()
+                .and_then(|mode| IntentFilterMode::parse(&mode))
+                .unwrap_or_default(),
+            intent_filter_reaction: std::env::var("INTENT_FILTER_REACTION")
+                .ok()
+                .filter(|reaction| !reaction.trim().is_empty()),
+            redactor: env_bool("REDACT_PII", false).then(|| {
+                let patterns: BTreeMap<String, String> =
+                    env_map("REDACT_PATTERNS").into_iter().collect();
+                Arc::new(Redactor::new(
+                    patterns
+                        .iter()
+                        .map(|(name, pattern)| (name.as_str(), pattern.as_str())),
+                ))
+            }),
+            error_echo_message: env_bool("ERROR_ECHO_MESSAGE", false),
+            error_show_reference: env_bool("ERROR_SHOW_REFERENCE", false),
+            retry_max: env_u64("AGENT_RETRY_MAX", 3) as u32,
+            random_request_ids: env_bool("RANDOM_REQUEST_IDS", false),
+            profile_pipeline: env_bool("PROFILE_PIPELINE", false),
+            profile_footer: env_bool("PROFILE_FOOTER", false),
+            webhook: std::env::var("WEBHOOK_ADDR")
+                .ok()
+                .and_then(|addr| addr.trim().parse().ok())
+                .map(|addr| WebhookConfig {
+                    addr,
+                    // Configured as name=token; looked up by token
+                    tokens: env_map("WEBHOOK_TOKENS")
+                        .into_iter()
+                        .map(|(name, token)| (token, name))
+                        .collect(),
+                    rooms: env_list("WEBHOOK_ROOMS"),
+                    rate_per_minute: env_u64("WEBHOOK_RATE_PER_MINUTE", 30) as usize,
+                }),
+            conversation_max_entries: env_u64("CONVERSATION_MAX_ENTRIES", 10_000) as usize,
+            conversation_persist: env_bool("CONVERSATION_PERSIST", true),
+            daily_quota: DailyQuotaPolicy::from_env(),
+            room_budget: RoomBudgetPolicy::from_env(),
+            usage_footer: env_bool("USAGE_FOOTER", false),
+            archive: ArchivePolicy::from_env(),
+            system_rooms: SystemRoomPolicy::from_env(),
+            maintenance_mode: env_bool("MAINTENANCE_MODE", false),
+            maintenance_message: std::env::var("MAINTENANCE_MESSAGE")
+                .ok()
+                .filter(|message| !message.trim().is_empty()),
+            maintenance_not_ready: env_bool("MAINTENANCE_NOT_READY", true),
+            shadow_mode: env_bool("SHADOW_MODE", false),
+            shadow_log: std::env::var("SHADOW_LOG").ok().map(PathBuf::from),
+        }
+    }
+
+    /// Check whether a user is on the admin list
+    pub fn is_admin(&self, user_id: &str) -> bool {
+        self.admin_users.iter().any(|admin| admin == user_id)
+    }
+
+    /// Agent for a room given its selection; selections no longer configured
+    /// fall back to the default
+    pub fn agent_for(&self, selected: Option<&str>) -> Option<String> {
+        match selected {
+            Some(agent) if self.agents.contains_key(agent) => Some(agent.to_string()),
+            _ => self.default_agent.clone(),
+        }
+    }
+
+    /// Every room ID or alias in the room settings, for resolving aliases at startup
+    pub fn configured_rooms(&self) -> Vec<&str> {
+        let mut rooms: Vec<&str> = self
+            .allowed_rooms
+            .iter()
+            .chain(self.responder_rooms.values().flatten())
+            .chain(&self.system_rooms.system_rooms)
+            .map(String::as_str)
+            .collect();
+        rooms.extend(self.admin_room.as_deref());
+        rooms.extend(self.policy_room.as_deref());
+        if let Some(webhook) = &self.webhook {
+            rooms.extend(webhook.rooms.iter().map(String::as_str));
+        }
+        rooms
+    }
+
+    /// Room scope configured for a responder (everywhere unless listed)
+    pub fn responder_scope(&self, responder: &str) -> RoomScope {
+        match self.responder_rooms.get(responder) {
+            Some(rooms) => RoomScope::Rooms(rooms.clone()),
+            None => RoomScope::Everywhere,
+        }
+    }
+}
+
+/// Read keyed settings written as `key=value;other=value`
+pub fn env_map(name: &str) -> HashMap<String, String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(responder, value)| (responder.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// Read a comma-separated list, ignoring empty entries
+pub fn env_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Read a boolean ("true"/"1"/"yes"/"on" or "false"/"0"/"no"/"off")
+pub fn env_bool(name: &str, default: bool) -> bool {
+    match std::env::var(name) {
+        Ok(value) => match value.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => true,
+            "false" | "0" | "no" | "off" => false,
+            _ => default,
+        },
+        Err(_) => default,
+    }
+}
+
+/// Read a setting the bot can't run without; a missing one is a
+/// [`BotError::Config`]
+pub fn env_required(name: &str) -> anyhow::Result<String> {
+    use anyhow::Context;
+    std::env::var(name)
+        .with_context(|| format!("{} environment variable not set", name))
+        .context(BotError::Config)
+}
+
+/// Read an unsigned integer, falling back to the default when unset or invalid
+pub fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
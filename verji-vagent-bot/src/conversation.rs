@@ -0,0 +1,592 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+
+use crate::clock::{Clock, SystemClock};
+use crate::db;
+
+/// Identifies one piece of conversation state
+///
+/// `slot` names the feature that owns the value (e.g. `hitl.pending`), so
+/// features sharing a (room, thread, user) never collide.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConversationKey {
+    pub room_id: String,
+    /// Thread root event ID, `None` for the main timeline
+    pub thread_id: Option<String>,
+    pub user_id: String,
+    pub slot: String,
+}
+
+impl ConversationKey {
+    pub fn new(room_id: &str, user_id: &str, slot: &str) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            thread_id: None,
+            user_id: user_id.to_string(),
+            slot: slot.to_string(),
+        }
+    }
+
+    /// Scope the key to a thread
+    pub fn in_thread(mut self, thread_id: &str) -> Self {
+        self.thread_id = Some(thread_id.to_string());
+        self
+    }
+}
+
+/// Feature-specific work done by the sweep task on every run
+///
+/// Hooks run before expired entries are dropped, so features can act on
+/// their own deadlines (reminders, cancellations) within the same task.
+#[async_trait]
+pub trait SweepHook: Send + Sync {
+    async fn on_sweep(&self, store: &ConversationStore);
+}
+
+struct Entry {
+    value: Value,
+    /// Unix seconds after which the entry is gone (None = no expiry)
+    expires_at: Option<u64>,
+    /// Position in the LRU order
+    tick: u64,
+}
+
+impl Entry {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+}
+
+/// Map plus recency index; all operations are O(log n)
+#[derive(Default)]
+struct Entries {
+    map: HashMap<ConversationKey, Entry>,
+    /// tick -> key, oldest first
+    lru: BTreeMap<u64, ConversationKey>,
+    next_tick: u64,
+}
+
+impl Entries {
+    fn touch(&mut self, key: &ConversationKey) {
+        let tick = self.next_tick;
+        if let Some(entry) = self.map.get_mut(key) {
+            self.lru.remove(&entry.tick);
+            entry.tick = tick;
+            self.lru.insert(tick, key.clone());
+            self.next_tick += 1;
+        }
+    }
+
+    fn insert(&mut self, key: ConversationKey, value: Value, expires_at: Option<u64>) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        if let Some(old) = self.map.insert(
+            key.clone(),
+            Entry {
+                value,
+                expires_at,
+                tick,
+            },
+        ) {
+            self.lru.remove(&old.tick);
+        }
+        self.lru.insert(tick, key);
+    }
+
+    fn remove(&mut self, key: &ConversationKey) -> Option<Entry> {
+        let entry = self.map.remove(key)?;
+        self.lru.remove(&entry.tick);
+        Some(entry)
+    }
+
+    /// Remove the least recently used entry
+    fn pop_oldest(&mut self) -> Option<ConversationKey> {
+        let (_, key) = self.lru.pop_first()?;
+        self.map.remove(&key);
+        Some(key)
+    }
+}
+
+/// Write-behind operations for the sqlite copy
+enum PersistOp {
+    Upsert(ConversationKey, Value, Option<u64>),
+    Delete(ConversationKey),
+    Expire(ConversationKey, Option<u64>),
+    /// Delete (or with `dry_run` count) every row of a user
+    EraseUser {
+        user_id: String,
+        dry_run: bool,
+        done: oneshot::Sender<Result<usize>>,
+    },
+    /// Answered once every earlier operation has been applied
+    Flush(oneshot::Sender<()>),
+}
+
+/// Per-(room, thread, user) state shared by all responders
+///
+/// Values are JSON blobs with optional TTLs. The in-memory map is the source
+/// of truth; when persistence is enabled, changes are written behind to the
+/// bot database by a single writer task, so they land in the order they were
+/// made. Expired entries are removed on access and by a periodic sweep, and
+/// the least recently used entry is evicted once `max_entries` is reached.
+pub struct ConversationStore {
+    entries: Mutex<Entries>,
+    max_entries: usize,
+    persist: Option<mpsc::UnboundedSender<PersistOp>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ConversationStore {
+    /// Store without persistence (state is lost on restart)
+    pub fn in_memory(max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            max_entries: max_entries.max(1),
+            persist: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Expire values by `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Current time as unix seconds, by the store's clock
+    fn now_secs(&self) -> u64 {
+        self.clock.now_ms() / 1000
+    }
+
+    /// Store persisted to the bot database, loading unexpired entries
+    pub fn open(store_path: &Path, max_entries: usize) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS conversation_state (
+                room_id TEXT NOT NULL,
+                thread_id TEXT NOT NULL DEFAULT '',
+                user_id TEXT NOT NULL,
+                slot TEXT NOT NULL,
+                value TEXT NOT NULL,
+                expires_at INTEGER,
+                PRIMARY KEY (room_id, thread_id, user_id, slot)
+            );",
+        )
+        .context("Failed to create conversation_state table")?;
+
+        let now = db::now_secs();
+        conn.execute(
+            "DELETE FROM conversation_state WHERE expires_at IS NOT NULL AND expires_at <= ?1",
+            [now as i64],
+        )?;
+
+        let store = Self {
+            persist: Some(spawn_writer(db_path)),
+            ..Self::in_memory(max_entries)
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT room_id, thread_id, user_id, slot, value, expires_at FROM conversation_state",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let thread_id: String = row.get(1)?;
+            Ok((
+                ConversationKey {
+                    room_id: row.get(0)?,
+                    thread_id: (!thread_id.is_empty()).then_some(thread_id),
+                    user_id: row.get(2)?,
+                    slot: row.get(3)?,
+                },
+                row.get::<_, String>(4)?,
+                row.get::<_, Option<i64>>(5)?.map(|at| at as u64),
+            ))
+        })?;
+
+        let mut loaded = 0;
+        {
+            let mut entries = store.entries.lock().unwrap();
+            for row in rows {
+                let (key, raw, expires_at) = row?;
+                match serde_json::from_str(&raw) {
+                    Ok(value) => {
+                        entries.insert(key, value, expires_at);
+                        loaded += 1;
+                    }
+                    Err(e) => warn!("Skipping unreadable conversation state {:?}: {}", key, e),
+                }
+            }
+            while entries.map.len() > store.max_entries {
+                entries.pop_oldest();
+            }
+        }
+
+        info!("💬 Loaded {} conversation state entries", loaded);
+        Ok(store)
+    }
+
+    /// Get a value, refreshing its LRU position
+    pub async fn get(&self, key: &ConversationKey) -> Option<Value> {
+        let now = self.now_secs();
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.map.get(key)?.is_expired(now) {
+            entries.remove(key);
+            return None;
+        }
+        entries.touch(key);
+        entries.map.get(key).map(|entry| entry.value.clone())
+    }
+
+    /// Set a value with an optional time-to-live
+    pub async fn set(&self, key: ConversationKey, value: Value, ttl: Option<Duration>) {
+        let expires_at = ttl.map(|ttl| self.now_secs() + ttl.as_secs().max(1));
+
+        let evicted = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.insert(key.clone(), value.clone(), expires_at);
+
+            let mut evicted = Vec::new();
+            while entries.map.len() > self.max_entries {
+                match entries.pop_oldest() {
+                    Some(old) => evicted.push(old),
+                    None => break,
+                }
+            }
+            evicted
+        };
+
+        if !evicted.is_empty() {
+            debug!(
+                "💬 Evicted {} conversation state entries (LRU)",
+                evicted.len()
+            );
+        }
+        for old in evicted {
+            self.write(PersistOp::Delete(old));
+        }
+        self.write(PersistOp::Upsert(key, value, expires_at));
+    }
+
+    /// Remove a value, returning it if it was present and unexpired
+    pub async fn remove(&self, key: &ConversationKey) -> Option<Value> {
+        let entry = self.entries.lock().unwrap().remove(key)?;
+        self.write(PersistOp::Delete(key.clone()));
+        (!entry.is_expired(self.now_secs())).then_some(entry.value)
+    }
+
+    /// Remove a value if it is present, unexpired and `predicate` accepts it,
+    /// returning it
+    ///
+    /// Checked and removed under the store lock, so another task can't
+    /// replace or take the value in between.
+    pub async fn remove_if<F>(&self, key: &ConversationKey, predicate: F) -> Option<Value>
+    where
+        F: FnOnce(&Value) -> bool,
+    {
+        let now = self.now_secs();
+        let entry = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.map.get(key)?;
+            if entry.is_expired(now) {
+                entries.remove(key);
+                return None;
+            }
+            if !predicate(&entry.value) {
+                return None;
+            }
+            entries.remove(key)?
+        };
+        self.write(PersistOp::Delete(key.clone()));
+        Some(entry.value)
+    }
+
+    /// Remove every value kept for a room, returning the unexpired ones
+    pub async fn remove_room(&self, room_id: &str) -> Vec<(ConversationKey, Value)> {
+        let now = self.now_secs();
+        let removed: Vec<(ConversationKey, Entry)> = {
+            let mut entries = self.entries.lock().unwrap();
+            let keys: Vec<ConversationKey> = entries
+                .map
+                .keys()
+                .filter(|key| key.room_id == room_id)
+                .cloned()
+                .collect();
+            keys.into_iter()
+                .filter_map(|key| entries.remove(&key).map(|entry| (key, entry)))
+                .collect()
+        };
+        for (key, _) in &removed {
+            self.write(PersistOp::Delete(key.clone()));
+        }
+        removed
+            .into_iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, entry)| (key, entry.value))
+            .collect()
+    }
+
+    /// Modify a value in place if it is present and unexpired, returning the new value
+    ///
+    /// The change is made under the store lock, so it cannot resurrect a value
+    /// another task removed in the meantime.
+    pub async fn update<F>(&self, key: &ConversationKey, f: F) -> Option<Value>
+    where
+        F: FnOnce(&mut Value),
+    {
+        let now = self.now_secs();
+        let (value, expires_at) = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.map.get_mut(key)?;
+            if entry.is_expired(now) {
+                entries.remove(key);
+                return None;
+            }
+            f(&mut entry.value);
+            let updated = (entry.value.clone(), entry.expires_at);
+            entries.touch(key);
+            updated
+        };
+
+        self.write(PersistOp::Upsert(key.clone(), value.clone(), expires_at));
+        Some(value)
+    }
+
+    /// All unexpired values owned by a feature, without touching their LRU position
+    pub fn entries_in_slot(&self, slot: &str) -> Vec<(ConversationKey, Value)> {
+        let now = self.now_secs();
+        self.entries
+            .lock()
+            .unwrap()
+            .map
+            .iter()
+            .filter(|(key, entry)| key.slot == slot && !entry.is_expired(now))
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect()
+    }
+
+    /// Change the time-to-live of an existing value (None = never expires)
+    /// Returns false if there is no such value
+    pub async fn expire(&self, key: &ConversationKey, ttl: Option<Duration>) -> bool {
+        let now = self.now_secs();
+        let expires_at = ttl.map(|ttl| now + ttl.as_secs().max(1));
+
+        {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.map.get_mut(key) {
+                Some(entry) if !entry.is_expired(now) => entry.expires_at = expires_at,
+                Some(_) => {
+                    entries.remove(key);
+                    return false;
+                }
+                None => return false,
+            }
+        }
+
+        self.write(PersistOp::Expire(key.clone(), expires_at));
+        true
+    }
+
+    /// Delete all state of a user (pending HITL requests, reminders, ...)
+    ///
+    /// Returns how many entries were removed, counted in the database when
+    /// persistence is on. With `dry_run` nothing is removed.
+    pub async fn erase_user(&self, user_id: &str, dry_run: bool) -> Result<usize> {
+        let in_memory = {
+            let mut entries = self.entries.lock().unwrap();
+            let keys: Vec<ConversationKey> = entries
+                .map
+                .keys()
+                .filter(|key| key.user_id == user_id)
+                .cloned()
+                .collect();
+            if !dry_run {
+                for key in &keys {
+                    entries.remove(key);
+                }
+            }
+            keys.len()
+        };
+        let Some(persist) = &self.persist else {
+            return Ok(in_memory);
+        };
+
+        // Through the writer, so earlier writes for the user land first
+        let (done, result) = oneshot::channel();
+        persist
+            .send(PersistOp::EraseUser {
+                user_id: user_id.to_string(),
+                dry_run,
+                done,
+            })
+            .map_err(|_| anyhow!("Conversation state writer has stopped"))?;
+        result
+            .await
+            .context("Conversation state writer dropped the erasure")?
+    }
+
+    /// Wait until every change made so far is in the database
+    ///
+    /// Returns at once without persistence.
+    pub async fn flush(&self) -> Result<()> {
+        let Some(persist) = &self.persist else {
+            return Ok(());
+        };
+        let (done, flushed) = oneshot::channel();
+        persist
+            .send(PersistOp::Flush(done))
+            .map_err(|_| anyhow!("Conversation state writer has stopped"))?;
+        flushed
+            .await
+            .context("Conversation state writer stopped before the flush")
+    }
+
+    /// Number of entries currently held in memory
+    pub fn entry_count(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Drop every expired entry, returning how many were removed
+    pub fn sweep(&self) -> usize {
+        let now = self.now_secs();
+        let mut entries = self.entries.lock().unwrap();
+        let expired: Vec<ConversationKey> = entries
+            .map
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            entries.remove(key);
+        }
+        // The database copy is cleaned on the next startup
+        expired.len()
+    }
+
+    /// Spawn the periodic expiry sweep, running `hooks` first on every pass
+    pub fn spawn_sweep_task(
+        self: &Arc<Self>,
+        interval: Duration,
+        hooks: Vec<Arc<dyn SweepHook>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                for hook in &hooks {
+                    hook.on_sweep(&store).await;
+                }
+                let removed = store.sweep();
+                if removed > 0 {
+                    debug!("💬 Swept {} expired conversation state entries", removed);
+                }
+            }
+        })
+    }
+
+    fn write(&self, op: PersistOp) {
+        if let Some(persist) = &self.persist {
+            if persist.send(op).is_err() {
+                warn!("Conversation state writer has stopped; change not persisted");
+            }
+        }
+    }
+}
+
+/// Start the single writer task that applies persistence operations in order
+fn spawn_writer(db_path: PathBuf) -> mpsc::UnboundedSender<PersistOp> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PersistOp>();
+
+    tokio::spawn(async move {
+        while let Some(op) = rx.recv().await {
+            // Operations are applied one at a time, so everything before it is done
+            if let PersistOp::Flush(done) = op {
+                let _ = done.send(());
+                continue;
+            }
+            let db_path = db_path.clone();
+            let result = tokio::task::spawn_blocking(move || apply(&db_path, op)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Failed to persist conversation state: {:#}", e),
+                Err(e) => error!("Conversation state writer panicked: {}", e),
+            }
+        }
+    });
+
+    tx
+}
+
+fn apply(db_path: &Path, op: PersistOp) -> Result<()> {
+    let conn = db::open(db_path)?;
+    match op {
+        PersistOp::Upsert(key, value, expires_at) => {
+            conn.execute(
+                "INSERT OR REPLACE INTO conversation_state
+                    (room_id, thread_id, user_id, slot, value, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![
+                    key.room_id,
+                    key.thread_id.unwrap_or_default(),
+                    key.user_id,
+                    key.slot,
+                    value.to_string(),
+                    expires_at.map(|at| at as i64)
+                ],
+            )?;
+        }
+        PersistOp::Delete(key) => {
+            conn.execute(
+                "DELETE FROM conversation_state
+                 WHERE room_id = ?1 AND thread_id = ?2 AND user_id = ?3 AND slot = ?4",
+                rusqlite::params![
+                    key.room_id,
+                    key.thread_id.unwrap_or_default(),
+                    key.user_id,
+                    key.slot
+                ],
+            )?;
+        }
+        PersistOp::Expire(key, expires_at) => {
+            conn.execute(
+                "UPDATE conversation_state SET expires_at = ?5
+                 WHERE room_id = ?1 AND thread_id = ?2 AND user_id = ?3 AND slot = ?4",
+                rusqlite::params![
+                    key.room_id,
+                    key.thread_id.unwrap_or_default(),
+                    key.user_id,
+                    key.slot,
+                    expires_at.map(|at| at as i64)
+                ],
+            )?;
+        }
+        PersistOp::EraseUser {
+            user_id,
+            dry_run,
+            done,
+        } => {
+            let result = db::erase_rows(
+                &conn,
+                "conversation_state",
+                "user_id = ?1",
+                [user_id],
+                dry_run,
+            );
+            let _ = done.send(result);
+        }
+        PersistOp::Flush(done) => {
+            let _ = done.send(());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+//! Panic reports that outlive the process
+//!
+//! [`CrashReporter::install`] adds a panic hook in front of the existing one.
+//! Each panic is written with its backtrace to `crashes/crash-<time>.log`
+//! under the store path and counted in `crashes/count`; plain files, so the
+//! hook never waits on a database or a lock a panicking thread may hold. Once
+//! the client is up ([`CrashReporter::set_alert_target`]), the hook also posts
+//! a one-line alert to
+//! `ADMIN_ROOM`, giving up after [`ALERT_TIMEOUT`]. The next start picks up
+//! the unreported crash with [`take_unreported`] for the startup summary.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use matrix_sdk::Client;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::admin_room;
+use crate::runtime::Runtime;
+
+/// How long a panicking thread waits for the admin room alert
+pub const ALERT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Crash files kept; older ones are deleted at startup
+const CRASH_FILES_KEPT: usize = 20;
+
+const CRASH_DIR: &str = "crashes";
+const COUNT_FILE: &str = "count";
+/// Time of the last crash that no startup has reported yet
+const UNREPORTED_FILE: &str = "unreported";
+
+thread_local! {
+    /// Set while this thread runs the hook, so a panic inside it is not reported again
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+struct AlertTarget {
+    client: Client,
+    admin_room: String,
+    /// The bot the alert is posted as
+    runtime: Runtime,
+    /// Where the alert is sent from, since the hook runs outside of it
+    tokio: tokio::runtime::Handle,
+}
+
+/// A bot's panic reports: the crash directory in its store and its admin room
+pub struct CrashReporter {
+    dir: PathBuf,
+    alert: OnceLock<AlertTarget>,
+}
+
+/// A crash recorded by an earlier run
+#[derive(Debug, Clone)]
+pub struct PreviousCrash {
+    pub at: DateTime<Utc>,
+    /// Crashes recorded in this store so far
+    pub total: u64,
+}
+
+impl CrashReporter {
+    /// Record panics under `store_path`, then run the previous hook
+    ///
+    /// Each call puts a hook of its own in front of the ones installed before.
+    pub fn install(store_path: &Path) -> Result<Arc<Self>> {
+        let dir = store_path.join(CRASH_DIR);
+        std::fs::create_dir_all(&dir).context("Failed to create the crash directory")?;
+        prune(&dir);
+        let reporter = Arc::new(Self {
+            dir,
+            alert: OnceLock::new(),
+        });
+
+        let hooked = Arc::clone(&reporter);
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !IN_HOOK.with(|in_hook| in_hook.replace(true)) {
+                hooked.report(info);
+                IN_HOOK.with(|in_hook| in_hook.set(false));
+            }
+            previous(info);
+        }));
+        Ok(reporter)
+    }
+
+    /// Post crash alerts to `admin_room` through `client` from now on
+    ///
+    /// Must be called from within the Tokio runtime.
+    pub fn set_alert_target(&self, client: Client, admin_room: String, runtime: Runtime) {
+        let _ = self.alert.set(AlertTarget {
+            client,
+            admin_room,
+            runtime,
+            tokio: tokio::runtime::Handle::current(),
+        });
+    }
+
+    fn report(&self, info: &PanicHookInfo<'_>) {
+        let now = Utc::now();
+        let message = panic_message(info);
+        let location = info
+            .location()
+            .map_or_else(|| "unknown location".to_string(), |l| l.to_string());
+        let thread = std::thread::current()
+            .name()
+            .unwrap_or("unnamed")
+            .to_string();
+
+        let dir = &self.dir;
+        let file = dir.join(format!("crash-{}.log", now.format("%Y%m%dT%H%M%SZ")));
+        let report = format!(
+            "time: {}\nthread: {}\nlocation: {}\nmessage: {}\n\n{}\n",
+            now.to_rfc3339(),
+            thread,
+            location,
+            message,
+            Backtrace::force_capture()
+        );
+        match std::fs::write(&file, report) {
+            Ok(()) => error!(
+                "💥 Panic in thread {} at {}; report written to {:?}",
+                thread, location, file
+            ),
+            Err(e) => error!(
+                "💥 Panic in thread {} at {}; failed to write {:?}: {}",
+                thread, location, file, e
+            ),
+        }
+        let _ = std::fs::write(dir.join(COUNT_FILE), (read_count(dir) + 1).to_string());
+        let _ = std::fs::write(dir.join(UNREPORTED_FILE), now.timestamp().to_string());
+
+        if let Some(target) = self.alert.get() {
+            alert(
+                target,
+                format!(
+                    "💥 **Panic** in `{}` at `{}`: {}",
+                    thread, location, message
+                ),
+            );
+        }
+    }
+}
+
+/// The last crash nobody was told about yet, marking it as reported
+pub fn take_unreported(store_path: &Path) -> Option<PreviousCrash> {
+    let dir = store_path.join(CRASH_DIR);
+    let marker = dir.join(UNREPORTED_FILE);
+    let secs = std::fs::read_to_string(&marker)
+        .ok()?
+        .trim()
+        .parse::<i64>()
+        .ok();
+    if let Err(e) = std::fs::remove_file(&marker) {
+        warn!("Failed to clear the crash marker {:?}: {}", marker, e);
+    }
+    Some(PreviousCrash {
+        at: DateTime::from_timestamp(secs?, 0)?,
+        total: read_count(&dir),
+    })
+}
+
+/// Post the alert from the runtime and wait for it, at most `ALERT_TIMEOUT`
+///
+/// Waits on a plain channel, never `block_on`, so it can't deadlock a runtime
+/// thread: at worst the timeout runs out.
+fn alert(target: &AlertTarget, line: String) {
+    let (done, finished) = mpsc::channel();
+    let client = target.client.clone();
+    let admin_room = target.admin_room.clone();
+    let runtime = target.runtime.clone();
+    target.tokio.spawn(async move {
+        let post = admin_room::post(&runtime, &client, &admin_room, "crash alert", &line);
+        let _ = tokio::time::timeout(ALERT_TIMEOUT, post).await;
+        let _ = done.send(());
+    });
+    let _ = finished.recv_timeout(ALERT_TIMEOUT);
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "(non-string panic payload)".to_string())
+}
+
+fn read_count(dir: &Path) -> u64 {
+    std::fs::read_to_string(dir.join(COUNT_FILE))
+        .ok()
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Delete all but the newest `CRASH_FILES_KEPT` crash files
+fn prune(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("crash-") && name.ends_with(".log"))
+        })
+        .collect();
+    if files.len() <= CRASH_FILES_KEPT {
+        return;
+    }
+    // Names sort by time
+    files.sort();
+    let old = files.len() - CRASH_FILES_KEPT;
+    for file in &files[..old] {
+        let _ = std::fs::remove_file(file);
+    }
+    info!("🧹 Deleted {} old crash reports", old);
+}
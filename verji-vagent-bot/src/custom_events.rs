@@ -0,0 +1,108 @@
+//! Custom event types and msgtypes the bot should see
+//!
+//! `CUSTOM_EVENTS` maps timeline event types, or custom (non-`m.`) msgtypes
+//! of `m.room.message`, to a treatment, e.g.
+//! `no.verji.task=forward_to_graph;org.example.ping=log`:
+//!
+//! - `forward_to_graph`: dispatched like a message addressed to the bot, and
+//!   sent to vagent-graph with the raw event as the request's `custom_event`
+//!   payload and an empty query
+//! - `log`: logged with the keys of its content
+//! - `ignore`: dropped
+//!
+//! Unlisted types stay invisible. Only `type` is required of an event; the
+//! content may have any shape.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Treatment {
+    ForwardToGraph,
+    Log,
+    Ignore,
+}
+
+impl Treatment {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "forward_to_graph" | "forward" => Some(Treatment::ForwardToGraph),
+            "log" => Some(Treatment::Log),
+            "ignore" => Some(Treatment::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// Treatments from `type=treatment` entries, skipping unknown treatments
+pub fn parse_treatments(entries: HashMap<String, String>) -> HashMap<String, Treatment> {
+    entries
+        .into_iter()
+        .filter_map(
+            |(event_type, treatment)| match Treatment::parse(&treatment) {
+                Some(treatment) => Some((event_type, treatment)),
+                None => {
+                    warn!(
+                    "Ignoring CUSTOM_EVENTS entry {}={}: expected forward_to_graph, log or ignore",
+                    event_type, treatment
+                );
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// The configured type an event falls under and its treatment
+///
+/// `m.room.message` events are looked up by their msgtype, and only for
+/// msgtypes outside the `m.` namespace, which the message handler covers.
+pub fn classify(
+    event: &Value,
+    treatments: &HashMap<String, Treatment>,
+) -> Option<(String, Treatment)> {
+    let event_type = event.get("type")?.as_str()?;
+    if event.pointer("/unsigned/redacted_because").is_some() {
+        return None;
+    }
+    let key = if event_type == "m.room.message" {
+        let msgtype = event.get("content")?.get("msgtype")?.as_str()?;
+        if msgtype.starts_with("m.") {
+            return None;
+        }
+        msgtype
+    } else {
+        event_type
+    };
+    treatments
+        .get(key)
+        .map(|treatment| (key.to_string(), *treatment))
+}
+
+/// Root of the thread the event was sent in, if any
+pub fn thread_id(event: &Value) -> Option<String> {
+    let relates_to = event.get("content")?.get("m.relates_to")?;
+    if relates_to.get("rel_type")?.as_str()? != "m.thread" {
+        return None;
+    }
+    Some(relates_to.get("event_id")?.as_str()?.to_string())
+}
+
+/// Top-level keys of the event content, for logging without the values
+pub fn content_keys(event: &Value) -> Vec<String> {
+    match event.get("content") {
+        Some(Value::Object(content)) => content.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Request payload of a forwarded event
+pub fn payload(custom_type: &str, event: &Value) -> Value {
+    json!({
+        "custom_event": {
+            "type": custom_type,
+            "event": event,
+        }
+    })
+}
@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+/// File name of the bot's own sqlite database (separate from the Matrix SDK stores)
+const BOT_DB_FILE: &str = "vagent_bot.sqlite3";
+
+/// Path of the bot database inside the store directory
+pub fn bot_db_path(store_path: &Path) -> PathBuf {
+    store_path.join(BOT_DB_FILE)
+}
+
+/// Open the bot database with the pragmas every store relies on
+///
+/// Connections are cheap to open, so stores open one per flush inside
+/// `spawn_blocking` instead of sharing a long-lived handle across tasks.
+pub fn open(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("Failed to open bot database at {:?}", path))?;
+
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = NORMAL;
+         PRAGMA busy_timeout = 5000;",
+    )
+    .context("Failed to configure bot database")?;
+
+    Ok(conn)
+}
+
+/// Current time as unix seconds
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Delete the rows of `table` matching `filter`, or with `dry_run` only count them
+///
+/// Used by user erasure, so every store reports its counts the same way.
+pub fn erase_rows<P: rusqlite::Params>(
+    conn: &Connection,
+    table: &str,
+    filter: &str,
+    params: P,
+    dry_run: bool,
+) -> Result<usize> {
+    let count = if dry_run {
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM {} WHERE {}", table, filter),
+            params,
+            |row| row.get::<_, i64>(0),
+        )? as usize
+    } else {
+        conn.execute(&format!("DELETE FROM {} WHERE {}", table, filter), params)?
+    };
+    Ok(count)
+}
+
+/// Store directory for tests, deleted when dropped
+#[cfg(any(test, feature = "testing"))]
+pub struct TempStore {
+    path: PathBuf,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl TempStore {
+    pub fn new(name: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "vagent-test-{}-{}",
+            name,
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&path).context("Failed to create temporary store")?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Drop for TempStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::clock::{Clock, SystemClock};
+use crate::responder::{Responder, ResponderContext, ResponderResult, Route};
+
+/// Maximum number of rooms tracked before stale entries are pruned
+const MAX_TRACKED_ROOMS: usize = 10_000;
+
+/// Lets a responder fire at most once per `cooldown` in each room
+///
+/// Within the window the wrapped responder is skipped as if `should_handle`
+/// returned false. The window starts when the responder actually handles a
+/// message, so declining or deferring does not use it up.
+pub struct Cooldown<R> {
+    inner: R,
+    cooldown_ms: u64,
+    clock: Arc<dyn Clock>,
+    /// Room ID -> time (ms) the responder last fired there
+    last_fired: Mutex<HashMap<String, u64>>,
+}
+
+impl<R: Responder> Cooldown<R> {
+    pub fn new(inner: R, cooldown: Duration) -> Self {
+        Self::with_clock(inner, cooldown, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(inner: R, cooldown: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            cooldown_ms: cooldown.as_millis() as u64,
+            clock,
+            last_fired: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cooling_down(&self, room_id: &str, now: u64) -> bool {
+        self.last_fired
+            .lock()
+            .unwrap()
+            .get(room_id)
+            .is_some_and(|fired| now.saturating_sub(*fired) < self.cooldown_ms)
+    }
+
+    fn record(&self, room_id: &str, now: u64) {
+        let mut last_fired = self.last_fired.lock().unwrap();
+        if last_fired.len() >= MAX_TRACKED_ROOMS {
+            let cooldown_ms = self.cooldown_ms;
+            last_fired.retain(|_, fired| now.saturating_sub(*fired) < cooldown_ms);
+        }
+        // Still full of active cooldowns: forget the oldest one
+        if last_fired.len() >= MAX_TRACKED_ROOMS {
+            if let Some(oldest) = last_fired
+                .iter()
+                .min_by_key(|(_, fired)| **fired)
+                .map(|(room, _)| room.clone())
+            {
+                last_fired.remove(&oldest);
+            }
+        }
+        last_fired.insert(room_id.to_string(), now);
+    }
+}
+
+#[async_trait]
+impl<R: Responder> Responder for Cooldown<R> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.route(context).await == Route::Accept
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        let room_id = context.room.room_id().as_str();
+        if self.cooling_down(room_id, self.clock.now_ms()) {
+            debug!(
+                "🧊 Responder '{}' is cooling down in {}, skipping",
+                self.inner.name(),
+                room_id
+            );
+            return Route::decline("cooldown");
+        }
+        self.inner.route(context).await
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let result = self.inner.handle(context).await?;
+        if result.is_handled() {
+            self.record(context.room.room_id().as_str(), self.clock.now_ms());
+        }
+        Ok(result)
+    }
+
+    async fn fallback(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let result = self.inner.fallback(context).await?;
+        if result.is_handled() {
+            self.record(context.room.room_id().as_str(), self.clock.now_ms());
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::clock::{Clock, SystemClock};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult, Route};
+
+/// Enforces the per-user daily query limit (`DAILY_QUOTA`) on agent responders
+///
+/// Counts go to the usage stats under (user, day in `QUOTA_TIMEZONE`), so
+/// every wrapped responder draws from the same daily allowance. Admins are
+/// exempt; messages the responder ends up not handling don't count.
+pub struct DailyQuota<R> {
+    inner: R,
+    clock: Arc<dyn Clock>,
+}
+
+impl<R: Responder> DailyQuota<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_clock(inner, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(inner: R, clock: Arc<dyn Clock>) -> Self {
+        Self { inner, clock }
+    }
+}
+
+#[async_trait]
+impl<R: Responder> Responder for DailyQuota<R> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.inner.should_handle(context).await
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        self.inner.route(context).await
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let policy = &context.config.daily_quota;
+        let limit = policy.limit(
+            context.room.room_id().as_str(),
+            context.stats.quota_override(&context.identity),
+        );
+        let Some(limit) = limit.filter(|_| !context.sender_is_admin()) else {
+            return self.inner.handle(context).await;
+        };
+
+        let now_ms = self.clock.now_ms();
+        let day = policy.day(now_ms);
+        if !context
+            .stats
+            .try_count_daily(&context.identity, &day, limit)
+        {
+            info!("⏳ Daily quota of {} used up ({})", context.identity, limit);
+            let reset = policy.next_reset(now_ms).format("%H:%M %Z").to_string();
+            let notice = t(
+                context,
+                "quota.daily_exceeded",
+                &[("limit", &limit.to_string()), ("time", &reset)],
+            );
+            return Ok(ResponderResult::HandledWithContent(vec![
+                OutgoingMessage::notice_mentioning(&context.sender, notice),
+            ]));
+        }
+
+        let result = self.inner.handle(context).await;
+        if !result.as_ref().is_ok_and(ResponderResult::is_handled) {
+            context.stats.uncount_daily(&context.identity, &day);
+        }
+        result
+    }
+
+    async fn fallback(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.inner.fallback(context).await
+    }
+}
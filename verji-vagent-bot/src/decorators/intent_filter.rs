@@ -0,0 +1,155 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::agent_service::AgentService;
+use crate::hitl::HITL_SLOT;
+use crate::intent::{self, Intent, IntentFilterMode, Signals};
+use crate::redis_client::{GraphMessageType, GraphRequest};
+use crate::request_id;
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult, Route};
+
+/// How long the graph may take to classify a message before it is asked anyway
+const GRAPH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keeps acknowledgements ("ok", "thanks") from reaching the agent (`INTENT_FILTER`)
+///
+/// Messages [`intent::classify`] calls acknowledgements are consumed without
+/// a graph call, or answered with `INTENT_FILTER_REACTION`. Unsure ones go to
+/// the agent, or in `graph` mode are first put to a `classify_intent` command.
+/// Answers to a pending HITL question, retries and custom events always pass.
+pub struct IntentFilter<R> {
+    inner: R,
+    mode: IntentFilterMode,
+    /// None = drop acknowledgements silently
+    reaction: Option<String>,
+    agent: Arc<AgentService>,
+}
+
+impl<R: Responder> IntentFilter<R> {
+    pub fn new(
+        inner: R,
+        mode: IntentFilterMode,
+        reaction: Option<String>,
+        agent: Arc<AgentService>,
+    ) -> Self {
+        Self {
+            inner,
+            mode,
+            reaction,
+            agent,
+        }
+    }
+
+    /// Ask vagent-graph whether to answer; anything but a clear "acknowledgement" is a question
+    async fn ask_graph(&self, context: &ResponderContext) -> Intent {
+        let mut request = GraphRequest::command(
+            "classify_intent",
+            serde_json::json!({ "text": &*context.message_body }),
+            context.room.room_id().to_string(),
+            context.identity.to_string(),
+        );
+        request.request_id = request_id::for_step(context, "intent");
+        match tokio::time::timeout(GRAPH_TIMEOUT, self.agent.run_command(request)).await {
+            Ok(Ok(message)) if message.message_type != GraphMessageType::Error => {
+                match message.content.trim().to_lowercase().as_str() {
+                    "acknowledgement" | "ignore" => Intent::Acknowledgement,
+                    _ => Intent::Question,
+                }
+            }
+            Ok(Ok(message)) => {
+                warn!(
+                    "🧹 vagent-graph could not classify a message: {}",
+                    message.content
+                );
+                Intent::Question
+            }
+            Ok(Err(e)) => {
+                warn!("🧹 Failed to classify a message: {:#}", e);
+                Intent::Question
+            }
+            Err(_) => {
+                warn!("🧹 vagent-graph took too long to classify a message");
+                Intent::Question
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<R: Responder> Responder for IntentFilter<R> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.inner.should_handle(context).await
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        self.inner.route(context).await
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        if self.mode == IntentFilterMode::Off
+            || context.custom_event.is_some()
+            || context.retry_attempt > 0
+        {
+            return self.inner.handle(context).await;
+        }
+        // "ok" may well be the answer the agent is waiting for
+        let hitl_key = context.conversation_key(HITL_SLOT);
+        if context.conversations.get(&hitl_key).await.is_some() {
+            return self.inner.handle(context).await;
+        }
+
+        let signals = Signals {
+            mentioned: context.is_direct_mention,
+            direct_chat: context.room.member_count() <= 2,
+        };
+        let classification = intent::classify(&context.message_body, signals);
+        let intent = match (classification.intent, self.mode) {
+            (Intent::Unsure, IntentFilterMode::Graph) => self.ask_graph(context).await,
+            (intent, _) => intent,
+        };
+        info!(
+            "🧹 Intent prefilter: {} ({}, {} chars) in {}",
+            intent.as_str(),
+            classification.reason,
+            context.message_body.chars().count(),
+            context.room.room_id()
+        );
+        debug!("🧹 Classified {:?}", context.message_body);
+        context.runtime.metrics.increment(
+            "intent_prefilter_total",
+            &[
+                ("intent", intent.as_str()),
+                ("reason", classification.reason),
+            ],
+        );
+
+        if intent != Intent::Acknowledgement {
+            return self.inner.handle(context).await;
+        }
+        Ok(match &self.reaction {
+            Some(reaction) => ResponderResult::HandledWithContent(vec![OutgoingMessage::Reaction(
+                reaction.clone(),
+            )]),
+            None => ResponderResult::HandledSilently,
+        })
+    }
+
+    async fn fallback(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.inner.fallback(context).await
+    }
+}
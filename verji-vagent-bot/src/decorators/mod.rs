@@ -0,0 +1,11 @@
+pub mod cooldown;
+pub mod daily_quota;
+pub mod intent_filter;
+pub mod rate_limited;
+pub mod room_budget;
+
+pub use cooldown::Cooldown;
+pub use daily_quota::DailyQuota;
+pub use intent_filter::IntentFilter;
+pub use rate_limited::RateLimited;
+pub use room_budget::RoomBudget;
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::clock::{Clock, SystemClock};
+use crate::i18n::t;
+use crate::quota::{Quota, QuotaDecision, QuotaStore};
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult, Route};
+
+/// Limits how often a single responder may be used
+///
+/// Each handled message uses one unit of the quota in its bucket (user, room
+/// or user+room). Once the quota is used up the responder still claims the
+/// message but replies with when the quota resets; messages the responder
+/// ends up not handling don't count.
+pub struct RateLimited<R> {
+    inner: R,
+    /// Quota bucket name; defaults to the responder's own name
+    quota_name: Option<String>,
+    quota: Quota,
+    store: Arc<QuotaStore>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<R: Responder> RateLimited<R> {
+    pub fn new(inner: R, quota: Quota, store: Arc<QuotaStore>) -> Self {
+        Self::with_clock(inner, quota, store, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(
+        inner: R,
+        quota: Quota,
+        store: Arc<QuotaStore>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            inner,
+            quota_name: None,
+            quota,
+            store,
+            clock,
+        }
+    }
+
+    /// Count usage against another responder's quota (e.g. commands that
+    /// query the agent share the agent's quota)
+    pub fn sharing_quota_of(mut self, name: &str) -> Self {
+        self.quota_name = Some(name.to_string());
+        self
+    }
+}
+
+#[async_trait]
+impl<R: Responder> Responder for RateLimited<R> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.inner.should_handle(context).await
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        self.inner.route(context).await
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let name = self.quota_name.as_deref().unwrap_or(self.inner.name());
+        let bucket = self.quota.scope.key(context);
+
+        match self
+            .store
+            .try_acquire(name, &bucket, &self.quota, self.clock.now_ms())
+        {
+            QuotaDecision::Allowed => {}
+            QuotaDecision::Exceeded { resets_at_ms } => {
+                info!("⏳ Quota of '{}' exceeded for {}", name, bucket);
+                let time = format_reset_time(resets_at_ms);
+                let notice = t(context, "quota.exceeded", &[("time", &time)]);
+                return Ok(ResponderResult::HandledWithContent(vec![
+                    OutgoingMessage::notice_mentioning(&context.sender, notice),
+                ]));
+            }
+        }
+
+        let result = self.inner.handle(context).await;
+        if !result.as_ref().is_ok_and(ResponderResult::is_handled) {
+            self.store.release(name, &bucket);
+        }
+        result
+    }
+
+    async fn fallback(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.inner.fallback(context).await
+    }
+}
+
+/// Reset time as `HH:MM UTC`, with the date when it is not today
+fn format_reset_time(resets_at_ms: u64) -> String {
+    let Some(reset) = chrono::DateTime::from_timestamp((resets_at_ms / 1000) as i64, 0) else {
+        return "unknown".to_string();
+    };
+    if reset.date_naive() == chrono::Utc::now().date_naive() {
+        reset.format("%H:%M UTC").to_string()
+    } else {
+        reset.format("%Y-%m-%d %H:%M UTC").to_string()
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::clock::{Clock, SystemClock};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult, Route};
+
+/// Answers with a notice instead of asking the agent once a room's monthly budget is spent
+///
+/// Spend is what vagent-graph reported for the room's answers this period
+/// (see `RoomBudgetPolicy`); the answer that crosses the limit still goes out.
+pub struct RoomBudget<R> {
+    inner: R,
+    clock: Arc<dyn Clock>,
+}
+
+impl<R: Responder> RoomBudget<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_clock(inner, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(inner: R, clock: Arc<dyn Clock>) -> Self {
+        Self { inner, clock }
+    }
+}
+
+#[async_trait]
+impl<R: Responder> Responder for RoomBudget<R> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.inner.priority()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.inner.should_handle(context).await
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        self.inner.route(context).await
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let policy = &context.config.room_budget;
+        let Some(limit) = policy.limit(context.room_config.monthly_budget) else {
+            return self.inner.handle(context).await;
+        };
+
+        let now_ms = self.clock.now_ms();
+        let room_id = context.room.room_id().as_str();
+        let spent = context.stats.room_spend(room_id, &policy.period(now_ms));
+        if spent.cost < limit {
+            return self.inner.handle(context).await;
+        }
+
+        info!(
+            "💸 Monthly budget of {} used up ({:.2} of {:.2})",
+            room_id, spent.cost, limit
+        );
+        let reset = policy.next_reset(now_ms).format("%Y-%m-%d").to_string();
+        let notice = t(context, "budget.exhausted", &[("date", &reset)]);
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::notice_mentioning(&context.sender, notice),
+        ]))
+    }
+
+    async fn fallback(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.inner.fallback(context).await
+    }
+}
@@ -0,0 +1,27 @@
+use anyhow::{bail, Context, Result};
+use matrix_sdk::{ruma::UserId, Client};
+use tracing::info;
+
+use crate::room::BotRoom;
+use crate::runtime::Runtime;
+
+/// The bot's direct chat with a user, created if there is none yet
+pub async fn dm_room(runtime: &Runtime, client: &Client, user_id: &str) -> Result<BotRoom> {
+    let user_id = UserId::parse(user_id).context("Invalid user ID")?;
+    if let Some(room) = client.get_dm_room(&user_id) {
+        return Ok(BotRoom::new(room, runtime.clone()));
+    }
+
+    if runtime.shadow.is_active() {
+        bail!(
+            "No direct chat with {} yet, and shadow mode can't create one",
+            user_id
+        );
+    }
+    info!("💬 Creating direct chat with {}", user_id);
+    let room = client
+        .create_dm(&user_id)
+        .await
+        .context("Failed to create direct chat")?;
+    Ok(BotRoom::new(room, runtime.clone()))
+}
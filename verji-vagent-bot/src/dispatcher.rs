@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use matrix_sdk::{
+    attachment::AttachmentConfig,
+    ruma::{
+        events::{
+            reaction::ReactionEventContent, relation::Annotation,
+            room::message::RoomMessageEventContent,
+        },
+        EventId, OwnedEventId,
+    },
+};
+use std::collections::HashMap;
+use tracing::{error, info};
+
+use crate::fallback_dm;
+use crate::mentions;
+use crate::outbox::{Origin, Outbox};
+use crate::responder::OutgoingMessage;
+use crate::room::{BotRoom, RoomHandle};
+use crate::runtime::Runtime;
+use crate::sent_events::{SentEventRegistry, SentKind};
+use crate::shadow::RoomWrite;
+
+/// Convert a text-like outgoing message into room message content
+///
+/// Returns `None` for reactions and attachments, which use their own send paths.
+pub fn message_content(message: &OutgoingMessage) -> Option<RoomMessageEventContent> {
+    named_message_content(message, &HashMap::new())
+}
+
+/// [`message_content`], with the display names in `names` on mention pills
+pub fn named_message_content(
+    message: &OutgoingMessage,
+    names: &HashMap<String, String>,
+) -> Option<RoomMessageEventContent> {
+    match message {
+        OutgoingMessage::Text(body) => Some(RoomMessageEventContent::text_plain(body)),
+        OutgoingMessage::Markdown(body) => Some(RoomMessageEventContent::text_markdown(body)),
+        OutgoingMessage::Notice(body) => Some(RoomMessageEventContent::notice_plain(body)),
+        OutgoingMessage::Mention {
+            body,
+            mentions,
+            notice,
+            markdown,
+        } => {
+            let (plain, html) = mentions::bodies(body, mentions, names, *markdown);
+            let mut content = if *notice {
+                RoomMessageEventContent::notice_html(plain, html)
+            } else {
+                RoomMessageEventContent::text_html(plain, html)
+            };
+            content.mentions = Some(mentions.to_ruma());
+            Some(content)
+        }
+        OutgoingMessage::Reaction(_) | OutgoingMessage::Attachment { .. } => None,
+    }
+}
+
+/// Send a single outgoing message to the room, returning its event ID
+pub async fn send_message(
+    room: &BotRoom,
+    trigger: &EventId,
+    message: OutgoingMessage,
+) -> Result<OwnedEventId> {
+    let runtime = room.runtime();
+    // Owned, since the message is consumed by the send
+    let (kind, body) = match &message {
+        OutgoingMessage::Reaction(key) => ("reaction", key.clone()),
+        OutgoingMessage::Attachment { filename, .. } => ("attachment", filename.clone()),
+        OutgoingMessage::Mention { body, .. }
+        | OutgoingMessage::Text(body)
+        | OutgoingMessage::Markdown(body)
+        | OutgoingMessage::Notice(body) => ("message", body.clone()),
+    };
+    let write = RoomWrite::new(room.room_id(), kind)
+        .trigger(trigger)
+        .body(&body);
+    if let Some(event_id) = runtime.shadow.suppress(write) {
+        return Ok(event_id);
+    }
+
+    let event_id = match message {
+        OutgoingMessage::Reaction(key) => {
+            let content = ReactionEventContent::new(Annotation::new(trigger.to_owned(), key));
+            runtime
+                .pacer
+                .send("reaction", || room.send(content.clone()))
+                .await
+                .context("Failed to send reaction")?
+                .event_id
+        }
+        OutgoingMessage::Attachment {
+            filename,
+            content_type,
+            data,
+        } => {
+            let mime: mime::Mime = content_type
+                .parse()
+                .with_context(|| format!("Invalid attachment content type: {}", content_type))?;
+            runtime
+                .pacer
+                .send("attachment", || {
+                    room.send_attachment(
+                        filename.clone(),
+                        &mime,
+                        data.clone(),
+                        AttachmentConfig::new(),
+                    )
+                })
+                .await
+                .context("Failed to send attachment")?
+                .event_id
+        }
+        OutgoingMessage::Mention { ref mentions, .. } => {
+            // Pills show display names, which need the room's member list
+            let names = mentions::display_names(room, mentions).await;
+            let content = named_message_content(&message, &names).context("Not a text message")?;
+            runtime
+                .pacer
+                .send("message", || room.send(content.clone()))
+                .await
+                .context("Failed to send message")?
+                .event_id
+        }
+        text => {
+            let content = message_content(&text).context("Not a text message")?;
+            runtime
+                .pacer
+                .send("message", || room.send(content.clone()))
+                .await
+                .context("Failed to send message")?
+                .event_id
+        }
+    };
+
+    runtime.shadow.record_sent(write, &event_id);
+    Ok(event_id)
+}
+
+/// Send responder output in order, continuing past individual failures
+///
+/// The messages are sent in one turn of the send queue of `thread_id`'s
+/// thread (or the main timeline), so nothing else the bot sends there lands
+/// between them, and are recorded in
+/// `sent_events` with the agent request linked to `trigger`. Final answers
+/// that fail to send are kept in `outbox` for later delivery, or, when the
+/// room refuses them, sent to `sender` by direct message (see `fallback_dm`).
+/// Messages still queued when the bot leaves the room are dropped. Returns
+/// the number of messages that were delivered.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_all(
+    runtime: &Runtime,
+    room: &dyn RoomHandle,
+    trigger: &EventId,
+    thread_id: Option<&str>,
+    sender: Option<&str>,
+    messages: Vec<OutgoingMessage>,
+    sent_events: &SentEventRegistry,
+    outbox: Option<&Outbox>,
+) -> usize {
+    let total = messages.len();
+    let mut sent = 0;
+    let (request_id, output_kind) = match sent_events.take_request(trigger) {
+        Some((request_id, kind)) => (Some(request_id), kind),
+        None => (None, SentKind::Final),
+    };
+    let room_id = room.room_id().to_string();
+
+    let mut turn = runtime.send_queues.turn(room.room_id(), thread_id).await;
+    for (index, message) in messages.into_iter().enumerate() {
+        if runtime.memberships.has_left(&room_id) {
+            info!(
+                "🚪 Left {}, dropping {} queued message(s)",
+                room_id,
+                total - index
+            );
+            break;
+        }
+        let kind = match message {
+            OutgoingMessage::Reaction(_) => SentKind::Ack,
+            _ => output_kind,
+        };
+        let undelivered = (kind == SentKind::Final).then(|| message.clone());
+        match turn.send(room.send_content(trigger, message)).await {
+            Ok(event_id) => {
+                runtime.key_rotations.after_send(room, &event_id).await;
+                sent_events
+                    .record(event_id, &room_id, kind, request_id.as_deref())
+                    .await;
+                sent += 1;
+            }
+            Err(e) => {
+                if !runtime.store_health.report_error("send", &e) {
+                    error!("Failed to send response: {:#}", e);
+                }
+                if let Some(message) = undelivered {
+                    let origin = Origin {
+                        room_id: &room_id,
+                        trigger,
+                        thread_id,
+                        request_id: request_id.as_deref(),
+                        sender,
+                    };
+                    keep_undelivered(runtime, &origin, message, &e, outbox).await;
+                }
+            }
+        }
+    }
+
+    if sent > 0 {
+        info!("✅ Sent {}/{} response message(s)", sent, total);
+        runtime.startup.responded();
+    }
+
+    sent
+}
+
+/// A final answer that failed to send: the DM fallback takes it when the room
+/// refuses it for good, the outbox keeps it otherwise
+async fn keep_undelivered(
+    runtime: &Runtime,
+    origin: &Origin<'_>,
+    message: OutgoingMessage,
+    error: &anyhow::Error,
+    outbox: Option<&Outbox>,
+) {
+    if let Some(sender) = origin.sender.filter(|_| fallback_dm::is_refused(error)) {
+        let reason = format!("{:#}", error);
+        let fallback = fallback_dm::deliver(
+            runtime,
+            origin.room_id,
+            origin.trigger,
+            sender,
+            message.clone(),
+            &reason,
+        )
+        .await;
+        if let Some((disposition, detail)) = fallback {
+            if let Some(outbox) = outbox {
+                outbox
+                    .record(
+                        origin.room_id,
+                        origin.trigger.as_str(),
+                        origin.request_id,
+                        disposition,
+                        &detail,
+                    )
+                    .await;
+            }
+            return;
+        }
+    }
+    if let Some(outbox) = outbox {
+        outbox.defer(origin, &message, error).await;
+    }
+}
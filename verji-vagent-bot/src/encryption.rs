@@ -1,5 +1,5 @@
 use anyhow::Result;
-use matrix_sdk::Client;
+use matrix_sdk::{room::Room, ruma::events::room::encryption::OriginalSyncRoomEncryptionEvent, Client};
 use std::path::PathBuf;
 use tracing::{info, warn};
 
@@ -189,6 +189,13 @@ pub async fn setup_backup_only(client: &Client, store_path: &PathBuf) -> Result<
     setup_recovery_and_backups(client, store_path, false).await
 }
 
+/// Log rooms that enable encryption while the bot is running
+pub fn log_encryption_changes(client: &Client) {
+    client.add_event_handler(|event: OriginalSyncRoomEncryptionEvent, room: Room| async move {
+        info!("🔐 Room {} enabled encryption ({})", room.room_id(), event.content.algorithm);
+    });
+}
+
 /// Log encryption status
 pub async fn log_encryption_status(client: &Client, label: &str) {
     info!("🔐 Encryption status {}:", label);
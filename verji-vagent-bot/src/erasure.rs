@@ -0,0 +1,185 @@
+//! Erasure of everything the bot stored about a user (GDPR right to erasure)
+//!
+//! Covers the usage stats and interaction history, quota windows,
+//! conversation state (pending HITL requests), scheduled follow-ups
+//! (reminders), journaled failed
+//! requests, answer feedback, preferences and the response cache, then tells vagent-graph to purge the user's session memory. The
+//! audit observer only writes to the log output, so there is nothing stored
+//! to erase there. Running it again is harmless: every count is then zero.
+
+use anyhow::Result;
+use std::fmt::Write;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::conversation::ConversationStore;
+use crate::feedback::FeedbackStore;
+use crate::follow_up::FollowUpScheduler;
+use crate::preferences::PreferenceStore;
+use crate::quota::QuotaStore;
+use crate::replay::FailedRequests;
+use crate::responders::VerjiAgentResponder;
+use crate::stats::UsageStats;
+
+/// What happened to the erasure notice for vagent-graph
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphNotice {
+    /// Dry run, nothing was sent
+    Skipped,
+    Sent,
+    Failed(String),
+}
+
+/// Records removed (or with a dry run, found) per store
+#[derive(Debug, Clone)]
+pub struct ErasureReport {
+    pub user_id: String,
+    pub dry_run: bool,
+    pub counts: Vec<(&'static str, usize)>,
+    /// Rooms the user has agent usage in, sent to vagent-graph
+    pub rooms: Vec<String>,
+    pub graph: GraphNotice,
+}
+
+impl ErasureReport {
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Markdown table of the counts plus the graph notice outcome
+    pub fn render(&self) -> String {
+        let mut out = if self.dry_run {
+            format!(
+                "**Data stored about {}** (dry run, nothing was deleted)\n\n",
+                self.user_id
+            )
+        } else {
+            format!("**Erased data of {}**\n\n", self.user_id)
+        };
+        out.push_str("| Store | Records |\n|---|---|\n");
+        for (store, count) in &self.counts {
+            let _ = writeln!(out, "| {} | {} |", store, count);
+        }
+
+        let rooms = self.rooms.len();
+        let graph = match &self.graph {
+            GraphNotice::Skipped => format!("would be sent ({} rooms)", rooms),
+            GraphNotice::Sent => format!("sent ({} rooms)", rooms),
+            GraphNotice::Failed(e) => format!("**failed**: {} (run the erasure again)", e),
+        };
+        let _ = write!(out, "\nvagent-graph session memory purge: {}\n", graph);
+        out
+    }
+}
+
+/// Erases a user's data across the bot's stores
+pub struct Erasure {
+    stats: Arc<UsageStats>,
+    quotas: Arc<QuotaStore>,
+    conversations: Arc<ConversationStore>,
+    follow_ups: Arc<FollowUpScheduler>,
+    failed: Arc<FailedRequests>,
+    feedback: Arc<FeedbackStore>,
+    preferences: Arc<PreferenceStore>,
+    agent: Arc<VerjiAgentResponder>,
+}
+
+impl Erasure {
+    pub fn new(
+        stats: Arc<UsageStats>,
+        quotas: Arc<QuotaStore>,
+        conversations: Arc<ConversationStore>,
+        follow_ups: Arc<FollowUpScheduler>,
+        failed: Arc<FailedRequests>,
+        feedback: Arc<FeedbackStore>,
+        preferences: Arc<PreferenceStore>,
+        agent: Arc<VerjiAgentResponder>,
+    ) -> Self {
+        Self {
+            stats,
+            quotas,
+            conversations,
+            follow_ups,
+            failed,
+            feedback,
+            preferences,
+            agent,
+        }
+    }
+
+    /// Erase (or with `dry_run` only count) the user's data
+    ///
+    /// State kept per person (stats, quotas, preferences, graph sessions) is
+    /// erased for `identity`, the canonical identity of `user_id` (see
+    /// `identity`); with bridged accounts mapped to one identity, that covers
+    /// all of them. Local stores are erased even if vagent-graph can't be
+    /// reached; the failure is in the report so the erasure can be repeated.
+    pub async fn erase(
+        &self,
+        user_id: &str,
+        identity: &str,
+        dry_run: bool,
+    ) -> Result<ErasureReport> {
+        // Read before the stats are gone
+        let rooms = self.stats.user_rooms(identity);
+
+        let mut counts = self.stats.erase_user(identity, dry_run).await?;
+        counts.push((
+            "responder_quotas",
+            self.quotas.erase_user(identity, dry_run).await?,
+        ));
+        counts.push((
+            "conversation_state",
+            self.conversations.erase_user(user_id, dry_run).await?,
+        ));
+        counts.push((
+            "follow_ups",
+            self.follow_ups.erase_user(user_id, dry_run).await?,
+        ));
+        counts.push((
+            "failed_requests",
+            self.failed.erase_user(user_id, dry_run).await?,
+        ));
+        counts.push((
+            "feedback",
+            self.feedback.erase_user(user_id, dry_run).await?,
+        ));
+        counts.push((
+            "preferences",
+            self.preferences.erase_user(identity, dry_run).await?,
+        ));
+        counts.push((
+            "response_cache (all entries)",
+            self.agent.clear_response_cache(dry_run),
+        ));
+
+        let graph = if dry_run {
+            GraphNotice::Skipped
+        } else {
+            match self.agent.service().notify_erasure(identity, &rooms).await {
+                Ok(()) => GraphNotice::Sent,
+                Err(e) => {
+                    warn!("Erasure notice for {} not delivered: {:#}", user_id, e);
+                    GraphNotice::Failed(format!("{:#}", e))
+                }
+            }
+        };
+
+        let report = ErasureReport {
+            user_id: user_id.to_string(),
+            dry_run,
+            counts,
+            rooms,
+            graph,
+        };
+        if !dry_run {
+            info!(
+                "🗑️  Erased {} records of {} (graph notice: {:?})",
+                report.total(),
+                user_id,
+                report.graph
+            );
+        }
+        Ok(report)
+    }
+}
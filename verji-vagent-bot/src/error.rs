@@ -0,0 +1,246 @@
+//! Failure classes the bot reacts to differently
+//!
+//! Errors reach the bot as `anyhow` chains wrapping matrix-sdk, Redis or
+//! transport errors. [`BotError::classify`] looks for the typed error in the
+//! chain, never at its text, and tells callers whether to retry, what to tell
+//! the operator and which exit code to use.
+//!
+//! Exit codes tell a supervisor whether restarting can help:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0    | Clean shutdown |
+//! | 10   | Configuration or credentials are wrong; restarting is pointless |
+//! | 20   | Infrastructure failed for now (also unrecognized errors); restart |
+//! | 30   | The store needs an operator (damaged, or another device's) |
+//!
+//! With systemd, `RestartPreventExitStatus=10 30` stops the crash loops.
+
+use matrix_sdk::encryption::{CryptoStoreError, OlmError};
+use matrix_sdk::ruma::api::client::error::{ErrorKind, RetryAfter};
+use matrix_sdk::HttpError;
+use std::time::{Duration, SystemTime};
+
+use crate::agent_service::CircuitOpen;
+use crate::transport;
+
+/// A failure the bot knows how to handle
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BotError {
+    /// A setting is missing or invalid
+    #[error("Invalid configuration")]
+    Config,
+    /// A database in the store is damaged
+    #[error("The store is damaged")]
+    StoreCorrupt,
+    /// The crypto store was created for another account or device
+    #[error("The crypto store belongs to a different account or device")]
+    CryptoStoreMismatch,
+    /// The homeserver rejected the credentials or access token
+    #[error("The homeserver rejected the bot's credentials")]
+    AuthFailed,
+    /// The homeserver asked to slow down
+    #[error("Rate limited by the homeserver")]
+    RateLimited { retry_after: Option<Duration> },
+    /// Connection problem or server error worth retrying
+    #[error("Temporary network failure")]
+    NetworkTransient,
+    /// vagent-graph sent no final message in time
+    #[error("Timeout waiting for vagent-graph")]
+    GraphTimeout,
+    /// vagent-graph (or its broker) can't be reached
+    #[error("vagent-graph can't be reached")]
+    GraphUnavailable,
+}
+
+impl BotError {
+    /// Class of the first recognized error in the chain
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<BotError>() {
+                return Some(e.clone());
+            }
+            if let Some(e) = cause.downcast_ref::<matrix_sdk::Error>() {
+                return Self::from_matrix(e);
+            }
+            if let Some(e) = cause.downcast_ref::<HttpError>() {
+                return Self::from_http(e);
+            }
+            if let Some(e) = cause.downcast_ref::<CryptoStoreError>() {
+                return Self::from_crypto_store(e);
+            }
+            if let Some(e) = cause.downcast_ref::<redis::RedisError>() {
+                return Self::from_redis(e);
+            }
+            if let Some(e) = cause.downcast_ref::<rusqlite::Error>() {
+                return Self::from_sqlite(e);
+            }
+            if cause.downcast_ref::<transport::GraphTimeout>().is_some() {
+                return Some(BotError::GraphTimeout);
+            }
+            if cause.downcast_ref::<CircuitOpen>().is_some() {
+                return Some(BotError::GraphUnavailable);
+            }
+            None
+        })
+    }
+
+    pub fn from_matrix(error: &matrix_sdk::Error) -> Option<Self> {
+        match error {
+            matrix_sdk::Error::Http(e) => Self::from_http(e),
+            matrix_sdk::Error::CryptoStoreError(e) => Self::from_crypto_store(e),
+            matrix_sdk::Error::OlmError(e) => Self::from_olm(e),
+            matrix_sdk::Error::AuthenticationRequired => Some(BotError::AuthFailed),
+            _ => None,
+        }
+    }
+
+    pub fn from_http(error: &HttpError) -> Option<Self> {
+        if let HttpError::Reqwest(_) = error {
+            return Some(BotError::NetworkTransient);
+        }
+        if let Some(kind) = error.client_api_error_kind() {
+            if let Some(class) = Self::from_error_kind(kind) {
+                return Some(class);
+            }
+        }
+        error
+            .as_client_api_error()
+            .filter(|e| e.status_code.is_server_error())
+            .map(|_| BotError::NetworkTransient)
+    }
+
+    /// Class of a Matrix client API error code
+    pub fn from_error_kind(kind: &ErrorKind) -> Option<Self> {
+        match kind {
+            ErrorKind::Forbidden { .. }
+            | ErrorKind::UnknownToken { .. }
+            | ErrorKind::MissingToken
+            | ErrorKind::UserDeactivated => Some(BotError::AuthFailed),
+            ErrorKind::LimitExceeded { retry_after } => Some(BotError::RateLimited {
+                retry_after: retry_after.as_ref().map(|retry_after| match retry_after {
+                    RetryAfter::Delay(delay) => *delay,
+                    RetryAfter::DateTime(at) => {
+                        at.duration_since(SystemTime::now()).unwrap_or_default()
+                    }
+                }),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn from_crypto_store(error: &CryptoStoreError) -> Option<Self> {
+        match error {
+            CryptoStoreError::MismatchedAccount { .. } => Some(BotError::CryptoStoreMismatch),
+            _ => None,
+        }
+    }
+
+    pub fn from_olm(error: &OlmError) -> Option<Self> {
+        match error {
+            OlmError::Store(e) => Self::from_crypto_store(e),
+            _ => None,
+        }
+    }
+
+    /// Redis only carries requests to vagent-graph, so its failures are the graph's
+    pub fn from_redis(error: &redis::RedisError) -> Option<Self> {
+        if error.is_timeout() {
+            Some(BotError::GraphTimeout)
+        } else if error.is_connection_refusal()
+            || error.is_connection_dropped()
+            || error.is_io_error()
+            || error.kind() == redis::ErrorKind::AuthenticationFailed
+        {
+            Some(BotError::GraphUnavailable)
+        } else {
+            None
+        }
+    }
+
+    /// A damaged bot database; other SQLite failures are not classified
+    pub fn from_sqlite(error: &rusqlite::Error) -> Option<Self> {
+        match error {
+            rusqlite::Error::SqliteFailure(e, _)
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase
+                ) =>
+            {
+                Some(BotError::StoreCorrupt)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether trying again later can succeed
+    pub fn is_transient(&self) -> bool {
+        !matches!(
+            self,
+            BotError::Config
+                | BotError::StoreCorrupt
+                | BotError::CryptoStoreMismatch
+                | BotError::AuthFailed
+        )
+    }
+
+    /// Process exit code when the bot stops because of this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BotError::Config | BotError::AuthFailed => exit_code::CONFIG,
+            BotError::StoreCorrupt | BotError::CryptoStoreMismatch => exit_code::STORE,
+            BotError::RateLimited { .. }
+            | BotError::NetworkTransient
+            | BotError::GraphTimeout
+            | BotError::GraphUnavailable => exit_code::TRANSIENT,
+        }
+    }
+
+    /// What the operator can do about it
+    pub fn hint(&self) -> &'static str {
+        match self {
+            BotError::Config => {
+                "Fix the setting named above (see .env.example) and start the bot again."
+            }
+            BotError::StoreCorrupt => {
+                "Restore MATRIX_STORE_PATH from a backup, or restart with --clear-store to log in \
+                 as a new device."
+            }
+            BotError::CryptoStoreMismatch => {
+                "The store was created for another device. Restart with --clear-store to log in again, \
+                 or point MATRIX_STORE_PATH at this device's store."
+            }
+            BotError::AuthFailed => {
+                "Check MATRIX_USER and MATRIX_PASSWORD; a revoked session needs --clear-store."
+            }
+            BotError::RateLimited { .. } => "The homeserver is rate limiting the bot; try again later.",
+            BotError::NetworkTransient => "The homeserver can't be reached right now; try again later.",
+            BotError::GraphTimeout | BotError::GraphUnavailable => {
+                "Check that vagent-graph and its transport (GRAPH_TRANSPORT) are running."
+            }
+        }
+    }
+}
+
+/// Process exit codes, see the module docs
+pub mod exit_code {
+    /// Clean shutdown
+    pub const CLEAN: i32 = 0;
+    /// Configuration or credentials are wrong; restarting is pointless
+    pub const CONFIG: i32 = 10;
+    /// Infrastructure failed for now; restarting is sensible
+    pub const TRANSIENT: i32 = 20;
+    /// The store needs operator action
+    pub const STORE: i32 = 30;
+}
+
+/// The exit code for how the bot (or a subcommand) ended
+///
+/// Errors the taxonomy doesn't recognize count as transient, so the
+/// supervisor keeps trying.
+pub fn exit_code_of(result: &anyhow::Result<()>) -> i32 {
+    match result {
+        Ok(()) => exit_code::CLEAN,
+        Err(e) => BotError::classify(e).map_or(exit_code::TRANSIENT, |class| class.exit_code()),
+    }
+}
@@ -0,0 +1,169 @@
+//! Answers sent by direct message when the room they belong in refuses them
+//!
+//! Sometimes the bot can't post in the room it was asked in any more: its
+//! power levels were revoked mid-conversation, or the room became invite-only
+//! after an upgrade. With `FALLBACK_DM=true`, a final answer the room refuses
+//! (or one the outbox gives up on) goes to the asker's direct chat with a note
+//! why; if that fails too, `ADMIN_ROOM` is alerted. Answers that fail in the
+//! asker's direct chat itself never fall back again, and the DM is sent
+//! directly, so a failing DM can't start another fallback. The outbox records
+//! where each answer ended up.
+
+use matrix_sdk::ruma::api::client::error::ErrorKind;
+use matrix_sdk::ruma::{EventId, RoomId};
+use matrix_sdk::{Client, HttpError};
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+use crate::admin_room;
+use crate::direct;
+use crate::outbox::{self, Disposition};
+use crate::responder::OutgoingMessage;
+use crate::room::{BotRoom, RoomHandle};
+use crate::runtime::Runtime;
+
+struct Fallback {
+    client: Client,
+    admin_room: Option<String>,
+    /// Language of the note in front of the answer
+    language: String,
+}
+
+/// A bot's DM fallback; off until [`FallbackDm::init`]
+#[derive(Default)]
+pub struct FallbackDm {
+    fallback: OnceLock<Fallback>,
+}
+
+impl FallbackDm {
+    /// Turn the fallback on; without this call answers are never sent by DM
+    pub fn init(&self, client: &Client, admin_room: Option<String>, language: &str) {
+        let fallback = Fallback {
+            client: client.clone(),
+            admin_room,
+            language: language.to_string(),
+        };
+        if self.fallback.set(fallback).is_ok() {
+            info!("📨 Answers a room refuses are sent by direct message");
+        }
+    }
+
+    /// Whether `FALLBACK_DM` is on
+    pub fn is_enabled(&self) -> bool {
+        self.fallback.get().is_some()
+    }
+}
+
+/// Whether the room refused the send for good, so retrying is pointless
+///
+/// That is the homeserver answering `M_FORBIDDEN` (no permission to post) or
+/// the bot no longer being joined to the room.
+pub fn is_refused(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<matrix_sdk::Error>() {
+            return match e {
+                matrix_sdk::Error::Http(e) => is_forbidden(e),
+                matrix_sdk::Error::WrongRoomState(_) => true,
+                _ => false,
+            };
+        }
+        cause.downcast_ref::<HttpError>().is_some_and(is_forbidden)
+    })
+}
+
+fn is_forbidden(error: &HttpError) -> bool {
+    matches!(
+        error.client_api_error_kind(),
+        Some(ErrorKind::Forbidden { .. })
+    )
+}
+
+/// Send `message`, which `room_id` didn't take, to `asker`'s direct chat
+///
+/// Alerts `ADMIN_ROOM` when the direct chat fails too. Returns where the
+/// answer ended up with a detail for the outbox record, or None when the
+/// fallback is off.
+pub async fn deliver(
+    runtime: &Runtime,
+    room_id: &str,
+    trigger: &EventId,
+    asker: &str,
+    message: OutgoingMessage,
+    reason: &str,
+) -> Option<(Disposition, String)> {
+    let fallback = runtime.fallback_dm.fallback.get()?;
+    let client = &fallback.client;
+    let room_name = RoomId::parse(room_id)
+        .ok()
+        .and_then(|room_id| client.get_room(&room_id))
+        .map_or_else(
+            || room_id.to_string(),
+            |room| BotRoom::new(room, runtime.clone()).display_name(),
+        );
+
+    let failure = match direct::dm_room(runtime, client, asker).await {
+        Ok(dm) if dm.room_id().as_str() == room_id => {
+            "the answer was for the direct chat itself".to_string()
+        }
+        Ok(dm) => {
+            let note = runtime.catalog.translate(
+                &fallback.language,
+                "fallback_dm.note",
+                &[("room", &room_name)],
+            );
+            let message = outbox::with_marker(message, &note);
+            match dm.send_content(trigger, message).await {
+                Ok(_) => {
+                    runtime
+                        .metrics
+                        .increment("fallback_dm_total", &[("result", "delivered")]);
+                    info!(
+                        "📨 Sent the answer for {} to {} by direct message ({})",
+                        trigger, asker, reason
+                    );
+                    return Some((
+                        Disposition::DirectMessage,
+                        format!("sent to {} in {}", asker, dm.room_id()),
+                    ));
+                }
+                Err(e) => format!("{:#}", e),
+            }
+        }
+        Err(e) => format!("{:#}", e),
+    };
+
+    warn!(
+        "📨 The answer for {} in {} could not be sent to {} either: {}",
+        trigger, room_id, asker, failure
+    );
+    let detail = format!("direct chat with {} failed: {}", asker, failure);
+    let alerted = match &fallback.admin_room {
+        Some(admin_room) => {
+            let text = format!(
+                "🚨 **Undeliverable answer**: the answer to {} in {} (`{}`) could not be \
+                 posted there ({}), and not in their direct chat either ({}).",
+                asker, room_name, trigger, reason, failure
+            );
+            admin_room::post(
+                runtime,
+                client,
+                admin_room,
+                "undeliverable answer alert",
+                &text,
+            )
+            .await
+        }
+        None => false,
+    };
+    if alerted {
+        runtime
+            .metrics
+            .increment("fallback_dm_total", &[("result", "admin_alert")]);
+        Some((Disposition::AdminAlert, detail))
+    } else {
+        runtime
+            .metrics
+            .increment("fallback_dm_total", &[("result", "lost")]);
+        Some((Disposition::Abandoned, detail))
+    }
+}
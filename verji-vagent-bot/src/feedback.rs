@@ -0,0 +1,311 @@
+//! 👍/👎 reactions to agent answers
+//!
+//! A 👍 or 👎 on a message the sent event registry knows as an agent answer is
+//! stored as the reacting user's verdict on that answer's request; reacting
+//! again replaces the verdict. The question is kept with it while the answer
+//! is still the asker's latest one in the room (conversation slot
+//! `feedback.answer`).
+
+use anyhow::{Context, Result};
+use matrix_sdk::ruma::EventId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::db;
+use crate::sent_events::{SentEvent, SentEventRegistry, SentKind};
+use crate::stats::{self, csv_field};
+
+/// Conversation slot holding a user's latest agent answer in a room
+pub const ANSWER_SLOT: &str = "feedback.answer";
+
+/// How long after an answer reactions still count as feedback on it
+pub const ANSWER_FEEDBACK_WINDOW: Duration = Duration::from_secs(24 * 3600);
+
+/// Characters of the question kept with the feedback
+const QUESTION_PREVIEW_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Up,
+    Down,
+}
+
+impl Verdict {
+    /// Verdict of a reaction key; skin tone variants count too
+    pub fn from_reaction(key: &str) -> Option<Self> {
+        if key.starts_with('👍') {
+            Some(Verdict::Up)
+        } else if key.starts_with('👎') {
+            Some(Verdict::Down)
+        } else {
+            None
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Up => "up",
+            Verdict::Down => "down",
+        }
+    }
+}
+
+/// The answer feedback would be about, kept in the conversation store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnsweredRequest {
+    pub request_id: String,
+    /// First characters of the question
+    pub question: String,
+    /// Unix milliseconds when the query was received
+    pub asked_at_ms: u64,
+}
+
+impl AnsweredRequest {
+    pub fn new(request_id: &str, question: &str, asked_at_ms: u64) -> Self {
+        let mut preview: String = question.chars().take(QUESTION_PREVIEW_CHARS).collect();
+        if preview.len() < question.len() {
+            preview.push('…');
+        }
+        Self {
+            request_id: request_id.to_string(),
+            question: preview,
+            asked_at_ms,
+        }
+    }
+}
+
+/// 👍/👎 totals of one room
+#[derive(Debug, Clone, Default)]
+pub struct FeedbackTotals {
+    pub up: u64,
+    pub down: u64,
+}
+
+impl FeedbackTotals {
+    /// Share of 👍 as a percentage
+    pub fn ratio(&self) -> String {
+        match self.up + self.down {
+            0 => "-".to_string(),
+            total => format!("{}%", self.up * 100 / total),
+        }
+    }
+}
+
+/// Stored verdicts on agent answers, in the bot database
+pub struct FeedbackStore {
+    db_path: PathBuf,
+}
+
+impl FeedbackStore {
+    pub fn open(store_path: &Path) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS feedback (
+                request_id TEXT NOT NULL,
+                room_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                verdict TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                question TEXT NOT NULL,
+                PRIMARY KEY (request_id, user_id)
+            );
+            CREATE INDEX IF NOT EXISTS feedback_time ON feedback (timestamp);
+            CREATE INDEX IF NOT EXISTS feedback_room_time ON feedback (room_id, timestamp);",
+        )
+        .context("Failed to create feedback table")?;
+
+        Ok(Self { db_path })
+    }
+
+    /// Store a reaction as feedback if it is on an agent answer
+    ///
+    /// Returns whether it was stored.
+    pub async fn on_reaction(
+        &self,
+        room_id: &str,
+        conversations: &ConversationStore,
+        sent_events: &SentEventRegistry,
+        sender: &str,
+        target: &EventId,
+        key: &str,
+    ) -> Result<bool> {
+        let Some(verdict) = Verdict::from_reaction(key) else {
+            return Ok(false);
+        };
+        let Some(SentEvent {
+            kind: SentKind::Final,
+            request_id: Some(request_id),
+            ..
+        }) = sent_events.lookup(target).await
+        else {
+            return Ok(false);
+        };
+        let answer_key = ConversationKey::new(room_id, sender, ANSWER_SLOT);
+        let question = conversations
+            .get(&answer_key)
+            .await
+            .and_then(|value| serde_json::from_value::<AnsweredRequest>(value).ok())
+            .filter(|answer| answer.request_id == request_id)
+            .map(|answer| answer.question)
+            .unwrap_or_default();
+
+        let db_path = self.db_path.clone();
+        let room_id = room_id.to_string();
+        let user = sender.to_string();
+        let logged_request_id = request_id.clone();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = db::open(&db_path)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO feedback
+                    (request_id, room_id, user_id, verdict, timestamp, question)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![
+                    request_id,
+                    room_id,
+                    user,
+                    verdict.as_str(),
+                    db::now_secs() as i64,
+                    question
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .context("Feedback write panicked")??;
+
+        info!("👍 Feedback {:?} on request {}", verdict, logged_request_id);
+        Ok(true)
+    }
+
+    /// Totals per room since `since` (unix seconds), most feedback first
+    pub async fn totals(&self, since: u64) -> Result<Vec<(String, FeedbackTotals)>> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<(String, FeedbackTotals)>> {
+            let conn = db::open(&db_path)?;
+            let mut stmt = conn.prepare(
+                "SELECT room_id,
+                        SUM(verdict = 'up'),
+                        SUM(verdict = 'down')
+                 FROM feedback WHERE timestamp >= ?1
+                 GROUP BY room_id
+                 ORDER BY COUNT(*) DESC, room_id",
+            )?;
+            let rows = stmt.query_map([since as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    FeedbackTotals {
+                        up: row.get::<_, i64>(1)? as u64,
+                        down: row.get::<_, i64>(2)? as u64,
+                    },
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+                .context("Failed to read feedback totals")
+        })
+        .await
+        .context("Feedback query panicked")?
+    }
+
+    /// Write raw records since `since` (unix seconds) as CSV, oldest first
+    ///
+    /// With `anonymize` users are replaced by a hash of their Matrix ID.
+    pub fn export_csv<W: Write>(&self, writer: &mut W, since: u64, anonymize: bool) -> Result<()> {
+        let conn = db::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT request_id, room_id, user_id, verdict, timestamp, question FROM feedback
+             WHERE timestamp >= ?1 ORDER BY timestamp, rowid",
+        )?;
+        let mut rows = stmt.query([since as i64])?;
+
+        writeln!(writer, "request_id,room_id,user,verdict,timestamp,question")?;
+        while let Some(row) = rows.next()? {
+            let user: String = row.get(2)?;
+            let user = if anonymize { hash_user(&user) } else { user };
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                csv_field(&row.get::<_, String>(0)?),
+                csv_field(&row.get::<_, String>(1)?),
+                csv_field(&user),
+                row.get::<_, String>(3)?,
+                stats::format_timestamp(row.get::<_, i64>(4)? as u64),
+                csv_field(&row.get::<_, String>(5)?)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Delete (or with `dry_run` count) a user's verdicts
+    pub async fn erase_user(&self, user_id: &str, dry_run: bool) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        let user = user_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = db::open(&db_path)?;
+            db::erase_rows(&conn, "feedback", "user_id = ?1", [&user], dry_run)
+        })
+        .await
+        .context("Feedback erasure panicked")?
+    }
+}
+
+/// Stable pseudonym of a user for exports (first 16 hex digits of SHA-256)
+fn hash_user(user_id: &str) -> String {
+    let digest = Sha256::digest(user_id.as_bytes());
+    hex::encode(&digest[..8])
+}
+
+/// Markdown table of per-room totals with an overall row
+pub fn render_totals(title: &str, rooms: &[(String, FeedbackTotals)]) -> String {
+    if rooms.is_empty() {
+        return format!("{}\n\nNo feedback recorded in this period.", title);
+    }
+
+    let mut total = FeedbackTotals::default();
+    let mut out = format!(
+        "{}\n\n| Room | 👍 | 👎 | 👍 share |\n|---|---:|---:|---:|\n",
+        title
+    );
+    for (room, totals) in rooms {
+        total.up += totals.up;
+        total.down += totals.down;
+        out.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            room,
+            totals.up,
+            totals.down,
+            totals.ratio()
+        ));
+    }
+    out.push_str(&format!(
+        "| **Total** | {} | {} | {} |\n",
+        total.up,
+        total.down,
+        total.ratio()
+    ));
+    out
+}
+
+/// Remember the answer a user's next 👍/👎 refers to
+pub async fn remember_answer(
+    conversations: &ConversationStore,
+    room_id: &str,
+    user_id: &str,
+    answer: &AnsweredRequest,
+) {
+    let value: Value = serde_json::to_value(answer).unwrap_or_default();
+    conversations
+        .set(
+            ConversationKey::new(room_id, user_id, ANSWER_SLOT),
+            value,
+            Some(ANSWER_FEEDBACK_WINDOW),
+        )
+        .await;
+}
@@ -0,0 +1,421 @@
+//! Follow-up actions responders schedule for later
+//!
+//! Features that need to "do something later in this room" (HITL reminders
+//! and expiry, progress notices) schedule a [`FollowUpAction`] instead of
+//! spawning their own task. Follow-ups are kept in the bot database and run by
+//! one background task, so they survive restarts: those that fell due while
+//! the bot was down run right after the initial sync. Each follow-up has a
+//! token to cancel it by; a room can have at most `FOLLOW_UPS_PER_ROOM_MAX`
+//! pending.
+
+use anyhow::{bail, Context, Result};
+use matrix_sdk::ruma::RoomId;
+use matrix_sdk::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::db;
+use crate::dispatcher;
+use crate::hitl::{self, HITL_SLOT};
+use crate::mentions;
+use crate::metrics::Metrics;
+use crate::redis_client::{GraphRequest, RequestKind};
+use crate::responder::OutgoingMessage;
+use crate::responders::VerjiAgentResponder;
+use crate::room::{BotRoom, RoomHandle};
+use crate::runtime::Runtime;
+use crate::shadow::RoomWrite;
+
+/// How often the runner looks for due follow-ups
+pub const RUN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a follow-up does when it is due
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FollowUpAction {
+    /// Send a Markdown message, notifying `mention` if set
+    SendText {
+        body: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mention: Option<String>,
+    },
+    /// Send a notice
+    SendNotice { body: String },
+    /// Send a request vagent-graph doesn't answer (e.g. `hitl_cancel`), then
+    /// `notice` notifying the user
+    ///
+    /// A `hitl_cancel` is only sent while the user's question is still
+    /// pending: it claims the question like an answer would, so a question is
+    /// never both resumed and cancelled.
+    PublishControl {
+        kind: RequestKind,
+        request_id: String,
+        user_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        notice: Option<String>,
+    },
+}
+
+impl FollowUpAction {
+    /// The user the action is about, so their follow-ups can be erased
+    fn user_id(&self) -> Option<&str> {
+        match self {
+            FollowUpAction::SendText { mention, .. } => mention.as_deref(),
+            FollowUpAction::SendNotice { .. } => None,
+            FollowUpAction::PublishControl { user_id, .. } => Some(user_id),
+        }
+    }
+}
+
+/// A follow-up waiting to be run
+#[derive(Debug, Clone)]
+pub struct FollowUp {
+    pub token: String,
+    pub room_id: String,
+    /// Unix seconds
+    pub due_at: u64,
+    pub action: FollowUpAction,
+}
+
+/// The follow-up table in the bot database
+pub struct FollowUpScheduler {
+    db_path: PathBuf,
+    /// Pending follow-ups allowed per room
+    per_room_max: usize,
+    metrics: Arc<Metrics>,
+}
+
+impl FollowUpScheduler {
+    pub fn open(store_path: &Path, per_room_max: usize) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS follow_ups (
+                token TEXT PRIMARY KEY,
+                room_id TEXT NOT NULL,
+                user_id TEXT,
+                due_at INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS follow_ups_due ON follow_ups (due_at);
+            CREATE INDEX IF NOT EXISTS follow_ups_room ON follow_ups (room_id);",
+        )
+        .context("Failed to create follow-up table")?;
+
+        let pending: i64 =
+            conn.query_row("SELECT COUNT(*) FROM follow_ups", [], |row| row.get(0))?;
+        if pending > 0 {
+            info!("⏰ {} follow-ups pending", pending);
+        }
+        Ok(Self {
+            db_path,
+            per_room_max,
+            metrics: Arc::new(Metrics::default()),
+        })
+    }
+
+    /// Count scheduled follow-ups in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Run `action` in the room after `delay`, returning the token to cancel it by
+    pub async fn schedule(
+        &self,
+        room_id: &str,
+        delay: Duration,
+        action: FollowUpAction,
+    ) -> Result<String> {
+        let token = uuid::Uuid::new_v4().to_string();
+        self.schedule_as(&token, room_id, delay, action).await?;
+        Ok(token)
+    }
+
+    /// Like `schedule`, with a token chosen by the caller
+    ///
+    /// A pending follow-up with the same token is replaced, so features can
+    /// push their deadline back without cancelling first.
+    pub async fn schedule_as(
+        &self,
+        token: &str,
+        room_id: &str,
+        delay: Duration,
+        action: FollowUpAction,
+    ) -> Result<()> {
+        let json = serde_json::to_string(&action).context("Failed to serialize follow-up")?;
+        let db_path = self.db_path.clone();
+        let per_room_max = self.per_room_max;
+        let token = token.to_string();
+        let room_id = room_id.to_string();
+        let user_id = action.user_id().map(str::to_string);
+        let now = db::now_secs();
+        let due_at = now + delay.as_secs();
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = db::open(&db_path)?;
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM follow_ups WHERE token = ?1", [&token])?;
+            let pending: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM follow_ups WHERE room_id = ?1",
+                [&room_id],
+                |row| row.get(0),
+            )?;
+            if pending as usize >= per_room_max {
+                bail!("{} already has {} pending follow-ups", room_id, pending);
+            }
+            tx.execute(
+                "INSERT INTO follow_ups (token, room_id, user_id, due_at, action, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![token, room_id, user_id, due_at as i64, json, now as i64],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .context("Follow-up scheduling panicked")??;
+
+        self.metrics.increment("follow_ups_scheduled_total", &[]);
+        Ok(())
+    }
+
+    /// Drop a pending follow-up; false if it already ran or never existed
+    pub async fn cancel(&self, token: &str) -> bool {
+        let db_path = self.db_path.clone();
+        let token = token.to_string();
+        let result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            Ok(db::open(&db_path)?.execute("DELETE FROM follow_ups WHERE token = ?1", [&token])?)
+        })
+        .await;
+        match result {
+            Ok(Ok(removed)) => removed > 0,
+            Ok(Err(e)) => {
+                warn!("Failed to cancel a follow-up: {:#}", e);
+                false
+            }
+            Err(e) => {
+                warn!("Follow-up cancellation panicked: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Drop every pending follow-up of a room, returning how many there were
+    pub async fn cancel_room(&self, room_id: &str) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        let room_id = room_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            Ok(db::open(&db_path)?
+                .execute("DELETE FROM follow_ups WHERE room_id = ?1", [&room_id])?)
+        })
+        .await
+        .context("Follow-up cancellation panicked")?
+    }
+
+    /// Delete (or with `dry_run` count) the follow-ups about a user
+    pub async fn erase_user(&self, user_id: &str, dry_run: bool) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        let user_id = user_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            db::erase_rows(
+                &db::open(&db_path)?,
+                "follow_ups",
+                "user_id = ?1",
+                [&user_id],
+                dry_run,
+            )
+        })
+        .await
+        .context("Follow-up erasure panicked")?
+    }
+
+    /// Remove and return the follow-ups due by now, earliest first
+    ///
+    /// Taking them out before they run means a follow-up runs at most once,
+    /// even if the bot stops halfway.
+    async fn take_due(&self) -> Result<Vec<FollowUp>> {
+        let db_path = self.db_path.clone();
+        let now = db::now_secs() as i64;
+        tokio::task::spawn_blocking(move || -> Result<Vec<FollowUp>> {
+            let mut conn = db::open(&db_path)?;
+            let tx = conn.transaction()?;
+            let rows = {
+                let mut stmt = tx.prepare(
+                    "SELECT token, room_id, due_at, action FROM follow_ups
+                     WHERE due_at <= ?1 ORDER BY due_at",
+                )?;
+                let rows = stmt.query_map([now], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)? as u64,
+                        row.get::<_, String>(3)?,
+                    ))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            tx.execute("DELETE FROM follow_ups WHERE due_at <= ?1", [now])?;
+            tx.commit()?;
+
+            let mut due = Vec::new();
+            for (token, room_id, due_at, action) in rows {
+                match serde_json::from_str(&action) {
+                    Ok(action) => due.push(FollowUp {
+                        token,
+                        room_id,
+                        due_at,
+                        action,
+                    }),
+                    Err(e) => warn!("Dropping unreadable follow-up {}: {}", token, e),
+                }
+            }
+            Ok(due)
+        })
+        .await
+        .context("Follow-up query panicked")?
+    }
+
+    /// Run the follow-ups that are due every `interval` until the bot stops
+    pub fn spawn_runner(
+        self: &Arc<Self>,
+        runtime: Runtime,
+        client: Client,
+        agent: Arc<VerjiAgentResponder>,
+        conversations: Arc<ConversationStore>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let scheduler = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let due = match scheduler.take_due().await {
+                    Ok(due) => due,
+                    Err(e) => {
+                        warn!("Failed to read due follow-ups: {:#}", e);
+                        continue;
+                    }
+                };
+                for follow_up in due {
+                    run(&runtime, &client, &agent, &conversations, follow_up).await;
+                }
+            }
+        })
+    }
+}
+
+async fn run(
+    runtime: &Runtime,
+    client: &Client,
+    agent: &VerjiAgentResponder,
+    conversations: &ConversationStore,
+    follow_up: FollowUp,
+) {
+    let Some(room) = RoomId::parse(&follow_up.room_id)
+        .ok()
+        .and_then(|room_id| client.get_room(&room_id))
+        .map(|room| BotRoom::new(room, runtime.clone()))
+    else {
+        warn!(
+            "⏰ Dropping follow-up {}: room {} is no longer known",
+            follow_up.token, follow_up.room_id
+        );
+        return;
+    };
+
+    let message = match follow_up.action {
+        FollowUpAction::SendText {
+            body,
+            mention: Some(user_id),
+        } => OutgoingMessage::mentioning(&user_id, body),
+        FollowUpAction::SendText {
+            body,
+            mention: None,
+        } => OutgoingMessage::Markdown(body),
+        FollowUpAction::SendNotice { body } => OutgoingMessage::Notice(body),
+        FollowUpAction::PublishControl {
+            kind,
+            request_id,
+            user_id,
+            thread_id,
+            notice,
+        } => {
+            if kind == RequestKind::HitlCancel {
+                let mut key = ConversationKey::new(&follow_up.room_id, &user_id, HITL_SLOT);
+                if let Some(thread_id) = &thread_id {
+                    key = key.in_thread(thread_id);
+                }
+                if hitl::claim(conversations, &key, &request_id)
+                    .await
+                    .is_none()
+                {
+                    debug!(
+                        "⏰ HITL request {} was resolved before its deadline",
+                        request_id
+                    );
+                    return;
+                }
+                info!("⌛ HITL request {} expired for {}", request_id, user_id);
+                runtime.metrics.increment("hitl_expired_total", &[]);
+            }
+            let request = GraphRequest::new(
+                kind,
+                request_id,
+                String::new(),
+                follow_up.room_id.clone(),
+                user_id.clone(),
+            );
+            if let Err(e) = agent.service().publish(&request).await {
+                warn!(
+                    "Failed to send {:?} request {} to vagent-graph: {:#}",
+                    request.kind, request.request_id, e
+                );
+            }
+            match notice {
+                Some(notice) => OutgoingMessage::notice_mentioning(&user_id, notice),
+                None => return,
+            }
+        }
+    };
+
+    match send(&room, message).await {
+        Ok(()) => runtime.metrics.increment("follow_ups_run_total", &[]),
+        Err(e) => warn!(
+            "⏰ Follow-up {} in {} failed: {:#}",
+            follow_up.token, follow_up.room_id, e
+        ),
+    }
+}
+
+async fn send(room: &BotRoom, message: OutgoingMessage) -> Result<()> {
+    let runtime = room.runtime();
+    // Pills show display names, which need the room's member list
+    let names = match &message {
+        OutgoingMessage::Mention { mentions, .. } => mentions::display_names(room, mentions).await,
+        _ => HashMap::new(),
+    };
+    let content =
+        dispatcher::named_message_content(&message, &names).context("Not a text message")?;
+    let write = RoomWrite::new(room.room_id(), "message").body(content.body());
+    if runtime.shadow.suppress(write).is_some() {
+        return Ok(());
+    }
+    let mut turn = runtime.send_queues.turn(room.room_id(), None).await;
+    let event_id = turn
+        .send(runtime.pacer.send("message", || room.send(content.clone())))
+        .await
+        .context("Failed to send follow-up")?
+        .event_id;
+    drop(turn);
+    runtime.key_rotations.after_send(room, &event_id).await;
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Reply that goes back to the previous field
+const BACK: &str = "back";
+/// Reply that abandons the form
+const CANCEL: &str = "cancel";
+
+/// How an answer is interpreted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    #[default]
+    Text,
+    /// One of the field's `options` (case-insensitive)
+    Choice,
+    Number,
+}
+
+/// One question of a multi-step HITL form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormField {
+    /// Key of the answer in the resume payload
+    pub name: String,
+    pub prompt: String,
+    #[serde(rename = "type", default)]
+    pub field_type: FieldType,
+    /// Allowed answers of a choice field
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Regular expression the whole answer must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<String>,
+}
+
+/// Why an answer was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldError {
+    NotANumber,
+    NotAnOption,
+    NoMatch,
+}
+
+/// Result of feeding one user message to a form
+#[derive(Debug)]
+pub enum FormStep {
+    /// Moved to another field (after an answer or `back`); ask it
+    Ask,
+    /// The answer was rejected; ask the same field again
+    Invalid(FieldError),
+    /// Every field is answered; the map is the resume payload
+    Complete(Map<String, Value>),
+    /// The user asked to stop
+    Cancelled,
+}
+
+/// A HITL pause asking several questions, one message at a time
+///
+/// Graph metadata shape: `{"form": {"fields": [{"name", "prompt", "type",
+/// "options", "validation"}]}}`. The form carries its own progress so it can
+/// be stored between messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Form {
+    pub fields: Vec<FormField>,
+    /// Index of the field being asked
+    #[serde(default)]
+    pub step: usize,
+    #[serde(default)]
+    pub answers: Map<String, Value>,
+}
+
+impl Form {
+    /// Read a form from HITL request metadata, if it describes one
+    pub fn from_metadata(metadata: &Value) -> Result<Option<Self>> {
+        let Some(raw) = metadata.get("form") else {
+            return Ok(None);
+        };
+        let form: Form =
+            serde_json::from_value(raw.clone()).context("Malformed HITL form metadata")?;
+
+        if form.fields.is_empty() {
+            bail!("HITL form has no fields");
+        }
+        for field in &form.fields {
+            if let Some(pattern) = &field.validation {
+                Regex::new(pattern).with_context(|| {
+                    format!("Invalid validation pattern for field '{}'", field.name)
+                })?;
+            }
+            if field.field_type == FieldType::Choice && field.options.is_empty() {
+                bail!("Choice field '{}' has no options", field.name);
+            }
+        }
+        Ok(Some(form))
+    }
+
+    /// Field currently being asked
+    pub fn current(&self) -> &FormField {
+        &self.fields[self.step.min(self.fields.len() - 1)]
+    }
+
+    /// Prompt for the current field with its position and options
+    pub fn render_current(&self) -> String {
+        let field = self.current();
+        let mut text = format!(
+            "**({}/{})** {}",
+            self.step + 1,
+            self.fields.len(),
+            field.prompt
+        );
+        if field.field_type == FieldType::Choice {
+            let options: Vec<String> = field
+                .options
+                .iter()
+                .map(|option| format!("`{}`", option))
+                .collect();
+            text.push_str(&format!("\n\n{}", options.join(" / ")));
+        }
+        text
+    }
+
+    /// Apply one user message: an answer, `back` or `cancel`
+    pub fn advance(&mut self, input: &str) -> FormStep {
+        let input = input.trim();
+
+        if input.eq_ignore_ascii_case(CANCEL) {
+            return FormStep::Cancelled;
+        }
+        if input.eq_ignore_ascii_case(BACK) {
+            self.step = self.step.saturating_sub(1);
+            let name = self.current().name.clone();
+            self.answers.remove(&name);
+            return FormStep::Ask;
+        }
+
+        let field = self.current();
+        let value = match validate(field, input) {
+            Ok(value) => value,
+            Err(error) => return FormStep::Invalid(error),
+        };
+        let name = field.name.clone();
+        self.answers.insert(name, value);
+
+        if self.step + 1 >= self.fields.len() {
+            FormStep::Complete(self.answers.clone())
+        } else {
+            self.step += 1;
+            FormStep::Ask
+        }
+    }
+}
+
+/// Check an answer against the field and convert it to its payload value
+fn validate(field: &FormField, input: &str) -> Result<Value, FieldError> {
+    if let Some(pattern) = &field.validation {
+        // Patterns were checked when the form was read
+        let matches = Regex::new(pattern)
+            .map(|regex| {
+                regex
+                    .find(input)
+                    .is_some_and(|m| m.start() == 0 && m.end() == input.len())
+            })
+            .unwrap_or(true);
+        if !matches {
+            return Err(FieldError::NoMatch);
+        }
+    }
+
+    match field.field_type {
+        FieldType::Text => Ok(Value::String(input.to_string())),
+        FieldType::Number => input
+            .parse::<f64>()
+            .ok()
+            .and_then(|number| {
+                // Keep integers integral in the payload
+                if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+                    Some(Value::from(number as i64))
+                } else {
+                    serde_json::Number::from_f64(number).map(Value::Number)
+                }
+            })
+            .ok_or(FieldError::NotANumber),
+        FieldType::Choice => field
+            .options
+            .iter()
+            .find(|option| option.eq_ignore_ascii_case(input))
+            .map(|option| Value::String(option.clone()))
+            .ok_or(FieldError::NotAnOption),
+    }
+}
@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::config::BotConfig;
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::db;
+use crate::follow_up::{FollowUpAction, FollowUpScheduler};
+use crate::i18n::Catalog;
+use crate::mentions;
+use crate::redis_client::RequestKind;
+
+pub mod form;
+
+pub use form::Form;
+use form::{FieldError, FormStep};
+
+/// Conversation slot holding the question a user still has to answer
+pub const HITL_SLOT: &str = "hitl.pending";
+
+/// A HITL question from vagent-graph waiting for the user's answer
+///
+/// The graph execution stays paused (checkpointed) until the answer is sent
+/// back with the same request ID, or until it is cancelled on timeout. The
+/// reminder and the timeout are follow-ups, rescheduled whenever a form
+/// advances and cancelled once the question is resolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingHitl {
+    /// Graph request that paused for the answer
+    pub request_id: String,
+    pub question: String,
+    /// Accepted answers; empty means any answer
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Message that led to the question (reminders relate to it)
+    pub event_id: String,
+    /// Room language when the question was asked
+    pub language: String,
+    /// Unix seconds
+    pub asked_at: u64,
+    /// Multi-step form with its progress, instead of a single question
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub form: Option<Form>,
+}
+
+/// What to do with a user's message while a question is pending
+pub enum Answer {
+    /// Reply and keep waiting
+    Reply(String),
+    /// Resume the graph with the answer (`payload` holds a form's answers)
+    Resume {
+        request_id: String,
+        payload: Option<Value>,
+    },
+    /// The user abandoned the form
+    Cancel { request_id: String },
+    /// The question was already resolved (e.g. timed out); treat the message as a new query
+    Gone,
+}
+
+impl PendingHitl {
+    pub fn from_value(value: Value) -> Option<Self> {
+        serde_json::from_value(value).ok()
+    }
+
+    /// Whether `answer` is acceptable (any answer when there are no options)
+    pub fn accepts(&self, answer: &str) -> bool {
+        let answer = answer.trim();
+        self.options.is_empty()
+            || self
+                .options
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(answer))
+    }
+
+    /// Question text shown to the user, with the options or current form field
+    pub fn render(&self, catalog: &Catalog) -> String {
+        if let Some(form) = &self.form {
+            let hint = catalog.translate(&self.language, "hitl.form_hint", &[]);
+            return format!("{}\n\n{}\n\n{}", self.question, form.render_current(), hint);
+        }
+        if self.options.is_empty() {
+            return self.question.clone();
+        }
+        let options: Vec<String> = self
+            .options
+            .iter()
+            .map(|option| format!("`{}`", option))
+            .collect();
+        format!("{}\n\n{}", self.question, options.join(" / "))
+    }
+}
+
+fn remind_token(request_id: &str) -> String {
+    format!("hitl.remind.{}", request_id)
+}
+
+fn expire_token(request_id: &str) -> String {
+    format!("hitl.expire.{}", request_id)
+}
+
+/// Schedule the reminder and the timeout of a question, replacing earlier ones
+async fn schedule_follow_ups(
+    follow_ups: &FollowUpScheduler,
+    key: &ConversationKey,
+    pending: &PendingHitl,
+    config: &BotConfig,
+    catalog: &Catalog,
+) {
+    let pill = mentions::pill(&key.user_id, None);
+    if config.hitl_reminder_after < config.hitl_timeout {
+        let reminder = catalog.translate(
+            &pending.language,
+            "hitl.reminder",
+            &[("user", &pill), ("question", &pending.render(catalog))],
+        );
+        let action = FollowUpAction::SendText {
+            body: reminder,
+            mention: Some(key.user_id.clone()),
+        };
+        if let Err(e) = follow_ups
+            .schedule_as(
+                &remind_token(&pending.request_id),
+                &key.room_id,
+                config.hitl_reminder_after,
+                action,
+            )
+            .await
+        {
+            warn!(
+                "No reminder for HITL request {}: {:#}",
+                pending.request_id, e
+            );
+        }
+    }
+
+    let expired = catalog.translate(&pending.language, "hitl.expired", &[("user", &pill)]);
+    let action = FollowUpAction::PublishControl {
+        kind: RequestKind::HitlCancel,
+        request_id: pending.request_id.clone(),
+        user_id: key.user_id.clone(),
+        thread_id: key.thread_id.clone(),
+        notice: Some(expired),
+    };
+    if let Err(e) = follow_ups
+        .schedule_as(
+            &expire_token(&pending.request_id),
+            &key.room_id,
+            config.hitl_timeout,
+            action,
+        )
+        .await
+    {
+        // The store TTL still drops the question, but the graph isn't told
+        warn!(
+            "No timeout for HITL request {}: {:#}",
+            pending.request_id, e
+        );
+    }
+}
+
+/// Drop the reminder and timeout of a resolved question
+pub async fn cancel_follow_ups(follow_ups: &FollowUpScheduler, request_id: &str) {
+    follow_ups.cancel(&remind_token(request_id)).await;
+    follow_ups.cancel(&expire_token(request_id)).await;
+}
+
+/// Remember a question for the user (replaces any earlier one)
+///
+/// The TTL is only a safety net for questions whose timeout never ran (e.g.
+/// the follow-up couldn't be scheduled).
+pub async fn ask(
+    store: &ConversationStore,
+    follow_ups: &FollowUpScheduler,
+    key: ConversationKey,
+    pending: &PendingHitl,
+    config: &BotConfig,
+    catalog: &Catalog,
+) {
+    if let Some(earlier) = store.get(&key).await.and_then(PendingHitl::from_value) {
+        cancel_follow_ups(follow_ups, &earlier.request_id).await;
+    }
+    match serde_json::to_value(pending) {
+        Ok(value) => {
+            store
+                .set(key.clone(), value, Some(config.hitl_timeout * 2))
+                .await;
+            schedule_follow_ups(follow_ups, &key, pending, config, catalog).await;
+        }
+        Err(e) => warn!("Failed to store HITL request {}: {}", pending.request_id, e),
+    }
+}
+
+/// Apply a user's message to their pending question
+///
+/// Plain questions are resolved by one valid answer. Forms advance one field
+/// per message; partial progress is written back to the store with a fresh
+/// deadline, so users filling in a long form are not cut off.
+pub async fn answer(
+    store: &ConversationStore,
+    follow_ups: &FollowUpScheduler,
+    key: &ConversationKey,
+    pending: PendingHitl,
+    input: &str,
+    config: &BotConfig,
+    catalog: &Catalog,
+) -> Answer {
+    let Some(mut form) = pending.form.clone() else {
+        if !pending.accepts(input) {
+            return Answer::Reply(catalog.translate(
+                &pending.language,
+                "hitl.choose",
+                &[("options", &pending.options.join(", "))],
+            ));
+        }
+        return match resolve(store, follow_ups, key).await {
+            Some(pending) => Answer::Resume {
+                request_id: pending.request_id,
+                payload: None,
+            },
+            None => Answer::Gone,
+        };
+    };
+
+    match form.advance(input) {
+        FormStep::Ask => {
+            let next = PendingHitl {
+                form: Some(form),
+                asked_at: db::now_secs(),
+                ..pending
+            };
+            let Ok(value) = serde_json::to_value(&next) else {
+                return Answer::Gone;
+            };
+            // Only overwrite a question that is still pending
+            if store.update(key, |stored| *stored = value).await.is_none() {
+                return Answer::Gone;
+            }
+            store.expire(key, Some(config.hitl_timeout * 2)).await;
+            schedule_follow_ups(follow_ups, key, &next, config, catalog).await;
+            debug!(
+                "🙋 HITL form {} at field {}",
+                next.request_id,
+                next.form.as_ref().map_or(0, |form| form.step + 1)
+            );
+            Answer::Reply(next.render(catalog))
+        }
+        FormStep::Invalid(error) => {
+            let id = match error {
+                FieldError::NotANumber => "hitl.invalid_number",
+                FieldError::NotAnOption => "hitl.invalid_choice",
+                FieldError::NoMatch => "hitl.invalid_format",
+            };
+            let reason = catalog.translate(&pending.language, id, &[]);
+            Answer::Reply(format!("{}\n\n{}", reason, form.render_current()))
+        }
+        FormStep::Complete(answers) => match resolve(store, follow_ups, key).await {
+            Some(pending) => Answer::Resume {
+                request_id: pending.request_id,
+                payload: Some(Value::Object(answers)),
+            },
+            None => Answer::Gone,
+        },
+        FormStep::Cancelled => match resolve(store, follow_ups, key).await {
+            Some(pending) => Answer::Cancel {
+                request_id: pending.request_id,
+            },
+            None => Answer::Gone,
+        },
+    }
+}
+
+/// Claim the user's pending question so it can be resumed
+///
+/// Removal is atomic: if the timeout cancelled the question first this
+/// returns `None`, and the answer must be treated as a new query.
+pub async fn take(store: &ConversationStore, key: &ConversationKey) -> Option<PendingHitl> {
+    store.remove(key).await.and_then(PendingHitl::from_value)
+}
+
+/// `take`, then drop the question's reminder and timeout
+async fn resolve(
+    store: &ConversationStore,
+    follow_ups: &FollowUpScheduler,
+    key: &ConversationKey,
+) -> Option<PendingHitl> {
+    let pending = take(store, key).await?;
+    cancel_follow_ups(follow_ups, &pending.request_id).await;
+    Some(pending)
+}
+
+/// Claim the user's pending question for its timeout, if it is still `request_id`
+///
+/// Checked and removed in one step, so an answer or a newer question that
+/// lands meanwhile is never taken by mistake.
+pub async fn claim(
+    store: &ConversationStore,
+    key: &ConversationKey,
+    request_id: &str,
+) -> Option<PendingHitl> {
+    store
+        .remove_if(key, |value| {
+            PendingHitl::from_value(value.clone())
+                .is_some_and(|pending| pending.request_id == request_id)
+        })
+        .await
+        .and_then(PendingHitl::from_value)
+}
@@ -0,0 +1,155 @@
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::responder::ResponderContext;
+
+/// Language every message must exist in; also the fallback for missing translations
+pub const FALLBACK_LANGUAGE: &str = "en";
+
+/// Built-in catalog, compiled into the binary
+const EMBEDDED_MESSAGES: &str = include_str!("messages.json");
+
+/// Message ID -> language -> template
+type Messages = HashMap<String, HashMap<String, String>>;
+
+/// Translations of the bot's own phrases
+///
+/// Templates use `{name}` placeholders. The embedded catalog can be extended
+/// or overridden with a JSON file of the same shape (`I18N_FILE`).
+pub struct Catalog {
+    messages: Messages,
+}
+
+impl Catalog {
+    /// Load the embedded catalog plus an optional override file, validating both
+    pub fn load(override_path: Option<&Path>) -> Result<Self> {
+        let mut messages: Messages = serde_json::from_str(EMBEDDED_MESSAGES)
+            .context("Embedded message catalog is not valid JSON")?;
+
+        if let Some(path) = override_path {
+            let raw = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read message catalog {:?}", path))?;
+            let overrides: Messages = serde_json::from_str(&raw)
+                .with_context(|| format!("Message catalog {:?} is not valid JSON", path))?;
+            for (id, variants) in overrides {
+                messages.entry(id).or_default().extend(variants);
+            }
+            info!("🌐 Loaded message overrides from {:?}", path);
+        }
+
+        let catalog = Self { messages };
+        catalog.validate()?;
+        Ok(catalog)
+    }
+
+    /// Just the catalog compiled into the binary
+    pub fn embedded() -> Self {
+        Self::load(None).expect("embedded message catalog is valid")
+    }
+
+    /// Every message needs a fallback-language variant, and every translation
+    /// must use exactly the fallback's placeholders
+    fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        for (id, variants) in &self.messages {
+            let Some(fallback) = variants.get(FALLBACK_LANGUAGE) else {
+                problems.push(format!("'{}' has no '{}' variant", id, FALLBACK_LANGUAGE));
+                continue;
+            };
+            let expected = placeholders(fallback);
+            for (language, template) in variants {
+                let found = placeholders(template);
+                if found != expected {
+                    problems.push(format!(
+                        "'{}' [{}] uses placeholders {:?}, expected {:?}",
+                        id, language, found, expected
+                    ));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            problems.sort();
+            bail!("Invalid message catalog:\n  {}", problems.join("\n  "));
+        }
+        Ok(())
+    }
+
+    /// Languages with at least one translated message
+    pub fn languages(&self) -> BTreeSet<String> {
+        self.messages
+            .values()
+            .flat_map(|variants| variants.keys().cloned())
+            .collect()
+    }
+
+    /// Render a message, falling back to English and then to the message ID
+    pub fn translate(&self, language: &str, id: &str, args: &[(&str, &str)]) -> String {
+        let Some(variants) = self.messages.get(id) else {
+            warn!("🌐 Unknown message ID '{}'", id);
+            return id.to_string();
+        };
+        let template = variants
+            .get(language)
+            .or_else(|| variants.get(FALLBACK_LANGUAGE))
+            .map(String::as_str)
+            .unwrap_or(id);
+
+        args.iter()
+            .fold(template.to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+    }
+}
+
+/// The `{name}` placeholders used by a template
+fn placeholders(template: &str) -> BTreeSet<&str> {
+    let mut found = BTreeSet::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('}') else { break };
+        let name = &rest[..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            found.insert(name);
+        }
+        rest = &rest[end + 1..];
+    }
+    found
+}
+
+/// Translate a message into the language of the context's room
+pub fn t(context: &ResponderContext, id: &str, args: &[(&str, &str)]) -> String {
+    context.runtime.catalog.translate(&context.language(), id, args)
+}
+
+/// A configurable bot reply: silent, the localized default, or operator-supplied text
+#[derive(Debug, Clone)]
+pub enum CannedReply {
+    Silent,
+    Default,
+    Custom(String),
+}
+
+impl CannedReply {
+    /// Unset = localized default, empty = silent, anything else = custom text
+    pub fn from_env(name: &str) -> Self {
+        match std::env::var(name) {
+            Ok(text) if text.trim().is_empty() => CannedReply::Silent,
+            Ok(text) => CannedReply::Custom(text),
+            Err(_) => CannedReply::Default,
+        }
+    }
+
+    /// The text to send, using message `id` for the default
+    pub fn render(&self, context: &ResponderContext, id: &str) -> Option<String> {
+        match self {
+            CannedReply::Silent => None,
+            CannedReply::Default => Some(t(context, id, &[])),
+            CannedReply::Custom(text) => Some(text.clone()),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! One identity per person for senders that reach the bot through bridges
+//!
+//! Bridged WhatsApp or Signal users get a puppet MXID per bridge
+//! (`@whatsapp_4791234567:example.org`), so the same person would have
+//! separate quotas, sessions, preferences and stats for every bridge.
+//! `SENDER_IDENTITY_RULES` maps such MXIDs to a canonical identity, which the
+//! bot keys that state by; the MXID itself is still used for everything on
+//! the Matrix side (mentions, direct messages, reactions).
+
+use regex::Regex;
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+struct IdentityRule {
+    pattern: Regex,
+    /// `$1`/`${name}` are filled in from the match
+    canonical: String,
+}
+
+/// Ordered rules mapping MXIDs to identities; the first match wins
+#[derive(Debug, Clone, Default)]
+pub struct IdentityRules {
+    rules: Vec<IdentityRule>,
+}
+
+impl IdentityRules {
+    /// Rules from `(regex, canonical)` pairs, in order
+    ///
+    /// Invalid patterns are logged and skipped.
+    pub fn new<'a>(rules: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let rules = rules
+            .into_iter()
+            .filter_map(|(pattern, canonical)| match Regex::new(pattern) {
+                Ok(pattern) => Some(IdentityRule {
+                    pattern,
+                    canonical: canonical.to_string(),
+                }),
+                Err(e) => {
+                    warn!("🪪 Ignoring identity rule {}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Rules from `regex=canonical` entries separated by `;`
+    ///
+    /// The entry is split at its last `=`, so patterns may contain one.
+    pub fn parse(spec: &str) -> Self {
+        Self::new(
+            spec.split(';')
+                .filter_map(|entry| entry.rsplit_once('='))
+                .map(|(pattern, canonical)| (pattern.trim(), canonical.trim()))
+                .filter(|(pattern, _)| !pattern.is_empty()),
+        )
+    }
+
+    /// Rules from `SENDER_IDENTITY_RULES`
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("SENDER_IDENTITY_RULES").unwrap_or_default())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The identity of `user_id`: the first matching rule's canonical form,
+    /// or the MXID itself when no rule matches
+    pub fn canonical(&self, user_id: &str) -> String {
+        for rule in &self.rules {
+            if let Some(captures) = rule.pattern.captures(user_id) {
+                let mut identity = String::new();
+                captures.expand(&rule.canonical, &mut identity);
+                if !identity.is_empty() {
+                    return identity;
+                }
+            }
+        }
+        user_id.to_string()
+    }
+}
@@ -0,0 +1,73 @@
+//! What the bot makes of message and sticker events before routing
+//!
+//! Decided from the event content alone, so the event handlers only look up
+//! the sender's display name, log the decision and act on it.
+
+use matrix_sdk::ruma::events::room::message::MessageType;
+
+use crate::addressing;
+use crate::room_config::StickerMode;
+
+/// What becomes of a room message, by msgtype
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incoming {
+    /// A text message, routed as it is
+    Text(String),
+    /// An emote addressing the bot, routed as a sentence (see [`emote_query`])
+    Emote(String),
+    /// An emote that doesn't address the bot; room chatter
+    UnaddressedEmote,
+    /// Usually other bots; answering them risks reply loops
+    Notice,
+    /// Any other msgtype, named
+    Other(String),
+}
+
+/// Sort a message by msgtype; emotes count only when they mention the bot
+pub fn classify(msgtype: MessageType, mentions_bot: bool, bot_user_id: &str) -> Incoming {
+    match msgtype {
+        MessageType::Text(text) => Incoming::Text(text.body),
+        MessageType::Emote(emote) => {
+            if mentions_bot || addressing::mentions_by_name(&emote.body, bot_user_id) {
+                Incoming::Emote(emote.body)
+            } else {
+                Incoming::UnaddressedEmote
+            }
+        }
+        MessageType::Notice(_) => Incoming::Notice,
+        other => Incoming::Other(other.msgtype().to_string()),
+    }
+}
+
+/// "/me waves at vagent" from Alice becomes "Alice waves at vagent"
+pub fn emote_query(sender_name: &str, body: &str) -> String {
+    format!("{} {}", sender_name, body)
+}
+
+/// What to do with a sticker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StickerAction {
+    Ignore,
+    /// React to it with this key
+    React(String),
+    /// Route its description as a message (see [`sticker_query`])
+    Forward,
+}
+
+/// The room's sticker mode, or `default` where it has none, as an action
+pub fn sticker_action(
+    room_mode: Option<StickerMode>,
+    default: StickerMode,
+    ack: &str,
+) -> StickerAction {
+    match room_mode.unwrap_or(default) {
+        StickerMode::Ignore => StickerAction::Ignore,
+        StickerMode::Ack => StickerAction::React(ack.to_string()),
+        StickerMode::Forward => StickerAction::Forward,
+    }
+}
+
+/// The message a forwarded sticker becomes, from its body (alt text)
+pub fn sticker_query(sender_name: &str, body: &str) -> String {
+    format!("{} sent a sticker: {}", sender_name, body)
+}
@@ -0,0 +1,226 @@
+//! Telling acknowledgements ("ok", "thanks", "lol") from messages worth asking about
+//!
+//! In busy rooms many messages that reach the agent are reactions to what
+//! someone said, and cost a graph call for an answer nobody wants.
+//! [`classify`] looks at the text alone (length, punctuation, question words)
+//! plus whether the bot was mentioned or the room is a direct chat, and is
+//! deliberately cautious: a message that mentions the bot or is sent in a
+//! direct chat is only ever an acknowledgement if it is one of a few fixed
+//! phrases ([`ACK_PHRASES`]). The `IntentFilter` decorator acts on the result.
+//!
+//! `tests/data/intent_samples.tsv` holds labeled messages to tune against.
+
+/// How the agent's prefilter is run (`INTENT_FILTER`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntentFilterMode {
+    /// Every message goes to the agent
+    #[default]
+    Off,
+    /// Acknowledgements are dropped; unsure messages go to the agent
+    Heuristic,
+    /// Like `heuristic`, but vagent-graph decides the unsure messages
+    Graph,
+}
+
+impl IntentFilterMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(IntentFilterMode::Off),
+            "heuristic" => Some(IntentFilterMode::Heuristic),
+            "graph" | "graph-assisted" => Some(IntentFilterMode::Graph),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntentFilterMode::Off => "off",
+            IntentFilterMode::Heuristic => "heuristic",
+            IntentFilterMode::Graph => "graph",
+        }
+    }
+}
+
+/// What a message is, as far as the heuristic can tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intent {
+    /// Nothing to answer
+    Acknowledgement,
+    /// Worth asking the agent
+    Question,
+    /// Short, but not clearly either
+    Unsure,
+}
+
+impl Intent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Intent::Acknowledgement => "acknowledgement",
+            Intent::Question => "question",
+            Intent::Unsure => "unsure",
+        }
+    }
+}
+
+/// What is known about a message besides its text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Signals {
+    /// The message mentions the bot
+    pub mentioned: bool,
+    /// Sent in a direct chat with the bot
+    pub direct_chat: bool,
+}
+
+/// The intent and the rule that decided it, for the logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification {
+    pub intent: Intent,
+    pub reason: &'static str,
+}
+
+impl Classification {
+    fn new(intent: Intent, reason: &'static str) -> Self {
+        Self { intent, reason }
+    }
+}
+
+/// Whole messages that are acknowledgements wherever they are sent
+pub const ACK_PHRASES: &[&str] = &[
+    "ok",
+    "okay",
+    "ok thanks",
+    "ok thank you",
+    "thanks",
+    "thank you",
+    "thanks a lot",
+    "thx",
+    "ty",
+    "got it",
+    "takk",
+    "tusen takk",
+    "ok takk",
+    "takk skal du ha",
+    "👍",
+    "🙏",
+    "👌",
+];
+
+/// Words short acknowledgements are made of
+const ACK_WORDS: &[&str] = &[
+    "ok",
+    "okay",
+    "okey",
+    "k",
+    "kk",
+    "thanks",
+    "thank",
+    "you",
+    "thx",
+    "ty",
+    "tnx",
+    "cheers",
+    "great",
+    "cool",
+    "nice",
+    "perfect",
+    "awesome",
+    "good",
+    "lol",
+    "haha",
+    "hah",
+    "hehe",
+    "lmao",
+    "rofl",
+    "yay",
+    "wow",
+    "np",
+    "noted",
+    "sure",
+    "alright",
+    "got",
+    "it",
+    "a",
+    "lot",
+    "so",
+    "much",
+    "takk",
+    "tusen",
+    "flott",
+    "supert",
+    "bra",
+    "fint",
+    "skjønner",
+    "greit",
+    "ja",
+    "jo",
+    "da",
+    "+1",
+];
+
+/// Words that start a question in English or Norwegian
+const QUESTION_WORDS: &[&str] = &[
+    "what", "how", "why", "when", "where", "who", "whom", "whose", "which", "can", "could",
+    "would", "should", "is", "are", "do", "does", "did", "will", "may", "explain", "tell", "show",
+    "help", "hva", "hvordan", "hvorfor", "når", "hvor", "hvem", "hvilken", "hvilke", "kan",
+    "kunne", "skal", "bør", "er", "vil", "forklar", "fortell", "vis", "hjelp",
+];
+
+/// Messages longer than this (in words) always go to the agent
+const MAX_ACK_WORDS: usize = 4;
+/// Messages longer than this (in characters) always go to the agent
+const MAX_ACK_CHARS: usize = 32;
+
+/// Lowercased, with surrounding whitespace and trailing punctuation removed
+fn normalize(text: &str) -> String {
+    text.trim()
+        .trim_end_matches(['.', '!', ',', '…', '~'])
+        .trim()
+        .to_lowercase()
+}
+
+fn is_symbols(text: &str) -> bool {
+    text.chars()
+        .all(|c| !c.is_alphanumeric() || c.is_whitespace())
+}
+
+/// Classify `text` as an acknowledgement or a question
+pub fn classify(text: &str, signals: Signals) -> Classification {
+    let normalized = normalize(text);
+    if ACK_PHRASES.contains(&normalized.as_str()) {
+        return Classification::new(Intent::Acknowledgement, "ack phrase");
+    }
+    // Outside the fixed phrases, anything addressed to the bot is answered
+    if signals.mentioned {
+        return Classification::new(Intent::Question, "mentions the bot");
+    }
+    if signals.direct_chat {
+        return Classification::new(Intent::Question, "direct chat");
+    }
+
+    if normalized.contains('?') {
+        return Classification::new(Intent::Question, "question mark");
+    }
+    let words: Vec<&str> = normalized
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.len() > MAX_ACK_WORDS || normalized.chars().count() > MAX_ACK_CHARS {
+        return Classification::new(Intent::Question, "long");
+    }
+    if words
+        .first()
+        .is_some_and(|first| QUESTION_WORDS.contains(first))
+    {
+        return Classification::new(Intent::Question, "question word");
+    }
+    if normalized.is_empty() || is_symbols(&normalized) {
+        return Classification::new(Intent::Acknowledgement, "emoji or punctuation only");
+    }
+    if words.iter().all(|word| {
+        let word = word.trim_end_matches(['.', '!']);
+        ACK_WORDS.contains(&word) || is_symbols(word)
+    }) {
+        return Classification::new(Intent::Acknowledgement, "ack words only");
+    }
+    Classification::new(Intent::Unsure, "short")
+}
@@ -0,0 +1,156 @@
+//! Importing an Element room key export at startup
+//!
+//! When an account moves from a person to the bot, the keys of the old
+//! sessions let the bot decrypt the rooms' history for context. With
+//! `ROOM_KEYS_IMPORT_PATH` and `ROOM_KEYS_IMPORT_PASSPHRASE` set, the export is
+//! imported once after the initial sync; its SHA-256 is recorded in the bot
+//! database so later startups skip the same file. A missing file, a corrupt
+//! export or a wrong passphrase is logged and the bot starts anyway, and a
+//! failed import is tried again on the next startup.
+
+use anyhow::{Context, Result};
+use matrix_sdk::Client;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::db;
+
+/// Key export to import at startup
+#[derive(Clone)]
+pub struct RoomKeysImport {
+    pub path: PathBuf,
+    pub passphrase: String,
+}
+
+impl fmt::Debug for RoomKeysImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoomKeysImport")
+            .field("path", &self.path)
+            .field("passphrase", &"<redacted>")
+            .finish()
+    }
+}
+
+impl RoomKeysImport {
+    /// None unless `ROOM_KEYS_IMPORT_PATH` is set; a path without a passphrase
+    /// is ignored with a warning
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("ROOM_KEYS_IMPORT_PATH")
+            .ok()
+            .filter(|path| !path.trim().is_empty())?;
+        match std::env::var("ROOM_KEYS_IMPORT_PASSPHRASE") {
+            Ok(passphrase) if !passphrase.is_empty() => Some(Self {
+                path: PathBuf::from(path),
+                passphrase,
+            }),
+            _ => {
+                warn!(
+                    "⚠️  ROOM_KEYS_IMPORT_PATH is set without ROOM_KEYS_IMPORT_PASSPHRASE, \
+                     not importing room keys"
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Import the export unless it was imported before; never fails the startup
+pub async fn import_on_startup(client: &Client, store_path: &Path, import: &RoomKeysImport) {
+    if let Err(e) = import_once(client, store_path, import).await {
+        warn!(
+            "🔑 Room keys from {:?} were not imported, trying again at the next startup: {:#}",
+            import.path, e
+        );
+    }
+}
+
+async fn import_once(client: &Client, store_path: &Path, import: &RoomKeysImport) -> Result<()> {
+    if !tokio::fs::try_exists(&import.path).await.unwrap_or(false) {
+        info!(
+            "🔑 No room key export at {:?}, nothing to import",
+            import.path
+        );
+        return Ok(());
+    }
+    let contents = tokio::fs::read(&import.path)
+        .await
+        .with_context(|| format!("Failed to read {:?}", import.path))?;
+    let hash = hex::encode(Sha256::digest(&contents));
+
+    let db_path = db::bot_db_path(store_path);
+    let lookup_path = db_path.clone();
+    let lookup_hash = hash.clone();
+    let previous =
+        tokio::task::spawn_blocking(move || imported_at(&lookup_path, &lookup_hash)).await??;
+    if let Some(at) = previous {
+        info!(
+            "🔑 Room key export {:?} (sha256 {}) was imported at {}, skipping",
+            import.path,
+            &hash[..12],
+            at
+        );
+        return Ok(());
+    }
+
+    info!("🔑 Importing room keys from {:?}...", import.path);
+    let result = client
+        .encryption()
+        .import_room_keys(import.path.clone(), &import.passphrase)
+        .await
+        .context("Failed to import room keys (wrong passphrase or not an Element key export?)")?;
+    info!(
+        "🔑 Imported {} new room keys of {} in {:?}",
+        result.imported_count, result.total_count, import.path
+    );
+
+    let (imported, total) = (result.imported_count, result.total_count);
+    let recorded =
+        tokio::task::spawn_blocking(move || record(&db_path, &hash, imported, total)).await?;
+    if let Err(e) = recorded {
+        warn!(
+            "⚠️  Room keys were imported but not recorded, the file will be imported again: {:#}",
+            e
+        );
+    }
+    Ok(())
+}
+
+fn open(db_path: &Path) -> Result<Connection> {
+    let conn = db::open(db_path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS room_key_imports (
+             hash TEXT PRIMARY KEY,
+             imported_at INTEGER NOT NULL,
+             imported INTEGER NOT NULL,
+             total INTEGER NOT NULL
+         );",
+    )
+    .context("Failed to create room key import table")?;
+    Ok(conn)
+}
+
+/// When the export with this hash was imported, if it was
+fn imported_at(db_path: &Path, hash: &str) -> Result<Option<u64>> {
+    let conn = open(db_path)?;
+    let at = conn
+        .query_row(
+            "SELECT imported_at FROM room_key_imports WHERE hash = ?1",
+            params![hash],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+    Ok(at.map(|at| at as u64))
+}
+
+fn record(db_path: &Path, hash: &str, imported: usize, total: usize) -> Result<()> {
+    let conn = open(db_path)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO room_key_imports (hash, imported_at, imported, total)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![hash, db::now_secs() as i64, imported as i64, total as i64],
+    )?;
+    Ok(())
+}
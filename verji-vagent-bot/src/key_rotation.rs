@@ -0,0 +1,120 @@
+//! Rotating the bot's room key when members leave sensitive rooms
+//!
+//! In rooms flagged sensitive (`!admin sensitive on`) a member leaving or
+//! being banned makes the bot discard its outbound Megolm session, so its next
+//! message is encrypted with a session the departed member never received.
+//! The SDK rotates on most membership changes by itself; this makes the
+//! rotation explicit and audited (`RUST_LOG=audit=info`). `!admin
+//! rotate-session` rotates by hand.
+//!
+//! The first send after a rotation is checked: the session ID of the sent
+//! event is logged and compared with the one seen after the previous rotation
+//! in the room (unknown the first time).
+
+use anyhow::Result;
+use matrix_sdk::ruma::EventId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::room::RoomHandle;
+
+/// A rotation whose first send hasn't been checked yet
+struct Pending {
+    reason: String,
+    /// Session seen after the previous rotation in the room
+    previous_session: Option<String>,
+}
+
+/// A bot's room key rotations, and the sessions seen after them
+#[derive(Default)]
+pub struct KeyRotations {
+    rotations: Mutex<Rotations>,
+}
+
+#[derive(Default)]
+struct Rotations {
+    /// Room ID -> rotation waiting for its first send
+    pending: HashMap<String, Pending>,
+    /// Room ID -> session of the first send after the last rotation
+    sessions: HashMap<String, String>,
+}
+
+impl KeyRotations {
+    /// Discard the room's outbound session before the next send
+    ///
+    /// `reason` names what triggered it (a membership event or an admin) for the
+    /// audit log.
+    pub async fn rotate(&self, room: &dyn RoomHandle, reason: &str) -> Result<()> {
+        room.rotate_room_key().await?;
+        let room_id = room.room_id().to_string();
+        info!(
+            target: "audit",
+            room_id = %room_id,
+            reason = %reason,
+            "outbound room key rotated"
+        );
+        info!("🔑 Rotated the room key of {} ({})", room_id, reason);
+
+        let mut rotations = self.rotations.lock().unwrap();
+        let previous_session = rotations.sessions.get(&room_id).cloned();
+        rotations.pending.insert(
+            room_id,
+            Pending {
+                reason: reason.to_string(),
+                previous_session,
+            },
+        );
+        Ok(())
+    }
+
+    /// Check that the first send after a rotation used a new session
+    pub async fn after_send(&self, room: &dyn RoomHandle, event_id: &EventId) {
+        let room_id = room.room_id().to_string();
+        let Some(pending) = self.rotations.lock().unwrap().pending.remove(&room_id) else {
+            return;
+        };
+
+        let session = match room.megolm_session_id(event_id).await {
+            Ok(Some(session)) => session,
+            Ok(None) => {
+                info!(
+                    "🔑 First send to {} after the rotation was not encrypted",
+                    room_id
+                );
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to check the session of {} after the rotation: {:#}",
+                    event_id, e
+                );
+                return;
+            }
+        };
+        let rotated = pending.previous_session.as_deref() != Some(session.as_str());
+        info!(
+            target: "audit",
+            room_id = %room_id,
+            event_id = %event_id,
+            reason = %pending.reason,
+            session_id = %session,
+            previous_session_id = pending.previous_session.as_deref().unwrap_or("unknown"),
+            rotated,
+            "first send after room key rotation"
+        );
+        if rotated {
+            info!("🔑 {} now sends with session {}", room_id, session);
+        } else {
+            warn!(
+                "🔑 {} still sends with session {} after the rotation ({})",
+                room_id, session, pending.reason
+            );
+        }
+        self.rotations
+            .lock()
+            .unwrap()
+            .sessions
+            .insert(room_id, session);
+    }
+}
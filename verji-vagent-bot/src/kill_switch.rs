@@ -0,0 +1,145 @@
+//! Room-level kill switch set by the room's own moderators
+//!
+//! A `no.verji.vagent.disabled` state event with `{"disabled": true}` (and an
+//! optional `reason`) silences the bot in that room; anyone allowed to send
+//! state there can set it. The bot posts one notice when the switch is turned
+//! on and a short confirmation when it is turned off again (`disabled: false`,
+//! emptied or redacted content). Whether the notice was posted is kept in the
+//! room config, so restarts don't repeat it.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::config::BotConfig;
+use crate::room::RoomHandle;
+use crate::room_config::RoomConfigStore;
+use crate::runtime::Runtime;
+
+pub const DISABLED_EVENT_TYPE: &str = "no.verji.vagent.disabled";
+
+/// The switch is on: the bot stays silent in the room
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disabled {
+    pub reason: Option<String>,
+}
+
+/// Read the switch from the state event content (None = the bot is enabled)
+pub fn parse(content: &Value) -> Option<Disabled> {
+    if content.get("disabled").and_then(Value::as_bool) != Some(true) {
+        return None;
+    }
+    let reason = content
+        .get("reason")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty())
+        .map(str::to_string);
+    Some(Disabled { reason })
+}
+
+/// Cached kill switch state of each room
+pub struct KillSwitch {
+    /// Room ID -> state
+    cache: Mutex<HashMap<String, Option<Disabled>>>,
+}
+
+impl KillSwitch {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the bot is disabled in the room; read failures count as enabled and are retried
+    pub async fn check(&self, room: &dyn RoomHandle) -> Option<Disabled> {
+        let room_id = room.room_id().to_string();
+        if let Some(state) = self.cache.lock().unwrap().get(&room_id) {
+            return state.clone();
+        }
+
+        let state = match room.state_event(DISABLED_EVENT_TYPE, "").await {
+            Ok(content) => content.as_ref().and_then(parse),
+            Err(e) => {
+                warn!("Failed to read the kill switch of {}: {:#}", room_id, e);
+                return None;
+            }
+        };
+        self.cache.lock().unwrap().insert(room_id, state.clone());
+        state
+    }
+
+    /// Apply a changed kill switch event and tell the room when the switch flips
+    pub async fn on_state_event(
+        &self,
+        room: &dyn RoomHandle,
+        content: &Value,
+        room_configs: &RoomConfigStore,
+        config: &BotConfig,
+        runtime: &Runtime,
+    ) {
+        let room_id = room.room_id().to_string();
+        let state = parse(content);
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(room_id.clone(), state.clone());
+
+        let room_config = room_configs.get(room).await;
+        if state.is_some() == room_config.disabled_notice_sent {
+            debug!("🔇 Kill switch of {} unchanged", room_id);
+            return;
+        }
+        let language = room_config.language.as_deref().unwrap_or(&config.locale);
+        let notice = match &state {
+            Some(Disabled {
+                reason: Some(reason),
+            }) => {
+                info!("🔇 Disabled in {} by its moderators: {}", room_id, reason);
+                runtime.catalog.translate(
+                    language,
+                    "kill_switch.disabled_reason",
+                    &[("reason", reason)],
+                )
+            }
+            Some(Disabled { reason: None }) => {
+                info!("🔇 Disabled in {} by its moderators", room_id);
+                runtime
+                    .catalog
+                    .translate(language, "kill_switch.disabled", &[])
+            }
+            None => {
+                info!("🔊 Re-enabled in {} by its moderators", room_id);
+                runtime
+                    .catalog
+                    .translate(language, "kill_switch.enabled", &[])
+            }
+        };
+
+        let mut turn = runtime.send_queues.turn(room.room_id(), None).await;
+        if let Err(e) = turn.send(room.send_text(&notice)).await {
+            warn!(
+                "Failed to post the kill switch notice in {}: {:#}",
+                room_id, e
+            );
+            return;
+        }
+        let disabled = state.is_some();
+        if let Err(e) = room_configs
+            .update(room, |config| config.disabled_notice_sent = disabled)
+            .await
+        {
+            warn!(
+                "Failed to remember the kill switch notice in {}: {:#}",
+                room_id, e
+            );
+        }
+    }
+}
+
+impl Default for KillSwitch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
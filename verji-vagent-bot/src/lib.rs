@@ -0,0 +1,144 @@
+//! Matrix bot with pluggable responders, answering through vagent-graph
+//!
+//! The `verji-vagent-bot` binary parses its CLI and runs a [`BotBuilder`];
+//! other services can embed the same pipeline and add their own responders:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use verji_vagent_bot::{
+//!     async_trait, BotBuilder, BotConfig, Responder, ResponderContext, ResponderResult,
+//! };
+//!
+//! /// Answers `!uptime`
+//! struct UptimeResponder;
+//!
+//! #[async_trait]
+//! impl Responder for UptimeResponder {
+//!     fn name(&self) -> &str {
+//!         "UptimeResponder"
+//!     }
+//!
+//!     fn priority(&self) -> i32 {
+//!         90
+//!     }
+//!
+//!     async fn should_handle(&self, context: &ResponderContext) -> bool {
+//!         context.message_body.trim() == "!uptime"
+//!     }
+//!
+//!     async fn handle(&self, _context: &ResponderContext) -> anyhow::Result<ResponderResult> {
+//!         Ok(ResponderResult::Handled(Some("Up and running".to_string())))
+//!     }
+//! }
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! BotBuilder::new(BotConfig::from_env())
+//!     .responder(Arc::new(UptimeResponder))
+//!     .run()
+//!     .await
+//! # }
+//! ```
+//!
+//! The modules are public for embedders and the CLI; the items re-exported
+//! here are the supported surface.
+
+pub mod addressing;
+pub mod alias;
+pub mod admin_room;
+pub mod agent_service;
+pub mod archive;
+pub mod bot;
+pub mod capabilities;
+pub mod cli;
+pub mod client;
+pub mod clock;
+pub mod coalesce;
+pub mod command;
+pub mod config;
+pub mod conversation;
+pub mod crash;
+pub mod custom_events;
+pub mod db;
+pub mod decorators;
+pub mod direct;
+pub mod dispatcher;
+pub mod encryption;
+pub mod erasure;
+pub mod error;
+pub mod fallback_dm;
+pub mod feedback;
+pub mod follow_up;
+pub mod hitl;
+pub mod i18n;
+pub mod identity;
+pub mod incoming;
+pub mod intent;
+pub mod key_import;
+pub mod key_rotation;
+pub mod kill_switch;
+pub mod maintenance;
+pub mod membership;
+pub mod mentions;
+pub mod metrics;
+pub mod middleware;
+pub mod middlewares;
+pub mod observer;
+pub mod observers;
+pub mod outbound_webhook;
+pub mod outbox;
+pub mod pager;
+pub mod policy;
+pub mod preferences;
+pub mod processing;
+pub mod profiling;
+pub mod progress;
+pub mod quota;
+pub mod redact;
+pub mod redis_client;
+pub mod replay;
+pub mod request_id;
+pub mod responder;
+pub mod responder_manager;
+pub mod responders;
+pub mod response_cache;
+pub mod retraction;
+pub mod retry;
+pub mod room;
+pub mod room_config;
+pub mod room_context;
+pub mod room_status;
+pub mod routing;
+pub mod runtime;
+pub mod self_test;
+pub mod send_pacing;
+pub mod send_queue;
+pub mod send_timing;
+pub mod sent_events;
+pub mod session;
+pub mod shadow;
+pub mod startup;
+pub mod startup_announce;
+pub mod stats;
+pub mod still_working;
+pub mod store;
+pub mod store_health;
+pub mod sync;
+pub mod system_rooms;
+pub mod tenant;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tokens;
+pub mod translation;
+pub mod transport;
+pub mod warmup;
+pub mod webhook;
+
+pub use async_trait::async_trait;
+pub use bot::BotBuilder;
+pub use config::BotConfig;
+pub use middleware::{Middleware, MiddlewareDecision};
+pub use redis_client::{GraphMessage, GraphMessageType, GraphRequest, RedisGraphClient};
+pub use responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+pub use responder_manager::ResponderManager;
+pub use session::{load_session, save_session, FullSession};
+pub use transport::{GraphTransport, TransportConfig};
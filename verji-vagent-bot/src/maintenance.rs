@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use rusqlite::OptionalExtension;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::db;
+
+/// Whether agent queries are paused, and the notice sent instead
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaintenanceState {
+    pub enabled: bool,
+    /// Custom notice (None = the `maintenance.notice` message)
+    pub message: Option<String>,
+    /// Who changed the mode last, and when (unix seconds)
+    pub changed_by: Option<String>,
+    pub changed_at: u64,
+}
+
+/// Maintenance mode toggled by `!admin maintenance`
+///
+/// The state is kept in memory for the dispatch path and written through to
+/// the bot database, so a restart during a deployment stays in maintenance.
+pub struct MaintenanceMode {
+    db_path: PathBuf,
+    state: Mutex<MaintenanceState>,
+}
+
+impl MaintenanceMode {
+    /// Load the stored state; `boot_enabled` (`MAINTENANCE_MODE`) forces it on
+    pub fn open(
+        store_path: &Path,
+        boot_enabled: bool,
+        boot_message: Option<String>,
+    ) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS maintenance (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                enabled INTEGER NOT NULL,
+                message TEXT,
+                changed_by TEXT,
+                changed_at INTEGER NOT NULL
+            );",
+        )
+        .context("Failed to create maintenance table")?;
+
+        let stored = conn
+            .query_row(
+                "SELECT enabled, message, changed_by, changed_at FROM maintenance WHERE id = 1",
+                [],
+                |row| {
+                    Ok(MaintenanceState {
+                        enabled: row.get(0)?,
+                        message: row.get(1)?,
+                        changed_by: row.get(2)?,
+                        changed_at: row.get::<_, i64>(3)? as u64,
+                    })
+                },
+            )
+            .optional()
+            .context("Failed to load maintenance state")?;
+
+        let mut state = stored.unwrap_or_default();
+        if boot_enabled {
+            state.enabled = true;
+            state.message = boot_message.or(state.message);
+            state.changed_by = Some("MAINTENANCE_MODE".to_string());
+            state.changed_at = db::now_secs();
+            save(&conn, &state)?;
+        }
+        if state.enabled {
+            warn!(
+                "🚧 Maintenance mode is on; agent queries get a notice until `!admin maintenance off`"
+            );
+        }
+
+        Ok(Self {
+            db_path,
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn state(&self) -> MaintenanceState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Switch the mode and persist it; `message` only matters when enabling
+    pub async fn set(
+        &self,
+        enabled: bool,
+        message: Option<String>,
+        changed_by: &str,
+    ) -> Result<()> {
+        let state = MaintenanceState {
+            enabled,
+            message: if enabled { message } else { None },
+            changed_by: Some(changed_by.to_string()),
+            changed_at: db::now_secs(),
+        };
+
+        let db_path = self.db_path.clone();
+        let stored = state.clone();
+        tokio::task::spawn_blocking(move || save(&db::open(&db_path)?, &stored))
+            .await
+            .context("Maintenance state write panicked")??;
+
+        info!(
+            "🚧 Maintenance mode {} by {}",
+            if enabled { "enabled" } else { "disabled" },
+            changed_by
+        );
+        *self.state.lock().unwrap() = state;
+        Ok(())
+    }
+}
+
+fn save(conn: &rusqlite::Connection, state: &MaintenanceState) -> Result<()> {
+    conn.execute(
+        "INSERT INTO maintenance (id, enabled, message, changed_by, changed_at)
+         VALUES (1, ?1, ?2, ?3, ?4)
+         ON CONFLICT (id) DO UPDATE SET
+             enabled = excluded.enabled,
+             message = excluded.message,
+             changed_by = excluded.changed_by,
+             changed_at = excluded.changed_at",
+        rusqlite::params![
+            state.enabled,
+            state.message,
+            state.changed_by,
+            state.changed_at as i64
+        ],
+    )
+    .context("Failed to save maintenance state")?;
+    Ok(())
+}
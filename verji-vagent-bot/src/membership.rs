@@ -0,0 +1,182 @@
+//! The bot's own membership of rooms, so it stops working for rooms it left
+//!
+//! When the bot leaves a room, or is kicked or banned from it, work for the
+//! room is given up: messages still being coalesced or delayed are not
+//! dispatched, dispatches in progress are abandoned, the graph requests they
+//! made are cancelled and queued sends are dropped. The room's state is
+//! cleared at the same time, so joining again (e.g. after a new invite)
+//! starts from a clean slate.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use crate::agent_service::AgentService;
+use crate::metrics::Metrics;
+use crate::redis_client::GraphRequest;
+
+/// A graph request in progress, with the service that can cancel it
+struct InFlight {
+    request: GraphRequest,
+    service: Arc<AgentService>,
+}
+
+struct RoomState {
+    /// The bot left and hasn't joined again
+    left: bool,
+    /// Bumped every time the bot leaves; dispatches in the room watch it
+    departures: watch::Sender<u64>,
+    in_flight: HashMap<u64, InFlight>,
+}
+
+impl Default for RoomState {
+    fn default() -> Self {
+        Self {
+            left: false,
+            departures: watch::channel(0).0,
+            in_flight: HashMap::new(),
+        }
+    }
+}
+
+/// Which rooms a bot left, and the graph requests to cancel when it does
+pub struct Memberships {
+    rooms: Mutex<HashMap<String, RoomState>>,
+    next_id: AtomicU64,
+    metrics: Arc<Metrics>,
+}
+
+/// Notices the bot leaving a room it was in when the watch started
+pub struct Presence {
+    departures: watch::Receiver<u64>,
+}
+
+impl Presence {
+    /// Resolves when the bot leaves the room
+    pub async fn departed(&mut self) {
+        let _ = self.departures.changed().await;
+    }
+}
+
+/// Keeps a graph request cancellable until dropped
+pub struct Tracked {
+    memberships: Arc<Memberships>,
+    room_id: String,
+    id: u64,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut rooms = self.memberships.rooms.lock().unwrap();
+        if let Some(room) = rooms.get_mut(&self.room_id) {
+            room.in_flight.remove(&self.id);
+        }
+    }
+}
+
+impl Memberships {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            rooms: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            metrics,
+        }
+    }
+
+    /// Whether the bot left the room (or was removed) and hasn't joined again
+    pub fn has_left(&self, room_id: &str) -> bool {
+        self.rooms
+            .lock()
+            .unwrap()
+            .get(room_id)
+            .is_some_and(|room| room.left)
+    }
+
+    /// Watch for the bot leaving `room_id` from now on
+    pub fn watch(&self, room_id: &str) -> Presence {
+        let departures = self
+            .rooms
+            .lock()
+            .unwrap()
+            .entry(room_id.to_string())
+            .or_default()
+            .departures
+            .subscribe();
+        Presence { departures }
+    }
+
+    /// Cancel `request` through `service` if the bot leaves its room before the
+    /// returned guard is dropped
+    pub fn track(self: &Arc<Self>, request: &GraphRequest, service: &Arc<AgentService>) -> Tracked {
+        let room_id = request.metadata.room_id.clone();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.rooms
+            .lock()
+            .unwrap()
+            .entry(room_id.clone())
+            .or_default()
+            .in_flight
+            .insert(
+                id,
+                InFlight {
+                    request: request.clone(),
+                    service: Arc::clone(service),
+                },
+            );
+        Tracked {
+            memberships: Arc::clone(self),
+            room_id,
+            id,
+        }
+    }
+
+    /// The bot left `room_id`, was kicked or banned: give up its work
+    ///
+    /// Wakes the room's dispatches and cancels their graph requests in the
+    /// background. Returns how many requests are being cancelled.
+    pub fn left(&self, room_id: &str) -> usize {
+        let in_flight: Vec<InFlight> = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.entry(room_id.to_string()).or_default();
+            room.left = true;
+            room.departures.send_modify(|departures| *departures += 1);
+            room.in_flight
+                .drain()
+                .map(|(_, in_flight)| in_flight)
+                .collect()
+        };
+
+        let cancelled = in_flight.len();
+        if cancelled > 0 {
+            info!(
+                "🚪 Cancelling {} graph request(s) from {}",
+                cancelled, room_id
+            );
+            self.metrics
+                .increment_by("graph_requests_cancelled_total", &[], cancelled as u64);
+        }
+        for InFlight { request, service } in in_flight {
+            tokio::spawn(async move {
+                if let Err(e) = service.cancel(&request).await {
+                    warn!(
+                        "Failed to cancel graph request {}: {:#}",
+                        request.request_id, e
+                    );
+                }
+            });
+        }
+        cancelled
+    }
+
+    /// The bot joined (or was invited to) `room_id`: handle its messages again
+    pub fn joined(&self, room_id: &str) {
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(room_id) {
+            if room.left {
+                debug!("🚪 Back in {}", room_id);
+                room.left = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Intentional mentions (`m.mentions`) on messages the bot sends
+//!
+//! Clients only notify users listed in a message's `m.mentions`, whatever its
+//! body says. Messages that address someone (HITL reminders, quota notices,
+//! answers in mention-only rooms) are sent as [`OutgoingMessage::Mention`],
+//! which lists the users there and makes sure the formatted body has a pill
+//! for each of them, showing their display name when the room knows it. The
+//! plain body shows the same name, never the pill's markup.
+//!
+//! [`OutgoingMessage::Mention`]: crate::responder::OutgoingMessage::Mention
+
+use matrix_sdk::ruma::{
+    events::{room::message::FormattedBody, Mentions as RumaMentions},
+    OwnedUserId,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::responder::OutgoingMessage;
+use crate::room::RoomHandle;
+
+/// Who a message deliberately notifies
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mentions {
+    pub user_ids: Vec<String>,
+    /// `@room`: everyone in the room, for announcements
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub room: bool,
+}
+
+impl Mentions {
+    pub fn user(user_id: &str) -> Self {
+        Self {
+            user_ids: vec![user_id.to_string()],
+            room: false,
+        }
+    }
+
+    pub fn room() -> Self {
+        Self {
+            user_ids: Vec::new(),
+            room: true,
+        }
+    }
+
+    /// The `m.mentions` content; invalid user IDs are left out
+    pub fn to_ruma(&self) -> RumaMentions {
+        let user_ids = self.user_ids.iter().filter_map(|user_id| {
+            match OwnedUserId::try_from(user_id.as_str()) {
+                Ok(user_id) => Some(user_id),
+                Err(e) => {
+                    warn!("Not mentioning invalid user ID {}: {}", user_id, e);
+                    None
+                }
+            }
+        });
+        let mut mentions = RumaMentions::with_user_ids(user_ids);
+        mentions.room = self.room;
+        mentions
+    }
+}
+
+fn permalink(user_id: &str) -> String {
+    format!("https://matrix.to/#/{}", user_id)
+}
+
+/// Markdown link that clients render as a mention pill
+///
+/// Shows `display_name` when it is known, the user ID otherwise.
+pub fn pill(user_id: &str, display_name: Option<&str>) -> String {
+    let text = match display_name {
+        Some(name) => name.replace('[', "\\[").replace(']', "\\]"),
+        None => user_id.to_string(),
+    };
+    format!("[{}]({})", text, permalink(user_id))
+}
+
+/// The HTML form of [`pill`]
+fn html_pill(user_id: &str, display_name: Option<&str>) -> String {
+    format!(
+        "<a href=\"{}\">{}</a>",
+        escape_html(&permalink(user_id)),
+        escape_html(display_name.unwrap_or(user_id))
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Plain and HTML bodies of a message mentioning `mentions`
+///
+/// The HTML body has a pill for every mentioned user, showing the display name
+/// from `names` when there is one, and the plain body has that name where the
+/// pill is. Pills already in a markdown body are kept; missing ones, and
+/// `@room` if mentioned, are put in front of the body. A body that isn't
+/// markdown is escaped rather than rendered.
+pub fn bodies(
+    body: &str,
+    mentions: &Mentions,
+    names: &HashMap<String, String>,
+    markdown: bool,
+) -> (String, String) {
+    let mut plain = body.to_string();
+    let mut source = body.to_string();
+    let mut missing = Vec::new();
+    for user_id in &mentions.user_ids {
+        let name = names.get(user_id).map(String::as_str);
+        if markdown && body.contains(&format!("]({})", permalink(user_id))) {
+            let label = name.unwrap_or(user_id.as_str());
+            source = source.replace(&pill(user_id, None), &pill(user_id, name));
+            plain = plain.replace(&pill(user_id, None), label);
+            if let Some(name) = name {
+                plain = plain.replace(&pill(user_id, Some(name)), label);
+            }
+        } else {
+            missing.push((user_id.as_str(), name));
+        }
+    }
+    let room = mentions.room && !body.contains("@room");
+    let shown = |show: fn(&str, Option<&str>) -> String| {
+        let mut shown: Vec<String> = missing
+            .iter()
+            .map(|(user_id, name)| show(user_id, *name))
+            .collect();
+        if room {
+            shown.insert(0, "@room".to_string());
+        }
+        shown.join(" ")
+    };
+    let prefixed = |shown: String, body: String| {
+        if shown.is_empty() {
+            body
+        } else {
+            format!("{}: {}", shown, body)
+        }
+    };
+
+    let plain = prefixed(
+        shown(|user_id, name| name.unwrap_or(user_id).to_string()),
+        plain,
+    );
+    let html = if markdown {
+        let source = prefixed(shown(pill), source);
+        FormattedBody::markdown(&source)
+            .map(|formatted| formatted.body)
+            .unwrap_or_else(|| escape_html(&source))
+    } else {
+        let escaped = escape_html(&source).replace('\n', "<br>");
+        prefixed(shown(html_pill), escaped)
+    };
+    (plain, html)
+}
+
+/// Display names of the mentioned users that have one in `room`
+pub async fn display_names(room: &dyn RoomHandle, mentions: &Mentions) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    for user_id in &mentions.user_ids {
+        if let Some(name) = room.member_display_name(user_id).await {
+            names.insert(user_id.clone(), name);
+        }
+    }
+    names
+}
+
+/// Make the first text-like message of a reply notify `user_id`
+pub fn address(messages: &mut [OutgoingMessage], user_id: &str) {
+    let Some(message) = messages.iter_mut().find(|message| {
+        !matches!(
+            message,
+            OutgoingMessage::Reaction(_) | OutgoingMessage::Attachment { .. }
+        )
+    }) else {
+        return;
+    };
+    *message = match std::mem::replace(message, OutgoingMessage::Text(String::new())) {
+        OutgoingMessage::Markdown(body) => OutgoingMessage::mentioning(user_id, body),
+        // Plain text stays plain, only the pill is formatted
+        OutgoingMessage::Text(body) => OutgoingMessage::Mention {
+            body,
+            mentions: Mentions::user(user_id),
+            notice: false,
+            markdown: false,
+        },
+        OutgoingMessage::Notice(body) => OutgoingMessage::Mention {
+            body,
+            mentions: Mentions::user(user_id),
+            notice: true,
+            markdown: false,
+        },
+        OutgoingMessage::Mention {
+            body,
+            mut mentions,
+            notice,
+            markdown,
+        } => {
+            if !mentions
+                .user_ids
+                .iter()
+                .any(|mentioned| mentioned == user_id)
+            {
+                mentions.user_ids.push(user_id.to_string());
+            }
+            OutgoingMessage::Mention {
+                body,
+                mentions,
+                notice,
+                markdown,
+            }
+        }
+        other => other,
+    };
+}
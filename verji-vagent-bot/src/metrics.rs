@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Upper bounds (milliseconds) of the latency histogram buckets
+const BUCKETS_MS: [u64; 10] = [10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS_MS.len()],
+    count: u64,
+    sum: u64,
+}
+
+/// A bot's counters, gauges and latency histograms
+///
+/// Metric keys include their labels (`name{label="value"}`) so the registry
+/// stays a flat map.
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<String, u64>>,
+    gauges: Mutex<BTreeMap<String, u64>>,
+    histograms: Mutex<BTreeMap<String, Histogram>>,
+}
+
+fn key(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+impl Metrics {
+    /// Increment a counter by one
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        self.increment_by(name, labels, 1);
+    }
+
+    /// Increment a counter by an arbitrary amount
+    pub fn increment_by(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(key(name, labels))
+            .or_default() += value;
+    }
+
+    /// Set a gauge to its current value
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        self.gauges
+            .lock()
+            .unwrap()
+            .insert(key(name, labels), value);
+    }
+
+    /// Record a latency observation in milliseconds
+    pub fn observe_ms(&self, name: &str, labels: &[(&str, &str)], value_ms: u64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry(key(name, labels)).or_default();
+        histogram.count += 1;
+        histogram.sum += value_ms;
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(BUCKETS_MS) {
+            if value_ms <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// All metrics in the Prometheus text format, one `# TYPE` line per metric
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, samples) in by_name(&self.counters.lock().unwrap()) {
+            out.push_str(&format!("# TYPE {} counter\n", name));
+            for (key, value) in samples {
+                out.push_str(&format!("{} {}\n", key, value));
+            }
+        }
+        for (name, samples) in by_name(&self.gauges.lock().unwrap()) {
+            out.push_str(&format!("# TYPE {} gauge\n", name));
+            for (key, value) in samples {
+                out.push_str(&format!("{} {}\n", key, value));
+            }
+        }
+        for (name, samples) in by_name(&self.histograms.lock().unwrap()) {
+            out.push_str(&format!("# TYPE {} histogram\n", name));
+            for (key, histogram) in samples {
+                let labels = &key[name.len()..];
+                let bucket = format!("{}_bucket{}", name, labels);
+                for (count, bound) in histogram.buckets.iter().zip(BUCKETS_MS) {
+                    let le = format!("le=\"{}\"", bound);
+                    out.push_str(&format!("{} {}\n", with_label(&bucket, &le), count));
+                }
+                let inf = with_label(&bucket, "le=\"+Inf\"");
+                out.push_str(&format!("{} {}\n", inf, histogram.count));
+                out.push_str(&format!("{}_sum{} {}\n", name, labels, histogram.sum));
+                out.push_str(&format!("{}_count{} {}\n", name, labels, histogram.count));
+            }
+        }
+        out
+    }
+}
+
+/// Metric name without its labels
+fn base_name(key: &str) -> &str {
+    key.split_once('{').map_or(key, |(name, _)| name)
+}
+
+/// `key` with one more label added
+fn with_label(key: &str, label: &str) -> String {
+    match key.strip_suffix('}') {
+        Some(labeled) => format!("{},{}}}", labeled, label),
+        None => format!("{}{{{}}}", key, label),
+    }
+}
+
+/// Samples grouped by metric name, in name order
+///
+/// Sorting the keys alone would put `name_other` between the labelled and
+/// unlabelled samples of `name`, splitting its group.
+fn by_name<V>(samples: &BTreeMap<String, V>) -> BTreeMap<&str, Vec<(&str, &V)>> {
+    let mut grouped: BTreeMap<&str, Vec<(&str, &V)>> = BTreeMap::new();
+    for (key, value) in samples {
+        grouped
+            .entry(base_name(key))
+            .or_default()
+            .push((key.as_str(), value));
+    }
+    grouped
+}
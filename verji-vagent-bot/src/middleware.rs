@@ -0,0 +1,38 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::responder::{OutgoingMessage, ResponderContext};
+
+/// Outcome of a middleware's `before` hook
+#[non_exhaustive]
+pub enum MiddlewareDecision {
+    /// Continue to the next middleware and then the responder chain
+    Continue,
+    /// Skip the responder chain and send this reply instead
+    ShortCircuit(OutgoingMessage),
+    /// Drop the message silently (no reply, no further processing)
+    Drop,
+}
+
+/// Cross-cutting logic executed around responder dispatch
+///
+/// `before` hooks run in registration order; `after` hooks run in reverse
+/// order once the outgoing messages are known. Errors are logged and treated
+/// as `Continue` so one broken middleware cannot silence the bot.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Returns the name of this middleware
+    fn name(&self) -> &str;
+
+    /// Inspect the message before any responder sees it
+    async fn before(&self, context: &ResponderContext) -> Result<MiddlewareDecision>;
+
+    /// Observe the outgoing messages after dispatch
+    async fn after(
+        &self,
+        _context: &ResponderContext,
+        _response: &[OutgoingMessage],
+    ) -> Result<()> {
+        Ok(())
+    }
+}
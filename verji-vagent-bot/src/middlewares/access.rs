@@ -0,0 +1,116 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+use crate::alias::Aliases;
+use crate::config::BotConfig;
+use crate::middleware::{Middleware, MiddlewareDecision};
+use crate::policy::{PolicyList, RuleKind};
+use crate::responder::ResponderContext;
+
+/// Room/user allow and deny lists
+///
+/// Denied messages are dropped silently, matching the RBAC rule that the bot
+/// does not respond to users without access. Bans from the policy room
+/// (`POLICY_ROOM`) add to the deny lists, but a user or room listed in the
+/// local allow lists is let through anyway.
+pub struct AccessControlMiddleware {
+    allowed_rooms: Vec<String>,
+    allowed_users: Vec<String>,
+    denied_users: Vec<String>,
+    policy: Option<Arc<PolicyList>>,
+    /// Policy bans overridden by a local allow, logged once each
+    overrides_logged: Mutex<HashSet<(RuleKind, String)>>,
+}
+
+impl AccessControlMiddleware {
+    pub fn new(config: &BotConfig) -> Self {
+        Self {
+            allowed_rooms: config.allowed_rooms.clone(),
+            allowed_users: config.allowed_users.clone(),
+            denied_users: config.denied_users.clone(),
+            policy: None,
+            overrides_logged: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Also deny users and rooms banned in the policy room
+    pub fn with_policy(mut self, policy: Option<Arc<PolicyList>>) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Check a (room, user) pair against the lists; empty allow lists allow everyone
+    ///
+    /// Aliases in the room list match through `aliases`.
+    pub fn is_allowed(&self, room_id: &str, user_id: &str, aliases: &Aliases) -> bool {
+        if self.denied_users.iter().any(|u| u == user_id) {
+            return false;
+        }
+        if self.policy_banned(RuleKind::User, user_id, &self.allowed_users)
+            || self.policy_banned(RuleKind::Room, room_id, &self.allowed_rooms)
+        {
+            return false;
+        }
+        let room_ok = self.allowed_rooms.is_empty()
+            || self
+                .allowed_rooms
+                .iter()
+                .any(|r| aliases.matches(r, room_id));
+        let user_ok =
+            self.allowed_users.is_empty() || self.allowed_users.iter().any(|u| u == user_id);
+        room_ok && user_ok
+    }
+
+    /// Whether the policy room bans `id` and `allowed` doesn't list it explicitly
+    fn policy_banned(&self, kind: RuleKind, id: &str, allowed: &[String]) -> bool {
+        let Some(rule) = self.policy.as_ref().and_then(|policy| policy.ban(kind, id)) else {
+            return false;
+        };
+        if !allowed.iter().any(|a| a == id) {
+            return true;
+        }
+        if self
+            .overrides_logged
+            .lock()
+            .unwrap()
+            .insert((kind, id.to_string()))
+        {
+            info!(
+                "🛡️ {} is banned by policy rule {} ({}) but allowed locally; local allow wins",
+                id,
+                rule.entity,
+                rule.reason.as_deref().unwrap_or("no reason")
+            );
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Middleware for AccessControlMiddleware {
+    fn name(&self) -> &str {
+        "AccessControlMiddleware"
+    }
+
+    async fn before(&self, context: &ResponderContext) -> Result<MiddlewareDecision> {
+        // Admins always get through so they can fix a misconfigured allowlist
+        if context.sender_is_admin() {
+            return Ok(MiddlewareDecision::Continue);
+        }
+
+        let room_id = context.room.room_id().as_str();
+        if self.is_allowed(room_id, &context.sender, &context.runtime.aliases) {
+            Ok(MiddlewareDecision::Continue)
+        } else {
+            info!(
+                "🚫 Dropping message from {} in {} (access denied)",
+                context.sender,
+                context.room.room_id()
+            );
+            Ok(MiddlewareDecision::Drop)
+        }
+    }
+}
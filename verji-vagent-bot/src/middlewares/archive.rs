@@ -0,0 +1,77 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::archive::ArchiveWatch;
+use crate::command::COMMAND_PREFIX;
+use crate::i18n::t;
+use crate::middleware::{Middleware, MiddlewareDecision};
+use crate::responder::{OutgoingMessage, ResponderContext};
+
+/// Keeps the bot read-only in archived rooms (see `archive`)
+///
+/// Messages are dropped after the observers saw them. The first direct
+/// mention gets a one-time notice instead; `!roominfo` and bot admins'
+/// commands still get through.
+pub struct ArchiveMiddleware {
+    archive: Arc<ArchiveWatch>,
+}
+
+impl ArchiveMiddleware {
+    pub fn new(archive: Arc<ArchiveWatch>) -> Self {
+        Self { archive }
+    }
+}
+
+#[async_trait]
+impl Middleware for ArchiveMiddleware {
+    fn name(&self) -> &str {
+        "ArchiveMiddleware"
+    }
+
+    async fn before(&self, context: &ResponderContext) -> Result<MiddlewareDecision> {
+        if self.archive.check(context.room.as_ref()).await.is_none() {
+            return Ok(MiddlewareDecision::Continue);
+        }
+        let body = context.message_body.trim_start();
+        if body.starts_with(COMMAND_PREFIX)
+            && (context.sender_is_admin() || body.split_whitespace().next() == Some("!roominfo"))
+        {
+            return Ok(MiddlewareDecision::Continue);
+        }
+        context
+            .runtime
+            .metrics
+            .increment("archived_room_messages_total", &[]);
+
+        if context.is_direct_mention && !context.room_config.archived_notice_sent {
+            if let Err(e) = context
+                .room_configs
+                .update(context.room.as_ref(), |config| {
+                    config.archived_notice_sent = true
+                })
+                .await
+            {
+                // Better to skip the notice than to repeat it on every mention
+                warn!(
+                    "Failed to remember the archive notice in {}: {:#}",
+                    context.room.room_id(),
+                    e
+                );
+                return Ok(MiddlewareDecision::Drop);
+            }
+            info!("🗄️ Telling {} that the room is archived", context.sender);
+            return Ok(MiddlewareDecision::ShortCircuit(OutgoingMessage::Notice(
+                t(context, "archive.notice", &[]),
+            )));
+        }
+
+        debug!(
+            "🗄️ {} is archived, dropping message from {}",
+            context.room.room_id(),
+            context.sender
+        );
+        Ok(MiddlewareDecision::Drop)
+    }
+}
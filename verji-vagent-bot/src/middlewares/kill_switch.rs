@@ -0,0 +1,55 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::command::COMMAND_PREFIX;
+use crate::kill_switch::KillSwitch;
+use crate::middleware::{Middleware, MiddlewareDecision};
+use crate::responder::ResponderContext;
+
+/// Drops everything in rooms whose moderators disabled the bot
+///
+/// Bot admins' commands still get through, so the switch can be inspected
+/// from the room itself.
+pub struct KillSwitchMiddleware {
+    kill_switch: Arc<KillSwitch>,
+}
+
+impl KillSwitchMiddleware {
+    pub fn new(kill_switch: Arc<KillSwitch>) -> Self {
+        Self { kill_switch }
+    }
+}
+
+#[async_trait]
+impl Middleware for KillSwitchMiddleware {
+    fn name(&self) -> &str {
+        "KillSwitchMiddleware"
+    }
+
+    async fn before(&self, context: &ResponderContext) -> Result<MiddlewareDecision> {
+        if self
+            .kill_switch
+            .check(context.room.as_ref())
+            .await
+            .is_none()
+        {
+            return Ok(MiddlewareDecision::Continue);
+        }
+        let is_command = context
+            .message_body
+            .trim_start()
+            .starts_with(COMMAND_PREFIX);
+        if is_command && context.sender_is_admin() {
+            return Ok(MiddlewareDecision::Continue);
+        }
+
+        debug!(
+            "🔇 Disabled in {}, dropping message from {}",
+            context.room.room_id(),
+            context.sender
+        );
+        Ok(MiddlewareDecision::Drop)
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::command::COMMAND_PREFIX;
+use crate::i18n::t;
+use crate::maintenance::MaintenanceMode;
+use crate::middleware::{Middleware, MiddlewareDecision};
+use crate::responder::{OutgoingMessage, ResponderContext};
+
+/// Answers agent queries with a notice while maintenance mode is on
+///
+/// Commands still reach their responders, so `!ping`, `!help` and
+/// `!admin maintenance off` keep working during a graph deployment.
+pub struct MaintenanceMiddleware {
+    maintenance: Arc<MaintenanceMode>,
+}
+
+impl MaintenanceMiddleware {
+    pub fn new(maintenance: Arc<MaintenanceMode>) -> Self {
+        Self { maintenance }
+    }
+}
+
+#[async_trait]
+impl Middleware for MaintenanceMiddleware {
+    fn name(&self) -> &str {
+        "MaintenanceMiddleware"
+    }
+
+    async fn before(&self, context: &ResponderContext) -> Result<MiddlewareDecision> {
+        let state = self.maintenance.state();
+        if !state.enabled
+            || context
+                .message_body
+                .trim_start()
+                .starts_with(COMMAND_PREFIX)
+        {
+            return Ok(MiddlewareDecision::Continue);
+        }
+
+        info!(
+            "🚧 Maintenance mode: not forwarding query from {} in {}",
+            context.sender,
+            context.room.room_id()
+        );
+        let notice = state
+            .message
+            .unwrap_or_else(|| t(context, "maintenance.notice", &[]));
+        Ok(MiddlewareDecision::ShortCircuit(OutgoingMessage::Notice(
+            notice,
+        )))
+    }
+}
@@ -0,0 +1,11 @@
+pub mod access;
+pub mod archive;
+pub mod kill_switch;
+pub mod maintenance;
+pub mod rate_limit;
+
+pub use access::AccessControlMiddleware;
+pub use archive::ArchiveMiddleware;
+pub use kill_switch::KillSwitchMiddleware;
+pub use maintenance::MaintenanceMiddleware;
+pub use rate_limit::RateLimitMiddleware;
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::i18n::t;
+use crate::middleware::{Middleware, MiddlewareDecision};
+use crate::responder::{OutgoingMessage, ResponderContext};
+
+/// Maximum number of users tracked before idle entries are pruned
+const MAX_TRACKED_USERS: usize = 10_000;
+
+/// Global per-user rate limit (sliding window of one minute)
+pub struct RateLimitMiddleware {
+    max_per_window: usize,
+    window: Duration,
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimitMiddleware {
+    pub fn new(max_per_minute: usize) -> Self {
+        Self {
+            max_per_window: max_per_minute,
+            window: Duration::from_secs(60),
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a message from the user; returns false if they are over the limit
+    fn try_acquire(&self, user_id: &str, now: Instant) -> bool {
+        let mut recent = self.recent.lock().unwrap();
+
+        if recent.len() > MAX_TRACKED_USERS {
+            let window = self.window;
+            recent.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < window)
+            });
+        }
+
+        let times = recent.entry(user_id.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= self.window)
+        {
+            times.pop_front();
+        }
+
+        if times.len() >= self.max_per_window {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimitMiddleware {
+    fn name(&self) -> &str {
+        "RateLimitMiddleware"
+    }
+
+    async fn before(&self, context: &ResponderContext) -> Result<MiddlewareDecision> {
+        if self.max_per_window == 0 || context.sender_is_admin() {
+            return Ok(MiddlewareDecision::Continue);
+        }
+
+        if self.try_acquire(&context.sender, Instant::now()) {
+            Ok(MiddlewareDecision::Continue)
+        } else {
+            info!("🐢 Rate limit hit for {}", context.sender);
+            Ok(MiddlewareDecision::ShortCircuit(
+                OutgoingMessage::notice_mentioning(
+                    &context.sender,
+                    t(context, "rate_limit.exceeded", &[]),
+                ),
+            ))
+        }
+    }
+}
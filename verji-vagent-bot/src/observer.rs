@@ -0,0 +1,208 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use futures::FutureExt;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+
+use crate::feedback::AnsweredRequest;
+use crate::metrics::Metrics;
+use crate::responder::ResponderContext;
+use crate::responder_manager::panic_message;
+use crate::stats::TokenUsage;
+
+/// Number of worker tasks observers run on; each room is pinned to one worker
+const OBSERVER_WORKERS: usize = 8;
+
+/// Messages queued per worker before new ones are dropped
+const OBSERVER_QUEUE_CAPACITY: usize = 256;
+
+/// Upper bound for a single `observe` call
+const OBSERVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sees every dispatched message without consuming it or replying
+///
+/// Used for statistics, audit logging and feedback capture. Observers never
+/// affect the responder chain: they run on background workers, and their
+/// errors, panics and timeouts are logged and swallowed.
+#[async_trait]
+pub trait Observer: Send + Sync {
+    /// Returns the name of this observer
+    fn name(&self) -> &str;
+
+    /// Called once for every message entering dispatch
+    async fn observe(&self, context: &ResponderContext) -> Result<()>;
+
+    /// Called when the agent is done with a message, after `observe` saw it
+    async fn outcome(&self, _context: &ResponderContext, _outcome: &QueryOutcome) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// How the agent dealt with one message, reported to observers
+#[derive(Debug, Clone)]
+pub struct QueryOutcome {
+    /// From the agent taking the message to its answer
+    pub latency: Duration,
+    /// Whether vagent-graph (or the cache) came back with a message
+    pub success: bool,
+    /// The interaction's status in the history (`answered`, `failed`, ...)
+    pub status: &'static str,
+    /// Tokens and cost of the answer, when one came back
+    pub usage: Option<TokenUsage>,
+    /// The answer 👍/👎 reactions would be about, if it was one
+    pub answered: Option<AnsweredRequest>,
+}
+
+/// What a worker is handed
+enum Observed {
+    Message(ResponderContext),
+    Outcome(ResponderContext, QueryOutcome),
+    /// Answered once everything queued before it was observed
+    Idle(oneshot::Sender<()>),
+}
+
+/// Runs observers off the dispatch path
+///
+/// Ordering guarantee: all messages of a room go to the same worker, which
+/// processes them one at a time, so every observer sees a room's messages in
+/// the order they entered `dispatch`, each message's outcome after the
+/// message. Observers are invoked concurrently for each message, and there is
+/// no ordering across rooms.
+///
+/// `notify` never waits: when a worker's queue is full the message is dropped
+/// for observers (counted in `observer_dropped_total`) rather than slowing
+/// down replies.
+pub struct ObserverPool {
+    observers: Mutex<Vec<Arc<dyn Observer>>>,
+    /// Worker queues, started on the first notification
+    workers: OnceLock<Vec<mpsc::Sender<Observed>>>,
+}
+
+impl ObserverPool {
+    pub fn new() -> Self {
+        Self {
+            observers: Mutex::new(Vec::new()),
+            workers: OnceLock::new(),
+        }
+    }
+
+    /// Add an observer; must happen before the first message is dispatched
+    pub fn add(&self, observer: Arc<dyn Observer>) {
+        info!("📝 Registering observer: {}", observer.name());
+        self.observers.lock().unwrap().push(observer);
+    }
+
+    /// Queue a message for all observers without waiting for them
+    pub fn notify(&self, context: &ResponderContext) {
+        self.queue(context, Observed::Message(context.clone()));
+    }
+
+    /// Queue the agent's outcome for a message, behind the message itself
+    pub fn report(&self, context: &ResponderContext, outcome: QueryOutcome) {
+        self.queue(context, Observed::Outcome(context.clone(), outcome));
+    }
+
+    /// Wait until observers are done with everything queued so far
+    pub async fn idle(&self) {
+        let Some(workers) = self.workers.get() else {
+            return;
+        };
+        for worker in workers {
+            let (done, wait) = oneshot::channel();
+            if worker.send(Observed::Idle(done)).await.is_ok() {
+                let _ = wait.await;
+            }
+        }
+    }
+
+    fn queue(&self, context: &ResponderContext, observed: Observed) {
+        if self.observers.lock().unwrap().is_empty() {
+            return;
+        }
+
+        let workers = self.workers.get_or_init(|| self.start_workers());
+        let mut hasher = DefaultHasher::new();
+        context.room.room_id().hash(&mut hasher);
+        let worker = &workers[(hasher.finish() % workers.len() as u64) as usize];
+
+        if let Err(e) = worker.try_send(observed) {
+            warn!("⚠️  Observer queue unavailable, skipping message: {}", e);
+            context
+                .runtime
+                .metrics
+                .increment("observer_dropped_total", &[]);
+        }
+    }
+
+    fn start_workers(&self) -> Vec<mpsc::Sender<Observed>> {
+        let observers = self.observers.lock().unwrap().clone();
+        (0..OBSERVER_WORKERS)
+            .map(|_| {
+                let (tx, mut rx) = mpsc::channel::<Observed>(OBSERVER_QUEUE_CAPACITY);
+                let observers = observers.clone();
+                tokio::spawn(async move {
+                    while let Some(observed) = rx.recv().await {
+                        match observed {
+                            Observed::Message(context) => {
+                                join_all(observers.iter().map(|observer| {
+                                    run_observer(
+                                        observer.as_ref(),
+                                        &context.runtime.metrics,
+                                        observer.observe(&context),
+                                    )
+                                }))
+                                .await;
+                            }
+                            Observed::Outcome(context, outcome) => {
+                                join_all(observers.iter().map(|observer| {
+                                    run_observer(
+                                        observer.as_ref(),
+                                        &context.runtime.metrics,
+                                        observer.outcome(&context, &outcome),
+                                    )
+                                }))
+                                .await;
+                            }
+                            Observed::Idle(done) => {
+                                let _ = done.send(());
+                            }
+                        }
+                    }
+                });
+                tx
+            })
+            .collect()
+    }
+}
+
+impl Default for ObserverPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run one observer call, swallowing its errors, panics and timeouts
+async fn run_observer(
+    observer: &dyn Observer,
+    metrics: &Metrics,
+    call: impl Future<Output = Result<()>>,
+) {
+    let observe = AssertUnwindSafe(call).catch_unwind();
+
+    let failure = match tokio::time::timeout(OBSERVER_TIMEOUT, observe).await {
+        Ok(Ok(Ok(()))) => return,
+        Ok(Ok(Err(e))) => format!("{:#}", e),
+        Ok(Err(panic)) => format!("panicked: {}", panic_message(panic.as_ref())),
+        Err(_) => format!("exceeded {:?}", OBSERVER_TIMEOUT),
+    };
+
+    error!("Observer '{}' failed: {}", observer.name(), failure);
+    metrics.increment("observer_errors_total", &[("observer", observer.name())]);
+}
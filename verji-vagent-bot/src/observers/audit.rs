@@ -0,0 +1,39 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::debug;
+
+use crate::observer::Observer;
+use crate::responder::ResponderContext;
+
+/// Logs one structured line per message under the `audit` tracing target
+///
+/// Message bodies are not logged, only their length. Enable with
+/// `RUST_LOG=audit=debug`.
+#[derive(Default)]
+pub struct AuditObserver;
+
+impl AuditObserver {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Observer for AuditObserver {
+    fn name(&self) -> &str {
+        "AuditObserver"
+    }
+
+    async fn observe(&self, context: &ResponderContext) -> Result<()> {
+        debug!(
+            target: "audit",
+            room_id = %context.room.room_id(),
+            event_id = %context.event_id,
+            sender = %context.sender,
+            direct_mention = context.is_direct_mention,
+            length = context.message_body.chars().count(),
+            "message received"
+        );
+        Ok(())
+    }
+}
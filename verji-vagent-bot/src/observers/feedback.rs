@@ -0,0 +1,40 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::feedback;
+use crate::observer::{Observer, QueryOutcome};
+use crate::responder::ResponderContext;
+
+/// Remembers each agent answer as the one 👍/👎 reactions are about
+#[derive(Default)]
+pub struct FeedbackObserver;
+
+impl FeedbackObserver {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Observer for FeedbackObserver {
+    fn name(&self) -> &str {
+        "FeedbackObserver"
+    }
+
+    async fn observe(&self, _context: &ResponderContext) -> Result<()> {
+        Ok(())
+    }
+
+    async fn outcome(&self, context: &ResponderContext, outcome: &QueryOutcome) -> Result<()> {
+        if let Some(answered) = &outcome.answered {
+            feedback::remember_answer(
+                &context.conversations,
+                context.room.room_id().as_str(),
+                &context.sender,
+                answered,
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
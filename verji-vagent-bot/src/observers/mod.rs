@@ -0,0 +1,20 @@
+pub mod audit;
+pub mod feedback;
+pub mod stats;
+
+use std::sync::Arc;
+
+use crate::observer::Observer;
+
+pub use audit::AuditObserver;
+pub use feedback::FeedbackObserver;
+pub use stats::StatsObserver;
+
+/// The observers the bot runs with, in registration order
+pub fn standard() -> Vec<Arc<dyn Observer>> {
+    vec![
+        Arc::new(AuditObserver::new()),
+        Arc::new(StatsObserver::new()),
+        Arc::new(FeedbackObserver::new()),
+    ]
+}
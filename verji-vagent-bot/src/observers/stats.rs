@@ -0,0 +1,49 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::db;
+use crate::observer::{Observer, QueryOutcome};
+use crate::responder::ResponderContext;
+
+/// Counts agent queries, their token usage and the `!history` line of each
+///
+/// Usage counts towards the room's budget period it was reported in.
+#[derive(Default)]
+pub struct StatsObserver;
+
+impl StatsObserver {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl Observer for StatsObserver {
+    fn name(&self) -> &str {
+        "StatsObserver"
+    }
+
+    async fn observe(&self, _context: &ResponderContext) -> Result<()> {
+        Ok(())
+    }
+
+    async fn outcome(&self, context: &ResponderContext, outcome: &QueryOutcome) -> Result<()> {
+        let room_id = context.room.room_id().as_str();
+        context
+            .stats
+            .record_query(room_id, &context.identity, outcome.latency, outcome.success);
+        if let Some(usage) = &outcome.usage {
+            let period = context.config.room_budget.period(db::now_secs() * 1000);
+            context
+                .stats
+                .record_usage(room_id, &context.identity, &period, usage);
+        }
+        context.stats.record_interaction(
+            room_id,
+            &context.identity,
+            &context.message_body,
+            outcome.status,
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Outbound webhooks: every finished agent exchange POSTed to external services
+//!
+//! Configured with `OUTBOUND_WEBHOOKS`, a JSON list of
+//! `{"name", "url", "secret", "rooms", "redact", "max_attempts"}`. Bodies are
+//! signed with HMAC-SHA256 of the secret (`X-Vagent-Signature: sha256=<hex>`).
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::db;
+use crate::metrics::Metrics;
+use crate::redact::Redactor;
+
+/// First retry delay; doubled for every further attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for one delivery attempt
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One configured endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutboundWebhookConfig {
+    /// Used in logs and metric labels (defaults to `webhook<N>`)
+    #[serde(default)]
+    pub name: Option<String>,
+    pub url: String,
+    /// HMAC key for the signature header and user hashes
+    pub secret: String,
+    /// Only exchanges in these room IDs are sent (empty = all rooms)
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    /// Mask personal data in the query and response
+    #[serde(default)]
+    pub redact: bool,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    4
+}
+
+/// A finished agent exchange
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub request_id: String,
+    pub room_id: String,
+    pub user_id: String,
+    pub query: String,
+    pub response: Option<String>,
+    pub latency: Duration,
+    pub status: String,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    request_id: &'a str,
+    room_id: &'a str,
+    /// Keyed hash of the user ID, stable per endpoint
+    user_hash: String,
+    query: String,
+    response: Option<String>,
+    latency_ms: u64,
+    status: &'a str,
+    timestamp: u64,
+}
+
+/// Delivers exchanges to every matching endpoint in the background
+pub struct OutboundWebhooks {
+    hooks: Vec<OutboundWebhookConfig>,
+    http: reqwest::Client,
+    redactor: Redactor,
+    metrics: Arc<Metrics>,
+}
+
+impl OutboundWebhooks {
+    /// Endpoints from `OUTBOUND_WEBHOOKS`, or None if unset; deliveries are
+    /// counted in `metrics`
+    pub fn from_env(metrics: Arc<Metrics>) -> Result<Option<Arc<Self>>> {
+        let Ok(json) = std::env::var("OUTBOUND_WEBHOOKS") else {
+            return Ok(None);
+        };
+        if json.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let mut hooks: Vec<OutboundWebhookConfig> =
+            serde_json::from_str(&json).context("Invalid OUTBOUND_WEBHOOKS")?;
+        for (index, hook) in hooks.iter_mut().enumerate() {
+            hook.name.get_or_insert_with(|| format!("webhook{}", index));
+        }
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to build HTTP client for outbound webhooks")?;
+
+        info!("📤 {} outbound webhook(s) configured", hooks.len());
+        Ok(Some(Arc::new(Self {
+            hooks,
+            http,
+            redactor: Redactor::new(std::iter::empty()),
+            metrics,
+        })))
+    }
+
+    /// Queue an exchange for every endpoint interested in its room
+    ///
+    /// Returns immediately; deliveries run on their own tasks.
+    pub fn fire(self: &Arc<Self>, exchange: Exchange) {
+        let exchange = Arc::new(exchange);
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if !hook.rooms.is_empty() && !hook.rooms.contains(&exchange.room_id) {
+                continue;
+            }
+            let webhooks = Arc::clone(self);
+            let exchange = Arc::clone(&exchange);
+            tokio::spawn(async move {
+                webhooks.deliver(&webhooks.hooks[index], &exchange).await;
+            });
+        }
+    }
+
+    async fn deliver(&self, hook: &OutboundWebhookConfig, exchange: &Exchange) {
+        let name = hook.name.as_deref().unwrap_or_default();
+        let body = match self.payload(hook, exchange) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("📤 Failed to build payload for {}: {:#}", name, e);
+                return;
+            }
+        };
+        let signature = format!("sha256={}", hex::encode(sign(&hook.secret, &body)));
+
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=hook.max_attempts.max(1) {
+            let result = self
+                .http
+                .post(&hook.url)
+                .header("content-type", "application/json")
+                .header("x-vagent-signature", &signature)
+                .body(body.clone())
+                .send()
+                .await;
+
+            let retry = match result {
+                Ok(response) if response.status().is_success() => {
+                    debug!("📤 Delivered {} to {}", exchange.request_id, name);
+                    self.metrics
+                        .increment("outbound_webhook_delivered_total", &[("webhook", name)]);
+                    return;
+                }
+                Ok(response) if response.status().is_server_error() => {
+                    warn!(
+                        "📤 {} answered {} (attempt {})",
+                        name,
+                        response.status(),
+                        attempt
+                    );
+                    true
+                }
+                Ok(response) => {
+                    warn!(
+                        "📤 {} rejected the payload with {}",
+                        name,
+                        response.status()
+                    );
+                    false
+                }
+                Err(e) => {
+                    warn!("📤 Failed to reach {} (attempt {}): {}", name, attempt, e);
+                    true
+                }
+            };
+            if !retry || attempt == hook.max_attempts {
+                break;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+
+        warn!("📤 Dropping exchange {} for {}", exchange.request_id, name);
+        self.metrics
+            .increment("outbound_webhook_dropped_total", &[("webhook", name)]);
+    }
+
+    fn payload(&self, hook: &OutboundWebhookConfig, exchange: &Exchange) -> Result<Vec<u8>> {
+        let redact = |text: &str| {
+            if hook.redact {
+                self.redactor.redact(text)
+            } else {
+                text.to_string()
+            }
+        };
+        let payload = Payload {
+            request_id: &exchange.request_id,
+            room_id: &exchange.room_id,
+            user_hash: hex::encode(&sign(&hook.secret, exchange.user_id.as_bytes())[..8]),
+            query: redact(&exchange.query),
+            response: exchange.response.as_deref().map(redact),
+            latency_ms: exchange.latency.as_millis() as u64,
+            status: &exchange.status,
+            timestamp: db::now_secs(),
+        };
+        serde_json::to_vec(&payload).context("Failed to serialize payload")
+    }
+}
+
+/// HMAC-SHA256 of `data` keyed with `secret`
+fn sign(secret: &str, data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,742 @@
+//! Final answers that could not be sent, kept for later delivery
+//!
+//! When sending a final answer fails after the send retries (e.g. the
+//! homeserver is briefly down), the message is written to the bot database
+//! instead of being lost with the agent run that produced it. A background
+//! task sends due entries again, the first time right after the initial sync,
+//! backing off from `OUTBOX_RETRY_SECS` up to `OUTBOX_RETRY_MAX_SECS` between
+//! attempts. Delivered answers are marked as delayed; entries older than
+//! `OUTBOX_MAX_AGE_HOURS` are abandoned. With `FALLBACK_DM=true`, answers the
+//! room refuses for good and entries the outbox gives up on go to the asker's
+//! direct chat instead (see `fallback_dm`). Where each of these answers ended
+//! up is recorded; `!admin outbox` lists what is waiting and recent outcomes.
+
+use anyhow::{Context, Result};
+use matrix_sdk::ruma::{EventId, RoomId};
+use matrix_sdk::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::db;
+use crate::fallback_dm;
+use crate::mentions::Mentions;
+use crate::responder::OutgoingMessage;
+use crate::room::{BotRoom, RoomHandle};
+use crate::runtime::Runtime;
+use crate::sent_events::{SentEventRegistry, SentKind};
+
+/// Characters of an answer shown by `!admin outbox`
+const PREVIEW_CHARS: usize = 60;
+
+/// Recent outcomes shown by `!admin outbox`
+const RECENT_OUTCOMES: usize = 10;
+
+/// Where an answer that failed to send ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    /// Sent to its room by a later attempt
+    Delayed,
+    /// Sent to the asker's direct chat
+    DirectMessage,
+    /// Not delivered; the admin room was told
+    AdminAlert,
+    /// Not delivered
+    Abandoned,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Delayed => "delayed",
+            Disposition::DirectMessage => "direct_message",
+            Disposition::AdminAlert => "admin_alert",
+            Disposition::Abandoned => "abandoned",
+        }
+    }
+}
+
+/// The question an answer belongs to
+#[derive(Debug, Clone, Copy)]
+pub struct Origin<'a> {
+    pub room_id: &'a str,
+    /// Message the answer replies to
+    pub trigger: &'a EventId,
+    pub thread_id: Option<&'a str>,
+    pub request_id: Option<&'a str>,
+    /// Who asked, for the direct message fallback
+    pub sender: Option<&'a str>,
+}
+
+/// A recorded disposition
+#[derive(Debug)]
+pub struct Outcome {
+    pub room_id: String,
+    pub trigger: String,
+    pub request_id: Option<String>,
+    pub disposition: String,
+    pub detail: String,
+    /// Unix seconds
+    pub recorded_at: u64,
+}
+
+/// An answer waiting to be delivered
+#[derive(Debug)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub room_id: String,
+    /// Message the answer replies to
+    pub trigger: String,
+    pub thread_id: Option<String>,
+    pub request_id: Option<String>,
+    /// Who asked; unknown for entries kept before the DM fallback existed
+    pub sender: Option<String>,
+    pub message: OutgoingMessage,
+    /// Failed sends so far, including the original one
+    pub attempts: u32,
+    /// Unix seconds
+    pub created_at: u64,
+    /// Unix seconds
+    pub next_attempt_at: u64,
+    pub last_error: String,
+}
+
+/// A mention as stored: the body and whom it notifies
+#[derive(Serialize, Deserialize)]
+struct StoredMention {
+    body: String,
+    mentions: Mentions,
+    notice: bool,
+    /// Mentions stored before plain ones existed were all markdown
+    #[serde(default = "markdown_by_default")]
+    markdown: bool,
+}
+
+fn markdown_by_default() -> bool {
+    true
+}
+
+/// Storage form of a text-like message; other kinds are never deferred
+fn encode(message: &OutgoingMessage) -> Option<(&'static str, String)> {
+    match message {
+        OutgoingMessage::Text(body) => Some(("text", body.clone())),
+        OutgoingMessage::Markdown(body) => Some(("markdown", body.clone())),
+        OutgoingMessage::Notice(body) => Some(("notice", body.clone())),
+        OutgoingMessage::Mention {
+            body,
+            mentions,
+            notice,
+            markdown,
+        } => {
+            let stored = StoredMention {
+                body: body.clone(),
+                mentions: mentions.clone(),
+                notice: *notice,
+                markdown: *markdown,
+            };
+            Some(("mention", serde_json::to_string(&stored).ok()?))
+        }
+        OutgoingMessage::Reaction(_) | OutgoingMessage::Attachment { .. } => None,
+    }
+}
+
+fn decode(format: &str, body: String) -> Option<OutgoingMessage> {
+    match format {
+        "text" => Some(OutgoingMessage::Text(body)),
+        "markdown" => Some(OutgoingMessage::Markdown(body)),
+        "notice" => Some(OutgoingMessage::Notice(body)),
+        "mention" => {
+            let stored: StoredMention = serde_json::from_str(&body).ok()?;
+            Some(OutgoingMessage::Mention {
+                body: stored.body,
+                mentions: stored.mentions,
+                notice: stored.notice,
+                markdown: stored.markdown,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The same message with `marker` (e.g. the delayed delivery note) in front
+pub fn with_marker(message: OutgoingMessage, marker: &str) -> OutgoingMessage {
+    match message {
+        OutgoingMessage::Text(body) => OutgoingMessage::Text(format!("{}\n\n{}", marker, body)),
+        OutgoingMessage::Markdown(body) => {
+            OutgoingMessage::Markdown(format!("_{}_\n\n{}", marker, body))
+        }
+        OutgoingMessage::Notice(body) => OutgoingMessage::Notice(format!("{}\n\n{}", marker, body)),
+        OutgoingMessage::Mention {
+            body,
+            mentions,
+            notice,
+            markdown: true,
+        } => OutgoingMessage::Mention {
+            body: format!("_{}_\n\n{}", marker, body),
+            mentions,
+            notice,
+            markdown: true,
+        },
+        OutgoingMessage::Mention {
+            body,
+            mentions,
+            notice,
+            markdown: false,
+        } => OutgoingMessage::Mention {
+            body: format!("{}\n\n{}", marker, body),
+            mentions,
+            notice,
+            markdown: false,
+        },
+        other => other,
+    }
+}
+
+/// The undelivered answer table in the bot database
+pub struct Outbox {
+    db_path: PathBuf,
+    /// Entries older than this are abandoned
+    max_age: Duration,
+    /// Wait before the first retry, doubled after every failed one
+    retry: Duration,
+    retry_max: Duration,
+    /// Language of the delayed delivery marker
+    language: String,
+    runtime: Runtime,
+}
+
+impl Outbox {
+    pub fn open(
+        store_path: &Path,
+        max_age: Duration,
+        retry: Duration,
+        retry_max: Duration,
+        language: &str,
+        runtime: Runtime,
+    ) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                room_id TEXT NOT NULL,
+                trigger_event_id TEXT NOT NULL,
+                thread_id TEXT,
+                request_id TEXT,
+                sender TEXT,
+                format TEXT NOT NULL,
+                body TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                next_attempt_at INTEGER NOT NULL,
+                last_error TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS outbox_due ON outbox (next_attempt_at);
+            CREATE TABLE IF NOT EXISTS outbox_outcomes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                room_id TEXT NOT NULL,
+                trigger_event_id TEXT NOT NULL,
+                request_id TEXT,
+                disposition TEXT NOT NULL,
+                detail TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL
+            );",
+        )
+        .context("Failed to create outbox table")?;
+        // Tables created before the DM fallback don't know who asked
+        if conn.prepare("SELECT sender FROM outbox LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE outbox ADD COLUMN sender TEXT")
+                .context("Failed to add the sender to the outbox table")?;
+        }
+
+        let outbox = Self {
+            db_path,
+            max_age,
+            retry: retry.max(Duration::from_secs(1)),
+            retry_max,
+            language: language.to_string(),
+            runtime,
+        };
+        let pending = count(&conn)?;
+        if pending > 0 {
+            info!("📮 {} undelivered answers waiting in the outbox", pending);
+        }
+        Ok(outbox)
+    }
+
+    /// Keep a message whose send failed; false if its kind can't be deferred
+    pub async fn defer(
+        &self,
+        origin: &Origin<'_>,
+        message: &OutgoingMessage,
+        error: &anyhow::Error,
+    ) -> bool {
+        let Some((format, body)) = encode(message) else {
+            return false;
+        };
+        let now = db::now_secs();
+        let params: Vec<rusqlite::types::Value> = vec![
+            origin.room_id.to_string().into(),
+            origin.trigger.to_string().into(),
+            origin.thread_id.map(str::to_string).into(),
+            origin.request_id.map(str::to_string).into(),
+            format.to_string().into(),
+            body.into(),
+            ((now + self.backoff(1).as_secs()) as i64).into(),
+            format!("{:#}", error).into(),
+            (now as i64).into(),
+            origin.sender.map(str::to_string).into(),
+        ];
+        let result = self
+            .execute(
+                "INSERT INTO outbox (room_id, trigger_event_id, thread_id, request_id, format, body,
+                    attempts, next_attempt_at, last_error, created_at, sender)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, ?7, ?8, ?9, ?10)",
+                params,
+            )
+            .await;
+
+        match result {
+            Ok(_) => {
+                self.runtime.metrics.increment("outbox_deferred_total", &[]);
+                warn!(
+                    "📮 Answer for {} in {} could not be sent, kept in the outbox",
+                    origin.trigger, origin.room_id
+                );
+                self.update_gauge().await;
+                true
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to keep the undelivered answer for {}: {:#}",
+                    origin.trigger, e
+                );
+                false
+            }
+        }
+    }
+
+    /// Remember where an answer that failed to send ended up
+    pub async fn record(
+        &self,
+        room_id: &str,
+        trigger: &str,
+        request_id: Option<&str>,
+        disposition: Disposition,
+        detail: &str,
+    ) {
+        self.runtime.metrics.increment(
+            "answer_dispositions_total",
+            &[("disposition", disposition.as_str())],
+        );
+        let result = self
+            .execute(
+                "INSERT INTO outbox_outcomes
+                    (room_id, trigger_event_id, request_id, disposition, detail, recorded_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                vec![
+                    room_id.to_string().into(),
+                    trigger.to_string().into(),
+                    request_id.map(str::to_string).into(),
+                    disposition.as_str().to_string().into(),
+                    detail.to_string().into(),
+                    (db::now_secs() as i64).into(),
+                ],
+            )
+            .await;
+        if let Err(e) = result {
+            warn!("Failed to record the outcome for {}: {:#}", trigger, e);
+        }
+    }
+
+    /// The latest recorded outcomes, newest first
+    pub async fn outcomes(&self, limit: usize) -> Result<Vec<Outcome>> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<Outcome>> {
+            let conn = db::open(&db_path)?;
+            let mut stmt = conn.prepare(
+                "SELECT room_id, trigger_event_id, request_id, disposition, detail, recorded_at
+                 FROM outbox_outcomes ORDER BY id DESC LIMIT ?1",
+            )?;
+            let rows = stmt.query_map([limit as i64], |row| {
+                Ok(Outcome {
+                    room_id: row.get(0)?,
+                    trigger: row.get(1)?,
+                    request_id: row.get(2)?,
+                    disposition: row.get(3)?,
+                    detail: row.get(4)?,
+                    recorded_at: row.get::<_, i64>(5)? as u64,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        })
+        .await
+        .context("Outbox query panicked")?
+    }
+
+    /// What `!admin outbox` shows
+    pub async fn render(&self) -> Result<String> {
+        Ok(render(
+            &self.pending().await?,
+            &self.outcomes(RECENT_OUTCOMES).await?,
+        ))
+    }
+
+    /// Every waiting entry, oldest first
+    pub async fn pending(&self) -> Result<Vec<OutboxEntry>> {
+        self.query(i64::MAX, 0).await
+    }
+
+    /// Send the entries that are due, returning how many went out
+    ///
+    /// A room's entries go out in order: after a failure the room's later
+    /// entries wait for the next run. Expired entries, and those the room
+    /// refuses, are given up.
+    pub async fn deliver_due(&self, client: &Client, sent_events: &SentEventRegistry) -> usize {
+        let now = db::now_secs();
+        let cutoff = now.saturating_sub(self.max_age.as_secs());
+        if let Err(e) = self
+            .execute(
+                "DELETE FROM outbox_outcomes WHERE recorded_at < ?1",
+                vec![(cutoff as i64).into()],
+            )
+            .await
+        {
+            warn!("Failed to drop old outbox outcomes: {:#}", e);
+        }
+        let entries = match self.query(now as i64, cutoff as i64).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read the outbox: {:#}", e);
+                return 0;
+            }
+        };
+        if entries.is_empty() {
+            return 0;
+        }
+        let (expired, due): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|entry| entry.created_at < cutoff);
+        for entry in expired {
+            let reason = format!("older than {}h", self.max_age.as_secs() / 3600);
+            self.give_up(entry, &reason).await;
+        }
+
+        let marker = self
+            .runtime
+            .catalog
+            .translate(&self.language, "outbox.delayed", &[]);
+        let mut blocked_rooms = HashSet::new();
+        let mut delivered = 0;
+        for entry in due {
+            if blocked_rooms.contains(&entry.room_id) {
+                continue;
+            }
+            let room = RoomId::parse(&entry.room_id)
+                .ok()
+                .and_then(|room_id| client.get_room(&room_id))
+                .map(|room| BotRoom::new(room, self.runtime.clone()));
+            let (Some(room), Ok(trigger)) = (room, EventId::parse(&entry.trigger)) else {
+                self.give_up(entry, "the room is no longer known").await;
+                continue;
+            };
+
+            let message = with_marker(entry.message.clone(), &marker);
+            let mut turn = self
+                .runtime
+                .send_queues
+                .turn(room.room_id(), entry.thread_id.as_deref())
+                .await;
+            match turn.send(room.send_content(&trigger, message)).await {
+                Ok(event_id) => {
+                    drop(turn);
+                    self.runtime
+                        .key_rotations
+                        .after_send(&room, &event_id)
+                        .await;
+                    sent_events
+                        .record(
+                            event_id,
+                            &entry.room_id,
+                            SentKind::Final,
+                            entry.request_id.as_deref(),
+                        )
+                        .await;
+                    if let Err(e) = self.remove(entry.id).await {
+                        warn!(
+                            "Failed to remove delivered outbox entry {}: {:#}",
+                            entry.id, e
+                        );
+                    }
+                    self.runtime
+                        .metrics
+                        .increment("outbox_delivered_total", &[]);
+                    info!(
+                        "📮 Delivered the answer for {} to {} after {} failed attempt(s)",
+                        entry.trigger, entry.room_id, entry.attempts
+                    );
+                    let detail = format!("after {} failed attempt(s)", entry.attempts);
+                    self.record(
+                        &entry.room_id,
+                        &entry.trigger,
+                        entry.request_id.as_deref(),
+                        Disposition::Delayed,
+                        &detail,
+                    )
+                    .await;
+                    delivered += 1;
+                }
+                Err(e) if self.runtime.fallback_dm.is_enabled() && fallback_dm::is_refused(&e) => {
+                    drop(turn);
+                    self.give_up(entry, &format!("{:#}", e)).await;
+                }
+                Err(e) => {
+                    let attempts = entry.attempts + 1;
+                    let next = db::now_secs() + self.backoff(attempts).as_secs();
+                    warn!(
+                        "📮 Delivery of the answer for {} failed again (attempt {}): {:#}",
+                        entry.trigger, attempts, e
+                    );
+                    if let Err(e) = self.reschedule(entry.id, attempts, next, &e).await {
+                        warn!("Failed to reschedule outbox entry {}: {:#}", entry.id, e);
+                    }
+                    blocked_rooms.insert(entry.room_id);
+                }
+            }
+        }
+
+        self.update_gauge().await;
+        delivered
+    }
+
+    /// Deliver due entries every `interval` until the bot stops, starting now
+    pub fn spawn_delivery_task(
+        self: &Arc<Self>,
+        client: Client,
+        sent_events: Arc<SentEventRegistry>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let outbox = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                outbox.deliver_due(&client, &sent_events).await;
+            }
+        })
+    }
+
+    /// Wait before attempt `attempts + 1`
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 1u32 << attempts.saturating_sub(1).min(16);
+        (self.retry * factor).min(self.retry_max.max(self.retry))
+    }
+
+    /// Stop trying the entry's room: hand it to the DM fallback, or abandon it
+    ///
+    /// Answers for rooms the bot left on purpose are dropped, not sent by DM.
+    async fn give_up(&self, entry: OutboxEntry, reason: &str) {
+        let fallback = match (entry.sender.as_deref(), EventId::parse(&entry.trigger)) {
+            (Some(sender), Ok(trigger)) if !self.runtime.memberships.has_left(&entry.room_id) => {
+                let message = entry.message.clone();
+                let room_id = &entry.room_id;
+                fallback_dm::deliver(&self.runtime, room_id, &trigger, sender, message, reason)
+                    .await
+            }
+            _ => None,
+        };
+        let (disposition, detail) =
+            fallback.unwrap_or_else(|| (Disposition::Abandoned, reason.to_string()));
+        if disposition != Disposition::DirectMessage {
+            self.runtime
+                .metrics
+                .increment("outbox_abandoned_total", &[]);
+            warn!(
+                "📮 Abandoned the undelivered answer for {} in {}: {}",
+                entry.trigger, entry.room_id, reason
+            );
+        }
+        self.record(
+            &entry.room_id,
+            &entry.trigger,
+            entry.request_id.as_deref(),
+            disposition,
+            &detail,
+        )
+        .await;
+        if let Err(e) = self.remove(entry.id).await {
+            warn!("Failed to remove outbox entry {}: {:#}", entry.id, e);
+        }
+    }
+
+    async fn remove(&self, id: i64) -> Result<()> {
+        self.execute("DELETE FROM outbox WHERE id = ?1", vec![id.into()])
+            .await
+            .map(|_| ())
+    }
+
+    async fn reschedule(
+        &self,
+        id: i64,
+        attempts: u32,
+        next: u64,
+        error: &anyhow::Error,
+    ) -> Result<()> {
+        self.execute(
+            "UPDATE outbox SET attempts = ?2, next_attempt_at = ?3, last_error = ?4 WHERE id = ?1",
+            vec![
+                id.into(),
+                (attempts as i64).into(),
+                (next as i64).into(),
+                format!("{:#}", error).into(),
+            ],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn update_gauge(&self) {
+        let db_path = self.db_path.clone();
+        let pending =
+            tokio::task::spawn_blocking(move || -> Result<usize> { count(&db::open(&db_path)?) })
+                .await;
+        if let Ok(Ok(pending)) = pending {
+            self.runtime
+                .metrics
+                .set_gauge("outbox_pending", &[], pending as u64);
+        }
+    }
+
+    /// Entries due at or before `due_by` or created before `created_before`
+    /// (unix seconds), oldest first
+    async fn query(&self, due_by: i64, created_before: i64) -> Result<Vec<OutboxEntry>> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<OutboxEntry>> {
+            let conn = db::open(&db_path)?;
+            let mut stmt = conn.prepare(
+                "SELECT id, room_id, trigger_event_id, thread_id, request_id, format, body,
+                        attempts, created_at, next_attempt_at, last_error, sender
+                 FROM outbox WHERE next_attempt_at <= ?1 OR created_at < ?2 ORDER BY id",
+            )?;
+            let rows = stmt.query_map([due_by, created_before], |row| {
+                Ok((
+                    (
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ),
+                    (
+                        row.get::<_, String>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, i64>(7)? as u32,
+                        row.get::<_, i64>(8)? as u64,
+                        row.get::<_, i64>(9)? as u64,
+                        row.get::<_, String>(10)?,
+                        row.get::<_, Option<String>>(11)?,
+                    ),
+                ))
+            })?;
+
+            let mut entries = Vec::new();
+            for row in rows {
+                let (
+                    (id, room_id, trigger, thread_id, request_id),
+                    (format, body, attempts, created_at, next_attempt_at, last_error, sender),
+                ) = row?;
+                let Some(message) = decode(&format, body) else {
+                    warn!("Skipping outbox entry {} of unknown format {}", id, format);
+                    continue;
+                };
+                entries.push(OutboxEntry {
+                    id,
+                    room_id,
+                    trigger,
+                    thread_id,
+                    request_id,
+                    sender,
+                    message,
+                    attempts,
+                    created_at,
+                    next_attempt_at,
+                    last_error,
+                });
+            }
+            Ok(entries)
+        })
+        .await
+        .context("Outbox query panicked")?
+    }
+
+    async fn execute(
+        &self,
+        sql: &'static str,
+        params: Vec<rusqlite::types::Value>,
+    ) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = db::open(&db_path)?;
+            Ok(conn.execute(sql, rusqlite::params_from_iter(params))?)
+        })
+        .await
+        .context("Outbox write panicked")?
+    }
+}
+
+fn count(conn: &rusqlite::Connection) -> Result<usize> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM outbox", [], |row| row.get(0))?;
+    Ok(count as usize)
+}
+
+/// Markdown list of waiting entries and recent outcomes for `!admin outbox`
+pub fn render(entries: &[OutboxEntry], outcomes: &[Outcome]) -> String {
+    let now = db::now_secs();
+    let mut out = if entries.is_empty() {
+        "📮 The outbox is empty; no answer is waiting for delivery.".to_string()
+    } else {
+        format!("📮 **{} undelivered answers**\n", entries.len())
+    };
+    for entry in entries {
+        let body = match &entry.message {
+            OutgoingMessage::Text(body)
+            | OutgoingMessage::Markdown(body)
+            | OutgoingMessage::Notice(body)
+            | OutgoingMessage::Mention { body, .. } => body.as_str(),
+            _ => "",
+        };
+        let mut preview: String = body.chars().take(PREVIEW_CHARS).collect();
+        if preview.len() < body.len() {
+            preview.push('…');
+        }
+        out.push_str(&format!(
+            "\n- `{}` in {}, {} attempt(s), {}m old, next in {}s: {}\n  last error: {}",
+            entry.request_id.as_deref().unwrap_or(&entry.trigger),
+            entry.room_id,
+            entry.attempts,
+            now.saturating_sub(entry.created_at) / 60,
+            entry.next_attempt_at.saturating_sub(now),
+            preview.replace('\n', " "),
+            entry.last_error
+        ));
+    }
+
+    if !outcomes.is_empty() {
+        out.push_str("\n\n**Recent outcomes**\n");
+        for outcome in outcomes {
+            out.push_str(&format!(
+                "\n- `{}` in {}, {}m ago: {} ({})",
+                outcome.request_id.as_deref().unwrap_or(&outcome.trigger),
+                outcome.room_id,
+                now.saturating_sub(outcome.recorded_at) / 60,
+                outcome.disposition,
+                outcome.detail
+            ));
+        }
+    }
+    out
+}
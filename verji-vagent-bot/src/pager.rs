@@ -0,0 +1,223 @@
+//! Long output split into pages that are turned with reactions
+//!
+//! [`send`] posts the first page with a footer and keeps all pages in the
+//! conversation store for `PAGER_TTL_SECS`, keyed to the user who asked and
+//! the sent message. ▶️ and ◀️ from that user on the message edit it to the
+//! next or previous page ([`on_reaction`]); reactions from anyone else, or
+//! after the pages expired, are ignored.
+
+use anyhow::Result;
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::i18n::Catalog;
+use crate::responder::ResponderContext;
+use crate::room::RoomHandle;
+use crate::sent_events::{SentEventRegistry, SentKind};
+
+/// Reaction that shows the next page
+pub const NEXT: &str = "▶️";
+/// Reaction that shows the previous page
+pub const PREVIOUS: &str = "◀️";
+
+/// Prefix of the conversation slot holding a message's pages
+const SLOT_PREFIX: &str = "pager.";
+
+/// Which way a reaction turns the pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    Next,
+    Previous,
+}
+
+impl Turn {
+    /// The turn a reaction asks for; clients may leave out the emoji variation selector
+    pub fn from_reaction(key: &str) -> Option<Self> {
+        match key.trim_end_matches('\u{fe0f}') {
+            "▶" => Some(Turn::Next),
+            "◀" => Some(Turn::Previous),
+            _ => None,
+        }
+    }
+}
+
+/// The pages of one message and the one it shows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PagerState {
+    pages: Vec<String>,
+    /// 0-based
+    current: usize,
+    /// Language of the footer
+    language: String,
+}
+
+impl PagerState {
+    pub fn new(pages: Vec<String>, language: &str) -> Self {
+        Self {
+            pages,
+            current: 0,
+            language: language.to_string(),
+        }
+    }
+
+    /// 0-based index of the page shown
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Move one page; `false` if there is no page that way
+    pub fn turn(&mut self, turn: Turn) -> bool {
+        let target = match turn {
+            Turn::Next => self.current + 1,
+            Turn::Previous => match self.current.checked_sub(1) {
+                Some(previous) => previous,
+                None => return false,
+            },
+        };
+        if target >= self.pages.len() {
+            return false;
+        }
+        self.current = target;
+        true
+    }
+
+    /// The page shown, with a footer when there are several
+    pub fn render(&self, catalog: &Catalog) -> String {
+        let page = self.pages.get(self.current).cloned().unwrap_or_default();
+        if self.pages.len() < 2 {
+            return page;
+        }
+        let last = self.pages.len() - 1;
+        let id = match self.current {
+            0 => "pager.footer_first",
+            current if current == last => "pager.footer_last",
+            _ => "pager.footer_middle",
+        };
+        let number = (self.current + 1).to_string();
+        let total = self.pages.len().to_string();
+        let footer = catalog.translate(
+            &self.language,
+            id,
+            &[
+                ("page", &number),
+                ("pages", &total),
+                ("next", NEXT),
+                ("previous", PREVIOUS),
+            ],
+        );
+        format!("{}\n\n_{}_", page, footer)
+    }
+}
+
+/// Split `text` into pages of at most about `max_chars`, between lines
+///
+/// A single line longer than `max_chars` gets a page of its own.
+pub fn paginate(text: &str, max_chars: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    for line in text.lines() {
+        if !page.is_empty() && page.len() + line.len() + 1 > max_chars {
+            pages.push(std::mem::take(&mut page).trim_end().to_string());
+        }
+        page.push_str(line);
+        page.push('\n');
+    }
+    if !page.trim().is_empty() || pages.is_empty() {
+        pages.push(page.trim_end().to_string());
+    }
+    pages
+}
+
+fn state_key(room_id: &str, user_id: &str, event_id: &EventId) -> ConversationKey {
+    ConversationKey::new(room_id, user_id, &format!("{}{}", SLOT_PREFIX, event_id))
+}
+
+/// Send `pages` to `room` as one message the sender of `context` can page through
+///
+/// `room` is usually `context.room`, but may be a direct chat with the sender.
+/// A single page is sent as it is.
+pub async fn send(
+    context: &ResponderContext,
+    room: &dyn RoomHandle,
+    pages: Vec<String>,
+) -> Result<OwnedEventId> {
+    let state = PagerState::new(pages, &context.language());
+    let event_id = room
+        .send_markdown(&state.render(&context.runtime.catalog))
+        .await?;
+    let room_id = room.room_id().to_string();
+    context
+        .sent_events
+        .record(event_id.clone(), &room_id, SentKind::Final, None)
+        .await;
+
+    if state.page_count() > 1 {
+        let pages = state.page_count();
+        context
+            .conversations
+            .set(
+                state_key(&room_id, &context.sender, &event_id),
+                serde_json::to_value(&state)?,
+                Some(context.config.pager_ttl),
+            )
+            .await;
+        info!("📄 Sent page 1/{} to {}", pages, context.sender);
+    }
+    Ok(event_id)
+}
+
+/// Turn the pages of `target` if `reaction` is ▶️ or ◀️ from the user they were sent to
+///
+/// Returns whether the reaction was for a pager, so it isn't also taken as
+/// feedback.
+pub async fn on_reaction(
+    room: &dyn RoomHandle,
+    catalog: &Catalog,
+    conversations: &ConversationStore,
+    sent_events: &SentEventRegistry,
+    sender: &str,
+    target: &EventId,
+    reaction: &str,
+) -> Result<bool> {
+    let Some(turn) = Turn::from_reaction(reaction) else {
+        return Ok(false);
+    };
+    // Only messages the bot sent (and nobody redacted) have pages
+    if sent_events.lookup(target).await.is_none() {
+        return Ok(false);
+    }
+
+    let mut moved = None;
+    let updated = conversations
+        .update(
+            &state_key(room.room_id().as_str(), sender, target),
+            |value| {
+                let Ok(mut state) = serde_json::from_value::<PagerState>(value.clone()) else {
+                    return;
+                };
+                if state.turn(turn) {
+                    moved = Some(state.render(catalog));
+                    if let Ok(turned) = serde_json::to_value(&state) {
+                        *value = turned;
+                    }
+                }
+            },
+        )
+        .await;
+    if updated.is_none() {
+        return Ok(false);
+    }
+
+    if let Some(page) = moved {
+        if let Err(e) = room.edit_markdown(target, &page).await {
+            warn!("Failed to show another page of {}: {:#}", target, e);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,267 @@
+//! Bans shared between bots through a policy room
+//!
+//! With `POLICY_ROOM` set the bot joins that room and reads its
+//! `m.policy.rule.user` and `m.policy.rule.room` state events, the format of
+//! Mjolnir-style policy lists. Rules recommending `m.ban` deny matching users
+//! and rooms on top of the local lists; entities are globs (`*` and `?`).
+//! Changed or removed rules apply as soon as their state event syncs.
+//! Malformed rules are skipped with a warning.
+
+use anyhow::{bail, Context, Result};
+use matrix_sdk::{
+    deserialized_responses::RawAnySyncOrStrippedState,
+    ruma::{events::StateEventType, OwnedRoomOrAliasId},
+    Client, RoomState,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::alias::Aliases;
+
+pub const USER_RULE: &str = "m.policy.rule.user";
+pub const ROOM_RULE: &str = "m.policy.rule.room";
+
+/// Recommendations that ban the entity (Mjolnir used the second before `m.ban`)
+const BAN_RECOMMENDATIONS: &[&str] = &["m.ban", "org.matrix.mjolnir.ban"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuleKind {
+    User,
+    Room,
+}
+
+impl RuleKind {
+    fn of_event_type(event_type: &str) -> Option<Self> {
+        match event_type {
+            USER_RULE => Some(RuleKind::User),
+            ROOM_RULE => Some(RuleKind::Room),
+            _ => None,
+        }
+    }
+}
+
+/// A ban from the policy room
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyRule {
+    pub kind: RuleKind,
+    /// Glob of user or room IDs
+    pub entity: String,
+    pub reason: Option<String>,
+}
+
+impl PolicyRule {
+    pub fn matches(&self, id: &str) -> bool {
+        glob_match(&self.entity, id)
+    }
+}
+
+/// What a policy state event does to the rule under its state key
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleUpdate {
+    Ban(PolicyRule),
+    /// Emptied content or a recommendation other than a ban
+    Remove,
+}
+
+/// Parse a policy rule state event
+///
+/// Returns None for other event types and an error for malformed rules.
+pub fn parse_event(event: &Value) -> Result<Option<(RuleKind, String, RuleUpdate)>> {
+    let Some(kind) = event
+        .get("type")
+        .and_then(Value::as_str)
+        .and_then(RuleKind::of_event_type)
+    else {
+        return Ok(None);
+    };
+    let state_key = event
+        .get("state_key")
+        .and_then(Value::as_str)
+        .context("Policy rule has no state key")?
+        .to_string();
+    let content = event
+        .get("content")
+        .and_then(Value::as_object)
+        .context("Policy rule content is not an object")?;
+    if content.is_empty() {
+        return Ok(Some((kind, state_key, RuleUpdate::Remove)));
+    }
+
+    let entity = match content.get("entity") {
+        Some(Value::String(entity)) if !entity.trim().is_empty() => entity.trim().to_string(),
+        Some(_) => bail!("Policy rule {} has an invalid entity", state_key),
+        None => bail!("Policy rule {} has no entity", state_key),
+    };
+    let recommendation = content
+        .get("recommendation")
+        .and_then(Value::as_str)
+        .with_context(|| format!("Policy rule {} has no recommendation", state_key))?;
+    if !BAN_RECOMMENDATIONS.contains(&recommendation) {
+        return Ok(Some((kind, state_key, RuleUpdate::Remove)));
+    }
+    let reason = content
+        .get("reason")
+        .and_then(Value::as_str)
+        .filter(|reason| !reason.is_empty())
+        .map(str::to_string);
+
+    Ok(Some((
+        kind,
+        state_key,
+        RuleUpdate::Ban(PolicyRule {
+            kind,
+            entity,
+            reason,
+        }),
+    )))
+}
+
+/// Whether `text` matches `pattern`, where `*` is any run and `?` one character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character
+                Some((after_star, tried)) => {
+                    p = after_star;
+                    t = tried + 1;
+                    backtrack = Some((after_star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The bans of the policy room, kept current from its state events
+pub struct PolicyList {
+    /// Configured room ID or alias
+    room: String,
+    /// Resolved room ID, set once joined
+    room_id: RwLock<Option<String>>,
+    /// (kind, state key) -> rule
+    rules: RwLock<HashMap<(RuleKind, String), PolicyRule>>,
+}
+
+impl PolicyList {
+    pub fn new(room: &str) -> Self {
+        Self {
+            room: room.to_string(),
+            room_id: RwLock::new(None),
+            rules: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Whether events of `room_id` update this list
+    pub fn is_policy_room(&self, room_id: &str) -> bool {
+        self.room_id.read().unwrap().as_deref() == Some(room_id)
+    }
+
+    /// Join the policy room if needed and load its current rules
+    pub async fn join_and_load(&self, client: &Client, aliases: &Aliases) -> Result<()> {
+        let id_or_alias = OwnedRoomOrAliasId::try_from(self.room.as_str())
+            .with_context(|| format!("POLICY_ROOM {} is not a room ID or alias", self.room))?;
+        let joined = aliases
+            .resolver(client)
+            .resolve_room(&self.room)
+            .await
+            .ok()
+            .and_then(|room_id| client.get_room(&room_id))
+            .filter(|room| room.state() == RoomState::Joined);
+        let room = match joined {
+            Some(room) => room,
+            None => client
+                .join_room_by_id_or_alias(&id_or_alias, &[])
+                .await
+                .with_context(|| format!("Failed to join the policy room {}", self.room))?,
+        };
+        *self.room_id.write().unwrap() = Some(room.room_id().to_string());
+
+        let mut rules = HashMap::new();
+        for event_type in [USER_RULE, ROOM_RULE] {
+            let events = room
+                .get_state_events(StateEventType::from(event_type))
+                .await
+                .context("Failed to read the policy room state")?;
+            for raw in events {
+                let json = match &raw {
+                    RawAnySyncOrStrippedState::Sync(raw) => raw.json().get(),
+                    RawAnySyncOrStrippedState::Stripped(raw) => raw.json().get(),
+                };
+                let Ok(event) = serde_json::from_str::<Value>(json) else {
+                    warn!("🛡️ Skipping a policy rule that is not valid JSON");
+                    continue;
+                };
+                match parse_event(&event) {
+                    Ok(Some((kind, key, RuleUpdate::Ban(rule)))) => {
+                        rules.insert((kind, key), rule);
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("🛡️ Skipping a malformed policy rule: {:#}", e),
+                }
+            }
+        }
+        info!(
+            "🛡️ Loaded {} ban rules from the policy room {}",
+            rules.len(),
+            room.room_id()
+        );
+        *self.rules.write().unwrap() = rules;
+        Ok(())
+    }
+
+    /// Apply a state event from the policy room
+    pub fn on_state_event(&self, event: &Value) {
+        let (kind, key, update) = match parse_event(event) {
+            Ok(Some(parsed)) => parsed,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("🛡️ Ignoring a malformed policy rule: {:#}", e);
+                return;
+            }
+        };
+        let mut rules = self.rules.write().unwrap();
+        match update {
+            RuleUpdate::Ban(rule) => {
+                info!("🛡️ Policy ban on {:?} {}", kind, rule.entity);
+                rules.insert((kind, key), rule);
+            }
+            RuleUpdate::Remove => {
+                if let Some(rule) = rules.remove(&(kind, key)) {
+                    info!("🛡️ Policy ban on {:?} {} lifted", kind, rule.entity);
+                }
+            }
+        }
+    }
+
+    /// The rule banning `id`, if any
+    pub fn ban(&self, kind: RuleKind, id: &str) -> Option<PolicyRule> {
+        self.rules
+            .read()
+            .unwrap()
+            .values()
+            .find(|rule| rule.kind == kind && rule.matches(id))
+            .cloned()
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.read().unwrap().len()
+    }
+}
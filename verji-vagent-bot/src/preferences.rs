@@ -0,0 +1,329 @@
+//! Per-user preferences (`!prefs`)
+//!
+//! A user has one set of preferences for all rooms and, optionally, one per
+//! room that overrides it key by key. They are kept in the bot database and
+//! all loaded into memory when the bot starts; changes are written to both, so
+//! reading a user's preferences for every message never touches the store.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::db;
+use crate::i18n::Catalog;
+
+/// `room_id` of the preferences that apply in every room
+const ALL_ROOMS: &str = "";
+
+/// A preference users can set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefKey {
+    /// Start answers in group rooms with the user's name
+    Address,
+    /// Language of the bot's own messages
+    Language,
+    /// "Still working" notices on slow requests
+    DelayNotices,
+    /// Default format of `!export`
+    TranscriptFormat,
+}
+
+impl PrefKey {
+    pub const ALL: [PrefKey; 4] = [
+        PrefKey::Address,
+        PrefKey::Language,
+        PrefKey::DelayNotices,
+        PrefKey::TranscriptFormat,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|key| key.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrefKey::Address => "address",
+            PrefKey::Language => "language",
+            PrefKey::DelayNotices => "delay_notices",
+            PrefKey::TranscriptFormat => "transcript_format",
+        }
+    }
+
+    /// Values the key accepts, for usage messages
+    pub fn accepted(&self, catalog: &Catalog) -> String {
+        match self {
+            PrefKey::Address | PrefKey::DelayNotices => "on|off".to_string(),
+            PrefKey::Language => catalog
+                .languages()
+                .into_iter()
+                .collect::<Vec<_>>()
+                .join("|"),
+            PrefKey::TranscriptFormat => "md|txt".to_string(),
+        }
+    }
+
+    /// Comma-separated names of all keys
+    pub fn names() -> String {
+        Self::ALL
+            .iter()
+            .map(|key| key.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Preferences of one user, for all rooms or one room; `None` = not set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserPreferences {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_notices: Option<bool>,
+    /// `md` or `txt`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript_format: Option<String>,
+}
+
+impl UserPreferences {
+    /// Whether answers in group rooms may start with the user's name
+    pub fn address_by_name(&self) -> bool {
+        self.address.unwrap_or(true)
+    }
+
+    /// Whether slow requests get a "still working" notice
+    pub fn delay_notices(&self) -> bool {
+        self.delay_notices.unwrap_or(true)
+    }
+
+    /// These preferences with every key set in `overrides` replaced
+    pub fn overlay(mut self, overrides: &UserPreferences) -> Self {
+        if overrides.address.is_some() {
+            self.address = overrides.address;
+        }
+        if overrides.language.is_some() {
+            self.language = overrides.language.clone();
+        }
+        if overrides.delay_notices.is_some() {
+            self.delay_notices = overrides.delay_notices;
+        }
+        if overrides.transcript_format.is_some() {
+            self.transcript_format = overrides.transcript_format.clone();
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == UserPreferences::default()
+    }
+
+    /// The value of `key` as shown to users, `None` if unset
+    pub fn get(&self, key: PrefKey) -> Option<String> {
+        let on_off = |value: bool| if value { "on" } else { "off" }.to_string();
+        match key {
+            PrefKey::Address => self.address.map(on_off),
+            PrefKey::Language => self.language.clone(),
+            PrefKey::DelayNotices => self.delay_notices.map(on_off),
+            PrefKey::TranscriptFormat => self.transcript_format.clone(),
+        }
+    }
+
+    /// Set `key` from user input; returns `false` if the value isn't valid
+    ///
+    /// Languages are valid if `catalog` has messages in them.
+    pub fn set(&mut self, key: PrefKey, value: &str, catalog: &Catalog) -> bool {
+        let value = value.trim().to_lowercase();
+        match key {
+            PrefKey::Address | PrefKey::DelayNotices => {
+                let Some(on) = parse_on_off(&value) else {
+                    return false;
+                };
+                if key == PrefKey::Address {
+                    self.address = Some(on);
+                } else {
+                    self.delay_notices = Some(on);
+                }
+            }
+            PrefKey::Language => {
+                if !catalog.languages().contains(&value) {
+                    return false;
+                }
+                self.language = Some(value);
+            }
+            PrefKey::TranscriptFormat => {
+                let format = match value.as_str() {
+                    "md" | "markdown" => "md",
+                    "txt" | "text" | "plain" => "txt",
+                    _ => return false,
+                };
+                self.transcript_format = Some(format.to_string());
+            }
+        }
+        true
+    }
+
+    pub fn unset(&mut self, key: PrefKey) {
+        match key {
+            PrefKey::Address => self.address = None,
+            PrefKey::Language => self.language = None,
+            PrefKey::DelayNotices => self.delay_notices = None,
+            PrefKey::TranscriptFormat => self.transcript_format = None,
+        }
+    }
+}
+
+fn parse_on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" | "yes" => Some(true),
+        "off" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// All users' preferences, cached in memory with write-through to the bot database
+pub struct PreferenceStore {
+    db_path: PathBuf,
+    /// (user ID, room ID or `ALL_ROOMS`) -> preferences
+    cache: Mutex<HashMap<(String, String), UserPreferences>>,
+}
+
+impl PreferenceStore {
+    pub fn open(store_path: &Path) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS user_preferences (
+                user_id TEXT NOT NULL,
+                room_id TEXT NOT NULL,
+                prefs TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (user_id, room_id)
+            );",
+        )
+        .context("Failed to create user_preferences table")?;
+
+        let mut cache = HashMap::new();
+        let mut stmt = conn.prepare("SELECT user_id, room_id, prefs FROM user_preferences")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (user_id, room_id, prefs) = row?;
+            match serde_json::from_str(&prefs) {
+                Ok(prefs) => {
+                    cache.insert((user_id, room_id), prefs);
+                }
+                Err(e) => warn!("Ignoring invalid preferences of {}: {}", user_id, e),
+            }
+        }
+        info!("🙋 Loaded {} preference sets", cache.len());
+
+        Ok(Self {
+            db_path,
+            cache: Mutex::new(cache),
+        })
+    }
+
+    /// What applies to `user_id` in `room_id`: room preferences over global ones
+    pub fn effective(&self, user_id: &str, room_id: &str) -> UserPreferences {
+        let cache = self.cache.lock().unwrap();
+        let global = cache
+            .get(&(user_id.to_string(), ALL_ROOMS.to_string()))
+            .cloned()
+            .unwrap_or_default();
+        match cache.get(&(user_id.to_string(), room_id.to_string())) {
+            Some(room) => global.overlay(room),
+            None => global,
+        }
+    }
+
+    /// Preferences set for one room (`Some`) or for all rooms (`None`)
+    pub fn scoped(&self, user_id: &str, room_id: Option<&str>) -> UserPreferences {
+        self.cache
+            .lock()
+            .unwrap()
+            .get(&(
+                user_id.to_string(),
+                room_id.unwrap_or(ALL_ROOMS).to_string(),
+            ))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Change and save the preferences of `user_id` for one room or all rooms
+    pub async fn update(
+        &self,
+        user_id: &str,
+        room_id: Option<&str>,
+        change: impl FnOnce(&mut UserPreferences),
+    ) -> Result<UserPreferences> {
+        let key = (
+            user_id.to_string(),
+            room_id.unwrap_or(ALL_ROOMS).to_string(),
+        );
+        let mut prefs = self.scoped(user_id, room_id);
+        change(&mut prefs);
+
+        let db_path = self.db_path.clone();
+        let (user, room) = key.clone();
+        let row = (!prefs.is_empty())
+            .then(|| serde_json::to_string(&prefs))
+            .transpose()?;
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = db::open(&db_path)?;
+            match row {
+                Some(json) => conn.execute(
+                    "INSERT OR REPLACE INTO user_preferences (user_id, room_id, prefs, updated_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![user, room, json, db::now_secs() as i64],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM user_preferences WHERE user_id = ?1 AND room_id = ?2",
+                    rusqlite::params![user, room],
+                )?,
+            };
+            Ok(())
+        })
+        .await
+        .context("Preference write panicked")??;
+
+        let mut cache = self.cache.lock().unwrap();
+        if prefs.is_empty() {
+            cache.remove(&key);
+        } else {
+            cache.insert(key, prefs.clone());
+        }
+        Ok(prefs)
+    }
+
+    /// Delete (or with `dry_run` only count) the user's preference sets
+    pub async fn erase_user(&self, user_id: &str, dry_run: bool) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        let user = user_id.to_string();
+        let count = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = db::open(&db_path)?;
+            db::erase_rows(&conn, "user_preferences", "user_id = ?1", [&user], dry_run)
+        })
+        .await
+        .context("Preference erasure panicked")??;
+
+        if !dry_run {
+            self.cache
+                .lock()
+                .unwrap()
+                .retain(|(user, _), _| user != user_id);
+        }
+        Ok(count)
+    }
+}
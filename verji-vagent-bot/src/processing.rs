@@ -0,0 +1,213 @@
+//! Ordered processing of incoming messages per conversation lane
+//!
+//! A lane is a thread of a room, or the room's main timeline. Messages in one
+//! lane are handled one at a time in arrival order, so a follow-up never
+//! overtakes the question it follows; different lanes, including different
+//! threads of one room, are handled concurrently. With `TYPING_INDICATOR=true`
+//! the bot shows typing in a room while any of its lanes is busy and only
+//! stops once the last request in the room has finished.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tracing::debug;
+
+use crate::metrics::Metrics;
+use crate::room::RoomHandle;
+
+type LaneKey = (String, Option<String>);
+
+#[derive(Default)]
+struct Lane {
+    /// Held by the message being handled
+    busy: Arc<AsyncMutex<()>>,
+    /// Messages waiting or being handled
+    depth: AtomicUsize,
+}
+
+/// Counts a message in its lane's depth until dropped, even if it's dropped
+/// while still waiting; the last one out removes the lane
+struct Queued {
+    lanes: Arc<ProcessingLanes>,
+    key: LaneKey,
+    lane: Arc<Lane>,
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        // Under the map lock, so no message joins the lane as it goes
+        let mut lanes = self.lanes.lanes.lock().unwrap();
+        if self.lane.depth.fetch_sub(1, Ordering::SeqCst) == 1
+            && lanes
+                .get(&self.key)
+                .is_some_and(|lane| Arc::ptr_eq(lane, &self.lane))
+        {
+            lanes.remove(&self.key);
+        }
+    }
+}
+
+/// The conversation lanes of a bot's rooms
+pub struct ProcessingLanes {
+    /// (room ID, thread root) -> lane; None is the main timeline
+    lanes: Mutex<HashMap<LaneKey, Arc<Lane>>>,
+    /// Room ID -> requests being handled in any of its lanes; typing changes
+    /// are made under the lock so they can't overtake each other
+    active: Mutex<HashMap<String, Arc<AsyncMutex<usize>>>>,
+    metrics: Arc<Metrics>,
+}
+
+/// The lane's turn to handle a message; the next one waits until it finishes
+pub struct Turn {
+    room: Arc<dyn RoomHandle>,
+    lanes: Arc<ProcessingLanes>,
+    _queued: Queued,
+    _busy: OwnedMutexGuard<()>,
+    typing: bool,
+    /// Counted in the room's active requests and not yet uncounted
+    counted: bool,
+}
+
+impl ProcessingLanes {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            lanes: Mutex::default(),
+            active: Mutex::default(),
+            metrics,
+        }
+    }
+
+    /// Wait for the turn to handle a message in a thread of a room (None: its
+    /// main timeline); with `typing`, the room shows the bot typing from now on
+    pub async fn turn(
+        self: &Arc<Self>,
+        room: &Arc<dyn RoomHandle>,
+        thread_id: Option<&str>,
+        typing: bool,
+    ) -> Turn {
+        let room_id = room.room_id().to_string();
+        let key = (room_id.clone(), thread_id.map(str::to_string));
+        let (lane, ahead) = {
+            let mut lanes = self.lanes.lock().unwrap();
+            let lane = Arc::clone(lanes.entry(key.clone()).or_default());
+            let ahead = lane.depth.fetch_add(1, Ordering::SeqCst);
+            (lane, ahead)
+        };
+        let queued = Queued {
+            lanes: Arc::clone(self),
+            key,
+            lane: Arc::clone(&lane),
+        };
+        if ahead > 0 {
+            debug!(
+                "📥 Message in {} (thread {}) waits behind {} other(s)",
+                room_id,
+                thread_id.unwrap_or("main"),
+                ahead
+            );
+        }
+
+        let started = Instant::now();
+        let busy = Arc::clone(&lane.busy).lock_owned().await;
+        self.metrics.observe_ms(
+            "processing_queue_wait_ms",
+            &[],
+            started.elapsed().as_millis() as u64,
+        );
+
+        let mut turn = Turn {
+            room: Arc::clone(room),
+            lanes: Arc::clone(self),
+            _queued: queued,
+            _busy: busy,
+            typing,
+            counted: false,
+        };
+        let activity = self.activity(&room_id);
+        let mut active = activity.lock().await;
+        *active += 1;
+        turn.counted = true;
+        if *active == 1 && typing {
+            set_typing(room.as_ref(), true).await;
+        }
+        drop(active);
+        turn
+    }
+
+    /// Messages of a room waiting or being handled, threads included
+    pub fn room_depth(&self, room_id: &str) -> usize {
+        self.lanes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((room, _), _)| room == room_id)
+            .map(|(_, lane)| lane.depth.load(Ordering::SeqCst))
+            .sum()
+    }
+
+    /// Lanes of a room with messages waiting or being handled; idle lanes are
+    /// removed as their last message finishes
+    pub fn lane_count(&self, room_id: &str) -> usize {
+        self.lanes
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(room, _)| room == room_id)
+            .count()
+    }
+
+    /// Forget the typing state of a room the bot left, unless it's still busy
+    pub fn remove_room(&self, room_id: &str) {
+        if self.room_depth(room_id) == 0 {
+            self.active.lock().unwrap().remove(room_id);
+        }
+    }
+
+    fn activity(&self, room_id: &str) -> Arc<AsyncMutex<usize>> {
+        Arc::clone(
+            self.active
+                .lock()
+                .unwrap()
+                .entry(room_id.to_string())
+                .or_default(),
+        )
+    }
+}
+
+impl Turn {
+    /// Let the lane's next message go; typing stops if the room is idle now
+    pub async fn finish(mut self) {
+        self.counted = false;
+        end(Arc::clone(&self.lanes), Arc::clone(&self.room), self.typing).await;
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        // Abandoned without `finish` (e.g. the dispatch was dropped)
+        if self.counted {
+            tokio::spawn(end(
+                Arc::clone(&self.lanes),
+                Arc::clone(&self.room),
+                self.typing,
+            ));
+        }
+    }
+}
+
+async fn end(lanes: Arc<ProcessingLanes>, room: Arc<dyn RoomHandle>, typing: bool) {
+    let activity = lanes.activity(room.room_id().as_str());
+    let mut active = activity.lock().await;
+    *active = active.saturating_sub(1);
+    if *active == 0 && typing {
+        set_typing(room.as_ref(), false).await;
+    }
+}
+
+async fn set_typing(room: &dyn RoomHandle, typing: bool) {
+    if let Err(e) = room.typing(typing).await {
+        debug!("Failed to set typing in {}: {:#}", room.room_id(), e);
+    }
+}
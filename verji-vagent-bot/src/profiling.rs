@@ -0,0 +1,79 @@
+//! Per-stage timing of agent requests (`PROFILE_PIPELINE=true`)
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, Span};
+
+use crate::metrics::Metrics;
+use crate::progress::format_duration;
+
+/// Records how long each stage of a request took
+///
+/// Each mark measures the time since the previous one. When disabled every
+/// call returns immediately, so call sites don't need to check the flag.
+pub struct StageTimer {
+    enabled: bool,
+    last: Instant,
+    stages: Vec<(&'static str, Duration)>,
+    /// Span whose fields (named after the stages) receive the durations in ms
+    span: Span,
+    metrics: Arc<Metrics>,
+}
+
+impl StageTimer {
+    pub fn new(enabled: bool, span: Span, metrics: Arc<Metrics>) -> Self {
+        Self {
+            enabled,
+            last: Instant::now(),
+            stages: Vec::new(),
+            span,
+            metrics,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// End `stage` now
+    pub fn mark(&mut self, stage: &'static str) {
+        if self.enabled {
+            self.mark_at(stage, Instant::now());
+        }
+    }
+
+    /// End `stage` at an instant captured elsewhere (e.g. in a callback)
+    pub fn mark_at(&mut self, stage: &'static str, at: Instant) {
+        if !self.enabled {
+            return;
+        }
+        let duration = at.saturating_duration_since(self.last);
+        self.last = at;
+        self.stages.push((stage, duration));
+        self.span.record(stage, duration.as_millis() as u64);
+        self.metrics.observe_ms(
+            "pipeline_stage_ms",
+            &[("stage", stage)],
+            duration.as_millis() as u64,
+        );
+    }
+
+    /// Compact one-line summary ("context 12ms · publish 3ms · final 4.2s")
+    pub fn summary(&self) -> String {
+        self.stages
+            .iter()
+            .map(|(stage, duration)| format!("{} {}", stage, format_duration(*duration)))
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+
+    /// Log the stages, returning the summary if anything was measured
+    pub fn finish(&self) -> Option<String> {
+        if !self.enabled || self.stages.is_empty() {
+            return None;
+        }
+        let summary = self.summary();
+        info!(parent: &self.span, "⏱️  Pipeline timing: {}", summary);
+        Some(summary)
+    }
+}
@@ -0,0 +1,191 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::redis_client::GraphMessage;
+
+/// Longest argument dump shown per step in the details section
+const MAX_ARGS_CHARS: usize = 300;
+/// Steps listed in the progress message; earlier ones are folded into a count
+const MAX_SHOWN_STEPS: usize = 15;
+
+/// A structured progress update from vagent-graph
+///
+/// Sent as the metadata of a progress message: `{"tool", "step", "total",
+/// "summary", "duration_ms", "args"}`; only `summary` is required. Progress
+/// without it is shown as the raw message text.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct ProgressStep {
+    #[serde(default)]
+    pub tool: Option<String>,
+    /// 1-based position of the step
+    #[serde(default)]
+    pub step: Option<u32>,
+    #[serde(default)]
+    pub total: Option<u32>,
+    /// Human-readable description of what the step does
+    pub summary: String,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub args: Option<Value>,
+}
+
+impl ProgressStep {
+    /// Structured step from progress metadata, if it has one
+    pub fn from_metadata(metadata: Option<&Value>) -> Option<Self> {
+        serde_json::from_value(metadata?.clone()).ok()
+    }
+
+    /// Compact single-line rendering
+    fn render_line(&self, done: bool) -> String {
+        let mut line = String::from(if done { "✅ " } else { "⏳ " });
+        match (self.step, self.total) {
+            (Some(step), Some(total)) => line.push_str(&format!("**{}/{}** ", step, total)),
+            (Some(step), None) => line.push_str(&format!("**{}** ", step)),
+            _ => {}
+        }
+        if let Some(tool) = &self.tool {
+            line.push_str(&format!("`{}` — ", tool));
+        }
+        line.push_str(&self.summary);
+        if let Some(ms) = self.duration_ms {
+            line.push_str(&format!(
+                " ({})",
+                format_duration(Duration::from_millis(ms))
+            ));
+        }
+        line
+    }
+}
+
+/// Progress message for the steps reported so far, edited in place as steps arrive
+///
+/// Every step but the last is shown as done. Tool arguments go into one
+/// collapsed details section so the step list stays compact.
+pub fn render_steps(steps: &[ProgressStep]) -> String {
+    let hidden = steps.len().saturating_sub(MAX_SHOWN_STEPS);
+    let steps = &steps[hidden..];
+    let last = steps.len().saturating_sub(1);
+    let mut out: Vec<String> = Vec::new();
+    if hidden > 0 {
+        out.push(format!("✅ … {} earlier steps", hidden));
+    }
+    out.extend(
+        steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| step.render_line(index < last)),
+    );
+
+    let args: Vec<String> = steps
+        .iter()
+        .filter_map(|step| {
+            let args = step.args.as_ref()?;
+            let label = step.tool.as_deref().unwrap_or(&step.summary);
+            Some(format!(
+                "`{}`: `{}`",
+                label,
+                truncate(&args.to_string(), MAX_ARGS_CHARS)
+            ))
+        })
+        .collect();
+    if !args.is_empty() {
+        out.push(String::new());
+        out.push("<details><summary>Arguments</summary>".to_string());
+        out.push(String::new());
+        out.extend(args.into_iter().map(|line| format!("- {}", line)));
+        out.push(String::new());
+        out.push("</details>".to_string());
+    }
+
+    out.join("\n")
+}
+
+/// What the room should currently see of a request's progress
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressUpdate {
+    /// Raw progress text, sent as a message of its own
+    Text(String),
+    /// The rendered step list, edited into a single message
+    Steps(String),
+}
+
+/// Collects progress from the graph and keeps only the newest unsent update
+///
+/// Fed from the request's [`AgentEvent::Progress`] events; the task relaying
+/// updates to Matrix reads the receiver returned by `new`.
+///
+/// When sending falls behind, older pending updates are overwritten instead
+/// of queued, so a graph emitting hundreds of events costs at most one
+/// pending message. Steps are accumulated here, so the step list itself
+/// never loses entries. The receiver ends once the feed is dropped.
+///
+/// [`AgentEvent::Progress`]: crate::agent_service::AgentEvent::Progress
+pub struct ProgressFeed {
+    steps: Mutex<Vec<ProgressStep>>,
+    latest: watch::Sender<Option<ProgressUpdate>>,
+    received: AtomicUsize,
+}
+
+impl ProgressFeed {
+    pub fn new() -> (Arc<Self>, watch::Receiver<Option<ProgressUpdate>>) {
+        let (latest, updates) = watch::channel(None);
+        let feed = Self {
+            steps: Mutex::new(Vec::new()),
+            latest,
+            received: AtomicUsize::new(0),
+        };
+        (Arc::new(feed), updates)
+    }
+
+    /// Record a progress message from vagent-graph
+    pub fn push(&self, message: &GraphMessage) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        let update = match ProgressStep::from_metadata(message.metadata.as_ref()) {
+            Some(step) => {
+                let mut steps = self.steps.lock().unwrap();
+                steps.push(step);
+                ProgressUpdate::Steps(render_steps(&steps))
+            }
+            None => ProgressUpdate::Text(message.content.clone()),
+        };
+        self.latest.send_replace(Some(update));
+    }
+
+    /// Structured steps reported so far
+    pub fn step_count(&self) -> usize {
+        self.steps.lock().unwrap().len()
+    }
+
+    /// Progress messages received so far
+    pub fn received(&self) -> usize {
+        self.received.load(Ordering::Relaxed)
+    }
+}
+
+/// Short human-readable duration ("850ms", "4.2s", "2m 05s")
+pub fn format_duration(duration: Duration) -> String {
+    let ms = duration.as_millis();
+    if ms < 1_000 {
+        format!("{}ms", ms)
+    } else if ms < 60_000 {
+        format!("{:.1}s", ms as f64 / 1_000.0)
+    } else {
+        let secs = duration.as_secs();
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Cut text to at most `max` characters, marking the cut; backticks are
+/// replaced so the text can't break out of inline code
+fn truncate(text: &str, max: usize) -> String {
+    let text = text.replace('`', "'");
+    match text.char_indices().nth(max) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text,
+    }
+}
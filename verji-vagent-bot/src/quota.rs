@@ -0,0 +1,428 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, TimeZone};
+use chrono_tz::Tz;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config;
+use crate::db;
+use crate::responder::ResponderContext;
+use crate::stats::RoomSpend;
+
+/// What a quota is counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaScope {
+    User,
+    Room,
+    UserRoom,
+}
+
+impl QuotaScope {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "user" => Some(QuotaScope::User),
+            "room" => Some(QuotaScope::Room),
+            "user+room" | "user_room" => Some(QuotaScope::UserRoom),
+            _ => None,
+        }
+    }
+
+    /// Key identifying the bucket the message is counted in
+    pub fn key(&self, context: &ResponderContext) -> String {
+        match self {
+            QuotaScope::User => context.identity.to_string(),
+            QuotaScope::Room => context.room.room_id().to_string(),
+            QuotaScope::UserRoom => format!("{}|{}", context.room.room_id(), context.identity),
+        }
+    }
+}
+
+/// At most `limit` uses per `window` and bucket
+#[derive(Debug, Clone)]
+pub struct Quota {
+    pub limit: u32,
+    pub window: Duration,
+    pub scope: QuotaScope,
+}
+
+impl Quota {
+    /// Parse `limit/window_secs/scope`, e.g. `10/3600/user`
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split('/');
+        let limit = parts.next()?.trim().parse().ok()?;
+        let window_secs: u64 = parts.next()?.trim().parse().ok()?;
+        let scope = QuotaScope::parse(parts.next().unwrap_or("user"))?;
+        (window_secs > 0).then_some(Self {
+            limit,
+            window: Duration::from_secs(window_secs),
+            scope,
+        })
+    }
+}
+
+/// Agent queries allowed per user and calendar day
+///
+/// Limits resolve as user override (`!admin quota set`), then the room's limit
+/// (`DAILY_QUOTA_ROOMS`), then `DAILY_QUOTA`; 0 means unlimited at every level.
+#[derive(Debug, Clone)]
+pub struct DailyQuotaPolicy {
+    pub default_limit: u32,
+    /// Room ID -> limit for queries sent in that room
+    pub room_limits: HashMap<String, u32>,
+    /// Zone whose midnight starts a new day
+    pub timezone: Tz,
+}
+
+/// `QUOTA_TIMEZONE`, or UTC
+fn quota_timezone() -> Tz {
+    std::env::var("QUOTA_TIMEZONE")
+        .ok()
+        .filter(|zone| !zone.trim().is_empty())
+        .and_then(|zone| match zone.trim().parse() {
+            Ok(zone) => Some(zone),
+            Err(_) => {
+                warn!("Unknown QUOTA_TIMEZONE {:?}, using UTC", zone);
+                None
+            }
+        })
+        .unwrap_or(Tz::UTC)
+}
+
+fn local_time(timezone: Tz, now_ms: u64) -> DateTime<Tz> {
+    DateTime::from_timestamp_millis(now_ms as i64)
+        .unwrap_or_default()
+        .with_timezone(&timezone)
+}
+
+impl DailyQuotaPolicy {
+    pub fn from_env() -> Self {
+        let timezone = quota_timezone();
+        Self {
+            default_limit: config::env_u64("DAILY_QUOTA", 0) as u32,
+            room_limits: config::env_map("DAILY_QUOTA_ROOMS")
+                .into_iter()
+                .filter_map(|(room, limit)| Some((room, limit.parse().ok()?)))
+                .collect(),
+            timezone,
+        }
+    }
+
+    /// The sender's daily limit in a room (None = unlimited)
+    pub fn limit(&self, room_id: &str, user_override: Option<u32>) -> Option<u32> {
+        let limit = user_override
+            .or_else(|| self.room_limits.get(room_id).copied())
+            .unwrap_or(self.default_limit);
+        (limit > 0).then_some(limit)
+    }
+
+    /// Day the counters are kept under, as `YYYY-MM-DD` in the quota timezone
+    pub fn day(&self, now_ms: u64) -> String {
+        self.local(now_ms).format("%Y-%m-%d").to_string()
+    }
+
+    /// Next midnight in the quota timezone
+    pub fn next_reset(&self, now_ms: u64) -> DateTime<Tz> {
+        let now = self.local(now_ms);
+        now.date_naive()
+            .succ_opt()
+            .and_then(|tomorrow| tomorrow.and_hms_opt(0, 0, 0))
+            .and_then(|midnight| self.timezone.from_local_datetime(&midnight).earliest())
+            // Midnight skipped by a DST change
+            .unwrap_or_else(|| now + chrono::Duration::days(1))
+    }
+
+    fn local(&self, now_ms: u64) -> DateTime<Tz> {
+        local_time(self.timezone, now_ms)
+    }
+}
+
+/// What a room may spend on agent answers per month, in the graph's cost units
+///
+/// The room's own budget (`!admin budget`) comes before `MONTHLY_BUDGET`; 0
+/// means unlimited at both levels. Months start at midnight in
+/// `QUOTA_TIMEZONE` on `BUDGET_RESET_DAY` (1-28).
+#[derive(Debug, Clone)]
+pub struct RoomBudgetPolicy {
+    pub default_limit: f64,
+    pub reset_day: u32,
+    pub timezone: Tz,
+}
+
+impl RoomBudgetPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            default_limit: std::env::var("MONTHLY_BUDGET")
+                .ok()
+                .and_then(|limit| limit.trim().parse().ok())
+                .filter(|limit: &f64| limit.is_finite() && *limit >= 0.0)
+                .unwrap_or(0.0),
+            reset_day: (config::env_u64("BUDGET_RESET_DAY", 1) as u32).clamp(1, 28),
+            timezone: quota_timezone(),
+        }
+    }
+
+    /// The room's monthly budget (None = unlimited)
+    pub fn limit(&self, room_override: Option<f64>) -> Option<f64> {
+        let limit = room_override.unwrap_or(self.default_limit);
+        (limit > 0.0).then_some(limit)
+    }
+
+    /// Period spend is kept under, as `YYYY-MM` of the month it started in
+    pub fn period(&self, now_ms: u64) -> String {
+        let (year, month) = self.period_start(now_ms);
+        format!("{:04}-{:02}", year, month)
+    }
+
+    /// When the current period ends
+    pub fn next_reset(&self, now_ms: u64) -> DateTime<Tz> {
+        let (year, month) = self.period_start(now_ms);
+        let (year, month) = if month == 12 {
+            (year + 1, 1)
+        } else {
+            (year, month + 1)
+        };
+        chrono::NaiveDate::from_ymd_opt(year, month, self.reset_day)
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .and_then(|midnight| self.timezone.from_local_datetime(&midnight).earliest())
+            .unwrap_or_else(|| local_time(self.timezone, now_ms) + chrono::Duration::days(31))
+    }
+
+    /// Spend against the limit, for `!stats` and `!admin budget`
+    pub fn status(&self, spent: &RoomSpend, room_override: Option<f64>, now_ms: u64) -> String {
+        let limit = match self.limit(room_override) {
+            Some(limit) => format!("{:.2}", limit),
+            None => "unlimited".to_string(),
+        };
+        format!(
+            "💸 Monthly budget: {:.2} of {} spent ({} tokens), resets {}",
+            spent.cost,
+            limit,
+            spent.tokens,
+            self.next_reset(now_ms).format("%Y-%m-%d")
+        )
+    }
+
+    /// (year, month) the current period started in
+    fn period_start(&self, now_ms: u64) -> (i32, u32) {
+        let today = local_time(self.timezone, now_ms).date_naive();
+        match (today.day() >= self.reset_day, today.month()) {
+            (true, month) => (today.year(), month),
+            (false, 1) => (today.year() - 1, 12),
+            (false, month) => (today.year(), month - 1),
+        }
+    }
+}
+
+/// Outcome of trying to use a quota
+pub enum QuotaDecision {
+    Allowed,
+    /// Quota used up until the given time (unix milliseconds)
+    Exceeded {
+        resets_at_ms: u64,
+    },
+}
+
+/// Fixed window that starts with the first use
+#[derive(Debug, Clone, Copy)]
+struct QuotaWindow {
+    ends_at_ms: u64,
+    used: u32,
+}
+
+/// (responder, bucket key)
+type QuotaKey = (String, String);
+
+/// Quota usage for all rate-limited responders
+///
+/// Like `UsageStats`, counting happens in memory and a background task
+/// flushes changed windows to the bot database, so restarts don't hand out
+/// fresh hourly or daily quotas.
+pub struct QuotaStore {
+    db_path: PathBuf,
+    windows: Mutex<HashMap<QuotaKey, QuotaWindow>>,
+    dirty: Mutex<HashSet<QuotaKey>>,
+}
+
+impl QuotaStore {
+    /// Open the quota table and load windows that have not ended yet
+    pub fn open(store_path: &Path) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS responder_quotas (
+                responder TEXT NOT NULL,
+                bucket TEXT NOT NULL,
+                ends_at_ms INTEGER NOT NULL,
+                used INTEGER NOT NULL,
+                PRIMARY KEY (responder, bucket)
+            );",
+        )
+        .context("Failed to create responder_quotas table")?;
+
+        let now_ms = (db::now_secs() * 1000) as i64;
+        conn.execute(
+            "DELETE FROM responder_quotas WHERE ends_at_ms <= ?1",
+            [now_ms],
+        )?;
+
+        let mut windows = HashMap::new();
+        let mut stmt =
+            conn.prepare("SELECT responder, bucket, ends_at_ms, used FROM responder_quotas")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+                QuotaWindow {
+                    ends_at_ms: row.get::<_, i64>(2)? as u64,
+                    used: row.get::<_, i64>(3)? as u32,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (key, window) = row?;
+            windows.insert(key, window);
+        }
+
+        info!("⏳ Loaded {} active quota windows", windows.len());
+
+        Ok(Self {
+            db_path,
+            windows: Mutex::new(windows),
+            dirty: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Use one unit of the quota if any is left
+    pub fn try_acquire(
+        &self,
+        responder: &str,
+        bucket: &str,
+        quota: &Quota,
+        now_ms: u64,
+    ) -> QuotaDecision {
+        let key = (responder.to_string(), bucket.to_string());
+        {
+            let mut windows = self.windows.lock().unwrap();
+            let window = windows.entry(key.clone()).or_insert(QuotaWindow {
+                ends_at_ms: 0,
+                used: 0,
+            });
+
+            if now_ms >= window.ends_at_ms {
+                *window = QuotaWindow {
+                    ends_at_ms: now_ms + quota.window.as_millis() as u64,
+                    used: 0,
+                };
+            }
+            if window.used >= quota.limit {
+                return QuotaDecision::Exceeded {
+                    resets_at_ms: window.ends_at_ms,
+                };
+            }
+            window.used += 1;
+        }
+        self.dirty.lock().unwrap().insert(key);
+        QuotaDecision::Allowed
+    }
+
+    /// Give back a unit acquired for a message the responder did not handle
+    pub fn release(&self, responder: &str, bucket: &str) {
+        let key = (responder.to_string(), bucket.to_string());
+        if let Some(window) = self.windows.lock().unwrap().get_mut(&key) {
+            window.used = window.used.saturating_sub(1);
+        }
+        self.dirty.lock().unwrap().insert(key);
+    }
+
+    /// Delete a user's quota windows (user and user+room buckets)
+    ///
+    /// Room-wide buckets aren't keyed by the user and stay.
+    pub async fn erase_user(&self, user_id: &str, dry_run: bool) -> Result<usize> {
+        let suffix = format!("|{}", user_id);
+        let owned_by_user = |bucket: &str| bucket == user_id || bucket.ends_with(&suffix);
+        if !dry_run {
+            self.windows
+                .lock()
+                .unwrap()
+                .retain(|(_, bucket), _| !owned_by_user(bucket));
+            self.dirty
+                .lock()
+                .unwrap()
+                .retain(|(_, bucket)| !owned_by_user(bucket));
+        }
+
+        let db_path = self.db_path.clone();
+        let user = user_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = db::open(&db_path)?;
+            db::erase_rows(
+                &conn,
+                "responder_quotas",
+                "bucket = ?1 OR substr(bucket, -length(?2)) = ?2",
+                [&user, &suffix],
+                dry_run,
+            )
+        })
+        .await
+        .context("Quota erasure panicked")?
+    }
+
+    /// Persist windows that changed since the last flush
+    pub async fn flush(&self) -> Result<()> {
+        let rows: Vec<(QuotaKey, QuotaWindow)> = {
+            let dirty: Vec<QuotaKey> = self.dirty.lock().unwrap().drain().collect();
+            if dirty.is_empty() {
+                return Ok(());
+            }
+            let windows = self.windows.lock().unwrap();
+            dirty
+                .into_iter()
+                .filter_map(|key| windows.get(&key).copied().map(|window| (key, window)))
+                .collect()
+        };
+
+        let db_path = self.db_path.clone();
+        let count = rows.len();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = db::open(&db_path)?;
+            let tx = conn.transaction()?;
+            for ((responder, bucket), window) in &rows {
+                tx.execute(
+                    "INSERT OR REPLACE INTO responder_quotas (responder, bucket, ends_at_ms, used)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![
+                        responder,
+                        bucket,
+                        window.ends_at_ms as i64,
+                        window.used as i64
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .context("Quota flush task panicked")??;
+
+        debug!("⏳ Flushed {} quota windows", count);
+        Ok(())
+    }
+
+    /// Spawn the periodic flush task
+    pub fn spawn_flush_task(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = store.flush().await {
+                    warn!("Failed to flush quotas: {}", e);
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,144 @@
+//! Masking of personal data before text leaves the bot
+//!
+//! Matches are replaced with typed placeholders such as `[REDACTED:EMAIL]`,
+//! so the agent still knows something was there.
+
+use regex::{Captures, Regex};
+use tracing::{debug, warn};
+
+/// Built-in rules, applied in this order (before any custom patterns)
+///
+/// Cards and national IDs come before phone numbers so their digits are
+/// never half-matched as one.
+const BUILT_IN: &[(&str, &str, Option<fn(&str) -> bool>)] = &[
+    (
+        "EMAIL",
+        r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+        None,
+    ),
+    ("CARD", r"\b\d(?:[ -]?\d){12,18}\b", Some(luhn_valid)),
+    ("FNR", r"\b\d{6} ?\d{5}\b", Some(fnr_valid)),
+    (
+        "PHONE",
+        r"\+\d[\d -]{6,16}\d|\b\d{2,3}(?:[ -]?\d{2,3}){2,3}\b",
+        Some(phone_valid),
+    ),
+];
+
+/// One kind of personal data and how to find it
+#[derive(Debug)]
+struct Rule {
+    placeholder: String,
+    pattern: Regex,
+    /// Extra check on a match (e.g. a checksum) to avoid false positives
+    validate: Option<fn(&str) -> bool>,
+}
+
+/// Replaces personal data in text with placeholders
+#[derive(Debug)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+
+impl Redactor {
+    /// Built-in rules plus custom `(name, regex)` patterns
+    ///
+    /// Invalid custom patterns are logged and skipped.
+    pub fn new<'a>(custom: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut rules: Vec<Rule> = BUILT_IN
+            .iter()
+            .map(|(name, pattern, validate)| Rule {
+                placeholder: placeholder(name),
+                pattern: Regex::new(pattern).expect("built-in redaction pattern is valid"),
+                validate: *validate,
+            })
+            .collect();
+
+        for (name, pattern) in custom {
+            match Regex::new(pattern) {
+                Ok(pattern) => rules.push(Rule {
+                    placeholder: placeholder(name),
+                    pattern,
+                    validate: None,
+                }),
+                Err(e) => warn!("🔒 Ignoring redaction pattern {}: {}", name, e),
+            }
+        }
+        Self { rules }
+    }
+
+    /// `text` with every match of every rule replaced by its placeholder
+    pub fn redact(&self, text: &str) -> String {
+        let mut redacted = text.to_string();
+        for rule in &self.rules {
+            let mut count = 0;
+            let replaced = rule.pattern.replace_all(&redacted, |caps: &Captures| {
+                let matched = &caps[0];
+                if rule.validate.map_or(true, |validate| validate(matched)) {
+                    count += 1;
+                    rule.placeholder.clone()
+                } else {
+                    matched.to_string()
+                }
+            });
+            if count > 0 {
+                debug!("🔒 Redacted {} × {}", count, rule.placeholder);
+                redacted = replaced.into_owned();
+            }
+        }
+        redacted
+    }
+}
+
+fn placeholder(name: &str) -> String {
+    format!("[REDACTED:{}]", name.trim().to_uppercase())
+}
+
+fn digits(text: &str) -> Vec<u32> {
+    text.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+/// Luhn checksum used by payment cards
+fn luhn_valid(text: &str) -> bool {
+    let sum: u32 = digits(text)
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, &digit)| match index % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// Both mod-11 check digits of a Norwegian national ID (fødselsnummer)
+fn fnr_valid(text: &str) -> bool {
+    const K1: [u32; 9] = [3, 7, 6, 1, 8, 9, 4, 5, 2];
+    const K2: [u32; 10] = [5, 4, 3, 2, 7, 6, 5, 4, 3, 2];
+
+    let digits = digits(text);
+    if digits.len() != 11 {
+        return false;
+    }
+    let check = |weights: &[u32]| {
+        let sum: u32 = weights.iter().zip(&digits).map(|(w, d)| w * d).sum();
+        match 11 - sum % 11 {
+            11 => Some(0),
+            10 => None,
+            digit => Some(digit),
+        }
+    };
+    check(&K1) == Some(digits[9]) && check(&K2) == Some(digits[10])
+}
+
+/// International numbers (`+...`) or 8-digit Norwegian numbers
+fn phone_valid(text: &str) -> bool {
+    let count = digits(text).len();
+    if text.starts_with('+') {
+        (8..=15).contains(&count)
+    } else {
+        count == 8
+    }
+}
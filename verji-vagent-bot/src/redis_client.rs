@@ -1,26 +1,122 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use futures::StreamExt;
 use redis::aio::ConnectionManager;
 use redis::{AsyncCommands, Client};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::capabilities::BotCapabilities;
+use crate::error::BotError;
+use crate::room_context::{ContextTrim, HistoryMessage};
+use crate::transport::{self, GraphStream, GraphTransport};
+
+/// How long `query` waits for the final message
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest serialization buffer kept for the next request
+const MAX_KEPT_BUFFER: usize = 256 * 1024;
+
+/// What a request asks vagent-graph to do
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestKind {
+    /// New query (starts a graph execution)
+    #[default]
+    Query,
+    /// User's answer to a HITL request; `query` carries the answer
+    HitlResponse,
+    /// Abandon a paused HITL execution (no response is sent)
+    HitlCancel,
+    /// Abandon a running query, e.g. from a room the bot left (no response is sent)
+    Cancel,
+}
+
 /// Message sent to vagent-graph for processing
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct GraphRequest {
     pub request_id: String,
+    #[serde(default)]
+    pub kind: RequestKind,
     pub query: String,
+    /// Agent graph that should answer (None = the graph's default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// One-off graph command run outside the conversation session (e.g. `summarize`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Structured data for the graph (e.g. the answers of a HITL form)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// Recent room messages, oldest first, trimmed to the token budget
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<HistoryMessage>,
     pub metadata: RequestMetadata,
+    /// What the bot can do (see `capabilities`), added by `AgentService`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bot_capabilities: Option<BotCapabilities>,
+}
+
+impl GraphRequest {
+    pub fn new(kind: RequestKind, request_id: String, query: String, room_id: String, user_id: String) -> Self {
+        Self {
+            request_id,
+            kind,
+            query,
+            agent: None,
+            command: None,
+            payload: None,
+            context: Vec::new(),
+            bot_capabilities: None,
+            metadata: RequestMetadata {
+                room_id,
+                user_id,
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+                system_prompt: None,
+                context_trim: None,
+                tenant_id: None,
+                thread_id: None,
+            },
+        }
+    }
+
+    /// One-off command request (answered without touching conversation memory)
+    pub fn command(command: &str, payload: serde_json::Value, room_id: String, user_id: String) -> Self {
+        let mut request = Self::new(RequestKind::Query, Uuid::new_v4().to_string(), String::new(), room_id, user_id);
+        request.command = Some(command.to_string());
+        request.payload = Some(payload);
+        request
+    }
+
+    pub fn with_payload(mut self, payload: Option<serde_json::Value>) -> Self {
+        self.payload = payload;
+        self
+    }
 }
 
 /// Metadata about the request
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequestMetadata {
     pub room_id: String,
     pub user_id: String,
     pub timestamp: u64,
+    /// Room-specific system prompt for the agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// What was cut from `context` to fit the token budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_trim: Option<ContextTrim>,
+    /// Customer organization of the room (see `tenant`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// Thread the query was asked in; the graph keeps a session per thread
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
 }
 
 /// Type of message from vagent-graph
@@ -83,10 +179,15 @@ pub struct RedisGraphClient {
     redis_url: String,
     request_channel: String,
     response_channel: String,
+    /// Serialized request, reused between publishes
+    buffer: Vec<u8>,
 }
 
 impl RedisGraphClient {
     /// Create a new Redis client
+    ///
+    /// Channels are `<GRAPH_CHANNEL_PREFIX>:requests` and `:responses`, so a
+    /// shadow deployment can talk to its own graph instance.
     pub async fn new(redis_url: &str) -> Result<Self> {
         info!("Connecting to Redis at {}", redis_url);
 
@@ -94,68 +195,87 @@ impl RedisGraphClient {
 
         let connection = ConnectionManager::new(client)
             .await
-            .context("Failed to create Redis connection manager")?;
+            .map_err(|e| classified(e, "Failed to create Redis connection manager"))?;
 
+        let prefix = std::env::var("GRAPH_CHANNEL_PREFIX")
+            .ok()
+            .filter(|prefix| !prefix.trim().is_empty())
+            .unwrap_or_else(|| "vagent".to_string());
         Ok(Self {
             connection,
             redis_url: redis_url.to_string(),
-            request_channel: "vagent:requests".to_string(),
-            response_channel: "vagent:responses".to_string(),
+            request_channel: format!("{}:requests", prefix),
+            response_channel: format!("{}:responses", prefix),
+            buffer: Vec::new(),
         })
     }
 
-    /// Send a query to vagent-graph with streaming support
+    /// Subscribe to responses and publish the request
     ///
-    /// The on_progress callback is called for each progress notification
-    /// Returns the final response content
-    pub async fn query_with_streaming<F>(
-        &mut self,
-        query: String,
-        room_id: String,
-        user_id: String,
-        on_progress: F,
-    ) -> Result<String>
-    where
-        F: Fn(String) + Send + 'static,
-    {
-        let request_id = Uuid::new_v4().to_string();
-
-        let request = GraphRequest {
-            request_id: request_id.clone(),
-            query: query.clone(),
-            metadata: RequestMetadata {
-                room_id,
-                user_id,
-                timestamp: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-            },
-        };
-
-        debug!("Sending request {} to vagent-graph", request_id);
+    /// The subscription carries the answer; `submit` turns it into a
+    /// [`GraphStream`].
+    pub async fn start(&mut self, request: &GraphRequest) -> Result<redis::aio::PubSub> {
+        debug!("Sending {:?} request {} to vagent-graph", request.kind, request.request_id);
 
         // IMPORTANT: Subscribe BEFORE publishing to avoid race condition
         // Create pubsub connection and subscribe to response channel first
         let client = Client::open(self.redis_url.as_str())
             .context("Failed to create Redis client for pubsub")?;
-        let mut pubsub = client.get_async_pubsub().await?;
-        pubsub.subscribe(&self.response_channel).await?;
+        let mut pubsub = client
+            .get_async_pubsub()
+            .await
+            .map_err(|e| classified(e, "Failed to open Redis pubsub connection"))?;
+        pubsub
+            .subscribe(&self.response_channel)
+            .await
+            .map_err(|e| classified(e, "Failed to subscribe to the response channel"))?;
         debug!("Subscribed to response channel before publishing request");
 
-        // Now serialize and publish request
-        let request_json = serde_json::to_string(&request).context("Failed to serialize request")?;
+        // Now publish the request
+        self.publish(request).await?;
+
+        debug!("Request {} published, waiting for response...", request.request_id);
+        Ok(pubsub)
+    }
+
+    /// Publish a request without waiting for a response (e.g. HITL cancellation)
+    pub async fn publish(&mut self, request: &GraphRequest) -> Result<()> {
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, request).context("Failed to serialize request")?;
 
         self.connection
-            .publish::<_, _, ()>(&self.request_channel, &request_json)
+            .publish::<_, _, ()>(&self.request_channel, self.buffer.as_slice())
             .await
-            .context("Failed to publish request to Redis")?;
+            .map_err(|e| classified(e, "Failed to publish request to Redis"))?;
+        // Don't hold on to the memory of one unusually large request
+        if self.buffer.capacity() > MAX_KEPT_BUFFER {
+            self.buffer = Vec::new();
+        }
 
-        debug!("Request {} published, waiting for response...", request_id);
+        debug!("Published {:?} request {}", request.kind, request.request_id);
+        Ok(())
+    }
+
+    /// PING the command connection, returning the round-trip time
+    pub async fn ping(&mut self) -> Result<Duration> {
+        let started = Instant::now();
+        redis::cmd("PING")
+            .query_async::<String>(&mut self.connection)
+            .await
+            .map_err(|e| classified(e, "Redis PING failed"))?;
+        Ok(started.elapsed())
+    }
 
-        // Wait for final response, calling on_progress for intermediate messages
-        let final_message = self
-            .wait_for_final_response_with_pubsub(&request_id, pubsub, on_progress)
+    /// Send a query to vagent-graph and wait for the answer's text
+    ///
+    /// Legacy shim: progress is dropped and graph errors come back as
+    /// `Error: …` text. Use `submit` (or `AgentService::ask`) to follow a
+    /// request as it runs.
+    pub async fn query(&mut self, query: String, room_id: String, user_id: String) -> Result<String> {
+        let request = GraphRequest::new(RequestKind::Query, Uuid::new_v4().to_string(), query, room_id, user_id);
+        let request_id = request.request_id.clone();
+        let stream = self.submit(&request, RESPONSE_TIMEOUT).await?;
+        let final_message = transport::wait_for_final(stream, RESPONSE_TIMEOUT)
             .await
             .context("Failed to get response from vagent-graph")?;
 
@@ -167,111 +287,92 @@ impl RedisGraphClient {
                 );
                 Ok(format!("Error: {}", final_message.content))
             }
-            GraphMessageType::FinalResponse | GraphMessageType::HitlRequest => {
+            GraphMessageType::FinalResponse | GraphMessageType::HitlRequest | GraphMessageType::Progress => {
                 debug!("Received final response for request {}", request_id);
                 Ok(final_message.content)
             }
-            GraphMessageType::Progress => {
-                // This shouldn't happen (progress should not be returned as final)
-                warn!("Received progress message as final response");
-                Ok(final_message.content)
-            }
         }
     }
+}
 
-    /// Send a query to vagent-graph and wait for response (legacy method without streaming)
-    pub async fn query(&mut self, query: String, room_id: String, user_id: String) -> Result<String> {
-        // Use streaming method with no-op callback
-        self.query_with_streaming(query, room_id, user_id, |_| {}).await
+#[async_trait]
+impl GraphTransport for RedisGraphClient {
+    fn name(&self) -> &'static str {
+        "redis"
     }
 
-    /// Wait for final response, calling on_progress for intermediate progress messages
-    async fn wait_for_final_response_with_pubsub<F>(
-        &mut self,
-        request_id: &str,
-        mut pubsub: redis::aio::PubSub,
-        on_progress: F,
-    ) -> Result<GraphMessage>
-    where
-        F: Fn(String) + Send + 'static,
-    {
-        // Pubsub connection already subscribed before calling this function
-
-        let timeout_duration = Duration::from_secs(30);
-        let start_time = std::time::Instant::now();
-
-        // Listen for messages
-        let mut pubsub_stream = pubsub.on_message();
-
-        loop {
-            if start_time.elapsed() > timeout_duration {
-                anyhow::bail!("Timeout waiting for response from vagent-graph");
-            }
+    /// Redis can't carry the deadline; the caller's wait enforces it
+    async fn submit(&mut self, request: &GraphRequest, _deadline: Duration) -> Result<GraphStream> {
+        let pubsub = self.start(request).await?;
+        Ok(response_stream(pubsub, &request.request_id))
+    }
+
+    async fn cancel(&mut self, request: &GraphRequest) -> Result<()> {
+        self.publish(request).await
+    }
 
-            // Use tokio::time::timeout to add timeout to the next message
-            let message = match tokio::time::timeout(Duration::from_secs(1), pubsub_stream.next()).await {
-                Ok(Some(msg)) => msg,
-                Ok(None) => {
-                    anyhow::bail!("Pubsub stream ended unexpectedly");
-                }
-                Err(_) => {
-                    // Timeout elapsed, continue loop to check overall timeout
-                    continue;
-                }
+    async fn ping(&mut self) -> Result<Duration> {
+        RedisGraphClient::ping(self).await
+    }
+}
+
+/// A Redis error with `what` as context, marked with its failure class
+fn classified(error: redis::RedisError, what: &'static str) -> anyhow::Error {
+    let class = BotError::from_redis(&error);
+    let error = anyhow::Error::new(error).context(what);
+    match class {
+        Some(class) => error.context(class),
+        None => error,
+    }
+}
+
+/// Messages for `request_id` from a subscribed response channel
+fn response_stream(pubsub: redis::aio::PubSub, request_id: &str) -> GraphStream {
+    let request_id = request_id.to_string();
+    pubsub
+        .into_on_message()
+        .filter_map(move |message| {
+            let result = match message.get_payload::<String>() {
+                Ok(payload) => parse_payload(&payload, &request_id).map(Ok),
+                Err(e) => Some(Err(anyhow::Error::new(e).context("Invalid Redis message"))),
             };
+            futures::future::ready(result)
+        })
+        .boxed()
+}
 
-            let payload: String = message.get_payload()?;
-            debug!("Received Redis message: {}", payload);
-
-            // Try to parse as GraphMessage first (new format)
-            if let Ok(graph_msg) = serde_json::from_str::<GraphMessage>(&payload) {
-                debug!("Parsed GraphMessage: type={:?}, request_id={}", graph_msg.message_type, graph_msg.request_id);
-                if graph_msg.request_id == request_id {
-                    debug!("Request ID matches! Type: {:?}", graph_msg.message_type);
-                    match graph_msg.message_type {
-                        GraphMessageType::Progress => {
-                            // Call progress callback and continue waiting
-                            info!("📊 Progress: {}", graph_msg.content);
-                            on_progress(graph_msg.content);
-                            continue;
-                        }
-                        GraphMessageType::FinalResponse
-                        | GraphMessageType::HitlRequest
-                        | GraphMessageType::Error => {
-                            // This is the final message, return it
-                            return Ok(graph_msg);
-                        }
-                    }
-                }
-                // Not our message, keep waiting
-                continue;
-            }
+/// Parse a response channel message, or None if it isn't for `request_id`
+fn parse_payload(payload: &str, request_id: &str) -> Option<GraphMessage> {
+    debug!("Received Redis message: {}", payload);
 
-            // Fall back to legacy GraphResponse format for backward compatibility
-            match serde_json::from_str::<GraphResponse>(&payload) {
-                Ok(response) => {
-                    if response.request_id == request_id {
-                        // Convert legacy response to GraphMessage
-                        let message_type = if response.status == "error" {
-                            GraphMessageType::Error
-                        } else {
-                            GraphMessageType::FinalResponse
-                        };
-
-                        return Ok(GraphMessage {
-                            request_id: response.request_id,
-                            message_type,
-                            content: response.response,
-                            metadata: None,
-                        });
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to parse response from Redis: {}", e);
-                    continue;
-                }
-            }
-        }
+    // Try to parse as GraphMessage first (new format)
+    if let Ok(graph_msg) = serde_json::from_str::<GraphMessage>(payload) {
+        debug!("Parsed GraphMessage: type={:?}, request_id={}", graph_msg.message_type, graph_msg.request_id);
+        // Not our message otherwise, keep waiting
+        return (graph_msg.request_id == request_id).then_some(graph_msg);
     }
 
+    // Fall back to legacy GraphResponse format for backward compatibility
+    match serde_json::from_str::<GraphResponse>(payload) {
+        Ok(response) if response.request_id == request_id => {
+            // Convert legacy response to GraphMessage
+            let message_type = if response.status == "error" {
+                GraphMessageType::Error
+            } else {
+                GraphMessageType::FinalResponse
+            };
+
+            Some(GraphMessage {
+                request_id: response.request_id,
+                message_type,
+                content: response.response,
+                metadata: None,
+            })
+        }
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to parse response from Redis: {}", e);
+            None
+        }
+    }
 }
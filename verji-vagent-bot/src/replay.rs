@@ -0,0 +1,404 @@
+//! Journal of agent queries that failed, and their later replay
+//!
+//! Queries that could not reach vagent-graph or got no answer in time are
+//! written to the bot database with the full request. `!admin replay-failed`
+//! and the `replay` subcommand send them again, oldest first, and post the
+//! answers to the original rooms. Each entry is claimed before it is sent, so
+//! two replays running at once never send the same request twice.
+
+use anyhow::{bail, Context, Result};
+use matrix_sdk::ruma::{EventId, RoomId};
+use matrix_sdk::Client;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::db;
+use crate::mentions;
+use crate::redis_client::{GraphMessageType, GraphRequest};
+use crate::responder::OutgoingMessage;
+use crate::responders::VerjiAgentResponder;
+use crate::room::{BotRoom, RoomHandle};
+use crate::runtime::Runtime;
+use crate::sent_events::{SentEventRegistry, SentKind};
+
+/// A claim older than this is from a replay that died; the entry is free again
+const CLAIM_TIMEOUT_SECS: u64 = 3600;
+
+/// Characters of the question quoted above a delayed answer
+const QUESTION_PREVIEW_CHARS: usize = 100;
+
+/// One journaled request
+#[derive(Debug)]
+pub struct FailedRequest {
+    pub request: GraphRequest,
+    /// Message the query came from, so the answer can point back to it
+    pub event_id: String,
+    /// Language of the room when the query failed
+    pub language: String,
+    pub reason: String,
+    /// Unix seconds
+    pub failed_at: u64,
+}
+
+/// The failed request journal in the bot database
+pub struct FailedRequests {
+    db_path: PathBuf,
+    /// Entries older than this are dropped instead of replayed
+    max_age: Duration,
+}
+
+impl FailedRequests {
+    /// Open the journal table, dropping entries too old to replay
+    pub fn open(store_path: &Path, max_age: Duration) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS failed_requests (
+                request_id TEXT PRIMARY KEY,
+                room_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                event_id TEXT NOT NULL,
+                language TEXT NOT NULL,
+                request TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                failed_at INTEGER NOT NULL,
+                claimed_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS failed_requests_time
+                ON failed_requests (failed_at);",
+        )
+        .context("Failed to create failed_requests table")?;
+        let expired = prune(&conn, max_age)?;
+        if expired > 0 {
+            info!("📼 Dropped {} failed requests too old to replay", expired);
+        }
+
+        Ok(Self { db_path, max_age })
+    }
+
+    /// Journal a request that failed; errors are logged, not returned
+    pub async fn record(&self, entry: FailedRequest) {
+        let request_id = entry.request.request_id.clone();
+        let db_path = self.db_path.clone();
+        let max_age = self.max_age;
+        let result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = db::open(&db_path)?;
+            prune(&conn, max_age)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO failed_requests
+                    (request_id, room_id, user_id, event_id, language, request, reason, failed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    entry.request.request_id,
+                    entry.request.metadata.room_id,
+                    entry.request.metadata.user_id,
+                    entry.event_id,
+                    entry.language,
+                    serde_json::to_string(&entry.request)?,
+                    entry.reason,
+                    entry.failed_at as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => info!("📼 Journaled failed request {} for replay", request_id),
+            Ok(Err(e)) => warn!("Failed to journal request {}: {:#}", request_id, e),
+            Err(e) => warn!("Journaling request {} panicked: {}", request_id, e),
+        }
+    }
+
+    /// Unclaimed entries that failed at or after `since` (unix seconds), oldest first
+    async fn pending(&self, since: u64) -> Result<Vec<FailedRequest>> {
+        let db_path = self.db_path.clone();
+        let max_age = self.max_age;
+        let stale = db::now_secs().saturating_sub(CLAIM_TIMEOUT_SECS);
+        tokio::task::spawn_blocking(move || -> Result<Vec<FailedRequest>> {
+            let conn = db::open(&db_path)?;
+            prune(&conn, max_age)?;
+            let mut stmt = conn.prepare(
+                "SELECT request, event_id, language, reason, failed_at FROM failed_requests
+                 WHERE failed_at >= ?1 AND (claimed_at IS NULL OR claimed_at < ?2)
+                 ORDER BY failed_at, rowid",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![since as i64, stale as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)? as u64,
+                ))
+            })?;
+
+            let mut entries = Vec::new();
+            for row in rows {
+                let (raw, event_id, language, reason, failed_at) = row?;
+                match serde_json::from_str(&raw) {
+                    Ok(request) => entries.push(FailedRequest {
+                        request,
+                        event_id,
+                        language,
+                        reason,
+                        failed_at,
+                    }),
+                    Err(e) => warn!("Skipping unreadable failed request: {}", e),
+                }
+            }
+            Ok(entries)
+        })
+        .await
+        .context("Failed request query panicked")?
+    }
+
+    /// Take an entry for this replay; false if another replay has it
+    async fn claim(&self, request_id: &str) -> Result<bool> {
+        let now = db::now_secs();
+        self.execute(
+            "UPDATE failed_requests SET claimed_at = ?2
+             WHERE request_id = ?1 AND (claimed_at IS NULL OR claimed_at < ?3)",
+            vec![
+                request_id.to_string().into(),
+                (now as i64).into(),
+                (now.saturating_sub(CLAIM_TIMEOUT_SECS) as i64).into(),
+            ],
+        )
+        .await
+        .map(|claimed| claimed == 1)
+    }
+
+    /// Give a claimed entry back after another failure
+    async fn release(&self, request_id: &str, reason: &str) -> Result<()> {
+        self.execute(
+            "UPDATE failed_requests SET claimed_at = NULL, reason = ?2 WHERE request_id = ?1",
+            vec![request_id.to_string().into(), reason.to_string().into()],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Drop an entry, e.g. because its asker retried it with 🔁
+    pub async fn remove(&self, request_id: &str) -> Result<()> {
+        self.execute(
+            "DELETE FROM failed_requests WHERE request_id = ?1",
+            vec![request_id.to_string().into()],
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Delete (or with `dry_run` count) a user's entries
+    pub async fn erase_user(&self, user_id: &str, dry_run: bool) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        let user = user_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = db::open(&db_path)?;
+            db::erase_rows(&conn, "failed_requests", "user_id = ?1", [&user], dry_run)
+        })
+        .await
+        .context("Failed request erasure panicked")?
+    }
+
+    async fn execute(
+        &self,
+        sql: &'static str,
+        params: Vec<rusqlite::types::Value>,
+    ) -> Result<usize> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = db::open(&db_path)?;
+            Ok(conn.execute(sql, rusqlite::params_from_iter(params))?)
+        })
+        .await
+        .context("Failed request journal write panicked")?
+    }
+}
+
+fn prune(conn: &rusqlite::Connection, max_age: Duration) -> Result<usize> {
+    let cutoff = db::now_secs().saturating_sub(max_age.as_secs());
+    Ok(conn.execute(
+        "DELETE FROM failed_requests WHERE failed_at < ?1",
+        [cutoff as i64],
+    )?)
+}
+
+/// Parse how far back a replay reaches: `30m`, `6h`, `2d` or plain seconds
+pub fn parse_since(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last()? {
+        (at, unit) if unit.is_ascii_alphabetic() => (&value[..at], unit.to_ascii_lowercase()),
+        _ => (value, 's'),
+    };
+    let number: u64 = number.parse().ok()?;
+    let secs = match unit {
+        's' => number,
+        'm' => number * 60,
+        'h' => number * 3600,
+        'd' => number * 24 * 3600,
+        _ => return None,
+    };
+    Some(Duration::from_secs(secs))
+}
+
+/// Outcome of one replay run
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    pub replayed: usize,
+    /// Entries another replay was already sending
+    pub claimed_elsewhere: usize,
+    /// Entries whose room the bot is no longer in (dropped)
+    pub unknown_room: usize,
+    /// Why the run stopped early, if it did
+    pub stopped: Option<String>,
+}
+
+impl ReplaySummary {
+    pub fn render(&self) -> String {
+        let mut out = format!("📼 Replayed {} failed requests", self.replayed);
+        if self.claimed_elsewhere > 0 {
+            out.push_str(&format!(
+                ", skipped {} already being replayed",
+                self.claimed_elsewhere
+            ));
+        }
+        if self.unknown_room > 0 {
+            out.push_str(&format!(
+                ", dropped {} from rooms the bot left",
+                self.unknown_room
+            ));
+        }
+        match &self.stopped {
+            Some(reason) => format!(
+                "{}.\n\nStopped at a request that failed again (kept for the next replay): {}",
+                out, reason
+            ),
+            None => format!("{}.", out),
+        }
+    }
+}
+
+/// Sends journaled requests again and posts the answers
+pub struct Replayer {
+    journal: Arc<FailedRequests>,
+    agent: Arc<VerjiAgentResponder>,
+    sent_events: Arc<SentEventRegistry>,
+    /// Pause between two replayed requests
+    interval: Duration,
+    runtime: Runtime,
+}
+
+impl Replayer {
+    pub fn new(
+        journal: Arc<FailedRequests>,
+        agent: Arc<VerjiAgentResponder>,
+        sent_events: Arc<SentEventRegistry>,
+        interval: Duration,
+        runtime: Runtime,
+    ) -> Self {
+        Self {
+            journal,
+            agent,
+            sent_events,
+            interval,
+            runtime,
+        }
+    }
+
+    /// Replay entries that failed within `since` (None = all kept ones), oldest first
+    ///
+    /// Stops at the first request that fails again, so answers keep their
+    /// order; that entry and all later ones stay in the journal.
+    pub async fn run(&self, client: &Client, since: Option<Duration>) -> Result<ReplaySummary> {
+        let since = since.map_or(0, |since| db::now_secs().saturating_sub(since.as_secs()));
+        let entries = self.journal.pending(since).await?;
+        info!("📼 Replaying {} failed requests", entries.len());
+
+        let mut summary = ReplaySummary::default();
+        for entry in entries {
+            let request_id = entry.request.request_id.clone();
+            if !self.journal.claim(&request_id).await? {
+                summary.claimed_elsewhere += 1;
+                continue;
+            }
+            let room = RoomId::parse(&entry.request.metadata.room_id)
+                .ok()
+                .and_then(|room_id| client.get_room(&room_id))
+                .map(|room| BotRoom::new(room, self.runtime.clone()));
+            let Some(room) = room else {
+                warn!(
+                    "Dropping failed request {}: room {} is no longer known",
+                    request_id, entry.request.metadata.room_id
+                );
+                self.journal.remove(&request_id).await?;
+                summary.unknown_room += 1;
+                continue;
+            };
+
+            if summary.replayed > 0 {
+                tokio::time::sleep(self.interval).await;
+            }
+            match self.replay_one(&room, entry).await {
+                Ok(()) => {
+                    self.journal.remove(&request_id).await?;
+                    summary.replayed += 1;
+                }
+                Err(e) => {
+                    let reason = format!("{:#}", e);
+                    warn!("Replay of {} failed: {}", request_id, reason);
+                    self.journal.release(&request_id, &reason).await?;
+                    summary.stopped = Some(reason);
+                    break;
+                }
+            }
+        }
+
+        info!("{}", summary.render());
+        Ok(summary)
+    }
+
+    async fn replay_one(&self, room: &dyn RoomHandle, entry: FailedRequest) -> Result<()> {
+        let user_id = entry.request.metadata.user_id.clone();
+        let mut question: String = entry
+            .request
+            .query
+            .chars()
+            .take(QUESTION_PREVIEW_CHARS)
+            .collect();
+        if question.len() < entry.request.query.len() {
+            question.push('…');
+        }
+
+        let message = self.agent.service().run_command(entry.request).await?;
+        if message.message_type == GraphMessageType::Error {
+            bail!("vagent-graph returned an error: {}", message.content);
+        }
+
+        let prefix = self.runtime.catalog.translate(
+            &entry.language,
+            "replay.delayed_answer",
+            &[
+                ("user", &mentions::pill(&user_id, None)),
+                ("question", &question.replace('\n', " ")),
+            ],
+        );
+        let body = format!("{}\n\n{}", prefix, message.content);
+        let event_id = EventId::parse(&entry.event_id).context("Invalid event ID")?;
+        let sent = room
+            .send_content(&event_id, OutgoingMessage::mentioning(&user_id, body))
+            .await?;
+        self.sent_events
+            .record(
+                sent,
+                room.room_id().as_str(),
+                SentKind::Final,
+                Some(&entry.request.request_id),
+            )
+            .await;
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+//! Request IDs of agent queries
+//!
+//! A query's request ID is a UUIDv5 of the triggering Matrix event ID, so an
+//! event processed again (e.g. after a crash) gets the same ID: vagent-graph
+//! can drop the duplicate and logs of both runs join on it. Coalesced bursts
+//! use their last event, and 🔁 retries add their attempt number so the graph
+//! doesn't take them for duplicates; side requests such as translations derive
+//! their own ID from the query's. `RANDOM_REQUEST_IDS=true` restores random
+//! v4 IDs for graphs that can't handle a replayed ID.
+
+use matrix_sdk::ruma::EventId;
+use uuid::Uuid;
+
+use crate::responder::ResponderContext;
+
+/// Namespace of the v5 IDs; changing it changes every derived ID
+const NAMESPACE: Uuid = Uuid::from_u128(0x2edcc28b_f4f5_59ee_8e56_209062132175);
+
+/// Request ID derived from a Matrix event ID
+pub fn from_event(event_id: &EventId) -> String {
+    Uuid::new_v5(&NAMESPACE, event_id.as_bytes()).to_string()
+}
+
+/// Request ID of the `attempt`th retry of the query triggered by `event_id`
+pub fn for_retry(event_id: &EventId, attempt: u32) -> String {
+    let name = format!("{}/retry/{}", event_id, attempt);
+    Uuid::new_v5(&NAMESPACE, name.as_bytes()).to_string()
+}
+
+/// Request ID for the query triggered by the message in `context`
+pub fn for_message(context: &ResponderContext) -> String {
+    if context.config.random_request_ids {
+        Uuid::new_v4().to_string()
+    } else if context.retry_attempt > 0 {
+        for_retry(&context.event_id, context.retry_attempt)
+    } else {
+        from_event(&context.event_id)
+    }
+}
+
+/// Request ID of a side request (e.g. a translation) made for the message in `context`
+pub fn for_step(context: &ResponderContext, step: &str) -> String {
+    let name = format!("{}/{}", for_message(context), step);
+    Uuid::new_v5(&NAMESPACE, name.as_bytes()).to_string()
+}
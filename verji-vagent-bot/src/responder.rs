@@ -1,32 +1,261 @@
 use anyhow::Result;
 use async_trait::async_trait;
-use matrix_sdk::{room::Room, Client};
+use matrix_sdk::{ruma::OwnedEventId, Client};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::BotConfig;
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::follow_up::FollowUpScheduler;
+use crate::mentions::Mentions;
+use crate::observer::ObserverPool;
+use crate::preferences::{PreferenceStore, UserPreferences};
+use crate::room::RoomHandle;
+use crate::room_config::{RoomConfig, RoomConfigStore};
+use crate::runtime::Runtime;
+use crate::sent_events::SentEventRegistry;
+use crate::stats::UsageStats;
+use crate::still_working::OutputActivity;
+use crate::tenant::TenantResolver;
 
 /// Context provided to responders for handling messages
+///
+/// Cloned for every observer and for rewritten messages, so the text fields
+/// shared read-only by the whole pipeline are `Arc<str>`.
 #[derive(Clone)]
+#[non_exhaustive]
 pub struct ResponderContext {
     /// Matrix SDK client
     pub client: Client,
     /// The room where the message was received
-    pub room: Room,
+    pub room: Arc<dyn RoomHandle>,
+    /// ID of the event that triggered this dispatch
+    pub event_id: OwnedEventId,
+    /// Event the message replies to, if it is a reply
+    pub in_reply_to: Option<OwnedEventId>,
     /// User ID of the message sender
-    pub sender: String,
+    pub sender: Arc<str>,
+    /// The sender's display name in this room, looked up for each message
+    pub sender_display_name: Option<String>,
+    /// Who the sender is for quotas, sessions, preferences and stats:
+    /// `sender`, unless `SENDER_IDENTITY_RULES` maps it (see `identity`)
+    pub identity: Arc<str>,
+    /// Root event of the thread the message is in, if any
+    pub thread_id: Option<String>,
     /// The actual message text
-    pub message_body: String,
+    pub message_body: Arc<str>,
     /// Whether the bot was directly mentioned
     pub is_direct_mention: bool,
+    /// Whether the message had a formatted (HTML) body, i.e. the sender's
+    /// client writes rich text (`RESPONSE_FORMAT=auto`)
+    pub message_formatted: bool,
     /// List of all registered responders (name, priority)
     pub registered_responders: Vec<(String, i32)>,
+    /// Shared bot configuration
+    pub config: Arc<BotConfig>,
+    /// Per-room/per-user usage counters
+    pub stats: Arc<UsageStats>,
+    /// Shared per-(room, thread, user) state
+    pub conversations: Arc<ConversationStore>,
+    /// Actions to run later in a room, kept across restarts
+    pub follow_ups: Arc<FollowUpScheduler>,
+    /// Per-user preferences (`!prefs`); read them with [`Self::prefs`]
+    pub preferences: Arc<PreferenceStore>,
+    /// Key/value notes added by responders that rewrote the message
+    pub annotations: HashMap<String, String>,
+    /// Settings of the room, as loaded when the message arrived
+    pub room_config: RoomConfig,
+    /// Access to room settings for commands that change them
+    pub room_configs: Arc<RoomConfigStore>,
+    /// Tenant of each room, for requests to multi-tenant graphs
+    pub tenants: Arc<TenantResolver>,
+    /// Events the bot sent, for correlating reactions with requests
+    pub sent_events: Arc<SentEventRegistry>,
+    /// Times this query was re-submitted with a 🔁 reaction (0 = first try)
+    pub retry_attempt: u32,
+    /// Responders showing progress report it here, so the dispatch layer
+    /// doesn't post a "still working" notice over it
+    pub output: Arc<OutputActivity>,
+    /// Observers of the dispatching manager, told how the agent dealt with
+    /// the message
+    pub observers: Arc<ObserverPool>,
+    /// Payload of a forwarded custom event (`CUSTOM_EVENTS`), sent to the
+    /// graph instead of a text query
+    pub custom_event: Option<serde_json::Value>,
+    /// The bot's shared state: metrics, translations, send queues and the rest
+    pub runtime: Runtime,
+}
+
+impl ResponderContext {
+    /// Whether the sender is on the admin list
+    pub fn sender_is_admin(&self) -> bool {
+        self.config.is_admin(&self.sender)
+    }
+
+    /// The sender's preferences in this room, from memory
+    pub fn prefs(&self) -> UserPreferences {
+        self.preferences
+            .effective(&self.identity, self.room.room_id().as_str())
+    }
+
+    /// Language for the bot's own replies to the sender in this room
+    pub fn language(&self) -> String {
+        self.prefs()
+            .language
+            .or_else(|| self.room_config.language.clone())
+            .unwrap_or_else(|| self.config.locale.clone())
+    }
+
+    /// Copy of this context with a new message body and an annotation
+    pub fn rewrite(&self, message_body: String, key: &str, value: &str) -> Self {
+        let mut context = self.clone();
+        context.message_body = message_body.into();
+        context
+            .annotations
+            .insert(key.to_string(), value.to_string());
+        context
+    }
+
+    /// Conversation state key for the sender in this room (and thread)
+    pub fn conversation_key(&self, slot: &str) -> ConversationKey {
+        let key = ConversationKey::new(self.room.room_id().as_str(), &self.sender, slot);
+        match &self.thread_id {
+            Some(thread_id) => key.in_thread(thread_id),
+            None => key,
+        }
+    }
+}
+
+/// A single message the bot should send in response
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum OutgoingMessage {
+    /// Plain text message
+    Text(String),
+    /// Markdown rendered to HTML with a plain-text fallback
+    Markdown(String),
+    /// Plain text notice (`m.notice`, rendered less prominently by clients)
+    Notice(String),
+    /// Reaction to the triggering event
+    Reaction(String),
+    /// File upload (encrypted automatically in E2EE rooms)
+    Attachment {
+        filename: String,
+        content_type: String,
+        data: Vec<u8>,
+    },
+    /// A message that notifies the users it addresses (`m.mentions`)
+    Mention {
+        body: String,
+        mentions: Mentions,
+        /// Sent as `m.notice` instead of `m.text`
+        notice: bool,
+        /// The body is markdown; otherwise it is sent as written
+        markdown: bool,
+    },
+}
+
+impl OutgoingMessage {
+    /// Markdown addressed to `user_id`, who gets notified
+    pub fn mentioning(user_id: &str, body: impl Into<String>) -> Self {
+        OutgoingMessage::Mention {
+            body: body.into(),
+            mentions: Mentions::user(user_id),
+            notice: false,
+            markdown: true,
+        }
+    }
+
+    /// The message without formatting: markdown is sent as plain text
+    ///
+    /// Mentions keep their pills, which only go in the formatted body.
+    pub fn into_plain(self) -> Self {
+        match self {
+            OutgoingMessage::Markdown(body) => OutgoingMessage::Text(body),
+            OutgoingMessage::Mention {
+                body,
+                mentions,
+                notice,
+                ..
+            } => OutgoingMessage::Mention {
+                body,
+                mentions,
+                notice,
+                markdown: false,
+            },
+            other => other,
+        }
+    }
+
+    /// A notice addressed to `user_id`, who gets notified
+    pub fn notice_mentioning(user_id: &str, body: impl Into<String>) -> Self {
+        OutgoingMessage::Mention {
+            body: body.into(),
+            mentions: Mentions::user(user_id),
+            notice: true,
+            markdown: true,
+        }
+    }
 }
 
 /// Response from a responder
+#[non_exhaustive]
 pub enum ResponderResult {
-    /// Message was handled, optionally with a reply
+    /// Message was handled, optionally with a plain-text reply
     Handled(Option<String>),
+    /// Message was handled with one or more rich messages, sent in order
+    HandledWithContent(Vec<OutgoingMessage>),
+    /// Message was consumed without replying; the chain stops
+    HandledSilently,
+    /// Let lower-priority responders try first; if none handles the message,
+    /// this responder's `fallback()` is invoked
+    Defer,
+    /// Run the chain again from the top with this modified context
+    /// (e.g. expanded shorthand); limited per message to prevent loops
+    Rewritten(Box<ResponderContext>),
     /// Message was not handled, pass to next responder
     NotHandled,
 }
 
+impl ResponderResult {
+    /// Whether this result stops the chain with the message handled
+    pub fn is_handled(&self) -> bool {
+        matches!(
+            self,
+            ResponderResult::Handled(_)
+                | ResponderResult::HandledWithContent(_)
+                | ResponderResult::HandledSilently
+        )
+    }
+}
+
+/// A responder's answer to whether it takes a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Accept,
+    /// Pass on the message; the reason (e.g. `not_a_command`) is shown in the
+    /// routing trace
+    Decline(Option<&'static str>),
+}
+
+impl Route {
+    /// Decline with a machine-readable reason
+    pub fn decline(reason: &'static str) -> Self {
+        Route::Decline(Some(reason))
+    }
+}
+
+impl From<bool> for Route {
+    fn from(accept: bool) -> Self {
+        if accept {
+            Route::Accept
+        } else {
+            Route::Decline(None)
+        }
+    }
+}
+
 /// Core trait that all responders must implement
 #[async_trait]
 pub trait Responder: Send + Sync {
@@ -39,11 +268,61 @@ pub trait Responder: Send + Sync {
         0
     }
 
+    /// Maximum time `handle()` may take before the manager gives up on it
+    /// Default is no timeout
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
     /// Check if this responder should handle the message
     /// This is called first as a fast filter before handle()
     async fn should_handle(&self, context: &ResponderContext) -> bool;
 
+    /// `should_handle` with a reason when declining, for the routing trace
+    /// Default is `should_handle` without a reason; the manager calls this one
+    async fn route(&self, context: &ResponderContext) -> Route {
+        self.should_handle(context).await.into()
+    }
+
     /// Handle the message and return a response
     /// Only called if should_handle() returns true
     async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult>;
+
+    /// Handle a message this responder deferred on and nobody else handled
+    /// Returning `Defer` again is treated as NotHandled
+    async fn fallback(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::NotHandled)
+    }
+}
+
+/// Lets decorators such as `Cooldown` wrap already type-erased responders
+#[async_trait]
+impl Responder for Arc<dyn Responder> {
+    fn name(&self) -> &str {
+        self.as_ref().name()
+    }
+
+    fn priority(&self) -> i32 {
+        self.as_ref().priority()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.as_ref().timeout()
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.as_ref().should_handle(context).await
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        self.as_ref().route(context).await
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.as_ref().handle(context).await
+    }
+
+    async fn fallback(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.as_ref().fallback(context).await
+    }
 }
@@ -1,46 +1,469 @@
 use anyhow::Result;
-use std::sync::Arc;
-use tracing::{info, warn};
+use futures::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
-use crate::responder::{Responder, ResponderContext, ResponderResult};
+use crate::alias::Aliases;
+use crate::i18n::CannedReply;
+use crate::middleware::{Middleware, MiddlewareDecision};
+use crate::observer::{Observer, ObserverPool};
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult, Route};
+use crate::room::{RoomHandle, RoomScope};
+use crate::routing::{RoutingTrace, RoutingTraces, Verdict};
+
+/// What the chain does after a responder returns
+enum ChainStep {
+    /// Stop the chain and send these messages (possibly none)
+    Done(Vec<OutgoingMessage>),
+    /// Try the next responder
+    Next,
+    /// Try the rest of the chain, then come back to this responder's fallback
+    Deferred,
+    /// Restart the chain with a modified context
+    Rewritten(Box<ResponderContext>),
+}
+
+impl ChainStep {
+    /// How the step shows in the routing trace
+    fn verdict(&self) -> Verdict {
+        match self {
+            ChainStep::Done(_) => Verdict::Handled,
+            ChainStep::Next => Verdict::NotHandled,
+            ChainStep::Deferred => Verdict::Deferred,
+            ChainStep::Rewritten(_) => Verdict::Rewritten,
+        }
+    }
+}
+
+/// How one pass over the chain ended
+enum ChainOutcome {
+    /// Send these messages (possibly none)
+    Finished(Vec<OutgoingMessage>),
+    /// A responder rewrote the message; run the chain again on the new context
+    Rewritten(ResponderContext),
+}
+
+/// Rewrites allowed per message before further `Rewritten` results are ignored
+pub const MAX_REWRITES: usize = 3;
+
+/// Routing traces kept until [`ResponderManager::set_trace_max`] says otherwise
+const DEFAULT_TRACE_MAX: usize = 100;
+
+/// Upper bound for `should_handle`, which is meant to be a fast filter
+const SHOULD_HANDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What to do when a responder's `handle` exceeds its timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPolicy {
+    /// Try the next responder in the chain
+    Continue,
+    /// Stop processing the message
+    Abort,
+}
+
+impl TimeoutPolicy {
+    /// Parse from configuration ("abort" or anything else for continue)
+    pub fn parse(value: &str) -> Self {
+        if value.trim().eq_ignore_ascii_case("abort") {
+            TimeoutPolicy::Abort
+        } else {
+            TimeoutPolicy::Continue
+        }
+    }
+}
+
+/// What to do when a responder's `handle` returns an error or panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Log and try the next responder in the chain
+    Continue,
+    /// Stop the chain and send the configured error reply
+    Reply,
+    /// Stop processing the message silently
+    Abort,
+}
+
+impl ErrorPolicy {
+    /// Parse from configuration ("reply", "abort", or anything else for continue)
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "reply" => ErrorPolicy::Reply,
+            "abort" => ErrorPolicy::Abort,
+            _ => ErrorPolicy::Continue,
+        }
+    }
+}
+
+/// Why a responder call produced no usable result
+enum Failure {
+    TimedOut,
+    Error(anyhow::Error),
+    Panicked(String),
+}
+
+/// How the chain proceeds after a failure
+enum Recovery {
+    /// Move on to the next responder, optionally remembering a notice for the user
+    Continue(Option<OutgoingMessage>),
+    /// Stop the chain and send these messages
+    Stop(Vec<OutgoingMessage>),
+}
+
+/// A registered responder and the rooms it is active in
+#[derive(Clone)]
+struct Registration {
+    responder: Arc<dyn Responder>,
+    scope: RoomScope,
+}
+
+/// Immutable, priority-sorted view of the registered responders
+type ResponderList = Arc<Vec<Registration>>;
+
+/// Listing entry for a registered responder
+#[derive(Debug, Clone)]
+pub struct ResponderInfo {
+    pub name: String,
+    pub priority: i32,
+    /// Description of the responder's room scope
+    pub scope: String,
+}
 
 /// Manages registration and routing of responders using Chain of Responsibility pattern
+///
+/// The responder list can change at runtime (admin commands); each dispatch
+/// works on the snapshot taken when it started, so in-flight messages are
+/// unaffected by concurrent registration or removal. How each message was
+/// routed is kept as a [`RoutingTrace`].
 pub struct ResponderManager {
-    responders: Vec<Arc<dyn Responder>>,
+    responders: RwLock<ResponderList>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    observers: Arc<ObserverPool>,
+    traces: RoutingTraces,
+    timeout_policy: TimeoutPolicy,
+    timeout_reply: CannedReply,
+    error_policy: ErrorPolicy,
+    error_reply: CannedReply,
 }
 
 impl ResponderManager {
     /// Create a new empty responder manager
     pub fn new() -> Self {
         Self {
-            responders: Vec::new(),
+            responders: RwLock::new(Arc::new(Vec::new())),
+            middlewares: Vec::new(),
+            observers: Arc::new(ObserverPool::new()),
+            traces: RoutingTraces::new(DEFAULT_TRACE_MAX),
+            timeout_policy: TimeoutPolicy::Continue,
+            timeout_reply: CannedReply::Silent,
+            error_policy: ErrorPolicy::Continue,
+            error_reply: CannedReply::Silent,
         }
     }
 
-    /// Register a new responder
+    /// Configure how responder timeouts are handled
+    /// `reply` is sent to the room when a responder times out
+    pub fn set_timeout_policy(&mut self, policy: TimeoutPolicy, reply: CannedReply) {
+        self.timeout_policy = policy;
+        self.timeout_reply = reply;
+    }
+
+    /// Keep the routing traces of the last `max` messages (0 = none)
+    pub fn set_trace_max(&mut self, max: usize) {
+        self.traces = RoutingTraces::new(max);
+    }
+
+    /// Configure how responder errors and panics are handled
+    /// `reply` is only used with `ErrorPolicy::Reply`
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy, reply: CannedReply) {
+        self.error_policy = policy;
+        self.error_reply = reply;
+    }
+
+    /// Register a new responder active in all rooms
     /// Responders are automatically sorted by priority (highest first)
-    pub fn register(&mut self, responder: Arc<dyn Responder>) {
+    pub fn register(&self, responder: Arc<dyn Responder>) {
+        self.register_scoped(responder, RoomScope::Everywhere);
+    }
+
+    /// Register a new responder that only sees messages from rooms in `scope`
+    pub fn register_scoped(&self, responder: Arc<dyn Responder>, scope: RoomScope) {
         info!(
-            "📝 Registering responder: {} (priority: {})",
+            "📝 Registering responder: {} (priority: {}, scope: {})",
             responder.name(),
-            responder.priority()
+            responder.priority(),
+            scope.describe()
         );
-        self.responders.push(responder);
+        self.update(|responders| responders.push(Registration { responder, scope }));
+    }
+
+    /// Remove a responder by name, returning it and its scope if it was registered
+    pub fn unregister(&self, name: &str) -> Option<(Arc<dyn Responder>, RoomScope)> {
+        let mut removed = None;
+        self.update(|responders| {
+            if let Some(index) = responders.iter().position(|r| r.responder.name() == name) {
+                removed = Some(responders.remove(index));
+            }
+        });
+
+        match &removed {
+            Some(_) => info!("🗑️  Unregistered responder: {}", name),
+            None => warn!("⚠️  Cannot unregister unknown responder: {}", name),
+        }
+        removed.map(|registration| (registration.responder, registration.scope))
+    }
+
+    /// Replace the responder registered under `name`, keeping its scope
+    /// Returns false (and registers nothing) if no responder has that name
+    pub fn replace(&self, name: &str, responder: Arc<dyn Responder>) -> bool {
+        let mut replaced = false;
+        self.update(|responders| {
+            if let Some(index) = responders.iter().position(|r| r.responder.name() == name) {
+                responders[index].responder = Arc::clone(&responder);
+                replaced = true;
+            }
+        });
+
+        if replaced {
+            info!(
+                "🔁 Replaced responder '{}' with '{}' (priority: {})",
+                name,
+                responder.name(),
+                responder.priority()
+            );
+        }
+        replaced
+    }
+
+    /// Copy-on-write update of the responder list, keeping it sorted by priority
+    fn update(&self, change: impl FnOnce(&mut Vec<Registration>)) {
+        let mut guard = self.responders.write().unwrap();
+        let mut responders = guard.as_ref().clone();
+        change(&mut responders);
 
         // Sort by priority (highest first)
-        self.responders
-            .sort_by(|a, b| b.priority().cmp(&a.priority()));
+        responders.sort_by(|a, b| b.responder.priority().cmp(&a.responder.priority()));
+        *guard = Arc::new(responders);
+    }
+
+    /// The current responder list; cheap to clone and stable for the caller
+    fn snapshot(&self) -> ResponderList {
+        Arc::clone(&self.responders.read().unwrap())
+    }
+
+    /// Register a middleware; middlewares run in registration order
+    pub fn add_middleware(&mut self, middleware: Arc<dyn Middleware>) {
+        info!("📝 Registering middleware: {}", middleware.name());
+        self.middlewares.push(middleware);
+    }
+
+    /// Register an observer; observers see every message, see [`ObserverPool`]
+    pub fn add_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observers.add(observer);
+    }
+
+    /// The observers, for contexts to report agent outcomes to
+    pub fn observers(&self) -> &Arc<ObserverPool> {
+        &self.observers
+    }
+
+    /// How recent messages were routed (`!admin trace`)
+    pub fn traces(&self) -> &RoutingTraces {
+        &self.traces
+    }
+
+    /// Run a message through the middleware stack and the responder chain
+    /// Returns the messages to send (empty if the message was dropped or unhandled)
+    ///
+    /// Messages from rooms the bot has left are dropped, and so is a dispatch
+    /// still running when the bot leaves (see [`crate::membership`]).
+    pub async fn dispatch(&self, context: &ResponderContext) -> Result<Vec<OutgoingMessage>> {
+        let room_id = context.room.room_id().as_str();
+        let memberships = &context.runtime.memberships;
+        let mut presence = memberships.watch(room_id);
+        if memberships.has_left(room_id) {
+            debug!("🚪 Not dispatching in {}, the bot left it", room_id);
+            return Ok(Vec::new());
+        }
+
+        // Observers see every message, including ones middleware drops
+        self.observers.notify(context);
+
+        // Wait for earlier messages of the same thread; other threads go on
+        let turn = tokio::select! {
+            turn = context.runtime.processing.turn(
+                &context.room,
+                context.thread_id.as_deref(),
+                context.config.typing_indicator,
+            ) => turn,
+            _ = presence.departed() => {
+                info!("🚪 Left {} while a message waited its turn, dropping it", room_id);
+                return Ok(Vec::new());
+            }
+        };
+        if memberships.has_left(room_id) {
+            turn.finish().await;
+            return Ok(Vec::new());
+        }
+
+        let result = tokio::select! {
+            result = self.run_pipeline(context) => result,
+            _ = presence.departed() => {
+                info!("🚪 Left {} while handling a message, dropping it", room_id);
+                Ok(Vec::new())
+            }
+        };
+        turn.finish().await;
+        result
+    }
+
+    /// Middleware `before`, the responder chain, middleware `after`
+    async fn run_pipeline(&self, context: &ResponderContext) -> Result<Vec<OutgoingMessage>> {
+        let mut trace = RoutingTrace::new(
+            context.room.room_id().as_str(),
+            context.event_id.as_str(),
+            &context.sender,
+        );
+        let mut short_circuit = None;
+
+        for middleware in &self.middlewares {
+            match middleware.before(context).await {
+                Ok(MiddlewareDecision::Continue) => {}
+                Ok(MiddlewareDecision::ShortCircuit(reply)) => {
+                    info!(
+                        "↪️  Middleware '{}' short-circuited dispatch",
+                        middleware.name()
+                    );
+                    trace.push(middleware.name(), Verdict::Handled);
+                    short_circuit = Some(vec![reply]);
+                    break;
+                }
+                Ok(MiddlewareDecision::Drop) => {
+                    info!("🗑️  Middleware '{}' dropped the message", middleware.name());
+                    trace.dropped_by = Some(middleware.name().to_string());
+                    self.finish_trace(trace);
+                    return Ok(Vec::new());
+                }
+                Err(e) => {
+                    // Isolate failures: a broken middleware must not silence the bot
+                    error!(
+                        "Middleware '{}' failed in before(), continuing: {:#}",
+                        middleware.name(),
+                        e
+                    );
+                }
+            }
+        }
+
+        let messages = match short_circuit {
+            Some(messages) => messages,
+            None => self.route_message(context, &mut trace).await?,
+        };
+        trace.answered = !messages.is_empty();
+        self.finish_trace(trace);
+
+        for middleware in self.middlewares.iter().rev() {
+            if let Err(e) = middleware.after(context, &messages).await {
+                error!(
+                    "Middleware '{}' failed in after(): {:#}",
+                    middleware.name(),
+                    e
+                );
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Log a message's trace and keep it for `!admin trace`
+    fn finish_trace(&self, trace: RoutingTrace) {
+        debug!("🧭 Routing of {}: {}", trace.event_id, trace.summary());
+        self.traces.record(trace);
     }
 
     /// Process a message through all registered responders
-    /// Returns the response from the first responder that handles it, or None if no responder handles it
-    pub async fn process_message(&self, context: &ResponderContext) -> Result<Option<String>> {
+    /// Returns the messages produced by the first responder that handles it (empty if none does)
+    ///
+    /// Responders returning `Defer` are remembered and, if nobody else handles the
+    /// message, get one chance each (in priority order) to answer via `fallback()`.
+    ///
+    /// A responder returning `Rewritten` restarts the chain from the top with the
+    /// new context. After `MAX_REWRITES` rewrites, further ones count as NotHandled.
+    pub async fn process_message(
+        &self,
+        context: &ResponderContext,
+    ) -> Result<Vec<OutgoingMessage>> {
+        let mut trace = RoutingTrace::new(
+            context.room.room_id().as_str(),
+            context.event_id.as_str(),
+            &context.sender,
+        );
+        self.route_message(context, &mut trace).await
+    }
+
+    /// [`Self::process_message`], noting every responder's verdict in `trace`
+    async fn route_message(
+        &self,
+        context: &ResponderContext,
+        trace: &mut RoutingTrace,
+    ) -> Result<Vec<OutgoingMessage>> {
+        // Set when a responder failed and the chain continued, so the user learns why
+        let mut notice: Option<OutgoingMessage> = None;
+        let mut rewritten: Option<ResponderContext> = None;
+        let mut rewrites = 0;
+
+        loop {
+            let current = rewritten.as_ref().unwrap_or(context);
+            let outcome = self
+                .run_chain(current, rewrites < MAX_REWRITES, &mut notice, trace)
+                .await?;
+
+            match outcome {
+                ChainOutcome::Finished(messages) => return Ok(messages),
+                ChainOutcome::Rewritten(next) => {
+                    rewrites += 1;
+                    // The body is the user's message; only its size is logged
+                    debug!(
+                        "✏️  Message rewritten ({}/{}, {} chars), restarting chain",
+                        rewrites,
+                        MAX_REWRITES,
+                        next.message_body.chars().count()
+                    );
+                    rewritten = Some(next);
+                }
+            }
+        }
+    }
+
+    /// One pass over the responder chain (plus deferred fallbacks)
+    async fn run_chain(
+        &self,
+        context: &ResponderContext,
+        allow_rewrite: bool,
+        notice: &mut Option<OutgoingMessage>,
+        trace: &mut RoutingTrace,
+    ) -> Result<ChainOutcome> {
+        let responders = self.snapshot();
         info!(
             "📨 Processing message through {} responders",
-            self.responders.len()
+            responders.len()
         );
 
-        for responder in &self.responders {
+        let mut deferred: Vec<&Arc<dyn Responder>> = Vec::new();
+
+        for registration in responders.iter() {
+            // Scope is enforced before the responder sees the message at all
+            if !registration
+                .scope
+                .allows(context.room.as_ref(), &context.runtime.aliases)
+            {
+                trace.push(registration.responder.name(), Verdict::OutOfScope);
+                continue;
+            }
+            let responder = &registration.responder;
+
             info!(
                 "🔍 Checking responder: {} (priority: {})",
                 responder.name(),
@@ -48,41 +471,300 @@ impl ResponderManager {
             );
 
             // Two-phase dispatch: check first, then handle
-            if responder.should_handle(context).await {
-                info!("✅ Responder '{}' will handle message", responder.name());
+            if let Route::Decline(reason) = self.check(responder.as_ref(), context).await {
+                info!(
+                    "⏩ Responder '{}' declined to handle ({})",
+                    responder.name(),
+                    reason.unwrap_or("no reason given")
+                );
+                trace.push(responder.name(), Verdict::Declined(reason));
+                continue;
+            }
 
-                match responder.handle(context).await? {
-                    ResponderResult::Handled(response) => {
-                        info!("✅ Message handled by responder: {}", responder.name());
-                        return Ok(response);
+            info!("✅ Responder '{}' will handle message", responder.name());
+
+            let result = match self
+                .guarded(responder.as_ref(), context, responder.handle(context))
+                .await
+            {
+                Ok(result) => result,
+                Err(failure) => {
+                    trace.push(responder.name(), Verdict::Failed);
+                    match self.recover(responder.as_ref(), failure, context) {
+                        Recovery::Continue(failure_notice) => {
+                            *notice = failure_notice.or(notice.take());
+                            continue;
+                        }
+                        Recovery::Stop(messages) => return Ok(ChainOutcome::Finished(messages)),
                     }
-                    ResponderResult::NotHandled => {
-                        info!(
-                            "⏭️  Responder '{}' returned NotHandled, trying next",
-                            responder.name()
-                        );
-                        continue;
+                }
+            };
+
+            let step = Self::interpret(responder.name(), result, allow_rewrite);
+            trace.push(responder.name(), step.verdict());
+            match step {
+                ChainStep::Done(messages) => {
+                    return Ok(ChainOutcome::Finished(Self::with_notice(
+                        notice.take(),
+                        messages,
+                    )))
+                }
+                ChainStep::Rewritten(next) => return Ok(ChainOutcome::Rewritten(*next)),
+                ChainStep::Next => continue,
+                ChainStep::Deferred => {
+                    info!(
+                        "⏸️  Responder '{}' deferred, trying the rest of the chain first",
+                        responder.name()
+                    );
+                    deferred.push(responder);
+                }
+            }
+        }
+
+        // Second pass: nobody else handled it, let deferring responders fall back.
+        // Each deferrer is asked exactly once; deferring again counts as NotHandled.
+        for responder in deferred {
+            info!(
+                "↩️  Invoking fallback of deferred responder '{}'",
+                responder.name()
+            );
+
+            let result = match self
+                .guarded(responder.as_ref(), context, responder.fallback(context))
+                .await
+            {
+                Ok(result) => result,
+                Err(failure) => {
+                    trace.push(responder.name(), Verdict::Failed);
+                    match self.recover(responder.as_ref(), failure, context) {
+                        Recovery::Continue(failure_notice) => {
+                            *notice = failure_notice.or(notice.take());
+                            continue;
+                        }
+                        Recovery::Stop(messages) => return Ok(ChainOutcome::Finished(messages)),
                     }
                 }
-            } else {
-                info!("⏩ Responder '{}' declined to handle", responder.name());
+            };
+
+            let step = Self::interpret(responder.name(), result, allow_rewrite);
+            trace.push(responder.name(), step.verdict());
+            match step {
+                ChainStep::Done(messages) => {
+                    return Ok(ChainOutcome::Finished(Self::with_notice(
+                        notice.take(),
+                        messages,
+                    )))
+                }
+                ChainStep::Rewritten(next) => return Ok(ChainOutcome::Rewritten(*next)),
+                ChainStep::Next => continue,
+                ChainStep::Deferred => {
+                    warn!(
+                        "⚠️  Responder '{}' deferred again from its fallback, skipping",
+                        responder.name()
+                    );
+                }
             }
         }
 
         warn!("⚠️  No responder handled the message");
-        Ok(None)
+        Ok(ChainOutcome::Finished(notice.take().into_iter().collect()))
+    }
+
+    /// Run `route` under the fixed fast-filter timeout
+    /// Timeouts and panics count as a decline
+    async fn check(&self, responder: &dyn Responder, context: &ResponderContext) -> Route {
+        let filter = AssertUnwindSafe(responder.route(context)).catch_unwind();
+
+        match tokio::time::timeout(SHOULD_HANDLE_TIMEOUT, filter).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(panic)) => {
+                error!(
+                    "💥 Responder '{}' panicked in should_handle(): {}",
+                    responder.name(),
+                    panic_message(panic.as_ref())
+                );
+                context
+                    .runtime
+                    .metrics
+                    .increment("responder_panics_total", &[("responder", responder.name())]);
+                Route::decline("should_handle_panicked")
+            }
+            Err(_) => {
+                warn!(
+                    "⏱️  Responder '{}' should_handle() exceeded {:?}, treating as declined",
+                    responder.name(),
+                    SHOULD_HANDLE_TIMEOUT
+                );
+                context.runtime.metrics.increment(
+                    "responder_should_handle_timeouts_total",
+                    &[("responder", responder.name())],
+                );
+                Route::decline("should_handle_timed_out")
+            }
+        }
+    }
+
+    /// Await a responder call under its timeout, converting errors and panics into failures
+    async fn guarded<F>(
+        &self,
+        responder: &dyn Responder,
+        context: &ResponderContext,
+        future: F,
+    ) -> std::result::Result<ResponderResult, Failure>
+    where
+        F: Future<Output = Result<ResponderResult>>,
+    {
+        let started = Instant::now();
+        // A panicking responder must not take the event handler task down with it
+        let future = AssertUnwindSafe(future).catch_unwind();
+
+        let outcome = match responder.timeout() {
+            Some(limit) => match tokio::time::timeout(limit, future).await {
+                Ok(outcome) => Some(outcome),
+                Err(_) => None,
+            },
+            None => Some(future.await),
+        };
+
+        context.runtime.metrics.observe_ms(
+            "responder_handle_duration_ms",
+            &[("responder", responder.name())],
+            started.elapsed().as_millis() as u64,
+        );
+
+        match outcome {
+            Some(Ok(Ok(result))) => Ok(result),
+            Some(Ok(Err(e))) => Err(Failure::Error(e)),
+            Some(Err(panic)) => Err(Failure::Panicked(panic_message(panic.as_ref()))),
+            None => Err(Failure::TimedOut),
+        }
+    }
+
+    /// Log a failure and decide, per the configured policies, how the chain proceeds
+    fn recover(
+        &self,
+        responder: &dyn Responder,
+        failure: Failure,
+        context: &ResponderContext,
+    ) -> Recovery {
+        let name = responder.name();
+        let metrics = &context.runtime.metrics;
+
+        match failure {
+            Failure::TimedOut => {
+                warn!(
+                    "⏱️  Responder '{}' exceeded its {:?} timeout ({:?})",
+                    name,
+                    responder.timeout().unwrap_or_default(),
+                    self.timeout_policy
+                );
+                metrics.increment("responder_timeouts_total", &[("responder", name)]);
+
+                let notice = self
+                    .timeout_reply
+                    .render(context, "responder.timeout")
+                    .map(OutgoingMessage::Notice);
+                match self.timeout_policy {
+                    TimeoutPolicy::Continue => Recovery::Continue(notice),
+                    TimeoutPolicy::Abort => Recovery::Stop(notice.into_iter().collect()),
+                }
+            }
+            Failure::Error(e) => {
+                error!("❌ Responder '{}' failed: {:#}", name, e);
+                metrics.increment("responder_errors_total", &[("responder", name)]);
+                self.recover_from_error(context)
+            }
+            Failure::Panicked(message) => {
+                error!("💥 Responder '{}' panicked: {}", name, message);
+                metrics.increment("responder_panics_total", &[("responder", name)]);
+                self.recover_from_error(context)
+            }
+        }
+    }
+
+    fn recover_from_error(&self, context: &ResponderContext) -> Recovery {
+        match self.error_policy {
+            ErrorPolicy::Continue => Recovery::Continue(None),
+            ErrorPolicy::Reply => Recovery::Stop(
+                self.error_reply
+                    .render(context, "responder.error")
+                    .map(OutgoingMessage::Notice)
+                    .into_iter()
+                    .collect(),
+            ),
+            ErrorPolicy::Abort => Recovery::Stop(Vec::new()),
+        }
+    }
+
+    /// Prefix a responder's output with a pending failure notice
+    fn with_notice(
+        notice: Option<OutgoingMessage>,
+        messages: Vec<OutgoingMessage>,
+    ) -> Vec<OutgoingMessage> {
+        notice.into_iter().chain(messages).collect()
+    }
+
+    /// Map a responder result onto the next step of the chain
+    fn interpret(name: &str, result: ResponderResult, allow_rewrite: bool) -> ChainStep {
+        match result {
+            ResponderResult::Handled(response) => {
+                info!("✅ Message handled by responder: {}", name);
+                ChainStep::Done(response.map(OutgoingMessage::Text).into_iter().collect())
+            }
+            ResponderResult::HandledWithContent(messages) => {
+                info!(
+                    "✅ Message handled by responder: {} ({} message(s))",
+                    name,
+                    messages.len()
+                );
+                ChainStep::Done(messages)
+            }
+            ResponderResult::HandledSilently => {
+                info!("🤫 Message handled silently by responder: {}", name);
+                ChainStep::Done(Vec::new())
+            }
+            ResponderResult::NotHandled => {
+                info!("⏭️  Responder '{}' returned NotHandled, trying next", name);
+                ChainStep::Next
+            }
+            ResponderResult::Defer => ChainStep::Deferred,
+            ResponderResult::Rewritten(context) if allow_rewrite => {
+                info!("✏️  Responder '{}' rewrote the message", name);
+                ChainStep::Rewritten(context)
+            }
+            ResponderResult::Rewritten(_) => {
+                warn!(
+                    "⚠️  Responder '{}' exceeded the rewrite limit, treating as NotHandled",
+                    name
+                );
+                ChainStep::Next
+            }
+        }
     }
 
     /// Get the number of registered responders
     pub fn count(&self) -> usize {
-        self.responders.len()
+        self.snapshot().len()
+    }
+
+    /// List all registered responders with their priorities and scopes
+    pub fn list_responders(&self) -> Vec<ResponderInfo> {
+        self.snapshot()
+            .iter()
+            .map(|r| ResponderInfo {
+                name: r.responder.name().to_string(),
+                priority: r.responder.priority(),
+                scope: r.scope.describe(),
+            })
+            .collect()
     }
 
-    /// List all registered responders with their priorities
-    pub fn list_responders(&self) -> Vec<(String, i32)> {
-        self.responders
+    /// List the responders whose scope includes `room` (name, priority)
+    pub fn active_in(&self, room: &dyn RoomHandle, aliases: &Aliases) -> Vec<(String, i32)> {
+        self.snapshot()
             .iter()
-            .map(|r| (r.name().to_string(), r.priority()))
+            .filter(|r| r.scope.allows(room, aliases))
+            .map(|r| (r.responder.name().to_string(), r.responder.priority()))
             .collect()
     }
 }
@@ -92,3 +774,14 @@ impl Default for ResponderManager {
         Self::new()
     }
 }
+
+/// Extract a readable message from a panic payload
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
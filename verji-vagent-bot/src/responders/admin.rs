@@ -0,0 +1,707 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+use tracing::{info, warn};
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::db;
+use crate::erasure::Erasure;
+use crate::feedback::{self, FeedbackStore};
+use crate::i18n::t;
+use crate::maintenance::MaintenanceMode;
+use crate::outbox::Outbox;
+use crate::replay::{self, Replayer};
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+use crate::responder_manager::ResponderManager;
+use crate::responders::{history, quota};
+use crate::room::RoomScope;
+use crate::room_config::{ReplyMode, ResponseFormat, StickerMode, TranslateMode};
+use crate::routing;
+use crate::stats;
+
+/// Period `!admin feedbackstats` covers without an argument
+const DEFAULT_FEEDBACK_DAYS: u64 = 30;
+
+/// Operator commands (`!admin ...`), restricted to `ADMIN_USERS`
+pub struct AdminResponder {
+    /// Weak to avoid a reference cycle (the manager owns this responder)
+    manager: Weak<ResponderManager>,
+    /// Responders disabled at runtime with their scopes, kept so they can be re-enabled
+    disabled: Mutex<BTreeMap<String, (Arc<dyn Responder>, RoomScope)>>,
+    maintenance: Arc<MaintenanceMode>,
+    erasure: Arc<Erasure>,
+    replayer: Arc<Replayer>,
+    feedback: Arc<FeedbackStore>,
+    /// Undelivered answers (None = `OUTBOX_MAX_AGE_HOURS=0`)
+    outbox: Option<Arc<Outbox>>,
+}
+
+impl AdminResponder {
+    pub fn new(
+        manager: Weak<ResponderManager>,
+        maintenance: Arc<MaintenanceMode>,
+        erasure: Arc<Erasure>,
+        replayer: Arc<Replayer>,
+        feedback: Arc<FeedbackStore>,
+        outbox: Option<Arc<Outbox>>,
+    ) -> Self {
+        Self {
+            manager,
+            disabled: Mutex::new(BTreeMap::new()),
+            maintenance,
+            erasure,
+            replayer,
+            feedback,
+            outbox,
+        }
+    }
+
+    fn usage() -> String {
+        [
+            "**Admin commands**",
+            "",
+            "- `!admin stats all` - usage statistics across all rooms",
+            "- `!admin responders` - list registered and disabled responders",
+            "- `!admin responders here` - list responders active in this room",
+            "- `!admin responders disable <name>` - disable a responder at runtime",
+            "- `!admin responders enable <name>` - re-enable a disabled responder",
+            "- `!admin language [code|reset]` - show or set the bot's language in this room",
+            "- `!admin history <@user> [N]` - a user's recent agent questions across rooms",
+            "- `!admin maintenance [on [message]|off]` - pause agent queries with a notice",
+            "- `!admin tenant` - the tenant resolved for this room",
+            "- `!admin quota <@user>` - a user's agent queries today and their daily limit",
+            "- `!admin quota set <@user> <N>` - override a user's daily limit (0 = unlimited)",
+            "- `!admin quota reset <@user>` - drop the override",
+            "- `!admin erase <@user>` - list what the bot stored about a user",
+            "- `!admin erase <@user> confirm` - delete it and purge their graph session memory",
+            "- `!admin replay-failed [since]` - resend failed agent queries (e.g. `since` = `2h`)",
+            "- `!admin feedbackstats [days]` - 👍/👎 on agent answers per room (default 30 days)",
+            "- `!admin stickers [ignore|ack|forward]` - show or set how stickers are handled here",
+            "- `!admin replymode [all|mentions]` - show or set which messages are answered here",
+            "- `!admin format [markdown|plain|auto]` - show or set the format of answers here",
+            "- `!admin sensitive [on|off]` - show or set whether leaving members rotate the room key",
+            "- `!admin budget [<amount>|default|reset]` - show or set this room's monthly agent budget (0 = unlimited), or start this month's spend over",
+            "- `!admin outbox` - answers waiting for delivery, and where failed ones ended up",
+            "- `!admin trace [N]` - the last N (default 5) unanswered messages here and why each responder passed",
+            "- `!admin rotate-session` - rotate the bot's room key here before its next message",
+            "- `!admin translate [<language> [below|replace] [questions]|off]` - show or set how answers are translated here",
+        ]
+        .join("\n")
+    }
+
+    fn list_responders(&self, manager: &ResponderManager) -> String {
+        let mut out = String::from("**Registered responders**\n\n");
+        for info in manager.list_responders() {
+            out.push_str(&format!(
+                "- {} (priority {}, {})\n",
+                info.name, info.priority, info.scope
+            ));
+        }
+
+        let disabled = self.disabled.lock().unwrap();
+        if !disabled.is_empty() {
+            out.push_str("\n**Disabled responders**\n\n");
+            for name in disabled.keys() {
+                out.push_str(&format!("- {}\n", name));
+            }
+        }
+        out
+    }
+
+    fn list_active_here(manager: &ResponderManager, context: &ResponderContext) -> String {
+        let mut out = format!(
+            "**Responders active in {}**\n\n",
+            context.room.display_name()
+        );
+        for (name, priority) in manager.active_in(context.room.as_ref(), &context.runtime.aliases) {
+            out.push_str(&format!("- {} (priority {})\n", name, priority));
+        }
+        out
+    }
+
+    /// The latest unanswered messages in this room with their routing traces
+    fn trace(
+        manager: &ResponderManager,
+        context: &ResponderContext,
+        count: Option<&String>,
+    ) -> String {
+        let limit = match count.map(|count| count.parse::<usize>()) {
+            None => 5,
+            Some(Ok(limit)) if limit > 0 => limit,
+            Some(_) => return "Usage: `!admin trace [N]`, N a positive number".to_string(),
+        };
+        let room_id = context.room.room_id().as_str();
+        routing::render(&manager.traces().unanswered(room_id, limit))
+    }
+
+    async fn language(context: &ResponderContext, requested: Option<&str>) -> Result<String> {
+        let Some(requested) = requested.map(str::to_lowercase) else {
+            return Ok(format!("🌐 Room language: `{}`", context.language()));
+        };
+        let catalog = &context.runtime.catalog;
+
+        if requested == "reset" {
+            context
+                .room_configs
+                .update(context.room.as_ref(), |config| config.language = None)
+                .await?;
+            let default = &context.config.locale;
+            return Ok(catalog.translate(
+                default,
+                "admin.language_reset",
+                &[("language", default)],
+            ));
+        }
+
+        let available = catalog.languages();
+        if !available.contains(&requested) {
+            let available: Vec<String> = available.into_iter().collect();
+            return Ok(t(
+                context,
+                "admin.language_unknown",
+                &[
+                    ("language", &requested),
+                    ("available", &available.join(", ")),
+                ],
+            ));
+        }
+
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.language = Some(requested.clone())
+            })
+            .await?;
+        info!(
+            "🌐 Language of {} set to {} by {}",
+            context.room.room_id(),
+            requested,
+            context.sender
+        );
+        Ok(catalog.translate(
+            &requested,
+            "admin.language_set",
+            &[("language", &requested)],
+        ))
+    }
+
+    async fn history(
+        context: &ResponderContext,
+        user: &str,
+        count: Option<&String>,
+    ) -> Result<String> {
+        let Some(count) = history::parse_count(count) else {
+            return Ok(Self::usage());
+        };
+        let identity = context.config.identities.canonical(user);
+        let interactions = context.stats.history(&identity, None, count).await?;
+        let title = format!("**Recent agent questions of {}**", user);
+        Ok(stats::render_history(&title, &interactions, true))
+    }
+
+    async fn maintenance(&self, context: &ResponderContext, args: &[String]) -> Result<String> {
+        let keyword = args
+            .first()
+            .map(|arg| arg.to_lowercase())
+            .unwrap_or_default();
+        match keyword.as_str() {
+            "on" => {
+                let message = Some(args[1..].join(" ")).filter(|message| !message.is_empty());
+                self.maintenance.set(true, message, &context.sender).await?;
+                return Ok(t(context, "maintenance.enabled", &[]));
+            }
+            "off" => {
+                self.maintenance.set(false, None, &context.sender).await?;
+                return Ok(t(context, "maintenance.disabled", &[]));
+            }
+            "" => {}
+            _ => return Ok(Self::usage()),
+        }
+
+        let state = self.maintenance.state();
+        if !state.enabled {
+            return Ok(t(context, "maintenance.status_off", &[]));
+        }
+        let message = state
+            .message
+            .unwrap_or_else(|| t(context, "maintenance.notice", &[]));
+        Ok(t(
+            context,
+            "maintenance.status_on",
+            &[
+                ("user", state.changed_by.as_deref().unwrap_or("?")),
+                ("since", &stats::format_timestamp(state.changed_at)),
+                ("message", &message),
+            ],
+        ))
+    }
+
+    async fn quota(context: &ResponderContext, args: &[String]) -> Result<String> {
+        let keyword = args
+            .first()
+            .map(|arg| arg.to_lowercase())
+            .unwrap_or_default();
+        let (user, change) = match (keyword.as_str(), args.get(1), args.get(2)) {
+            ("set", Some(user), Some(limit)) => match limit.parse::<u32>() {
+                Ok(limit) => (user, Some(Some(limit))),
+                Err(_) => return Ok(Self::usage()),
+            },
+            ("reset", Some(user), None) => (user, Some(None)),
+            (_, None, None) if keyword.starts_with('@') => (&args[0], None),
+            _ => return Ok(Self::usage()),
+        };
+
+        let identity = context.config.identities.canonical(user);
+        if let Some(limit) = change {
+            context.stats.set_quota_override(&identity, limit).await?;
+            info!(
+                "⏳ Daily quota of {} set to {:?} by {}",
+                user, limit, context.sender
+            );
+        }
+        Ok(format!(
+            "**{}**: {}",
+            user,
+            quota::render_daily_usage(context, &identity)
+        ))
+    }
+
+    async fn erase(&self, context: &ResponderContext, args: &[String]) -> Result<String> {
+        let (user, confirmed) = match args {
+            [user] => (user, false),
+            [user, confirm] if confirm.eq_ignore_ascii_case("confirm") => (user, true),
+            _ => return Ok(Self::usage()),
+        };
+        if !user.starts_with('@') {
+            return Ok(Self::usage());
+        }
+
+        if confirmed {
+            warn!("🗑️  Erasure of {} requested by {}", user, context.sender);
+        }
+        let identity = context.config.identities.canonical(user);
+        let report = self.erasure.erase(user, &identity, !confirmed).await?;
+        let mut out = report.render();
+        if !confirmed {
+            out.push_str(&format!(
+                "\nRun `!admin erase {} confirm` to delete it.",
+                user
+            ));
+        }
+        Ok(out)
+    }
+
+    async fn stickers(context: &ResponderContext, requested: Option<&String>) -> Result<String> {
+        let Some(requested) = requested else {
+            let mode = context
+                .room_config
+                .sticker_mode
+                .unwrap_or(context.config.sticker_mode);
+            return Ok(format!("🖼️ Sticker mode: `{}`", mode.as_str()));
+        };
+        let Some(mode) = StickerMode::parse(requested) else {
+            return Ok(Self::usage());
+        };
+
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.sticker_mode = Some(mode)
+            })
+            .await?;
+        info!(
+            "🖼️  Sticker mode of {} set to {} by {}",
+            context.room.room_id(),
+            mode.as_str(),
+            context.sender
+        );
+        Ok(format!("🖼️ Sticker mode set to `{}`.", mode.as_str()))
+    }
+
+    async fn reply_mode(context: &ResponderContext, requested: Option<&String>) -> Result<String> {
+        let Some(requested) = requested else {
+            let mode = context
+                .room_config
+                .reply_mode
+                .unwrap_or(context.config.reply_mode);
+            return Ok(format!("💬 Reply mode: `{}`", mode.as_str()));
+        };
+        let Some(mode) = ReplyMode::parse(requested) else {
+            return Ok(Self::usage());
+        };
+
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.reply_mode = Some(mode)
+            })
+            .await?;
+        info!(
+            "💬 Reply mode of {} set to {} by {}",
+            context.room.room_id(),
+            mode.as_str(),
+            context.sender
+        );
+        Ok(format!("💬 Reply mode set to `{}`.", mode.as_str()))
+    }
+
+    async fn response_format(
+        context: &ResponderContext,
+        requested: Option<&String>,
+    ) -> Result<String> {
+        let Some(requested) = requested else {
+            let format = context
+                .room_config
+                .response_format
+                .unwrap_or(context.config.response_format);
+            return Ok(format!("📝 Answer format: `{}`", format.as_str()));
+        };
+        let Some(format) = ResponseFormat::parse(requested) else {
+            return Ok(Self::usage());
+        };
+
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.response_format = Some(format)
+            })
+            .await?;
+        info!(
+            "📝 Answer format of {} set to {} by {}",
+            context.room.room_id(),
+            format.as_str(),
+            context.sender
+        );
+        Ok(format!("📝 Answer format set to `{}`.", format.as_str()))
+    }
+
+    async fn budget(context: &ResponderContext, requested: Option<&String>) -> Result<String> {
+        let policy = &context.config.room_budget;
+        let room_id = context.room.room_id().as_str();
+        let now_ms = db::now_secs() * 1000;
+        let period = policy.period(now_ms);
+        let Some(requested) = requested else {
+            let spent = context.stats.room_spend(room_id, &period);
+            return Ok(policy.status(&spent, context.room_config.monthly_budget, now_ms));
+        };
+
+        let budget = match requested.trim().to_lowercase().as_str() {
+            "reset" => {
+                context.stats.reset_room_spend(room_id, &period);
+                info!("💸 Spend of {} reset by {}", room_id, context.sender);
+                return Ok("💸 This month's spend reset to 0.".to_string());
+            }
+            "default" => None,
+            amount => match amount.parse::<f64>() {
+                Ok(amount) if amount.is_finite() && amount >= 0.0 => Some(amount),
+                _ => return Ok(Self::usage()),
+            },
+        };
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.monthly_budget = budget
+            })
+            .await?;
+        info!(
+            "💸 Monthly budget of {} set to {:?} by {}",
+            room_id, budget, context.sender
+        );
+        let spent = context.stats.room_spend(room_id, &period);
+        Ok(policy.status(&spent, budget, now_ms))
+    }
+
+    async fn sensitive(context: &ResponderContext, requested: Option<&String>) -> Result<String> {
+        let Some(requested) = requested else {
+            let state = if context.room_config.sensitive {
+                "on"
+            } else {
+                "off"
+            };
+            return Ok(format!("🔑 Sensitive room: `{}`", state));
+        };
+        let sensitive = match requested.to_lowercase().as_str() {
+            "on" => true,
+            "off" => false,
+            _ => return Ok(Self::usage()),
+        };
+
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| config.sensitive = sensitive)
+            .await?;
+        info!(
+            "🔑 Sensitive flag of {} set to {} by {}",
+            context.room.room_id(),
+            sensitive,
+            context.sender
+        );
+        Ok(if sensitive {
+            "🔑 This room is now sensitive: the room key is rotated when a member leaves."
+                .to_string()
+        } else {
+            "🔑 This room is no longer sensitive.".to_string()
+        })
+    }
+
+    async fn rotate_session(context: &ResponderContext) -> Result<String> {
+        if !context.room.is_encrypted().await {
+            return Ok(
+                "🔑 This room is not encrypted; there is no room key to rotate.".to_string(),
+            );
+        }
+        let reason = format!("requested by {} ({})", context.sender, context.event_id);
+        context
+            .runtime
+            .key_rotations
+            .rotate(context.room.as_ref(), &reason)
+            .await?;
+        Ok("🔑 Room key rotated; the next message uses a new session.".to_string())
+    }
+
+    async fn translate(context: &ResponderContext, args: &[String]) -> Result<String> {
+        let Some(language) = args.first() else {
+            let config = &context.room_config;
+            return Ok(match &config.translate_to {
+                Some(language) => format!(
+                    "🌐 Answers are translated into `{}` ({}{})",
+                    language,
+                    config.translate_mode.unwrap_or_default().as_str(),
+                    if config.translate_questions {
+                        ", questions too"
+                    } else {
+                        ""
+                    }
+                ),
+                None => "🌐 Answers are not translated here.".to_string(),
+            });
+        };
+
+        if language.eq_ignore_ascii_case("off") {
+            if args.len() > 1 {
+                return Ok(Self::usage());
+            }
+            context
+                .room_configs
+                .update(context.room.as_ref(), |config| {
+                    config.translate_to = None;
+                    config.translate_mode = None;
+                    config.translate_questions = false;
+                })
+                .await?;
+            info!(
+                "🌐 Translation in {} turned off by {}",
+                context.room.room_id(),
+                context.sender
+            );
+            return Ok("🌐 Answers are no longer translated here.".to_string());
+        }
+
+        let mut mode = None;
+        let mut questions = false;
+        for option in &args[1..] {
+            match TranslateMode::parse(option) {
+                Some(parsed) if mode.is_none() => mode = Some(parsed),
+                None if option.eq_ignore_ascii_case("questions") && !questions => questions = true,
+                _ => return Ok(Self::usage()),
+            }
+        }
+        let language = language.to_string();
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.translate_to = Some(language.clone());
+                config.translate_mode = mode;
+                config.translate_questions = questions;
+            })
+            .await?;
+        let mode = mode.unwrap_or_default();
+        info!(
+            "🌐 Translation in {} set to {} ({}, questions: {}) by {}",
+            context.room.room_id(),
+            language,
+            mode.as_str(),
+            questions,
+            context.sender
+        );
+        Ok(format!(
+            "🌐 Answers are now translated into `{}` ({}{}).",
+            language,
+            mode.as_str(),
+            if questions { ", questions too" } else { "" }
+        ))
+    }
+
+    async fn feedback_stats(&self, days: Option<&String>) -> Result<String> {
+        let days = match days.map(|days| days.parse::<u64>()) {
+            None => DEFAULT_FEEDBACK_DAYS,
+            Some(Ok(days)) if days > 0 => days,
+            Some(_) => return Ok(Self::usage()),
+        };
+        let since = db::now_secs().saturating_sub(days * 24 * 3600);
+        let rooms = self.feedback.totals(since).await?;
+        let title = format!("**Answer feedback, last {} days**", days);
+        Ok(feedback::render_totals(&title, &rooms))
+    }
+
+    /// Start a replay in the background; the summary is posted when it ends
+    fn replay_failed(&self, context: &ResponderContext, since: Option<&String>) -> String {
+        let since = match since {
+            Some(since) => match replay::parse_since(since) {
+                Some(since) => Some(since),
+                None => return Self::usage(),
+            },
+            None => None,
+        };
+
+        info!("📼 Replay of failed requests started by {}", context.sender);
+        let replayer = Arc::clone(&self.replayer);
+        let client = context.client.clone();
+        let room = Arc::clone(&context.room);
+        tokio::spawn(async move {
+            let summary = match replayer.run(&client, since).await {
+                Ok(summary) => summary.render(),
+                Err(e) => format!("📼 Replay failed: {:#}", e),
+            };
+            if let Err(e) = room.send_markdown(&summary).await {
+                warn!("Failed to post the replay summary: {}", e);
+            }
+        });
+        "📼 Replaying failed requests, I'll post a summary when done.".to_string()
+    }
+
+    fn disable_responder(&self, manager: &ResponderManager, requested: &str) -> String {
+        let Some(name) = manager
+            .list_responders()
+            .into_iter()
+            .map(|info| info.name)
+            .find(|name| name.eq_ignore_ascii_case(requested))
+        else {
+            return format!("No registered responder named `{}`.", requested);
+        };
+
+        if name == CommandResponder::name(self) {
+            return "Refusing to disable the admin responder (it could not be re-enabled)."
+                .to_string();
+        }
+
+        match manager.unregister(&name) {
+            Some(registration) => {
+                warn!("🛑 Responder '{}' disabled by admin", name);
+                self.disabled
+                    .lock()
+                    .unwrap()
+                    .insert(name.clone(), registration);
+                format!("🛑 Disabled `{}`.", name)
+            }
+            None => format!("`{}` was already removed.", name),
+        }
+    }
+
+    fn enable_responder(&self, manager: &ResponderManager, requested: &str) -> String {
+        let removed = {
+            let mut disabled = self.disabled.lock().unwrap();
+            let name = disabled
+                .keys()
+                .find(|name| name.eq_ignore_ascii_case(requested))
+                .cloned();
+            name.and_then(|name| disabled.remove(&name))
+        };
+
+        match removed {
+            Some((responder, scope)) => {
+                info!("✅ Responder '{}' re-enabled by admin", responder.name());
+                let message = format!("✅ Re-enabled `{}`.", responder.name());
+                manager.register_scoped(responder, scope);
+                message
+            }
+            None => format!("No disabled responder named `{}`.", requested),
+        }
+    }
+}
+
+#[async_trait]
+impl CommandResponder for AdminResponder {
+    fn name(&self) -> &str {
+        "AdminResponder"
+    }
+
+    fn priority(&self) -> i32 {
+        95 // Command responder, checked before regular commands
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!admin"],
+            min_args: 0,
+            max_args: None,
+            admin_only: true,
+            usage: "`!admin <command> [args]`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult> {
+        let Some(manager) = self.manager.upgrade() else {
+            return Ok(ResponderResult::NotHandled);
+        };
+
+        // Keywords are case-insensitive; responder names keep their original case
+        let keyword = |index: usize| {
+            args.get(index)
+                .map(|arg| arg.to_lowercase())
+                .unwrap_or_default()
+        };
+
+        info!("🛠️  Admin command from {}: {:?}", context.sender, args);
+
+        let response = match (keyword(0).as_str(), keyword(1).as_str()) {
+            ("stats", "all") => context.stats.render_all_rooms_table(),
+            ("responders", "") => self.list_responders(&manager),
+            ("responders", "here") => Self::list_active_here(&manager, context),
+            ("responders", "disable") => match args.get(2) {
+                Some(name) => self.disable_responder(&manager, name),
+                None => Self::usage(),
+            },
+            ("language", _) => Self::language(context, args.get(1).map(String::as_str)).await?,
+            ("history", _) => match args.get(1) {
+                Some(user) => Self::history(context, user, args.get(2)).await?,
+                None => Self::usage(),
+            },
+            ("maintenance", _) => self.maintenance(context, &args[1..]).await?,
+            ("quota", _) => Self::quota(context, &args[1..]).await?,
+            ("erase", _) => self.erase(context, &args[1..]).await?,
+            ("replay-failed", _) if args.len() <= 2 => self.replay_failed(context, args.get(1)),
+            ("feedbackstats", _) if args.len() <= 2 => self.feedback_stats(args.get(1)).await?,
+            ("trace", _) if args.len() <= 2 => Self::trace(&manager, context, args.get(1)),
+            ("outbox", "") => match &self.outbox {
+                Some(outbox) => outbox.render().await?,
+                None => "📮 The outbox is disabled (`OUTBOX_MAX_AGE_HOURS=0`).".to_string(),
+            },
+            ("stickers", _) if args.len() <= 2 => Self::stickers(context, args.get(1)).await?,
+            ("replymode", _) if args.len() <= 2 => Self::reply_mode(context, args.get(1)).await?,
+            ("format", _) if args.len() <= 2 => Self::response_format(context, args.get(1)).await?,
+            ("sensitive", _) if args.len() <= 2 => Self::sensitive(context, args.get(1)).await?,
+            ("budget", _) if args.len() <= 2 => Self::budget(context, args.get(1)).await?,
+            ("rotate-session", "") => Self::rotate_session(context).await?,
+            ("translate", _) if args.len() <= 4 => Self::translate(context, &args[1..]).await?,
+            ("tenant", "") => {
+                let tenants = &context.tenants;
+                let tenant = tenants.resolve(context.room.as_ref()).await;
+                format!(
+                    "🏢 Tenant of this room: {}\n\nRead from `{}` (state key `{}`, path `{}`); required: {}",
+                    tenant, tenants.event_type, tenants.state_key, tenants.path, tenants.required
+                )
+            }
+            ("responders", "enable") => match args.get(2) {
+                Some(name) => self.enable_responder(&manager, name),
+                None => Self::usage(),
+            },
+            _ => Self::usage(),
+        };
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(response),
+        ]))
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Chooses which agent graph a room talks to (`!agent list|show|set <name>`)
+///
+/// Agents come from `AGENTS`; changing the selection is limited to admins.
+#[derive(Default)]
+pub struct AgentSelectResponder;
+
+impl AgentSelectResponder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn list(context: &ResponderContext) -> String {
+        let agents = &context.config.agents;
+        if agents.is_empty() {
+            return t(context, "agent_select.none_configured", &[]);
+        }
+
+        let active = Self::active(context);
+        let mut out = format!("{}\n\n", t(context, "agent_select.list_title", &[]));
+        for (name, description) in agents {
+            let marker = if active.as_deref() == Some(name.as_str()) {
+                " ✅"
+            } else {
+                ""
+            };
+            out.push_str(&format!("- `{}` - {}{}\n", name, description, marker));
+        }
+        out
+    }
+
+    fn show(context: &ResponderContext) -> String {
+        match Self::active(context) {
+            Some(agent) => t(context, "agent_select.current", &[("agent", &agent)]),
+            None => t(context, "agent_select.graph_default", &[]),
+        }
+    }
+
+    async fn set(context: &ResponderContext, requested: &str) -> Result<String> {
+        let agents = &context.config.agents;
+        let Some(name) = agents
+            .keys()
+            .find(|name| name.eq_ignore_ascii_case(requested))
+            .cloned()
+        else {
+            let available: Vec<&str> = agents.keys().map(String::as_str).collect();
+            return Ok(t(
+                context,
+                "agent_select.unknown",
+                &[("agent", requested), ("available", &available.join(", "))],
+            ));
+        };
+
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.agent = Some(name.clone())
+            })
+            .await?;
+        info!(
+            "🧭 Agent of {} set to {} by {}",
+            context.room.room_id(),
+            name,
+            context.sender
+        );
+        Ok(t(context, "agent_select.set", &[("agent", &name)]))
+    }
+
+    /// Agent queries from this room go to
+    fn active(context: &ResponderContext) -> Option<String> {
+        context
+            .config
+            .agent_for(context.room_config.agent.as_deref())
+    }
+}
+
+#[async_trait]
+impl CommandResponder for AgentSelectResponder {
+    fn name(&self) -> &str {
+        "AgentSelectResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!agent"],
+            min_args: 1,
+            max_args: Some(2),
+            admin_only: false,
+            usage: "`!agent list`, `!agent show` or `!agent set <name>`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult> {
+        let response = match (args[0].to_lowercase().as_str(), args.get(1)) {
+            ("list", None) => Self::list(context),
+            ("show", None) => Self::show(context),
+            ("set", Some(_)) if !context.sender_is_admin() => t(context, "command.admin_only", &[]),
+            ("set", Some(name)) => Self::set(context, name).await?,
+            _ => t(context, "command.usage", &[("usage", self.spec().usage)]),
+        };
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(response),
+        ]))
+    }
+}
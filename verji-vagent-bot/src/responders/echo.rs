@@ -0,0 +1,46 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::responder::{ResponderContext, ResponderResult};
+
+/// Repeats the text after `!echo`, for connectivity testing
+#[derive(Default)]
+pub struct EchoResponder;
+
+impl EchoResponder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl CommandResponder for EchoResponder {
+    fn name(&self) -> &str {
+        "EchoResponder"
+    }
+
+    fn priority(&self) -> i32 {
+        100 // Same slot as ping: answered without the agent
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!echo"],
+            min_args: 1,
+            max_args: None,
+            admin_only: false,
+            usage: "`!echo <text>`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        // The raw text, so quotes and spacing come back as sent
+        let text = context
+            .message_body
+            .trim()
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest.trim_start());
+        Ok(ResponderResult::Handled(Some(text.to_string())))
+    }
+}
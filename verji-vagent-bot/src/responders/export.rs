@@ -0,0 +1,285 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::config;
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+use crate::room::{TimelineBody, TimelineEntry};
+use crate::stats;
+
+/// Messages exported when `!export` has no count
+const DEFAULT_MESSAGES: usize = 200;
+/// Messages fetched per history request
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Markdown,
+    Plain,
+}
+
+impl Format {
+    fn parse(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "md" | "markdown" => Some(Self::Markdown),
+            "txt" | "text" | "plain" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Plain => "txt",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Plain => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// Uploads a transcript of the room's messages as a file (`!export [N|all] [md|txt]`)
+///
+/// History is read page by page and each page is rendered right away, so only
+/// the transcript text is held, not the events. Exports are limited to one
+/// per room per `EXPORT_COOLDOWN_SECS`.
+pub struct ExportResponder {
+    max_messages: usize,
+    cooldown: Duration,
+    /// Room ID -> time of the last export
+    last_export: Mutex<HashMap<String, Instant>>,
+}
+
+impl ExportResponder {
+    pub fn new() -> Self {
+        Self {
+            max_messages: config::env_u64("EXPORT_MAX_MESSAGES", 5000) as usize,
+            cooldown: Duration::from_secs(config::env_u64("EXPORT_COOLDOWN_SECS", 300)),
+            last_export: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Parse `[N|all] [md|txt]`, in either order; the format defaults to
+    /// the `transcript_format` preference
+    fn parse_args(&self, args: &[String], default_format: Format) -> Option<(usize, Format)> {
+        let mut count = DEFAULT_MESSAGES.min(self.max_messages);
+        let mut format = default_format;
+        for arg in args {
+            if let Some(parsed) = Format::parse(arg) {
+                format = parsed;
+            } else if arg.eq_ignore_ascii_case("all") {
+                count = self.max_messages;
+            } else {
+                match arg.parse::<usize>() {
+                    Ok(n) if n > 0 => count = n.min(self.max_messages),
+                    _ => return None,
+                }
+            }
+        }
+        Some((count, format))
+    }
+
+    /// Time left before the room may export again, recording an export if none
+    fn claim(&self, room_id: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let mut last_export = self.last_export.lock().unwrap();
+        last_export.retain(|_, at| now.duration_since(*at) < self.cooldown);
+        if let Some(at) = last_export.get(room_id) {
+            return Some(self.cooldown - now.duration_since(*at));
+        }
+        last_export.insert(room_id.to_string(), now);
+        None
+    }
+}
+
+impl Default for ExportResponder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CommandResponder for ExportResponder {
+    fn name(&self) -> &str {
+        "ExportResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!export"],
+            min_args: 0,
+            max_args: Some(2),
+            admin_only: false,
+            usage: "`!export [number of messages|all] [md|txt]`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult> {
+        let default_format = context
+            .prefs()
+            .transcript_format
+            .as_deref()
+            .and_then(Format::parse)
+            .unwrap_or(Format::Markdown);
+        let Some((count, format)) = self.parse_args(&args, default_format) else {
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "command.usage",
+                &[("usage", self.spec().usage)],
+            ))));
+        };
+        let room_id = context.room.room_id().to_string();
+        if let Some(wait) = self.claim(&room_id) {
+            let minutes = wait.as_secs().div_ceil(60).to_string();
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "export.too_soon",
+                &[("minutes", &minutes)],
+            ))));
+        }
+
+        let mut names = HashMap::new();
+        // Rendered pages, newest first
+        let mut pages = Vec::new();
+        let mut exported = 0;
+        let mut from = None;
+        while exported < count {
+            let page = context
+                .room
+                .history_page(from.take(), (count - exported).min(PAGE_SIZE))
+                .await?;
+
+            let mut rendered = String::new();
+            for entry in page.entries.iter().rev() {
+                // The export command itself is not part of the transcript
+                if entry.event_id == context.event_id.as_str() {
+                    continue;
+                }
+                if !names.contains_key(&entry.sender) {
+                    let name = context.room.member_display_name(&entry.sender).await;
+                    names.insert(entry.sender.clone(), name);
+                }
+                let name = names[&entry.sender].as_deref();
+                rendered.push_str(&render_entry(context, entry, name, format));
+                exported += 1;
+            }
+            pages.push(rendered);
+
+            match page.next {
+                Some(next) if !page.entries.is_empty() => from = Some(next),
+                _ => break,
+            }
+        }
+
+        if exported == 0 {
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "export.empty",
+                &[],
+            ))));
+        }
+
+        let room_name = context.room.display_name();
+        let date = chrono::Utc::now();
+        let summary = t(
+            context,
+            "export.header",
+            &[
+                ("count", &exported.to_string()),
+                ("date", &date.format("%Y-%m-%d %H:%M UTC").to_string()),
+            ],
+        );
+        let mut transcript = match format {
+            Format::Markdown => format!("# {}\n\n_{}_\n\n", room_name, summary),
+            Format::Plain => format!("{}\n{}\n\n", room_name, summary),
+        };
+        for page in pages.iter().rev() {
+            transcript.push_str(page);
+        }
+
+        let filename = format!(
+            "transcript-{}-{}.{}",
+            slug(&room_name),
+            date.format("%Y-%m-%d"),
+            format.extension()
+        );
+        info!(
+            "📤 Exporting {} messages from {} as {}",
+            exported, room_id, filename
+        );
+
+        let reply = t(
+            context,
+            "export.done",
+            &[("count", &exported.to_string()), ("filename", &filename)],
+        );
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Attachment {
+                filename,
+                content_type: format.content_type().to_string(),
+                data: transcript.into_bytes(),
+            },
+            OutgoingMessage::Notice(reply),
+        ]))
+    }
+}
+
+/// One message of the transcript
+fn render_entry(
+    context: &ResponderContext,
+    entry: &TimelineEntry,
+    display_name: Option<&str>,
+    format: Format,
+) -> String {
+    let time = stats::format_timestamp(entry.timestamp_ms / 1000);
+    let sender = match display_name {
+        Some(name) => format!("{} ({})", name, entry.sender),
+        None => entry.sender.clone(),
+    };
+    let body = match &entry.body {
+        TimelineBody::Text(body) => body.clone(),
+        TimelineBody::Redacted => t(context, "export.redacted", &[]),
+        TimelineBody::Undecryptable => t(context, "export.undecryptable", &[]),
+    };
+
+    match (format, &entry.body) {
+        (Format::Markdown, TimelineBody::Text(_)) => {
+            format!("**{}** · {}\n\n{}\n\n", sender, time, body)
+        }
+        (Format::Markdown, _) => format!("**{}** · {}\n\n_{}_\n\n", sender, time, body),
+        (Format::Plain, _) => format!("[{}] {}: {}\n", time, sender, body.replace('\n', "\n    ")),
+    }
+}
+
+/// Room name reduced to filename-safe characters
+fn slug(name: &str) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let slug = slug
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "room".to_string()
+    } else {
+        slug
+    }
+}
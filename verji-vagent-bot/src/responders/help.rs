@@ -0,0 +1,82 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Lists the available commands (`!help`)
+#[derive(Default)]
+pub struct HelpResponder;
+
+impl HelpResponder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl CommandResponder for HelpResponder {
+    fn name(&self) -> &str {
+        "HelpResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!help"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!help`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        let mut commands = vec![
+            ("!ping", "help.ping"),
+            ("!echo <text>", "help.echo"),
+            ("!stats", "help.stats"),
+            ("!summary [N]", "help.summary"),
+            ("!history [N]", "help.history"),
+            ("!export [N|all] [md|txt]", "help.export"),
+            ("!pin | !unpin | !pins", "help.pin"),
+            ("!quota", "help.quota"),
+            ("!agent list|show", "help.agent_select"),
+            ("!prefs [set|unset <key> [value] [here]]", "help.prefs"),
+            ("!whoami", "help.whoami"),
+            ("!roominfo", "help.roominfo"),
+            ("!status", "help.status"),
+            ("!help", "help.help"),
+        ];
+        if context.sender_is_admin() {
+            commands.push(("!setprompt <text>", "help.setprompt"));
+            commands.push(("!getprompt", "help.getprompt"));
+            commands.push(("!clearprompt", "help.clearprompt"));
+            commands.push(("!admin", "help.admin"));
+        }
+
+        let mut help = format!("{}\n\n", t(context, "help.title", &[]));
+        for (command, description) in commands {
+            help.push_str(&format!(
+                "- `{}` - {}\n",
+                command,
+                t(context, description, &[])
+            ));
+        }
+        help.push_str(&format!("\n{}\n\n", t(context, "help.agent", &[])));
+
+        help.push_str(&format!("{}\n\n", t(context, "help.responders", &[])));
+        for (name, priority) in &context.registered_responders {
+            let priority = priority.to_string();
+            help.push_str(&format!(
+                "- {} ({})\n",
+                name,
+                t(context, "help.priority", &[("priority", &priority)])
+            ));
+        }
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(help),
+        ]))
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::direct;
+use crate::i18n::t;
+use crate::pager;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+use crate::stats;
+
+/// Interactions listed when `!history` has no count
+const DEFAULT_ENTRIES: usize = 10;
+/// Upper bound for the count
+const MAX_ENTRIES: usize = 50;
+/// Interactions per page; longer lists are paged with reactions
+const PAGE_ENTRIES: usize = 10;
+
+/// Shows the sender's recent agent interactions in this room (`!history [N]`)
+///
+/// In group rooms the list is sent by direct message, so it isn't shown to
+/// the other members. Lists longer than a page are sent with the pager.
+#[derive(Default)]
+pub struct HistoryResponder;
+
+impl HistoryResponder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Parse an optional entry count argument
+pub fn parse_count(arg: Option<&String>) -> Option<usize> {
+    match arg.map(|arg| arg.parse::<usize>()) {
+        None => Some(DEFAULT_ENTRIES),
+        Some(Ok(count)) if count > 0 => Some(count.min(MAX_ENTRIES)),
+        Some(_) => None,
+    }
+}
+
+#[async_trait]
+impl CommandResponder for HistoryResponder {
+    fn name(&self) -> &str {
+        "HistoryResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!history"],
+            min_args: 0,
+            max_args: Some(1),
+            admin_only: false,
+            usage: "`!history [number of entries]`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult> {
+        let Some(count) = parse_count(args.first()) else {
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "command.usage",
+                &[("usage", self.spec().usage)],
+            ))));
+        };
+
+        let room_id = context.room.room_id().to_string();
+        let interactions = context
+            .stats
+            .history(&context.identity, Some(&room_id), count)
+            .await?;
+        let title = format!(
+            "**Your recent agent questions in {}**",
+            context.room.display_name()
+        );
+        let pages: Vec<String> = if interactions.len() > PAGE_ENTRIES {
+            interactions
+                .chunks(PAGE_ENTRIES)
+                .map(|chunk| stats::render_history(&title, chunk, false))
+                .collect()
+        } else {
+            vec![stats::render_history(&title, &interactions, false)]
+        };
+
+        if context.room.member_count() <= 2 {
+            if pages.len() > 1 {
+                pager::send(context, context.room.as_ref(), pages).await?;
+                return Ok(ResponderResult::HandledSilently);
+            }
+            return Ok(ResponderResult::HandledWithContent(
+                pages.into_iter().map(OutgoingMessage::Markdown).collect(),
+            ));
+        }
+
+        let sent = match direct::dm_room(&context.runtime, &context.client, &context.sender).await {
+            Ok(dm) => pager::send(context, &dm, pages).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        let reply = match sent {
+            Ok(()) => t(context, "history.sent_dm", &[]),
+            Err(e) => {
+                warn!("Failed to send history to {}: {:#}", context.sender, e);
+                t(context, "history.dm_failed", &[])
+            }
+        };
+        Ok(ResponderResult::Handled(Some(reply)))
+    }
+}
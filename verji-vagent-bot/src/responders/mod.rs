@@ -1,5 +1,37 @@
+pub mod admin;
+pub mod agent_select;
+pub mod echo;
+pub mod export;
+pub mod help;
+pub mod history;
+pub mod pin;
 pub mod pingpong;
+pub mod prefs;
+pub mod prompt;
+pub mod quota;
+pub mod roominfo;
+pub mod shortcut;
+pub mod stats;
+pub mod status;
+pub mod summary;
 pub mod verji_agent;
+pub mod whoami;
 
+pub use admin::AdminResponder;
+pub use agent_select::AgentSelectResponder;
+pub use echo::EchoResponder;
+pub use export::ExportResponder;
+pub use help::HelpResponder;
+pub use history::HistoryResponder;
+pub use pin::PinResponder;
 pub use pingpong::PingPongResponder;
+pub use prefs::PrefsResponder;
+pub use prompt::PromptResponder;
+pub use quota::QuotaResponder;
+pub use roominfo::RoomInfoResponder;
+pub use shortcut::ShortcutResponder;
+pub use stats::StatsResponder;
+pub use status::StatusResponder;
+pub use summary::SummaryResponder;
 pub use verji_agent::VerjiAgentResponder;
+pub use whoami::WhoamiResponder;
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use matrix_sdk::ruma::OwnedEventId;
+use tracing::{info, warn};
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+use crate::room::TimelineBody;
+
+/// Pinned events listed by `!pins`
+const MAX_LISTED: usize = 20;
+/// Characters of each pinned message shown by `!pins`
+const PREVIEW_CHARS: usize = 80;
+
+/// Keeps agent answers visible via the room's pinned events
+///
+/// `!pin` and `!unpin` are sent as replies to one of the bot's messages;
+/// `!pins` lists what is pinned.
+#[derive(Default)]
+pub struct PinResponder;
+
+impl PinResponder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    async fn pin(context: &ResponderContext, target: OwnedEventId) -> Result<String> {
+        let bot_id = context.client.user_id().map(|id| id.to_string());
+        match context.room.fetch_event(&target).await {
+            Ok(Some(entry)) if Some(&entry.sender) == bot_id.as_ref() => {}
+            Ok(Some(_)) => return Ok(t(context, "pin.not_own", &[])),
+            Ok(None) => return Ok(t(context, "pin.not_found", &[])),
+            Err(e) => {
+                warn!("Failed to fetch {} for pinning: {:#}", target, e);
+                return Ok(t(context, "pin.not_found", &[]));
+            }
+        }
+        if !context.room.can_pin().await? {
+            return Ok(t(context, "pin.no_permission", &[]));
+        }
+
+        let mut pinned = context.room.pinned_events().await?;
+        if pinned.contains(&target) {
+            return Ok(t(context, "pin.already", &[]));
+        }
+        pinned.push(target.clone());
+        if !Self::save(context, pinned).await {
+            return Ok(t(context, "pin.no_permission", &[]));
+        }
+        info!(
+            "📌 {} pinned {} in {}",
+            context.sender,
+            target,
+            context.room.room_id()
+        );
+        Ok(t(context, "pin.pinned", &[]))
+    }
+
+    async fn unpin(context: &ResponderContext, target: OwnedEventId) -> Result<String> {
+        let mut pinned = context.room.pinned_events().await?;
+        if !pinned.contains(&target) {
+            return Ok(t(context, "pin.not_pinned", &[]));
+        }
+        if !context.room.can_pin().await? {
+            return Ok(t(context, "pin.no_permission", &[]));
+        }
+
+        pinned.retain(|event_id| *event_id != target);
+        if !Self::save(context, pinned).await {
+            return Ok(t(context, "pin.no_permission", &[]));
+        }
+        info!(
+            "📌 {} unpinned {} in {}",
+            context.sender,
+            target,
+            context.room.room_id()
+        );
+        Ok(t(context, "pin.unpinned", &[]))
+    }
+
+    /// Write the pinned events, false if the server refused
+    ///
+    /// The power level can change between the check and the write.
+    async fn save(context: &ResponderContext, pinned: Vec<OwnedEventId>) -> bool {
+        match context.room.set_pinned_events(pinned).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(
+                    "Failed to update pinned events in {}: {:#}",
+                    context.room.room_id(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    async fn list(context: &ResponderContext) -> Result<String> {
+        let pinned = context.room.pinned_events().await?;
+        if pinned.is_empty() {
+            return Ok(t(context, "pin.none", &[]));
+        }
+
+        let mut list = format!("{}\n\n", t(context, "pin.title", &[]));
+        for event_id in pinned.iter().rev().take(MAX_LISTED) {
+            let link = format!(
+                "https://matrix.to/#/{}/{}",
+                context.room.room_id(),
+                event_id
+            );
+            let preview = match context.room.fetch_event(event_id).await {
+                Ok(Some(entry)) => match entry.body {
+                    TimelineBody::Text(body) => preview(&body),
+                    TimelineBody::Redacted => t(context, "export.redacted", &[]),
+                    TimelineBody::Undecryptable => t(context, "export.undecryptable", &[]),
+                },
+                _ => t(context, "pin.unavailable", &[]),
+            };
+            list.push_str(&format!("- [{}]({})\n", preview, link));
+        }
+        if pinned.len() > MAX_LISTED {
+            list.push_str(&format!(
+                "\n{}",
+                t(
+                    context,
+                    "pin.more",
+                    &[("count", &(pinned.len() - MAX_LISTED).to_string())],
+                )
+            ));
+        }
+        Ok(list)
+    }
+}
+
+#[async_trait]
+impl CommandResponder for PinResponder {
+    fn name(&self) -> &str {
+        "PinResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!pin", "!unpin", "!pins"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!pin` or `!unpin` as a reply to one of my messages, or `!pins`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        let command = context
+            .message_body
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let response = match (command.as_str(), context.in_reply_to.clone()) {
+            ("!pins", _) => Self::list(context).await?,
+            ("!pin", Some(target)) => Self::pin(context, target).await?,
+            ("!unpin", Some(target)) => Self::unpin(context, target).await?,
+            _ => t(context, "pin.not_reply", &[]),
+        };
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(response),
+        ]))
+    }
+}
+
+/// First line of a message, shortened for a list
+fn preview(body: &str) -> String {
+    let line = body
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    let mut preview: String = line.chars().take(PREVIEW_CHARS).collect();
+    if line.chars().count() > PREVIEW_CHARS {
+        preview.push('…');
+    }
+    // Brackets would end the Markdown link text early
+    preview.replace(['[', ']'], "")
+}
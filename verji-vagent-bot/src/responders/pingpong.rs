@@ -1,9 +1,12 @@
 use anyhow::Result;
 use async_trait::async_trait;
 
-use crate::responder::{Responder, ResponderContext, ResponderResult};
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{ResponderContext, ResponderResult};
 
 /// Simple ping-pong responder for health checks
+#[derive(Default)]
 pub struct PingPongResponder;
 
 impl PingPongResponder {
@@ -13,7 +16,7 @@ impl PingPongResponder {
 }
 
 #[async_trait]
-impl Responder for PingPongResponder {
+impl CommandResponder for PingPongResponder {
     fn name(&self) -> &str {
         "PingPongResponder"
     }
@@ -22,12 +25,17 @@ impl Responder for PingPongResponder {
         100 // High priority for simple commands
     }
 
-    async fn should_handle(&self, context: &ResponderContext) -> bool {
-        let msg = context.message_body.trim().to_lowercase();
-        msg == "ping" || msg == "!ping"
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!ping", "ping"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!ping`",
+        }
     }
 
-    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
-        Ok(ResponderResult::Handled(Some("Pong!".to_string())))
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some(t(context, "pong", &[]))))
     }
 }
@@ -0,0 +1,173 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::preferences::{PrefKey, UserPreferences};
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Argument that scopes `set` and `unset` to the current room
+const HERE: &str = "here";
+
+/// Shows and changes the sender's preferences (`!prefs`)
+///
+/// `!prefs set <key> <value> [here]` and `!prefs unset <key> [here]` change
+/// them for all rooms, or with `here` only for this one; `!prefs <key> <value>`
+/// is short for `set`.
+#[derive(Default)]
+pub struct PrefsResponder;
+
+impl PrefsResponder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn show(context: &ResponderContext) -> String {
+        let room_id = context.room.room_id().as_str();
+        let global = context.preferences.scoped(&context.identity, None);
+        let room = context.preferences.scoped(&context.identity, Some(room_id));
+
+        let mut out = format!("{}\n\n", t(context, "prefs.title", &[]));
+        for key in PrefKey::ALL {
+            let (value, source) = match (room.get(key), global.get(key)) {
+                (Some(value), _) => (value, t(context, "prefs.source_room", &[])),
+                (None, Some(value)) => (value, t(context, "prefs.source_all", &[])),
+                (None, None) => (
+                    Self::default_value(context, key),
+                    t(context, "prefs.source_default", &[]),
+                ),
+            };
+            out.push_str(&format!("- `{}`: {} ({})\n", key.name(), value, source));
+        }
+        out.push_str(&format!("\n{}", t(context, "prefs.footer", &[])));
+        out
+    }
+
+    /// What applies when the user set nothing
+    fn default_value(context: &ResponderContext, key: PrefKey) -> String {
+        let on_off = |on: bool| if on { "on" } else { "off" }.to_string();
+        let defaults = UserPreferences::default();
+        match key {
+            PrefKey::Address => on_off(defaults.address_by_name()),
+            PrefKey::DelayNotices => on_off(defaults.delay_notices()),
+            PrefKey::Language => context
+                .room_config
+                .language
+                .clone()
+                .unwrap_or_else(|| context.config.locale.clone()),
+            PrefKey::TranscriptFormat => "md".to_string(),
+        }
+    }
+
+    async fn change(
+        context: &ResponderContext,
+        key: &str,
+        value: Option<&str>,
+        here: bool,
+    ) -> Result<String> {
+        let Some(key) = PrefKey::parse(key) else {
+            return Ok(t(
+                context,
+                "prefs.unknown_key",
+                &[("key", key), ("keys", &PrefKey::names())],
+            ));
+        };
+        // Validate before writing, so a typo leaves the stored value alone
+        if let Some(value) = value {
+            let catalog = &context.runtime.catalog;
+            if !UserPreferences::default().set(key, value, catalog) {
+                return Ok(t(
+                    context,
+                    "prefs.invalid_value",
+                    &[
+                        ("key", key.name()),
+                        ("value", value),
+                        ("accepted", &key.accepted(catalog)),
+                    ],
+                ));
+            }
+        }
+
+        let room_id = here.then(|| context.room.room_id().as_str());
+        context
+            .preferences
+            .update(&context.identity, room_id, |prefs| match value {
+                Some(value) => {
+                    prefs.set(key, value, &context.runtime.catalog);
+                }
+                None => prefs.unset(key),
+            })
+            .await?;
+
+        let scope = if here {
+            t(context, "prefs.source_room", &[])
+        } else {
+            t(context, "prefs.source_all", &[])
+        };
+        info!(
+            "🙋 {} changed preference {} ({})",
+            context.sender,
+            key.name(),
+            if here { "this room" } else { "all rooms" }
+        );
+        Ok(match value {
+            Some(value) => t(
+                context,
+                "prefs.set",
+                &[("key", key.name()), ("value", value), ("scope", &scope)],
+            ),
+            None => t(
+                context,
+                "prefs.unset",
+                &[("key", key.name()), ("scope", &scope)],
+            ),
+        })
+    }
+}
+
+#[async_trait]
+impl CommandResponder for PrefsResponder {
+    fn name(&self) -> &str {
+        "PrefsResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!prefs"],
+            min_args: 0,
+            max_args: Some(4),
+            admin_only: false,
+            usage: "`!prefs`, `!prefs set <key> <value> [here]` or `!prefs unset <key> [here]`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult> {
+        let here = args.len() > 1
+            && args
+                .last()
+                .is_some_and(|arg| arg.eq_ignore_ascii_case(HERE));
+        let args = if here {
+            &args[..args.len() - 1]
+        } else {
+            &args[..]
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        let response = match args[..] {
+            [] if !here => Self::show(context),
+            [command, key, value] if command.eq_ignore_ascii_case("set") => {
+                Self::change(context, key, Some(value), here).await?
+            }
+            [command, key] if command.eq_ignore_ascii_case("unset") => {
+                Self::change(context, key, None, here).await?
+            }
+            [key, value] => Self::change(context, key, Some(value), here).await?,
+            _ => t(context, "command.usage", &[("usage", self.spec().usage)]),
+        };
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(response),
+        ]))
+    }
+}
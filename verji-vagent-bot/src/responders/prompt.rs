@@ -0,0 +1,119 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Longest system prompt accepted by `!setprompt`
+const MAX_PROMPT_CHARS: usize = 2000;
+
+/// Manages the room's agent system prompt (`!setprompt`, `!getprompt`, `!clearprompt`)
+///
+/// The prompt lives in the room config and is sent to vagent-graph with every
+/// query from the room.
+#[derive(Default)]
+pub struct PromptResponder;
+
+impl PromptResponder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    async fn set(context: &ResponderContext, prompt: &str) -> Result<String> {
+        let length = prompt.chars().count();
+        if length > MAX_PROMPT_CHARS {
+            return Ok(t(
+                context,
+                "prompt.too_long",
+                &[
+                    ("length", &length.to_string()),
+                    ("max", &MAX_PROMPT_CHARS.to_string()),
+                ],
+            ));
+        }
+
+        let config = context
+            .room_configs
+            .update(context.room.as_ref(), |config| {
+                config.system_prompt = Some(prompt.to_string())
+            })
+            .await?;
+        info!(
+            "🎭 System prompt of {} set by {} ({} chars)",
+            context.room.room_id(),
+            context.sender,
+            length
+        );
+
+        // Confirm with what is now stored, not what was typed
+        let active = config.system_prompt.unwrap_or_default();
+        Ok(t(context, "prompt.set", &[("prompt", &quote(&active))]))
+    }
+
+    fn get(context: &ResponderContext) -> String {
+        match &context.room_config.system_prompt {
+            Some(prompt) => t(context, "prompt.current", &[("prompt", &quote(prompt))]),
+            None => t(context, "prompt.none", &[]),
+        }
+    }
+
+    async fn clear(context: &ResponderContext) -> Result<String> {
+        context
+            .room_configs
+            .update(context.room.as_ref(), |config| config.system_prompt = None)
+            .await?;
+        info!(
+            "🎭 System prompt of {} cleared by {}",
+            context.room.room_id(),
+            context.sender
+        );
+        Ok(t(context, "prompt.cleared", &[]))
+    }
+}
+
+#[async_trait]
+impl CommandResponder for PromptResponder {
+    fn name(&self) -> &str {
+        "PromptResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!setprompt", "!getprompt", "!clearprompt"],
+            min_args: 0,
+            max_args: None,
+            admin_only: true,
+            usage: "`!setprompt <text>`, `!getprompt` or `!clearprompt`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        // The prompt is taken verbatim, so quotes and line breaks survive
+        let body = context.message_body.trim();
+        let (command, text) = body
+            .split_once(char::is_whitespace)
+            .map(|(command, text)| (command, text.trim()))
+            .unwrap_or((body, ""));
+
+        let response = match command.to_lowercase().as_str() {
+            "!setprompt" if !text.is_empty() => Self::set(context, text).await?,
+            "!getprompt" => Self::get(context),
+            "!clearprompt" => Self::clear(context).await?,
+            _ => t(context, "command.usage", &[("usage", self.spec().usage)]),
+        };
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(response),
+        ]))
+    }
+}
+
+/// Render text as a Markdown block quote
+fn quote(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::clock::{Clock, SystemClock};
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Shows the sender's daily agent query usage (`!quota`)
+#[derive(Default)]
+pub struct QuotaResponder;
+
+impl QuotaResponder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A user's queries today against their limit in the current room
+pub fn render_daily_usage(context: &ResponderContext, user_id: &str) -> String {
+    let policy = &context.config.daily_quota;
+    let now_ms = SystemClock.now_ms();
+    let used = context
+        .stats
+        .daily_queries(user_id, &policy.day(now_ms))
+        .to_string();
+
+    let limit = policy.limit(
+        context.room.room_id().as_str(),
+        context.stats.quota_override(user_id),
+    );
+    match limit {
+        _ if context.config.is_admin(user_id) => {
+            t(context, "quota.unlimited_admin", &[("used", &used)])
+        }
+        None => t(context, "quota.unlimited", &[("used", &used)]),
+        Some(limit) => {
+            let reset = policy.next_reset(now_ms).format("%H:%M %Z").to_string();
+            t(
+                context,
+                "quota.usage",
+                &[
+                    ("used", &used),
+                    ("limit", &limit.to_string()),
+                    ("time", &reset),
+                ],
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl CommandResponder for QuotaResponder {
+    fn name(&self) -> &str {
+        "QuotaResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!quota"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!quota`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(render_daily_usage(context, &context.identity)),
+        ]))
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::archive::ArchiveWatch;
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::kill_switch::KillSwitch;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Shows the room's settings, the agent it talks to, its kill switch and
+/// whether it is archived (`!roominfo`)
+///
+/// While the kill switch is on only bot admins get an answer, see
+/// `KillSwitchMiddleware`.
+pub struct RoomInfoResponder {
+    archive: Arc<ArchiveWatch>,
+    kill_switch: Arc<KillSwitch>,
+}
+
+impl RoomInfoResponder {
+    pub fn new(archive: Arc<ArchiveWatch>, kill_switch: Arc<KillSwitch>) -> Self {
+        Self {
+            archive,
+            kill_switch,
+        }
+    }
+}
+
+#[async_trait]
+impl CommandResponder for RoomInfoResponder {
+    fn name(&self) -> &str {
+        "RoomInfoResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!roominfo"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!roominfo`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        let room = context.room.as_ref();
+        let room_id = room.room_id().to_string();
+        let name = room.display_name();
+        let members = room.member_count().to_string();
+        let language = context
+            .room_config
+            .language
+            .clone()
+            .unwrap_or_else(|| context.config.locale.clone());
+        let reply_mode = context
+            .room_config
+            .reply_mode
+            .unwrap_or(context.config.reply_mode)
+            .as_str();
+        // The `!agent` selection, as agent queries from the room are routed
+        let agent = match context
+            .config
+            .agent_for(context.room_config.agent.as_deref())
+        {
+            Some(agent) => format!("`{}`", agent),
+            None => t(context, "roominfo.agent_default", &[]),
+        };
+        let kill_switch = match self.kill_switch.check(room).await {
+            Some(disabled) => match disabled.reason {
+                Some(reason) => t(
+                    context,
+                    "roominfo.kill_switch_reason",
+                    &[("reason", &reason)],
+                ),
+                None => t(context, "roominfo.kill_switch_on", &[]),
+            },
+            None => t(context, "roominfo.kill_switch_off", &[]),
+        };
+        let archived = match self.archive.check(room).await {
+            Some(trigger) => {
+                let (id, value) = trigger.describe();
+                t(context, id, &[("value", value)])
+            }
+            None => t(context, "roominfo.not_archived", &[]),
+        };
+
+        let mut info = t(
+            context,
+            "roominfo.summary",
+            &[
+                ("name", &name),
+                ("room_id", &room_id),
+                ("members", &members),
+                ("language", &language),
+                ("reply_mode", reply_mode),
+                ("agent", &agent),
+                ("kill_switch", &kill_switch),
+                ("archived", &archived),
+            ],
+        );
+        if let Some(alias) = room.canonical_alias() {
+            info.push_str(&format!(
+                "\n{}",
+                t(context, "roominfo.alias", &[("alias", &alias)])
+            ));
+        }
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(info),
+        ]))
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+use crate::responder::{Responder, ResponderContext, ResponderResult, Route};
+
+/// Expands prompt shortcuts (`/sql ...`) and hands the result back to the chain
+///
+/// Templates come from `PROMPT_SHORTCUTS`; `{}` is replaced by the rest of the
+/// message. The rewritten message is usually answered by the agent responder.
+pub struct ShortcutResponder {
+    shortcuts: HashMap<String, String>,
+}
+
+impl ShortcutResponder {
+    pub fn new(shortcuts: HashMap<String, String>) -> Self {
+        Self {
+            shortcuts: shortcuts
+                .into_iter()
+                .map(|(shortcut, template)| (shortcut.to_lowercase(), template))
+                .collect(),
+        }
+    }
+
+    /// The shortcut `word` names; only mixed-case words are lowercased
+    fn lookup(&self, word: &str) -> Option<(&String, &String)> {
+        if word.chars().any(char::is_uppercase) {
+            self.shortcuts.get_key_value(&word.to_lowercase())
+        } else {
+            self.shortcuts.get_key_value(word)
+        }
+    }
+
+    fn split(message: &str) -> (&str, &str) {
+        let message = message.trim();
+        message
+            .split_once(char::is_whitespace)
+            .unwrap_or((message, ""))
+    }
+
+    fn expand(&self, message: &str) -> Option<(&str, String)> {
+        let (shortcut, rest) = Self::split(message);
+        let (shortcut, template) = self.lookup(shortcut)?;
+
+        let expanded = if template.contains("{}") {
+            template.replace("{}", rest.trim())
+        } else {
+            format!("{} {}", template, rest.trim())
+        };
+        Some((shortcut.as_str(), expanded))
+    }
+}
+
+#[async_trait]
+impl Responder for ShortcutResponder {
+    fn name(&self) -> &str {
+        "ShortcutResponder"
+    }
+
+    fn priority(&self) -> i32 {
+        50 // Preprocessor: below commands, above the agent
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        self.route(context).await == Route::Accept
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        match self.lookup(Self::split(&context.message_body).0) {
+            Some(_) => Route::Accept,
+            None => Route::decline("no_shortcut"),
+        }
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let Some((shortcut, expanded)) = self.expand(&context.message_body) else {
+            return Ok(ResponderResult::NotHandled);
+        };
+
+        Ok(ResponderResult::Rewritten(Box::new(
+            context.rewrite(expanded, "shortcut", shortcut),
+        )))
+    }
+}
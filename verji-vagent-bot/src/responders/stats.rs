@@ -0,0 +1,49 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::db;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Shows agent usage statistics for the current room (`!stats`)
+#[derive(Default)]
+pub struct StatsResponder;
+
+impl StatsResponder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl CommandResponder for StatsResponder {
+    fn name(&self) -> &str {
+        "StatsResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!stats"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!stats`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        let room_id = context.room.room_id().as_str();
+        let mut table = context.stats.render_room_table(room_id);
+
+        let policy = &context.config.room_budget;
+        let now_ms = db::now_secs() * 1000;
+        let budget = context.room_config.monthly_budget;
+        if policy.limit(budget).is_some() {
+            let spent = context.stats.room_spend(room_id, &policy.period(now_ms));
+            table.push_str(&format!("\n{}", policy.status(&spent, budget, now_ms)));
+        }
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(table),
+        ]))
+    }
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::maintenance::MaintenanceMode;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Shows the bot's version, its device name and whether maintenance mode is
+/// on (`!status`)
+///
+/// A command, so it still answers while maintenance holds back questions.
+pub struct StatusResponder {
+    maintenance: Arc<MaintenanceMode>,
+}
+
+impl StatusResponder {
+    pub fn new(maintenance: Arc<MaintenanceMode>) -> Self {
+        Self { maintenance }
+    }
+}
+
+#[async_trait]
+impl CommandResponder for StatusResponder {
+    fn name(&self) -> &str {
+        "StatusResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!status"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!status`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        let state = self.maintenance.state();
+        let maintenance = if state.enabled {
+            let message = state
+                .message
+                .unwrap_or_else(|| t(context, "maintenance.notice", &[]));
+            t(context, "status.maintenance_on", &[("message", &message)])
+        } else {
+            t(context, "status.maintenance_off", &[])
+        };
+
+        let status = t(
+            context,
+            "status.summary",
+            &[
+                ("version", env!("CARGO_PKG_VERSION")),
+                ("device", &context.config.device_display_name),
+                ("maintenance", &maintenance),
+            ],
+        );
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(status),
+        ]))
+    }
+}
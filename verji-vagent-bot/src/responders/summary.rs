@@ -0,0 +1,121 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::config;
+use crate::i18n::t;
+use crate::redis_client::{GraphMessageType, GraphRequest};
+use crate::request_id;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+use crate::responders::VerjiAgentResponder;
+use crate::room_context;
+
+/// Messages summarized when `!summary` has no count
+const DEFAULT_MESSAGES: usize = 50;
+/// Upper bound for the message count
+const MAX_MESSAGES: usize = 500;
+
+/// Summarizes recent room messages through vagent-graph (`!summary [N]`)
+///
+/// Sent as a `summarize` command, so the graph answers without adding the
+/// transcript to the conversation's memory.
+pub struct SummaryResponder {
+    agent: Arc<VerjiAgentResponder>,
+    max_bytes: usize,
+}
+
+impl SummaryResponder {
+    pub fn new(agent: Arc<VerjiAgentResponder>) -> Self {
+        Self {
+            agent,
+            max_bytes: config::env_u64("SUMMARY_MAX_BYTES", 32 * 1024) as usize,
+        }
+    }
+}
+
+#[async_trait]
+impl CommandResponder for SummaryResponder {
+    fn name(&self) -> &str {
+        "SummaryResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!summary"],
+            min_args: 0,
+            max_args: Some(1),
+            admin_only: false,
+            usage: "`!summary [number of messages]`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, args: Vec<String>) -> Result<ResponderResult> {
+        let count = match args.first().map(|arg| arg.parse::<usize>()) {
+            None => DEFAULT_MESSAGES,
+            Some(Ok(count)) if count > 0 => count.min(MAX_MESSAGES),
+            Some(_) => {
+                return Ok(ResponderResult::Handled(Some(t(
+                    context,
+                    "command.usage",
+                    &[("usage", self.spec().usage)],
+                ))))
+            }
+        };
+
+        let tenant = context.tenants.resolve(context.room.as_ref()).await;
+        if context.tenants.required && tenant.id().is_none() {
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "tenant.missing",
+                &[],
+            ))));
+        }
+
+        let mut messages =
+            room_context::fetch_recent(context.room.as_ref(), &context.sender, count).await?;
+        let fetched = messages.len();
+        if let Some(redactor) = &context.config.redactor {
+            room_context::redact(&mut messages, redactor);
+        }
+        let messages = room_context::cap_bytes(messages, self.max_bytes);
+        if messages.is_empty() {
+            return Ok(ResponderResult::Handled(Some(t(
+                context,
+                "summary.empty",
+                &[],
+            ))));
+        }
+        info!(
+            "📝 Summarizing {} messages ({} fetched) in {}",
+            messages.len(),
+            fetched,
+            context.room.room_id()
+        );
+
+        let mut request = GraphRequest::command(
+            "summarize",
+            serde_json::json!({ "messages": messages, "language": context.language() }),
+            context.room.room_id().to_string(),
+            context.identity.to_string(),
+        );
+        request.request_id = request_id::for_message(context);
+        request.metadata.tenant_id = tenant.id().map(str::to_string);
+        let response = match self.agent.service().run_command(request).await {
+            Ok(message) if message.message_type != GraphMessageType::Error => message.content,
+            Ok(message) => {
+                warn!("vagent-graph could not summarize: {}", message.content);
+                t(context, "summary.failed", &[])
+            }
+            Err(e) => {
+                warn!("Summary request failed: {:#}", e);
+                t(context, "summary.failed", &[])
+            }
+        };
+
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Notice(response),
+        ]))
+    }
+}
@@ -1,49 +1,319 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use futures::StreamExt;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{info, warn};
+use std::time::{Duration, Instant};
+use tracing::{field, info, info_span, warn, Span};
 
-use crate::redis_client::RedisGraphClient;
-use crate::responder::{Responder, ResponderContext, ResponderResult};
+use crate::agent_service::{AgentEvent, AgentService, AskOptions, CircuitOpen};
+use crate::config;
+use crate::db;
+use crate::error::BotError;
+use crate::feedback::AnsweredRequest;
+use crate::hitl::{self, Answer, Form, PendingHitl, HITL_SLOT};
+use crate::i18n::t;
+use crate::observer::QueryOutcome;
+use crate::outbound_webhook::{Exchange, OutboundWebhooks};
+use crate::profiling::StageTimer;
+use crate::progress::{self, ProgressFeed, ProgressUpdate};
+use crate::redis_client::{GraphMessageType, GraphRequest, RequestKind};
+use crate::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+use crate::response_cache::{CacheKey, ResponseCache};
+use crate::replay::{FailedRequest, FailedRequests};
+use crate::request_id;
+use crate::retry;
+use crate::room_config::TranslateMode;
+use crate::room_context;
+use crate::runtime::Runtime;
+use crate::sent_events::SentKind;
+use crate::stats::TokenUsage;
+use crate::shadow;
+use crate::tenant::Tenant;
+use crate::translation::Translator;
+use crate::transport::TransportConfig;
 
-/// Verji AI Agent responder backed by LangGraph via Redis
+/// Conversation slot marking that the user has talked to the agent recently
+const SESSION_SLOT: &str = "agent.session";
+/// How long after an answer follow-up questions count as the same session
+const SESSION_IDLE: Duration = Duration::from_secs(30 * 60);
+/// Time for the legs that don't talk to the graph (tenant, room context, sends)
+const HANDLE_MARGIN: Duration = Duration::from_secs(15);
+
+/// Why an agent query got no answer; selects the reply's message ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentFailure {
+    /// vagent-graph can't be reached
+    Unavailable,
+    /// Connecting is paused after a recent failure
+    CircuitOpen,
+    /// No final answer before `AGENT_TIMEOUT_SECS`
+    Timeout,
+    /// The request failed after it was sent
+    Failed,
+    /// vagent-graph answered with an error
+    GraphError,
+}
+
+impl AgentFailure {
+    /// Failure class of an error from connecting to vagent-graph
+    pub fn of_connect(error: &anyhow::Error) -> Self {
+        if error.downcast_ref::<CircuitOpen>().is_some() {
+            AgentFailure::CircuitOpen
+        } else {
+            AgentFailure::Unavailable
+        }
+    }
+
+    /// Failure class of an error from a sent request
+    pub fn of_request(error: &anyhow::Error) -> Self {
+        if error.downcast_ref::<CircuitOpen>().is_some() {
+            return AgentFailure::CircuitOpen;
+        }
+        match BotError::classify(error) {
+            Some(BotError::GraphTimeout) => AgentFailure::Timeout,
+            Some(BotError::GraphUnavailable | BotError::NetworkTransient) => AgentFailure::Unavailable,
+            _ => AgentFailure::Failed,
+        }
+    }
+
+    pub fn message_id(&self) -> &'static str {
+        match self {
+            AgentFailure::Unavailable => "agent.unavailable",
+            AgentFailure::CircuitOpen => "agent.circuit_open",
+            AgentFailure::Timeout => "agent.timeout",
+            AgentFailure::Failed => "agent.failed",
+            AgentFailure::GraphError => "agent.graph_error",
+        }
+    }
+
+    /// The user-facing reply, with the question and request ID if configured
+    pub fn reply(&self, context: &ResponderContext, request_id: &str) -> String {
+        let mut reply = t(context, self.message_id(), &[]);
+        if context.config.error_echo_message {
+            reply.push('\n');
+            reply.push_str(&t(context, "agent.you_said", &[("message", &context.message_body)]));
+        }
+        if context.config.error_show_reference {
+            reply.push('\n');
+            reply.push_str(&t(context, "agent.reference", &[("reference", request_id)]));
+        }
+        reply
+    }
+}
+
+/// Verji AI Agent responder backed by LangGraph via Redis or gRPC
 /// This is the default responder (no prefix/codeword required)
+///
+/// Turns messages into requests for [`AgentService`] and its events into
+/// replies; the connection itself is the service's.
 pub struct VerjiAgentResponder {
-    redis_client: Arc<Mutex<Option<RedisGraphClient>>>,
-    redis_url: String,
+    service: Arc<AgentService>,
+    /// Answers to repeated questions (None = `RESPONSE_CACHE_TTL_SECS` unset)
+    cache: Option<ResponseCache>,
+    /// Whether cached answers get a "(cached)" marker
+    mark_cached: bool,
+    /// External endpoints told about every finished exchange
+    outbound: Option<Arc<OutboundWebhooks>>,
+    /// Where failed queries are kept for `!admin replay-failed`
+    failed: Option<Arc<FailedRequests>>,
+    /// Translations for rooms with `translate_to` set
+    translator: Translator,
+    /// Retries of queries refused as too large (`CONTEXT_RETRIES`), for the timeout
+    context_retries: u32,
 }
 
 impl VerjiAgentResponder {
-    pub fn new() -> Self {
-        let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
+    /// Talk to vagent-graph as configured in the environment, on the bot's runtime
+    pub fn new(runtime: &Runtime) -> Result<Self> {
+        Ok(Self::with_service(Arc::new(AgentService::from_env()?.with_runtime(runtime))))
+    }
+
+    /// Talk to vagent-graph through `transport_config` (e.g. a mock in tests)
+    pub fn with_transport(transport_config: TransportConfig) -> Self {
+        Self::with_service(Arc::new(AgentService::new(transport_config)))
+    }
+
+    /// Ask through a service shared with other parts of the bot
+    pub fn with_service(service: Arc<AgentService>) -> Self {
+        let cache_ttl = config::env_u64("RESPONSE_CACHE_TTL_SECS", 0);
+        let cache = (cache_ttl > 0).then(|| {
+            ResponseCache::new(
+                Duration::from_secs(cache_ttl),
+                config::env_u64("RESPONSE_CACHE_MAX_ENTRIES", 500) as usize,
+            )
+            .with_metrics(Arc::clone(service.metrics()))
+        });
 
         Self {
-            redis_client: Arc::new(Mutex::new(None)),
-            redis_url,
+            service,
+            cache,
+            mark_cached: config::env_bool("RESPONSE_CACHE_MARK", false),
+            outbound: None,
+            failed: None,
+            translator: Translator::from_env(Arc::clone(service.metrics())),
+            context_retries: config::env_u64("CONTEXT_RETRIES", 2) as u32,
         }
     }
 
-    /// Ensure Redis client is connected (lazy initialization)
-    async fn ensure_connected(&self) -> Result<()> {
-        let mut client_guard = self.redis_client.lock().await;
+    /// The connection to vagent-graph, for requests that aren't user queries
+    pub fn service(&self) -> &Arc<AgentService> {
+        &self.service
+    }
 
-        if client_guard.is_none() {
-            info!("Initializing Redis connection to vagent-graph");
-            match RedisGraphClient::new(&self.redis_url).await {
-                Ok(client) => {
-                    *client_guard = Some(client);
-                    info!("✅ Connected to vagent-graph via Redis");
-                }
-                Err(e) => {
-                    warn!("Failed to connect to Redis: {}", e);
-                    return Err(e);
-                }
+    /// Report finished exchanges to outbound webhooks
+    pub fn with_outbound_webhooks(mut self, outbound: Option<Arc<OutboundWebhooks>>) -> Self {
+        self.outbound = outbound;
+        self
+    }
+
+    fn new_query(context: &ResponderContext, room_id: &str) -> GraphRequest {
+        GraphRequest::new(
+            RequestKind::Query,
+            request_id::for_message(context),
+            context.message_body.to_string(),
+            room_id.to_string(),
+            context.identity.to_string(),
+        )
+        .with_payload(context.custom_event.clone())
+    }
+
+    /// Room settings, tenant, thread and redaction every request gets before it is sent
+    fn prepare(context: &ResponderContext, request: &mut GraphRequest, tenant: &Tenant) {
+        request.metadata.thread_id = context.thread_id.clone();
+        request.metadata.system_prompt = context.room_config.system_prompt.clone();
+        request.metadata.tenant_id = tenant.id().map(str::to_string);
+        request.agent = context.config.agent_for(context.room_config.agent.as_deref());
+        if let Some(redactor) = &context.config.redactor {
+            request.query = redactor.redact(&request.query);
+        }
+    }
+
+    /// The answer with the room's translation below it or in its place
+    ///
+    /// Without `translate_to`, or if translating fails, the answer is kept as is.
+    async fn with_translation(&self, context: &ResponderContext, tenant: &Tenant, answer: String) -> String {
+        let Some(target) = context.room_config.translate_to.as_deref() else {
+            return answer;
+        };
+        let Some(translation) = self.translator.translate(&self.service, context, tenant, "answer", &answer, target).await else {
+            return answer;
+        };
+        match context.room_config.translate_mode.unwrap_or_default() {
+            TranslateMode::Replace => translation,
+            TranslateMode::Below => {
+                let label = t(context, "translation.label", &[("language", target)]);
+                format!("{}\n\n---\n{}\n\n{}", answer, label, translation)
             }
         }
+    }
+
+    /// Reply to a failed request, offering a 🔁 retry while the query has some left
+    async fn failure_reply(
+        context: &ResponderContext,
+        failure: AgentFailure,
+        request: &GraphRequest,
+        retryable: bool,
+    ) -> String {
+        context.sent_events.link_failure(&context.event_id, &request.request_id);
+        let mut reply = failure.reply(context, &request.request_id);
+        // Retries re-submit the text, which a custom event doesn't have
+        let retryable = retryable && context.custom_event.is_none();
+        if retryable && retry::remember(context, &request.request_id).await {
+            reply.push('\n');
+            reply.push_str(&t(context, "agent.retry_hint", &[("reaction", retry::RETRY_REACTION)]));
+        }
+        reply
+    }
+
+    /// Journal queries that fail so they can be replayed later
+    pub fn with_failure_journal(mut self, failed: Arc<FailedRequests>) -> Self {
+        self.failed = Some(failed);
+        self
+    }
+
+    /// Keep a new query that got no answer for replay
+    async fn journal_failure(&self, context: &ResponderContext, request: &GraphRequest, reason: String) {
+        let Some(failed) = self.failed.as_ref().filter(|_| request.kind == RequestKind::Query) else {
+            return;
+        };
+        failed
+            .record(FailedRequest {
+                request: request.clone(),
+                event_id: context.event_id.to_string(),
+                language: context.language(),
+                reason,
+                failed_at: db::now_secs(),
+            })
+            .await;
+    }
+
+    /// Cache key for a new query, or None if its answer must not be cached
+    ///
+    /// Follow-ups in an ongoing session may lean on earlier turns ("and the
+    /// guest network?"), so only session openers are cached. Threads follow
+    /// up on their root, so nothing asked in one is cached.
+    async fn cache_key(&self, context: &ResponderContext, request: &GraphRequest) -> Option<CacheKey> {
+        self.cache.as_ref()?;
+        if request.kind != RequestKind::Query || context.thread_id.is_some() || request.payload.is_some() {
+            return None;
+        }
+        if context.conversations.get(&context.conversation_key(SESSION_SLOT)).await.is_some() {
+            return None;
+        }
+        Some(CacheKey::new(
+            &request.metadata.room_id,
+            request.agent.as_deref(),
+            request.metadata.system_prompt.as_deref(),
+            &request.query,
+        ))
+    }
 
-        Ok(())
+    /// Drop all cached answers (they aren't keyed by user, so erasure clears everything)
+    pub fn clear_response_cache(&self, dry_run: bool) -> usize {
+        match &self.cache {
+            Some(cache) if dry_run => cache.entry_count(),
+            Some(cache) => cache.clear(),
+            None => 0,
+        }
     }
+
+    /// Add recent room messages to a new query, trimmed to the token budget
+    ///
+    /// Context is best-effort: if the timeline can't be read the query goes
+    /// out without it.
+    async fn attach_context(&self, context: &ResponderContext, request: &mut GraphRequest, timer: &mut StageTimer) {
+        let limit = context.config.context_messages;
+        if limit == 0 {
+            return;
+        }
+
+        // One extra, since the triggering message is usually the newest
+        let messages = match room_context::fetch_recent(context.room.as_ref(), &context.sender, limit + 1).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                warn!("Failed to fetch room context, querying without it: {:#}", e);
+                return;
+            }
+        };
+        timer.mark("context");
+        let trigger_id = context.event_id.to_string();
+        let mut messages: Vec<_> = messages.into_iter().filter(|message| message.event_id != trigger_id).collect();
+        let excess = messages.len().saturating_sub(limit);
+        messages.drain(..excess);
+
+        // Redact first so the budget is measured on what is actually sent
+        if let Some(redactor) = &context.config.redactor {
+            room_context::redact(&mut messages, redactor);
+        }
+        let (messages, trim) =
+            room_context::trim_to_budget(messages, &request.query, context.config.context_token_budget);
+        request.context = messages;
+        if trim != Default::default() {
+            request.metadata.context_trim = Some(trim);
+        }
+        timer.mark("redact_trim");
+    }
+
 }
 
 #[async_trait]
@@ -57,79 +327,452 @@ impl Responder for VerjiAgentResponder {
         10
     }
 
+    fn timeout(&self) -> Option<Duration> {
+        // Only a backstop: every graph leg is bounded by AGENT_TIMEOUT_SECS,
+        // and its own timeout reply (journaled, with a retry button) must come
+        // first. The legs are the question, its retries with less context and
+        // translating question and answer.
+        let graph_legs = 1 + self.context_retries + 2;
+        Some(self.service.timeout() * graph_legs + HANDLE_MARGIN)
+    }
+
     async fn should_handle(&self, _context: &ResponderContext) -> bool {
         // Handle everything that reaches this point (default responder)
         true
     }
 
     async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        // The body itself was logged when the message arrived
         info!(
-            "🤖 VerjiAgent handling message: {}",
-            context.message_body
+            "🤖 VerjiAgent handling a message ({} bytes) from {}",
+            context.message_body.len(),
+            context.sender
         );
 
-        // Try to connect to Redis if not connected
-        if let Err(e) = self.ensure_connected().await {
-            warn!("Redis unavailable, falling back to local echo: {}", e);
-            let response = format!(
-                "[Offline Mode - Redis unavailable]\nYou said: {}",
-                context.message_body
+        let started = Instant::now();
+        let asked_at_ms = db::now_secs() * 1000;
+        let room_id = context.room.room_id().to_string();
+
+        let tenant = context.tenants.resolve(context.room.as_ref()).await;
+        if context.tenants.required && tenant.id().is_none() {
+            info!("🏢 Not forwarding query from {}: no tenant ({})", room_id, tenant);
+            return Ok(ResponderResult::Handled(Some(t(context, "tenant.missing", &[]))));
+        }
+
+        // Try to connect to vagent-graph if not connected
+        if let Err(e) = self.service.connect().await {
+            let failure = AgentFailure::of_connect(&e);
+            warn!("vagent-graph unavailable ({:?}): {:#}", failure, e);
+            let mut request = Self::new_query(context, &room_id);
+            // A message answering a pending HITL question can't be replayed on its own
+            let answers_hitl = context.conversations.get(&context.conversation_key(HITL_SLOT)).await.is_some();
+            if !answers_hitl {
+                Self::prepare(context, &mut request, &tenant);
+                self.journal_failure(context, &request, format!("vagent-graph unavailable: {:#}", e)).await;
+            }
+            context.observers.report(
+                context,
+                QueryOutcome {
+                    latency: started.elapsed(),
+                    success: false,
+                    status: "agent unavailable",
+                    usage: None,
+                    answered: None,
+                },
             );
-            return Ok(ResponderResult::Handled(Some(response)));
+            let reply = Self::failure_reply(context, failure, &request, !answers_hitl).await;
+            return Ok(ResponderResult::Handled(Some(reply)));
         }
 
-        // Send query to vagent-graph via Redis with streaming support
-        let mut client_guard = self.redis_client.lock().await;
-        let client = client_guard.as_mut().expect("Redis client should be initialized");
+        // A pending HITL question turns this message into its answer
+        let hitl_key = context.conversation_key(HITL_SLOT);
+        let mut request = None;
+        // A custom event is never the answer, whatever is pending
+        let pending = match context.custom_event {
+            Some(_) => None,
+            None => context.conversations.get(&hitl_key).await.and_then(PendingHitl::from_value),
+        };
+        if let Some(pending) = pending {
+            let answer = hitl::answer(
+                &context.conversations,
+                &context.follow_ups,
+                &hitl_key,
+                pending,
+                &context.message_body,
+                &context.config,
+                &context.runtime.catalog,
+            )
+            .await;
+            match answer {
+                Answer::Reply(text) => {
+                    return Ok(ResponderResult::HandledWithContent(vec![
+                        OutgoingMessage::Markdown(text),
+                    ]));
+                }
+                Answer::Resume { request_id, payload } => {
+                    info!("🙋 Resuming HITL request {} with the user's answer", request_id);
+                    request = Some(
+                        GraphRequest::new(
+                            RequestKind::HitlResponse,
+                            request_id,
+                            context.message_body.to_string(),
+                            room_id.clone(),
+                            context.identity.to_string(),
+                        )
+                        .with_payload(payload),
+                    );
+                }
+                Answer::Cancel { request_id } => {
+                    info!("🚫 User cancelled HITL request {}", request_id);
+                    if let Err(e) = self.service.cancel_hitl(&request_id, &room_id, &context.identity).await {
+                        warn!("Failed to cancel HITL request {} in vagent-graph: {}", request_id, e);
+                    }
+                    return Ok(ResponderResult::Handled(Some(t(context, "hitl.cancelled", &[]))));
+                }
+                // The timeout resolved it meanwhile; this is a new query
+                Answer::Gone => {}
+            }
+        }
+        let mut request = match request {
+            Some(request) => request,
+            None => {
+                let request = Self::new_query(context, &room_id);
+                // Same event, same ID: it was answered before a restart
+                if !context.config.random_request_ids
+                    && context.sent_events.answered(&request.request_id).await
+                {
+                    info!("♻️  Request {} was already answered, not asking again", request.request_id);
+                    return Ok(ResponderResult::HandledSilently);
+                }
+                request
+            }
+        };
+        Self::prepare(context, &mut request, &tenant);
+        // Questions are translated after redaction, so the graph never sees what was redacted
+        if let Some(target) = context.room_config.translate_to.as_deref().filter(|_| {
+            context.room_config.translate_questions
+                && request.kind == RequestKind::Query
+                && context.custom_event.is_none()
+        }) {
+            if let Some(question) =
+                self.translator.translate(&self.service, context, &tenant, "question", &request.query, target).await
+            {
+                request.query = question;
+            }
+        }
 
-        // Create a channel for progress messages
-        let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let span = if context.config.profile_pipeline {
+            info_span!(
+                "agent_request",
+                request_id = %request.request_id,
+                context = field::Empty,
+                redact_trim = field::Empty,
+                publish = field::Empty,
+                first_progress = field::Empty,
+                response = field::Empty,
+            )
+        } else {
+            Span::none()
+        };
+        let metrics = Arc::clone(&context.runtime.metrics);
+        let mut timer = StageTimer::new(context.config.profile_pipeline, span, metrics);
+
+        let cache_key = self.cache_key(context, &request).await;
+        if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+            if let Some(response) = cache.get(key) {
+                info!("🗄️  Answering from the response cache");
+                context.sent_events.link_request(&context.event_id, &request.request_id);
+                context.observers.report(
+                    context,
+                    QueryOutcome {
+                        latency: started.elapsed(),
+                        success: true,
+                        status: "cached",
+                        usage: None,
+                        answered: Some(AnsweredRequest::new(&request.request_id, &context.message_body, asked_at_ms)),
+                    },
+                );
+                let response = self.with_translation(context, &tenant, response).await;
+                let response = if self.mark_cached {
+                    format!("{} {}", response, t(context, "cache.marker", &[]))
+                } else {
+                    response
+                };
+                return Ok(ResponderResult::HandledWithContent(vec![
+                    OutgoingMessage::Markdown(response),
+                ]));
+            }
+        }
+
+        if request.kind == RequestKind::Query {
+            self.attach_context(context, &mut request, &mut timer).await;
+        }
+
+        // Progress is coalesced: only the newest update waits while a send is in flight
+        let request_id = request.request_id.clone();
+        let (feed, mut updates) = ProgressFeed::new();
 
         // Spawn a task to send progress messages to Matrix
-        let room_clone = context.room.clone();
-        let progress_task = tokio::spawn(async move {
-            while let Some(progress_msg) = progress_rx.recv().await {
-                info!("📊 Sending progress to Matrix: {}", progress_msg);
+        // Structured steps share one message that is edited in place; raw
+        // progress strings are sent as they come. Returns the number of sends.
+        let room_clone = Arc::clone(&context.room);
+        let send_queues = Arc::clone(&context.runtime.send_queues);
+        let sent_events = Arc::clone(&context.sent_events);
+        let output = Arc::clone(&context.output);
+        let progress_request_id = request_id.clone();
+        let progress_thread_id = context.thread_id.clone();
+        // Spawned tasks don't inherit the trigger shadow mode records sends under
+        let progress_trigger = context.event_id.clone();
+        let progress_task = tokio::spawn(shadow::scope(progress_trigger, async move {
+            let mut sent = 0;
+            let mut step_message = None;
 
-                use matrix_sdk::ruma::events::room::message::RoomMessageEventContent;
-                let content = RoomMessageEventContent::text_plain(&progress_msg);
+            while updates.changed().await.is_ok() {
+                let Some(update) = updates.borrow_and_update().clone() else {
+                    continue;
+                };
+                sent += 1;
 
-                if let Err(e) = room_clone.send(content).await {
-                    warn!("Failed to send progress message to Matrix: {}", e);
+                let mut turn = send_queues.turn(room_clone.room_id(), progress_thread_id.as_deref()).await;
+                // Edits keep the step message's event ID, so only new messages are recorded
+                let result = match update {
+                    ProgressUpdate::Text(text) => {
+                        info!("📊 Sending progress to Matrix: {}", text);
+                        turn.send(room_clone.send_text(&text)).await.map(Some)
+                    }
+                    ProgressUpdate::Steps(body) => {
+                        if let Some(event_id) = &step_message {
+                            turn.send(room_clone.edit_markdown(event_id, &body)).await.map(|_| None)
+                        } else {
+                            turn.send(room_clone.send_markdown(&body)).await.map(|event_id| {
+                                step_message = Some(event_id.clone());
+                                Some(event_id)
+                            })
+                        }
+                    }
+                };
+                if result.is_ok() {
+                    output.touch();
+                }
+                match result {
+                    Ok(Some(event_id)) => {
+                        let room_id = room_clone.room_id().to_string();
+                        sent_events
+                            .record(event_id, &room_id, SentKind::Progress, Some(&progress_request_id))
+                            .await;
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to send progress message to Matrix: {}", e),
                 }
             }
-        });
 
-        // Define progress callback that sends to the channel
-        let on_progress = move |progress_msg: String| {
-            let _ = progress_tx.send(progress_msg);
+            sent
+        }));
+
+        // Send query to vagent-graph, feeding its progress to the relay task
+        // Queries refused as too large are asked again with less room context
+        // Cancelled at the graph if the bot leaves the room meanwhile
+        let in_flight = context.runtime.memberships.track(&request, &self.service);
+        let mut first_progress = None;
+        let mut retries = 0;
+        let result = loop {
+            let mut result = Err(anyhow!("The request ended without an answer"));
+            let mut events = self.service.ask(request.clone(), AskOptions::default());
+            while let Some(event) = events.next().await {
+                match event {
+                    AgentEvent::Submitted => timer.mark("publish"),
+                    AgentEvent::Progress(message) => {
+                        if timer.is_enabled() {
+                            first_progress.get_or_insert_with(Instant::now);
+                        }
+                        feed.push(&message);
+                    }
+                    AgentEvent::Finished(message) => result = Ok(message),
+                    AgentEvent::Failed(e) => result = Err(e),
+                }
+            }
+            if retries >= context.config.context_retries
+                || request.context.is_empty()
+                || !room_context::is_payload_too_large(&result)
+            {
+                break result;
+            }
+            retries += 1;
+            let before = request.context.len();
+            let dropped = room_context::shrink(&mut request.context, retries == context.config.context_retries);
+            request.metadata.context_trim.get_or_insert_with(Default::default).messages_dropped += dropped;
+            context.runtime.metrics.increment("graph_context_retries_total", &[]);
+            info!(
+                "✂️  vagent-graph refused request {} as too large, retrying with {} of {} context messages",
+                request_id,
+                request.context.len(),
+                before
+            );
         };
+        drop(in_flight);
+        if let Some(at) = first_progress {
+            timer.mark_at("first_progress", at);
+        }
+        timer.mark("response");
 
-        let result = client
-            .query_with_streaming(
-                context.message_body.clone(),
-                context.room.room_id().to_string(),
-                context.sender.clone(),
-                on_progress,
-            )
-            .await;
+        // Wait for progress task to finish sending the last update
+        let steps = feed.step_count();
+        let received = feed.received();
+        drop(feed); // Ends the relay task once the last update is sent
+        let sent = progress_task.await.unwrap_or(0);
+        if received > sent {
+            context.runtime.metrics.increment_by("progress_coalesced_total", &[], (received - sent) as u64);
+            info!(
+                "📊 Coalesced {} of {} progress updates for request {}",
+                received - sent,
+                received,
+                request_id
+            );
+        }
 
-        // Wait for progress task to finish sending all messages
-        drop(client_guard); // Release lock before waiting
-        progress_task.await.ok();
+        let timings = timer.finish();
+
+        // Tokens and cost count towards the room's budget; answers without them count as nothing
+        let usage = result.as_ref().ok().map(|message| {
+            let usage = TokenUsage::from_metadata(message.metadata.as_ref());
+            if usage.is_none() && message.message_type == GraphMessageType::FinalResponse {
+                context.runtime.metrics.increment("graph_usage_missing_total", &[]);
+            }
+            usage.unwrap_or_default()
+        });
+        let status = match &result {
+            Ok(message) => match message.message_type {
+                GraphMessageType::HitlRequest => "asked for input",
+                GraphMessageType::Error => "error",
+                _ => "answered",
+            },
+            Err(_) => "failed",
+        };
+        // Questions and graph errors aren't answers to give feedback on
+        let answered = (status == "answered")
+            .then(|| AnsweredRequest::new(&request_id, &context.message_body, asked_at_ms));
+        context.observers.report(
+            context,
+            QueryOutcome {
+                latency: started.elapsed(),
+                success: result.is_ok(),
+                status,
+                usage,
+                answered,
+            },
+        );
+        // A HITL question is not the end of the exchange; its answer will be
+        if let Some(outbound) = self.outbound.as_ref().filter(|_| status != "asked for input") {
+            outbound.fire(Exchange {
+                request_id: request_id.clone(),
+                room_id: room_id.clone(),
+                user_id: context.sender.to_string(),
+                query: request.query.clone(),
+                response: result.as_ref().ok().map(|message| message.content.clone()),
+                latency: started.elapsed(),
+                status: status.to_string(),
+            });
+        }
+        if result.is_ok() {
+            context
+                .conversations
+                .set(context.conversation_key(SESSION_SLOT), serde_json::json!(true), Some(SESSION_IDLE))
+                .await;
+        }
 
         match result {
-            Ok(response) => {
+            Ok(message) if message.message_type == GraphMessageType::HitlRequest => {
+                info!("🙋 vagent-graph paused request {} for user input", message.request_id);
+                let metadata = message.metadata.unwrap_or_default();
+                let options = metadata
+                    .get("options")
+                    .and_then(|options| serde_json::from_value(options.clone()).ok())
+                    .unwrap_or_default();
+                let form = Form::from_metadata(&metadata).unwrap_or_else(|e| {
+                    warn!("Ignoring form of HITL request {}: {:#}", message.request_id, e);
+                    None
+                });
+                let pending = PendingHitl {
+                    request_id: message.request_id,
+                    question: message.content,
+                    options,
+                    event_id: context.event_id.to_string(),
+                    language: context.language(),
+                    asked_at: db::now_secs(),
+                    form,
+                };
+                let catalog = &context.runtime.catalog;
+                hitl::ask(
+                    &context.conversations,
+                    &context.follow_ups,
+                    hitl_key,
+                    &pending,
+                    &context.config,
+                    catalog,
+                )
+                .await;
+                Ok(ResponderResult::HandledWithContent(vec![
+                    OutgoingMessage::Markdown(pending.render(catalog)),
+                ]))
+            }
+            Ok(message) => {
+                let response = match message.message_type {
+                    GraphMessageType::Error => {
+                        warn!("vagent-graph returned error for request {}: {}", message.request_id, message.content);
+                        let retryable = request.kind == RequestKind::Query;
+                        Self::failure_reply(context, AgentFailure::GraphError, &request, retryable).await
+                    }
+                    _ => {
+                        if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+                            cache.insert(key, message.content.clone());
+                        }
+                        context.sent_events.link_request(&context.event_id, &request_id);
+                        let answer = self.with_translation(context, &tenant, message.content).await;
+                        if retries > 0 {
+                            format!("{}\n\n{}", answer, t(context, "agent.context_reduced", &[]))
+                        } else {
+                            answer
+                        }
+                    }
+                };
                 info!("✅ Received final response from vagent-graph");
-                Ok(ResponderResult::Handled(Some(response)))
+                let response = if steps > 0 {
+                    let summary = t(
+                        context,
+                        "progress.summary",
+                        &[
+                            ("steps", &steps.to_string()),
+                            ("duration", &progress::format_duration(started.elapsed())),
+                        ],
+                    );
+                    format!("{}\n\n{}", summary, response)
+                } else {
+                    response
+                };
+                let response = match timings {
+                    Some(timings) if context.config.profile_footer && context.config.is_admin(&context.sender) => {
+                        format!("{}\n\n_⏱️ {}_", response, timings)
+                    }
+                    _ => response,
+                };
+                let response = match usage {
+                    Some(usage) if context.config.usage_footer && context.config.is_admin(&context.sender) => {
+                        format!("{}\n\n_🪙 {}_", response, usage.summary())
+                    }
+                    _ => response,
+                };
+                // Agent answers are Markdown; render them instead of showing raw syntax
+                Ok(ResponderResult::HandledWithContent(vec![
+                    OutgoingMessage::Markdown(response),
+                ]))
             }
             Err(e) => {
-                warn!("Error querying vagent-graph: {}", e);
-                let fallback = format!(
-                    "[Error communicating with AI service]\nYou said: {}",
-                    context.message_body
-                );
-                Ok(ResponderResult::Handled(Some(fallback)))
+                let failure = AgentFailure::of_request(&e);
+                warn!("Error querying vagent-graph ({:?}): {:#}", failure, e);
+                self.journal_failure(context, &request, format!("{:#}", e)).await;
+                let retryable = request.kind == RequestKind::Query;
+                let reply = Self::failure_reply(context, failure, &request, retryable).await;
+                Ok(ResponderResult::Handled(Some(reply)))
             }
         }
     }
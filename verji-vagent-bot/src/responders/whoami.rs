@@ -0,0 +1,49 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::command::{CommandResponder, CommandSpec};
+use crate::i18n::t;
+use crate::responder::{OutgoingMessage, ResponderContext, ResponderResult};
+
+/// Shows the sender's Matrix ID and the identity the bot knows them by (`!whoami`)
+#[derive(Default)]
+pub struct WhoamiResponder;
+
+impl WhoamiResponder {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl CommandResponder for WhoamiResponder {
+    fn name(&self) -> &str {
+        "WhoamiResponder"
+    }
+
+    fn spec(&self) -> CommandSpec {
+        CommandSpec {
+            names: &["!whoami"],
+            min_args: 0,
+            max_args: Some(0),
+            admin_only: false,
+            usage: "`!whoami`",
+        }
+    }
+
+    async fn run(&self, context: &ResponderContext, _args: Vec<String>) -> Result<ResponderResult> {
+        let id = if context.identity == context.sender {
+            "whoami.unmapped"
+        } else {
+            "whoami.mapped"
+        };
+        let text = t(
+            context,
+            id,
+            &[("mxid", &context.sender), ("identity", &context.identity)],
+        );
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Markdown(text),
+        ]))
+    }
+}
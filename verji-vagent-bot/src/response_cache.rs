@@ -0,0 +1,186 @@
+//! Short-lived cache of agent answers to identical questions
+//!
+//! Keyed by a hash of everything that shapes the answer (room, agent, system
+//! prompt) plus the normalized question, so "What's the wifi password?" and
+//! "what's the  wifi password" share an entry.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::clock::{Clock, SystemClock};
+use crate::metrics::Metrics;
+
+struct Entry {
+    response: String,
+    expires_at_ms: u64,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<u64, Entry>,
+    /// tick -> key, oldest first
+    lru: BTreeMap<u64, u64>,
+    next_tick: u64,
+}
+
+impl Entries {
+    fn remove(&mut self, key: u64) {
+        if let Some(entry) = self.map.remove(&key) {
+            self.lru.remove(&entry.tick);
+        }
+    }
+}
+
+/// Hash of what a cached answer depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    pub fn new(
+        room_id: &str,
+        agent: Option<&str>,
+        system_prompt: Option<&str>,
+        query: &str,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+        room_id.hash(&mut hasher);
+        agent.hash(&mut hasher);
+        system_prompt.hash(&mut hasher);
+        normalize(query).hash(&mut hasher);
+        Self(hasher.finish())
+    }
+
+    /// Key for text that must match exactly (no normalization)
+    pub fn verbatim(scope: &str, text: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        scope.hash(&mut hasher);
+        text.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
+
+/// Case, whitespace and trailing punctuation don't change the question
+fn normalize(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['?', '!', '.'])
+        .to_lowercase()
+}
+
+/// Bounded in-memory answer cache with a TTL and LRU eviction
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+    clock: Arc<dyn Clock>,
+    /// Prefix of the hit/miss and eviction counters
+    metric: &'static str,
+    metrics: Arc<Metrics>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries: max_entries.max(1),
+            entries: Mutex::new(Entries::default()),
+            clock: Arc::new(SystemClock),
+            metric: "response_cache",
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Count hits, misses and evictions under `<metric>_total` and `<metric>_evictions_total`
+    pub fn with_metric(mut self, metric: &'static str) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Record those counters in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// A fresh answer for this key, refreshing its LRU position
+    pub fn get(&self, key: CacheKey) -> Option<String> {
+        let hash = key.0;
+        let now = self.clock.now_ms();
+        let mut entries = self.entries.lock().unwrap();
+
+        let fresh = entries
+            .map
+            .get(&hash)
+            .map(|entry| entry.expires_at_ms > now);
+        if fresh == Some(false) {
+            entries.remove(hash);
+        }
+        if fresh != Some(true) {
+            self.metrics
+                .increment(&format!("{}_total", self.metric), &[("result", "miss")]);
+            return None;
+        }
+
+        let tick = entries.next_tick;
+        entries.next_tick += 1;
+        let entry = entries.map.get_mut(&hash)?;
+        let old_tick = std::mem::replace(&mut entry.tick, tick);
+        let response = entry.response.clone();
+        entries.lru.remove(&old_tick);
+        entries.lru.insert(tick, hash);
+
+        self.metrics
+            .increment(&format!("{}_total", self.metric), &[("result", "hit")]);
+        debug!("🗄️  {} hit", self.metric);
+        Some(response)
+    }
+
+    /// Number of answers currently cached (including expired ones not yet dropped)
+    pub fn entry_count(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Drop every cached answer, returning how many there were
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.map.len();
+        *entries = Entries::default();
+        count
+    }
+
+    /// Remember an answer, evicting the least recently used ones if full
+    pub fn insert(&self, key: CacheKey, response: String) {
+        let hash = key.0;
+        let expires_at_ms = self.clock.now_ms() + self.ttl.as_millis() as u64;
+        let mut entries = self.entries.lock().unwrap();
+
+        entries.remove(hash);
+        while entries.map.len() >= self.max_entries {
+            let Some((_, oldest)) = entries.lru.pop_first() else {
+                break;
+            };
+            entries.map.remove(&oldest);
+            self.metrics
+                .increment(&format!("{}_evictions_total", self.metric), &[]);
+        }
+
+        let tick = entries.next_tick;
+        entries.next_tick += 1;
+        entries.map.insert(
+            hash,
+            Entry {
+                response,
+                expires_at_ms,
+                tick,
+            },
+        );
+        entries.lru.insert(tick, hash);
+    }
+}
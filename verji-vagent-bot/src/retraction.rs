@@ -0,0 +1,79 @@
+//! Grace period for retracting a message before the bot acts on it
+//!
+//! With `PROCESSING_DELAY_MS` set, a message (or coalesced burst) waits that
+//! much longer before it reaches the responders. Deleting one of its messages
+//! before the delay ends drops it without any reply. Redactions are kept for
+//! a minute, so deleting during the coalescing window counts too.
+
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// How long a redaction is remembered for messages still waiting
+const REMEMBER_REDACTIONS: Duration = Duration::from_secs(60);
+
+pub struct ProcessingDelay {
+    delay: Duration,
+    /// Recently redacted events and when the redaction arrived
+    redacted: Mutex<HashMap<OwnedEventId, Instant>>,
+    /// Wakes waiting messages when a redaction arrives
+    changed: Notify,
+}
+
+impl ProcessingDelay {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            redacted: Mutex::new(HashMap::new()),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.delay.is_zero()
+    }
+
+    /// Whether a message waits out the delay; commands and a lone message in
+    /// a direct chat are clearly meant to be answered
+    pub fn applies(&self, command: bool, direct_chat: bool, messages: usize) -> bool {
+        self.is_enabled() && !command && !(direct_chat && messages == 1)
+    }
+
+    /// Note a redaction in case a waiting message is the one retracted
+    pub fn on_redaction(&self, event_id: &EventId) {
+        if !self.is_enabled() {
+            return;
+        }
+        let now = Instant::now();
+        let mut redacted = self.redacted.lock().unwrap();
+        redacted.retain(|_, at| now.duration_since(*at) < REMEMBER_REDACTIONS);
+        redacted.insert(event_id.to_owned(), now);
+        drop(redacted);
+        self.changed.notify_waiters();
+    }
+
+    /// Wait out the delay; returns false if one of `event_ids` was redacted
+    pub async fn wait(&self, event_ids: &[OwnedEventId]) -> bool {
+        let deadline = Instant::now() + self.delay;
+        loop {
+            if self.any_redacted(event_ids) {
+                return false;
+            }
+            if Instant::now() >= deadline {
+                return true;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.changed.notified() => {}
+            }
+        }
+    }
+
+    fn any_redacted(&self, event_ids: &[OwnedEventId]) -> bool {
+        let redacted = self.redacted.lock().unwrap();
+        event_ids.iter().any(|event_id| redacted.contains_key(event_id))
+    }
+}
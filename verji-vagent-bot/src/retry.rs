@@ -0,0 +1,85 @@
+//! 🔁 reactions re-submitting a failed agent query
+//!
+//! When an agent query fails, its question is kept for `RETRY_WINDOW` in the
+//! asker's conversation state and the error reply mentions the retry. A 🔁 on
+//! that reply from the asker sends the question through the responders again,
+//! so rate limits and quotas apply as for a new message. Each query can be
+//! retried `AGENT_RETRY_MAX` times; the answer goes to the original message.
+
+use matrix_sdk::ruma::OwnedEventId;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::conversation::{ConversationKey, ConversationStore};
+use crate::responder::ResponderContext;
+
+/// Reaction that asks for a retry
+pub const RETRY_REACTION: &str = "🔁";
+
+/// How long after the failure a retry can be asked for
+const RETRY_WINDOW: Duration = Duration::from_secs(3600);
+
+/// A failed query, as needed to dispatch it again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedQuery {
+    /// The message that triggered the query, answered by the retry
+    pub event_id: OwnedEventId,
+    pub body: String,
+    pub thread_id: Option<String>,
+    pub in_reply_to: Option<OwnedEventId>,
+    pub is_direct_mention: bool,
+    /// Retries made before this failure
+    pub attempts: u32,
+}
+
+/// Whether a reaction key asks for a retry
+pub fn is_retry_reaction(key: &str) -> bool {
+    key.starts_with(RETRY_REACTION)
+}
+
+fn slot(request_id: &str) -> String {
+    format!("agent.retry.{}", request_id)
+}
+
+/// Keep the query of `context` for a retry of `request_id`
+///
+/// Returns false once the query used up its retries.
+pub async fn remember(context: &ResponderContext, request_id: &str) -> bool {
+    if context.retry_attempt >= context.config.retry_max {
+        return false;
+    }
+    let query = FailedQuery {
+        event_id: context.event_id.clone(),
+        body: context.message_body.to_string(),
+        thread_id: context.thread_id.clone(),
+        in_reply_to: context.in_reply_to.clone(),
+        is_direct_mention: context.is_direct_mention,
+        attempts: context.retry_attempt,
+    };
+    let key = ConversationKey::new(
+        context.room.room_id().as_str(),
+        &context.sender,
+        &slot(request_id),
+    );
+    context
+        .conversations
+        .set(
+            key,
+            serde_json::to_value(&query).unwrap_or_default(),
+            Some(RETRY_WINDOW),
+        )
+        .await;
+    true
+}
+
+/// The failed query of `request_id` if `user_id` asked it; used up
+pub async fn take(
+    conversations: &ConversationStore,
+    room_id: &str,
+    user_id: &str,
+    request_id: &str,
+) -> Option<FailedQuery> {
+    let key = ConversationKey::new(room_id, user_id, &slot(request_id));
+    let value = conversations.remove(&key).await?;
+    serde_json::from_value(value).ok()
+}
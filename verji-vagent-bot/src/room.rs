@@ -0,0 +1,612 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use matrix_sdk::{
+    deserialized_responses::RawAnySyncOrStrippedState,
+    room::{MessagesOptions, Room},
+    ruma::{
+        events::{
+            relation::Replacement,
+            room::{
+                history_visibility::HistoryVisibility as RoomHistoryVisibility,
+                message::{
+                    Relation, RoomMessageEventContent, RoomMessageEventContentWithoutRelation,
+                    SyncRoomMessageEvent,
+                },
+                pinned_events::RoomPinnedEventsEventContent,
+            },
+            AnySyncMessageLikeEvent, AnySyncTimelineEvent, RoomAccountDataEventType,
+            StateEventType,
+        },
+        serde::Raw,
+        EventId, OwnedEventId, RoomId, UInt, UserId,
+    },
+};
+use serde_json::Value;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::alias::Aliases;
+use crate::dispatcher;
+use crate::responder::OutgoingMessage;
+use crate::room_context::{HistoryMessage, HistoryVisibility, Membership, MembershipChange};
+use crate::runtime::Runtime;
+use crate::shadow::RoomWrite;
+
+/// Largest page requested from the homeserver when reading history
+const HISTORY_PAGE_SIZE: usize = 100;
+/// Membership events followed back when checking what a user could see
+const MEMBERSHIP_CHAIN_LIMIT: usize = 20;
+
+/// What a timeline message shows in a transcript
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimelineBody {
+    Text(String),
+    /// Removed by a redaction
+    Redacted,
+    /// Encrypted and the bot has no key for it
+    Undecryptable,
+}
+
+/// A message in a page of room history
+#[derive(Debug, Clone)]
+pub struct TimelineEntry {
+    pub event_id: String,
+    pub sender: String,
+    pub body: TimelineBody,
+    /// Server timestamp, unix milliseconds
+    pub timestamp_ms: u64,
+}
+
+/// One page of room history, newest first
+#[derive(Debug, Default)]
+pub struct TimelinePage {
+    pub entries: Vec<TimelineEntry>,
+    /// Token for the next (older) page; None at the start of the room
+    pub next: Option<String>,
+}
+
+/// The subset of room operations responders rely on
+///
+/// Implemented for the real `matrix_sdk` room (as a [`BotRoom`]) and, for
+/// tests, by `testing::MockRoom`, so responders can run without a homeserver.
+#[async_trait]
+pub trait RoomHandle: Send + Sync {
+    /// ID of the room
+    fn room_id(&self) -> &RoomId;
+
+    /// Human-readable room name, falling back to the room ID
+    fn display_name(&self) -> String;
+
+    /// Canonical alias (`#name:server`), if the room has one
+    fn canonical_alias(&self) -> Option<String>;
+
+    /// Number of joined members, including the bot
+    fn member_count(&self) -> u64;
+
+    /// Send a plain-text message, returning its event ID
+    async fn send_text(&self, body: &str) -> Result<OwnedEventId>;
+
+    /// Send rich responder output; reactions attach to `trigger`
+    async fn send_content(
+        &self,
+        trigger: &EventId,
+        message: OutgoingMessage,
+    ) -> Result<OwnedEventId>;
+
+    /// Send a Markdown message, returning its event ID so it can be edited
+    async fn send_markdown(&self, body: &str) -> Result<OwnedEventId>;
+
+    /// Replace the body of a message the bot sent earlier
+    async fn edit_markdown(&self, event_id: &EventId, body: &str) -> Result<()>;
+
+    /// Redact an event (e.g. a notice the bot no longer needs)
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> Result<()>;
+
+    /// Start or stop the typing indicator
+    async fn typing(&self, typing: bool) -> Result<()>;
+
+    /// Up to `limit` most recent text messages, oldest first
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<HistoryMessage>>;
+
+    /// One page of messages going back from `from` (None = the newest),
+    /// including redacted and undecryptable ones
+    async fn history_page(&self, from: Option<String>, limit: usize) -> Result<TimelinePage>;
+
+    /// Display name of a member, if they set one
+    async fn member_display_name(&self, user_id: &str) -> Option<String>;
+
+    /// A single message by ID; None if the event isn't a message
+    async fn fetch_event(&self, event_id: &EventId) -> Result<Option<TimelineEntry>>;
+
+    /// Events in the room's `m.room.pinned_events` (empty if never set)
+    async fn pinned_events(&self) -> Result<Vec<OwnedEventId>>;
+
+    /// Replace the room's pinned events
+    async fn set_pinned_events(&self, pinned: Vec<OwnedEventId>) -> Result<()>;
+
+    /// Whether the bot's power level allows changing the pinned events
+    async fn can_pin(&self) -> Result<bool>;
+
+    /// Whether the room has end-to-end encryption enabled
+    async fn is_encrypted(&self) -> bool;
+
+    /// Discard the bot's outbound Megolm session so the next send starts a new one
+    async fn rotate_room_key(&self) -> Result<()>;
+
+    /// Megolm session an event was encrypted with (None if unencrypted)
+    async fn megolm_session_id(&self, event_id: &EventId) -> Result<Option<String>>;
+
+    /// Who may read history from before they joined
+    fn history_visibility(&self) -> HistoryVisibility;
+
+    /// A user's recent membership events, oldest first
+    ///
+    /// Older events may be missing; the list can be empty if the server's
+    /// state has no membership for the user.
+    async fn membership_changes(&self, user_id: &str) -> Result<Vec<MembershipChange>>;
+
+    /// Content of the room state event with this type and state key, if set
+    async fn state_event(&self, event_type: &str, state_key: &str) -> Result<Option<Value>>;
+
+    /// User who created the room, from its `m.room.create` event
+    async fn creator(&self) -> Result<Option<String>>;
+
+    /// Content of the bot's room account data of this type, if set
+    async fn room_account_data(&self, event_type: &str) -> Result<Option<Value>>;
+
+    /// Replace the bot's room account data of this type
+    async fn set_room_account_data(&self, event_type: &str, content: Value) -> Result<()>;
+}
+
+/// A joined room, writing through the bot's shadow mode, pacing and send timing
+#[derive(Clone)]
+pub struct BotRoom {
+    room: Room,
+    runtime: Runtime,
+}
+
+impl BotRoom {
+    pub fn new(room: Room, runtime: Runtime) -> Self {
+        Self { room, runtime }
+    }
+
+    /// The bot's runtime the room's writes go through
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+}
+
+impl Deref for BotRoom {
+    type Target = Room;
+
+    fn deref(&self) -> &Room {
+        &self.room
+    }
+}
+
+#[async_trait]
+impl RoomHandle for BotRoom {
+    fn room_id(&self) -> &RoomId {
+        self.room.room_id()
+    }
+
+    fn display_name(&self) -> String {
+        self.room
+            .cached_display_name()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| self.room.room_id().to_string())
+    }
+
+    fn canonical_alias(&self) -> Option<String> {
+        self.room.canonical_alias().map(|alias| alias.to_string())
+    }
+
+    fn member_count(&self) -> u64 {
+        self.room.joined_members_count()
+    }
+
+    async fn send_text(&self, body: &str) -> Result<OwnedEventId> {
+        let write = RoomWrite::new(self.room_id(), "message").body(body);
+        if let Some(event_id) = self.runtime.shadow.suppress(write) {
+            return Ok(event_id);
+        }
+        let send = self.runtime.pacer.send("message", || {
+            self.room.send(RoomMessageEventContent::text_plain(body))
+        });
+        let response = self.runtime.send_timing.timed(&self.room, send)
+            .await
+            .context("Failed to send message")?;
+        self.runtime.shadow.record_sent(write, &response.event_id);
+        Ok(response.event_id)
+    }
+
+    async fn send_content(
+        &self,
+        trigger: &EventId,
+        message: OutgoingMessage,
+    ) -> Result<OwnedEventId> {
+        self.runtime.send_timing.timed(&self.room, dispatcher::send_message(self, trigger, message)).await
+    }
+
+    async fn send_markdown(&self, body: &str) -> Result<OwnedEventId> {
+        let write = RoomWrite::new(self.room_id(), "message").body(body);
+        if let Some(event_id) = self.runtime.shadow.suppress(write) {
+            return Ok(event_id);
+        }
+        let send = self.runtime.pacer.send("message", || {
+            self.room.send(RoomMessageEventContent::text_markdown(body))
+        });
+        let response = self.runtime.send_timing.timed(&self.room, send)
+            .await
+            .context("Failed to send message")?;
+        self.runtime.shadow.record_sent(write, &response.event_id);
+        Ok(response.event_id)
+    }
+
+    async fn edit_markdown(&self, event_id: &EventId, body: &str) -> Result<()> {
+        let write = RoomWrite::new(self.room_id(), "edit")
+            .target(event_id)
+            .body(body);
+        if self.runtime.shadow.suppress(write).is_some() {
+            return Ok(());
+        }
+        // Clients without edit support show the fallback body
+        let mut content = RoomMessageEventContent::text_markdown(format!("* {}", body));
+        content.relates_to = Some(Relation::Replacement(Replacement::new(
+            event_id.to_owned(),
+            RoomMessageEventContentWithoutRelation::text_markdown(body),
+        )));
+        let send = self.runtime.pacer.send("edit", || self.room.send(content.clone()));
+        let response = self.runtime.send_timing.timed(&self.room, send)
+            .await
+            .context("Failed to edit message")?;
+        self.runtime.shadow.record_sent(write, &response.event_id);
+        Ok(())
+    }
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> Result<()> {
+        let write = RoomWrite::new(self.room_id(), "redaction").target(event_id);
+        if self.runtime.shadow.suppress(write).is_some() {
+            return Ok(());
+        }
+        let response = self.room.redact(event_id, reason, None)
+            .await
+            .context("Failed to redact event")?;
+        self.runtime.shadow.record_sent(write, &response.event_id);
+        Ok(())
+    }
+
+    async fn typing(&self, typing: bool) -> Result<()> {
+        let body = if typing { "on" } else { "off" };
+        if self.runtime.shadow.suppress(RoomWrite::new(self.room_id(), "typing").body(body)).is_some() {
+            return Ok(());
+        }
+        self.room.typing_notice(typing)
+            .await
+            .context("Failed to send typing notice")
+    }
+
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<HistoryMessage>> {
+        let mut messages = Vec::new();
+        let mut from: Option<String> = None;
+
+        // Paginate backwards; undecryptable and non-text events are skipped
+        while messages.len() < limit {
+            let mut options = MessagesOptions::backward();
+            options.from = from.take();
+            options.limit = UInt::from((limit - messages.len()).min(HISTORY_PAGE_SIZE) as u32);
+
+            let page = self
+                .room
+                .messages(options)
+                .await
+                .context("Failed to fetch room messages")?;
+            for event in &page.chunk {
+                if let Ok(AnySyncTimelineEvent::MessageLike(
+                    AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Original(event)),
+                )) = event.raw().deserialize()
+                {
+                    messages.push(HistoryMessage {
+                        event_id: event.event_id.to_string(),
+                        sender: event.sender.to_string(),
+                        body: event.content.body().to_string(),
+                        timestamp_ms: event.origin_server_ts.0.into(),
+                    });
+                }
+            }
+
+            match page.end {
+                Some(end) if !page.chunk.is_empty() => from = Some(end),
+                _ => break,
+            }
+        }
+
+        messages.truncate(limit);
+        messages.reverse();
+        Ok(messages)
+    }
+
+    async fn history_page(&self, from: Option<String>, limit: usize) -> Result<TimelinePage> {
+        let mut options = MessagesOptions::backward();
+        options.from = from;
+        options.limit = UInt::from(limit.min(HISTORY_PAGE_SIZE) as u32);
+        let page = self
+            .room
+            .messages(options)
+            .await
+            .context("Failed to fetch room messages")?;
+
+        let entries = page
+            .chunk
+            .iter()
+            .filter_map(|event| timeline_entry(event.raw()))
+            .collect();
+
+        let next = page.end.filter(|_| !page.chunk.is_empty());
+        Ok(TimelinePage { entries, next })
+    }
+
+    async fn member_display_name(&self, user_id: &str) -> Option<String> {
+        let user_id = UserId::parse(user_id).ok()?;
+        let member = self.room.get_member_no_sync(&user_id).await.ok()??;
+        member.display_name().map(str::to_string)
+    }
+
+    async fn fetch_event(&self, event_id: &EventId) -> Result<Option<TimelineEntry>> {
+        let event = self
+            .room
+            .event(event_id, None)
+            .await
+            .with_context(|| format!("Failed to fetch event {}", event_id))?;
+        Ok(timeline_entry(event.raw()))
+    }
+
+    async fn pinned_events(&self) -> Result<Vec<OwnedEventId>> {
+        // No m.room.pinned_events state yet means nothing is pinned
+        Ok(self.room.pinned_event_ids().unwrap_or_default())
+    }
+
+    async fn set_pinned_events(&self, pinned: Vec<OwnedEventId>) -> Result<()> {
+        let body = pinned
+            .iter()
+            .map(|event_id| event_id.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if self.runtime.shadow.suppress(RoomWrite::new(self.room_id(), "pins").body(&body)).is_some() {
+            return Ok(());
+        }
+        self.room.send_state_event(RoomPinnedEventsEventContent::new(pinned))
+            .await
+            .context("Failed to update pinned events")?;
+        Ok(())
+    }
+
+    async fn can_pin(&self) -> Result<bool> {
+        self.room.can_user_send_state(self.room.own_user_id(), StateEventType::RoomPinnedEvents)
+            .await
+            .context("Failed to read power levels")
+    }
+
+    async fn is_encrypted(&self) -> bool {
+        self.room
+            .latest_encryption_state()
+            .await
+            .map(|state| state.is_encrypted())
+            .unwrap_or(false)
+    }
+
+    async fn rotate_room_key(&self) -> Result<()> {
+        self.room.discard_room_key()
+            .await
+            .context("Failed to discard the outbound room key")
+    }
+
+    async fn megolm_session_id(&self, event_id: &EventId) -> Result<Option<String>> {
+        let event = self
+            .room
+            .event(event_id, None)
+            .await
+            .with_context(|| format!("Failed to fetch event {}", event_id))?;
+        Ok(event
+            .encryption_info()
+            .and_then(|info| info.session_id())
+            .map(str::to_string))
+    }
+
+    fn history_visibility(&self) -> HistoryVisibility {
+        match self.room.history_visibility_or_default() {
+            RoomHistoryVisibility::WorldReadable => HistoryVisibility::WorldReadable,
+            RoomHistoryVisibility::Invited => HistoryVisibility::Invited,
+            RoomHistoryVisibility::Joined => HistoryVisibility::Joined,
+            _ => HistoryVisibility::Shared,
+        }
+    }
+
+    async fn membership_changes(&self, user_id: &str) -> Result<Vec<MembershipChange>> {
+        let mut changes = Vec::new();
+        let mut event = raw_state_event(&self.room, StateEventType::RoomMember, user_id).await?;
+
+        // Walk back through the events each membership replaced
+        while let Some(json) = event.take() {
+            let membership = json["content"]["membership"]
+                .as_str()
+                .and_then(Membership::parse);
+            if let (Some(membership), Some(timestamp_ms)) =
+                (membership, json["origin_server_ts"].as_u64())
+            {
+                changes.push(MembershipChange {
+                    membership,
+                    timestamp_ms,
+                });
+            }
+            if changes.len() >= MEMBERSHIP_CHAIN_LIMIT {
+                break;
+            }
+
+            let Some(previous) = json["unsigned"]["replaces_state"].as_str() else {
+                break;
+            };
+            let previous = EventId::parse(previous).context("Invalid replaces_state")?;
+            let timeline_event = self
+                .room
+                .event(&previous, None)
+                .await
+                .context("Failed to fetch an earlier membership event")?;
+            event = Some(
+                serde_json::from_str(timeline_event.raw().json().get())
+                    .context("Membership event is not valid JSON")?,
+            );
+        }
+
+        changes.reverse();
+        Ok(changes)
+    }
+
+    async fn state_event(&self, event_type: &str, state_key: &str) -> Result<Option<Value>> {
+        let event = raw_state_event(&self.room, StateEventType::from(event_type), state_key).await?;
+        Ok(event.and_then(|event| event.get("content").cloned()))
+    }
+
+    async fn creator(&self) -> Result<Option<String>> {
+        let Some(event) = raw_state_event(&self.room, StateEventType::RoomCreate, "").await? else {
+            return Ok(None);
+        };
+        // Room versions before 11 name the creator in the content
+        let creator = event
+            .pointer("/content/creator")
+            .or_else(|| event.get("sender"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        Ok(creator)
+    }
+
+    async fn room_account_data(&self, event_type: &str) -> Result<Option<Value>> {
+        let Some(raw) = self
+            .room
+            .account_data(RoomAccountDataEventType::from(event_type))
+            .await
+            .context("Failed to read room account data")?
+        else {
+            return Ok(None);
+        };
+
+        let event: Value = serde_json::from_str(raw.json().get())
+            .context("Room account data is not valid JSON")?;
+        Ok(event.get("content").cloned())
+    }
+
+    async fn set_room_account_data(&self, event_type: &str, content: Value) -> Result<()> {
+        let write = RoomWrite::new(self.room_id(), "account_data").body(event_type);
+        if self.runtime.shadow.suppress(write).is_some() {
+            return Ok(());
+        }
+        let raw = Raw::from_json(serde_json::value::to_raw_value(&content)?);
+        self.room.set_account_data_raw(RoomAccountDataEventType::from(event_type), raw)
+            .await
+            .context("Failed to write room account data")?;
+        Ok(())
+    }
+}
+
+/// A whole room state event as JSON
+async fn raw_state_event(
+    room: &Room,
+    event_type: StateEventType,
+    state_key: &str,
+) -> Result<Option<Value>> {
+    let Some(raw) = room
+        .get_state_event(event_type, state_key)
+        .await
+        .context("Failed to read room state")?
+    else {
+        return Ok(None);
+    };
+
+    let json = match &raw {
+        RawAnySyncOrStrippedState::Sync(raw) => raw.json().get(),
+        RawAnySyncOrStrippedState::Stripped(raw) => raw.json().get(),
+    };
+    let event = serde_json::from_str(json).context("Room state event is not valid JSON")?;
+    Ok(Some(event))
+}
+
+/// A timeline event as a transcript entry
+///
+/// State events and non-message events (reactions, ...) give None.
+fn timeline_entry(raw: &Raw<AnySyncTimelineEvent>) -> Option<TimelineEntry> {
+    let AnySyncTimelineEvent::MessageLike(event) = raw.deserialize().ok()? else {
+        return None;
+    };
+    let body = match &event {
+        AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Original(message)) => {
+            TimelineBody::Text(message.content.body().to_string())
+        }
+        AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Redacted(_)) => {
+            TimelineBody::Redacted
+        }
+        AnySyncMessageLikeEvent::RoomEncrypted(_) => TimelineBody::Undecryptable,
+        _ => return None,
+    };
+    Some(TimelineEntry {
+        event_id: event.event_id().to_string(),
+        sender: event.sender().to_string(),
+        body,
+        timestamp_ms: event.origin_server_ts().0.into(),
+    })
+}
+
+/// Rooms a responder is active in, checked before `should_handle`
+///
+/// Checks only use room data the SDK already has locally, so they are
+/// synchronous and cheap.
+#[derive(Clone)]
+pub enum RoomScope {
+    /// Active in every room
+    Everywhere,
+    /// Active in the listed rooms, given as room IDs (`!id:server`) or
+    /// aliases (`#alias:server`, matched once resolved or if canonical)
+    Rooms(Vec<String>),
+    /// Active wherever the predicate returns true
+    Predicate {
+        description: String,
+        matches: Arc<dyn Fn(&dyn RoomHandle) -> bool + Send + Sync>,
+    },
+}
+
+impl RoomScope {
+    /// Scope defined by a predicate; `description` is shown in responder listings
+    pub fn predicate(
+        description: &str,
+        matches: impl Fn(&dyn RoomHandle) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        RoomScope::Predicate {
+            description: description.to_string(),
+            matches: Arc::new(matches),
+        }
+    }
+
+    /// Whether a responder with this scope may see messages from `room`
+    ///
+    /// Aliases in a room list match through `aliases`.
+    pub fn allows(&self, room: &dyn RoomHandle, aliases: &Aliases) -> bool {
+        match self {
+            RoomScope::Everywhere => true,
+            RoomScope::Rooms(rooms) => {
+                let alias = room.canonical_alias();
+                rooms.iter().any(|entry| {
+                    aliases.matches(entry, room.room_id().as_str()) || alias.as_deref() == Some(entry.as_str())
+                })
+            }
+            RoomScope::Predicate { matches, .. } => matches(room),
+        }
+    }
+
+    /// Short description for listings
+    pub fn describe(&self) -> String {
+        match self {
+            RoomScope::Everywhere => "all rooms".to_string(),
+            RoomScope::Rooms(rooms) => rooms.join(", "),
+            RoomScope::Predicate { description, .. } => description.clone(),
+        }
+    }
+}
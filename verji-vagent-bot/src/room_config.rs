@@ -0,0 +1,261 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::room::RoomHandle;
+
+/// Room account data event type holding the bot's per-room settings
+pub const ROOM_CONFIG_EVENT_TYPE: &str = "no.verji.vagent.config";
+
+/// Per-room settings, stored in the bot's room account data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoomConfig {
+    /// Language for the bot's own replies (None = `LOCALE`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Agent system prompt for queries from this room (`!setprompt`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Agent graph the room talks to (`!agent set`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// Which messages the bot answers (`!admin replymode`; None = `REPLY_MODE`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_mode: Option<ReplyMode>,
+    /// What to do with stickers (`!admin stickers`; None = `STICKER_MODE`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticker_mode: Option<StickerMode>,
+    /// Plain or formatted answers (`!admin format`; None = `RESPONSE_FORMAT`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Rotate the bot's room key when a member leaves (`!admin sensitive`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+    /// Language agent answers are translated into (`!admin translate`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate_to: Option<String>,
+    /// Where the translation goes (None = below the original)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate_mode: Option<TranslateMode>,
+    /// Also translate questions into `translate_to` before asking the agent
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub translate_questions: bool,
+    /// Monthly spend cap for agent answers (`!admin budget`; None = `MONTHLY_BUDGET`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_budget: Option<f64>,
+    /// The bot told the room its moderators disabled it (see `kill_switch`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled_notice_sent: bool,
+    /// The bot told the room it is archived and read-only (see `archive`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived_notice_sent: bool,
+    /// Fields written by newer versions, preserved on save
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Which messages the bot answers in a room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyMode {
+    /// Every message
+    #[default]
+    All,
+    /// Only messages addressed to the bot (see `addressing`)
+    Mentions,
+}
+
+impl ReplyMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "all" => Some(ReplyMode::All),
+            "mentions" => Some(ReplyMode::Mentions),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplyMode::All => "all",
+            ReplyMode::Mentions => "mentions",
+        }
+    }
+}
+
+/// How the bot treats stickers in a room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StickerMode {
+    /// Log and drop
+    #[default]
+    Ignore,
+    /// React with `STICKER_ACK`
+    Ack,
+    /// Pass the sticker's description to the responders as a message
+    Forward,
+}
+
+impl StickerMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "ignore" => Some(StickerMode::Ignore),
+            "ack" => Some(StickerMode::Ack),
+            "forward" => Some(StickerMode::Forward),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StickerMode::Ignore => "ignore",
+            StickerMode::Ack => "ack",
+            StickerMode::Forward => "forward",
+        }
+    }
+}
+
+/// Whether answers are sent as plain text or rendered markdown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    /// Markdown rendered to HTML
+    #[default]
+    Markdown,
+    /// Plain text, for clients that show HTML poorly
+    Plain,
+    /// Like the question: markdown if it had a formatted body, plain otherwise
+    Auto,
+}
+
+impl ResponseFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Some(ResponseFormat::Markdown),
+            "plain" | "text" => Some(ResponseFormat::Plain),
+            "auto" => Some(ResponseFormat::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseFormat::Markdown => "markdown",
+            ResponseFormat::Plain => "plain",
+            ResponseFormat::Auto => "auto",
+        }
+    }
+
+    /// The format to answer in: the room's setting over `self` (the
+    /// configured default), with `auto` following the question
+    ///
+    /// Never returns `Auto`.
+    pub fn resolve(self, room: Option<ResponseFormat>, question_formatted: bool) -> Self {
+        match room.unwrap_or(self) {
+            ResponseFormat::Auto if question_formatted => ResponseFormat::Markdown,
+            ResponseFormat::Auto => ResponseFormat::Plain,
+            pinned => pinned,
+        }
+    }
+}
+
+/// How a translated answer is posted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslateMode {
+    /// Original answer, then the translation under it
+    #[default]
+    Below,
+    /// Only the translation
+    Replace,
+}
+
+impl TranslateMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "below" => Some(TranslateMode::Below),
+            "replace" => Some(TranslateMode::Replace),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranslateMode::Below => "below",
+            TranslateMode::Replace => "replace",
+        }
+    }
+}
+
+/// Cached access to room configs
+///
+/// The bot is the only writer of its account data, so entries are cached for
+/// the lifetime of the process and updated on every save.
+pub struct RoomConfigStore {
+    cache: Mutex<HashMap<String, RoomConfig>>,
+}
+
+impl RoomConfigStore {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The room's config; unreadable or missing configs yield the defaults
+    pub async fn get(&self, room: &dyn RoomHandle) -> RoomConfig {
+        let room_id = room.room_id().to_string();
+        if let Some(config) = self.cache.lock().unwrap().get(&room_id) {
+            return config.clone();
+        }
+
+        let config = match room.room_account_data(ROOM_CONFIG_EVENT_TYPE).await {
+            Ok(Some(content)) => serde_json::from_value(content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid room config in {}: {}", room_id, e);
+                RoomConfig::default()
+            }),
+            Ok(None) => RoomConfig::default(),
+            Err(e) => {
+                // Don't cache: the next message retries the read
+                warn!("Failed to load room config for {}: {:#}", room_id, e);
+                return RoomConfig::default();
+            }
+        };
+
+        self.cache.lock().unwrap().insert(room_id, config.clone());
+        config
+    }
+
+    /// Drop the cached config of a room the bot left; read again on rejoin
+    pub fn forget(&self, room_id: &str) {
+        self.cache.lock().unwrap().remove(room_id);
+    }
+
+    /// Change the room's config and persist it to account data
+    pub async fn update(
+        &self,
+        room: &dyn RoomHandle,
+        change: impl FnOnce(&mut RoomConfig),
+    ) -> Result<RoomConfig> {
+        let mut config = self.get(room).await;
+        change(&mut config);
+
+        room.set_room_account_data(ROOM_CONFIG_EVENT_TYPE, serde_json::to_value(&config)?)
+            .await?;
+        info!("⚙️  Saved room config for {}", room.room_id());
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(room.room_id().to_string(), config.clone());
+        Ok(config)
+    }
+}
+
+impl Default for RoomConfigStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::command::COMMAND_PREFIX;
+use crate::redact::Redactor;
+use crate::redis_client::{GraphMessage, GraphMessageType};
+use crate::room::RoomHandle;
+use crate::tokens;
+
+/// Marker placed where the middle of a long message was cut
+const ELLIPSIS: &str = " […] ";
+
+/// `code` in the metadata of a graph error refusing a request for its size
+pub const PAYLOAD_TOO_LARGE: &str = "payload_too_large";
+
+/// Phrases of size errors from graphs (or brokers) that send no code
+const SIZE_ERROR_HINTS: &[&str] = &[
+    "too large",
+    "too big",
+    "context length",
+    "context window",
+    "maximum context",
+    "too many tokens",
+];
+
+/// A text message from the room timeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryMessage {
+    pub event_id: String,
+    pub sender: String,
+    pub body: String,
+    /// Server timestamp, unix milliseconds
+    pub timestamp_ms: u64,
+}
+
+impl HistoryMessage {
+    /// Approximate serialized size, used for byte budgets
+    fn size(&self) -> usize {
+        self.sender.len() + self.body.len() + 32
+    }
+}
+
+/// Who may read messages sent before they joined (`m.room.history_visibility`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryVisibility {
+    WorldReadable,
+    /// Members see all history, also from before they joined (the spec default)
+    Shared,
+    /// Members see history from their invite on
+    Invited,
+    /// Members see history from their join on
+    Joined,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Membership {
+    Invite,
+    Join,
+    Knock,
+    Leave,
+    Ban,
+}
+
+impl Membership {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "invite" => Some(Self::Invite),
+            "join" => Some(Self::Join),
+            "knock" => Some(Self::Knock),
+            "leave" => Some(Self::Leave),
+            "ban" => Some(Self::Ban),
+            _ => None,
+        }
+    }
+}
+
+/// One membership event of a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MembershipChange {
+    pub membership: Membership,
+    /// Server timestamp, unix milliseconds
+    pub timestamp_ms: u64,
+}
+
+/// Fetch up to `limit` recent conversational messages `viewer` could see, oldest first
+///
+/// Bot commands are left out; they are noise for the agent.
+pub async fn fetch_recent(
+    room: &dyn RoomHandle,
+    viewer: &str,
+    limit: usize,
+) -> Result<Vec<HistoryMessage>> {
+    let visibility = room.history_visibility();
+    let memberships = match visibility {
+        HistoryVisibility::WorldReadable | HistoryVisibility::Shared => Vec::new(),
+        HistoryVisibility::Invited | HistoryVisibility::Joined => room
+            .membership_changes(viewer)
+            .await
+            .context("Failed to read the requester's membership")?,
+    };
+
+    let messages = room.recent_messages(limit).await?;
+    let fetched = messages.len();
+    let messages = visible_to(visibility, &memberships, messages);
+    if messages.len() < fetched {
+        debug!(
+            "🙈 Left {} messages out of the context that {} could not see",
+            fetched - messages.len(),
+            viewer
+        );
+    }
+    Ok(messages
+        .into_iter()
+        .filter(|message| !message.body.trim_start().starts_with(COMMAND_PREFIX))
+        .collect())
+}
+
+/// Messages the viewer could have read themselves
+///
+/// Uses the room's current visibility. In `invited` and `joined` rooms a
+/// message counts as seen when the viewer's membership at the time it was sent
+/// (their newest change at or before it) was invite or join, or only join.
+/// Messages older than every known change are left out: the membership they
+/// were sent under is unknown, and leaking them is worse than losing context.
+pub fn visible_to(
+    visibility: HistoryVisibility,
+    memberships: &[MembershipChange],
+    messages: Vec<HistoryMessage>,
+) -> Vec<HistoryMessage> {
+    let allowed: &[Membership] = match visibility {
+        HistoryVisibility::WorldReadable | HistoryVisibility::Shared => return messages,
+        HistoryVisibility::Invited => &[Membership::Invite, Membership::Join],
+        HistoryVisibility::Joined => &[Membership::Join],
+    };
+
+    let mut memberships = memberships.to_vec();
+    memberships.sort_by_key(|change| change.timestamp_ms);
+    messages
+        .into_iter()
+        .filter(|message| {
+            memberships
+                .iter()
+                .rev()
+                .find(|change| change.timestamp_ms <= message.timestamp_ms)
+                .is_some_and(|change| allowed.contains(&change.membership))
+        })
+        .collect()
+}
+
+/// Mask personal data in message bodies
+pub fn redact(messages: &mut [HistoryMessage], redactor: &Redactor) {
+    for message in messages {
+        message.body = redactor.redact(&message.body);
+    }
+}
+
+/// What trimming removed from the room context, reported to the graph
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextTrim {
+    pub messages_dropped: usize,
+    pub messages_truncated: usize,
+    pub chars_truncated: usize,
+}
+
+/// Fit room context into a token budget
+///
+/// The triggering message is sent separately and never cut, so its tokens
+/// come off the budget first. Messages longer than a quarter of the budget
+/// are shortened to their head and tail; then the oldest messages are dropped
+/// until the rest fit.
+pub fn trim_to_budget(
+    messages: Vec<HistoryMessage>,
+    trigger: &str,
+    budget: usize,
+) -> (Vec<HistoryMessage>, ContextTrim) {
+    let mut report = ContextTrim::default();
+    let mut remaining = budget.saturating_sub(tokens::estimate(trigger));
+    let max_message_tokens = (budget / 4).max(1);
+
+    // Newest first, so the most recent context survives
+    let total = messages.len();
+    let mut kept = Vec::new();
+    for mut message in messages.into_iter().rev() {
+        let mut cut_chars = 0;
+        if tokens::estimate(&message.body) > max_message_tokens {
+            let cut = truncate_middle(&message.body, tokens::chars_for(max_message_tokens));
+            cut_chars = message.body.chars().count() - cut.chars().count();
+            message.body = cut;
+        }
+
+        let cost = tokens::estimate(&message.body) + tokens::estimate(&message.sender);
+        if cost > remaining {
+            break;
+        }
+        remaining -= cost;
+        if cut_chars > 0 {
+            report.messages_truncated += 1;
+            report.chars_truncated += cut_chars;
+        }
+        kept.push(message);
+    }
+    report.messages_dropped = total - kept.len();
+    kept.reverse();
+
+    if report != ContextTrim::default() {
+        debug!(
+            "✂️  Trimmed room context: {} dropped, {} truncated ({} chars)",
+            report.messages_dropped, report.messages_truncated, report.chars_truncated
+        );
+    }
+    (kept, report)
+}
+
+/// Keep the head and tail of `text` within `max_chars`, marking the cut
+fn truncate_middle(text: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let keep = max_chars.saturating_sub(ELLIPSIS.chars().count());
+    if chars.len() <= max_chars || keep == 0 {
+        return chars.into_iter().take(max_chars).collect();
+    }
+
+    let head = keep / 2;
+    let tail = keep - head;
+    let mut out: String = chars[..head].iter().collect();
+    out.push_str(ELLIPSIS);
+    out.extend(&chars[chars.len() - tail..]);
+    out
+}
+
+/// Drop the oldest messages until the rest fit in `max_bytes`
+pub fn cap_bytes(mut messages: Vec<HistoryMessage>, max_bytes: usize) -> Vec<HistoryMessage> {
+    let mut total: usize = messages.iter().map(HistoryMessage::size).sum();
+    let mut drop = 0;
+    while total > max_bytes && drop < messages.len() {
+        total -= messages[drop].size();
+        drop += 1;
+    }
+    messages.drain(..drop);
+    messages
+}
+
+/// Whether vagent-graph, or the way to it, refused a request for its size
+///
+/// Errors with a `code` are judged by it alone; without one, the message is
+/// searched for the usual phrasing.
+pub fn is_payload_too_large(result: &Result<GraphMessage>) -> bool {
+    let text = match result {
+        Ok(message) if message.message_type == GraphMessageType::Error => {
+            let code = message
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get("code"))
+                .and_then(Value::as_str);
+            if let Some(code) = code {
+                return code == PAYLOAD_TOO_LARGE;
+            }
+            message.content.to_lowercase()
+        }
+        Ok(_) => return false,
+        Err(e) => format!("{:#}", e).to_lowercase(),
+    };
+    SIZE_ERROR_HINTS.iter().any(|hint| text.contains(hint))
+}
+
+/// Cut the context of a request refused as too large, returning how many messages went
+///
+/// Halves it, keeping the newest messages; the `last` retry drops all of it.
+pub fn shrink(messages: &mut Vec<HistoryMessage>, last: bool) -> usize {
+    let keep = if last { 0 } else { messages.len() / 2 };
+    let dropped = messages.len() - keep;
+    messages.drain(..dropped);
+    dropped
+}
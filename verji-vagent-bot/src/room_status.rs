@@ -0,0 +1,119 @@
+//! Per-room bot activity for dashboards (`GET /api/rooms`)
+//!
+//! Built from in-memory state only: the usage stats, the send queues and the
+//! Matrix client's cached room list and names. Serving a page never waits on
+//! the homeserver, however many rooms the bot is in.
+
+use matrix_sdk::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::send_queue::SendQueues;
+use crate::stats::UsageStats;
+
+/// Rooms per page when the caller doesn't say
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Largest page a caller may ask for
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// One room as dashboards see it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomStatus {
+    pub room_id: String,
+    /// Cached display name; the room ID when the client has none
+    pub display_name: String,
+    /// Unix seconds of the bot's last successful answer
+    pub last_response: Option<u64>,
+    /// Unix seconds of the last agent query, answered or not
+    pub last_activity: Option<u64>,
+    pub queries: u64,
+    pub errors: u64,
+    /// Messages waiting in the room's send queues
+    pub queue_depth: usize,
+}
+
+/// One page of rooms, ordered by room ID
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomsPage {
+    pub rooms: Vec<RoomStatus>,
+    /// Rooms across all pages
+    pub total: usize,
+    /// `cursor` for the next page; None on the last one
+    pub next_cursor: Option<String>,
+}
+
+/// `?limit=&cursor=` of a rooms request
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<usize>,
+    /// Room ID the previous page ended with
+    pub cursor: Option<String>,
+}
+
+/// The page of `rooms` after `query.cursor`
+pub fn paginate(mut rooms: Vec<RoomStatus>, query: &PageQuery) -> RoomsPage {
+    rooms.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+    let total = rooms.len();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let start = query.cursor.as_deref().map_or(0, |cursor| {
+        rooms.partition_point(|room| room.room_id.as_str() <= cursor)
+    });
+    let rooms: Vec<RoomStatus> = rooms.into_iter().skip(start).take(limit).collect();
+    let next_cursor = if start + rooms.len() < total {
+        rooms.last().map(|room| room.room_id.clone())
+    } else {
+        None
+    };
+    RoomsPage {
+        rooms,
+        total,
+        next_cursor,
+    }
+}
+
+/// The room's status, named by its ID until the client's cache knows better
+fn entry(rooms: &mut BTreeMap<String, RoomStatus>, room_id: String) -> &mut RoomStatus {
+    rooms.entry(room_id.clone()).or_insert_with(|| RoomStatus {
+        display_name: room_id.clone(),
+        room_id,
+        ..RoomStatus::default()
+    })
+}
+
+/// Every joined room, plus rooms the bot has stats for but has since left
+pub fn collect(client: &Client, stats: &UsageStats, send_queues: &SendQueues) -> Vec<RoomStatus> {
+    let mut rooms: BTreeMap<String, RoomStatus> = BTreeMap::new();
+    for room in client.joined_rooms() {
+        let room_id = room.room_id().to_string();
+        let display_name = room
+            .cached_display_name()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| room_id.clone());
+        rooms.insert(
+            room_id.clone(),
+            RoomStatus {
+                room_id,
+                display_name,
+                ..RoomStatus::default()
+            },
+        );
+    }
+
+    for (room_id, counters) in stats.room_totals() {
+        let room = entry(&mut rooms, room_id);
+        room.queries = counters.queries;
+        room.errors = counters.errors;
+        room.last_activity = Some(counters.last_activity).filter(|at| *at > 0);
+    }
+    for (room_id, at) in stats.last_responses() {
+        entry(&mut rooms, room_id).last_response = Some(at);
+    }
+    for (room_id, depth) in send_queues.room_depths() {
+        entry(&mut rooms, room_id).queue_depth = depth;
+    }
+    rooms.into_values().collect()
+}
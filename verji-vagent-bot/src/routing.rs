@@ -0,0 +1,177 @@
+//! Routing traces: what each responder decided about a message, and why
+//!
+//! When a message gets no answer, the trace tells which responders passed on
+//! it. `ResponderManager` records one per dispatched message: the middleware
+//! that dropped it, or every responder the chain reached with its verdict and,
+//! for declines, the machine-readable reason from [`Responder::route`]. Traces
+//! are logged at debug; the last `ROUTING_TRACE_MAX` are kept in memory and
+//! `!admin trace` shows a room's unanswered ones.
+//!
+//! [`Responder::route`]: crate::responder::Responder::route
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::db;
+
+/// What one responder did with a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The responder isn't active in the room
+    OutOfScope,
+    /// `route` declined, with the responder's reason if it gave one
+    Declined(Option<&'static str>),
+    /// Accepted, then returned `NotHandled`
+    NotHandled,
+    /// Let the rest of the chain try first
+    Deferred,
+    /// Timed out, failed or panicked
+    Failed,
+    /// Rewrote the message and restarted the chain
+    Rewritten,
+    /// Handled the message
+    Handled,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::OutOfScope => write!(f, "out of scope"),
+            Verdict::Declined(Some(reason)) => write!(f, "declined ({})", reason),
+            Verdict::Declined(None) => write!(f, "declined"),
+            Verdict::NotHandled => write!(f, "not handled"),
+            Verdict::Deferred => write!(f, "deferred"),
+            Verdict::Failed => write!(f, "failed"),
+            Verdict::Rewritten => write!(f, "rewritten"),
+            Verdict::Handled => write!(f, "handled"),
+        }
+    }
+}
+
+/// A responder's verdict on a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub responder: String,
+    pub verdict: Verdict,
+}
+
+/// How one message was routed
+#[derive(Debug, Clone)]
+pub struct RoutingTrace {
+    pub room_id: String,
+    pub event_id: String,
+    pub sender: String,
+    /// Unix seconds
+    pub at: u64,
+    /// Middleware that dropped the message before the responder chain
+    pub dropped_by: Option<String>,
+    /// In chain order, including the fallback pass and rewritten reruns
+    pub steps: Vec<Step>,
+    /// Whether anything was sent in reply
+    pub answered: bool,
+}
+
+impl RoutingTrace {
+    pub fn new(room_id: &str, event_id: &str, sender: &str) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            event_id: event_id.to_string(),
+            sender: sender.to_string(),
+            at: db::now_secs(),
+            dropped_by: None,
+            steps: Vec::new(),
+            answered: false,
+        }
+    }
+
+    pub fn push(&mut self, responder: &str, verdict: Verdict) {
+        self.steps.push(Step {
+            responder: responder.to_string(),
+            verdict,
+        });
+    }
+
+    /// One line: `name: verdict, ...`
+    pub fn summary(&self) -> String {
+        if let Some(middleware) = &self.dropped_by {
+            return format!("dropped by middleware '{}'", middleware);
+        }
+        if self.steps.is_empty() {
+            return "no responder reached".to_string();
+        }
+        self.steps
+            .iter()
+            .map(|step| format!("{}: {}", step.responder, step.verdict))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The most recent traces, oldest dropped first
+pub struct RoutingTraces {
+    capacity: usize,
+    traces: Mutex<VecDeque<RoutingTrace>>,
+}
+
+impl RoutingTraces {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            traces: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, trace: RoutingTrace) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut traces = self.traces.lock().unwrap();
+        if traces.len() >= self.capacity {
+            traces.pop_front();
+        }
+        traces.push_back(trace);
+    }
+
+    /// The latest unanswered messages of `room_id`, newest first
+    pub fn unanswered(&self, room_id: &str, limit: usize) -> Vec<RoutingTrace> {
+        self.traces
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|trace| trace.room_id == room_id && !trace.answered)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Markdown list of traces for `!admin trace`
+pub fn render(traces: &[RoutingTrace]) -> String {
+    if traces.is_empty() {
+        return "🧭 No unanswered messages in this room recently.".to_string();
+    }
+    let now = db::now_secs();
+    let mut out = format!("🧭 **{} unanswered message(s)**\n", traces.len());
+    for trace in traces {
+        out.push_str(&format!(
+            "\n- `{}` from {}, {}m ago",
+            trace.event_id,
+            trace.sender,
+            now.saturating_sub(trace.at) / 60
+        ));
+        match &trace.dropped_by {
+            Some(middleware) => {
+                out.push_str(&format!("\n  dropped by middleware `{}`", middleware))
+            }
+            None if trace.steps.is_empty() => out.push_str("\n  no responder reached"),
+            None => {
+                for step in &trace.steps {
+                    out.push_str(&format!("\n  - `{}`: {}", step.responder, step.verdict));
+                }
+            }
+        }
+    }
+    out
+}
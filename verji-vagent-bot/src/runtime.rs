@@ -0,0 +1,87 @@
+//! The state a running bot shares between its parts
+//!
+//! One per bot: [`BotBuilder`](crate::BotBuilder) creates it at startup and
+//! hands it to everything it wires up, and the test harness makes its own,
+//! so nothing here is process-wide and two bots in one process don't see
+//! each other's counters or queues.
+
+use std::sync::Arc;
+
+use crate::alias::Aliases;
+use crate::capabilities::Capabilities;
+use crate::fallback_dm::FallbackDm;
+use crate::i18n::Catalog;
+use crate::key_rotation::KeyRotations;
+use crate::membership::Memberships;
+use crate::metrics::Metrics;
+use crate::processing::ProcessingLanes;
+use crate::send_pacing::SendPacer;
+use crate::send_queue::SendQueues;
+use crate::send_timing::SendTiming;
+use crate::shadow::Shadow;
+use crate::startup::Startup;
+use crate::store_health::StoreHealth;
+
+/// Handles to a bot's shared state; cheap to clone
+#[derive(Clone)]
+pub struct Runtime {
+    /// Counters and latencies served on `/metrics`
+    pub metrics: Arc<Metrics>,
+    /// Translations of the bot's own phrases
+    pub catalog: Arc<Catalog>,
+    /// What the bot can do, known once the responders are registered
+    pub capabilities: Arc<Capabilities>,
+    /// Per-room and per-thread order of message handling
+    pub processing: Arc<ProcessingLanes>,
+    /// Rooms the bot left, and the requests running there
+    pub memberships: Arc<Memberships>,
+    /// Per-room and per-thread order of sends
+    pub send_queues: Arc<SendQueues>,
+    /// Pace and rate-limit retries of all sends
+    pub pacer: Arc<SendPacer>,
+    /// Room key sharing and send latencies per room
+    pub send_timing: Arc<SendTiming>,
+    /// Shadow mode and its log
+    pub shadow: Arc<Shadow>,
+    /// Room alias resolution
+    pub aliases: Arc<Aliases>,
+    /// Whether the store can still be written
+    pub store_health: Arc<StoreHealth>,
+    /// Answers a room refuses, sent by direct message
+    pub fallback_dm: Arc<FallbackDm>,
+    /// Room key rotations in sensitive rooms
+    pub key_rotations: Arc<KeyRotations>,
+    /// Startup timing and the background encryption setup
+    pub startup: Arc<Startup>,
+}
+
+impl Runtime {
+    /// Fresh state, started now; pacing and queue settings come from the environment
+    pub fn new(catalog: Catalog) -> Self {
+        let metrics = Arc::new(Metrics::default());
+        let startup = Arc::new(Startup::new(Arc::clone(&metrics)));
+        Self {
+            catalog: Arc::new(catalog),
+            capabilities: Arc::new(Capabilities::default()),
+            processing: Arc::new(ProcessingLanes::new(Arc::clone(&metrics))),
+            memberships: Arc::new(Memberships::new(Arc::clone(&metrics))),
+            send_queues: Arc::new(SendQueues::from_env(Arc::clone(&metrics))),
+            pacer: Arc::new(SendPacer::from_env(Arc::clone(&metrics))),
+            send_timing: Arc::new(SendTiming::new(Arc::clone(&metrics), Arc::clone(&startup))),
+            shadow: Arc::new(Shadow::default()),
+            aliases: Arc::new(Aliases::default()),
+            store_health: Arc::new(StoreHealth::default()),
+            fallback_dm: Arc::new(FallbackDm::default()),
+            key_rotations: Arc::new(KeyRotations::default()),
+            startup,
+            metrics,
+        }
+    }
+}
+
+impl Default for Runtime {
+    /// The embedded catalog and empty state, for tests and one-off commands
+    fn default() -> Self {
+        Self::new(Catalog::embedded())
+    }
+}
@@ -0,0 +1,292 @@
+//! Daily self-test: a canary question through the real graph
+//!
+//! A running process doesn't mean the bot can answer. With `SELF_TEST_AT`
+//! set, the bot asks vagent-graph `SELF_TEST_QUESTION` once a day at that
+//! time (UTC) over the same connection user queries take, and checks that an
+//! answer matching `SELF_TEST_EXPECT` (any non-empty answer by default)
+//! arrives within `SELF_TEST_SLA_SECS`. The result and latency are posted to
+//! `ADMIN_ROOM`, with the stage that failed or timed out, and counted in
+//! `self_test_total`. The canary never goes through the responders, so it
+//! doesn't count towards usage statistics, quotas or budgets.
+
+use chrono::{NaiveTime, Utc};
+use futures::StreamExt;
+use matrix_sdk::Client;
+use regex::Regex;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::admin_room;
+use crate::agent_service::{AgentEvent, AgentService, AskOptions};
+use crate::config;
+use crate::redis_client::{GraphMessageType, GraphRequest, RequestKind};
+use crate::runtime::Runtime;
+
+const DEFAULT_QUESTION: &str = "What can you help me with?";
+
+/// When and what the self-test asks
+#[derive(Debug, Clone)]
+pub struct SelfTest {
+    /// Time of day (UTC) it runs
+    pub at: NaiveTime,
+    pub question: String,
+    /// The answer must match this (None = any non-empty answer)
+    pub expect: Option<Regex>,
+    /// Longest the answer may take
+    pub sla: Duration,
+    /// Room the canary request is attributed to (None = `ADMIN_ROOM`)
+    pub room: Option<String>,
+}
+
+impl SelfTest {
+    /// None unless `SELF_TEST_AT` is set; an invalid time or pattern disables
+    /// the self-test with a warning
+    pub fn from_env() -> Option<Self> {
+        let at = std::env::var("SELF_TEST_AT")
+            .ok()
+            .filter(|at| !at.trim().is_empty())?;
+        let Ok(at) = NaiveTime::parse_from_str(at.trim(), "%H:%M") else {
+            warn!("⚠️  SELF_TEST_AT={} is not HH:MM, self-test disabled", at);
+            return None;
+        };
+        let expect = match std::env::var("SELF_TEST_EXPECT") {
+            Ok(pattern) if !pattern.is_empty() => match Regex::new(&pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!(
+                        "⚠️  SELF_TEST_EXPECT is not a valid regex, self-test disabled: {}",
+                        e
+                    );
+                    return None;
+                }
+            },
+            _ => None,
+        };
+        Some(Self {
+            at,
+            question: std::env::var("SELF_TEST_QUESTION")
+                .ok()
+                .filter(|question| !question.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_QUESTION.to_string()),
+            expect,
+            sla: Duration::from_secs(config::env_u64("SELF_TEST_SLA_SECS", 60)),
+            room: std::env::var("SELF_TEST_ROOM")
+                .ok()
+                .filter(|room| !room.is_empty()),
+        })
+    }
+
+    /// How long until the next run
+    fn until_next(&self) -> Duration {
+        let now = Utc::now();
+        let mut next = now.date_naive().and_time(self.at).and_utc();
+        if next <= now {
+            next += chrono::Duration::days(1);
+        }
+        (next - now).to_std().unwrap_or_default()
+    }
+}
+
+/// Where the canary was when it passed or failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Connecting to vagent-graph and submitting the request
+    Connect,
+    /// Waiting for the graph's first message
+    FirstMessage,
+    /// Waiting for the final answer after progress updates
+    Answer,
+    /// Checking the answer
+    Validation,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Connect => "connect",
+            Stage::FirstMessage => "first_message",
+            Stage::Answer => "answer",
+            Stage::Validation => "validation",
+        }
+    }
+}
+
+/// Outcome of one self-test
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub passed: bool,
+    /// Last stage reached; the failing one when it failed
+    pub stage: Stage,
+    pub latency: Duration,
+    pub detail: String,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.latency.as_secs_f64();
+        if self.passed {
+            write!(
+                f,
+                "✅ **Self-test passed** in {:.1}s: {}",
+                seconds, self.detail
+            )
+        } else {
+            write!(
+                f,
+                "❌ **Self-test failed** at stage `{}` after {:.1}s: {}",
+                self.stage.as_str(),
+                seconds,
+                self.detail
+            )
+        }
+    }
+}
+
+/// Ask the canary question once and check the answer
+pub async fn run(agent: &AgentService, test: &SelfTest, room_id: &str, user_id: &str) -> Report {
+    let started = Instant::now();
+    let report = |passed: bool, stage: Stage, detail: String| Report {
+        passed,
+        stage,
+        latency: started.elapsed(),
+        detail,
+    };
+
+    let request = GraphRequest::new(
+        RequestKind::Query,
+        format!("selftest-{}", uuid::Uuid::new_v4()),
+        test.question.clone(),
+        room_id.to_string(),
+        user_id.to_string(),
+    );
+    let deadline = tokio::time::Instant::now() + test.sla;
+    let mut events = agent.ask(
+        request,
+        AskOptions {
+            timeout: Some(test.sla),
+        },
+    );
+    let mut stage = Stage::Connect;
+    loop {
+        let event = match tokio::time::timeout_at(deadline, events.next()).await {
+            Ok(Some(event)) => event,
+            Ok(None) => {
+                return report(
+                    false,
+                    stage,
+                    "the request ended without an answer".to_string(),
+                )
+            }
+            Err(_) => {
+                let detail = format!("timed out after the {}s SLA", test.sla.as_secs());
+                return report(false, stage, detail);
+            }
+        };
+        match event {
+            AgentEvent::Submitted => stage = Stage::FirstMessage,
+            AgentEvent::Progress(_) => stage = Stage::Answer,
+            AgentEvent::Failed(e) => return report(false, stage, format!("{:#}", e)),
+            AgentEvent::Finished(message) => {
+                return match message.message_type {
+                    GraphMessageType::FinalResponse => {
+                        let (passed, detail) = validate(test, message.content.trim());
+                        report(passed, Stage::Validation, detail)
+                    }
+                    GraphMessageType::HitlRequest => report(
+                        false,
+                        Stage::Answer,
+                        "the graph asked a question instead of answering".to_string(),
+                    ),
+                    _ => report(
+                        false,
+                        Stage::Answer,
+                        format!("graph error: {}", excerpt(&message.content)),
+                    ),
+                };
+            }
+        }
+    }
+}
+
+/// Whether the answer is what `SELF_TEST_EXPECT` asks for, and why
+fn validate(test: &SelfTest, answer: &str) -> (bool, String) {
+    match &test.expect {
+        Some(regex) if regex.is_match(answer) => (true, format!("answer matches `{}`", regex)),
+        Some(regex) => (
+            false,
+            format!("answer doesn't match `{}`: {}", regex, excerpt(answer)),
+        ),
+        None if answer.is_empty() => (false, "empty answer".to_string()),
+        None => (true, format!("{} character answer", answer.chars().count())),
+    }
+}
+
+fn excerpt(text: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    if text.chars().count() <= MAX_CHARS {
+        return text.to_string();
+    }
+    format!("{}…", text.chars().take(MAX_CHARS).collect::<String>())
+}
+
+/// Run the self-test every day at `test.at` and post each result to `admin_room`
+pub fn spawn(
+    runtime: Runtime,
+    client: Client,
+    agent: Arc<AgentService>,
+    test: SelfTest,
+    admin_room: Option<String>,
+) -> JoinHandle<()> {
+    info!(
+        "🩺 Self-test scheduled daily at {} UTC (SLA {}s)",
+        test.at.format("%H:%M"),
+        test.sla.as_secs()
+    );
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(test.until_next()).await;
+
+            let user_id = client
+                .user_id()
+                .map_or_else(String::new, |user_id| user_id.to_string());
+            let room_id = test
+                .room
+                .as_deref()
+                .or(admin_room.as_deref())
+                .unwrap_or("self-test")
+                .to_string();
+            let report = run(&agent, &test, &room_id, &user_id).await;
+
+            let result = if report.passed { "pass" } else { "fail" };
+            let metrics = &runtime.metrics;
+            metrics.increment(
+                "self_test_total",
+                &[("result", result), ("stage", report.stage.as_str())],
+            );
+            metrics.observe_ms(
+                "self_test_latency_ms",
+                &[("result", result)],
+                report.latency.as_millis() as u64,
+            );
+            metrics.set_gauge("self_test_passing", &[], report.passed as u64);
+            if report.passed {
+                info!("🩺 {}", report);
+            } else {
+                warn!("🩺 {}", report);
+            }
+            if let Some(admin_room) = &admin_room {
+                admin_room::post(
+                    &runtime,
+                    &client,
+                    admin_room,
+                    "self-test result",
+                    &report.to_string(),
+                )
+                .await;
+            }
+        }
+    })
+}
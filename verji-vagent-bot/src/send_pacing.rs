@@ -0,0 +1,152 @@
+//! Pacing and rate-limit retries for everything the bot sends to rooms
+//!
+//! All sends of a bot share one pace (`SEND_MAX_PER_SECOND`). When the
+//! homeserver still answers `M_LIMIT_EXCEEDED`, the send is retried after the
+//! server's `retry_after_ms` (capped by `SEND_RETRY_MAX_WAIT_MS`, plus up to
+//! 10% jitter so queued sends don't retry in lockstep), at most
+//! `SEND_RETRY_ATTEMPTS` times.
+
+use matrix_sdk::ruma::api::client::error::{ErrorKind, RetryAfter};
+use std::future::IntoFuture;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::time::{sleep_until, Instant};
+use tracing::warn;
+
+use crate::config;
+use crate::metrics::Metrics;
+
+/// Wait when a 429 comes without `retry_after_ms`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// The pace of a bot's sends
+pub struct SendPacer {
+    /// Minimum gap between two sends (zero = unpaced)
+    interval: Duration,
+    retry_attempts: u32,
+    max_wait: Duration,
+    /// Earliest time the next send may start
+    next_slot: Mutex<Instant>,
+    metrics: Arc<Metrics>,
+}
+
+impl SendPacer {
+    /// At most `per_second` sends (0 = unpaced), retrying rate-limited ones
+    /// `retry_attempts` times, waiting at most `max_wait` each time
+    pub fn new(
+        per_second: u32,
+        retry_attempts: u32,
+        max_wait: Duration,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            interval: if per_second == 0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs(1) / per_second
+            },
+            retry_attempts,
+            max_wait,
+            next_slot: Mutex::new(Instant::now()),
+            metrics,
+        }
+    }
+
+    pub fn from_env(metrics: Arc<Metrics>) -> Self {
+        Self::new(
+            config::env_u64("SEND_MAX_PER_SECOND", 5) as u32,
+            config::env_u64("SEND_RETRY_ATTEMPTS", 3) as u32,
+            Duration::from_millis(config::env_u64("SEND_RETRY_MAX_WAIT_MS", 10_000)),
+            metrics,
+        )
+    }
+
+    /// Run a send in the pace, retrying while the homeserver rate-limits it
+    ///
+    /// `send` builds the request again for every attempt; `what` names it in logs
+    /// and metrics (e.g. `message`, `reaction`).
+    pub async fn send<T, F, Fut>(&self, what: &str, mut send: F) -> Result<T, matrix_sdk::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: IntoFuture<Output = Result<T, matrix_sdk::Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.acquire().await;
+            let error = match send().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let Some(ErrorKind::LimitExceeded { retry_after }) = error.client_api_error_kind()
+            else {
+                return Err(error);
+            };
+
+            self.metrics
+                .increment("send_rate_limited_total", &[("kind", what)]);
+            if attempt >= self.retry_attempts {
+                warn!(
+                    "🐢 Homeserver still rate-limits {} sends after {} retries, giving up",
+                    what, attempt
+                );
+                return Err(error);
+            }
+            attempt += 1;
+
+            let wait = self.backoff(retry_after.as_ref());
+            warn!(
+                "🐢 Rate-limited sending {}, retry {}/{} in {}ms",
+                what,
+                attempt,
+                self.retry_attempts,
+                wait.as_millis()
+            );
+            self.metrics.increment_by(
+                "send_rate_limit_delay_ms_total",
+                &[("kind", what)],
+                wait.as_millis() as u64,
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Wait for this send's turn
+    async fn acquire(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        sleep_until(slot).await;
+    }
+
+    /// Pause before retrying, from the server's hint
+    fn backoff(&self, retry_after: Option<&RetryAfter>) -> Duration {
+        let wait = match retry_after {
+            Some(RetryAfter::Delay(delay)) => *delay,
+            Some(RetryAfter::DateTime(at)) => {
+                at.duration_since(SystemTime::now()).unwrap_or_default()
+            }
+            None => DEFAULT_RETRY_AFTER,
+        };
+        let wait = wait.min(self.max_wait);
+        wait + jitter(wait / 10)
+    }
+}
+
+/// Random-enough duration below `max`, from the clock's sub-second nanos
+fn jitter(max: Duration) -> Duration {
+    let max_nanos = max.as_nanos() as u64;
+    if max_nanos == 0 {
+        return Duration::ZERO;
+    }
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u64;
+    Duration::from_nanos(nanos % max_nanos)
+}
@@ -0,0 +1,182 @@
+//! Ordered per-thread queue for messages the bot sends
+//!
+//! A send first takes a turn in the queue of its lane: a thread of a room, or
+//! the room's main timeline. Turns are granted in the order they were
+//! requested and can cover several messages, so the parts of a split answer
+//! go out back-to-back and a progress update can't land between them.
+//! Different threads of one room have separate lanes and send concurrently.
+//! Consecutive sends in a lane are at least `SEND_ROOM_INTERVAL_MS` apart;
+//! each one still goes through the global pace and rate-limit retries of
+//! `send_pacing`.
+
+use matrix_sdk::ruma::RoomId;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tracing::debug;
+
+use crate::config;
+use crate::metrics::Metrics;
+
+/// How long shutdown waits for queued sends
+pub const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often `drain` checks whether the queues are empty
+const DRAIN_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+struct RoomQueue {
+    /// Held by the current turn; when the room's last send finished
+    last_sent: Arc<AsyncMutex<Option<Instant>>>,
+    /// Turns waiting or in progress
+    depth: AtomicUsize,
+}
+
+/// The send lanes of a bot's rooms
+pub struct SendQueues {
+    /// Minimum gap between two sends in the same lane
+    interval: Duration,
+    /// (room ID, thread root) -> queue; None is the main timeline
+    lanes: Mutex<HashMap<(String, Option<String>), Arc<RoomQueue>>>,
+    /// Turns waiting or in progress across all lanes
+    total: AtomicUsize,
+    metrics: Arc<Metrics>,
+}
+
+/// Counts a turn in the queue depth from request until it ends
+struct Queued {
+    queues: Arc<SendQueues>,
+    queue: Arc<RoomQueue>,
+}
+
+impl Queued {
+    fn new(queues: &Arc<SendQueues>, queue: Arc<RoomQueue>) -> Self {
+        queue.depth.fetch_add(1, Ordering::SeqCst);
+        let total = queues.total.fetch_add(1, Ordering::SeqCst) + 1;
+        queues
+            .metrics
+            .set_gauge("send_queue_depth", &[], total as u64);
+        Self {
+            queues: Arc::clone(queues),
+            queue,
+        }
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.queue.depth.fetch_sub(1, Ordering::SeqCst);
+        let total = self.queues.total.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.queues
+            .metrics
+            .set_gauge("send_queue_depth", &[], total as u64);
+    }
+}
+
+/// A lane's turn to send; other sends to the lane wait until it is dropped
+pub struct Turn {
+    last_sent: OwnedMutexGuard<Option<Instant>>,
+    queued: Queued,
+}
+
+impl Turn {
+    /// Run one send once the lane's inter-message delay has passed
+    pub async fn send<T>(&mut self, send: impl Future<Output = T>) -> T {
+        if let Some(last_sent) = *self.last_sent {
+            let interval = self.queued.queues.interval;
+            tokio::time::sleep_until((last_sent + interval).into()).await;
+        }
+        let result = send.await;
+        *self.last_sent = Some(Instant::now());
+        result
+    }
+}
+
+impl SendQueues {
+    pub fn new(interval: Duration, metrics: Arc<Metrics>) -> Self {
+        Self {
+            interval,
+            lanes: Mutex::new(HashMap::new()),
+            total: AtomicUsize::new(0),
+            metrics,
+        }
+    }
+
+    /// Lanes `SEND_ROOM_INTERVAL_MS` apart
+    pub fn from_env(metrics: Arc<Metrics>) -> Self {
+        Self::new(
+            Duration::from_millis(config::env_u64("SEND_ROOM_INTERVAL_MS", 250)),
+            metrics,
+        )
+    }
+
+    /// Wait for the turn to send in a thread of a room (None: its main timeline)
+    pub async fn turn(self: &Arc<Self>, room_id: &RoomId, thread_id: Option<&str>) -> Turn {
+        let queue = Arc::clone(
+            self.lanes
+                .lock()
+                .unwrap()
+                .entry((room_id.to_string(), thread_id.map(str::to_string)))
+                .or_default(),
+        );
+        let queued = Queued::new(self, Arc::clone(&queue));
+        let ahead = queue.depth.load(Ordering::SeqCst) - 1;
+        if ahead > 0 {
+            debug!(
+                "📬 Send to {} (thread {}) queued behind {} other(s)",
+                room_id,
+                thread_id.unwrap_or("main"),
+                ahead
+            );
+        }
+
+        let started = Instant::now();
+        let last_sent = Arc::clone(&queue.last_sent).lock_owned().await;
+        self.metrics.observe_ms(
+            "send_queue_wait_ms",
+            &[],
+            started.elapsed().as_millis() as u64,
+        );
+        Turn { last_sent, queued }
+    }
+
+    /// Turns waiting or in progress across all lanes
+    pub fn depth(&self) -> usize {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    /// Turns waiting or in progress per room, threads included; idle rooms are left out
+    pub fn room_depths(&self) -> HashMap<String, usize> {
+        let mut rooms = HashMap::new();
+        for ((room, _), queue) in self.lanes.lock().unwrap().iter() {
+            let depth = queue.depth.load(Ordering::SeqCst);
+            if depth > 0 {
+                *rooms.entry(room.clone()).or_default() += depth;
+            }
+        }
+        rooms
+    }
+
+    /// Forget the idle lanes of a room the bot left
+    pub fn remove_room(&self, room_id: &str) {
+        self.lanes
+            .lock()
+            .unwrap()
+            .retain(|(room, _), queue| room != room_id || queue.depth.load(Ordering::SeqCst) > 0);
+    }
+
+    /// Wait up to `timeout` for queued sends to finish; returns whether they did
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.depth() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(DRAIN_POLL).await;
+        }
+        true
+    }
+}
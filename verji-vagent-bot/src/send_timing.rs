@@ -0,0 +1,171 @@
+//! Per-room timing of sends, split into room key sharing and the send itself
+//!
+//! Before a send to an encrypted room the room key is pre-shared, so the
+//! Megolm work the SDK would otherwise do inside the send is measured on its
+//! own: claiming one-time keys and sharing a new outbound session with every
+//! member device. The rest (encryption and the upload, including any send
+//! pacing and rate-limit retries) is timed as the send.
+//! Both are histograms labeled with the room's size bucket, classified once
+//! per room:
+//!
+//! - `room_key_share_ms{size, first}`: `first="true"` for the first send to the
+//!   room since startup, which usually claims keys for every device
+//! - `room_send_ms{size, encrypted}`
+//!
+//! Pre-sharing that finds everything in place is near-instant; one slower
+//! than `KEY_SHARE_SLOW` means a session was (re)shared and is logged with the
+//! number of member devices it went to.
+
+use matrix_sdk::{room::Room, RoomMemberships};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::metrics::Metrics;
+use crate::startup::Startup;
+
+/// Pre-sharing slower than this did network work (new session or devices)
+const KEY_SHARE_SLOW: Duration = Duration::from_millis(50);
+
+#[derive(Default)]
+struct Rooms {
+    /// Size bucket of each room, fixed at its first send
+    sizes: HashMap<String, &'static str>,
+    /// Rooms the bot has sent to since startup
+    sent_to: HashSet<String>,
+}
+
+/// Bucket label of a room with `members` joined members
+pub fn size_bucket(members: u64) -> &'static str {
+    match members {
+        0..=2 => "dm",
+        3..=10 => "small",
+        11..=50 => "medium",
+        51..=200 => "large",
+        _ => "huge",
+    }
+}
+
+/// A bot's send timings
+pub struct SendTiming {
+    rooms: Mutex<Rooms>,
+    metrics: Arc<Metrics>,
+    /// Sends to encrypted rooms wait for its encryption setup
+    startup: Arc<Startup>,
+}
+
+impl SendTiming {
+    pub fn new(metrics: Arc<Metrics>, startup: Arc<Startup>) -> Self {
+        Self {
+            rooms: Mutex::default(),
+            metrics,
+            startup,
+        }
+    }
+
+    /// The room's size bucket, classified on first use
+    fn room_size(&self, room: &Room) -> &'static str {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms
+            .sizes
+            .entry(room.room_id().to_string())
+            .or_insert_with(|| size_bucket(room.joined_members_count()))
+    }
+
+    /// Run `send` for `room`, timing room key sharing and the send itself
+    pub async fn timed<T, F>(&self, room: &Room, send: F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let room_id = room.room_id().to_string();
+        let size = self.room_size(room);
+        let encrypted = room
+            .latest_encryption_state()
+            .await
+            .map(|state| state.is_encrypted())
+            .unwrap_or(false);
+
+        if encrypted {
+            // Not before cross-signing and backups are in place
+            self.startup.encryption_ready().await;
+            let first = self.rooms.lock().unwrap().sent_to.insert(room_id.clone());
+            let started = Instant::now();
+            // Failures resurface in the send, which shares the key itself
+            if let Err(e) = room.preshare_room_key().await {
+                warn!("Failed to share the room key in {}: {}", room_id, e);
+            }
+            let elapsed = started.elapsed();
+            self.metrics.observe_ms(
+                "room_key_share_ms",
+                &[
+                    ("size", size),
+                    ("first", if first { "true" } else { "false" }),
+                ],
+                elapsed.as_millis() as u64,
+            );
+            if elapsed >= KEY_SHARE_SLOW {
+                let devices = member_devices(room).await;
+                debug!(
+                    "🔐 Shared the room key in {} ({} room) in {}ms with {} member devices{}",
+                    room_id,
+                    size,
+                    elapsed.as_millis(),
+                    devices.map_or_else(|| "?".to_string(), |devices| devices.to_string()),
+                    if first { " (first send)" } else { "" }
+                );
+            } else {
+                debug!(
+                    "🔐 Room key already shared in {} ({}ms)",
+                    room_id,
+                    elapsed.as_millis()
+                );
+            }
+        }
+
+        let started = Instant::now();
+        let result = send.await;
+        let elapsed = started.elapsed();
+        self.metrics.observe_ms(
+            "room_send_ms",
+            &[
+                ("size", size),
+                ("encrypted", if encrypted { "true" } else { "false" }),
+            ],
+            elapsed.as_millis() as u64,
+        );
+        debug!(
+            "📤 Send to {} ({} room, {}) took {}ms",
+            room_id,
+            size,
+            if encrypted {
+                "encrypted"
+            } else {
+                "unencrypted"
+            },
+            elapsed.as_millis()
+        );
+        result
+    }
+}
+
+/// Devices of the room's active members, as known to the crypto store
+async fn member_devices(room: &Room) -> Option<usize> {
+    let members = match room.members(RoomMemberships::ACTIVE).await {
+        Ok(members) => members,
+        Err(e) => {
+            debug!("Failed to list members of {}: {}", room.room_id(), e);
+            return None;
+        }
+    };
+    let encryption = room.client().encryption();
+    let mut devices = 0;
+    for member in members {
+        match encryption.get_user_devices(member.user_id()).await {
+            Ok(user_devices) => devices += user_devices.devices().count(),
+            Err(e) => debug!("Failed to get devices of {}: {}", member.user_id(), e),
+        }
+    }
+    Some(devices)
+}
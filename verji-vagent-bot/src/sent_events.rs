@@ -0,0 +1,362 @@
+//! Events the bot sent, and which request produced them
+//!
+//! Every message the bot sends is recorded with its room, kind and (for agent
+//! answers) the request ID, so reactions and redactions can be traced back to
+//! it. The in-memory registry keeps the newest `SENT_EVENTS_MAX` entries for at
+//! most `SENT_EVENTS_MAX_AGE_HOURS`; final answers are also written to the bot
+//! database, so feedback on them still correlates after a restart.
+
+use anyhow::{Context, Result};
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use rusqlite::OptionalExtension;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::db;
+
+/// What a sent event was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SentKind {
+    /// Progress update while the agent works
+    Progress,
+    /// Responder output, e.g. the agent's answer
+    Final,
+    /// Reply to an agent request that failed
+    Error,
+    /// Reaction to the triggering message
+    Ack,
+}
+
+impl SentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SentKind::Progress => "progress",
+            SentKind::Final => "final",
+            SentKind::Error => "error",
+            SentKind::Ack => "ack",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SentEvent {
+    /// Agent request that produced it (None for command output and the like)
+    pub request_id: Option<String>,
+    pub room_id: String,
+    pub kind: SentKind,
+    /// Unix seconds
+    pub sent_at: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    events: HashMap<OwnedEventId, SentEvent>,
+    /// Oldest first
+    order: VecDeque<OwnedEventId>,
+    /// Triggering event -> agent query answering it, until the answer is sent
+    requests: HashMap<OwnedEventId, Link>,
+}
+
+/// Agent request whose output is about to be sent
+struct Link {
+    request_id: String,
+    /// Final for an answer, Error for a failure reply
+    kind: SentKind,
+    /// Unix seconds
+    linked_at: u64,
+}
+
+/// Bounded registry of the bot's sent events
+pub struct SentEventRegistry {
+    entries: Mutex<Entries>,
+    max_entries: usize,
+    max_age: Duration,
+    /// Bot database for final answers (None = memory only)
+    db_path: Option<PathBuf>,
+}
+
+impl SentEventRegistry {
+    pub fn in_memory(max_entries: usize, max_age: Duration) -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            max_entries: max_entries.max(1),
+            max_age,
+            db_path: None,
+        }
+    }
+
+    /// Registry persisting final answers to the bot database
+    pub fn open(store_path: &Path, max_entries: usize, max_age: Duration) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS sent_events (
+                event_id TEXT PRIMARY KEY,
+                request_id TEXT,
+                room_id TEXT NOT NULL,
+                sent_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS sent_events_time ON sent_events (sent_at);
+            CREATE INDEX IF NOT EXISTS sent_events_request ON sent_events (request_id);",
+        )
+        .context("Failed to create sent_events table")?;
+
+        let cutoff = db::now_secs().saturating_sub(max_age.as_secs());
+        let pruned = conn.execute(
+            "DELETE FROM sent_events WHERE sent_at < ?1",
+            [cutoff as i64],
+        )?;
+        if pruned > 0 {
+            debug!("Pruned {} expired sent events", pruned);
+        }
+
+        Ok(Self {
+            db_path: Some(db_path),
+            ..Self::in_memory(max_entries, max_age)
+        })
+    }
+
+    /// Remember that the agent request `request_id` answers `trigger`
+    ///
+    /// The responder output later sent for `trigger` is recorded with it.
+    pub fn link_request(&self, trigger: &EventId, request_id: &str) {
+        self.link(trigger, request_id, SentKind::Final);
+    }
+
+    /// Like `link_request`, for the reply to a request that failed
+    pub fn link_failure(&self, trigger: &EventId, request_id: &str) {
+        self.link(trigger, request_id, SentKind::Error);
+    }
+
+    fn link(&self, trigger: &EventId, request_id: &str, kind: SentKind) {
+        let now = db::now_secs();
+        let cutoff = now.saturating_sub(self.max_age.as_secs());
+        let mut entries = self.entries.lock().unwrap();
+        entries.requests.retain(|_, link| link.linked_at >= cutoff);
+        entries.requests.insert(
+            trigger.to_owned(),
+            Link {
+                request_id: request_id.to_string(),
+                kind,
+                linked_at: now,
+            },
+        );
+    }
+
+    /// Request linked to `trigger` and the kind of its output, if any; the
+    /// link is used up
+    pub fn take_request(&self, trigger: &EventId) -> Option<(String, SentKind)> {
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .requests
+            .remove(trigger)
+            .map(|link| (link.request_id, link.kind))
+    }
+
+    /// Record a sent event; final answers with a request are also persisted
+    pub async fn record(
+        &self,
+        event_id: OwnedEventId,
+        room_id: &str,
+        kind: SentKind,
+        request_id: Option<&str>,
+    ) {
+        let event = SentEvent {
+            request_id: request_id.map(str::to_string),
+            room_id: room_id.to_string(),
+            kind,
+            sent_at: db::now_secs(),
+        };
+        debug!(
+            "Sent {} event {} (request {:?})",
+            kind.as_str(),
+            event_id,
+            event.request_id
+        );
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if entries
+                .events
+                .insert(event_id.clone(), event.clone())
+                .is_none()
+            {
+                entries.order.push_back(event_id.clone());
+            }
+            self.evict(&mut entries, event.sent_at);
+        }
+
+        if let (Some(db_path), SentKind::Final, Some(request_id)) =
+            (&self.db_path, kind, &event.request_id)
+        {
+            if let Err(e) = persist(db_path, &event_id, request_id, &event, self.max_age).await {
+                warn!("Failed to persist sent event {}: {:#}", event_id, e);
+            }
+        }
+    }
+
+    /// The sent event with this ID, if it is one of the bot's
+    pub async fn lookup(&self, event_id: &EventId) -> Option<SentEvent> {
+        if let Some(event) = self.entries.lock().unwrap().events.get(event_id) {
+            return Some(event.clone());
+        }
+        let db_path = self.db_path.clone()?;
+
+        let cutoff = db::now_secs().saturating_sub(self.max_age.as_secs());
+        let event_id = event_id.to_string();
+        let lookup = tokio::task::spawn_blocking(move || -> Result<Option<SentEvent>> {
+            let conn = db::open(&db_path)?;
+            let event = conn
+                .query_row(
+                    "SELECT request_id, room_id, sent_at FROM sent_events
+                     WHERE event_id = ?1 AND sent_at >= ?2",
+                    rusqlite::params![event_id, cutoff as i64],
+                    |row| {
+                        Ok(SentEvent {
+                            request_id: row.get(0)?,
+                            room_id: row.get(1)?,
+                            kind: SentKind::Final,
+                            sent_at: row.get::<_, i64>(2)? as u64,
+                        })
+                    },
+                )
+                .optional()?;
+            Ok(event)
+        })
+        .await;
+
+        match lookup {
+            Ok(Ok(event)) => event,
+            Ok(Err(e)) => {
+                warn!("Failed to look up sent event: {:#}", e);
+                None
+            }
+            Err(e) => {
+                warn!("Sent event lookup panicked: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Whether an answer to `request_id` was sent within the retention
+    ///
+    /// With request IDs derived from event IDs this tells a reprocessed event
+    /// from a new one.
+    pub async fn answered(&self, request_id: &str) -> bool {
+        let in_memory = self.entries.lock().unwrap().events.values().any(|event| {
+            event.kind == SentKind::Final && event.request_id.as_deref() == Some(request_id)
+        });
+        if in_memory {
+            return true;
+        }
+        let Some(db_path) = self.db_path.clone() else {
+            return false;
+        };
+
+        let cutoff = db::now_secs().saturating_sub(self.max_age.as_secs());
+        let request_id = request_id.to_string();
+        let lookup = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = db::open(&db_path)?;
+            let found = conn
+                .query_row(
+                    "SELECT 1 FROM sent_events WHERE request_id = ?1 AND sent_at >= ?2 LIMIT 1",
+                    rusqlite::params![request_id, cutoff as i64],
+                    |_| Ok(()),
+                )
+                .optional()?;
+            Ok(found.is_some())
+        })
+        .await;
+
+        match lookup {
+            Ok(Ok(found)) => found,
+            Ok(Err(e)) => {
+                warn!("Failed to look up answered request: {:#}", e);
+                false
+            }
+            Err(e) => {
+                warn!("Answered request lookup panicked: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Drop a sent event (e.g. after it was redacted), returning it
+    pub async fn forget(&self, event_id: &EventId) -> Option<SentEvent> {
+        let forgotten = {
+            let mut entries = self.entries.lock().unwrap();
+            let event = entries.events.remove(event_id);
+            if event.is_some() {
+                entries.order.retain(|id| id != event_id);
+            }
+            event
+        };
+        let forgotten = match forgotten {
+            Some(event) => Some(event),
+            None => self.lookup(event_id).await,
+        };
+
+        if let Some(db_path) = self.db_path.clone() {
+            let event_id = event_id.to_string();
+            let deleted = tokio::task::spawn_blocking(move || -> Result<usize> {
+                let conn = db::open(&db_path)?;
+                Ok(conn.execute("DELETE FROM sent_events WHERE event_id = ?1", [event_id])?)
+            })
+            .await;
+            if let Ok(Err(e)) = deleted {
+                warn!("Failed to delete sent event: {:#}", e);
+            }
+        }
+        forgotten
+    }
+
+    /// Drop the oldest events beyond the count limit and those past the age limit
+    fn evict(&self, entries: &mut Entries, now: u64) {
+        let cutoff = now.saturating_sub(self.max_age.as_secs());
+        while let Some(oldest) = entries.order.front() {
+            let expired = entries
+                .events
+                .get(oldest)
+                .map_or(true, |event| event.sent_at < cutoff);
+            if !expired && entries.events.len() <= self.max_entries {
+                break;
+            }
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.events.remove(&oldest);
+            }
+        }
+    }
+}
+
+async fn persist(
+    db_path: &Path,
+    event_id: &EventId,
+    request_id: &str,
+    event: &SentEvent,
+    max_age: Duration,
+) -> Result<()> {
+    let db_path = db_path.to_path_buf();
+    let event_id = event_id.to_string();
+    let request_id = request_id.to_string();
+    let room_id = event.room_id.clone();
+    let sent_at = event.sent_at;
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let conn = db::open(&db_path)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO sent_events (event_id, request_id, room_id, sent_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![event_id, request_id, room_id, sent_at as i64],
+        )?;
+        let cutoff = sent_at.saturating_sub(max_age.as_secs());
+        conn.execute(
+            "DELETE FROM sent_events WHERE sent_at < ?1",
+            [cutoff as i64],
+        )?;
+        Ok(())
+    })
+    .await
+    .context("Sent event write panicked")?
+}
@@ -0,0 +1,346 @@
+//! Shadow mode: run the whole pipeline, but write nothing to rooms
+//!
+//! With `SHADOW_MODE=true` every write to a room (messages, reactions,
+//! attachments, edits, redactions, typing notices, pins, room account data,
+//! new direct chats) is dropped at the point the bot would send it, and
+//! recorded in `SHADOW_LOG` instead: one JSON line per write, with the event
+//! that triggered it. Dropped messages get made-up event IDs, so later edits
+//! and redactions of them are recorded as well. A candidate build can then
+//! answer real traffic next to the live bot without anyone seeing it.
+//!
+//! A live bot with `SHADOW_LOG` set records what it really sends in the same
+//! format, and `shadow-diff` compares the answers in two such files.
+
+use anyhow::{Context, Result};
+use matrix_sdk::ruma::{EventId, OwnedEventId, RoomId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+
+tokio::task_local! {
+    /// The event the current task is answering
+    static TRIGGER: OwnedEventId;
+}
+
+/// A bot's shadow mode; off (writes go out unrecorded) until installed
+#[derive(Default)]
+pub struct Shadow {
+    mode: OnceLock<Mode>,
+}
+
+struct Mode {
+    /// Drop room writes, not just record them
+    suppress: bool,
+    log: Option<Mutex<File>>,
+}
+
+/// One room write, as recorded in the shadow log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShadowRecord {
+    /// RFC 3339
+    pub at: String,
+    /// Event being answered, if the write belongs to one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    pub room_id: String,
+    /// `message`, `reaction`, `attachment`, `edit`, `redaction`, `typing`,
+    /// `pins` or `account_data`
+    pub kind: String,
+    /// Text sent, reaction key or file name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Event an edit or redaction applies to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Event ID of the write; made up when it was suppressed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<String>,
+    /// Dropped instead of sent
+    pub shadow: bool,
+}
+
+/// A room write about to happen
+#[derive(Debug, Clone, Copy)]
+pub struct RoomWrite<'a> {
+    room_id: &'a str,
+    kind: &'static str,
+    trigger: Option<&'a EventId>,
+    target: Option<&'a EventId>,
+    body: Option<&'a str>,
+}
+
+impl<'a> RoomWrite<'a> {
+    pub fn new(room_id: &'a RoomId, kind: &'static str) -> Self {
+        Self {
+            room_id: room_id.as_str(),
+            kind,
+            trigger: None,
+            target: None,
+            body: None,
+        }
+    }
+
+    /// The event being answered; defaults to the one of the current [`scope`]
+    pub fn trigger(mut self, trigger: &'a EventId) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+
+    pub fn target(mut self, target: &'a EventId) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn body(mut self, body: &'a str) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    fn record(&self, event_id: Option<&EventId>, shadow: bool) -> ShadowRecord {
+        let trigger = self
+            .trigger
+            .map(|trigger| trigger.to_string())
+            .or_else(|| TRIGGER.try_with(|trigger| trigger.to_string()).ok());
+        ShadowRecord {
+            at: chrono::Utc::now().to_rfc3339(),
+            trigger,
+            room_id: self.room_id.to_string(),
+            kind: self.kind.to_string(),
+            body: self.body.map(str::to_string),
+            target: self.target.map(|target| target.to_string()),
+            event_id: event_id.map(|event_id| event_id.to_string()),
+            shadow,
+        }
+    }
+}
+
+/// Run `future` with its room writes attributed to `trigger`
+pub async fn scope<F: Future>(trigger: OwnedEventId, future: F) -> F::Output {
+    TRIGGER.scope(trigger, future).await
+}
+
+/// The event the current task is answering, if it runs in a [`scope`]
+pub fn current_trigger() -> Option<OwnedEventId> {
+    TRIGGER.try_with(|trigger| trigger.clone()).ok()
+}
+
+impl Shadow {
+    /// Turn shadow mode on, and record room writes to `log`
+    ///
+    /// Without this (or with neither set) writes go out unrecorded. Only the
+    /// first call has an effect.
+    pub fn install(&self, suppress: bool, log: Option<&Path>) -> Result<()> {
+        if !suppress && log.is_none() {
+            return Ok(());
+        }
+        let log = match log {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open shadow log {}", path.display()))?;
+                Some(Mutex::new(file))
+            }
+            None => None,
+        };
+        if self.mode.set(Mode { suppress, log }).is_ok() && suppress {
+            warn!("👻 Shadow mode: nothing will be sent to rooms");
+        }
+        Ok(())
+    }
+
+    /// Whether room writes are being dropped
+    pub fn is_active(&self) -> bool {
+        self.mode.get().is_some_and(|mode| mode.suppress)
+    }
+
+    /// In shadow mode, record `write` instead of sending it
+    ///
+    /// Returns the made-up event ID to use in place of the real one, or `None`
+    /// when the write should go out.
+    pub fn suppress(&self, write: RoomWrite<'_>) -> Option<OwnedEventId> {
+        let mode = self.mode.get().filter(|mode| mode.suppress)?;
+        let event_id = EventId::parse(format!("$shadow{}", uuid::Uuid::new_v4().simple()))
+            .expect("made-up event ID is valid");
+        mode.append(&write.record(Some(&event_id), true));
+        Some(event_id)
+    }
+
+    /// Record a write that was sent, when a shadow log is kept
+    pub fn record_sent(&self, write: RoomWrite<'_>, event_id: &EventId) {
+        if let Some(mode) = self.mode.get() {
+            mode.append(&write.record(Some(event_id), false));
+        }
+    }
+}
+
+impl Mode {
+    fn append(&self, record: &ShadowRecord) {
+        let Some(log) = &self.log else {
+            return;
+        };
+        let Ok(line) = serde_json::to_string(record) else {
+            return;
+        };
+        let mut file = log.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(file, "{}", line) {
+            warn!("Failed to write the shadow log: {}", e);
+        }
+    }
+}
+
+/// Read a shadow log; lines that don't parse are skipped
+pub fn read_log(path: &Path) -> Result<Vec<ShadowRecord>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("Skipping shadow log line in {}: {}", path.display(), e),
+        }
+    }
+    Ok(records)
+}
+
+/// What each side answered to one trigger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerDiff {
+    pub trigger: String,
+    /// `None` if the trigger isn't in that file at all
+    pub a: Option<Vec<String>>,
+    pub b: Option<Vec<String>>,
+}
+
+/// Answers in two shadow logs, compared by triggering event
+#[derive(Debug, Clone, Default)]
+pub struct ShadowDiff {
+    /// Triggers both sides answered the same way
+    pub same: usize,
+    /// Triggers answered differently, or by one side only
+    pub different: Vec<TriggerDiff>,
+}
+
+/// The visible answer to each trigger: messages, attachments and reactions
+/// in order, with the last edit of a message in place of its first version
+fn answers(records: &[ShadowRecord]) -> BTreeMap<String, Vec<String>> {
+    let mut by_trigger: BTreeMap<String, Vec<(Option<String>, String)>> = BTreeMap::new();
+    let mut edits: BTreeMap<String, String> = BTreeMap::new();
+    let mut redacted: BTreeSet<String> = BTreeSet::new();
+
+    for record in records {
+        let Some(trigger) = &record.trigger else {
+            continue;
+        };
+        let body = record.body.clone().unwrap_or_default();
+        match record.kind.as_str() {
+            "message" | "attachment" => by_trigger
+                .entry(trigger.clone())
+                .or_default()
+                .push((record.event_id.clone(), body)),
+            "reaction" => by_trigger
+                .entry(trigger.clone())
+                .or_default()
+                .push((record.event_id.clone(), format!("reaction {}", body))),
+            "edit" => {
+                if let Some(target) = &record.target {
+                    edits.insert(target.clone(), body);
+                }
+            }
+            "redaction" => {
+                if let Some(target) = &record.target {
+                    redacted.insert(target.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    by_trigger
+        .into_iter()
+        .map(|(trigger, sent)| {
+            let shown = sent
+                .into_iter()
+                .filter(|(event_id, _)| !event_id.as_ref().is_some_and(|id| redacted.contains(id)))
+                .map(|(event_id, body)| {
+                    event_id
+                        .and_then(|id| edits.get(&id).cloned())
+                        .unwrap_or(body)
+                })
+                .collect();
+            (trigger, shown)
+        })
+        .collect()
+}
+
+/// Compare the answers in two shadow logs
+pub fn diff(a: &[ShadowRecord], b: &[ShadowRecord]) -> ShadowDiff {
+    let a = answers(a);
+    let mut b = answers(b);
+    let mut result = ShadowDiff::default();
+
+    for (trigger, a_answer) in a {
+        match b.remove(&trigger) {
+            Some(b_answer) if b_answer == a_answer => result.same += 1,
+            b_answer => result.different.push(TriggerDiff {
+                trigger,
+                a: Some(a_answer),
+                b: b_answer,
+            }),
+        }
+    }
+    for (trigger, b_answer) in b {
+        result.different.push(TriggerDiff {
+            trigger,
+            a: None,
+            b: Some(b_answer),
+        });
+    }
+    result.different.sort_by(|x, y| x.trigger.cmp(&y.trigger));
+    result
+}
+
+impl ShadowDiff {
+    /// Readable report of the differences
+    pub fn render(&self) -> String {
+        fn side(answer: &Option<Vec<String>>) -> String {
+            match answer {
+                None => "  (not answered)".to_string(),
+                Some(messages) if messages.is_empty() => "  (nothing visible)".to_string(),
+                Some(messages) => messages
+                    .iter()
+                    .map(|message| format!("  | {}", message.replace('\n', "\n  | ")))
+                    .collect::<Vec<_>>()
+                    .join("\n  --\n"),
+            }
+        }
+
+        let mut out = String::new();
+        for diff in &self.different {
+            out.push_str(&format!(
+                "=== {}\n< a\n{}\n> b\n{}\n\n",
+                diff.trigger,
+                side(&diff.a),
+                side(&diff.b)
+            ));
+        }
+        out.push_str(&format!(
+            "{} same, {} different\n",
+            self.same,
+            self.different.len()
+        ));
+        out
+    }
+}
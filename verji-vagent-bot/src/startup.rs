@@ -0,0 +1,192 @@
+//! Startup off the critical path: background encryption setup, and how long
+//! the bot takes until it answers
+//!
+//! Key backup setup of a new login can take half a minute on a slow
+//! homeserver, but nothing in it is needed to answer in unencrypted rooms. It
+//! runs in the background once the initial sync is done, while the bot
+//! already serves; only sends to encrypted rooms wait for it, for at most
+//! `ENCRYPTION_SETUP_HOLD_SECS`. `/ready` reports the phase and a failed
+//! setup is posted to `ADMIN_ROOM`. An explicit `--reset-encryption` still
+//! runs before the initial sync.
+//!
+//! The first response and the end of the setup are both logged as time since
+//! startup: the latter is when the first response would have come at the
+//! earliest with the setup before the sync loop, the former is when it did.
+
+use anyhow::Result;
+use matrix_sdk::Client;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::admin_room;
+use crate::config;
+use crate::metrics::Metrics;
+use crate::runtime::Runtime;
+
+/// Where the background encryption setup is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionSetup {
+    /// Running; sends to encrypted rooms wait for it
+    Pending,
+    /// Done, or nothing to set up
+    Ready,
+    /// Gave up; sends go ahead and the admin room was told
+    Failed,
+}
+
+impl EncryptionSetup {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EncryptionSetup::Pending => "pending",
+            EncryptionSetup::Ready => "ready",
+            EncryptionSetup::Failed => "failed",
+        }
+    }
+}
+
+/// When a bot started, and where its encryption setup is
+pub struct Startup {
+    started: Instant,
+    setup: watch::Sender<EncryptionSetup>,
+    first_response: Once,
+    /// Set once the startup summary was sent (or attempted)
+    announced: AtomicBool,
+    /// Longest a send to an encrypted room waits for the setup
+    hold: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl Startup {
+    /// Started now, with nothing to set up
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            started: Instant::now(),
+            setup: watch::channel(EncryptionSetup::Ready).0,
+            first_response: Once::new(),
+            announced: AtomicBool::new(false),
+            hold: Duration::from_secs(config::env_u64("ENCRYPTION_SETUP_HOLD_SECS", 60)),
+            metrics,
+        }
+    }
+
+    fn since_start(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// The bot handles messages from now on
+    pub fn serving(&self) {
+        let elapsed = self.since_start();
+        self.metrics.set_gauge("startup_serving_ms", &[], elapsed);
+        info!(
+            "⏱️  Serving {}ms after startup (encryption setup {})",
+            elapsed,
+            self.encryption_setup().as_str()
+        );
+    }
+
+    /// A response was delivered; the first one is logged with the time it took
+    pub fn responded(&self) {
+        self.first_response.call_once(|| {
+            let elapsed = self.since_start();
+            self.metrics
+                .set_gauge("startup_first_response_ms", &[], elapsed);
+            info!(
+                "⏱️  First response {}ms after startup (encryption setup {})",
+                elapsed,
+                self.encryption_setup().as_str()
+            );
+        });
+    }
+
+    /// Whether the startup summary is still to be posted; true only the first time
+    pub fn announcing(&self) -> bool {
+        !self.announced.swap(true, Ordering::SeqCst)
+    }
+
+    /// The phase of the background encryption setup
+    pub fn encryption_setup(&self) -> EncryptionSetup {
+        *self.setup.borrow()
+    }
+
+    /// Wait for the encryption setup to end, for at most `ENCRYPTION_SETUP_HOLD_SECS`
+    ///
+    /// Called before every send to an encrypted room.
+    pub async fn encryption_ready(&self) {
+        let mut phase = self.setup.subscribe();
+        if *phase.borrow() != EncryptionSetup::Pending {
+            return;
+        }
+
+        debug!("🔐 Holding a send to an encrypted room until encryption setup is done");
+        let timed_out = tokio::time::timeout(
+            self.hold,
+            phase.wait_for(|phase| *phase != EncryptionSetup::Pending),
+        )
+        .await
+        .is_err();
+        if timed_out {
+            warn!(
+                "🔐 Encryption setup still running after {}s, sending anyway",
+                self.hold.as_secs()
+            );
+        }
+    }
+}
+
+/// Run `setup` in the background; sends to encrypted rooms wait until it ends
+///
+/// A failure is logged and posted to `admin_room`.
+pub fn spawn_encryption_setup<F>(
+    runtime: Runtime,
+    client: Client,
+    admin_room: Option<String>,
+    setup: F,
+) -> JoinHandle<()>
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    runtime.startup.setup.send_replace(EncryptionSetup::Pending);
+    info!("🔐 Setting up encryption in the background");
+
+    tokio::spawn(async move {
+        let startup = &runtime.startup;
+        let started = Instant::now();
+        let result = setup.await;
+        let elapsed = started.elapsed().as_millis() as u64;
+        runtime
+            .metrics
+            .observe_ms("encryption_setup_ms", &[], elapsed);
+        let done_at = startup.since_start();
+        runtime
+            .metrics
+            .set_gauge("startup_encryption_setup_done_ms", &[], done_at);
+        info!(
+            "⏱️  Encryption setup done {}ms after startup; set up before the sync loop, \
+             it would have held the first response until then",
+            done_at
+        );
+        match result {
+            Ok(()) => {
+                info!("🔐 Encryption setup finished in {}ms", elapsed);
+                startup.setup.send_replace(EncryptionSetup::Ready);
+            }
+            Err(e) => {
+                error!("🔐 Encryption setup failed after {}ms: {:#}", elapsed, e);
+                startup.setup.send_replace(EncryptionSetup::Failed);
+                if let Some(room) = &admin_room {
+                    let text = format!(
+                        "🚨 **Encryption setup failed**: {:#}\n\nThe bot keeps answering, but \
+                         key backup may be missing until it is set up again.",
+                        e
+                    );
+                    admin_room::post(&runtime, &client, room, "encryption alert", &text).await;
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,111 @@
+//! One summary of the bot's state, posted to `ADMIN_ROOM` after startup
+//!
+//! Sent once per bot after the initial sync, so operators see each
+//! restart and what it came up with. Anything that can't be determined is
+//! shown as "unknown" rather than holding the summary back. Disabled with
+//! `STARTUP_ANNOUNCE=false`.
+
+use matrix_sdk::{encryption::backups::BackupState, Client};
+use tracing::{info, warn};
+
+use crate::admin_room;
+use crate::crash::PreviousCrash;
+use crate::responder_manager::ResponderInfo;
+use crate::responders::VerjiAgentResponder;
+use crate::runtime::Runtime;
+
+const UNKNOWN: &str = "unknown";
+
+/// Version with the commit it was built from
+pub fn version() -> String {
+    format!(
+        "{} ({})",
+        env!("CARGO_PKG_VERSION"),
+        option_env!("VAGENT_GIT_COMMIT").unwrap_or(UNKNOWN)
+    )
+}
+
+/// Gather the bot's state and post it to `admin_room`, once per bot
+pub async fn announce(
+    runtime: &Runtime,
+    client: &Client,
+    admin_room: &str,
+    session_source: &str,
+    previous_crash: Option<&PreviousCrash>,
+    agent: &VerjiAgentResponder,
+    responders: &[ResponderInfo],
+) {
+    if !runtime.startup.announcing() {
+        return;
+    }
+
+    let device = client
+        .device_id()
+        .map_or_else(|| UNKNOWN.to_string(), |device_id| device_id.to_string());
+    let graph = match agent.service().check_connection().await {
+        Ok((transport, latency)) => {
+            format!("connected via {} ({}ms)", transport, latency.as_millis())
+        }
+        Err(e) => {
+            warn!("Startup summary: vagent-graph check failed: {:#}", e);
+            UNKNOWN.to_string()
+        }
+    };
+    let responders = if responders.is_empty() {
+        UNKNOWN.to_string()
+    } else {
+        responders
+            .iter()
+            .map(|responder| format!("`{}`", responder.name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut summary = format!(
+        "🚀 **Bot started**\n\n\
+         - Version: {}\n\
+         - Session: {}\n\
+         - Device: `{}`\n\
+         - Cross-signing: {}\n\
+         - Key backup: {}\n\
+         - Joined rooms: {}\n\
+         - vagent-graph: {}\n\
+         - Responders: {}",
+        version(),
+        session_source,
+        device,
+        cross_signing(client).await,
+        backup(client),
+        client.joined_rooms().len(),
+        graph,
+        responders
+    );
+    if let Some(crash) = previous_crash {
+        summary.push_str(&format!(
+            "\n\n⚠️ Recovered from a crash at {} ({} crashes recorded, see `crashes/` in the store)",
+            crash.at.format("%Y-%m-%d %H:%M:%S UTC"),
+            crash.total
+        ));
+    }
+    if admin_room::post(runtime, client, admin_room, "startup summary", &summary).await {
+        info!("📣 Posted the startup summary to {}", admin_room);
+    }
+}
+
+async fn cross_signing(client: &Client) -> &'static str {
+    match client.encryption().cross_signing_status().await {
+        Some(status) if status.has_master && status.has_self_signing && status.has_user_signing => {
+            "complete"
+        }
+        Some(_) => "incomplete",
+        None => UNKNOWN,
+    }
+}
+
+fn backup(client: &Client) -> String {
+    match client.encryption().backups().state() {
+        BackupState::Enabled => "enabled".to_string(),
+        BackupState::Unknown => UNKNOWN.to_string(),
+        state => format!("{:?}", state).to_lowercase(),
+    }
+}
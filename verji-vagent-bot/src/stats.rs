@@ -0,0 +1,978 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::db;
+use crate::metrics::Metrics;
+
+/// Counters kept for every (room, user) pair
+#[derive(Debug, Clone, Default)]
+pub struct UsageCounters {
+    pub queries: u64,
+    pub errors: u64,
+    pub total_latency_ms: u64,
+    /// Unix timestamp (seconds) of the last agent query
+    pub last_activity: u64,
+    /// Tokens vagent-graph reported for the questions, context included
+    pub prompt_tokens: u64,
+    /// Tokens vagent-graph reported for the answers
+    pub completion_tokens: u64,
+    /// Cost vagent-graph estimated, in its currency
+    pub cost: f64,
+}
+
+impl UsageCounters {
+    /// Average latency per query in milliseconds
+    pub fn average_latency_ms(&self) -> u64 {
+        if self.queries == 0 {
+            0
+        } else {
+            self.total_latency_ms / self.queries
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    fn merge(&mut self, other: &UsageCounters) {
+        self.queries += other.queries;
+        self.errors += other.errors;
+        self.total_latency_ms += other.total_latency_ms;
+        self.last_activity = self.last_activity.max(other.last_activity);
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// Token usage vagent-graph reports in the `usage` block of a final answer's metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// `cost_estimate`, in the graph's currency
+    pub cost: f64,
+}
+
+impl TokenUsage {
+    /// The `usage` block of `metadata`, if the graph sent one
+    ///
+    /// Missing fields count as zero.
+    pub fn from_metadata(metadata: Option<&serde_json::Value>) -> Option<Self> {
+        let usage = metadata?.get("usage")?.as_object()?;
+        let tokens = |field: &str| {
+            usage
+                .get(field)
+                .and_then(|value| value.as_u64())
+                .unwrap_or(0)
+        };
+        Some(Self {
+            prompt_tokens: tokens("prompt_tokens"),
+            completion_tokens: tokens("completion_tokens"),
+            cost: usage
+                .get("cost_estimate")
+                .and_then(|value| value.as_f64())
+                .unwrap_or(0.0),
+        })
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// One line for answer footers
+    pub fn summary(&self) -> String {
+        format!(
+            "{} tokens ({} in, {} out), cost {:.4}",
+            self.total_tokens(),
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.cost
+        )
+    }
+}
+
+/// What a room spent in one budget period
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RoomSpend {
+    pub cost: f64,
+    pub tokens: u64,
+}
+
+type UsageKey = (String, String);
+
+/// (user, day as `YYYY-MM-DD`)
+type DailyKey = (String, String);
+
+/// (room, budget period as `YYYY-MM`)
+type SpendKey = (String, String);
+
+/// Gauge with each room's last successful answer, in unix seconds
+const LAST_RESPONSE_GAUGE: &str = "room_last_response_timestamp_seconds";
+
+/// Days of daily query counters kept, enough for any timezone's "today"
+const DAILY_DAYS_KEPT: i64 = 2;
+
+/// Characters of the question kept in the interaction history
+const QUESTION_PREVIEW_CHARS: usize = 100;
+
+/// One agent query as shown by `!history`
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub room_id: String,
+    pub user_id: String,
+    /// Unix seconds
+    pub timestamp: u64,
+    /// First characters of the question
+    pub question: String,
+    /// Outcome (`answered`, `error`, ...)
+    pub status: String,
+}
+
+/// What a flush took out of the in-memory state to write
+struct Unwritten {
+    usage: Vec<UsageKey>,
+    interactions: Vec<Interaction>,
+    daily: Vec<DailyKey>,
+    spend: Vec<SpendKey>,
+    last_responses: Vec<String>,
+}
+
+/// In-memory usage statistics with periodic persistence to the bot database
+///
+/// Recording only touches in-memory state, so it never blocks message
+/// handling; a background task flushes changed rows and new interactions to
+/// sqlite and prunes interactions older than the retention period.
+pub struct UsageStats {
+    db_path: PathBuf,
+    counters: Mutex<HashMap<UsageKey, UsageCounters>>,
+    dirty: Mutex<HashSet<UsageKey>>,
+    /// Interactions not yet written to the database
+    interactions: Mutex<Vec<Interaction>>,
+    history_max_age: Duration,
+    /// Agent queries per user and day, for daily quotas
+    daily: Mutex<HashMap<DailyKey, u32>>,
+    daily_dirty: Mutex<HashSet<DailyKey>>,
+    /// User ID -> daily quota set with `!admin quota set`
+    quota_overrides: Mutex<HashMap<String, u32>>,
+    /// What each room spent per budget period, for room budgets
+    spend: Mutex<HashMap<SpendKey, RoomSpend>>,
+    spend_dirty: Mutex<HashSet<SpendKey>>,
+    /// Room ID -> unix seconds of the bot's last successful answer there
+    last_responses: Mutex<HashMap<String, u64>>,
+    last_responses_dirty: Mutex<HashSet<String>>,
+    /// Where the time of each room's last answer is reported
+    metrics: Arc<Metrics>,
+}
+
+impl UsageStats {
+    /// Open the stats tables and load persisted counters
+    pub fn open(store_path: &Path, history_max_age: Duration) -> Result<Self> {
+        let db_path = db::bot_db_path(store_path);
+        let conn = db::open(&db_path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS usage_stats (
+                room_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                queries INTEGER NOT NULL DEFAULT 0,
+                errors INTEGER NOT NULL DEFAULT 0,
+                total_latency_ms INTEGER NOT NULL DEFAULT 0,
+                last_activity INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (room_id, user_id)
+            );
+            CREATE TABLE IF NOT EXISTS agent_interactions (
+                room_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                question TEXT NOT NULL,
+                status TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS agent_interactions_user
+                ON agent_interactions (user_id, timestamp);
+            CREATE TABLE IF NOT EXISTS daily_queries (
+                user_id TEXT NOT NULL,
+                day TEXT NOT NULL,
+                queries INTEGER NOT NULL,
+                PRIMARY KEY (user_id, day)
+            );
+            CREATE TABLE IF NOT EXISTS quota_overrides (
+                user_id TEXT PRIMARY KEY,
+                daily_limit INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS token_usage (
+                room_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL DEFAULT 0,
+                completion_tokens INTEGER NOT NULL DEFAULT 0,
+                cost REAL NOT NULL DEFAULT 0,
+                PRIMARY KEY (room_id, user_id)
+            );
+            CREATE TABLE IF NOT EXISTS room_spend (
+                room_id TEXT NOT NULL,
+                period TEXT NOT NULL,
+                cost REAL NOT NULL,
+                tokens INTEGER NOT NULL,
+                PRIMARY KEY (room_id, period)
+            );
+            CREATE TABLE IF NOT EXISTS room_activity (
+                room_id TEXT PRIMARY KEY,
+                last_response INTEGER NOT NULL
+            );",
+        )
+        .context("Failed to create usage_stats tables")?;
+        prune_interactions(&conn, history_max_age)?;
+        prune_daily(&conn)?;
+
+        let mut counters = HashMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT room_id, user_id, queries, errors, total_latency_ms, last_activity FROM usage_stats",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+                UsageCounters {
+                    queries: row.get::<_, i64>(2)? as u64,
+                    errors: row.get::<_, i64>(3)? as u64,
+                    total_latency_ms: row.get::<_, i64>(4)? as u64,
+                    last_activity: row.get::<_, i64>(5)? as u64,
+                    ..UsageCounters::default()
+                },
+            ))
+        })?;
+        for row in rows {
+            let (key, value) = row?;
+            counters.insert(key, value);
+        }
+
+        // Token usage lives in its own table, so older databases need no migration
+        let mut stmt = conn.prepare(
+            "SELECT room_id, user_id, prompt_tokens, completion_tokens, cost FROM token_usage",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+                (
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                    row.get::<_, f64>(4)?,
+                ),
+            ))
+        })?;
+        for row in rows {
+            let (key, (prompt_tokens, completion_tokens, cost)) = row?;
+            let entry: &mut UsageCounters = counters.entry(key).or_default();
+            entry.prompt_tokens = prompt_tokens;
+            entry.completion_tokens = completion_tokens;
+            entry.cost = cost;
+        }
+
+        let mut daily = HashMap::new();
+        let mut stmt = conn.prepare("SELECT user_id, day, queries FROM daily_queries")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+                row.get::<_, i64>(2)? as u32,
+            ))
+        })?;
+        for row in rows {
+            let (key, queries) = row?;
+            daily.insert(key, queries);
+        }
+
+        let mut quota_overrides = HashMap::new();
+        let mut stmt = conn.prepare("SELECT user_id, daily_limit FROM quota_overrides")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u32))
+        })?;
+        for row in rows {
+            let (user, limit) = row?;
+            quota_overrides.insert(user, limit);
+        }
+
+        let mut spend = HashMap::new();
+        let mut stmt = conn.prepare("SELECT room_id, period, cost, tokens FROM room_spend")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+                RoomSpend {
+                    cost: row.get(2)?,
+                    tokens: row.get::<_, i64>(3)? as u64,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (key, value) = row?;
+            spend.insert(key, value);
+        }
+
+        let mut last_responses = HashMap::new();
+        let mut stmt = conn.prepare("SELECT room_id, last_response FROM room_activity")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        for row in rows {
+            let (room, at) = row?;
+            last_responses.insert(room, at);
+        }
+
+        info!(
+            "📊 Loaded usage statistics for {} room/user pairs",
+            counters.len()
+        );
+
+        Ok(Self {
+            db_path,
+            counters: Mutex::new(counters),
+            dirty: Mutex::new(HashSet::new()),
+            interactions: Mutex::new(Vec::new()),
+            history_max_age,
+            daily: Mutex::new(daily),
+            daily_dirty: Mutex::new(HashSet::new()),
+            quota_overrides: Mutex::new(quota_overrides),
+            spend: Mutex::new(spend),
+            spend_dirty: Mutex::new(HashSet::new()),
+            last_responses: Mutex::new(last_responses),
+            last_responses_dirty: Mutex::new(HashSet::new()),
+            metrics: Arc::new(Metrics::default()),
+        })
+    }
+
+    /// Report the time of each room's last answer to `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        for (room, at) in self.last_responses.get_mut().unwrap().iter() {
+            metrics.set_gauge(LAST_RESPONSE_GAUGE, &[("room", room)], *at);
+        }
+        self.metrics = metrics;
+        self
+    }
+
+    /// Agent queries a user sent on a day
+    pub fn daily_queries(&self, user_id: &str, day: &str) -> u32 {
+        let key = (user_id.to_string(), day.to_string());
+        self.daily.lock().unwrap().get(&key).copied().unwrap_or(0)
+    }
+
+    /// Count one query against the user's daily limit; false if it is used up
+    pub fn try_count_daily(&self, user_id: &str, day: &str, limit: u32) -> bool {
+        let key = (user_id.to_string(), day.to_string());
+        {
+            let mut daily = self.daily.lock().unwrap();
+            let queries = daily.entry(key.clone()).or_insert(0);
+            if *queries >= limit {
+                return false;
+            }
+            *queries += 1;
+        }
+        self.daily_dirty.lock().unwrap().insert(key);
+        true
+    }
+
+    /// Give back a query counted for a message the agent did not handle
+    pub fn uncount_daily(&self, user_id: &str, day: &str) {
+        let key = (user_id.to_string(), day.to_string());
+        if let Some(queries) = self.daily.lock().unwrap().get_mut(&key) {
+            *queries = queries.saturating_sub(1);
+        }
+        self.daily_dirty.lock().unwrap().insert(key);
+    }
+
+    pub fn quota_override(&self, user_id: &str) -> Option<u32> {
+        self.quota_overrides.lock().unwrap().get(user_id).copied()
+    }
+
+    /// Set (or with None remove) a user's daily quota; written right away
+    pub async fn set_quota_override(&self, user_id: &str, limit: Option<u32>) -> Result<()> {
+        let db_path = self.db_path.clone();
+        let user = user_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = db::open(&db_path)?;
+            match limit {
+                Some(limit) => conn.execute(
+                    "INSERT OR REPLACE INTO quota_overrides (user_id, daily_limit) VALUES (?1, ?2)",
+                    rusqlite::params![user, limit as i64],
+                )?,
+                None => conn.execute("DELETE FROM quota_overrides WHERE user_id = ?1", [user])?,
+            };
+            Ok(())
+        })
+        .await
+        .context("Quota override write panicked")??;
+
+        let mut overrides = self.quota_overrides.lock().unwrap();
+        match limit {
+            Some(limit) => overrides.insert(user_id.to_string(), limit),
+            None => overrides.remove(user_id),
+        };
+        Ok(())
+    }
+
+    /// Rooms a user has agent usage in
+    pub fn user_rooms(&self, user_id: &str) -> Vec<String> {
+        let counters = self.counters.lock().unwrap();
+        let mut rooms: Vec<String> = counters
+            .keys()
+            .filter(|(_, user)| user == user_id)
+            .map(|(room, _)| room.clone())
+            .collect();
+        rooms.sort();
+        rooms
+    }
+
+    /// Delete everything recorded about a user, returning rows per table
+    ///
+    /// With `dry_run` nothing is removed and the counts are what would be.
+    /// Pending in-memory changes are flushed first so the counts are complete.
+    pub async fn erase_user(
+        &self,
+        user_id: &str,
+        dry_run: bool,
+    ) -> Result<Vec<(&'static str, usize)>> {
+        self.flush().await?;
+        if !dry_run {
+            self.counters
+                .lock()
+                .unwrap()
+                .retain(|(_, user), _| user != user_id);
+            self.dirty
+                .lock()
+                .unwrap()
+                .retain(|(_, user)| user != user_id);
+            self.interactions
+                .lock()
+                .unwrap()
+                .retain(|interaction| interaction.user_id != user_id);
+            self.daily
+                .lock()
+                .unwrap()
+                .retain(|(user, _), _| user != user_id);
+            self.daily_dirty
+                .lock()
+                .unwrap()
+                .retain(|(user, _)| user != user_id);
+            self.quota_overrides.lock().unwrap().remove(user_id);
+        }
+
+        let db_path = self.db_path.clone();
+        let user = user_id.to_string();
+        tokio::task::spawn_blocking(move || -> Result<Vec<(&'static str, usize)>> {
+            let conn = db::open(&db_path)?;
+            [
+                "usage_stats",
+                "agent_interactions",
+                "daily_queries",
+                "quota_overrides",
+                "token_usage",
+            ]
+            .into_iter()
+            .map(|table| {
+                let count = db::erase_rows(&conn, table, "user_id = ?1", [&user], dry_run)?;
+                Ok((table, count))
+            })
+            .collect()
+        })
+        .await
+        .context("Usage stats erasure panicked")?
+    }
+
+    /// Record one agent interaction for `!history`; only the start of the question is kept
+    pub fn record_interaction(&self, room_id: &str, user_id: &str, question: &str, status: &str) {
+        let mut preview: String = question.chars().take(QUESTION_PREVIEW_CHARS).collect();
+        if preview.len() < question.len() {
+            preview.push('…');
+        }
+        self.interactions.lock().unwrap().push(Interaction {
+            room_id: room_id.to_string(),
+            user_id: user_id.to_string(),
+            timestamp: db::now_secs(),
+            question: preview,
+            status: status.to_string(),
+        });
+    }
+
+    /// A user's most recent interactions, newest first, optionally limited to one room
+    pub async fn history(
+        &self,
+        user_id: &str,
+        room_id: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<Interaction>> {
+        // Write pending interactions first so the answer includes them
+        self.flush().await?;
+
+        let db_path = self.db_path.clone();
+        let user_id = user_id.to_string();
+        let room_id = room_id.map(str::to_string);
+        let cutoff = db::now_secs().saturating_sub(self.history_max_age.as_secs());
+        tokio::task::spawn_blocking(move || -> Result<Vec<Interaction>> {
+            let conn = db::open(&db_path)?;
+            let mut stmt = conn.prepare(
+                "SELECT room_id, user_id, timestamp, question, status FROM agent_interactions
+                 WHERE user_id = ?1 AND (?2 IS NULL OR room_id = ?2) AND timestamp >= ?3
+                 ORDER BY timestamp DESC, rowid DESC LIMIT ?4",
+            )?;
+            let rows = stmt.query_map(
+                rusqlite::params![user_id, room_id, cutoff as i64, limit as i64],
+                |row| {
+                    Ok(Interaction {
+                        room_id: row.get(0)?,
+                        user_id: row.get(1)?,
+                        timestamp: row.get::<_, i64>(2)? as u64,
+                        question: row.get(3)?,
+                        status: row.get(4)?,
+                    })
+                },
+            )?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+                .context("Failed to read interaction history")
+        })
+        .await
+        .context("History query task panicked")?
+    }
+
+    /// Record one agent query
+    pub fn record_query(&self, room_id: &str, user_id: &str, latency: Duration, success: bool) {
+        let key = (room_id.to_string(), user_id.to_string());
+        {
+            let mut counters = self.counters.lock().unwrap();
+            let entry = counters.entry(key.clone()).or_default();
+            entry.queries += 1;
+            entry.total_latency_ms += latency.as_millis() as u64;
+            if !success {
+                entry.errors += 1;
+            }
+            entry.last_activity = db::now_secs();
+        }
+        self.dirty.lock().unwrap().insert(key);
+
+        if success {
+            let now = db::now_secs();
+            self.metrics
+                .set_gauge(LAST_RESPONSE_GAUGE, &[("room", room_id)], now);
+            self.last_responses
+                .lock()
+                .unwrap()
+                .insert(room_id.to_string(), now);
+            self.last_responses_dirty
+                .lock()
+                .unwrap()
+                .insert(room_id.to_string());
+        }
+    }
+
+    /// Room ID -> unix seconds of the bot's last successful answer there
+    pub fn last_responses(&self) -> HashMap<String, u64> {
+        self.last_responses.lock().unwrap().clone()
+    }
+
+    /// Add the tokens and cost of one answer to the user's counters and the room's spend in `period`
+    pub fn record_usage(&self, room_id: &str, user_id: &str, period: &str, usage: &TokenUsage) {
+        let key = (room_id.to_string(), user_id.to_string());
+        {
+            let mut counters = self.counters.lock().unwrap();
+            let entry = counters.entry(key.clone()).or_default();
+            entry.prompt_tokens += usage.prompt_tokens;
+            entry.completion_tokens += usage.completion_tokens;
+            entry.cost += usage.cost;
+        }
+        self.dirty.lock().unwrap().insert(key);
+
+        let key = (room_id.to_string(), period.to_string());
+        {
+            let mut spend = self.spend.lock().unwrap();
+            let entry = spend.entry(key.clone()).or_default();
+            entry.cost += usage.cost;
+            entry.tokens += usage.total_tokens();
+        }
+        self.spend_dirty.lock().unwrap().insert(key);
+    }
+
+    /// What a room spent in a budget period
+    pub fn room_spend(&self, room_id: &str, period: &str) -> RoomSpend {
+        let key = (room_id.to_string(), period.to_string());
+        self.spend
+            .lock()
+            .unwrap()
+            .get(&key)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Start a room's budget period over (`!admin budget reset`)
+    pub fn reset_room_spend(&self, room_id: &str, period: &str) {
+        let key = (room_id.to_string(), period.to_string());
+        self.spend
+            .lock()
+            .unwrap()
+            .insert(key.clone(), RoomSpend::default());
+        self.spend_dirty.lock().unwrap().insert(key);
+    }
+
+    /// Per-user counters for one room, busiest users first
+    pub fn room_users(&self, room_id: &str) -> Vec<(String, UsageCounters)> {
+        let counters = self.counters.lock().unwrap();
+        let mut users: Vec<_> = counters
+            .iter()
+            .filter(|((room, _), _)| room == room_id)
+            .map(|((_, user), value)| (user.clone(), value.clone()))
+            .collect();
+        users.sort_by(|a, b| b.1.queries.cmp(&a.1.queries));
+        users
+    }
+
+    /// Totals per room, busiest rooms first
+    pub fn room_totals(&self) -> Vec<(String, UsageCounters)> {
+        let counters = self.counters.lock().unwrap();
+        let mut rooms: HashMap<String, UsageCounters> = HashMap::new();
+        for ((room, _), value) in counters.iter() {
+            rooms.entry(room.clone()).or_default().merge(value);
+        }
+        let mut rooms: Vec<_> = rooms.into_iter().collect();
+        rooms.sort_by(|a, b| b.1.queries.cmp(&a.1.queries));
+        rooms
+    }
+
+    /// Render the per-user table for one room as Markdown
+    pub fn render_room_table(&self, room_id: &str) -> String {
+        let users = self.room_users(room_id);
+        if users.is_empty() {
+            return "No agent queries recorded in this room yet.".to_string();
+        }
+
+        let mut total = UsageCounters::default();
+        let mut out = String::from("**Agent usage in this room**\n\n");
+        out.push_str("| User | Queries | Errors | Avg latency | Tokens | Cost | Last activity |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|---|\n");
+        for (user, counters) in &users {
+            total.merge(counters);
+            out.push_str(&render_row(user, counters));
+        }
+        out.push_str(&render_row("**Total**", &total));
+        out
+    }
+
+    /// Render the cross-room table as Markdown
+    pub fn render_all_rooms_table(&self) -> String {
+        let rooms = self.room_totals();
+        if rooms.is_empty() {
+            return "No agent queries recorded yet.".to_string();
+        }
+
+        let mut total = UsageCounters::default();
+        let mut out = String::from("**Agent usage across all rooms**\n\n");
+        out.push_str("| Room | Queries | Errors | Avg latency | Tokens | Cost | Last activity |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|---|\n");
+        for (room, counters) in &rooms {
+            total.merge(counters);
+            out.push_str(&render_row(room, counters));
+        }
+        out.push_str(&render_row("**Total**", &total));
+        out
+    }
+
+    /// Write all counters as CSV
+    pub fn export_csv<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let counters = self.counters.lock().unwrap();
+        let mut rows: Vec<_> = counters.iter().collect();
+        rows.sort_by(|a, b| a.0.cmp(b.0));
+
+        writeln!(
+            writer,
+            "room_id,user_id,queries,errors,total_latency_ms,avg_latency_ms,last_activity,prompt_tokens,completion_tokens,cost"
+        )?;
+        for ((room, user), value) in rows {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{}",
+                csv_field(room),
+                csv_field(user),
+                value.queries,
+                value.errors,
+                value.total_latency_ms,
+                value.average_latency_ms(),
+                format_timestamp(value.last_activity),
+                value.prompt_tokens,
+                value.completion_tokens,
+                value.cost
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Persist counters that changed and interactions recorded since the last flush
+    pub async fn flush(&self) -> Result<()> {
+        let rows: Vec<(UsageKey, UsageCounters)> = {
+            let dirty: Vec<UsageKey> = self.dirty.lock().unwrap().drain().collect();
+            let counters = self.counters.lock().unwrap();
+            dirty
+                .into_iter()
+                .filter_map(|key| counters.get(&key).cloned().map(|value| (key, value)))
+                .collect()
+        };
+        let interactions: Vec<Interaction> = self.interactions.lock().unwrap().drain(..).collect();
+        let daily: Vec<(DailyKey, u32)> = {
+            let dirty: Vec<DailyKey> = self.daily_dirty.lock().unwrap().drain().collect();
+            let daily = self.daily.lock().unwrap();
+            dirty
+                .into_iter()
+                .filter_map(|key| daily.get(&key).copied().map(|queries| (key, queries)))
+                .collect()
+        };
+        let spend: Vec<(SpendKey, RoomSpend)> = {
+            let dirty: Vec<SpendKey> = self.spend_dirty.lock().unwrap().drain().collect();
+            let spend = self.spend.lock().unwrap();
+            dirty
+                .into_iter()
+                .filter_map(|key| spend.get(&key).copied().map(|value| (key, value)))
+                .collect()
+        };
+        let last_responses: Vec<(String, u64)> = {
+            let dirty: Vec<String> = self.last_responses_dirty.lock().unwrap().drain().collect();
+            let last_responses = self.last_responses.lock().unwrap();
+            dirty
+                .into_iter()
+                .filter_map(|room| last_responses.get(&room).copied().map(|at| (room, at)))
+                .collect()
+        };
+        if rows.is_empty()
+            && interactions.is_empty()
+            && daily.is_empty()
+            && spend.is_empty()
+            && last_responses.is_empty()
+        {
+            return Ok(());
+        }
+
+        // What was drained, to mark unwritten again if the write fails
+        let unwritten = Unwritten {
+            usage: rows.iter().map(|(key, _)| key.clone()).collect(),
+            interactions: interactions.clone(),
+            daily: daily.iter().map(|(key, _)| key.clone()).collect(),
+            spend: spend.iter().map(|(key, _)| key.clone()).collect(),
+            last_responses: last_responses
+                .iter()
+                .map(|(room, _)| room.clone())
+                .collect(),
+        };
+        let db_path = self.db_path.clone();
+        let count = rows.len();
+        let new_interactions = interactions.len();
+        let written = tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = db::open(&db_path)?;
+            let tx = conn.transaction()?;
+            for interaction in &interactions {
+                tx.execute(
+                    "INSERT INTO agent_interactions (room_id, user_id, timestamp, question, status)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        interaction.room_id,
+                        interaction.user_id,
+                        interaction.timestamp as i64,
+                        interaction.question,
+                        interaction.status
+                    ],
+                )?;
+            }
+            for ((room, user), value) in &rows {
+                tx.execute(
+                    "INSERT OR REPLACE INTO usage_stats
+                        (room_id, user_id, queries, errors, total_latency_ms, last_activity)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    rusqlite::params![
+                        room,
+                        user,
+                        value.queries as i64,
+                        value.errors as i64,
+                        value.total_latency_ms as i64,
+                        value.last_activity as i64
+                    ],
+                )?;
+                tx.execute(
+                    "INSERT OR REPLACE INTO token_usage
+                        (room_id, user_id, prompt_tokens, completion_tokens, cost)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        room,
+                        user,
+                        value.prompt_tokens as i64,
+                        value.completion_tokens as i64,
+                        value.cost
+                    ],
+                )?;
+            }
+            for ((room, period), value) in &spend {
+                tx.execute(
+                    "INSERT OR REPLACE INTO room_spend (room_id, period, cost, tokens)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    rusqlite::params![room, period, value.cost, value.tokens as i64],
+                )?;
+            }
+            for (room, at) in &last_responses {
+                tx.execute(
+                    "INSERT OR REPLACE INTO room_activity (room_id, last_response) VALUES (?1, ?2)",
+                    rusqlite::params![room, *at as i64],
+                )?;
+            }
+            for ((user, day), queries) in &daily {
+                tx.execute(
+                    "INSERT OR REPLACE INTO daily_queries (user_id, day, queries)
+                     VALUES (?1, ?2, ?3)",
+                    rusqlite::params![user, day, *queries as i64],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .context("Usage stats flush task panicked")
+        .and_then(|written| written);
+        if let Err(e) = written {
+            self.restore(unwritten);
+            return Err(e);
+        }
+
+        debug!(
+            "📊 Flushed {} usage stat rows and {} interactions",
+            count, new_interactions
+        );
+        Ok(())
+    }
+
+    /// Mark what a failed flush drained as changed again, for the next flush
+    fn restore(&self, unwritten: Unwritten) {
+        self.dirty.lock().unwrap().extend(unwritten.usage);
+        self.daily_dirty.lock().unwrap().extend(unwritten.daily);
+        self.spend_dirty.lock().unwrap().extend(unwritten.spend);
+        self.last_responses_dirty
+            .lock()
+            .unwrap()
+            .extend(unwritten.last_responses);
+        // Ahead of interactions recorded since, keeping them in order
+        self.interactions
+            .lock()
+            .unwrap()
+            .splice(0..0, unwritten.interactions);
+    }
+
+    /// Delete interactions older than the retention period and past daily counters
+    pub async fn prune(&self) -> Result<()> {
+        let cutoff = daily_cutoff();
+        self.daily
+            .lock()
+            .unwrap()
+            .retain(|(_, day), _| *day >= cutoff);
+
+        let db_path = self.db_path.clone();
+        let max_age = self.history_max_age;
+        tokio::task::spawn_blocking(move || {
+            let conn = db::open(&db_path)?;
+            prune_interactions(&conn, max_age)?;
+            prune_daily(&conn)
+        })
+        .await
+        .context("History prune task panicked")?
+    }
+
+    /// Spawn the periodic flush task
+    pub fn spawn_flush_task(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let stats = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = stats.flush().await {
+                    warn!("Failed to flush usage statistics: {}", e);
+                }
+                if let Err(e) = stats.prune().await {
+                    warn!("Failed to prune interaction history: {}", e);
+                }
+            }
+        })
+    }
+}
+
+fn prune_interactions(conn: &rusqlite::Connection, max_age: Duration) -> Result<()> {
+    let cutoff = db::now_secs().saturating_sub(max_age.as_secs());
+    let removed = conn.execute(
+        "DELETE FROM agent_interactions WHERE timestamp < ?1",
+        [cutoff as i64],
+    )?;
+    if removed > 0 {
+        debug!("📊 Pruned {} old interactions", removed);
+    }
+    Ok(())
+}
+
+/// Oldest day whose counters are kept; days compare as `YYYY-MM-DD` strings
+fn daily_cutoff() -> String {
+    (chrono::Utc::now() - chrono::Duration::days(DAILY_DAYS_KEPT))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn prune_daily(conn: &rusqlite::Connection) -> Result<()> {
+    conn.execute("DELETE FROM daily_queries WHERE day < ?1", [daily_cutoff()])?;
+    Ok(())
+}
+
+/// Render interactions as a Markdown list, with the room when `with_room` is set
+pub fn render_history(title: &str, interactions: &[Interaction], with_room: bool) -> String {
+    if interactions.is_empty() {
+        return format!("{}\n\nNo agent interactions recorded.", title);
+    }
+
+    let mut out = format!("{}\n\n", title);
+    for interaction in interactions {
+        let room = if with_room {
+            format!(" ({})", interaction.room_id)
+        } else {
+            String::new()
+        };
+        out.push_str(&format!(
+            "- {}{} — \"{}\" — {}\n",
+            format_timestamp(interaction.timestamp),
+            room,
+            interaction.question.replace('\n', " "),
+            interaction.status
+        ));
+    }
+    out
+}
+
+fn render_row(label: &str, counters: &UsageCounters) -> String {
+    format!(
+        "| {} | {} | {} | {} ms | {} | {:.2} | {} |\n",
+        label,
+        counters.queries,
+        counters.errors,
+        counters.average_latency_ms(),
+        counters.total_tokens(),
+        counters.cost,
+        format_timestamp(counters.last_activity)
+    )
+}
+
+/// Format a unix timestamp for display (UTC)
+pub fn format_timestamp(secs: u64) -> String {
+    if secs == 0 {
+        return "never".to_string();
+    }
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Quote a CSV field if it needs it
+pub fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
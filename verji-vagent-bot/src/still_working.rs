@@ -0,0 +1,109 @@
+//! "Still working" notice for slow requests
+//!
+//! When a dispatched message has shown nothing in the room for
+//! `STILL_WORKING_SECS` (no progress, no answer), the dispatch layer posts a
+//! short notice, once per request, so users don't give up and ask again. It is
+//! redacted when the answer or error goes out. Responders that show progress
+//! themselves report it through [`OutputActivity`], which holds the notice
+//! back while progress keeps coming.
+
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::responder::OutgoingMessage;
+use crate::room::RoomHandle;
+use crate::send_queue::SendQueues;
+
+/// When a request last showed something in the room
+pub struct OutputActivity {
+    last: Mutex<Instant>,
+}
+
+impl OutputActivity {
+    pub fn new() -> Self {
+        Self {
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Record visible output (a progress message was sent or edited)
+    pub fn touch(&self) {
+        *self.last.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the last visible output, or since the request started
+    pub fn quiet_for(&self) -> Duration {
+        self.last.lock().unwrap().elapsed()
+    }
+}
+
+impl Default for OutputActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Watches one request and posts the notice if it stays quiet too long
+pub struct StillWorking {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Option<OwnedEventId>>,
+}
+
+impl StillWorking {
+    /// Start watching a request that was triggered by `trigger`
+    ///
+    /// The notice waits its turn in `send_queues` like any other send.
+    pub fn start(
+        send_queues: Arc<SendQueues>,
+        room: Arc<dyn RoomHandle>,
+        trigger: OwnedEventId,
+        thread_id: Option<String>,
+        activity: Arc<OutputActivity>,
+        after: Duration,
+        message: OutgoingMessage,
+    ) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            loop {
+                let quiet = activity.quiet_for();
+                if quiet >= after {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(after - quiet) => {}
+                    _ = &mut stopped => return None,
+                }
+            }
+
+            let mut turn = send_queues.turn(room.room_id(), thread_id.as_deref()).await;
+            match turn.send(room.send_content(&trigger, message)).await {
+                Ok(event_id) => {
+                    debug!("⏳ Posted still-working notice for {}", trigger);
+                    Some(event_id)
+                }
+                Err(e) => {
+                    warn!("Failed to post still-working notice: {:#}", e);
+                    None
+                }
+            }
+        });
+        Self { stop, task }
+    }
+
+    /// Stop watching, returning the notice if one was posted
+    pub async fn stop(self) -> Option<OwnedEventId> {
+        let _ = self.stop.send(());
+        self.task.await.ok().flatten()
+    }
+}
+
+/// Take a posted notice down once the answer or error is out
+pub async fn remove(room: &dyn RoomHandle, notice: &EventId) {
+    if let Err(e) = room.redact(notice, None).await {
+        warn!("Failed to remove still-working notice {}: {:#}", notice, e);
+    }
+}
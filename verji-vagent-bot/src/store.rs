@@ -0,0 +1,208 @@
+//! Store directory housekeeping: the process lock and sqlite maintenance
+
+use anyhow::{bail, Context, Result};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::db;
+
+/// Lock file marking the store as in use, holding the owner's PID
+const LOCK_FILE: &str = "vagent.lock";
+
+/// Exclusive use of a store directory, released on drop
+///
+/// A lock left behind by a crashed process is taken over when its PID is no
+/// longer running (checked on Linux; elsewhere the file must be removed by
+/// hand).
+pub struct StoreLock {
+    path: PathBuf,
+}
+
+impl StoreLock {
+    pub fn acquire(store_path: &Path) -> Result<Self> {
+        let path = store_path.join(LOCK_FILE);
+
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())
+                        .with_context(|| format!("Failed to write {:?}", path))?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let pid = std::fs::read_to_string(&path).unwrap_or_default();
+                    let pid = pid.trim();
+                    if is_running(pid) {
+                        bail!(
+                            "Store {:?} is in use by process {} (remove {:?} if that is wrong)",
+                            store_path,
+                            pid,
+                            path
+                        );
+                    }
+                    warn!("🔓 Taking over stale store lock of process {}", pid);
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove stale lock {:?}", path))?;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create lock {:?}", path))
+                }
+            }
+        }
+        bail!("Another process keeps taking the store lock {:?}", path)
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(pid: &str) -> bool {
+    !pid.is_empty() && Path::new("/proc").join(pid).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: &str) -> bool {
+    true
+}
+
+/// Size of one store database before and after maintenance
+#[derive(Debug)]
+pub struct FileReport {
+    pub name: String,
+    pub before: u64,
+    pub after: u64,
+}
+
+/// Sqlite databases in the store (the bot's own and the Matrix SDK's)
+fn sqlite_files(store_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(store_path)
+        .with_context(|| format!("Failed to read store directory {:?}", store_path))?
+    {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == "sqlite3" || ext == "db")
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Size of a database including its WAL and shared-memory files
+fn db_size(path: &Path) -> u64 {
+    ["", "-wal", "-shm"]
+        .iter()
+        .filter_map(|suffix| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(suffix);
+            std::fs::metadata(PathBuf::from(name)).ok()
+        })
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Checkpoint and VACUUM every sqlite database in the store
+///
+/// Blocking. Each VACUUM runs in its own transaction, so an interrupted run
+/// leaves every database intact. A database that fails (e.g. busy) is
+/// reported and skipped.
+pub fn vacuum(store_path: &Path) -> Result<Vec<FileReport>> {
+    let mut reports = Vec::new();
+    for path in sqlite_files(store_path)? {
+        let before = db_size(&path);
+        let result = db::open(&path).and_then(|conn| {
+            conn.execute_batch(
+                "PRAGMA wal_checkpoint(TRUNCATE); VACUUM; PRAGMA wal_checkpoint(TRUNCATE);",
+            )
+            .context("VACUUM failed")
+        });
+        if let Err(e) = result {
+            warn!("🧹 Skipping {:?}: {:#}", path, e);
+        }
+
+        reports.push(FileReport {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            before,
+            after: db_size(&path),
+        });
+    }
+    Ok(reports)
+}
+
+/// Before/after sizes as a plain-text table
+pub fn render_report(reports: &[FileReport]) -> String {
+    let mut out = format!("{:<40} {:>12} {:>12}\n", "file", "before", "after");
+    for report in reports {
+        out.push_str(&format!(
+            "{:<40} {:>12} {:>12}\n",
+            report.name,
+            format_bytes(report.before),
+            format_bytes(report.after)
+        ));
+    }
+    let before: u64 = reports.iter().map(|report| report.before).sum();
+    let after: u64 = reports.iter().map(|report| report.after).sum();
+    out.push_str(&format!(
+        "{:<40} {:>12} {:>12}\n",
+        "total",
+        format_bytes(before),
+        format_bytes(after)
+    ));
+    out
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Vacuum the store every `interval` from a running bot
+///
+/// The bot holds the store lock itself, so this runs alongside the SDK;
+/// databases it is busy writing are skipped until the next run.
+pub fn spawn_maintenance_task(store_path: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // First tick fires immediately
+        loop {
+            ticker.tick().await;
+            let path = store_path.clone();
+            match tokio::task::spawn_blocking(move || vacuum(&path)).await {
+                Ok(Ok(reports)) => {
+                    let before: u64 = reports.iter().map(|report| report.before).sum();
+                    let after: u64 = reports.iter().map(|report| report.after).sum();
+                    info!(
+                        "🧹 Store maintenance: {} → {}",
+                        format_bytes(before),
+                        format_bytes(after)
+                    );
+                }
+                Ok(Err(e)) => warn!("Store maintenance failed: {:#}", e),
+                Err(e) => warn!("Store maintenance task panicked: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,271 @@
+//! Whether the store directory can still be written
+//!
+//! A full or read-only filesystem makes the Matrix SDK's crypto and state
+//! writes fail, often without more than a debug line, so the bot keeps
+//! running while unable to decrypt or persist anything. The store is checked
+//! at startup and every `STORE_CHECK_INTERVAL_SECS` for writability and at
+//! least `STORE_MIN_FREE_MB` of free space. Store write errors seen on the
+//! send and sync paths count as failures too. Failures are logged as errors,
+//! posted to `ADMIN_ROOM` and turn the webhook listener's `/ready` to 503
+//! until a later check passes.
+
+use anyhow::{bail, Context, Result};
+use matrix_sdk::Client;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::admin_room;
+use crate::runtime::Runtime;
+use crate::store::format_bytes;
+
+/// Probe file written and removed by each check
+const PROBE_FILE: &str = ".vagent-write-check";
+
+/// Result of a passing check
+#[derive(Debug, Clone)]
+pub struct StoreStatus {
+    /// Space available to the bot (None where it can't be determined)
+    pub free_bytes: Option<u64>,
+}
+
+/// Check that `store_path` is writable and has `min_free_bytes` available
+///
+/// The error describes what is wrong, ready to be shown to an admin.
+pub fn check(store_path: &Path, min_free_bytes: u64) -> Result<StoreStatus> {
+    check_writable(store_path)?;
+
+    let free_bytes = free_space(store_path);
+    if let Some(free) = free_bytes {
+        if free < min_free_bytes {
+            bail!(
+                "Only {} free on the filesystem of {:?} (minimum {})",
+                format_bytes(free),
+                store_path,
+                format_bytes(min_free_bytes)
+            );
+        }
+    }
+    Ok(StoreStatus { free_bytes })
+}
+
+/// Write, sync and remove a probe file in `store_path`
+pub fn check_writable(store_path: &Path) -> Result<()> {
+    let probe = store_path.join(PROBE_FILE);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .with_context(|| format!("Store directory {:?} is not writable", store_path))?;
+    file.write_all(b"ok")
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Failed to write to the store directory {:?}", store_path))?;
+    drop(file);
+    std::fs::remove_file(&probe).with_context(|| format!("Failed to remove {:?}", probe))
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether an error comes from a full or read-only store
+///
+/// Looks for the OS error codes and SQLite result codes in the chain, then
+/// for their messages, since SDK store errors often only carry the text.
+pub fn is_store_error(error: &anyhow::Error) -> bool {
+    for cause in error.chain() {
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            if matches!(e.raw_os_error(), Some(code) if code == ENOSPC || code == EROFS) {
+                return true;
+            }
+        }
+        if let Some(rusqlite::Error::SqliteFailure(e, _)) = cause.downcast_ref::<rusqlite::Error>()
+        {
+            if matches!(
+                e.code,
+                rusqlite::ErrorCode::DiskFull
+                    | rusqlite::ErrorCode::ReadOnly
+                    | rusqlite::ErrorCode::SystemIoFailure
+            ) {
+                return true;
+            }
+        }
+    }
+    let message = format!("{:#}", error).to_lowercase();
+    STORE_ERROR_MESSAGES
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+const ENOSPC: i32 = 28;
+const EROFS: i32 = 30;
+
+/// Error texts of a full or read-only store (OS and SQLite wording)
+const STORE_ERROR_MESSAGES: &[&str] = &[
+    "no space left on device",
+    "read-only file system",
+    "database or disk is full",
+    "attempt to write a readonly database",
+    "disk i/o error",
+];
+
+struct Monitor {
+    /// Reason the store is unhealthy (None = healthy)
+    problem: Mutex<Option<String>>,
+    reports: mpsc::UnboundedSender<String>,
+}
+
+/// A bot's store health, known once [`spawn_monitor`] runs
+#[derive(Default)]
+pub struct StoreHealth {
+    monitor: OnceLock<Monitor>,
+}
+
+impl StoreHealth {
+    /// Whether the store passed its last check (true before monitoring starts)
+    pub fn is_healthy(&self) -> bool {
+        self.problem().is_none()
+    }
+
+    /// Why the store is unhealthy, if it is
+    pub fn problem(&self) -> Option<String> {
+        self.monitor
+            .get()
+            .and_then(|monitor| monitor.problem.lock().unwrap().clone())
+    }
+
+    /// Raise the alarm if `error` is a store write failure; returns whether it was
+    ///
+    /// `what` names the failed operation for the alert, e.g. "send".
+    pub fn report_error(&self, what: &str, error: &anyhow::Error) -> bool {
+        if !is_store_error(error) {
+            return false;
+        }
+        error!("💾 Store write failed during {}: {:#}", what, error);
+        if let Some(monitor) = self.monitor.get() {
+            let _ = monitor
+                .reports
+                .send(format!("{} failed to write the store: {:#}", what, error));
+        }
+        true
+    }
+}
+
+/// Check the store every `interval` and alert on changes
+///
+/// `initial_problem` is the result of the startup check, so a store that was
+/// already failing is alerted right away. Alerts go to the log and, when set,
+/// the admin room.
+pub fn spawn_monitor(
+    runtime: Runtime,
+    client: Client,
+    store_path: PathBuf,
+    min_free_bytes: u64,
+    interval: Duration,
+    admin_room: Option<String>,
+    initial_problem: Option<String>,
+) {
+    let (reports, mut reported) = mpsc::unbounded_channel();
+    let monitor = Monitor {
+        problem: Mutex::new(None),
+        reports,
+    };
+    if runtime.store_health.monitor.set(monitor).is_err() {
+        warn!("Store health monitor already running");
+        return;
+    }
+    let alerts = Alerts {
+        runtime,
+        client,
+        admin_room,
+    };
+
+    tokio::spawn(async move {
+        if let Some(problem) = initial_problem {
+            alerts.set_problem(Some(problem)).await;
+        }
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+        ticker.tick().await; // First tick fires immediately
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let path = store_path.clone();
+                    let result =
+                        tokio::task::spawn_blocking(move || check(&path, min_free_bytes)).await;
+                    match result {
+                        Ok(Ok(_)) => alerts.set_problem(None).await,
+                        Ok(Err(e)) => alerts.set_problem(Some(format!("{:#}", e))).await,
+                        Err(e) => warn!("Store check panicked: {}", e),
+                    }
+                }
+                Some(report) = reported.recv() => alerts.set_problem(Some(report)).await,
+            }
+        }
+    });
+}
+
+/// Where state changes are announced
+struct Alerts {
+    runtime: Runtime,
+    client: Client,
+    admin_room: Option<String>,
+}
+
+impl Alerts {
+    /// Record the current state, alerting when it changes
+    async fn set_problem(&self, problem: Option<String>) {
+        let Some(monitor) = self.runtime.store_health.monitor.get() else {
+            return;
+        };
+        let previous = {
+            let mut current = monitor.problem.lock().unwrap();
+            std::mem::replace(&mut *current, problem.clone())
+        };
+
+        match (previous, problem) {
+            (None, Some(problem)) => {
+                error!("💾 Store unhealthy, marking the bot not ready: {}", problem);
+                self.post(&format!(
+                    "🚨 **Store problem**: {}\n\nThe bot may be unable to decrypt messages or \
+                     persist its session until this is fixed.",
+                    problem
+                ))
+                .await;
+            }
+            (Some(_), Some(problem)) => error!("💾 Store still unhealthy: {}", problem),
+            (Some(_), None) => {
+                info!("💾 Store healthy again");
+                self.post("✅ The store is writable again.").await;
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Post to the admin room; the send itself may fail on a broken store
+    async fn post(&self, text: &str) {
+        if let Some(room) = &self.admin_room {
+            admin_room::post(&self.runtime, &self.client, room, "store alert", text).await;
+        }
+    }
+}
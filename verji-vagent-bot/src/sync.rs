@@ -0,0 +1,123 @@
+//! Initial and steady-state sync with the homeserver
+//!
+//! Full room state is only requested by the first sync after a fresh login
+//! (new store or `--clear-store`); everything after that is incremental from
+//! the stored sync token. The initial sync logs its size and duration
+//! (`sync_duration_ms`); incremental responses log their size and the time
+//! since the previous one (`sync_interval_ms`), which includes the server's
+//! long-poll wait, so the difference is visible on large accounts.
+
+use anyhow::{Context, Result};
+use matrix_sdk::{config::SyncSettings, sync::SyncResponse, Client, LoopCtrl};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::metrics::Metrics;
+
+/// What a sync response carried, for logging
+struct SyncSize {
+    joined: usize,
+    invited: usize,
+    left: usize,
+    timeline_events: usize,
+    to_device: usize,
+}
+
+impl SyncSize {
+    fn of(response: &SyncResponse) -> Self {
+        Self {
+            joined: response.rooms.joined.len(),
+            invited: response.rooms.invited.len(),
+            left: response.rooms.left.len(),
+            timeline_events: response
+                .rooms
+                .joined
+                .values()
+                .map(|room| room.timeline.events.len())
+                .sum(),
+            to_device: response.to_device.len(),
+        }
+    }
+}
+
+impl std::fmt::Display for SyncSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} joined, {} invited, {} left rooms, {} timeline events, {} to-device events",
+            self.joined, self.invited, self.left, self.timeline_events, self.to_device
+        )
+    }
+}
+
+/// First sync after startup
+///
+/// `full_state` is only needed when the store has no room state yet;
+/// restored sessions catch up incrementally from their sync token.
+pub async fn initial_sync(
+    client: &Client,
+    full_state: bool,
+    timeout: Duration,
+    metrics: &Metrics,
+) -> Result<()> {
+    info!(
+        "🔄 Performing initial sync ({})...",
+        if full_state {
+            "full state"
+        } else {
+            "incremental"
+        }
+    );
+    let started = Instant::now();
+
+    let settings = SyncSettings::default()
+        .full_state(full_state)
+        .timeout(timeout);
+    let response = client
+        .sync_once(settings)
+        .await
+        .context("Initial sync failed")?;
+
+    let elapsed = started.elapsed();
+    metrics.observe_ms(
+        "sync_duration_ms",
+        &[("phase", "initial")],
+        elapsed.as_millis() as u64,
+    );
+    info!(
+        "✅ Initial sync completed in {:.1}s: {}",
+        elapsed.as_secs_f64(),
+        SyncSize::of(&response)
+    );
+    Ok(())
+}
+
+/// Incremental sync loop; runs until the client stops syncing or fails
+///
+/// Rooms that turn on encryption later are picked up from their
+/// `m.room.encryption` state event in these incremental responses.
+pub async fn run(client: &Client, metrics: &Metrics) -> Result<()> {
+    let last = Mutex::new(Instant::now());
+
+    client
+        .sync_with_callback(SyncSettings::default(), |response| {
+            let elapsed = {
+                let mut last = last.lock().unwrap();
+                let elapsed = last.elapsed();
+                *last = Instant::now();
+                elapsed
+            };
+            // Time between responses, long-poll wait included; not the
+            // duration of a request
+            metrics.observe_ms("sync_interval_ms", &[], elapsed.as_millis() as u64);
+            debug!(
+                "🔄 Sync iteration after {}ms: {}",
+                elapsed.as_millis(),
+                SyncSize::of(&response)
+            );
+            async { LoopCtrl::Continue }
+        })
+        .await
+        .context("Sync loop failed")
+}
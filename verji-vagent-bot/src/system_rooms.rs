@@ -0,0 +1,218 @@
+//! Server-notice and other system rooms, where the bot never answers
+//!
+//! Homeservers send announcements (maintenance, usage limits, terms) to a
+//! server-notices room they create for each user, the bot included. Such rooms
+//! are tagged `m.server_notice` on the bot's account, or failing that
+//! recognized by their creator (`SERVER_NOTICES_USERS`). Rooms created by
+//! `SYSTEM_ROOM_CREATORS` or listed in `SYSTEM_ROOMS` are system rooms too.
+//! Responders never run in either kind; server notices are forwarded to
+//! `ADMIN_ROOM` instead, so operators see them.
+
+use matrix_sdk::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::admin_room;
+use crate::alias::Aliases;
+use crate::config::env_list;
+use crate::room::RoomHandle;
+use crate::runtime::Runtime;
+
+/// Room tag homeservers put on server-notices rooms
+pub const SERVER_NOTICE_TAG: &str = "m.server_notice";
+
+/// Why the bot stays out of a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemRoomKind {
+    /// The homeserver's announcements to the bot
+    ServerNotices,
+    /// Another room operators set aside (`SYSTEM_ROOMS`, `SYSTEM_ROOM_CREATORS`)
+    System,
+}
+
+/// A detected system room and the rule that found it, for the logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemRoom {
+    pub kind: SystemRoomKind,
+    pub reason: &'static str,
+}
+
+/// How system rooms are recognized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemRoomPolicy {
+    /// Users that create server-notices rooms: MXIDs, or localparts on any server
+    pub notice_users: Vec<String>,
+    /// Users whose rooms are system rooms, in the same form
+    pub system_creators: Vec<String>,
+    /// Room IDs or aliases of system rooms
+    pub system_rooms: Vec<String>,
+}
+
+impl Default for SystemRoomPolicy {
+    fn default() -> Self {
+        Self {
+            // Synapse's usual `system_mxid_localpart`
+            notice_users: vec!["notices".to_string()],
+            system_creators: Vec::new(),
+            system_rooms: Vec::new(),
+        }
+    }
+}
+
+impl SystemRoomPolicy {
+    pub fn from_env() -> Self {
+        let notice_users = match std::env::var("SERVER_NOTICES_USERS") {
+            Ok(_) => env_list("SERVER_NOTICES_USERS"),
+            Err(_) => Self::default().notice_users,
+        };
+        Self {
+            notice_users,
+            system_creators: env_list("SYSTEM_ROOM_CREATORS"),
+            system_rooms: env_list("SYSTEM_ROOMS"),
+        }
+    }
+}
+
+/// Whether `user_id` is `entry`: the same MXID, or a user with that localpart
+fn matches_user(entry: &str, user_id: &str) -> bool {
+    if entry.starts_with('@') {
+        return entry == user_id;
+    }
+    user_id
+        .strip_prefix('@')
+        .and_then(|user| user.split_once(':'))
+        .is_some_and(|(localpart, _)| localpart == entry)
+}
+
+/// Classify a room from its ID, the bot's `m.tag` content and its creator;
+/// `SYSTEM_ROOMS` aliases match once `aliases` has resolved them
+pub fn detect(
+    room_id: &str,
+    tags: Option<&Value>,
+    creator: Option<&str>,
+    policy: &SystemRoomPolicy,
+    aliases: &Aliases,
+) -> Option<SystemRoom> {
+    let tagged = tags
+        .and_then(|tags| tags.get("tags"))
+        .is_some_and(|tags| tags.get(SERVER_NOTICE_TAG).is_some());
+    if tagged {
+        return Some(SystemRoom {
+            kind: SystemRoomKind::ServerNotices,
+            reason: "m.server_notice tag",
+        });
+    }
+    if policy
+        .system_rooms
+        .iter()
+        .any(|room| aliases.matches(room, room_id))
+    {
+        return Some(SystemRoom {
+            kind: SystemRoomKind::System,
+            reason: "listed in SYSTEM_ROOMS",
+        });
+    }
+    let creator = creator?;
+    if policy
+        .notice_users
+        .iter()
+        .any(|entry| matches_user(entry, creator))
+    {
+        return Some(SystemRoom {
+            kind: SystemRoomKind::ServerNotices,
+            reason: "created by the server notices user",
+        });
+    }
+    if policy
+        .system_creators
+        .iter()
+        .any(|entry| matches_user(entry, creator))
+    {
+        return Some(SystemRoom {
+            kind: SystemRoomKind::System,
+            reason: "created by a system user",
+        });
+    }
+    None
+}
+
+/// Cached system room detection for each room
+pub struct SystemRooms {
+    policy: SystemRoomPolicy,
+    aliases: Arc<Aliases>,
+    /// Room ID -> detection (None = an ordinary room)
+    cache: Mutex<HashMap<String, Option<SystemRoom>>>,
+}
+
+impl SystemRooms {
+    pub fn new(policy: SystemRoomPolicy, aliases: Arc<Aliases>) -> Self {
+        Self {
+            policy,
+            aliases,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the room is a system room; read failures count as ordinary and are retried
+    pub async fn check(&self, room: &dyn RoomHandle) -> Option<SystemRoom> {
+        let room_id = room.room_id().to_string();
+        if let Some(state) = self.cache.lock().unwrap().get(&room_id) {
+            return *state;
+        }
+
+        let tags = match room.room_account_data("m.tag").await {
+            Ok(tags) => tags,
+            Err(e) => {
+                warn!("Failed to read the tags of {}: {:#}", room_id, e);
+                return None;
+            }
+        };
+        let creator = match room.creator().await {
+            Ok(creator) => creator,
+            Err(e) => {
+                warn!("Failed to read the creator of {}: {:#}", room_id, e);
+                return None;
+            }
+        };
+        let state = detect(
+            &room_id,
+            tags.as_ref(),
+            creator.as_deref(),
+            &self.policy,
+            &self.aliases,
+        );
+        if let Some(system) = state {
+            info!(
+                "🏛️ {} is a {:?} room ({}); responders won't run there",
+                room_id, system.kind, system.reason
+            );
+        }
+        self.cache.lock().unwrap().insert(room_id, state);
+        state
+    }
+
+    /// Forget the room's detection, after its tags changed
+    pub fn invalidate(&self, room_id: &str) {
+        self.cache.lock().unwrap().remove(room_id);
+    }
+}
+
+/// Post a server notice the bot received to `admin_room`
+pub async fn forward_notice(
+    runtime: &Runtime,
+    client: &Client,
+    admin_room: &str,
+    sender: &str,
+    body: &str,
+) {
+    info!("📢 Forwarding a server notice from {}", sender);
+    let quoted = body
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let markdown = format!("📢 **Server notice** from `{}`:\n\n{}", sender, quoted);
+    admin_room::post(runtime, client, admin_room, "server notice", &markdown).await;
+}
@@ -0,0 +1,119 @@
+//! Tenant (customer organization) of a room, read from a room state event
+//!
+//! The event type, state key and the path to the ID inside the content are
+//! configurable (`TENANT_EVENT_TYPE`, `TENANT_STATE_KEY`, `TENANT_PATH`).
+//! Resolutions are cached per room and dropped when a state event of that
+//! type arrives.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::config;
+use crate::room::RoomHandle;
+
+/// What the bot found for a room
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tenant {
+    Found(String),
+    /// No tenant state event in the room
+    Missing,
+    /// The event exists but has no usable ID at the configured path
+    Malformed(String),
+}
+
+impl Tenant {
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Tenant::Found(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Tenant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tenant::Found(id) => write!(f, "`{}`", id),
+            Tenant::Missing => write!(f, "none (no state event)"),
+            Tenant::Malformed(reason) => write!(f, "none (malformed state event: {})", reason),
+        }
+    }
+}
+
+/// Resolves and caches room tenants
+pub struct TenantResolver {
+    pub event_type: String,
+    pub state_key: String,
+    /// Dotted path to the ID in the event content, e.g. `org.id`
+    pub path: String,
+    /// Whether agent queries from rooms without a tenant are refused
+    pub required: bool,
+    /// Room ID -> resolution
+    cache: Mutex<HashMap<String, Tenant>>,
+}
+
+impl TenantResolver {
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+        Self {
+            event_type: var("TENANT_EVENT_TYPE", "no.verji.tenant"),
+            state_key: std::env::var("TENANT_STATE_KEY").unwrap_or_default(),
+            path: var("TENANT_PATH", "tenant_id"),
+            required: config::env_bool("REQUIRE_TENANT", false),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The room's tenant; read failures are not cached so the next message retries
+    pub async fn resolve(&self, room: &dyn RoomHandle) -> Tenant {
+        let room_id = room.room_id().to_string();
+        if let Some(tenant) = self.cache.lock().unwrap().get(&room_id) {
+            return tenant.clone();
+        }
+
+        let tenant = match room.state_event(&self.event_type, &self.state_key).await {
+            Ok(Some(content)) => self.extract(&content),
+            Ok(None) => Tenant::Missing,
+            Err(e) => {
+                warn!("Failed to read tenant of {}: {:#}", room_id, e);
+                return Tenant::Missing;
+            }
+        };
+        if let Tenant::Malformed(reason) = &tenant {
+            warn!("🏢 Ignoring tenant event in {}: {}", room_id, reason);
+        }
+
+        self.cache.lock().unwrap().insert(room_id, tenant.clone());
+        tenant
+    }
+
+    /// Forget the cached tenant after the room's state event changed
+    pub fn invalidate(&self, room_id: &str) {
+        if self.cache.lock().unwrap().remove(room_id).is_some() {
+            debug!("🏢 Tenant state of {} changed", room_id);
+        }
+    }
+
+    fn extract(&self, content: &Value) -> Tenant {
+        let value = self
+            .path
+            .split('.')
+            .try_fold(content, |value, key| value.get(key));
+        match value {
+            Some(Value::String(id)) if !id.trim().is_empty() => {
+                Tenant::Found(id.trim().to_string())
+            }
+            Some(Value::Number(id)) => Tenant::Found(id.to_string()),
+            Some(_) => Tenant::Malformed(format!("`{}` is not a non-empty string", self.path)),
+            None => Tenant::Malformed(format!("no `{}` in the content", self.path)),
+        }
+    }
+}
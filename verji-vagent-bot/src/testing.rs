@@ -0,0 +1,647 @@
+//! Test doubles for running responders without a homeserver
+//!
+//! Compiled for unit tests and with the `testing` feature.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use matrix_sdk::{
+    ruma::{EventId, OwnedEventId, OwnedRoomId, RoomId},
+    Client,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::config::BotConfig;
+use crate::conversation::ConversationStore;
+use crate::follow_up::FollowUpScheduler;
+use crate::observer::ObserverPool;
+use crate::observers;
+use crate::preferences::PreferenceStore;
+use crate::responder::{OutgoingMessage, Responder, ResponderContext};
+use crate::responder_manager::ResponderManager;
+use crate::room::{RoomHandle, TimelineBody, TimelineEntry, TimelinePage};
+use crate::room_config::RoomConfigStore;
+use crate::room_context::{HistoryMessage, HistoryVisibility, Membership, MembershipChange};
+use crate::runtime::Runtime;
+use crate::sent_events::SentEventRegistry;
+use crate::stats::UsageStats;
+use crate::still_working::OutputActivity;
+use crate::tenant::TenantResolver;
+
+/// In-memory room that records everything sent to it
+pub struct MockRoom {
+    room_id: OwnedRoomId,
+    name: Option<String>,
+    alias: Option<String>,
+    encrypted: bool,
+    members: u64,
+    sent: Mutex<Vec<OutgoingMessage>>,
+    typing: Mutex<Vec<bool>>,
+    edits: Mutex<Vec<(OwnedEventId, String)>>,
+    redactions: Mutex<Vec<OwnedEventId>>,
+    history: Vec<HistoryMessage>,
+    account_data: Mutex<HashMap<String, Value>>,
+    /// (event type, state key) -> content
+    state: HashMap<(String, String), Value>,
+    visibility: HistoryVisibility,
+    /// User ID -> membership events, oldest first
+    memberships: HashMap<String, Vec<MembershipChange>>,
+    pinned: Mutex<Vec<OwnedEventId>>,
+    can_pin: bool,
+    room_key_rotations: Mutex<usize>,
+    send_delay: Duration,
+}
+
+impl MockRoom {
+    pub fn new(room_id: &str) -> Result<Self> {
+        Ok(Self {
+            room_id: RoomId::parse(room_id).context("Invalid room ID")?,
+            name: None,
+            alias: None,
+            encrypted: false,
+            members: 2,
+            sent: Mutex::new(Vec::new()),
+            typing: Mutex::new(Vec::new()),
+            edits: Mutex::new(Vec::new()),
+            redactions: Mutex::new(Vec::new()),
+            history: Vec::new(),
+            account_data: Mutex::new(HashMap::new()),
+            state: HashMap::new(),
+            visibility: HistoryVisibility::Shared,
+            memberships: HashMap::new(),
+            pinned: Mutex::new(Vec::new()),
+            can_pin: true,
+            room_key_rotations: Mutex::new(0),
+            send_delay: Duration::ZERO,
+        })
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.to_string());
+        self
+    }
+
+    pub fn encrypted(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    /// Number of joined members (2 = a direct chat with the bot)
+    pub fn with_members(mut self, members: u64) -> Self {
+        self.members = members;
+        self
+    }
+
+    /// Timeline returned by `recent_messages`, as (sender, body) oldest first
+    pub fn with_history(mut self, messages: &[(&str, &str)]) -> Self {
+        self.history = messages
+            .iter()
+            .enumerate()
+            .map(|(index, (sender, body))| HistoryMessage {
+                event_id: format!("$hist{}:localhost", index),
+                sender: sender.to_string(),
+                body: body.to_string(),
+                timestamp_ms: index as u64 * 1_000,
+            })
+            .collect();
+        self
+    }
+
+    /// Room state event returned by `state_event`
+    pub fn with_state_event(mut self, event_type: &str, state_key: &str, content: Value) -> Self {
+        self.state
+            .insert((event_type.to_string(), state_key.to_string()), content);
+        self
+    }
+
+    /// History visibility of the room (default shared)
+    pub fn with_history_visibility(mut self, visibility: HistoryVisibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// A user's membership events as (membership, unix ms)
+    pub fn with_memberships(mut self, user_id: &str, changes: &[(Membership, u64)]) -> Self {
+        let changes = changes
+            .iter()
+            .map(|(membership, timestamp_ms)| MembershipChange {
+                membership: *membership,
+                timestamp_ms: *timestamp_ms,
+            })
+            .collect();
+        self.memberships.insert(user_id.to_string(), changes);
+        self
+    }
+
+    /// Whether the bot may change the pinned events (default true)
+    pub fn with_pin_permission(mut self, can_pin: bool) -> Self {
+        self.can_pin = can_pin;
+        self
+    }
+
+    /// How long each send and edit takes, like a slow homeserver (default none)
+    pub fn with_send_delay(mut self, delay: Duration) -> Self {
+        self.send_delay = delay;
+        self
+    }
+
+    /// Currently pinned events
+    pub fn pinned(&self) -> Vec<OwnedEventId> {
+        self.pinned.lock().unwrap().clone()
+    }
+
+    /// Times the outbound room key was discarded
+    pub fn room_key_rotations(&self) -> usize {
+        *self.room_key_rotations.lock().unwrap()
+    }
+
+    /// Everything sent so far, in order
+    pub fn sent(&self) -> Vec<OutgoingMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Typing indicator changes so far, in order
+    pub fn typing_changes(&self) -> Vec<bool> {
+        self.typing.lock().unwrap().clone()
+    }
+
+    /// Edits of earlier messages so far, in order
+    pub fn edits(&self) -> Vec<(OwnedEventId, String)> {
+        self.edits.lock().unwrap().clone()
+    }
+
+    /// Events redacted in the room, in order
+    pub fn redactions(&self) -> Vec<OwnedEventId> {
+        self.redactions.lock().unwrap().clone()
+    }
+
+    async fn delay_send(&self) {
+        if !self.send_delay.is_zero() {
+            tokio::time::sleep(self.send_delay).await;
+        }
+    }
+
+    /// Keep a sent message, returning a made-up event ID for it
+    fn record_sent(&self, message: OutgoingMessage) -> Result<OwnedEventId> {
+        let mut sent = self.sent.lock().unwrap();
+        sent.push(message);
+        EventId::parse(format!("$mock{}:localhost", sent.len())).context("Invalid event ID")
+    }
+}
+
+#[async_trait]
+impl RoomHandle for MockRoom {
+    fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+
+    fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| self.room_id.to_string())
+    }
+
+    fn canonical_alias(&self) -> Option<String> {
+        self.alias.clone()
+    }
+
+    fn member_count(&self) -> u64 {
+        self.members
+    }
+
+    async fn send_text(&self, body: &str) -> Result<OwnedEventId> {
+        self.delay_send().await;
+        self.record_sent(OutgoingMessage::Text(body.to_string()))
+    }
+
+    async fn send_content(
+        &self,
+        _trigger: &EventId,
+        message: OutgoingMessage,
+    ) -> Result<OwnedEventId> {
+        self.delay_send().await;
+        self.record_sent(message)
+    }
+
+    async fn send_markdown(&self, body: &str) -> Result<OwnedEventId> {
+        self.delay_send().await;
+        self.record_sent(OutgoingMessage::Markdown(body.to_string()))
+    }
+
+    async fn edit_markdown(&self, event_id: &EventId, body: &str) -> Result<()> {
+        self.delay_send().await;
+        self.edits
+            .lock()
+            .unwrap()
+            .push((event_id.to_owned(), body.to_string()));
+        Ok(())
+    }
+
+    async fn redact(&self, event_id: &EventId, _reason: Option<&str>) -> Result<()> {
+        self.redactions.lock().unwrap().push(event_id.to_owned());
+        Ok(())
+    }
+
+    async fn typing(&self, typing: bool) -> Result<()> {
+        self.typing.lock().unwrap().push(typing);
+        Ok(())
+    }
+
+    async fn recent_messages(&self, limit: usize) -> Result<Vec<HistoryMessage>> {
+        let skip = self.history.len().saturating_sub(limit);
+        Ok(self.history[skip..].to_vec())
+    }
+
+    async fn history_page(&self, from: Option<String>, limit: usize) -> Result<TimelinePage> {
+        // The token counts the newest messages already returned
+        let returned: usize = from.map(|token| token.parse()).transpose()?.unwrap_or(0);
+        let end = self.history.len().saturating_sub(returned);
+        let start = end.saturating_sub(limit);
+        let entries = self.history[start..end]
+            .iter()
+            .rev()
+            .map(|message| TimelineEntry {
+                event_id: message.event_id.clone(),
+                sender: message.sender.clone(),
+                body: TimelineBody::Text(message.body.clone()),
+                timestamp_ms: message.timestamp_ms,
+            })
+            .collect();
+        Ok(TimelinePage {
+            entries,
+            next: (start > 0).then(|| (returned + end - start).to_string()),
+        })
+    }
+
+    async fn member_display_name(&self, _user_id: &str) -> Option<String> {
+        None
+    }
+
+    async fn fetch_event(&self, event_id: &EventId) -> Result<Option<TimelineEntry>> {
+        Ok(self
+            .history
+            .iter()
+            .find(|message| message.event_id == event_id.as_str())
+            .map(|message| TimelineEntry {
+                event_id: message.event_id.clone(),
+                sender: message.sender.clone(),
+                body: TimelineBody::Text(message.body.clone()),
+                timestamp_ms: message.timestamp_ms,
+            }))
+    }
+
+    async fn pinned_events(&self) -> Result<Vec<OwnedEventId>> {
+        Ok(self.pinned())
+    }
+
+    async fn set_pinned_events(&self, pinned: Vec<OwnedEventId>) -> Result<()> {
+        if !self.can_pin {
+            anyhow::bail!("Missing power level for m.room.pinned_events");
+        }
+        *self.pinned.lock().unwrap() = pinned;
+        Ok(())
+    }
+
+    async fn can_pin(&self) -> Result<bool> {
+        Ok(self.can_pin)
+    }
+
+    async fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    async fn rotate_room_key(&self) -> Result<()> {
+        *self.room_key_rotations.lock().unwrap() += 1;
+        Ok(())
+    }
+
+    async fn megolm_session_id(&self, _event_id: &EventId) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn history_visibility(&self) -> HistoryVisibility {
+        self.visibility
+    }
+
+    async fn membership_changes(&self, user_id: &str) -> Result<Vec<MembershipChange>> {
+        Ok(self.memberships.get(user_id).cloned().unwrap_or_default())
+    }
+
+    async fn state_event(&self, event_type: &str, state_key: &str) -> Result<Option<Value>> {
+        let key = (event_type.to_string(), state_key.to_string());
+        Ok(self.state.get(&key).cloned())
+    }
+
+    async fn creator(&self) -> Result<Option<String>> {
+        let key = ("m.room.create".to_string(), String::new());
+        Ok(self
+            .state
+            .get(&key)
+            .and_then(|content| content.get("creator"))
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    async fn room_account_data(&self, event_type: &str) -> Result<Option<Value>> {
+        Ok(self.account_data.lock().unwrap().get(event_type).cloned())
+    }
+
+    async fn set_room_account_data(&self, event_type: &str, content: Value) -> Result<()> {
+        self.account_data
+            .lock()
+            .unwrap()
+            .insert(event_type.to_string(), content);
+        Ok(())
+    }
+}
+
+/// Clock that only moves when told to
+pub struct ManualClock {
+    now_ms: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start_ms: u64) -> Self {
+        Self {
+            now_ms: AtomicU64::new(start_ms),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now_ms
+            .fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+}
+
+/// The body of a text-like message, `None` for reactions and attachments
+pub fn message_text(message: &OutgoingMessage) -> Option<&str> {
+    match message {
+        OutgoingMessage::Text(body)
+        | OutgoingMessage::Markdown(body)
+        | OutgoingMessage::Notice(body)
+        | OutgoingMessage::Mention { body, .. } => Some(body),
+        OutgoingMessage::Reaction(_) | OutgoingMessage::Attachment { .. } => None,
+    }
+}
+
+/// Builds contexts around a [`MockRoom`] and runs responders against them
+///
+/// Each harness gets its own temporary store directory (for usage stats and
+/// any other bot databases), removed when the harness is dropped.
+pub struct ResponderTestHarness {
+    room: Arc<MockRoom>,
+    sender: String,
+    is_direct_mention: bool,
+    in_reply_to: Option<OwnedEventId>,
+    message_formatted: bool,
+    custom_event: Option<Value>,
+    config: BotConfig,
+    store_dir: PathBuf,
+    stats: Arc<UsageStats>,
+    conversations: Arc<ConversationStore>,
+    follow_ups: Arc<FollowUpScheduler>,
+    preferences: Arc<PreferenceStore>,
+    room_configs: Arc<RoomConfigStore>,
+    tenants: Arc<TenantResolver>,
+    sent_events: Arc<SentEventRegistry>,
+    runtime: Runtime,
+}
+
+impl ResponderTestHarness {
+    pub fn new() -> Result<Self> {
+        let store_dir = std::env::temp_dir().join(format!("vagent-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&store_dir)?;
+
+        let mut config = BotConfig::from_env();
+        config.store_path = store_dir.clone();
+        config.admin_users = Vec::new();
+        let runtime = Runtime::default();
+
+        Ok(Self {
+            room: Arc::new(MockRoom::new("!test:localhost")?),
+            sender: "@user:localhost".to_string(),
+            is_direct_mention: false,
+            in_reply_to: None,
+            message_formatted: true,
+            custom_event: None,
+            stats: Arc::new(
+                UsageStats::open(&store_dir, config.history_max_age)?.with_metrics(Arc::clone(&runtime.metrics)),
+            ),
+            conversations: Arc::new(ConversationStore::in_memory(
+                config.conversation_max_entries,
+            )),
+            follow_ups: Arc::new(
+                FollowUpScheduler::open(&store_dir, config.follow_ups_per_room_max)?
+                    .with_metrics(Arc::clone(&runtime.metrics)),
+            ),
+            preferences: Arc::new(PreferenceStore::open(&store_dir)?),
+            room_configs: Arc::new(RoomConfigStore::new()),
+            tenants: Arc::new(TenantResolver::from_env()),
+            sent_events: Arc::new(SentEventRegistry::in_memory(
+                config.sent_events_max,
+                config.sent_events_max_age,
+            )),
+            runtime,
+            config,
+            store_dir,
+        })
+    }
+
+    pub fn room(mut self, room: MockRoom) -> Self {
+        self.room = Arc::new(room);
+        self
+    }
+
+    pub fn sender(mut self, sender: &str) -> Self {
+        self.sender = sender.to_string();
+        self
+    }
+
+    /// Put the current sender on the admin list
+    pub fn admin(mut self) -> Self {
+        self.config.admin_users.push(self.sender.clone());
+        self
+    }
+
+    pub fn mention(mut self, is_direct_mention: bool) -> Self {
+        self.is_direct_mention = is_direct_mention;
+        self
+    }
+
+    /// Whether messages arrive with a formatted body (default: yes)
+    pub fn formatted(mut self, message_formatted: bool) -> Self {
+        self.message_formatted = message_formatted;
+        self
+    }
+
+    /// Make messages forwarded custom events with this request payload
+    /// (see `custom_events::payload`)
+    pub fn custom_event(mut self, payload: Value) -> Self {
+        self.custom_event = Some(payload);
+        self.is_direct_mention = true;
+        self
+    }
+
+    /// Make messages replies to `event_id`
+    pub fn reply_to(mut self, event_id: &EventId) -> Self {
+        self.in_reply_to = Some(event_id.to_owned());
+        self
+    }
+
+    pub fn configure(mut self, change: impl FnOnce(&mut BotConfig)) -> Self {
+        change(&mut self.config);
+        self
+    }
+
+    /// Resolve room tenants with `tenants` instead of the `TENANT_*` settings
+    pub fn tenants(mut self, tenants: TenantResolver) -> Self {
+        self.tenants = Arc::new(tenants);
+        self
+    }
+
+    /// The mock room messages are recorded in
+    pub fn mock_room(&self) -> &MockRoom {
+        &self.room
+    }
+
+    pub fn stats(&self) -> &Arc<UsageStats> {
+        &self.stats
+    }
+
+    pub fn conversations(&self) -> &Arc<ConversationStore> {
+        &self.conversations
+    }
+
+    pub fn follow_ups(&self) -> &Arc<FollowUpScheduler> {
+        &self.follow_ups
+    }
+
+    pub fn preferences(&self) -> &Arc<PreferenceStore> {
+        &self.preferences
+    }
+
+    pub fn room_configs(&self) -> &Arc<RoomConfigStore> {
+        &self.room_configs
+    }
+
+    pub fn sent_events(&self) -> &Arc<SentEventRegistry> {
+        &self.sent_events
+    }
+
+    /// The harness's own metrics, catalog, queues and other shared state
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// Build a context for `body` as if it had just arrived
+    pub async fn context(&self, body: &str) -> Result<ResponderContext> {
+        let client = Client::builder()
+            .homeserver_url("http://localhost:8008")
+            .build()
+            .await
+            .context("Failed to build offline client")?;
+        let event_id: OwnedEventId = EventId::parse(format!("${}", uuid::Uuid::new_v4()))?;
+
+        Ok(ResponderContext {
+            client,
+            room: Arc::clone(&self.room) as Arc<dyn RoomHandle>,
+            event_id,
+            in_reply_to: self.in_reply_to.clone(),
+            sender: self.sender.as_str().into(),
+            sender_display_name: self.room.member_display_name(&self.sender).await,
+            identity: self.config.identities.canonical(&self.sender).into(),
+            thread_id: None,
+            message_body: body.into(),
+            is_direct_mention: self.is_direct_mention,
+            message_formatted: self.message_formatted,
+            registered_responders: Vec::new(),
+            config: Arc::new(self.config.clone()),
+            stats: Arc::clone(&self.stats),
+            conversations: Arc::clone(&self.conversations),
+            follow_ups: Arc::clone(&self.follow_ups),
+            preferences: Arc::clone(&self.preferences),
+            annotations: HashMap::new(),
+            room_config: self.room_configs.get(self.room.as_ref()).await,
+            room_configs: Arc::clone(&self.room_configs),
+            tenants: Arc::clone(&self.tenants),
+            sent_events: Arc::clone(&self.sent_events),
+            retry_attempt: 0,
+            output: Arc::new(OutputActivity::new()),
+            observers: Arc::new(ObserverPool::new()),
+            custom_event: self.custom_event.clone(),
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Dispatch `body` through a manager and deliver the output to the mock room
+    pub async fn dispatch(
+        &self,
+        manager: &ResponderManager,
+        body: &str,
+    ) -> Result<Vec<OutgoingMessage>> {
+        let mut context = self.context(body).await?;
+        context.registered_responders = manager.active_in(context.room.as_ref(), &self.runtime.aliases);
+        context.observers = Arc::clone(manager.observers());
+
+        let messages = manager.dispatch(&context).await?;
+        for message in messages.clone() {
+            self.room.send_content(&context.event_id, message).await?;
+        }
+        // Stats and feedback are recorded by observers; let them catch up
+        manager.observers().idle().await;
+        Ok(messages)
+    }
+
+    /// Dispatch `body` to a single responder, with the bot's observers
+    pub async fn respond(
+        &self,
+        responder: Arc<dyn Responder>,
+        body: &str,
+    ) -> Result<Vec<OutgoingMessage>> {
+        let mut manager = ResponderManager::new();
+        for observer in observers::standard() {
+            manager.add_observer(observer);
+        }
+        manager.register(responder);
+        self.dispatch(&manager, body).await
+    }
+
+    /// Remove the bot from the room, as a kick would
+    pub fn kick(&self) {
+        self.runtime.memberships.left(self.room.room_id().as_str());
+    }
+
+    /// Put the bot back into the room after a kick
+    pub fn rejoin(&self) {
+        self.runtime.memberships.joined(self.room.room_id().as_str());
+    }
+
+    /// Assert that exactly these text bodies were sent, in order
+    pub fn assert_sent_texts(&self, expected: &[&str]) {
+        let sent = self.room.sent();
+        let texts: Vec<&str> = sent.iter().filter_map(message_text).collect();
+        assert_eq!(texts, expected, "unexpected messages sent to the room");
+    }
+}
+
+impl Drop for ResponderTestHarness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.store_dir);
+    }
+}
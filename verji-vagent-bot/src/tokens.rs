@@ -0,0 +1,17 @@
+//! Token estimates for budgeting what is sent to the graph
+//!
+//! A characters/4 heuristic that is close enough for English and Norwegian
+//! prose; kept behind this module so a real tokenizer can replace it.
+
+/// Approximate characters per token
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimated tokens in `text` (rounded up)
+pub fn estimate(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Characters that fit in roughly `tokens` tokens
+pub fn chars_for(tokens: usize) -> usize {
+    tokens * CHARS_PER_TOKEN
+}
@@ -0,0 +1,102 @@
+//! Translating questions and answers through vagent-graph
+//!
+//! Rooms with `translate_to` set (`!admin translate`) get the agent's answer
+//! translated by a `translate` command, posted under the original or in its
+//! place (`translate_mode`). With `translate_questions` the question is
+//! translated before the query too. A failed translation is logged and the
+//! untranslated text is used, so it never holds back an answer.
+//!
+//! Identical translations are cached for `TRANSLATION_CACHE_TTL_SECS`.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::agent_service::AgentService;
+use crate::config;
+use crate::metrics::Metrics;
+use crate::redis_client::{GraphMessageType, GraphRequest};
+use crate::request_id;
+use crate::responder::ResponderContext;
+use crate::response_cache::{CacheKey, ResponseCache};
+use crate::tenant::Tenant;
+
+/// Translations of texts seen recently
+pub struct Translator {
+    /// None = `TRANSLATION_CACHE_TTL_SECS=0`
+    cache: Option<ResponseCache>,
+}
+
+impl Translator {
+    /// Cache hits, misses and evictions are counted in `metrics`
+    pub fn from_env(metrics: Arc<Metrics>) -> Self {
+        let ttl = config::env_u64("TRANSLATION_CACHE_TTL_SECS", 300);
+        let cache = (ttl > 0).then(|| {
+            ResponseCache::new(
+                Duration::from_secs(ttl),
+                config::env_u64("TRANSLATION_CACHE_MAX_ENTRIES", 500) as usize,
+            )
+            .with_metric("translation_cache")
+            .with_metrics(metrics)
+        });
+        Self { cache }
+    }
+
+    /// `text` in `target`, or None if the graph couldn't translate it
+    ///
+    /// `step` names the leg (question or answer) in the request ID and logs.
+    pub async fn translate(
+        &self,
+        agent: &AgentService,
+        context: &ResponderContext,
+        tenant: &Tenant,
+        step: &str,
+        text: &str,
+        target: &str,
+    ) -> Option<String> {
+        if text.trim().is_empty() {
+            return None;
+        }
+        let key = CacheKey::verbatim(target, text);
+        if let Some(translation) = self.cache.as_ref().and_then(|cache| cache.get(key)) {
+            return Some(translation);
+        }
+
+        let mut request = GraphRequest::command(
+            "translate",
+            serde_json::json!({ "text": text, "target_language": target }),
+            context.room.room_id().to_string(),
+            context.identity.to_string(),
+        );
+        request.request_id = request_id::for_step(context, &format!("translate-{}", step));
+        request.metadata.thread_id = context.thread_id.clone();
+        request.metadata.tenant_id = tenant.id().map(str::to_string);
+        let translation = match agent.run_command(request).await {
+            Ok(message) if message.message_type != GraphMessageType::Error => message.content,
+            Ok(message) => {
+                warn!(
+                    "🌐 vagent-graph could not translate the {}: {}",
+                    step, message.content
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("🌐 Failed to translate the {}: {:#}", step, e);
+                return None;
+            }
+        };
+        if translation.trim().is_empty() {
+            warn!(
+                "🌐 vagent-graph returned an empty translation of the {}",
+                step
+            );
+            return None;
+        }
+
+        info!("🌐 Translated the {} into {}", step, target);
+        if let Some(cache) = &self.cache {
+            cache.insert(key, translation.clone());
+        }
+        Some(translation)
+    }
+}
@@ -0,0 +1,246 @@
+//! gRPC client for vagent-graph (`GRAPH_TRANSPORT=grpc`)
+//!
+//! Speaks the `GraphService` from `proto/vagent_graph.proto`. TLS is used for
+//! `https://` endpoints, optionally with a private CA and a client
+//! certificate.
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::time::{Duration, Instant};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tracing::{debug, info};
+
+use super::{GraphStream, GraphTransport};
+use crate::redis_client::{GraphMessage, GraphMessageType, GraphRequest, RequestKind};
+
+pub mod proto {
+    tonic::include_proto!("vagent.graph.v1");
+}
+
+use proto::graph_service_client::GraphServiceClient;
+
+/// Give up connecting after this long
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Deadline for Cancel and Ping calls
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the graph listens and how to authenticate to it
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    /// e.g. `https://vagent-graph:50051`
+    pub endpoint: String,
+    /// PEM file of the CA that signed the server certificate (None = system roots)
+    pub ca_cert: Option<String>,
+    /// PEM files of a client certificate and key, for mutual TLS
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    /// Name to verify the server certificate against (None = endpoint host)
+    pub tls_domain: Option<String>,
+}
+
+impl GrpcConfig {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        let config = Self {
+            endpoint: var("GRAPH_GRPC_ENDPOINT")
+                .context("GRAPH_TRANSPORT=grpc needs GRAPH_GRPC_ENDPOINT")?,
+            ca_cert: var("GRAPH_GRPC_CA_CERT"),
+            client_cert: var("GRAPH_GRPC_CLIENT_CERT"),
+            client_key: var("GRAPH_GRPC_CLIENT_KEY"),
+            tls_domain: var("GRAPH_GRPC_TLS_DOMAIN"),
+        };
+        if config.client_cert.is_some() != config.client_key.is_some() {
+            bail!("GRAPH_GRPC_CLIENT_CERT and GRAPH_GRPC_CLIENT_KEY must be set together");
+        }
+        Ok(config)
+    }
+
+    fn uses_tls(&self) -> bool {
+        self.endpoint.starts_with("https://")
+            || self.ca_cert.is_some()
+            || self.client_cert.is_some()
+    }
+
+    fn tls(&self) -> Result<ClientTlsConfig> {
+        let read =
+            |path: &str| std::fs::read(path).with_context(|| format!("Failed to read {}", path));
+
+        let mut tls = ClientTlsConfig::new();
+        tls = match &self.ca_cert {
+            Some(path) => tls.ca_certificate(Certificate::from_pem(read(path)?)),
+            None => tls.with_native_roots(),
+        };
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            tls = tls.identity(Identity::from_pem(read(cert)?, read(key)?));
+        }
+        if let Some(domain) = &self.tls_domain {
+            tls = tls.domain_name(domain.clone());
+        }
+        Ok(tls)
+    }
+}
+
+/// gRPC connection to vagent-graph
+pub struct GrpcGraphClient {
+    client: GraphServiceClient<Channel>,
+}
+
+impl GrpcGraphClient {
+    pub async fn connect(config: &GrpcConfig) -> Result<Self> {
+        info!(
+            "Connecting to vagent-graph over gRPC at {}",
+            config.endpoint
+        );
+
+        let mut endpoint = Endpoint::from_shared(config.endpoint.clone())
+            .context("Invalid GRAPH_GRPC_ENDPOINT")?
+            .connect_timeout(CONNECT_TIMEOUT);
+        if config.uses_tls() {
+            endpoint = endpoint
+                .tls_config(config.tls()?)
+                .context("Invalid gRPC TLS settings")?;
+        }
+        let channel = endpoint
+            .connect()
+            .await
+            .with_context(|| format!("Failed to connect to {}", config.endpoint))?;
+
+        Ok(Self {
+            client: GraphServiceClient::new(channel),
+        })
+    }
+}
+
+#[async_trait]
+impl GraphTransport for GrpcGraphClient {
+    fn name(&self) -> &'static str {
+        "grpc"
+    }
+
+    async fn submit(&mut self, request: &GraphRequest, deadline: Duration) -> Result<GraphStream> {
+        debug!(
+            "Sending {:?} request {} over gRPC",
+            request.kind, request.request_id
+        );
+
+        // Sent as grpc-timeout, so the graph stops working when we stop waiting
+        let mut call = tonic::Request::new(proto::QueryRequest::try_from(request)?);
+        call.set_timeout(deadline);
+        let stream = self
+            .client
+            .submit_query(call)
+            .await
+            .context("SubmitQuery failed")?
+            .into_inner();
+
+        Ok(stream
+            .map(|message| {
+                let message = message.context("gRPC response stream failed")?;
+                Ok(GraphMessage::from(message))
+            })
+            .boxed())
+    }
+
+    async fn cancel(&mut self, request: &GraphRequest) -> Result<()> {
+        let mut call = tonic::Request::new(proto::CancelRequest {
+            request_id: request.request_id.clone(),
+            room_id: request.metadata.room_id.clone(),
+            user_id: request.metadata.user_id.clone(),
+        });
+        call.set_timeout(CALL_TIMEOUT);
+        self.client.cancel(call).await.context("Cancel failed")?;
+        debug!("Cancelled request {} over gRPC", request.request_id);
+        Ok(())
+    }
+
+    async fn ping(&mut self) -> Result<Duration> {
+        let started = Instant::now();
+        let mut call = tonic::Request::new(proto::PingRequest {});
+        call.set_timeout(CALL_TIMEOUT);
+        self.client.ping(call).await.context("gRPC Ping failed")?;
+        Ok(started.elapsed())
+    }
+}
+
+impl TryFrom<&GraphRequest> for proto::QueryRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(request: &GraphRequest) -> Result<Self> {
+        let kind = match request.kind {
+            RequestKind::Query => proto::RequestKind::Query,
+            RequestKind::HitlResponse => proto::RequestKind::HitlResponse,
+            RequestKind::HitlCancel | RequestKind::Cancel => {
+                bail!("Cancellations go through the Cancel RPC")
+            }
+        };
+        let metadata = &request.metadata;
+
+        Ok(Self {
+            request_id: request.request_id.clone(),
+            kind: kind.into(),
+            query: request.query.clone(),
+            agent: request.agent.clone(),
+            command: request.command.clone(),
+            payload_json: request.payload.as_ref().map(|payload| payload.to_string()),
+            bot_capabilities_json: request
+                .bot_capabilities
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+            context: request
+                .context
+                .iter()
+                .map(|message| proto::ContextMessage {
+                    event_id: message.event_id.clone(),
+                    sender: message.sender.clone(),
+                    body: message.body.clone(),
+                    timestamp_ms: message.timestamp_ms,
+                })
+                .collect(),
+            metadata: Some(proto::RequestMetadata {
+                room_id: metadata.room_id.clone(),
+                user_id: metadata.user_id.clone(),
+                timestamp: metadata.timestamp,
+                system_prompt: metadata.system_prompt.clone(),
+                context_trim: metadata
+                    .context_trim
+                    .as_ref()
+                    .map(|trim| proto::ContextTrim {
+                        messages_dropped: trim.messages_dropped as u64,
+                        messages_truncated: trim.messages_truncated as u64,
+                        chars_truncated: trim.chars_truncated as u64,
+                    }),
+                tenant_id: metadata.tenant_id.clone(),
+                thread_id: metadata.thread_id.clone(),
+            }),
+        })
+    }
+}
+
+impl From<proto::GraphMessage> for GraphMessage {
+    fn from(message: proto::GraphMessage) -> Self {
+        let message_type = match message.message_type() {
+            proto::MessageType::Progress => GraphMessageType::Progress,
+            proto::MessageType::FinalResponse => GraphMessageType::FinalResponse,
+            proto::MessageType::HitlRequest => GraphMessageType::HitlRequest,
+            proto::MessageType::Error => GraphMessageType::Error,
+        };
+        // Metadata that isn't valid JSON is dropped rather than failing the answer
+        let metadata = message
+            .metadata_json
+            .as_deref()
+            .and_then(|json| serde_json::from_str(json).ok());
+
+        Self {
+            request_id: message.request_id,
+            message_type,
+            content: message.content,
+            metadata,
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Scripted stand-in for vagent-graph (`GRAPH_TRANSPORT=mock`)
+//!
+//! Lets the bot run without Redis or a graph. Scenarios come from the JSON
+//! file in `GRAPH_MOCK_SCENARIOS` (see `fixtures/mock_graph/`); the first
+//! scenario whose `match` regex (and `kind` and `min_context`, if given) fits
+//! the request plays its steps. Requests no scenario matches are echoed back after
+//! `fallback_delay_ms`.
+//!
+//! ```json
+//! {"scenarios": [{"name": "invoice", "match": "(?i)invoice", "steps": [
+//!     {"type": "progress", "content": "🔍 Looking it up", "delay_ms": 300},
+//!     {"type": "final", "content": "Invoice {query} is paid", "delay_ms": 800}
+//! ]}]}
+//! ```
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::stream;
+use futures::StreamExt;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use super::{GraphStream, GraphTransport};
+use crate::redis_client::{GraphMessage, GraphMessageType, GraphRequest, RequestKind};
+
+/// What a step sends
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MockStepKind {
+    Progress,
+    Final,
+    Hitl,
+    Error,
+    /// Send nothing more, so the caller runs into its timeout
+    Hang,
+}
+
+/// One scripted message; `{query}` in the content is replaced by the query
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockStep {
+    #[serde(rename = "type")]
+    pub kind: MockStepKind,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    /// Pause before the message is sent
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawScenario {
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "match")]
+    pattern: Option<String>,
+    #[serde(default)]
+    kind: Option<RequestKind>,
+    /// Only requests with at least this many context messages
+    #[serde(default)]
+    min_context: usize,
+    steps: Vec<MockStep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawScript {
+    #[serde(default)]
+    scenarios: Vec<RawScenario>,
+    #[serde(default = "default_fallback_delay")]
+    fallback_delay_ms: u64,
+}
+
+fn default_fallback_delay() -> u64 {
+    500
+}
+
+#[derive(Debug)]
+struct Scenario {
+    name: String,
+    pattern: Option<Regex>,
+    kind: Option<RequestKind>,
+    min_context: usize,
+    steps: Vec<MockStep>,
+}
+
+impl Scenario {
+    fn matches(&self, request: &GraphRequest) -> bool {
+        self.kind.map_or(true, |kind| kind == request.kind)
+            && request.context.len() >= self.min_context
+            && self
+                .pattern
+                .as_ref()
+                .map_or(true, |pattern| pattern.is_match(&request.query))
+    }
+}
+
+/// A parsed scenario file
+#[derive(Debug)]
+pub struct MockScript {
+    scenarios: Vec<Scenario>,
+    fallback_delay: Duration,
+    /// Request IDs cancelled so far, oldest first
+    cancelled: Mutex<Vec<String>>,
+    /// Requests submitted so far, oldest first
+    submitted: Mutex<Vec<GraphRequest>>,
+}
+
+impl Default for MockScript {
+    /// No scenarios: every request is echoed
+    fn default() -> Self {
+        Self {
+            scenarios: Vec::new(),
+            fallback_delay: Duration::from_millis(default_fallback_delay()),
+            cancelled: Mutex::new(Vec::new()),
+            submitted: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl MockScript {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let raw: RawScript = serde_json::from_str(json).context("Invalid mock scenario file")?;
+        let scenarios = raw
+            .scenarios
+            .into_iter()
+            .map(|scenario| {
+                let pattern = scenario
+                    .pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .with_context(|| {
+                        format!("Invalid match pattern in scenario {:?}", scenario.name)
+                    })?;
+                Ok(Scenario {
+                    name: scenario.name,
+                    pattern,
+                    kind: scenario.kind,
+                    min_context: scenario.min_context,
+                    steps: scenario.steps,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            scenarios,
+            fallback_delay: Duration::from_millis(raw.fallback_delay_ms),
+            cancelled: Mutex::new(Vec::new()),
+            submitted: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Self::from_json(&json)
+    }
+
+    /// Scenario file from `GRAPH_MOCK_SCENARIOS`, or echo-only if unset
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("GRAPH_MOCK_SCENARIOS") {
+            Ok(path) if !path.trim().is_empty() => Self::load(Path::new(path.trim())),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// IDs of the requests the bot cancelled (HITL or running), oldest first
+    pub fn cancelled(&self) -> Vec<String> {
+        self.cancelled.lock().unwrap().clone()
+    }
+
+    /// The requests the bot submitted, as the graph received them, oldest first
+    pub fn submitted(&self) -> Vec<GraphRequest> {
+        self.submitted.lock().unwrap().clone()
+    }
+
+    /// Steps answering a request
+    fn steps_for(&self, request: &GraphRequest) -> (&str, Vec<MockStep>) {
+        if let Some(scenario) = self
+            .scenarios
+            .iter()
+            .find(|scenario| scenario.matches(request))
+        {
+            return (&scenario.name, scenario.steps.clone());
+        }
+        let echo = MockStep {
+            kind: MockStepKind::Final,
+            content: "Echo: {query}".to_string(),
+            metadata: None,
+            delay_ms: self.fallback_delay.as_millis() as u64,
+        };
+        ("echo", vec![echo])
+    }
+}
+
+/// In-process graph playing a `MockScript`
+///
+/// Also usable directly, e.g. to drive `VerjiAgentResponder` in tests via
+/// `TransportConfig::Mock`.
+pub struct MockGraphBackend {
+    script: Arc<MockScript>,
+}
+
+impl MockGraphBackend {
+    pub fn new(script: Arc<MockScript>) -> Self {
+        Self { script }
+    }
+}
+
+#[async_trait]
+impl GraphTransport for MockGraphBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn submit(&mut self, request: &GraphRequest, _deadline: Duration) -> Result<GraphStream> {
+        self.script.submitted.lock().unwrap().push(request.clone());
+        let (name, steps) = self.script.steps_for(request);
+        info!(
+            "🎭 Mock graph plays {:?} for request {}",
+            name, request.request_id
+        );
+
+        let request_id = request.request_id.clone();
+        let query = request.query.clone();
+        let messages = stream::iter(steps).then(move |step| {
+            let request_id = request_id.clone();
+            let content = step.content.replace("{query}", &query);
+            async move {
+                tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+                let message_type = match step.kind {
+                    MockStepKind::Progress => GraphMessageType::Progress,
+                    MockStepKind::Final => GraphMessageType::FinalResponse,
+                    MockStepKind::Hitl => GraphMessageType::HitlRequest,
+                    MockStepKind::Error => GraphMessageType::Error,
+                    MockStepKind::Hang => return None,
+                };
+                Some(Ok::<_, anyhow::Error>(GraphMessage {
+                    request_id,
+                    message_type,
+                    content,
+                    metadata: step.metadata,
+                }))
+            }
+        });
+
+        // A hang (or a script without a final step) never ends the stream
+        Ok(messages
+            .take_while(|message| futures::future::ready(message.is_some()))
+            .filter_map(futures::future::ready)
+            .chain(stream::pending())
+            .boxed())
+    }
+
+    async fn cancel(&mut self, request: &GraphRequest) -> Result<()> {
+        debug!("🎭 Mock graph cancelled request {}", request.request_id);
+        self.script
+            .cancelled
+            .lock()
+            .unwrap()
+            .push(request.request_id.clone());
+        Ok(())
+    }
+
+    async fn ping(&mut self) -> Result<Duration> {
+        Ok(Duration::ZERO)
+    }
+}
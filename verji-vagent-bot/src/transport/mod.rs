@@ -0,0 +1,139 @@
+//! How requests reach vagent-graph: Redis pub/sub (default), gRPC or NATS,
+//! or a scripted mock for development
+//!
+//! Selected with `GRAPH_TRANSPORT`. The gRPC and NATS clients are only
+//! compiled with the `grpc` and `nats` cargo features.
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod mock;
+#[cfg(feature = "nats")]
+pub mod nats;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::redis_client::{GraphMessage, GraphMessageType, GraphRequest, RedisGraphClient};
+
+/// Messages vagent-graph sends for one request: progress, then a final one
+pub type GraphStream = BoxStream<'static, Result<GraphMessage>>;
+
+/// A connection to vagent-graph
+#[async_trait]
+pub trait GraphTransport: Send {
+    /// Short name for logs and metric labels
+    fn name(&self) -> &'static str;
+
+    /// Send a request and return the stream of messages answering it
+    ///
+    /// `deadline` is how long the caller will wait; transports that can pass
+    /// it on (gRPC) let the graph give up at the same time.
+    async fn submit(&mut self, request: &GraphRequest, deadline: Duration) -> Result<GraphStream>;
+
+    /// Tell the graph to abandon a request (e.g. an expired HITL execution)
+    ///
+    /// Nothing is sent back.
+    async fn cancel(&mut self, request: &GraphRequest) -> Result<()>;
+
+    /// Round-trip time to the graph (or its broker)
+    async fn ping(&mut self) -> Result<Duration>;
+}
+
+/// vagent-graph sent no final message before the deadline
+#[derive(Debug, thiserror::Error)]
+#[error("Timeout waiting for response from vagent-graph")]
+pub struct GraphTimeout;
+
+/// Drain a response stream until the message that ends the request
+///
+/// Progress messages are skipped; gives up after `deadline`. Callers that
+/// show progress read the stream themselves (see `AgentService::ask`), which
+/// also means a slow reader slows the reading instead of queueing messages.
+pub async fn wait_for_final(mut stream: GraphStream, deadline: Duration) -> Result<GraphMessage> {
+    let deadline = Instant::now() + deadline;
+    loop {
+        let message = match tokio::time::timeout_at(deadline, stream.next()).await {
+            Ok(Some(message)) => message?,
+            Ok(None) => bail!("Response stream ended before the final message"),
+            Err(_) => return Err(GraphTimeout.into()),
+        };
+
+        match message.message_type {
+            GraphMessageType::Progress => debug!("📊 Progress: {}", message.content),
+            GraphMessageType::FinalResponse
+            | GraphMessageType::HitlRequest
+            | GraphMessageType::Error => {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+/// Which transport to use and where to find the graph
+#[derive(Debug, Clone)]
+pub enum TransportConfig {
+    Redis {
+        url: String,
+    },
+    #[cfg(feature = "grpc")]
+    Grpc(grpc::GrpcConfig),
+    #[cfg(feature = "nats")]
+    Nats(nats::NatsConfig),
+    Mock(Arc<mock::MockScript>),
+}
+
+impl TransportConfig {
+    /// Read `GRAPH_TRANSPORT` (`redis`, `grpc`, `nats` or `mock`) and the
+    /// matching settings
+    pub fn from_env() -> Result<Self> {
+        let transport = std::env::var("GRAPH_TRANSPORT").unwrap_or_else(|_| "redis".to_string());
+        match transport.trim().to_lowercase().as_str() {
+            "" | "redis" => Ok(Self::Redis {
+                url: std::env::var("REDIS_URL")
+                    .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            }),
+            #[cfg(feature = "grpc")]
+            "grpc" => Ok(Self::Grpc(grpc::GrpcConfig::from_env()?)),
+            #[cfg(not(feature = "grpc"))]
+            "grpc" => bail!("GRAPH_TRANSPORT=grpc needs a build with the `grpc` feature"),
+            #[cfg(feature = "nats")]
+            "nats" => Ok(Self::Nats(nats::NatsConfig::from_env()?)),
+            #[cfg(not(feature = "nats"))]
+            "nats" => bail!("GRAPH_TRANSPORT=nats needs a build with the `nats` feature"),
+            "mock" => Ok(Self::Mock(Arc::new(mock::MockScript::from_env()?))),
+            other => bail!(
+                "Unknown GRAPH_TRANSPORT {:?} (expected redis, grpc, nats or mock)",
+                other
+            ),
+        }
+    }
+
+    /// Open a connection
+    pub async fn connect(&self) -> Result<Box<dyn GraphTransport>> {
+        match self {
+            Self::Redis { url } => {
+                let client = RedisGraphClient::new(url)
+                    .await
+                    .context("Failed to connect to Redis")?;
+                Ok(Box::new(client))
+            }
+            #[cfg(feature = "grpc")]
+            Self::Grpc(config) => {
+                let client = grpc::GrpcGraphClient::connect(config).await?;
+                Ok(Box::new(client))
+            }
+            #[cfg(feature = "nats")]
+            Self::Nats(config) => {
+                let client = nats::NatsGraphClient::connect(config).await?;
+                Ok(Box::new(client))
+            }
+            Self::Mock(script) => Ok(Box::new(mock::MockGraphBackend::new(Arc::clone(script)))),
+        }
+    }
+}
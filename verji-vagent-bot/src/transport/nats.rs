@@ -0,0 +1,202 @@
+//! NATS client for vagent-graph (`GRAPH_TRANSPORT=nats`)
+//!
+//! Requests (the same JSON as over Redis) go to `<prefix>.requests`, through
+//! JetStream when `NATS_JETSTREAM` is on so a restarting graph picks them up
+//! later. Each request names its own response subject
+//! (`<prefix>.responses.<request_id>`) in the `Vagent-Response-Subject`
+//! header; the graph publishes progress and the final message there.
+
+use anyhow::{bail, Context, Result};
+use async_nats::jetstream::{self, stream::RetentionPolicy};
+use async_nats::{Client, ConnectOptions, Event, HeaderMap};
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use super::{GraphStream, GraphTransport};
+use crate::config;
+use crate::redis_client::{GraphMessage, GraphRequest};
+
+/// Header naming the subject responses go to
+const RESPONSE_SUBJECT_HEADER: &str = "Vagent-Response-Subject";
+/// Header carrying how long the bot waits, in milliseconds
+const DEADLINE_HEADER: &str = "Vagent-Deadline-Ms";
+
+/// Server, credentials and subjects
+#[derive(Debug, Clone)]
+pub struct NatsConfig {
+    pub url: String,
+    /// Subject prefix (`<prefix>.requests`, `<prefix>.responses.<id>`)
+    pub prefix: String,
+    /// Auth token (NATS_TOKEN) or nkey seed (NATS_NKEY); at most one
+    pub token: Option<String>,
+    pub nkey: Option<String>,
+    /// JetStream stream persisting requests (None = core NATS)
+    pub stream: Option<String>,
+}
+
+impl NatsConfig {
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        let config = Self {
+            url: var("NATS_URL").unwrap_or_else(|| "nats://localhost:4222".to_string()),
+            prefix: var("NATS_SUBJECT_PREFIX").unwrap_or_else(|| "vagent".to_string()),
+            token: var("NATS_TOKEN"),
+            nkey: var("NATS_NKEY"),
+            stream: config::env_bool("NATS_JETSTREAM", false)
+                .then(|| var("NATS_STREAM").unwrap_or_else(|| "VAGENT_REQUESTS".to_string())),
+        };
+        if config.token.is_some() && config.nkey.is_some() {
+            bail!("Set only one of NATS_TOKEN and NATS_NKEY");
+        }
+        Ok(config)
+    }
+
+    fn request_subject(&self) -> String {
+        format!("{}.requests", self.prefix)
+    }
+
+    fn response_subject(&self, request_id: &str) -> String {
+        format!("{}.responses.{}", self.prefix, request_id)
+    }
+}
+
+/// NATS connection to vagent-graph
+///
+/// The client reconnects by itself after connection loss; requests published
+/// meanwhile are buffered, and with JetStream kept until the graph reads them.
+pub struct NatsGraphClient {
+    client: Client,
+    jetstream: Option<jetstream::Context>,
+    config: NatsConfig,
+}
+
+impl NatsGraphClient {
+    pub async fn connect(config: &NatsConfig) -> Result<Self> {
+        info!("Connecting to NATS at {}", config.url);
+
+        let mut options = ConnectOptions::new()
+            .name("verji-vagent-bot")
+            .event_callback(|event| async move {
+                match event {
+                    Event::Connected => info!("✅ Connected to NATS"),
+                    Event::Disconnected => warn!("💔 Lost connection to NATS, reconnecting"),
+                    other => debug!("NATS event: {}", other),
+                }
+            });
+        if let Some(token) = &config.token {
+            options = options.token(token.clone());
+        }
+        if let Some(seed) = &config.nkey {
+            options = options.nkey(seed.clone());
+        }
+        let client = options
+            .connect(config.url.as_str())
+            .await
+            .with_context(|| format!("Failed to connect to NATS at {}", config.url))?;
+
+        let jetstream = match &config.stream {
+            Some(stream) => {
+                let context = jetstream::new(client.clone());
+                // Work queue: each request is kept until one graph worker acks it
+                context
+                    .get_or_create_stream(jetstream::stream::Config {
+                        name: stream.clone(),
+                        subjects: vec![config.request_subject()],
+                        retention: RetentionPolicy::WorkQueue,
+                        ..Default::default()
+                    })
+                    .await
+                    .with_context(|| format!("Failed to set up JetStream stream {}", stream))?;
+                info!("📦 Requests persist in JetStream stream {}", stream);
+                Some(context)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            client,
+            jetstream,
+            config: config.clone(),
+        })
+    }
+
+    async fn send(&self, request: &GraphRequest, headers: HeaderMap) -> Result<()> {
+        let payload = serde_json::to_vec(request).context("Failed to serialize request")?;
+        let subject = self.config.request_subject();
+
+        match &self.jetstream {
+            Some(jetstream) => {
+                jetstream
+                    .publish_with_headers(subject, headers, payload.into())
+                    .await
+                    .context("Failed to publish request to JetStream")?
+                    .await
+                    .context("JetStream did not acknowledge the request")?;
+            }
+            None => {
+                self.client
+                    .publish_with_headers(subject, headers, payload.into())
+                    .await
+                    .context("Failed to publish request to NATS")?;
+            }
+        }
+        debug!(
+            "Published {:?} request {}",
+            request.kind, request.request_id
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GraphTransport for NatsGraphClient {
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    async fn submit(&mut self, request: &GraphRequest, deadline: Duration) -> Result<GraphStream> {
+        // Subscribe BEFORE publishing so no response can be missed
+        let response_subject = self.config.response_subject(&request.request_id);
+        let subscriber = self
+            .client
+            .subscribe(response_subject.clone())
+            .await
+            .context("Failed to subscribe to the response subject")?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RESPONSE_SUBJECT_HEADER, response_subject.as_str());
+        headers.insert(DEADLINE_HEADER, deadline.as_millis().to_string().as_str());
+        self.send(request, headers).await?;
+
+        // Dropping the stream unsubscribes
+        Ok(subscriber
+            .filter_map(|message| {
+                let parsed = match serde_json::from_slice::<GraphMessage>(&message.payload) {
+                    Ok(message) => Some(Ok(message)),
+                    Err(e) => {
+                        warn!("Failed to parse response from NATS: {}", e);
+                        None
+                    }
+                };
+                futures::future::ready(parsed)
+            })
+            .boxed())
+    }
+
+    async fn cancel(&mut self, request: &GraphRequest) -> Result<()> {
+        self.send(request, HeaderMap::new()).await
+    }
+
+    /// Time for a PING/PONG with the server
+    async fn ping(&mut self) -> Result<Duration> {
+        let started = Instant::now();
+        self.client.flush().await.context("NATS PING failed")?;
+        Ok(started.elapsed())
+    }
+}
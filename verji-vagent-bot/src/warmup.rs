@@ -0,0 +1,122 @@
+//! Encryption warm-up of rooms after the initial sync
+//!
+//! The first encrypted send to a large room has to load the member list and
+//! query every member's devices before a room key can be shared, which the
+//! first user of the day waits for. With `WARMUP_ROOMS=all` (or `allowlist`,
+//! the rooms in `ALLOWED_ROOMS`) the bot loads the members of its encrypted
+//! rooms right after the initial sync, which makes the SDK track their devices
+//! and query their keys with the next sync. Rooms are taken one at a time
+//! `WARMUP_INTERVAL_MS` apart, and the pass stops after `WARMUP_BUDGET_SECS`
+//! so it never holds the bot back for long.
+//!
+//! matrix-sdk doesn't expose sharing the room key ahead of a send, so that
+//! part still happens on the first send.
+
+use matrix_sdk::{Client, RoomState};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::runtime::Runtime;
+
+/// Which rooms are warmed up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarmupRooms {
+    #[default]
+    Off,
+    /// Every joined encrypted room
+    All,
+    /// Joined encrypted rooms in `ALLOWED_ROOMS`
+    Allowlist,
+}
+
+impl WarmupRooms {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "off" | "none" => Some(WarmupRooms::Off),
+            "all" => Some(WarmupRooms::All),
+            "allowlist" => Some(WarmupRooms::Allowlist),
+            _ => None,
+        }
+    }
+}
+
+/// Load the members of the selected encrypted rooms, within `budget`
+pub async fn run(
+    runtime: &Runtime,
+    client: &Client,
+    rooms: WarmupRooms,
+    allowed_rooms: &[String],
+    interval: Duration,
+    budget: Duration,
+) {
+    if rooms == WarmupRooms::Off {
+        return;
+    }
+    let started = Instant::now();
+    let mut candidates = Vec::new();
+    for room in client.joined_rooms() {
+        if room.state() != RoomState::Joined {
+            continue;
+        }
+        if rooms == WarmupRooms::Allowlist
+            && !allowed_rooms
+                .iter()
+                .any(|entry| runtime.aliases.matches(entry, room.room_id().as_str()))
+        {
+            continue;
+        }
+        let encrypted = room
+            .latest_encryption_state()
+            .await
+            .is_ok_and(|state| state.is_encrypted());
+        if encrypted {
+            candidates.push(room);
+        }
+    }
+    info!("🔥 Warming up {} encrypted rooms", candidates.len());
+
+    let mut warmed = 0;
+    for (index, room) in candidates.iter().enumerate() {
+        if index > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        let remaining = budget.saturating_sub(started.elapsed());
+        if remaining.is_zero() {
+            warn!(
+                "🔥 Warm-up budget of {}s used up, {} rooms left for their first send",
+                budget.as_secs(),
+                candidates.len() - index
+            );
+            break;
+        }
+
+        let room_started = Instant::now();
+        match tokio::time::timeout(remaining, room.sync_members()).await {
+            Ok(Ok(())) => {
+                let elapsed = room_started.elapsed();
+                runtime
+                    .metrics
+                    .observe_ms("warmup_room_ms", &[], elapsed.as_millis() as u64);
+                info!(
+                    "🔥 Warmed up {} ({} members) in {}ms",
+                    room.room_id(),
+                    room.joined_members_count(),
+                    elapsed.as_millis()
+                );
+                warmed += 1;
+            }
+            Ok(Err(e)) => warn!("🔥 Failed to warm up {}: {}", room.room_id(), e),
+            Err(_) => warn!(
+                "🔥 Warm-up of {} ran past the budget, stopping",
+                room.room_id()
+            ),
+        }
+    }
+
+    info!(
+        "🔥 Warm-up done: {}/{} rooms in {}ms",
+        warmed,
+        candidates.len(),
+        started.elapsed().as_millis()
+    );
+}
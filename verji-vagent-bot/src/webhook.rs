@@ -0,0 +1,390 @@
+//! Inbound webhook: lets monitoring and CI post messages into rooms
+//!
+//! `POST /rooms/{room_id_or_alias}/message` with `Authorization: Bearer
+//! <token>` and a JSON body `{"text", "format": "markdown|plain", "msgtype":
+//! "notice|text"}`; answers `{"event_id"}` or `{"error", "message"}`.
+//!
+//! `GET /ready` needs no token and answers 503 while the store is unhealthy
+//! or maintenance mode is on (unless `MAINTENANCE_NOT_READY=false`), for
+//! orchestrator readiness probes. Its `encryption_setup` is `pending`
+//! while the bot already serves but is still setting up cross-signing and
+//! backups.
+//!
+//! `GET /api/rooms?limit=&cursor=` (same tokens) lists the bot's rooms with
+//! their last answer, queries, errors and send queue depth, paginated by room
+//! ID; see `room_status`. `GET /metrics` (same tokens) serves the metrics in
+//! the Prometheus text format.
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use matrix_sdk::{
+    ruma::{events::room::message::RoomMessageEventContent, RoomAliasId, RoomId},
+    Client, RoomState,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::alias::UnknownAlias;
+use crate::maintenance::MaintenanceMode;
+use crate::room::{BotRoom, RoomScope};
+use crate::room_status::{self, PageQuery, RoomsPage};
+use crate::runtime::Runtime;
+use crate::shadow::RoomWrite;
+use crate::stats::UsageStats;
+
+/// Webhook listener settings
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub addr: SocketAddr,
+    /// Bearer token -> caller name (used in logs and rate limiting)
+    pub tokens: HashMap<String, String>,
+    /// Rooms (IDs or aliases) webhooks may post to; empty = none
+    pub rooms: Vec<String>,
+    /// Messages per token per minute
+    pub rate_per_minute: usize,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Markdown,
+    Plain,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum MsgType {
+    #[default]
+    Notice,
+    Text,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostMessage {
+    text: String,
+    #[serde(default)]
+    format: Format,
+    #[serde(default)]
+    msgtype: MsgType,
+}
+
+/// Structured error answer
+struct WebhookError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl WebhookError {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let body = json!({ "error": self.code, "message": self.message });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+struct WebhookState {
+    runtime: Runtime,
+    client: Client,
+    config: WebhookConfig,
+    stats: Arc<UsageStats>,
+    /// Maintenance mode, when it should make the bot not ready
+    maintenance: Option<Arc<MaintenanceMode>>,
+    /// Caller name -> send times within the last minute
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl WebhookState {
+    /// Caller name for the request's bearer token
+    fn authenticate(&self, headers: &HeaderMap) -> Result<String, WebhookError> {
+        let token = headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        token
+            .and_then(|token| {
+                self.config
+                    .tokens
+                    .iter()
+                    .find(|(known, _)| constant_time_eq(known.as_bytes(), token.as_bytes()))
+            })
+            .map(|(_, name)| name.clone())
+            .ok_or_else(|| {
+                WebhookError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "unauthorized",
+                    "Missing or unknown bearer token",
+                )
+            })
+    }
+
+    /// Sliding one-minute window per caller
+    fn try_acquire(&self, caller: &str) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(60);
+        let mut recent = self.recent.lock().unwrap();
+        let times = recent.entry(caller.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= window)
+        {
+            times.pop_front();
+        }
+        if times.len() >= self.config.rate_per_minute {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn post_message(
+    State(state): State<Arc<WebhookState>>,
+    Path(target): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<PostMessage>,
+) -> Result<Json<serde_json::Value>, WebhookError> {
+    let caller = state.authenticate(&headers)?;
+    if !state.try_acquire(&caller) {
+        return Err(WebhookError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            "Too many messages, try again later",
+        ));
+    }
+    if body.text.trim().is_empty() {
+        return Err(WebhookError::new(
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+            "`text` is empty",
+        ));
+    }
+
+    let room_id = if target.starts_with('#') {
+        let alias = RoomAliasId::parse(&target).map_err(|e| {
+            WebhookError::new(StatusCode::BAD_REQUEST, "bad_request", e.to_string())
+        })?;
+        state
+            .runtime
+            .aliases
+            .resolver(&state.client)
+            .resolve(&alias)
+            .await
+            .map_err(|e| {
+                let status = if e.downcast_ref::<UnknownAlias>().is_some() {
+                    StatusCode::NOT_FOUND
+                } else {
+                    StatusCode::BAD_GATEWAY
+                };
+                WebhookError::new(status, "unknown_room", format!("{:#}", e))
+            })?
+    } else {
+        RoomId::parse(&target)
+            .map_err(|e| WebhookError::new(StatusCode::BAD_REQUEST, "bad_request", e.to_string()))?
+    };
+
+    let room = state
+        .client
+        .get_room(&room_id)
+        .map(|room| BotRoom::new(room, state.runtime.clone()))
+        .ok_or_else(|| {
+            WebhookError::new(
+                StatusCode::NOT_FOUND,
+                "unknown_room",
+                format!("The bot does not know {}", target),
+            )
+        })?;
+    if room.state() != RoomState::Joined {
+        return Err(WebhookError::new(
+            StatusCode::CONFLICT,
+            "not_joined",
+            format!("The bot is not joined to {}", target),
+        ));
+    }
+    // The allowlist may name the room by the alias the caller used
+    let allowed = state.config.rooms.contains(&target)
+        || RoomScope::Rooms(state.config.rooms.clone()).allows(&room, &state.runtime.aliases);
+    if !allowed {
+        warn!(
+            "🪝 Webhook {} tried to post to {} (not allowed)",
+            caller, target
+        );
+        return Err(WebhookError::new(
+            StatusCode::FORBIDDEN,
+            "room_not_allowed",
+            format!("Webhooks may not post to {}", target),
+        ));
+    }
+
+    let content = match (body.msgtype, body.format) {
+        (MsgType::Notice, Format::Markdown) => RoomMessageEventContent::notice_markdown(&body.text),
+        (MsgType::Notice, Format::Plain) => RoomMessageEventContent::notice_plain(&body.text),
+        (MsgType::Text, Format::Markdown) => RoomMessageEventContent::text_markdown(&body.text),
+        (MsgType::Text, Format::Plain) => RoomMessageEventContent::text_plain(&body.text),
+    };
+    let write = RoomWrite::new(room.room_id(), "message").body(&body.text);
+    if let Some(event_id) = state.runtime.shadow.suppress(write) {
+        info!(
+            "👻 Webhook {} post to {} recorded, not sent",
+            caller, room_id
+        );
+        return Ok(Json(json!({ "event_id": event_id.to_string() })));
+    }
+    let response = state
+        .runtime
+        .pacer
+        .send("message", || room.send(content.clone()))
+        .await
+        .map_err(|e| {
+            warn!("🪝 Webhook {} failed to send to {}: {}", caller, target, e);
+            WebhookError::new(StatusCode::BAD_GATEWAY, "send_failed", e.to_string())
+        })?;
+
+    info!("🪝 Webhook {} posted to {}", caller, room_id);
+    Ok(Json(json!({ "event_id": response.event_id.to_string() })))
+}
+
+/// Activity of the bot's rooms for dashboards, from cached state only
+async fn list_rooms(
+    State(state): State<Arc<WebhookState>>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Result<Json<RoomsPage>, WebhookError> {
+    state.authenticate(&headers)?;
+    let rooms = room_status::collect(&state.client, &state.stats, &state.runtime.send_queues);
+    Ok(Json(room_status::paginate(rooms, &query)))
+}
+
+/// Metrics in the Prometheus text format
+async fn metrics_text(
+    State(state): State<Arc<WebhookState>>,
+    headers: HeaderMap,
+) -> Result<Response, WebhookError> {
+    state.authenticate(&headers)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.runtime.metrics.render(),
+    )
+        .into_response())
+}
+
+/// Readiness probe: not ready while the store can't be written or the bot is
+/// in maintenance
+async fn ready(State(state): State<Arc<WebhookState>>) -> Response {
+    let encryption_setup = state.runtime.startup.encryption_setup().as_str();
+    let in_maintenance = state
+        .maintenance
+        .as_ref()
+        .is_some_and(|maintenance| maintenance.state().enabled);
+    let problem = state
+        .runtime
+        .store_health
+        .problem()
+        .or_else(|| in_maintenance.then(|| "maintenance mode is on".to_string()));
+    match problem {
+        None => {
+            Json(json!({ "ready": true, "encryption_setup": encryption_setup })).into_response()
+        }
+        Some(problem) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "ready": false,
+                "reason": problem,
+                "encryption_setup": encryption_setup,
+            })),
+        )
+            .into_response(),
+    }
+}
+
+/// A running listener; stop it with `shutdown`
+pub struct WebhookServer {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl WebhookServer {
+    /// Bind the listener and serve in the background; with `maintenance`,
+    /// `/ready` reports not ready while it is on
+    pub async fn start(
+        runtime: Runtime,
+        client: Client,
+        config: WebhookConfig,
+        stats: Arc<UsageStats>,
+        maintenance: Option<Arc<MaintenanceMode>>,
+    ) -> Result<Self> {
+        if config.tokens.is_empty() {
+            warn!("🪝 WEBHOOK_TOKENS is empty; every webhook request will be rejected");
+        }
+        if config.rooms.is_empty() {
+            warn!("🪝 WEBHOOK_ROOMS is empty; webhooks can't post anywhere");
+        }
+
+        let listener = tokio::net::TcpListener::bind(config.addr)
+            .await
+            .with_context(|| format!("Failed to bind webhook listener on {}", config.addr))?;
+        info!("🪝 Webhook listener on http://{}", config.addr);
+
+        let state = Arc::new(WebhookState {
+            runtime,
+            client,
+            config,
+            stats,
+            maintenance,
+            recent: Mutex::new(HashMap::new()),
+        });
+        let app = Router::new()
+            .route("/rooms/:room/message", post(post_message))
+            .route("/ready", get(ready))
+            .route("/api/rooms", get(list_rooms))
+            .route("/metrics", get(metrics_text))
+            .with_state(state);
+
+        let (stop, stopped) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            let result = axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await;
+            if let Err(e) = result {
+                warn!("🪝 Webhook listener failed: {}", e);
+            }
+        });
+        Ok(Self { stop, task })
+    }
+
+    /// Stop accepting requests and wait for in-flight ones to finish
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
@@ -0,0 +1,124 @@
+//! Whether a message is addressed to the bot: threads off the bot's messages
+//! count as implicit mentions, whether their root is still in the sent event
+//! registry or has to be fetched
+
+use matrix_sdk::ruma::EventId;
+use std::time::Duration;
+use verji_vagent_bot::addressing::{sent_by_bot, Addressing};
+use verji_vagent_bot::room_config::ReplyMode;
+use verji_vagent_bot::sent_events::{SentEventRegistry, SentKind};
+use verji_vagent_bot::testing::MockRoom;
+
+const BOT: &str = "@vagent:localhost";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Thread {
+    None,
+    /// Rooted in one of the bot's messages
+    OffBot,
+    /// Rooted in someone else's message
+    OffOther,
+}
+
+#[test]
+fn threads_off_the_bot_are_implicit_mentions() {
+    for thread in [Thread::None, Thread::OffBot, Thread::OffOther] {
+        for mentioned in [false, true] {
+            for mode in [ReplyMode::All, ReplyMode::Mentions] {
+                let addressing = Addressing {
+                    mentioned,
+                    in_bot_thread: thread == Thread::OffBot,
+                    ..Addressing::default()
+                };
+                let expected = match mode {
+                    ReplyMode::All => true,
+                    ReplyMode::Mentions => mentioned || thread == Thread::OffBot,
+                };
+                assert_eq!(
+                    addressing.should_process(mode),
+                    expected,
+                    "{:?} thread, mentioned {}, {:?}",
+                    thread,
+                    mentioned,
+                    mode
+                );
+                assert_eq!(
+                    addressing.is_mention(),
+                    mentioned || thread == Thread::OffBot
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn other_reasons_to_answer_in_mention_only_rooms() {
+    let cases = [
+        Addressing {
+            command: true,
+            ..Addressing::default()
+        },
+        Addressing {
+            direct_chat: true,
+            ..Addressing::default()
+        },
+        Addressing {
+            awaiting_answer: true,
+            ..Addressing::default()
+        },
+        Addressing {
+            reply_to_bot: true,
+            ..Addressing::default()
+        },
+    ];
+    for addressing in cases {
+        assert!(
+            addressing.should_process(ReplyMode::Mentions),
+            "{:?}",
+            addressing
+        );
+    }
+    assert!(!Addressing::default().should_process(ReplyMode::Mentions));
+}
+
+#[tokio::test]
+async fn thread_roots_are_looked_up_in_the_registry_first() {
+    let registry = SentEventRegistry::in_memory(10, Duration::from_secs(3600));
+    let root = EventId::parse("$answer:localhost").expect("event ID");
+    registry
+        .record(
+            root.clone(),
+            "!test:localhost",
+            SentKind::Final,
+            Some("req-1"),
+        )
+        .await;
+
+    // The room doesn't know the event; the registry is enough
+    let room = MockRoom::new("!test:localhost").expect("room");
+    assert!(sent_by_bot(&room, &registry, &root, BOT).await);
+}
+
+#[tokio::test]
+async fn roots_past_the_registry_are_fetched_and_their_sender_checked() {
+    let registry = SentEventRegistry::in_memory(10, Duration::from_secs(3600));
+    let room = MockRoom::new("!test:localhost")
+        .expect("room")
+        .with_history(&[(BOT, "An old answer"), ("@user:localhost", "A question")]);
+
+    let cases = [
+        ("$hist0:localhost", true),
+        ("$hist1:localhost", false),
+        // Unknown to the homeserver too
+        ("$gone:localhost", false),
+    ];
+    for (root, expected) in cases {
+        let root = EventId::parse(root).expect("event ID");
+        assert_eq!(
+            sent_by_bot(&room, &registry, &root, BOT).await,
+            expected,
+            "{}",
+            root
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! A slow reader of `AgentService::ask` slows the reading down instead of
+//! letting messages pile up, on a paused clock
+//!
+//! The mock graph only produces its next message when asked for it, taking
+//! `STEP` each time, so the gap between two events shows who set the pace.
+
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use verji_vagent_bot::agent_service::{AgentEvent, AgentService, AskOptions};
+use verji_vagent_bot::redis_client::{GraphRequest, RequestKind};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::{GraphTimeout, TransportConfig};
+
+const STEP: Duration = Duration::from_millis(20);
+
+const SCRIPT: &str = r#"{"fallback_delay_ms": 0, "scenarios": [
+    {"match": "", "steps": [
+        {"type": "progress", "content": "Step 1", "delay_ms": 20},
+        {"type": "progress", "content": "Step 2", "delay_ms": 20},
+        {"type": "progress", "content": "Step 3", "delay_ms": 20},
+        {"type": "progress", "content": "Step 4", "delay_ms": 20},
+        {"type": "final", "content": "Done", "delay_ms": 20}
+    ]}
+]}"#;
+
+fn service() -> AgentService {
+    let script = Arc::new(MockScript::from_json(SCRIPT).expect("script"));
+    AgentService::new(TransportConfig::Mock(script))
+}
+
+fn request() -> GraphRequest {
+    GraphRequest::new(
+        RequestKind::Query,
+        "req-1".to_string(),
+        "Go".to_string(),
+        "!room:localhost".to_string(),
+        "@user:localhost".to_string(),
+    )
+}
+
+/// Read every event, spending `per_event` on each; returns the events and
+/// how long after the previous one each arrived
+async fn read(timeout: Duration, per_event: Duration) -> Vec<(AgentEvent, Duration)> {
+    let service = service();
+    let mut events = service.ask(
+        request(),
+        AskOptions {
+            timeout: Some(timeout),
+        },
+    );
+    let mut read = Vec::new();
+    let mut last = Instant::now();
+    while let Some(event) = events.next().await {
+        read.push((event, last.elapsed()));
+        tokio::time::sleep(per_event).await;
+        last = Instant::now();
+    }
+    read
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_fast_reader_gets_messages_as_the_graph_sends_them() {
+    let events = read(Duration::from_secs(10), Duration::ZERO).await;
+    assert_eq!(events.len(), 6);
+    assert!(matches!(events[5].0, AgentEvent::Finished(_)));
+    for (event, gap) in &events[1..] {
+        assert_eq!(*gap, STEP, "{:?}", event);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_slow_reader_holds_the_graph_back() {
+    let busy = Duration::from_millis(100);
+    let events = read(Duration::from_secs(10), busy).await;
+    assert_eq!(events.len(), 6);
+    let AgentEvent::Finished(message) = &events[5].0 else {
+        panic!("{:?}", events[5].0);
+    };
+    assert_eq!(message.content, "Done");
+
+    // Nothing was read ahead while the reader was busy: each message still
+    // took its full step after the reader asked for it
+    for (event, gap) in &events[1..] {
+        assert_eq!(*gap, STEP, "{:?}", event);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_timeout_keeps_running_while_the_reader_is_busy() {
+    // The graph alone needs 100ms; the reader's pauses push it past 250ms
+    let events = read(Duration::from_millis(250), Duration::from_millis(100)).await;
+    let kinds: Vec<_> = events
+        .iter()
+        .map(|(event, _)| match event {
+            AgentEvent::Submitted => "submitted",
+            AgentEvent::Progress(_) => "progress",
+            AgentEvent::Finished(_) => "finished",
+            AgentEvent::Failed(_) => "failed",
+        })
+        .collect();
+    assert_eq!(kinds, ["submitted", "progress", "progress", "failed"]);
+    let AgentEvent::Failed(error) = &events[3].0 else {
+        unreachable!();
+    };
+    assert!(
+        error
+            .chain()
+            .any(|cause| cause.downcast_ref::<GraphTimeout>().is_some()),
+        "{:#}",
+        error
+    );
+}
@@ -0,0 +1,97 @@
+//! Which reply each way of not getting an answer from the agent gets
+
+use anyhow::anyhow;
+use verji_vagent_bot::agent_service::CircuitOpen;
+use verji_vagent_bot::error::BotError;
+use verji_vagent_bot::i18n::Catalog;
+use verji_vagent_bot::responders::verji_agent::AgentFailure;
+use verji_vagent_bot::testing::ResponderTestHarness;
+use verji_vagent_bot::transport::GraphTimeout;
+
+#[test]
+fn connect_errors_are_unavailable_unless_the_circuit_is_open() {
+    let open = anyhow::Error::new(CircuitOpen).context("Failed to connect");
+    assert_eq!(AgentFailure::of_connect(&open), AgentFailure::CircuitOpen);
+
+    let refused = anyhow!("Connection refused");
+    assert_eq!(
+        AgentFailure::of_connect(&refused),
+        AgentFailure::Unavailable
+    );
+}
+
+#[test]
+fn request_errors_are_classified_by_cause() {
+    let cases = [
+        (anyhow::Error::new(CircuitOpen), AgentFailure::CircuitOpen),
+        (
+            anyhow::Error::new(GraphTimeout).context("Waiting for the answer"),
+            AgentFailure::Timeout,
+        ),
+        (
+            anyhow::Error::new(BotError::GraphUnavailable),
+            AgentFailure::Unavailable,
+        ),
+        (
+            anyhow::Error::new(BotError::NetworkTransient),
+            AgentFailure::Unavailable,
+        ),
+        (
+            anyhow!("Unexpected message from the graph"),
+            AgentFailure::Failed,
+        ),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(AgentFailure::of_request(&error), expected, "{:#}", error);
+    }
+}
+
+#[test]
+fn every_failure_has_its_own_message() {
+    let cases = [
+        (AgentFailure::Unavailable, "agent.unavailable"),
+        (AgentFailure::CircuitOpen, "agent.circuit_open"),
+        (AgentFailure::Timeout, "agent.timeout"),
+        (AgentFailure::Failed, "agent.failed"),
+        (AgentFailure::GraphError, "agent.graph_error"),
+    ];
+    for (failure, id) in cases {
+        assert_eq!(failure.message_id(), id);
+        // The catalog falls back to the ID for unknown messages
+        assert_ne!(Catalog::embedded().translate("en", id, &[]), id);
+    }
+}
+
+#[tokio::test]
+async fn replies_quote_the_message_and_reference_on_request() {
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| {
+            config.locale = "en".to_string();
+            config.error_echo_message = true;
+            config.error_show_reference = true;
+        });
+    let context = harness.context("What's new?").await.expect("context");
+
+    let reply = AgentFailure::Timeout.reply(&context, "req-1");
+
+    assert_eq!(
+        reply,
+        "⏱️ The AI assistant took too long to answer. Please try again.\n\
+         You said: What's new?\n\
+         Reference: `req-1`"
+    );
+}
+
+#[tokio::test]
+async fn replies_are_just_the_message_by_default() {
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string());
+    let context = harness.context("What's new?").await.expect("context");
+
+    assert_eq!(
+        AgentFailure::GraphError.reply(&context, "req-1"),
+        "⚠️ The AI assistant couldn't answer this question."
+    );
+}
@@ -0,0 +1,175 @@
+//! The event stream of `AgentService::ask` against the mock graph
+//!
+//! "slow" questions get one progress update and then never an answer;
+//! everything else is echoed straight away.
+
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::agent_service::{AgentEvent, AgentService, AskOptions};
+use verji_vagent_bot::redis_client::{GraphMessageType, GraphRequest, RequestKind};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::{GraphTimeout, TransportConfig};
+
+const SCRIPT: &str = r#"{"fallback_delay_ms": 0, "scenarios": [
+    {"match": "slow", "steps": [
+        {"type": "progress", "content": "Looking into it"},
+        {"type": "hang"}
+    ]},
+    {"match": "steps", "steps": [
+        {"type": "progress", "content": "Step 1"},
+        {"type": "progress", "content": "Step 2"},
+        {"type": "final", "content": "Done with {query}"}
+    ]},
+    {"match": "broken", "steps": [{"type": "error", "content": "Tool failed"}]}
+]}"#;
+
+fn service() -> (Arc<MockScript>, AgentService) {
+    let script = Arc::new(MockScript::from_json(SCRIPT).expect("script"));
+    let service = AgentService::new(TransportConfig::Mock(Arc::clone(&script)));
+    (script, service)
+}
+
+fn request(id: &str, query: &str) -> GraphRequest {
+    GraphRequest::new(
+        RequestKind::Query,
+        id.to_string(),
+        query.to_string(),
+        "!room:localhost".to_string(),
+        "@user:localhost".to_string(),
+    )
+}
+
+fn within(timeout: Duration) -> AskOptions {
+    AskOptions {
+        timeout: Some(timeout),
+    }
+}
+
+/// Every event of an ask, in order
+async fn events(
+    service: &AgentService,
+    request: GraphRequest,
+    timeout: Duration,
+) -> Vec<AgentEvent> {
+    service.ask(request, within(timeout)).collect().await
+}
+
+#[tokio::test]
+async fn progress_comes_before_the_final_answer() {
+    let (_, service) = service();
+    let events = events(
+        &service,
+        request("r1", "three steps"),
+        Duration::from_secs(5),
+    )
+    .await;
+
+    assert_eq!(events.len(), 4, "{:?}", events);
+    assert!(matches!(events[0], AgentEvent::Submitted));
+    match (&events[1], &events[2]) {
+        (AgentEvent::Progress(first), AgentEvent::Progress(second)) => {
+            assert_eq!(first.content, "Step 1");
+            assert_eq!(second.content, "Step 2");
+        }
+        other => panic!("expected two progress updates, got {:?}", other),
+    }
+    match &events[3] {
+        AgentEvent::Finished(message) => {
+            assert_eq!(message.message_type, GraphMessageType::FinalResponse);
+            assert_eq!(message.content, "Done with three steps");
+            assert_eq!(message.request_id, "r1");
+        }
+        other => panic!("expected the answer, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn graph_errors_finish_the_request() {
+    let (_, service) = service();
+    let events = events(&service, request("r1", "broken"), Duration::from_secs(5)).await;
+
+    match events.last() {
+        Some(AgentEvent::Finished(message)) => {
+            assert_eq!(message.message_type, GraphMessageType::Error);
+            assert_eq!(message.content, "Tool failed");
+        }
+        other => panic!("expected the graph error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn a_request_without_an_answer_fails_with_a_timeout() {
+    let (_, service) = service();
+    let events = events(&service, request("r1", "slow"), Duration::from_millis(200)).await;
+
+    assert_eq!(events.len(), 3, "{:?}", events);
+    assert!(matches!(events[1], AgentEvent::Progress(_)));
+    match &events[2] {
+        AgentEvent::Failed(e) => assert!(
+            e.chain()
+                .any(|cause| cause.downcast_ref::<GraphTimeout>().is_some()),
+            "{:#}",
+            e
+        ),
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn concurrent_asks_do_not_wait_for_each_other() {
+    let (script, service) = service();
+
+    // The slow request is submitted and stays in flight
+    let mut slow = service.ask(request("slow-1", "slow"), within(Duration::from_secs(30)));
+    assert!(matches!(slow.next().await, Some(AgentEvent::Submitted)));
+    assert!(matches!(slow.next().await, Some(AgentEvent::Progress(_))));
+
+    // Another request is answered meanwhile
+    let quick = tokio::time::timeout(
+        Duration::from_secs(2),
+        events(
+            &service,
+            request("quick-1", "hello"),
+            Duration::from_secs(30),
+        ),
+    )
+    .await
+    .expect("the second ask waited for the first");
+    match quick.last() {
+        Some(AgentEvent::Finished(message)) => assert_eq!(message.content, "Echo: hello"),
+        other => panic!("expected the echo, got {:?}", other),
+    }
+
+    // And so is cancelling the slow one
+    tokio::time::timeout(
+        Duration::from_secs(2),
+        service.cancel(&request("slow-1", "slow")),
+    )
+    .await
+    .expect("the cancel waited for the request in flight")
+    .expect("cancel");
+    assert_eq!(script.cancelled(), vec!["slow-1".to_string()]);
+}
+
+#[tokio::test]
+async fn two_slow_asks_stream_side_by_side() {
+    let (_, service) = service();
+    let mut first = service.ask(request("slow-1", "slow"), within(Duration::from_secs(30)));
+    let mut second = service.ask(request("slow-2", "slow"), within(Duration::from_secs(30)));
+
+    let both = async {
+        for stream in [&mut first, &mut second] {
+            assert!(matches!(stream.next().await, Some(AgentEvent::Submitted)));
+            match stream.next().await {
+                Some(AgentEvent::Progress(message)) => {
+                    assert_eq!(message.content, "Looking into it")
+                }
+                other => panic!("expected progress, got {:?}", other),
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(2), both)
+        .await
+        .expect("the second request waited for the first to finish");
+}
@@ -0,0 +1,95 @@
+//! A graph that never answers gets the agent's own timeout reply
+//!
+//! The responder timeout in the manager is only a backstop; the agent's
+//! reply (with its retry hint and journal entry) must come first. The agent
+//! timeout is one second for every test in this file.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::i18n::CannedReply;
+use verji_vagent_bot::responder::Responder;
+use verji_vagent_bot::responder_manager::{ResponderManager, TimeoutPolicy};
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const SCRIPT: &str = r#"{"scenarios": [{"match": "slow", "steps": [
+    {"type": "progress", "content": "Looking into it"},
+    {"type": "hang"}
+]}]}"#;
+
+const AGENT_TIMEOUT: &str = "The AI assistant took too long to answer";
+const RESPONDER_TIMEOUT: &str = "Sorry, that took too long";
+
+#[tokio::test]
+async fn a_slow_graph_gets_the_agent_timeout_reply() {
+    std::env::set_var("AGENT_TIMEOUT_SECS", "1");
+    let script = Arc::new(MockScript::from_json(SCRIPT).expect("script"));
+    let agent = Arc::new(VerjiAgentResponder::with_transport(TransportConfig::Mock(
+        script,
+    )));
+    let backstop = agent.timeout().expect("the agent has a responder timeout");
+    assert!(
+        backstop > agent.service().timeout(),
+        "responder timeout {:?} doesn't outlast the graph wait",
+        backstop
+    );
+
+    let mut manager = ResponderManager::new();
+    manager.set_timeout_policy(TimeoutPolicy::Continue, CannedReply::Default);
+    manager.register(agent);
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string());
+
+    let messages = tokio::time::timeout(
+        Duration::from_secs(10),
+        harness.dispatch(&manager, "A slow question"),
+    )
+    .await
+    .expect("the agent never replied")
+    .expect("dispatch");
+
+    let replies: Vec<&str> = messages.iter().filter_map(message_text).collect();
+    assert_eq!(replies.len(), 1, "{:?}", messages);
+    assert!(replies[0].contains(AGENT_TIMEOUT), "{}", replies[0]);
+    assert!(!replies[0].contains(RESPONDER_TIMEOUT), "{}", replies[0]);
+}
+
+#[tokio::test]
+async fn progress_then_silence_times_out_after_the_progress_was_shown() {
+    std::env::set_var("AGENT_TIMEOUT_SECS", "1");
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/mock_graph/timeout.json");
+    let script = Arc::new(MockScript::load(&path).expect("fixture"));
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::clone(&script)),
+    )));
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!stuck:localhost").expect("room"))
+        .configure(|config| config.locale = "en".to_string());
+
+    let messages = tokio::time::timeout(
+        Duration::from_secs(10),
+        harness.dispatch(&manager, "Anything at all"),
+    )
+    .await
+    .expect("the agent never replied")
+    .expect("dispatch");
+    let replies: Vec<&str> = messages.iter().filter_map(message_text).collect();
+    assert_eq!(replies.len(), 1, "{:?}", messages);
+    assert!(replies[0].contains(AGENT_TIMEOUT), "{}", replies[0]);
+
+    // The first update came in time; the second was due after the timeout
+    let sent = harness.mock_room().sent();
+    let progress: Vec<_> = sent
+        .iter()
+        .filter_map(message_text)
+        .filter(|text| !text.contains(AGENT_TIMEOUT))
+        .collect();
+    assert_eq!(progress, ["🔍 Looking into it"]);
+    assert_eq!(script.submitted().len(), 1);
+}
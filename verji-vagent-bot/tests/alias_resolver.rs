@@ -0,0 +1,195 @@
+//! Caching in `AliasResolver`, against a stub instead of the homeserver
+
+use anyhow::anyhow;
+use matrix_sdk::ruma::{room_alias_id, room_id, OwnedRoomId, RoomAliasId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use verji_vagent_bot::alias::{AliasResolver, UnknownAlias};
+use verji_vagent_bot::testing::ManualClock;
+
+const TTL: Duration = Duration::from_secs(3600);
+const NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+/// What the stub homeserver knows, and how often it was asked
+#[derive(Default)]
+struct Directory {
+    aliases: Mutex<HashMap<String, OwnedRoomId>>,
+    lookups: AtomicUsize,
+    down: AtomicBool,
+}
+
+impl Directory {
+    fn set(&self, alias: &RoomAliasId, room_id: Option<OwnedRoomId>) {
+        let mut aliases = self.aliases.lock().unwrap();
+        match room_id {
+            Some(room_id) => aliases.insert(alias.to_string(), room_id),
+            None => aliases.remove(alias.as_str()),
+        };
+    }
+
+    fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::SeqCst)
+    }
+}
+
+fn resolver(directory: &Arc<Directory>, clock: &Arc<ManualClock>) -> Arc<AliasResolver> {
+    let stub = Arc::clone(directory);
+    let resolver = AliasResolver::new(
+        move |alias| {
+            let directory = Arc::clone(&stub);
+            async move {
+                directory.lookups.fetch_add(1, Ordering::SeqCst);
+                if directory.down.load(Ordering::SeqCst) {
+                    return Err(anyhow!("homeserver unreachable"));
+                }
+                Ok(directory
+                    .aliases
+                    .lock()
+                    .unwrap()
+                    .get(alias.as_str())
+                    .cloned())
+            }
+        },
+        TTL,
+        NEGATIVE_TTL,
+    )
+    .with_clock(Arc::clone(clock) as _);
+    Arc::new(resolver)
+}
+
+fn setup() -> (Arc<Directory>, Arc<ManualClock>, Arc<AliasResolver>) {
+    let directory = Arc::new(Directory::default());
+    directory.set(
+        room_alias_id!("#support:example.org"),
+        Some(room_id!("!support:example.org").to_owned()),
+    );
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let resolver = resolver(&directory, &clock);
+    (directory, clock, resolver)
+}
+
+#[tokio::test]
+async fn repeated_lookups_hit_the_cache() {
+    let (directory, _clock, resolver) = setup();
+    let alias = room_alias_id!("#support:example.org");
+
+    for _ in 0..3 {
+        assert_eq!(
+            resolver.resolve(alias).await.unwrap().as_str(),
+            "!support:example.org"
+        );
+    }
+    assert_eq!(directory.lookups(), 1);
+    assert_eq!(
+        resolver.cached(alias.as_str()).as_deref(),
+        Some(room_id!("!support:example.org"))
+    );
+    assert!(resolver.matches("#support:example.org", "!support:example.org"));
+    assert!(!resolver.matches("#support:example.org", "!other:example.org"));
+}
+
+#[tokio::test]
+async fn entries_expire_after_the_ttl() {
+    let (directory, clock, resolver) = setup();
+    let alias = room_alias_id!("#support:example.org");
+    resolver.resolve(alias).await.unwrap();
+
+    directory.set(alias, Some(room_id!("!moved:example.org").to_owned()));
+    clock.advance(TTL - Duration::from_secs(1));
+    assert_eq!(
+        resolver.resolve(alias).await.unwrap().as_str(),
+        "!support:example.org"
+    );
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        resolver.resolve(alias).await.unwrap().as_str(),
+        "!moved:example.org"
+    );
+    assert_eq!(directory.lookups(), 2);
+}
+
+#[tokio::test]
+async fn unknown_aliases_are_cached_for_the_negative_ttl() {
+    let (directory, clock, resolver) = setup();
+    let alias = room_alias_id!("#missing:example.org");
+
+    for _ in 0..2 {
+        let error = resolver.resolve(alias).await.unwrap_err();
+        assert!(
+            error.downcast_ref::<UnknownAlias>().is_some(),
+            "{:#}",
+            error
+        );
+    }
+    assert_eq!(directory.lookups(), 1);
+    assert_eq!(resolver.cached(alias.as_str()), None);
+
+    directory.set(alias, Some(room_id!("!new:example.org").to_owned()));
+    clock.advance(NEGATIVE_TTL);
+    assert_eq!(
+        resolver.resolve(alias).await.unwrap().as_str(),
+        "!new:example.org"
+    );
+    assert_eq!(directory.lookups(), 2);
+}
+
+#[tokio::test]
+async fn failed_lookups_are_not_cached() {
+    let (directory, _clock, resolver) = setup();
+    let alias = room_alias_id!("#support:example.org");
+
+    directory.down.store(true, Ordering::SeqCst);
+    let error = resolver.resolve(alias).await.unwrap_err();
+    assert!(
+        error.downcast_ref::<UnknownAlias>().is_none(),
+        "{:#}",
+        error
+    );
+    directory.down.store(false, Ordering::SeqCst);
+    assert_eq!(
+        resolver.resolve(alias).await.unwrap().as_str(),
+        "!support:example.org"
+    );
+    assert_eq!(directory.lookups(), 2);
+}
+
+#[tokio::test]
+async fn alias_changes_resolve_the_room_again() {
+    let (directory, _clock, resolver) = setup();
+    let alias = room_alias_id!("#support:example.org");
+    resolver.resolve(alias).await.unwrap();
+
+    directory.set(alias, Some(room_id!("!moved:example.org").to_owned()));
+    resolver
+        .on_aliases_changed(room_id!("!support:example.org"), &[])
+        .await;
+    assert!(resolver.matches("#support:example.org", "!moved:example.org"));
+    assert_eq!(directory.lookups(), 2);
+}
+
+#[tokio::test]
+async fn batch_resolution_reports_every_failure() {
+    let (directory, _clock, resolver) = setup();
+    let rooms = [
+        "!plain:example.org",
+        "#support:example.org",
+        "#missing:example.org",
+        "#gone:example.org",
+        "#support:example.org",
+    ];
+
+    let error = resolver.resolve_all(rooms).await.unwrap_err().to_string();
+    assert!(error.contains("#missing:example.org"), "{}", error);
+    assert!(error.contains("#gone:example.org"), "{}", error);
+    assert!(!error.contains("#support:example.org"), "{}", error);
+    // Each alias is asked for once; room IDs need no lookup
+    assert_eq!(directory.lookups(), 3);
+
+    assert!(resolver
+        .resolve_all(["!plain:example.org", "#support:example.org"])
+        .await
+        .is_ok());
+    assert_eq!(directory.lookups(), 3);
+}
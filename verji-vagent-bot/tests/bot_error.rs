@@ -0,0 +1,325 @@
+//! Each mapping of the error taxonomy, over the typed errors it inspects
+//!
+//! Exit codes are covered in `exit_codes.rs`. Reqwest errors (mapped to
+//! `NetworkTransient`) can't be built outside reqwest and are left out.
+
+use anyhow::anyhow;
+use matrix_sdk::encryption::{CryptoStoreError, OlmError};
+use matrix_sdk::ruma::api::client::error::{ErrorBody, ErrorKind, RetryAfter};
+use matrix_sdk::ruma::api::client::Error as ClientApiError;
+use matrix_sdk::ruma::api::error::FromHttpResponseError;
+use matrix_sdk::ruma::exports::http::StatusCode;
+use matrix_sdk::ruma::{OwnedDeviceId, UserId};
+use matrix_sdk::{HttpError, RumaApiError};
+use std::io;
+use std::time::{Duration, SystemTime};
+use verji_vagent_bot::agent_service::CircuitOpen;
+use verji_vagent_bot::error::BotError;
+use verji_vagent_bot::transport::GraphTimeout;
+
+const ALL: [BotError; 8] = [
+    BotError::Config,
+    BotError::StoreCorrupt,
+    BotError::CryptoStoreMismatch,
+    BotError::AuthFailed,
+    BotError::RateLimited { retry_after: None },
+    BotError::NetworkTransient,
+    BotError::GraphTimeout,
+    BotError::GraphUnavailable,
+];
+
+fn http_error(status: StatusCode, kind: ErrorKind) -> HttpError {
+    let error = ClientApiError::new(
+        status,
+        ErrorBody::Standard {
+            kind,
+            message: "Homeserver error".to_string(),
+        },
+    );
+    HttpError::from(FromHttpResponseError::Server(RumaApiError::ClientApi(
+        error,
+    )))
+}
+
+fn mismatched_account() -> CryptoStoreError {
+    let user = UserId::parse("@vagent:localhost").expect("user ID");
+    CryptoStoreError::MismatchedAccount {
+        expected: (user.clone(), OwnedDeviceId::from("NEWDEVICE")),
+        got: (user, OwnedDeviceId::from("OLDDEVICE")),
+    }
+}
+
+fn redis_io(kind: io::ErrorKind) -> redis::RedisError {
+    redis::RedisError::from(io::Error::new(kind, "socket"))
+}
+
+#[test]
+fn matrix_error_codes() {
+    let cases = [
+        (ErrorKind::forbidden(), Some(BotError::AuthFailed)),
+        (
+            ErrorKind::UnknownToken { soft_logout: false },
+            Some(BotError::AuthFailed),
+        ),
+        (
+            ErrorKind::UnknownToken { soft_logout: true },
+            Some(BotError::AuthFailed),
+        ),
+        (ErrorKind::MissingToken, Some(BotError::AuthFailed)),
+        (ErrorKind::UserDeactivated, Some(BotError::AuthFailed)),
+        (
+            ErrorKind::LimitExceeded {
+                retry_after: Some(RetryAfter::Delay(Duration::from_secs(3))),
+            },
+            Some(BotError::RateLimited {
+                retry_after: Some(Duration::from_secs(3)),
+            }),
+        ),
+        (
+            ErrorKind::LimitExceeded { retry_after: None },
+            Some(BotError::RateLimited { retry_after: None }),
+        ),
+        // A retry time already past means now
+        (
+            ErrorKind::LimitExceeded {
+                retry_after: Some(RetryAfter::DateTime(SystemTime::UNIX_EPOCH)),
+            },
+            Some(BotError::RateLimited {
+                retry_after: Some(Duration::ZERO),
+            }),
+        ),
+        (ErrorKind::NotFound, None),
+        (ErrorKind::Unrecognized, None),
+    ];
+    for (kind, expected) in cases {
+        assert_eq!(BotError::from_error_kind(&kind), expected, "{:?}", kind);
+    }
+}
+
+#[test]
+fn rate_limits_at_a_future_time_wait_until_then() {
+    let at = SystemTime::now() + Duration::from_secs(60);
+    let kind = ErrorKind::LimitExceeded {
+        retry_after: Some(RetryAfter::DateTime(at)),
+    };
+    let Some(BotError::RateLimited {
+        retry_after: Some(wait),
+    }) = BotError::from_error_kind(&kind)
+    else {
+        panic!("not rate limited");
+    };
+    assert!(
+        wait > Duration::from_secs(55) && wait <= Duration::from_secs(60),
+        "{:?}",
+        wait
+    );
+}
+
+#[test]
+fn http_errors() {
+    let cases = [
+        (
+            http_error(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorKind::LimitExceeded { retry_after: None },
+            ),
+            Some(BotError::RateLimited { retry_after: None }),
+        ),
+        (
+            http_error(
+                StatusCode::UNAUTHORIZED,
+                ErrorKind::UnknownToken { soft_logout: false },
+            ),
+            Some(BotError::AuthFailed),
+        ),
+        // Server errors are worth retrying whatever their code
+        (
+            http_error(StatusCode::BAD_GATEWAY, ErrorKind::Unknown),
+            Some(BotError::NetworkTransient),
+        ),
+        (
+            http_error(StatusCode::SERVICE_UNAVAILABLE, ErrorKind::NotFound),
+            Some(BotError::NetworkTransient),
+        ),
+        (http_error(StatusCode::NOT_FOUND, ErrorKind::NotFound), None),
+        (
+            http_error(StatusCode::BAD_REQUEST, ErrorKind::Unknown),
+            None,
+        ),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(BotError::from_http(&error), expected, "{}", error);
+    }
+}
+
+#[test]
+fn crypto_store_errors() {
+    assert_eq!(
+        BotError::from_crypto_store(&mismatched_account()),
+        Some(BotError::CryptoStoreMismatch)
+    );
+    assert_eq!(
+        BotError::from_crypto_store(&CryptoStoreError::AccountUnset),
+        None
+    );
+    assert_eq!(
+        BotError::from_olm(&OlmError::Store(mismatched_account())),
+        Some(BotError::CryptoStoreMismatch)
+    );
+    assert_eq!(BotError::from_olm(&OlmError::MissingSession), None);
+}
+
+#[test]
+fn matrix_sdk_errors() {
+    let cases: [(matrix_sdk::Error, Option<BotError>); 5] = [
+        (
+            http_error(StatusCode::FORBIDDEN, ErrorKind::forbidden()).into(),
+            Some(BotError::AuthFailed),
+        ),
+        (
+            mismatched_account().into(),
+            Some(BotError::CryptoStoreMismatch),
+        ),
+        (
+            OlmError::Store(mismatched_account()).into(),
+            Some(BotError::CryptoStoreMismatch),
+        ),
+        (
+            matrix_sdk::Error::AuthenticationRequired,
+            Some(BotError::AuthFailed),
+        ),
+        (matrix_sdk::Error::InconsistentState, None),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(BotError::from_matrix(&error), expected, "{}", error);
+    }
+}
+
+#[test]
+fn redis_errors_are_the_graphs() {
+    let cases = [
+        (
+            redis_io(io::ErrorKind::TimedOut),
+            Some(BotError::GraphTimeout),
+        ),
+        (
+            redis_io(io::ErrorKind::ConnectionRefused),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            redis_io(io::ErrorKind::ConnectionReset),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            redis_io(io::ErrorKind::BrokenPipe),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            redis_io(io::ErrorKind::Other),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            redis::RedisError::from((redis::ErrorKind::AuthenticationFailed, "Wrong password")),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            redis::RedisError::from((redis::ErrorKind::TypeError, "Not a stream")),
+            None,
+        ),
+        (
+            redis::RedisError::from((redis::ErrorKind::ResponseError, "WRONGTYPE")),
+            None,
+        ),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(BotError::from_redis(&error), expected, "{}", error);
+    }
+}
+
+#[test]
+fn sqlite_errors() {
+    let failure = |code| rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None);
+    let cases = [
+        (
+            failure(rusqlite::ffi::SQLITE_CORRUPT),
+            Some(BotError::StoreCorrupt),
+        ),
+        (
+            failure(rusqlite::ffi::SQLITE_NOTADB),
+            Some(BotError::StoreCorrupt),
+        ),
+        (failure(rusqlite::ffi::SQLITE_BUSY), None),
+        (failure(rusqlite::ffi::SQLITE_FULL), None),
+        (rusqlite::Error::QueryReturnedNoRows, None),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(BotError::from_sqlite(&error), expected, "{}", error);
+    }
+}
+
+#[test]
+fn classify_finds_the_typed_error_in_the_chain() {
+    let cases = [
+        (
+            anyhow::Error::new(GraphTimeout).context("Waiting for the answer"),
+            Some(BotError::GraphTimeout),
+        ),
+        (
+            anyhow::Error::new(CircuitOpen).context("Failed to connect"),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            anyhow::Error::new(redis_io(io::ErrorKind::ConnectionRefused))
+                .context("Failed to connect to Redis"),
+            Some(BotError::GraphUnavailable),
+        ),
+        (
+            anyhow::Error::new(matrix_sdk::Error::from(mismatched_account()))
+                .context("Failed to build the client"),
+            Some(BotError::CryptoStoreMismatch),
+        ),
+        (
+            anyhow::Error::new(http_error(StatusCode::FORBIDDEN, ErrorKind::forbidden()))
+                .context("Login failed"),
+            Some(BotError::AuthFailed),
+        ),
+        (
+            anyhow!("underlying failure").context(BotError::Config),
+            Some(BotError::Config),
+        ),
+        // The wording alone is never matched
+        (
+            anyhow!("the account in the store doesn't match the account in the constructor"),
+            None,
+        ),
+        (anyhow!("Connection refused"), None),
+    ];
+    for (error, expected) in cases {
+        assert_eq!(BotError::classify(&error), expected, "{:#}", error);
+    }
+}
+
+#[test]
+fn the_outermost_recognized_error_wins() {
+    let error = anyhow::Error::new(redis_io(io::ErrorKind::TimedOut))
+        .context(BotError::NetworkTransient)
+        .context("Failed to start");
+    assert_eq!(BotError::classify(&error), Some(BotError::NetworkTransient));
+
+    // Unrecognized causes are skipped on the way down
+    let error = anyhow::Error::new(redis_io(io::ErrorKind::TimedOut))
+        .context(anyhow!("not classified"))
+        .context(redis::RedisError::from((
+            redis::ErrorKind::TypeError,
+            "Not a stream",
+        )));
+    assert_eq!(BotError::classify(&error), Some(BotError::GraphTimeout));
+}
+
+#[test]
+fn every_class_has_a_message_and_a_hint() {
+    for class in ALL {
+        assert!(!class.to_string().is_empty(), "{:?}", class);
+        assert!(class.hint().ends_with('.'), "{:?}", class);
+    }
+}
@@ -0,0 +1,103 @@
+//! The JSON shape of `bot_capabilities`, which vagent-graph codes against
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::json;
+use std::sync::Arc;
+use verji_vagent_bot::agent_service::AgentService;
+use verji_vagent_bot::capabilities::{BotCapabilities, MAX_MESSAGE_BYTES};
+use verji_vagent_bot::redis_client::{GraphRequest, RequestKind};
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::runtime::Runtime;
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+struct Named(&'static str, i32);
+
+#[async_trait]
+impl Responder for Named {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn priority(&self) -> i32 {
+        self.1
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        false
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::NotHandled)
+    }
+}
+
+fn capabilities() -> BotCapabilities {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Named("VerjiAgent", 0)));
+    manager.register(Arc::new(Named("Admin", 200)));
+    manager.register(Arc::new(Named("PingPong", 100)));
+    BotCapabilities::new(manager.list_responders())
+}
+
+#[test]
+fn capabilities_serialize_to_the_agreed_shape() {
+    let expected = json!({
+        "attachments": true,
+        "edits": true,
+        "reactions": true,
+        "polls": false,
+        "partial_responses": false,
+        "hitl_forms": true,
+        "max_message_bytes": 32768,
+        "responders": [
+            {"name": "Admin", "priority": 200},
+            {"name": "PingPong", "priority": 100},
+            {"name": "VerjiAgent", "priority": 0},
+        ],
+    });
+    assert_eq!(
+        serde_json::to_value(capabilities()).expect("serialize"),
+        expected
+    );
+    assert_eq!(MAX_MESSAGE_BYTES, 32 * 1024);
+}
+
+#[test]
+fn capabilities_round_trip() {
+    let capabilities = capabilities();
+    let json = serde_json::to_string(&capabilities).expect("serialize");
+    let parsed: BotCapabilities = serde_json::from_str(&json).expect("deserialize");
+    assert_eq!(parsed, capabilities);
+}
+
+#[tokio::test]
+async fn requests_carry_the_capabilities_installed_at_startup() {
+    let runtime = Runtime::default();
+    runtime.capabilities.init(capabilities());
+    // Installing again keeps the first
+    runtime.capabilities.init(BotCapabilities::new(Vec::new()));
+    let script = Arc::new(MockScript::from_json(r#"{"fallback_delay_ms": 0}"#).expect("script"));
+    let agent =
+        AgentService::new(TransportConfig::Mock(Arc::clone(&script))).with_runtime(&runtime);
+
+    let request = GraphRequest::new(
+        RequestKind::Query,
+        "req-1".to_string(),
+        "What's new?".to_string(),
+        "!test:localhost".to_string(),
+        "@user:localhost".to_string(),
+    );
+    agent.run_command(request).await.expect("answer");
+    let request = serde_json::to_value(&script.submitted()[0]).expect("serialize");
+    assert_eq!(
+        request["bot_capabilities"],
+        serde_json::to_value(capabilities()).expect("serialize")
+    );
+    assert_eq!(
+        request["bot_capabilities"]["responders"][0]["name"],
+        "Admin"
+    );
+}
@@ -0,0 +1,90 @@
+//! Arguments of the `send` subcommand
+
+use clap::Parser;
+use matrix_sdk::ruma::events::room::message::MessageType;
+use std::io::Cursor;
+use verji_vagent_bot::cli::{self, Command};
+
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+fn parse(args: &[&str]) -> Command {
+    let args = ["verji-vagent-bot"].iter().chain(args);
+    Args::try_parse_from(args).expect("valid arguments").command
+}
+
+#[test]
+fn an_alias_and_stdin_are_accepted() {
+    match parse(&["send", "--room", "#ops:example.org", "--message", "-"]) {
+        Command::Send {
+            room,
+            message,
+            markdown,
+            notice,
+        } => {
+            assert_eq!(room, "#ops:example.org");
+            assert_eq!(message, "-");
+            assert!(!markdown && !notice);
+        }
+        other => panic!("expected send, got {:?}", other),
+    }
+}
+
+#[test]
+fn markdown_and_notice_go_together() {
+    let command = parse(&[
+        "send",
+        "--room",
+        "!abc:example.org",
+        "--message",
+        "**Deploy done**",
+        "--markdown",
+        "--notice",
+    ]);
+    assert!(matches!(
+        command,
+        Command::Send {
+            markdown: true,
+            notice: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn the_message_is_read_from_stdin_for_a_dash() {
+    let body = cli::message_body("-", Cursor::new("Line one\nLine two\n\n")).expect("body");
+    assert_eq!(body, "Line one\nLine two");
+
+    let body = cli::message_body("Hello", Cursor::new("ignored")).expect("body");
+    assert_eq!(body, "Hello");
+}
+
+#[test]
+fn empty_messages_are_refused() {
+    assert!(cli::message_body("-", Cursor::new("  \n")).is_err());
+    assert!(cli::message_body(" ", Cursor::new("")).is_err());
+}
+
+#[test]
+fn markdown_notices_are_formatted() {
+    match cli::message_content("**Deploy done**", true, true).msgtype {
+        MessageType::Notice(notice) => {
+            assert_eq!(notice.body, "**Deploy done**");
+            let formatted = notice.formatted.expect("formatted body");
+            assert!(formatted.body.contains("<strong>Deploy done</strong>"));
+        }
+        other => panic!("expected a notice, got {:?}", other),
+    }
+    match cli::message_content("**Deploy done**", false, true).msgtype {
+        MessageType::Notice(notice) => assert!(notice.formatted.is_none()),
+        other => panic!("expected a notice, got {:?}", other),
+    }
+    assert!(matches!(
+        cli::message_content("**Deploy done**", true, false).msgtype,
+        MessageType::Text(_)
+    ));
+}
@@ -0,0 +1,96 @@
+//! Splitting command arguments into words
+
+use verji_vagent_bot::command::tokenize;
+
+fn words(input: &str) -> Vec<String> {
+    tokenize(input).unwrap_or_else(|e| panic!("{:?}: {}", input, e))
+}
+
+#[test]
+fn whitespace_separates_arguments() {
+    let cases: &[(&str, &[&str])] = &[
+        ("", &[]),
+        ("   ", &[]),
+        ("one", &["one"]),
+        ("one two  three", &["one", "two", "three"]),
+        ("  padded  ", &["padded"]),
+        ("tab\tnew\nline", &["tab", "new", "line"]),
+        ("no\u{a0}break", &["no", "break"]),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(words(input), *expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn quotes_group_words() {
+    let cases: &[(&str, &[&str])] = &[
+        ("\"hello world\" again", &["hello world", "again"]),
+        ("\"  kept  spacing \"", &["  kept  spacing "]),
+        ("pre\"fix suf\"fix", &["prefix suffix"]),
+        ("“smart quotes” too", &["smart quotes", "too"]),
+        ("“mixed\"", &["mixed"]),
+        (
+            "don't quote apostrophes",
+            &["don't", "quote", "apostrophes"],
+        ),
+        (
+            "'single quotes' are text",
+            &["'single", "quotes'", "are", "text"],
+        ),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(words(input), *expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn empty_quotes_are_empty_arguments() {
+    let cases: &[(&str, &[&str])] = &[
+        ("\"\"", &[""]),
+        ("a \"\" b", &["a", "", "b"]),
+        ("\"\" \"\"", &["", ""]),
+        ("“”", &[""]),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(words(input), *expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn backslashes_escape_quotes_and_themselves() {
+    let cases: &[(&str, &[&str])] = &[
+        (r#"\"quoted\""#, &[r#""quoted""#]),
+        (r#""say \"hi\" now""#, &[r#"say "hi" now"#]),
+        (r#"\""#, &[r#"""#]),
+        (r"a\\b", &[r"a\b"]),
+        (r#""ends with \\""#, &[r"ends with \"]),
+        // Other backslashes are kept as typed
+        (r"C:\temp\new", &[r"C:\temp\new"]),
+        (r"trailing\", &[r"trailing\"]),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(words(input), *expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn unicode_passes_through() {
+    let cases: &[(&str, &[&str])] = &[
+        ("ünïcödé 日本語 🎉", &["ünïcödé", "日本語", "🎉"]),
+        ("\"møte i morgen\" kl.10", &["møte i morgen", "kl.10"]),
+        ("👨‍👩‍👧 family", &["👨‍👩‍👧", "family"]),
+        ("\"é \\\"ß\\\"\"", &["é \"ß\""]),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(words(input), *expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn unterminated_quotes_are_errors() {
+    for input in ["\"open", "a \"b c", "“never closed", r#""escaped \""#] {
+        let error = tokenize(input).expect_err(input);
+        assert_eq!(error.to_string(), "Unterminated quote in arguments");
+    }
+}
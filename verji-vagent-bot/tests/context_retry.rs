@@ -0,0 +1,139 @@
+//! Retrying with less room context after vagent-graph refuses a query as too large
+//!
+//! The mock graph in `fixtures/mock_graph/payload_too_large.json` refuses
+//! requests carrying four or more context messages and echoes the rest.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const REDUCED_NOTE: &str = "Part of the conversation context was left out";
+
+fn fixture() -> MockScript {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/mock_graph/payload_too_large.json");
+    MockScript::load(&path).expect("fixture")
+}
+
+fn responder(script: MockScript) -> Arc<VerjiAgentResponder> {
+    Arc::new(VerjiAgentResponder::with_transport(TransportConfig::Mock(
+        Arc::new(script),
+    )))
+}
+
+/// A harness in a room with `messages` earlier messages to send as context
+fn harness(messages: usize, retries: usize) -> ResponderTestHarness {
+    let history: Vec<(String, String)> = (0..messages)
+        .map(|i| {
+            (
+                "@other:localhost".to_string(),
+                format!("Earlier message {}", i),
+            )
+        })
+        .collect();
+    let history: Vec<(&str, &str)> = history
+        .iter()
+        .map(|(sender, body)| (sender.as_str(), body.as_str()))
+        .collect();
+    ResponderTestHarness::new()
+        .expect("harness")
+        .room(
+            MockRoom::new("!retry:localhost")
+                .expect("room")
+                .with_history(&history),
+        )
+        .configure(|config| {
+            config.locale = "en".to_string();
+            config.context_messages = 20;
+            config.context_retries = retries;
+        })
+}
+
+async fn answer(harness: &ResponderTestHarness, responder: Arc<VerjiAgentResponder>) -> String {
+    let messages = harness
+        .respond(responder, "What did we decide?")
+        .await
+        .expect("dispatch");
+    assert_eq!(messages.len(), 1, "expected one answer: {:?}", messages);
+    message_text(&messages[0]).expect("text answer").to_string()
+}
+
+#[tokio::test]
+async fn halved_context_answers_with_a_note() {
+    // 6 messages are refused, 3 fit
+    let harness = harness(6, 2);
+    let answer = answer(&harness, responder(fixture())).await;
+    assert!(
+        answer.starts_with("Echo: What did we decide?"),
+        "{}",
+        answer
+    );
+    assert!(answer.contains(REDUCED_NOTE), "{}", answer);
+}
+
+#[tokio::test]
+async fn last_retry_drops_all_context() {
+    // 10 is refused, and so is half of it; the last retry sends none
+    let harness = harness(10, 2);
+    let answer = answer(&harness, responder(fixture())).await;
+    assert!(
+        answer.starts_with("Echo: What did we decide?"),
+        "{}",
+        answer
+    );
+    assert!(answer.contains(REDUCED_NOTE), "{}", answer);
+}
+
+#[tokio::test]
+async fn small_requests_are_not_annotated() {
+    let harness = harness(2, 2);
+    let answer = answer(&harness, responder(fixture())).await;
+    assert!(
+        answer.starts_with("Echo: What did we decide?"),
+        "{}",
+        answer
+    );
+    assert!(!answer.contains(REDUCED_NOTE), "{}", answer);
+}
+
+#[tokio::test]
+async fn no_retries_when_disabled() {
+    let harness = harness(6, 0);
+    let answer = answer(&harness, responder(fixture())).await;
+    assert!(!answer.starts_with("Echo:"), "{}", answer);
+    assert!(answer.contains("couldn't answer"), "{}", answer);
+}
+
+#[tokio::test]
+async fn errors_without_a_code_are_recognized_by_their_wording() {
+    let script = MockScript::from_json(
+        r#"{"fallback_delay_ms": 0, "scenarios": [{"min_context": 1, "steps": [
+            {"type": "error", "content": "This model's maximum context length is 8192 tokens"}
+        ]}]}"#,
+    )
+    .expect("script");
+    let harness = harness(4, 2);
+    let answer = answer(&harness, responder(script)).await;
+    assert!(
+        answer.starts_with("Echo: What did we decide?"),
+        "{}",
+        answer
+    );
+    assert!(answer.contains(REDUCED_NOTE), "{}", answer);
+}
+
+#[tokio::test]
+async fn other_graph_errors_are_not_retried() {
+    let script = MockScript::from_json(
+        r#"{"fallback_delay_ms": 0, "scenarios": [{"min_context": 1, "steps": [
+            {"type": "error", "content": "Too large a question", "metadata": {"code": "tool_failed"}}
+        ]}]}"#,
+    )
+    .expect("script");
+    let harness = harness(4, 2);
+    let answer = answer(&harness, responder(script)).await;
+    assert!(answer.contains("couldn't answer"), "{}", answer);
+}
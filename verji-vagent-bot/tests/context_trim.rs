@@ -0,0 +1,137 @@
+//! Fitting room context into the token budget, over messages of known sizes
+//!
+//! Senders are four characters (one token) and bodies multiples of four, so
+//! every cost below is exact.
+
+use serde_json::json;
+use verji_vagent_bot::room_context::{trim_to_budget, ContextTrim, HistoryMessage};
+use verji_vagent_bot::tokens;
+
+fn message(index: usize, body: String) -> HistoryMessage {
+    HistoryMessage {
+        event_id: format!("$m{}:x", index),
+        sender: "@a:x".to_string(),
+        body,
+        timestamp_ms: index as u64 * 1_000,
+    }
+}
+
+/// `count` messages of `tokens` tokens each, oldest first
+fn messages(count: usize, tokens: usize) -> Vec<HistoryMessage> {
+    (0..count)
+        .map(|index| message(index, "x".repeat(tokens * 4)))
+        .collect()
+}
+
+fn ids(messages: &[HistoryMessage]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|message| message.event_id.as_str())
+        .collect()
+}
+
+#[test]
+fn tokens_are_estimated_from_characters() {
+    let cases = [
+        ("", 0),
+        ("abc", 1),
+        ("abcd", 1),
+        ("abcde", 2),
+        ("æøå", 1),
+        ("🎉🎉🎉🎉🎉", 2),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(tokens::estimate(text), expected, "{:?}", text);
+    }
+    assert_eq!(tokens::chars_for(10), 40);
+}
+
+#[test]
+fn context_within_the_budget_is_untouched() {
+    let (kept, report) = trim_to_budget(messages(3, 10), "hi", 100);
+    assert_eq!(ids(&kept), vec!["$m0:x", "$m1:x", "$m2:x"]);
+    assert_eq!(report, ContextTrim::default());
+}
+
+#[test]
+fn the_oldest_messages_are_dropped_first() {
+    // 38 tokens left after the trigger; each message costs 11
+    let (kept, report) = trim_to_budget(messages(5, 10), "question", 40);
+    assert_eq!(ids(&kept), vec!["$m2:x", "$m3:x", "$m4:x"]);
+    assert_eq!(
+        report,
+        ContextTrim {
+            messages_dropped: 2,
+            messages_truncated: 0,
+            chars_truncated: 0,
+        }
+    );
+}
+
+#[test]
+fn long_messages_keep_their_head_and_tail() {
+    let long = format!("{}{}", "a".repeat(500), "b".repeat(500));
+    let (kept, report) = trim_to_budget(vec![message(0, long)], "hi", 100);
+
+    // A quarter of the budget is 25 tokens, so 100 characters with the marker
+    assert_eq!(
+        kept[0].body,
+        format!("{} […] {}", "a".repeat(47), "b".repeat(48))
+    );
+    assert_eq!(kept[0].body.chars().count(), 100);
+    assert_eq!(
+        report,
+        ContextTrim {
+            messages_dropped: 0,
+            messages_truncated: 1,
+            chars_truncated: 900,
+        }
+    );
+}
+
+#[test]
+fn the_trigger_comes_off_the_budget_and_is_never_cut() {
+    let trigger = "y".repeat(400);
+    let (kept, report) = trim_to_budget(messages(3, 1), &trigger, 50);
+    assert!(kept.is_empty());
+    assert_eq!(report.messages_dropped, 3);
+    assert_eq!(report.chars_truncated, 0);
+}
+
+#[test]
+fn nothing_older_than_the_first_message_that_does_not_fit_is_kept() {
+    let context = vec![
+        // Would fit on its own, but is older than the one that doesn't
+        message(0, "x".repeat(4)),
+        // Cut to 10 tokens, costing 11 with the sender
+        message(1, "x".repeat(400)),
+        // Costs 6
+        message(2, "x".repeat(20)),
+    ];
+    // 15 tokens left after the trigger
+    let (kept, report) = trim_to_budget(context, &"q".repeat(100), 40);
+
+    assert_eq!(ids(&kept), vec!["$m2:x"]);
+    // The cut message was dropped after all, so it isn't reported as cut
+    assert_eq!(
+        report,
+        ContextTrim {
+            messages_dropped: 2,
+            messages_truncated: 0,
+            chars_truncated: 0,
+        }
+    );
+}
+
+#[test]
+fn trimming_is_reported_to_the_graph() {
+    let report = ContextTrim {
+        messages_dropped: 4,
+        messages_truncated: 1,
+        chars_truncated: 900,
+    };
+    assert_eq!(
+        serde_json::to_value(&report).unwrap(),
+        json!({"messages_dropped": 4, "messages_truncated": 1, "chars_truncated": 900})
+    );
+}
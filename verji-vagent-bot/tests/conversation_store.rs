@@ -0,0 +1,293 @@
+//! Conversation state: expiry, LRU eviction and the sqlite copy
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::clock::{Clock, SystemClock};
+use verji_vagent_bot::conversation::{ConversationKey, ConversationStore};
+use verji_vagent_bot::db::TempStore;
+use verji_vagent_bot::testing::ManualClock;
+
+fn key(user: &str, slot: &str) -> ConversationKey {
+    ConversationKey::new("!room:localhost", user, slot)
+}
+
+/// A clock `behind` the system clock, moving only when told to
+fn clock(behind: Duration) -> Arc<ManualClock> {
+    let now_ms = SystemClock.now_ms() - behind.as_millis() as u64;
+    Arc::new(ManualClock::new(now_ms))
+}
+
+fn store_on(clock: &Arc<ManualClock>) -> ConversationStore {
+    ConversationStore::in_memory(100).with_clock(Arc::clone(clock) as Arc<dyn Clock>)
+}
+
+#[tokio::test]
+async fn values_are_kept_per_room_thread_user_and_slot() {
+    let store = ConversationStore::in_memory(100);
+    let main = key("@alice:localhost", "wizard");
+    let threaded = key("@alice:localhost", "wizard").in_thread("$root:localhost");
+    let other_slot = key("@alice:localhost", "feedback");
+    let other_user = key("@bob:localhost", "wizard");
+
+    store.set(main.clone(), json!(1), None).await;
+    store.set(threaded.clone(), json!(2), None).await;
+    store.set(other_slot.clone(), json!(3), None).await;
+    store.set(other_user.clone(), json!(4), None).await;
+
+    assert_eq!(store.get(&main).await, Some(json!(1)));
+    assert_eq!(store.get(&threaded).await, Some(json!(2)));
+    assert_eq!(store.get(&other_slot).await, Some(json!(3)));
+    assert_eq!(store.get(&other_user).await, Some(json!(4)));
+    assert_eq!(store.remove(&main).await, Some(json!(1)));
+    assert_eq!(store.get(&main).await, None);
+    assert_eq!(store.entry_count(), 3);
+}
+
+#[tokio::test]
+async fn expired_values_are_gone_and_reclaimed() {
+    let clock = clock(Duration::ZERO);
+    let store = store_on(&clock);
+    let short = key("@alice:localhost", "short");
+    let swept = key("@bob:localhost", "short");
+    let lasting = key("@alice:localhost", "lasting");
+    store
+        .set(
+            short.clone(),
+            json!("soon gone"),
+            Some(Duration::from_secs(1)),
+        )
+        .await;
+    store
+        .set(
+            swept.clone(),
+            json!("soon gone"),
+            Some(Duration::from_secs(1)),
+        )
+        .await;
+    store
+        .set(
+            lasting.clone(),
+            json!("stays"),
+            Some(Duration::from_secs(3600)),
+        )
+        .await;
+    assert_eq!(store.get(&short).await, Some(json!("soon gone")));
+
+    clock.advance(Duration::from_secs(1));
+
+    // On access ...
+    assert_eq!(store.get(&short).await, None);
+    assert_eq!(store.entry_count(), 2);
+    // ... and by the sweep, without anyone asking for it
+    assert_eq!(store.sweep(), 1);
+    assert_eq!(store.entry_count(), 1);
+    assert_eq!(store.get(&lasting).await, Some(json!("stays")));
+}
+
+#[tokio::test]
+async fn expire_changes_the_ttl() {
+    let clock = clock(Duration::ZERO);
+    let store = store_on(&clock);
+    let extended = key("@alice:localhost", "extended");
+    let shortened = key("@bob:localhost", "shortened");
+    store
+        .set(extended.clone(), json!(1), Some(Duration::from_secs(1)))
+        .await;
+    store.set(shortened.clone(), json!(2), None).await;
+
+    assert!(store.expire(&extended, None).await);
+    assert!(store.expire(&shortened, Some(Duration::from_secs(1))).await);
+    assert!(
+        !store
+            .expire(&key("@carol:localhost", "missing"), None)
+            .await
+    );
+    clock.advance(Duration::from_secs(1));
+
+    assert_eq!(store.get(&extended).await, Some(json!(1)));
+    assert_eq!(store.get(&shortened).await, None);
+    // An expired value can't be brought back
+    assert!(!store.expire(&shortened, None).await);
+}
+
+#[tokio::test]
+async fn the_least_recently_used_value_is_evicted() {
+    let store = ConversationStore::in_memory(3);
+    let keys: Vec<_> = ["a", "b", "c", "d", "e"]
+        .iter()
+        .map(|slot| key("@alice:localhost", slot))
+        .collect();
+    for key in &keys[..3] {
+        store.set(key.clone(), json!(key.slot), None).await;
+    }
+
+    // Reading "a" and updating "b" makes "c" the oldest
+    store.get(&keys[0]).await;
+    store.update(&keys[1], |value| *value = json!("b2")).await;
+    store.set(keys[3].clone(), json!("d"), None).await;
+
+    assert_eq!(store.entry_count(), 3);
+    assert_eq!(store.get(&keys[2]).await, None);
+    assert_eq!(store.get(&keys[1]).await, Some(json!("b2")));
+
+    // Overwriting a value doesn't grow the store
+    store.set(keys[3].clone(), json!("d2"), None).await;
+    assert_eq!(store.entry_count(), 3);
+
+    // Now "a" is the oldest
+    store.set(keys[4].clone(), json!("e"), None).await;
+    assert_eq!(store.get(&keys[0]).await, None);
+    assert_eq!(store.entry_count(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn parallel_handlers_share_the_store() {
+    let store = Arc::new(ConversationStore::in_memory(1000));
+    let counter = key("@alice:localhost", "counter");
+    store.set(counter.clone(), json!(0), None).await;
+
+    let tasks: Vec<_> = (0..8)
+        .map(|task| {
+            let store = Arc::clone(&store);
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                for round in 0..50 {
+                    store
+                        .update(&counter, |value| {
+                            *value = json!(value.as_u64().unwrap() + 1)
+                        })
+                        .await;
+                    let own = key(&format!("@user{}:localhost", task), &round.to_string());
+                    store.set(own.clone(), json!(round), None).await;
+                    assert_eq!(store.get(&own).await, Some(json!(round)));
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.expect("task");
+    }
+
+    assert_eq!(store.get(&counter).await, Some(json!(400)));
+    assert_eq!(store.entry_count(), 401);
+}
+
+#[tokio::test]
+async fn persisted_values_survive_a_restart() {
+    let dir = TempStore::new("conversation").expect("store");
+    let kept = key("@alice:localhost", "wizard").in_thread("$root:localhost");
+    let updated = key("@alice:localhost", "feedback");
+    let removed = key("@bob:localhost", "wizard");
+    let expiring = key("@bob:localhost", "short");
+    {
+        // A minute ago, so the one-second TTL has run out by the restart
+        let clock = clock(Duration::from_secs(60));
+        let store = ConversationStore::open(dir.path(), 100)
+            .expect("open")
+            .with_clock(clock as Arc<dyn Clock>);
+        store
+            .set(
+                kept.clone(),
+                json!({"step": 2, "answers": ["a", "b"]}),
+                Some(Duration::from_secs(3600)),
+            )
+            .await;
+        store.set(updated.clone(), json!("first"), None).await;
+        store
+            .update(&updated, |value| *value = json!("second"))
+            .await;
+        store.set(removed.clone(), json!("gone"), None).await;
+        store.remove(&removed).await;
+        store
+            .set(
+                expiring.clone(),
+                json!("short"),
+                Some(Duration::from_secs(1)),
+            )
+            .await;
+        store.flush().await.expect("flush");
+    }
+
+    let store = ConversationStore::open(dir.path(), 100).expect("reopen");
+    assert_eq!(
+        store.get(&kept).await,
+        Some(json!({"step": 2, "answers": ["a", "b"]}))
+    );
+    assert_eq!(store.get(&updated).await, Some(json!("second")));
+    assert_eq!(store.get(&removed).await, None);
+    assert_eq!(store.get(&expiring).await, None);
+    assert_eq!(store.entry_count(), 2);
+}
+
+#[tokio::test]
+async fn a_restart_keeps_at_most_max_entries() {
+    let dir = TempStore::new("conversation-cap").expect("store");
+    {
+        let store = ConversationStore::open(dir.path(), 100).expect("open");
+        for slot in 0..10 {
+            store
+                .set(
+                    key("@alice:localhost", &slot.to_string()),
+                    json!(slot),
+                    None,
+                )
+                .await;
+        }
+        store.flush().await.expect("flush");
+    }
+
+    let store = ConversationStore::open(dir.path(), 4).expect("reopen");
+    assert_eq!(store.entry_count(), 4);
+}
+
+#[tokio::test]
+async fn remove_if_takes_only_what_the_predicate_accepts() {
+    let store = ConversationStore::in_memory(100);
+    let question = key("@alice:localhost", "hitl");
+    store
+        .set(question.clone(), json!({"request_id": "req-2"}), None)
+        .await;
+
+    let is_first = |value: &serde_json::Value| value["request_id"] == "req-1";
+    assert_eq!(store.remove_if(&question, is_first).await, None);
+    assert_eq!(
+        store.get(&question).await,
+        Some(json!({"request_id": "req-2"}))
+    );
+
+    let is_second = |value: &serde_json::Value| value["request_id"] == "req-2";
+    assert_eq!(
+        store.remove_if(&question, is_second).await,
+        Some(json!({"request_id": "req-2"}))
+    );
+    assert_eq!(store.get(&question).await, None);
+    assert_eq!(store.remove_if(&question, |_| true).await, None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn only_one_of_racing_removals_gets_the_value() {
+    let store = Arc::new(ConversationStore::in_memory(100));
+    let question = key("@alice:localhost", "hitl");
+    for round in 0..50 {
+        store
+            .set(question.clone(), json!({"request_id": round}), None)
+            .await;
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let store = Arc::clone(&store);
+                let question = question.clone();
+                tokio::spawn(async move {
+                    store
+                        .remove_if(&question, |value| value["request_id"] == round)
+                        .await
+                })
+            })
+            .collect();
+        let mut taken = 0;
+        for task in tasks {
+            taken += task.await.expect("task").is_some() as usize;
+        }
+        assert_eq!(taken, 1, "round {}", round);
+    }
+}
@@ -0,0 +1,141 @@
+//! Per-room responder cooldowns on a manual clock
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::clock::Clock;
+use verji_vagent_bot::decorators::Cooldown;
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::routing::Verdict;
+use verji_vagent_bot::testing::{message_text, ManualClock, MockRoom, ResponderTestHarness};
+
+const COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Greets, except for messages saying "pass"
+struct Greeter;
+
+#[async_trait]
+impl Responder for Greeter {
+    fn name(&self) -> &str {
+        "Greeter"
+    }
+
+    fn priority(&self) -> i32 {
+        50
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        if &*context.message_body == "pass" {
+            return Ok(ResponderResult::NotHandled);
+        }
+        Ok(ResponderResult::Handled(Some("Hello there!".to_string())))
+    }
+}
+
+/// Answers whatever the greeter leaves
+struct Fallback;
+
+#[async_trait]
+impl Responder for Fallback {
+    fn name(&self) -> &str {
+        "Default"
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some("Default".to_string())))
+    }
+}
+
+fn setup() -> (ResponderManager, Arc<ManualClock>) {
+    let clock = Arc::new(ManualClock::new(1_700_000_000_000));
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Cooldown::with_clock(
+        Greeter,
+        COOLDOWN,
+        Arc::clone(&clock) as Arc<dyn Clock>,
+    )));
+    manager.register(Arc::new(Fallback));
+    (manager, clock)
+}
+
+async fn answer(harness: &ResponderTestHarness, manager: &ResponderManager, body: &str) -> String {
+    let messages = harness.dispatch(manager, body).await.expect("dispatch");
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    message_text(&messages[0]).expect("text").to_string()
+}
+
+#[tokio::test]
+async fn the_responder_is_skipped_until_the_cooldown_ends() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let (manager, clock) = setup();
+
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Hello there!");
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Default");
+
+    clock.advance(COOLDOWN - Duration::from_millis(1));
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Default");
+
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Hello there!");
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Default");
+}
+
+#[tokio::test]
+async fn skipping_is_traced_as_a_cooldown_decline() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Cooldown::with_clock(
+        Greeter,
+        COOLDOWN,
+        Arc::new(ManualClock::new(0)) as Arc<dyn Clock>,
+    )));
+
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Hello there!");
+    let messages = harness.dispatch(&manager, "Hi").await.expect("dispatch");
+    assert!(messages.is_empty());
+
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(traces.len(), 1);
+    assert_eq!(
+        traces[0].steps[0].verdict,
+        Verdict::Declined(Some("cooldown"))
+    );
+}
+
+#[tokio::test]
+async fn rooms_cool_down_separately() {
+    let (manager, _clock) = setup();
+    let first = ResponderTestHarness::new().expect("harness");
+    let second = ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!other:localhost").expect("room"));
+
+    assert_eq!(answer(&first, &manager, "Hi").await, "Hello there!");
+    assert_eq!(answer(&second, &manager, "Hi").await, "Hello there!");
+    assert_eq!(answer(&first, &manager, "Hi").await, "Default");
+    assert_eq!(answer(&second, &manager, "Hi").await, "Default");
+}
+
+#[tokio::test]
+async fn only_handled_messages_start_the_cooldown() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let (manager, _clock) = setup();
+
+    assert_eq!(answer(&harness, &manager, "pass").await, "Default");
+    assert_eq!(answer(&harness, &manager, "pass").await, "Default");
+    assert_eq!(answer(&harness, &manager, "Hi").await, "Hello there!");
+}
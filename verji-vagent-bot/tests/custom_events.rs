@@ -0,0 +1,186 @@
+//! Custom events forwarded to vagent-graph: which events qualify, and the
+//! request the (mock) graph receives for one
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use verji_vagent_bot::config::BotConfig;
+use verji_vagent_bot::conversation::ConversationKey;
+use verji_vagent_bot::custom_events::{self, parse_treatments, Treatment};
+use verji_vagent_bot::hitl::{self, PendingHitl, HITL_SLOT};
+use verji_vagent_bot::redis_client::RequestKind;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const SENDER: &str = "@alice:localhost";
+
+fn treatments() -> HashMap<String, Treatment> {
+    parse_treatments(HashMap::from([
+        ("no.verji.task".to_string(), "forward_to_graph".to_string()),
+        ("org.example.location".to_string(), "forward".to_string()),
+        ("org.example.ping".to_string(), "log".to_string()),
+        ("org.example.noise".to_string(), "ignore".to_string()),
+        ("org.example.typo".to_string(), "forwad".to_string()),
+    ]))
+}
+
+fn task() -> Value {
+    json!({
+        "type": "no.verji.task",
+        "event_id": "$task:localhost",
+        "sender": SENDER,
+        "origin_server_ts": 1_700_000_000_000u64,
+        "content": {"title": "Send the Q3 report", "due": "2026-10-31", "assignees": ["@bob:localhost"]},
+    })
+}
+
+#[test]
+fn configured_types_and_custom_msgtypes_are_classified() {
+    let treatments = treatments();
+    let message = |msgtype: &str| json!({"type": "m.room.message", "content": {"msgtype": msgtype, "body": "x"}});
+    let cases = [
+        (task(), Some(("no.verji.task", Treatment::ForwardToGraph))),
+        (
+            message("org.example.location"),
+            Some(("org.example.location", Treatment::ForwardToGraph)),
+        ),
+        (
+            json!({"type": "org.example.ping", "content": {}}),
+            Some(("org.example.ping", Treatment::Log)),
+        ),
+        (
+            json!({"type": "org.example.noise"}),
+            Some(("org.example.noise", Treatment::Ignore)),
+        ),
+        // Unknown treatments are dropped, unlisted types stay invisible
+        (json!({"type": "org.example.typo", "content": {}}), None),
+        (json!({"type": "org.example.other", "content": {}}), None),
+        // Standard msgtypes are the message handler's
+        (message("m.text"), None),
+        // Redacted events are not forwarded
+        (
+            json!({"type": "no.verji.task", "content": {}, "unsigned": {"redacted_because": {}}}),
+            None,
+        ),
+        (json!({"content": {}}), None),
+    ];
+    for (event, expected) in cases {
+        let classified = custom_events::classify(&event, &treatments);
+        assert_eq!(
+            classified,
+            expected.map(|(key, treatment)| (key.to_string(), treatment)),
+            "{}",
+            event
+        );
+    }
+}
+
+fn agent(script: &Arc<MockScript>) -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::clone(script)),
+    )));
+    manager
+}
+
+/// Forward `event` as the bot does, returning the graph's script
+async fn forward(harness: ResponderTestHarness, event: &Value) -> Arc<MockScript> {
+    let (custom_type, treatment) =
+        custom_events::classify(event, &treatments()).expect("configured");
+    assert_eq!(treatment, Treatment::ForwardToGraph);
+
+    let script = Arc::new(MockScript::from_json(r#"{"fallback_delay_ms": 0}"#).expect("script"));
+    let harness = harness.custom_event(custom_events::payload(&custom_type, event));
+    // Custom events have no text
+    let messages = harness
+        .dispatch(&agent(&script), "")
+        .await
+        .expect("dispatch");
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    assert!(message_text(&messages[0]).is_some_and(|text| text.starts_with("Echo:")));
+    script
+}
+
+fn harness() -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!tasks:localhost").expect("room"))
+        .sender(SENDER)
+}
+
+#[tokio::test]
+async fn a_forwarded_event_reaches_the_graph_as_the_payload() {
+    let event = task();
+    let script = forward(harness(), &event).await;
+
+    let submitted = script.submitted();
+    assert_eq!(submitted.len(), 1);
+    let request = &submitted[0];
+    assert_eq!(request.kind, RequestKind::Query);
+    assert_eq!(request.query, "");
+    assert_eq!(request.metadata.room_id, "!tasks:localhost");
+    assert_eq!(request.metadata.user_id, SENDER);
+    assert_eq!(
+        request.payload,
+        Some(json!({"custom_event": {"type": "no.verji.task", "event": event}}))
+    );
+
+    // On the wire the payload is the raw event, content and all
+    let wire = serde_json::to_value(request).expect("serialize");
+    assert_eq!(
+        wire["payload"]["custom_event"]["event"]["content"]["title"],
+        "Send the Q3 report"
+    );
+}
+
+#[tokio::test]
+async fn a_custom_msgtype_is_forwarded_under_its_msgtype() {
+    let event = json!({
+        "type": "m.room.message",
+        "event_id": "$location:localhost",
+        "sender": SENDER,
+        "content": {"msgtype": "org.example.location", "body": "Here", "geo": "geo:59.91,10.75"},
+    });
+    let script = forward(harness(), &event).await;
+    let payload = script.submitted()[0].payload.clone().expect("payload");
+    assert_eq!(payload["custom_event"]["type"], "org.example.location");
+    assert_eq!(
+        payload["custom_event"]["event"]["content"]["geo"],
+        "geo:59.91,10.75"
+    );
+}
+
+#[tokio::test]
+async fn a_pending_question_does_not_capture_a_custom_event() {
+    let harness = harness();
+    let key = ConversationKey::new("!tasks:localhost", SENDER, HITL_SLOT);
+    let pending = PendingHitl {
+        request_id: "req-waiting".to_string(),
+        question: "Which customer?".to_string(),
+        options: Vec::new(),
+        event_id: "$question:localhost".to_string(),
+        language: "en".to_string(),
+        asked_at: 0,
+        form: None,
+    };
+    hitl::ask(
+        harness.conversations(),
+        harness.follow_ups(),
+        key.clone(),
+        &pending,
+        &BotConfig::from_env(),
+        &harness.runtime().catalog,
+    )
+    .await;
+
+    let conversations = Arc::clone(harness.conversations());
+    let script = forward(harness, &task()).await;
+    let request = &script.submitted()[0];
+    assert_eq!(request.kind, RequestKind::Query);
+    assert_ne!(request.request_id, "req-waiting");
+    // The question still waits for its answer
+    assert!(conversations.get(&key).await.is_some());
+}
@@ -0,0 +1,235 @@
+//! Chains with responders that defer or handle a message silently
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::routing::{Step, Verdict};
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Answer(&'static str),
+    Silent,
+    Defer,
+    Pass,
+}
+
+impl Outcome {
+    fn result(self) -> ResponderResult {
+        match self {
+            Outcome::Answer(text) => ResponderResult::Handled(Some(text.to_string())),
+            Outcome::Silent => ResponderResult::HandledSilently,
+            Outcome::Defer => ResponderResult::Defer,
+            Outcome::Pass => ResponderResult::NotHandled,
+        }
+    }
+}
+
+/// Takes every message, answering `handle` and `fallback` as scripted and
+/// counting both
+struct Scripted {
+    name: &'static str,
+    priority: i32,
+    handle: Outcome,
+    fallback: Outcome,
+    handled: AtomicUsize,
+    fell_back: AtomicUsize,
+}
+
+impl Scripted {
+    fn new(name: &'static str, priority: i32, handle: Outcome, fallback: Outcome) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            priority,
+            handle,
+            fallback,
+            handled: AtomicUsize::new(0),
+            fell_back: AtomicUsize::new(0),
+        })
+    }
+
+    /// Times `handle` and `fallback` were called
+    fn calls(&self) -> (usize, usize) {
+        (
+            self.handled.load(Ordering::SeqCst),
+            self.fell_back.load(Ordering::SeqCst),
+        )
+    }
+}
+
+#[async_trait]
+impl Responder for Scripted {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        self.handled.fetch_add(1, Ordering::SeqCst);
+        Ok(self.handle.result())
+    }
+
+    async fn fallback(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        self.fell_back.fetch_add(1, Ordering::SeqCst);
+        Ok(self.fallback.result())
+    }
+}
+
+fn manager(responders: &[&Arc<Scripted>]) -> ResponderManager {
+    let manager = ResponderManager::new();
+    for responder in responders {
+        manager.register(Arc::clone(responder) as Arc<dyn Responder>);
+    }
+    manager
+}
+
+/// Dispatch one message, returning the texts sent
+async fn texts(manager: &ResponderManager) -> Vec<String> {
+    let harness = ResponderTestHarness::new().expect("harness");
+    harness
+        .dispatch(manager, "Hello")
+        .await
+        .expect("dispatch")
+        .iter()
+        .filter_map(message_text)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Steps of the one unanswered message
+fn unanswered_steps(manager: &ResponderManager) -> Vec<Step> {
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(traces.len(), 1);
+    traces[0].steps.clone()
+}
+
+fn step(responder: &str, verdict: Verdict) -> Step {
+    Step {
+        responder: responder.to_string(),
+        verdict,
+    }
+}
+
+#[tokio::test]
+async fn a_deferred_message_goes_to_the_default_first() {
+    let deferrer = Scripted::new(
+        "Deferrer",
+        100,
+        Outcome::Defer,
+        Outcome::Answer("From the fallback"),
+    );
+    let default = Scripted::new(
+        "Default",
+        0,
+        Outcome::Answer("From the default"),
+        Outcome::Pass,
+    );
+    let manager = manager(&[&deferrer, &default]);
+
+    assert_eq!(texts(&manager).await, vec!["From the default"]);
+    assert_eq!(deferrer.calls(), (1, 0));
+    assert_eq!(default.calls(), (1, 0));
+}
+
+#[tokio::test]
+async fn the_deferrer_falls_back_when_nobody_else_answers() {
+    let deferrer = Scripted::new(
+        "Deferrer",
+        100,
+        Outcome::Defer,
+        Outcome::Answer("From the fallback"),
+    );
+    let passer = Scripted::new("Passer", 0, Outcome::Pass, Outcome::Pass);
+    let manager = manager(&[&deferrer, &passer]);
+
+    assert_eq!(texts(&manager).await, vec!["From the fallback"]);
+    assert_eq!(deferrer.calls(), (1, 1));
+    // Only deferrers are asked again
+    assert_eq!(passer.calls(), (1, 0));
+}
+
+#[tokio::test]
+async fn fallbacks_run_in_priority_order_until_one_answers() {
+    let first = Scripted::new("First", 100, Outcome::Defer, Outcome::Pass);
+    let second = Scripted::new(
+        "Second",
+        50,
+        Outcome::Defer,
+        Outcome::Answer("Second answers"),
+    );
+    let third = Scripted::new(
+        "Third",
+        10,
+        Outcome::Defer,
+        Outcome::Answer("Third answers"),
+    );
+    let manager = manager(&[&third, &first, &second]);
+
+    assert_eq!(texts(&manager).await, vec!["Second answers"]);
+    assert_eq!(first.calls(), (1, 1));
+    assert_eq!(second.calls(), (1, 1));
+    assert_eq!(third.calls(), (1, 0));
+}
+
+#[tokio::test]
+async fn deferring_again_from_the_fallback_ends_the_chain() {
+    let deferrer = Scripted::new("Deferrer", 100, Outcome::Defer, Outcome::Defer);
+    let manager = manager(&[&deferrer]);
+
+    assert!(texts(&manager).await.is_empty());
+    assert_eq!(deferrer.calls(), (1, 1));
+    assert_eq!(
+        unanswered_steps(&manager),
+        vec![
+            step("Deferrer", Verdict::Deferred),
+            step("Deferrer", Verdict::Deferred),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn handling_silently_stops_the_chain() {
+    let silent = Scripted::new("Silent", 100, Outcome::Silent, Outcome::Pass);
+    let default = Scripted::new(
+        "Default",
+        0,
+        Outcome::Answer("From the default"),
+        Outcome::Pass,
+    );
+    let manager = manager(&[&silent, &default]);
+
+    assert!(texts(&manager).await.is_empty());
+    assert_eq!(default.calls(), (0, 0));
+    // Handled, though nothing was sent
+    assert_eq!(
+        unanswered_steps(&manager),
+        vec![step("Silent", Verdict::Handled)]
+    );
+}
+
+#[tokio::test]
+async fn a_silent_fallback_stops_the_chain() {
+    let deferrer = Scripted::new("Deferrer", 100, Outcome::Defer, Outcome::Silent);
+    let passer = Scripted::new("Passer", 0, Outcome::Pass, Outcome::Pass);
+    let manager = manager(&[&deferrer, &passer]);
+
+    assert!(texts(&manager).await.is_empty());
+    assert_eq!(
+        unanswered_steps(&manager),
+        vec![
+            step("Deferrer", Verdict::Deferred),
+            step("Passer", Verdict::NotHandled),
+            step("Deferrer", Verdict::Handled),
+        ]
+    );
+}
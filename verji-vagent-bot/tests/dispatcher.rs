@@ -0,0 +1,257 @@
+//! Responder output to room messages: the content each kind of outgoing
+//! message becomes, and how a batch of them is delivered
+
+use anyhow::Result;
+use async_trait::async_trait;
+use matrix_sdk::ruma::events::room::message::{MessageType, RoomMessageEventContent};
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::dispatcher::{message_content, send_all};
+use verji_vagent_bot::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::runtime::Runtime;
+use verji_vagent_bot::sent_events::{SentEventRegistry, SentKind};
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+
+/// Body, formatted HTML and whether it is a notice
+fn shown(content: &RoomMessageEventContent) -> (&str, Option<&str>, bool) {
+    match &content.msgtype {
+        MessageType::Text(text) => (
+            text.body.as_str(),
+            text.formatted
+                .as_ref()
+                .map(|formatted| formatted.body.as_str()),
+            false,
+        ),
+        MessageType::Notice(notice) => (
+            notice.body.as_str(),
+            notice
+                .formatted
+                .as_ref()
+                .map(|formatted| formatted.body.as_str()),
+            true,
+        ),
+        other => panic!("unexpected message type {}", other.msgtype()),
+    }
+}
+
+#[test]
+fn text_is_sent_as_plain_text() {
+    let content = message_content(&OutgoingMessage::Text("*not bold*".to_string())).unwrap();
+    assert_eq!(shown(&content), ("*not bold*", None, false));
+    assert!(content.mentions.is_none());
+}
+
+#[test]
+fn markdown_gets_a_formatted_body() {
+    let content = message_content(&OutgoingMessage::Markdown("**Commands**".to_string())).unwrap();
+    let (body, formatted, notice) = shown(&content);
+    assert_eq!((body, notice), ("**Commands**", false));
+    let formatted = formatted.expect("formatted body");
+    assert!(
+        formatted.contains("<strong>Commands</strong>"),
+        "{}",
+        formatted
+    );
+}
+
+#[test]
+fn notices_are_plain_notices() {
+    let content = message_content(&OutgoingMessage::Notice("Rate limited".to_string())).unwrap();
+    assert_eq!(shown(&content), ("Rate limited", None, true));
+}
+
+#[test]
+fn reactions_and_attachments_have_no_message_content() {
+    assert!(message_content(&OutgoingMessage::Reaction("👀".to_string())).is_none());
+    let attachment = OutgoingMessage::Attachment {
+        filename: "export.json".to_string(),
+        content_type: "application/json".to_string(),
+        data: b"{}".to_vec(),
+    };
+    assert!(message_content(&attachment).is_none());
+}
+
+fn registry() -> SentEventRegistry {
+    SentEventRegistry::in_memory(100, Duration::from_secs(3600))
+}
+
+/// ID the mock room gave its `index`th message, counting from 1
+fn mock_event(index: usize) -> OwnedEventId {
+    EventId::parse(format!("$mock{}:localhost", index)).unwrap()
+}
+
+#[tokio::test]
+async fn a_batch_is_sent_in_order_and_recorded() {
+    let room = MockRoom::new("!dispatch-order:localhost").unwrap();
+    let trigger = EventId::parse("$trigger:localhost").unwrap();
+    let sent_events = registry();
+    sent_events.link_request(&trigger, "req-1");
+
+    let messages = vec![
+        OutgoingMessage::Reaction("👀".to_string()),
+        OutgoingMessage::Markdown("**Answer**".to_string()),
+        OutgoingMessage::Notice("Sources: handbook".to_string()),
+    ];
+    let runtime = Runtime::default();
+    let sent = send_all(
+        &runtime,
+        &room,
+        &trigger,
+        None,
+        None,
+        messages,
+        &sent_events,
+        None,
+    )
+    .await;
+
+    assert_eq!(sent, 3);
+    let delivered: Vec<String> = room
+        .sent()
+        .iter()
+        .map(|message| format!("{:?}", message))
+        .collect();
+    assert_eq!(
+        delivered,
+        vec![
+            "Reaction(\"👀\")",
+            "Markdown(\"**Answer**\")",
+            "Notice(\"Sources: handbook\")"
+        ]
+    );
+
+    // The reaction is an acknowledgement, the rest answers the linked request
+    let kinds = [SentKind::Ack, SentKind::Final, SentKind::Final];
+    for (index, kind) in kinds.into_iter().enumerate() {
+        let event = sent_events
+            .lookup(&mock_event(index + 1))
+            .await
+            .expect("recorded");
+        assert_eq!(event.kind, kind);
+        assert_eq!(event.request_id.as_deref(), Some("req-1"));
+        assert_eq!(event.room_id, "!dispatch-order:localhost");
+    }
+    assert!(sent_events.answered("req-1").await);
+}
+
+#[tokio::test]
+async fn output_without_a_request_is_still_recorded() {
+    let room = MockRoom::new("!dispatch-plain:localhost").unwrap();
+    let trigger = EventId::parse("$trigger:localhost").unwrap();
+    let sent_events = registry();
+
+    let messages = vec![OutgoingMessage::Text("Pong!".to_string())];
+    let runtime = Runtime::default();
+    let sent = send_all(
+        &runtime,
+        &room,
+        &trigger,
+        None,
+        None,
+        messages,
+        &sent_events,
+        None,
+    )
+    .await;
+
+    assert_eq!(sent, 1);
+    let event = sent_events.lookup(&mock_event(1)).await.expect("recorded");
+    assert_eq!(event.kind, SentKind::Final);
+    assert_eq!(event.request_id, None);
+}
+
+#[tokio::test]
+async fn nothing_is_sent_after_leaving_the_room() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let trigger = EventId::parse("$trigger:localhost").unwrap();
+    harness.kick();
+
+    let messages = vec![
+        OutgoingMessage::Text("One".to_string()),
+        OutgoingMessage::Text("Two".to_string()),
+    ];
+    let sent = send_all(
+        harness.runtime(),
+        harness.mock_room(),
+        &trigger,
+        None,
+        None,
+        messages,
+        &registry(),
+        None,
+    )
+    .await;
+    harness.rejoin();
+
+    assert_eq!(sent, 0);
+    assert!(harness.mock_room().sent().is_empty());
+}
+
+/// Answers with a reaction, markdown and a notice
+struct Rich;
+
+#[async_trait]
+impl Responder for Rich {
+    fn name(&self) -> &str {
+        "Rich"
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::HandledWithContent(vec![
+            OutgoingMessage::Reaction("✅".to_string()),
+            OutgoingMessage::Markdown("# Done".to_string()),
+            OutgoingMessage::Notice("Took 2s".to_string()),
+        ]))
+    }
+}
+
+/// Answers with a plain string
+struct Plain;
+
+#[async_trait]
+impl Responder for Plain {
+    fn name(&self) -> &str {
+        "Plain"
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some("Plain answer".to_string())))
+    }
+}
+
+#[tokio::test]
+async fn rich_output_reaches_the_room_as_returned() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let messages = harness
+        .respond(Arc::new(Rich), "Hello")
+        .await
+        .expect("dispatch");
+
+    assert_eq!(messages.len(), 3);
+    assert!(matches!(&messages[0], OutgoingMessage::Reaction(key) if key == "✅"));
+    assert!(matches!(&messages[1], OutgoingMessage::Markdown(body) if body == "# Done"));
+    assert!(matches!(&messages[2], OutgoingMessage::Notice(body) if body == "Took 2s"));
+    harness.assert_sent_texts(&["# Done", "Took 2s"]);
+}
+
+#[tokio::test]
+async fn plain_answers_are_sent_as_text() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let messages = harness
+        .respond(Arc::new(Plain), "Hello")
+        .await
+        .expect("dispatch");
+
+    assert_eq!(messages.len(), 1);
+    assert!(matches!(&messages[0], OutgoingMessage::Text(_)));
+    assert_eq!(message_text(&messages[0]), Some("Plain answer"));
+}
@@ -0,0 +1,172 @@
+//! End-to-end tests against a disposable homeserver
+//!
+//! Ignored by default. Start a throwaway Synapse with open registration and
+//! point `E2E_HOMESERVER` at it:
+//!
+//! ```sh
+//! docker run -d --name vagent-e2e-synapse -p 8008:8008 \
+//!     -e SYNAPSE_SERVER_NAME=localhost -e SYNAPSE_REPORT_STATS=no \
+//!     --entrypoint sh matrixdotorg/synapse:latest -c \
+//!     '/start.py generate && printf "enable_registration: true\nenable_registration_without_verification: true\nrc_registration: {per_second: 100, burst_count: 100}\nrc_login: {address: {per_second: 100, burst_count: 100}, account: {per_second: 100, burst_count: 100}}\n" >> /data/homeserver.yaml && exec /start.py'
+//! E2E_HOMESERVER=http://localhost:8008 cargo test --test e2e_synapse -- --ignored
+//! docker rm -f vagent-e2e-synapse
+//! ```
+//!
+//! The bot runs in-process with `GRAPH_TRANSPORT=mock`, so neither Redis nor
+//! vagent-graph is needed. Every run registers fresh users and keeps its
+//! stores in temporary directories.
+
+mod test_support;
+
+use anyhow::Result;
+use matrix_sdk::ruma::{events::room::message::RoomMessageEventContent, UserId};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use verji_vagent_bot::db::TempStore;
+use verji_vagent_bot::{client, BotBuilder, BotConfig};
+
+use test_support::MessageFeed;
+
+/// Upper bound for one test, including the bot's startup
+const TEST_BUDGET: Duration = Duration::from_secs(90);
+
+/// How long to wait for an answer before asking again
+///
+/// Messages sent before the bot's first sync finishes are never answered.
+const RESEND_AFTER: Duration = Duration::from_secs(10);
+
+macro_rules! homeserver_or_skip {
+    () => {
+        match test_support::homeserver() {
+            Some(homeserver) => homeserver,
+            None => {
+                eprintln!("E2E_HOMESERVER is not set, skipping");
+                return Ok(());
+            }
+        }
+    };
+}
+
+/// A fresh login saves a session that the next start restores
+#[tokio::test]
+#[ignore = "needs a homeserver (E2E_HOMESERVER)"]
+async fn session_survives_restart() -> Result<()> {
+    let homeserver = homeserver_or_skip!();
+    let bot = test_support::register_user(&homeserver, "bot").await?;
+    let store = TempStore::new("bot")?;
+    let store_path = store.path().to_path_buf();
+    let session_file = store_path.join("session.json");
+
+    let (first, source) = client::fresh_login(
+        &homeserver,
+        &bot.username,
+        &bot.password,
+        &store_path.to_string_lossy(),
+        &store_path,
+        &bot.password,
+        &session_file,
+        "e2e bot",
+    )
+    .await?;
+    assert_eq!(source, "new_login");
+    assert!(session_file.exists(), "fresh login didn't save the session");
+    let device_id = first.device_id().expect("logged in").to_owned();
+    drop(first);
+
+    let (restored, source) = client::restore_or_login(
+        &session_file,
+        &homeserver,
+        &bot.username,
+        &bot.password,
+        &store_path,
+        &bot.password,
+        "e2e bot",
+    )
+    .await?;
+    assert_eq!(source, "restored");
+    assert_eq!(restored.device_id(), Some(&*device_id));
+    restored.whoami().await?;
+    Ok(())
+}
+
+/// A message in an encrypted room gets an encrypted answer from the pipeline
+#[tokio::test]
+#[ignore = "needs a homeserver (E2E_HOMESERVER)"]
+async fn answers_in_encrypted_room() -> Result<()> {
+    let homeserver = homeserver_or_skip!();
+    let started = Instant::now();
+
+    let bot = test_support::register_user(&homeserver, "bot").await?;
+    let alice = test_support::register_user(&homeserver, "alice").await?;
+    let bot_store = TempStore::new("bot")?;
+    let alice_store = TempStore::new("alice")?;
+    let bot_store_path = bot_store.path().to_path_buf();
+
+    // The bot doesn't accept invites itself: join with its own device first,
+    // then let BotBuilder restore that session
+    let (bot_client, _) = client::fresh_login(
+        &homeserver,
+        &bot.username,
+        &bot.password,
+        &bot_store_path.to_string_lossy(),
+        &bot_store_path,
+        &bot.password,
+        &bot_store_path.join("session.json"),
+        "e2e bot",
+    )
+    .await?;
+    let bot_user_id = bot_client.user_id().expect("logged in").to_owned();
+
+    let alice_client = test_support::login(&homeserver, &alice, &alice_store).await?;
+    let room = test_support::create_encrypted_room(&alice_client, &bot_user_id).await?;
+    let room_id = room.room_id().to_owned();
+    test_support::join_when_invited(&bot_client, &room_id, Duration::from_secs(20)).await?;
+    drop(bot_client);
+
+    std::env::set_var("MATRIX_HOMESERVER", &homeserver);
+    std::env::set_var("MATRIX_USER", &bot.username);
+    std::env::set_var("MATRIX_PASSWORD", &bot.password);
+    std::env::set_var("MATRIX_STORE_PATH", &bot_store_path);
+    std::env::set_var("GRAPH_TRANSPORT", "mock");
+    std::env::set_var(
+        "GRAPH_MOCK_SCENARIOS",
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/mock_graph/happy_path.json"),
+    );
+    let bot_task = tokio::spawn(BotBuilder::new(BotConfig::from_env()).run());
+
+    let mut feed = MessageFeed::start(&alice_client);
+    let result = ask_until_answered(&room, &mut feed, &bot_user_id, started).await;
+    bot_task.abort();
+    let answer = result?;
+
+    assert_eq!(answer.room_id, room_id);
+    assert!(answer.encryption.is_some(), "the answer wasn't encrypted");
+    let _ = room.leave().await;
+    Ok(())
+}
+
+/// Send pings until the bot echoes one, resending while it starts up
+async fn ask_until_answered(
+    room: &matrix_sdk::Room,
+    feed: &mut MessageFeed,
+    bot: &UserId,
+    started: Instant,
+) -> Result<test_support::ReceivedMessage> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let token = format!("e2e ping {}", attempt);
+        room.send(RoomMessageEventContent::text_plain(&token)).await?;
+
+        let left = TEST_BUDGET.saturating_sub(started.elapsed());
+        let wait = RESEND_AFTER.min(left);
+        match feed
+            .next_from(bot, wait, |message| message.body.contains(&token))
+            .await
+        {
+            Ok(answer) => return Ok(answer),
+            Err(e) if wait == left => return Err(e),
+            Err(_) => continue,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! `!echo` through the manager, next to ping and the agent
+
+use std::sync::Arc;
+use verji_vagent_bot::responder::OutgoingMessage;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::{EchoResponder, PingPongResponder, VerjiAgentResponder};
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+fn harness() -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string())
+}
+
+/// The commands at priority 100 ahead of an agent that echoes every query
+fn manager() -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(EchoResponder::new()));
+    manager.register(Arc::new(PingPongResponder::new()));
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::new(MockScript::default())),
+    )));
+    manager
+}
+
+fn texts(messages: &[OutgoingMessage]) -> Vec<&str> {
+    messages
+        .iter()
+        .map(|message| message_text(message).expect("text reply"))
+        .collect()
+}
+
+#[tokio::test]
+async fn echo_repeats_the_text_as_sent() {
+    let harness = harness();
+    let manager = manager();
+
+    let messages = harness
+        .dispatch(&manager, "!echo  hello   \"world\"")
+        .await
+        .expect("dispatch");
+    assert_eq!(texts(&messages), vec!["hello   \"world\""]);
+
+    let messages = harness
+        .dispatch(&manager, "!echo ünïcödé ✓")
+        .await
+        .expect("dispatch");
+    assert_eq!(texts(&messages), vec!["ünïcödé ✓"]);
+}
+
+#[tokio::test]
+async fn bare_echo_gets_usage() {
+    let harness = harness();
+    let manager = manager();
+
+    let messages = harness.dispatch(&manager, "!echo").await.expect("dispatch");
+    assert_eq!(texts(&messages), vec!["Usage: `!echo <text>`"]);
+}
+
+#[tokio::test]
+async fn other_messages_fall_through_to_the_agent() {
+    let harness = harness();
+    let manager = manager();
+
+    for body in ["echo this please", "!echoes", "hello"] {
+        let messages = harness.dispatch(&manager, body).await.expect("dispatch");
+        let expected = format!("Echo: {}", body);
+        assert_eq!(texts(&messages), vec![expected.as_str()], "{}", body);
+    }
+    // ... while ping is still answered in the same slot
+    let messages = harness.dispatch(&manager, "!ping").await.expect("dispatch");
+    assert_eq!(texts(&messages), vec!["Pong!"]);
+}
@@ -0,0 +1,132 @@
+//! How failures map onto the exit codes a supervisor acts on
+
+use anyhow::{anyhow, Context, Result};
+use verji_vagent_bot::cli::{self, Command};
+use verji_vagent_bot::error::{exit_code, exit_code_of, BotError};
+use verji_vagent_bot::BotConfig;
+
+fn failed(class: BotError) -> Result<()> {
+    Err(anyhow!("underlying failure")).context(class)
+}
+
+fn sqlite_failure(code: std::os::raw::c_int) -> Result<()> {
+    Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(code),
+        None,
+    ))
+    .context("Failed to open the usage statistics database")
+}
+
+#[test]
+fn clean_shutdown_exits_zero() {
+    assert_eq!(exit_code_of(&Ok(())), exit_code::CLEAN);
+    assert_eq!(exit_code::CLEAN, 0);
+}
+
+#[test]
+fn configuration_and_credentials_are_not_worth_a_restart() {
+    assert_eq!(exit_code_of(&failed(BotError::Config)), 10);
+    assert_eq!(exit_code_of(&failed(BotError::AuthFailed)), 10);
+}
+
+#[test]
+fn infrastructure_failures_are_worth_a_restart() {
+    for class in [
+        BotError::RateLimited { retry_after: None },
+        BotError::NetworkTransient,
+        BotError::GraphTimeout,
+        BotError::GraphUnavailable,
+    ] {
+        assert_eq!(exit_code_of(&failed(class.clone())), 20, "{:?}", class);
+        assert!(class.is_transient(), "{:?}", class);
+    }
+}
+
+#[test]
+fn store_problems_need_an_operator() {
+    assert_eq!(exit_code_of(&failed(BotError::StoreCorrupt)), 30);
+    assert_eq!(exit_code_of(&failed(BotError::CryptoStoreMismatch)), 30);
+    assert_eq!(
+        exit_code_of(&sqlite_failure(rusqlite::ffi::SQLITE_CORRUPT)),
+        30
+    );
+    assert_eq!(
+        exit_code_of(&sqlite_failure(rusqlite::ffi::SQLITE_NOTADB)),
+        30
+    );
+}
+
+#[test]
+fn other_sqlite_failures_are_not_store_corruption() {
+    assert_eq!(
+        exit_code_of(&sqlite_failure(rusqlite::ffi::SQLITE_BUSY)),
+        20
+    );
+}
+
+#[test]
+fn unrecognized_errors_count_as_transient() {
+    assert_eq!(exit_code_of(&Err(anyhow!("something odd"))), 20);
+}
+
+#[test]
+fn the_class_is_found_anywhere_in_the_chain() {
+    let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+    let result: Result<()> = Err(redis::RedisError::from(refused))
+        .context("Failed to connect to Redis")
+        .context("Failed to start the responders");
+    assert_eq!(exit_code_of(&result), 20);
+
+    let missing = verji_vagent_bot::config::env_required("EXIT_CODES_TEST_UNSET_VARIABLE");
+    assert_eq!(exit_code_of(&missing.map(|_| ())), 10);
+}
+
+#[test]
+fn every_class_has_one_of_the_documented_codes() {
+    for class in [
+        BotError::Config,
+        BotError::StoreCorrupt,
+        BotError::CryptoStoreMismatch,
+        BotError::AuthFailed,
+        BotError::RateLimited { retry_after: None },
+        BotError::NetworkTransient,
+        BotError::GraphTimeout,
+        BotError::GraphUnavailable,
+    ] {
+        assert!(
+            [exit_code::CONFIG, exit_code::TRANSIENT, exit_code::STORE]
+                .contains(&class.exit_code()),
+            "{:?}",
+            class
+        );
+        assert_eq!(
+            class.is_transient(),
+            class.exit_code() == exit_code::TRANSIENT
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_failed_send_exits_non_zero() {
+    let store = std::env::temp_dir().join(format!("exit-codes-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&store).expect("store directory");
+    let mut config = BotConfig::from_env();
+    config.store_path = store.clone();
+    let send = |message: &str| Command::Send {
+        room: "#ops:example.org".to_string(),
+        message: message.to_string(),
+        markdown: false,
+        notice: false,
+    };
+
+    // Nothing to send
+    let empty = cli::run(send("  "), &config).await;
+    assert_eq!(exit_code_of(&empty), exit_code::CONFIG);
+
+    // The bot never logged in with this store
+    let no_session = cli::run(send("Deploy done"), &config).await;
+    assert!(format!("{:#}", no_session.as_ref().unwrap_err()).contains("No saved session"));
+    assert_eq!(exit_code_of(&no_session), exit_code::CONFIG);
+
+    std::fs::remove_dir_all(&store).ok();
+}
@@ -0,0 +1,33 @@
+//! `!help`: only the command itself asks for it
+
+use std::sync::Arc;
+use verji_vagent_bot::responders::HelpResponder;
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+
+async fn help(body: &str) -> Vec<String> {
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string());
+    harness
+        .respond(Arc::new(HelpResponder::new()), body)
+        .await
+        .expect("respond")
+        .iter()
+        .filter_map(message_text)
+        .map(str::to_string)
+        .collect()
+}
+
+#[tokio::test]
+async fn the_command_lists_the_commands() {
+    let messages = help("!help").await;
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    assert!(messages[0].contains("`!status`"), "{}", messages[0]);
+}
+
+#[tokio::test]
+async fn the_bare_word_is_left_to_the_agent() {
+    for body in ["help", "help me with the Q3 report", "Help"] {
+        assert!(help(body).await.is_empty(), "{}", body);
+    }
+}
@@ -0,0 +1,186 @@
+//! Which room messages a user could have seen themselves, for every history
+//! visibility and membership, and the context fetched for them
+
+use verji_vagent_bot::room_context::{
+    fetch_recent, visible_to, HistoryMessage, HistoryVisibility, Membership, MembershipChange,
+};
+use verji_vagent_bot::testing::MockRoom;
+
+const VISIBILITIES: [HistoryVisibility; 4] = [
+    HistoryVisibility::WorldReadable,
+    HistoryVisibility::Shared,
+    HistoryVisibility::Invited,
+    HistoryVisibility::Joined,
+];
+
+const MEMBERSHIPS: [Membership; 5] = [
+    Membership::Invite,
+    Membership::Join,
+    Membership::Knock,
+    Membership::Leave,
+    Membership::Ban,
+];
+
+fn message(timestamp_ms: u64) -> HistoryMessage {
+    HistoryMessage {
+        event_id: format!("$m{}:localhost", timestamp_ms),
+        sender: "@other:localhost".to_string(),
+        body: format!("Sent at {}", timestamp_ms),
+        timestamp_ms,
+    }
+}
+
+fn change(membership: Membership, timestamp_ms: u64) -> MembershipChange {
+    MembershipChange {
+        membership,
+        timestamp_ms,
+    }
+}
+
+/// Timestamps of the messages `visible_to` keeps
+fn visible(
+    visibility: HistoryVisibility,
+    memberships: &[MembershipChange],
+    sent_at: &[u64],
+) -> Vec<u64> {
+    let messages = sent_at.iter().copied().map(message).collect();
+    visible_to(visibility, memberships, messages)
+        .into_iter()
+        .map(|message| message.timestamp_ms)
+        .collect()
+}
+
+#[test]
+fn every_visibility_against_every_membership() {
+    // Before the only change, at the same moment, and after it
+    let sent_at = [500, 1_000, 2_000];
+    for visibility in VISIBILITIES {
+        for membership in MEMBERSHIPS {
+            let expected: &[u64] = match (visibility, membership) {
+                (HistoryVisibility::WorldReadable | HistoryVisibility::Shared, _) => {
+                    &[500, 1_000, 2_000]
+                }
+                (HistoryVisibility::Invited, Membership::Invite | Membership::Join) => {
+                    &[1_000, 2_000]
+                }
+                (HistoryVisibility::Joined, Membership::Join) => &[1_000, 2_000],
+                _ => &[],
+            };
+            assert_eq!(
+                visible(visibility, &[change(membership, 1_000)], &sent_at),
+                expected,
+                "{:?} room, {:?}",
+                visibility,
+                membership
+            );
+        }
+    }
+}
+
+#[test]
+fn without_known_memberships_only_open_rooms_share_history() {
+    for visibility in VISIBILITIES {
+        let expected: &[u64] = match visibility {
+            HistoryVisibility::WorldReadable | HistoryVisibility::Shared => &[500, 1_000],
+            HistoryVisibility::Invited | HistoryVisibility::Joined => &[],
+        };
+        assert_eq!(
+            visible(visibility, &[], &[500, 1_000]),
+            expected,
+            "{:?}",
+            visibility
+        );
+    }
+}
+
+#[test]
+fn each_message_is_judged_by_the_membership_when_it_was_sent() {
+    let memberships = [
+        change(Membership::Invite, 1_000),
+        change(Membership::Join, 2_000),
+        change(Membership::Leave, 3_000),
+        change(Membership::Join, 4_000),
+        change(Membership::Ban, 5_000),
+    ];
+    let sent_at = [500, 1_500, 2_500, 3_500, 4_500, 5_500];
+
+    assert_eq!(
+        visible(HistoryVisibility::Invited, &memberships, &sent_at),
+        vec![1_500, 2_500, 4_500]
+    );
+    assert_eq!(
+        visible(HistoryVisibility::Joined, &memberships, &sent_at),
+        vec![2_500, 4_500]
+    );
+    assert_eq!(
+        visible(HistoryVisibility::Shared, &memberships, &sent_at),
+        sent_at
+    );
+
+    // The order membership changes are given in doesn't matter
+    let mut shuffled = memberships;
+    shuffled.reverse();
+    shuffled.swap(1, 3);
+    assert_eq!(
+        visible(HistoryVisibility::Joined, &shuffled, &sent_at),
+        vec![2_500, 4_500]
+    );
+}
+
+#[test]
+fn messages_keep_their_order() {
+    let memberships = [change(Membership::Join, 0)];
+    assert_eq!(
+        visible(
+            HistoryVisibility::Joined,
+            &memberships,
+            &[3_000, 1_000, 2_000]
+        ),
+        vec![3_000, 1_000, 2_000]
+    );
+}
+
+#[tokio::test]
+async fn the_fetched_context_leaves_out_unseen_messages_and_commands() {
+    // History timestamps are a second apart, starting at 0
+    let room = MockRoom::new("!test:localhost")
+        .expect("room")
+        .with_history(&[
+            ("@other:localhost", "Before the invite"),
+            ("@other:localhost", "While invited"),
+            ("@user:localhost", "!ping"),
+            ("@other:localhost", "After joining"),
+        ])
+        .with_history_visibility(HistoryVisibility::Joined)
+        .with_memberships(
+            "@user:localhost",
+            &[(Membership::Invite, 1_000), (Membership::Join, 2_000)],
+        );
+
+    let bodies = |messages: Vec<HistoryMessage>| -> Vec<String> {
+        messages.into_iter().map(|message| message.body).collect()
+    };
+    let messages = fetch_recent(&room, "@user:localhost", 10)
+        .await
+        .expect("fetch");
+    assert_eq!(bodies(messages), vec!["After joining"]);
+
+    // Another member without a known membership sees nothing
+    let messages = fetch_recent(&room, "@stranger:localhost", 10)
+        .await
+        .expect("fetch");
+    assert!(messages.is_empty(), "{:?}", messages);
+
+    // In a shared room everyone sees everything but the command
+    let room = MockRoom::new("!test:localhost")
+        .expect("room")
+        .with_history(&[
+            ("@other:localhost", "Before the invite"),
+            ("@user:localhost", "  !ping"),
+            ("@other:localhost", "After joining"),
+        ]);
+    let messages = fetch_recent(&room, "@stranger:localhost", 10)
+        .await
+        .expect("fetch");
+    assert_eq!(bodies(messages), vec!["Before the invite", "After joining"]);
+}
@@ -0,0 +1,258 @@
+//! Multi-step HITL forms: the state machine, a form driven message by
+//! message through the conversation store, and timeouts claiming questions
+
+use serde_json::{json, Value};
+use verji_vagent_bot::config::BotConfig;
+use verji_vagent_bot::conversation::{ConversationKey, ConversationStore};
+use verji_vagent_bot::hitl::form::{FieldError, FormStep};
+use verji_vagent_bot::hitl::{self, Answer, Form, PendingHitl, HITL_SLOT};
+use verji_vagent_bot::testing::ResponderTestHarness;
+
+/// Which customer, how many days, which format
+fn metadata() -> Value {
+    json!({
+        "form": {
+            "fields": [
+                {
+                    "name": "customer",
+                    "prompt": "Which customer?",
+                    "validation": "[A-Z]{3,}-\\d+"
+                },
+                {"name": "days", "prompt": "How many days back?", "type": "number"},
+                {
+                    "name": "format",
+                    "prompt": "Which format?",
+                    "type": "choice",
+                    "options": ["PDF", "CSV"]
+                }
+            ]
+        }
+    })
+}
+
+fn form() -> Form {
+    Form::from_metadata(&metadata())
+        .expect("valid form")
+        .expect("a form")
+}
+
+fn is_ask(step: &FormStep) -> bool {
+    matches!(step, FormStep::Ask)
+}
+
+#[test]
+fn a_form_is_walked_through_with_one_failed_answer() {
+    let mut form = form();
+    assert_eq!(form.render_current(), "**(1/3)** Which customer?");
+
+    assert!(is_ask(&form.advance("ACME-42")));
+    assert_eq!(form.render_current(), "**(2/3)** How many days back?");
+
+    // The failure keeps the form on the same field, without an answer
+    assert!(matches!(
+        form.advance("a fortnight"),
+        FormStep::Invalid(FieldError::NotANumber)
+    ));
+    assert_eq!(form.step, 1);
+    assert!(!form.answers.contains_key("days"));
+
+    assert!(is_ask(&form.advance(" 14 ")));
+    assert_eq!(
+        form.render_current(),
+        "**(3/3)** Which format?\n\n`PDF` / `CSV`"
+    );
+
+    match form.advance("csv") {
+        FormStep::Complete(answers) => assert_eq!(
+            Value::Object(answers),
+            json!({"customer": "ACME-42", "days": 14, "format": "CSV"})
+        ),
+        other => panic!("expected the form to complete, got {:?}", other),
+    }
+}
+
+#[test]
+fn answers_are_validated_by_field() {
+    let cases = [
+        (0, "acme-42", Err(FieldError::NoMatch)),
+        (0, "ACME-42 and more", Err(FieldError::NoMatch)),
+        (0, "ACME-42", Ok(json!("ACME-42"))),
+        (1, "ten", Err(FieldError::NotANumber)),
+        (1, "7", Ok(json!(7))),
+        (1, "2.5", Ok(json!(2.5))),
+        (2, "XLSX", Err(FieldError::NotAnOption)),
+        (2, "pdf", Ok(json!("PDF"))),
+    ];
+    for (step, input, expected) in cases {
+        let mut form = form();
+        form.step = step;
+        let name = form.current().name.clone();
+        let outcome = match form.advance(input) {
+            FormStep::Invalid(error) => Err(error),
+            FormStep::Ask | FormStep::Complete(_) => Ok(form.answers[&name].clone()),
+            FormStep::Cancelled => panic!("{:?} cancelled the form", input),
+        };
+        assert_eq!(outcome, expected, "{:?} for {}", input, name);
+    }
+}
+
+#[test]
+fn back_forgets_the_previous_answer_and_cancel_stops() {
+    let mut form = form();
+    form.advance("ACME-42");
+    form.advance("14");
+    assert_eq!(form.step, 2);
+
+    assert!(is_ask(&form.advance("BACK")));
+    assert_eq!(form.step, 1);
+    assert!(!form.answers.contains_key("days"));
+    assert!(form.answers.contains_key("customer"));
+
+    // Going back from the first field stays there
+    form.advance("back");
+    assert!(is_ask(&form.advance("back")));
+    assert_eq!(form.step, 0);
+
+    assert!(matches!(form.advance(" Cancel "), FormStep::Cancelled));
+}
+
+#[test]
+fn malformed_forms_are_rejected() {
+    assert!(Form::from_metadata(&json!({"question": "Sure?"}))
+        .expect("no form")
+        .is_none());
+
+    let cases = [
+        (json!({"form": {"fields": []}}), "HITL form has no fields"),
+        (
+            json!({"form": {"fields": [{"name": "x", "prompt": "X?", "validation": "("}]}}),
+            "Invalid validation pattern for field 'x'",
+        ),
+        (
+            json!({"form": {"fields": [{"name": "x", "prompt": "X?", "type": "choice"}]}}),
+            "Choice field 'x' has no options",
+        ),
+        (
+            json!({"form": {"fields": [{"name": "x"}]}}),
+            "Malformed HITL form metadata",
+        ),
+    ];
+    for (metadata, error) in cases {
+        let e = Form::from_metadata(&metadata).expect_err(error);
+        assert!(format!("{:#}", e).starts_with(error), "{:#}", e);
+    }
+}
+
+fn reply(answer: Answer) -> String {
+    match answer {
+        Answer::Reply(text) => text,
+        _ => panic!("expected a reply"),
+    }
+}
+
+/// Answer the question stored for `key` with `input`, reading it back from
+/// the store first as the bot does for every message
+async fn answer(
+    harness: &ResponderTestHarness,
+    key: &ConversationKey,
+    input: &str,
+    config: &BotConfig,
+) -> Answer {
+    let store = harness.conversations();
+    hitl::answer(
+        store,
+        harness.follow_ups(),
+        key,
+        stored(store, key).await,
+        input,
+        config,
+        &harness.runtime().catalog,
+    )
+    .await
+}
+
+async fn stored(store: &ConversationStore, key: &ConversationKey) -> PendingHitl {
+    PendingHitl::from_value(store.get(key).await.expect("pending")).expect("a question")
+}
+
+#[tokio::test]
+async fn progress_is_kept_in_the_conversation_store() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let store = harness.conversations();
+    let config = BotConfig::from_env();
+    let key = ConversationKey::new("!test:localhost", "@user:localhost", HITL_SLOT);
+    let pending = PendingHitl {
+        request_id: "req-form".to_string(),
+        question: "I need a few details for the report.".to_string(),
+        options: Vec::new(),
+        event_id: "$question:localhost".to_string(),
+        language: "en".to_string(),
+        asked_at: 0,
+        form: Some(form()),
+    };
+    hitl::ask(
+        store,
+        harness.follow_ups(),
+        key.clone(),
+        &pending,
+        &config,
+        &harness.runtime().catalog,
+    )
+    .await;
+
+    let text = reply(answer(&harness, &key, "ACME-42", &config).await);
+    assert!(text.contains("**(2/3)** How many days back?"), "{}", text);
+    assert_eq!(stored(store, &key).await.form.unwrap().step, 1);
+
+    let text = reply(answer(&harness, &key, "soon", &config).await);
+    assert!(
+        text.starts_with("⚠️ That doesn't look like a number.\n\n**(2/3)**"),
+        "{}",
+        text
+    );
+    assert_eq!(stored(store, &key).await.form.unwrap().step, 1);
+
+    reply(answer(&harness, &key, "14", &config).await);
+    match answer(&harness, &key, "PDF", &config).await {
+        Answer::Resume {
+            request_id,
+            payload,
+        } => {
+            assert_eq!(request_id, "req-form");
+            assert_eq!(
+                payload,
+                Some(json!({"customer": "ACME-42", "days": 14, "format": "PDF"}))
+            );
+        }
+        _ => panic!("expected the form to resume the graph"),
+    }
+    // Resolved: nothing is left waiting
+    assert!(store.get(&key).await.is_none());
+}
+
+#[tokio::test]
+async fn a_timeout_claims_only_its_own_question() {
+    let store = ConversationStore::in_memory(100);
+    let key = ConversationKey::new("!test:localhost", "@user:localhost", HITL_SLOT);
+    let pending = |request_id: &str| PendingHitl {
+        request_id: request_id.to_string(),
+        question: "Send the reminders?".to_string(),
+        options: Vec::new(),
+        event_id: "$question:localhost".to_string(),
+        language: "en".to_string(),
+        asked_at: 0,
+        form: None,
+    };
+    let value = |request_id: &str| serde_json::to_value(pending(request_id)).expect("serialize");
+
+    // The question was answered and a newer one asked before the timeout ran
+    store.set(key.clone(), value("req-new"), None).await;
+    assert!(hitl::claim(&store, &key, "req-old").await.is_none());
+    assert_eq!(stored(&store, &key).await.request_id, "req-new");
+
+    let claimed = hitl::claim(&store, &key, "req-new").await.expect("claimed");
+    assert_eq!(claimed.request_id, "req-new");
+    assert!(store.get(&key).await.is_none());
+    // Claimed once only
+    assert!(hitl::claim(&store, &key, "req-new").await.is_none());
+}
@@ -0,0 +1,165 @@
+//! What becomes of emotes, stickers and the other msgtypes, over synthetic
+//! events as the homeserver sends them
+
+use matrix_sdk::ruma::events::room::message::OriginalSyncRoomMessageEvent;
+use matrix_sdk::ruma::events::sticker::OriginalSyncStickerEvent;
+use serde_json::{json, Value};
+use verji_vagent_bot::incoming::{
+    classify, emote_query, sticker_action, sticker_query, Incoming, StickerAction,
+};
+use verji_vagent_bot::room_config::StickerMode;
+
+const BOT: &str = "@vagent:localhost";
+
+fn event(content: Value, event_type: &str) -> Value {
+    json!({
+        "type": event_type,
+        "event_id": "$event:localhost",
+        "sender": "@alice:localhost",
+        "origin_server_ts": 1_700_000_000_000u64,
+        "content": content,
+    })
+}
+
+fn message(content: Value) -> OriginalSyncRoomMessageEvent {
+    serde_json::from_value(event(content, "m.room.message")).expect("message event")
+}
+
+fn classified(content: Value, mentions_bot: bool) -> Incoming {
+    classify(message(content).content.msgtype, mentions_bot, BOT)
+}
+
+#[test]
+fn text_is_routed_as_it_is() {
+    assert_eq!(
+        classified(json!({"msgtype": "m.text", "body": "What's new?"}), false),
+        Incoming::Text("What's new?".to_string())
+    );
+}
+
+#[test]
+fn emotes_are_routed_only_when_they_address_the_bot() {
+    let addressed = [
+        // By name, in any case
+        (
+            json!({"msgtype": "m.emote", "body": "waves at vagent"}),
+            false,
+        ),
+        (json!({"msgtype": "m.emote", "body": "pokes VAgent"}), false),
+        // By user ID
+        (
+            json!({"msgtype": "m.emote", "body": "asks @vagent:localhost for help"}),
+            false,
+        ),
+        // In m.mentions
+        (
+            json!({"msgtype": "m.emote", "body": "waves at the bot"}),
+            true,
+        ),
+    ];
+    for (content, mentions_bot) in addressed {
+        let body = content["body"].as_str().unwrap().to_string();
+        assert_eq!(classified(content, mentions_bot), Incoming::Emote(body));
+    }
+
+    assert_eq!(
+        classified(
+            json!({"msgtype": "m.emote", "body": "is out for lunch"}),
+            false
+        ),
+        Incoming::UnaddressedEmote
+    );
+}
+
+#[test]
+fn an_emote_becomes_a_sentence_about_its_sender() {
+    let Incoming::Emote(body) = classified(
+        json!({"msgtype": "m.emote", "body": "waves at vagent"}),
+        false,
+    ) else {
+        panic!("emote not routed");
+    };
+    assert_eq!(emote_query("Alice", &body), "Alice waves at vagent");
+}
+
+#[test]
+fn notices_and_other_msgtypes_are_ignored() {
+    // Even when they name the bot
+    assert_eq!(
+        classified(
+            json!({"msgtype": "m.notice", "body": "vagent is down"}),
+            true
+        ),
+        Incoming::Notice
+    );
+    let image = json!({"msgtype": "m.image", "body": "vagent.png", "url": "mxc://localhost/image"});
+    assert_eq!(
+        classified(image, true),
+        Incoming::Other("m.image".to_string())
+    );
+    let custom = json!({"msgtype": "org.example.poll", "body": "Lunch?"});
+    assert_eq!(
+        classified(custom, false),
+        Incoming::Other("org.example.poll".to_string())
+    );
+}
+
+#[test]
+fn the_room_sticker_mode_wins_over_the_default() {
+    let cases = [
+        (None, StickerMode::Ignore, StickerAction::Ignore),
+        (
+            None,
+            StickerMode::Ack,
+            StickerAction::React("👍".to_string()),
+        ),
+        (None, StickerMode::Forward, StickerAction::Forward),
+        (
+            Some(StickerMode::Forward),
+            StickerMode::Ignore,
+            StickerAction::Forward,
+        ),
+        (
+            Some(StickerMode::Ack),
+            StickerMode::Forward,
+            StickerAction::React("👍".to_string()),
+        ),
+        (
+            Some(StickerMode::Ignore),
+            StickerMode::Ack,
+            StickerAction::Ignore,
+        ),
+    ];
+    for (room_mode, default, expected) in cases {
+        assert_eq!(
+            sticker_action(room_mode, default, "👍"),
+            expected,
+            "room {:?}, default {:?}",
+            room_mode,
+            default
+        );
+    }
+}
+
+#[test]
+fn a_forwarded_sticker_is_described_by_its_alt_text() {
+    let sticker: OriginalSyncStickerEvent = serde_json::from_value(event(
+        json!({"body": "Thumbs up", "info": {}, "url": "mxc://localhost/sticker"}),
+        "m.sticker",
+    ))
+    .expect("sticker event");
+    assert_eq!(
+        sticker_query("Alice", &sticker.content.body),
+        "Alice sent a sticker: Thumbs up"
+    );
+}
+
+#[test]
+fn sticker_modes_parse_from_settings() {
+    for mode in [StickerMode::Ignore, StickerMode::Ack, StickerMode::Forward] {
+        assert_eq!(StickerMode::parse(mode.as_str()), Some(mode));
+    }
+    assert_eq!(StickerMode::parse(" FORWARD "), Some(StickerMode::Forward));
+    assert_eq!(StickerMode::parse("react"), None);
+    assert_eq!(StickerMode::default(), StickerMode::Ignore);
+}
@@ -0,0 +1,62 @@
+//! The intent prefilter against the labeled messages in `data/intent_samples.tsv`
+//!
+//! Add misclassified messages from the "Intent prefilter" logs to the file
+//! when tuning `src/intent.rs`.
+
+use verji_vagent_bot::intent::{classify, Intent, Signals};
+
+const SAMPLES: &str = include_str!("data/intent_samples.tsv");
+
+/// (label, signals, text) for every sample line
+fn samples() -> Vec<(&'static str, Signals, &'static str)> {
+    SAMPLES
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            assert_eq!(fields.len(), 4, "malformed sample: {:?}", line);
+            let signals = Signals {
+                mentioned: fields[1] == "1",
+                direct_chat: fields[2] == "1",
+            };
+            (fields[0], signals, fields[3])
+        })
+        .collect()
+}
+
+#[test]
+fn classifies_every_labeled_sample() {
+    let mut wrong = Vec::new();
+    for (label, signals, text) in samples() {
+        let classification = classify(text, signals);
+        let dropped = classification.intent == Intent::Acknowledgement;
+        if dropped != (label == "ack") {
+            wrong.push(format!(
+                "{} {:?} ({:?}, {})",
+                label, text, classification.intent, classification.reason
+            ));
+        }
+    }
+    assert!(wrong.is_empty(), "misclassified:\n{}", wrong.join("\n"));
+}
+
+#[test]
+fn never_drops_mentions_or_direct_chats_outside_the_phrases() {
+    for (_, signals, text) in samples() {
+        for signals in [
+            Signals {
+                mentioned: true,
+                ..signals
+            },
+            Signals {
+                direct_chat: true,
+                ..signals
+            },
+        ] {
+            let classification = classify(text, signals);
+            if classification.intent == Intent::Acknowledgement {
+                assert_eq!(classification.reason, "ack phrase", "dropped {:?}", text);
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! `!status`, with the device name and maintenance mode, and the readiness
+//! probe during maintenance
+
+use matrix_sdk::Client;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use verji_vagent_bot::db::TempStore;
+use verji_vagent_bot::maintenance::MaintenanceMode;
+use verji_vagent_bot::responders::StatusResponder;
+use verji_vagent_bot::runtime::Runtime;
+use verji_vagent_bot::stats::UsageStats;
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+use verji_vagent_bot::webhook::{WebhookConfig, WebhookServer};
+
+async fn status(maintenance: &Arc<MaintenanceMode>) -> String {
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| {
+            config.locale = "en".to_string();
+            config.device_display_name = "Verji Assistant (staging)".to_string();
+        });
+    let messages = harness
+        .respond(
+            Arc::new(StatusResponder::new(Arc::clone(maintenance))),
+            "!status",
+        )
+        .await
+        .expect("respond");
+    message_text(&messages[0]).expect("text").to_string()
+}
+
+#[tokio::test]
+async fn status_shows_the_mode_and_its_notice() {
+    let store = TempStore::new("maintenance-status").expect("store");
+    let maintenance = Arc::new(MaintenanceMode::open(store.path(), false, None).expect("open"));
+
+    let text = status(&maintenance).await;
+    assert!(text.contains("- Maintenance: off"), "{}", text);
+    assert!(
+        text.contains("- Device: Verji Assistant (staging)"),
+        "{}",
+        text
+    );
+
+    maintenance
+        .set(true, Some("Back at 15:00".to_string()), "@admin:localhost")
+        .await
+        .expect("set");
+    let text = status(&maintenance).await;
+    assert!(
+        text.contains("- Maintenance: 🚧 on, questions get the notice: Back at 15:00"),
+        "{}",
+        text
+    );
+
+    // Without a message of its own the default notice is shown
+    maintenance
+        .set(true, None, "@admin:localhost")
+        .await
+        .expect("set");
+    let text = status(&maintenance).await;
+    assert!(text.contains("down for maintenance right now"), "{}", text);
+}
+
+/// A webhook listener on a free local port
+async fn listener(
+    maintenance: Option<Arc<MaintenanceMode>>,
+    store: &TempStore,
+) -> (WebhookServer, SocketAddr) {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .and_then(|probe| probe.local_addr())
+        .expect("free port");
+    let client = Client::builder()
+        .homeserver_url("http://localhost:8008")
+        .build()
+        .await
+        .expect("client");
+    let config = WebhookConfig {
+        addr,
+        tokens: HashMap::new(),
+        rooms: Vec::new(),
+        rate_per_minute: 10,
+    };
+    let stats = Arc::new(
+        UsageStats::open(store.path(), std::time::Duration::from_secs(3600)).expect("stats"),
+    );
+    let server = WebhookServer::start(Runtime::default(), client, config, stats, maintenance)
+        .await
+        .expect("start");
+    (server, addr)
+}
+
+async fn ready(addr: SocketAddr) -> (u16, serde_json::Value) {
+    let response = reqwest::get(format!("http://{}/ready", addr))
+        .await
+        .expect("request");
+    let status = response.status().as_u16();
+    let body = response.text().await.expect("body");
+    (status, serde_json::from_str(&body).expect("json"))
+}
+
+#[tokio::test]
+async fn maintenance_makes_the_bot_not_ready() {
+    let store = TempStore::new("maintenance-ready").expect("store");
+    let maintenance = Arc::new(MaintenanceMode::open(store.path(), false, None).expect("open"));
+    let (server, addr) = listener(Some(Arc::clone(&maintenance)), &store).await;
+
+    let (status, body) = ready(addr).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body["ready"], true);
+
+    maintenance
+        .set(true, None, "@admin:localhost")
+        .await
+        .expect("set");
+    let (status, body) = ready(addr).await;
+    assert_eq!(status, 503, "{}", body);
+    assert_eq!(body["ready"], false);
+    assert_eq!(body["reason"], "maintenance mode is on");
+
+    // Switched off, ready again without a restart
+    maintenance
+        .set(false, None, "@admin:localhost")
+        .await
+        .expect("set");
+    assert_eq!(ready(addr).await.0, 200);
+    server.shutdown().await;
+}
+
+#[tokio::test]
+async fn readiness_can_ignore_maintenance() {
+    let store = TempStore::new("maintenance-ignored").expect("store");
+    let maintenance = Arc::new(MaintenanceMode::open(store.path(), true, None).expect("open"));
+    assert!(maintenance.state().enabled);
+    // MAINTENANCE_NOT_READY=false: the mode isn't handed to the listener
+    let (server, addr) = listener(None, &store).await;
+
+    assert_eq!(ready(addr).await.0, 200);
+    server.shutdown().await;
+}
@@ -0,0 +1,128 @@
+//! Messages that notify users: the pill in the formatted body, the plain body
+//! beside it, and replies addressed to whoever asked
+
+use matrix_sdk::ruma::events::room::message::{MessageType, RoomMessageEventContent};
+use matrix_sdk::ruma::UserId;
+use std::collections::HashMap;
+use verji_vagent_bot::dispatcher::{message_content, named_message_content};
+use verji_vagent_bot::mentions::{self, Mentions};
+use verji_vagent_bot::responder::OutgoingMessage;
+
+/// Body, formatted HTML and whether it is a notice
+fn shown(content: &RoomMessageEventContent) -> (&str, &str, bool) {
+    let (text, notice) = match &content.msgtype {
+        MessageType::Text(text) => ((&text.body, &text.formatted), false),
+        MessageType::Notice(notice) => ((&notice.body, &notice.formatted), true),
+        other => panic!("unexpected message type {}", other.msgtype()),
+    };
+    let formatted = text.1.as_ref().expect("formatted body");
+    (text.0.as_str(), formatted.body.as_str(), notice)
+}
+
+fn names() -> HashMap<String, String> {
+    HashMap::from([("@alice:localhost".to_string(), "Alice".to_string())])
+}
+
+#[test]
+fn mentions_get_a_pill_and_notify_the_user() {
+    let message = OutgoingMessage::notice_mentioning("@alice:localhost", "Your form expires soon");
+    let content = message_content(&message).unwrap();
+
+    let (body, formatted, notice) = shown(&content);
+    assert!(notice);
+    // The pill is only in the formatted body
+    assert_eq!(body, "@alice:localhost: Your form expires soon");
+    assert!(
+        formatted.contains(
+            "<a href=\"https://matrix.to/#/@alice:localhost\">@alice:localhost</a>: \
+             Your form expires soon"
+        ),
+        "{}",
+        formatted
+    );
+    let mentions = content.mentions.expect("m.mentions");
+    assert!(mentions
+        .user_ids
+        .contains(<&UserId>::try_from("@alice:localhost").unwrap()));
+    assert!(!mentions.room);
+}
+
+#[test]
+fn pills_show_the_display_name_when_it_is_known() {
+    let message = OutgoingMessage::mentioning("@alice:localhost", "Your form expires soon");
+    let content = named_message_content(&message, &names()).unwrap();
+    let (body, formatted, _) = shown(&content);
+    assert_eq!(body, "Alice: Your form expires soon");
+    assert!(
+        formatted.contains("<a href=\"https://matrix.to/#/@alice:localhost\">Alice</a>"),
+        "{}",
+        formatted
+    );
+
+    // A pill already in the body (e.g. a HITL reminder) gets the name too
+    let body = format!(
+        "{}, the question is still open",
+        mentions::pill("@alice:localhost", None)
+    );
+    let (plain, html) =
+        mentions::bodies(&body, &Mentions::user("@alice:localhost"), &names(), true);
+    assert_eq!(plain, "Alice, the question is still open");
+    assert!(html.contains(">Alice</a>, the question"), "{}", html);
+    assert!(!html.contains("@alice:localhost</a>"), "{}", html);
+}
+
+#[test]
+fn room_mentions_notify_everyone() {
+    let message = OutgoingMessage::Mention {
+        body: "Maintenance tonight".to_string(),
+        mentions: Mentions::room(),
+        notice: false,
+        markdown: true,
+    };
+    let content = message_content(&message).unwrap();
+    assert_eq!(shown(&content).0, "@room: Maintenance tonight");
+    assert!(content.mentions.expect("m.mentions").room);
+}
+
+#[test]
+fn addressing_plain_text_keeps_it_plain() {
+    let mut messages = vec![
+        OutgoingMessage::Reaction("👀".to_string()),
+        OutgoingMessage::Text("*not bold* <b>".to_string()),
+    ];
+    mentions::address(&mut messages, "@user:localhost");
+    assert!(matches!(messages[0], OutgoingMessage::Reaction(_)));
+
+    let content = message_content(&messages[1]).unwrap();
+    let (body, formatted, notice) = shown(&content);
+    assert!(!notice);
+    assert_eq!(body, "@user:localhost: *not bold* <b>");
+    assert!(
+        formatted.ends_with(": *not bold* &lt;b&gt;"),
+        "{}",
+        formatted
+    );
+    assert!(formatted.contains("href=\"https://matrix.to/#/@user:localhost\""));
+    assert!(content.mentions.is_some());
+}
+
+#[test]
+fn addressing_markdown_keeps_it_markdown() {
+    let mut messages = vec![OutgoingMessage::Markdown("**Answer**".to_string())];
+    mentions::address(&mut messages, "@user:localhost");
+    let content = message_content(&messages[0]).unwrap();
+    let (body, formatted, _) = shown(&content);
+    assert_eq!(body, "@user:localhost: **Answer**");
+    assert!(
+        formatted.contains("<strong>Answer</strong>"),
+        "{}",
+        formatted
+    );
+
+    // Plain answers keep the pill but drop the markdown
+    let plain = messages.remove(0).into_plain();
+    let content = message_content(&plain).unwrap();
+    let formatted = shown(&content).1;
+    assert!(formatted.ends_with(": **Answer**"), "{}", formatted);
+    assert!(formatted.contains("href=\"https://matrix.to/#/@user:localhost\""));
+}
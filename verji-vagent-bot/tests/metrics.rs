@@ -0,0 +1,58 @@
+//! The Prometheus text output: one `# TYPE` line per metric, followed by all
+//! of its samples
+
+use verji_vagent_bot::metrics::Metrics;
+
+#[test]
+fn each_metric_is_typed_once_with_its_samples_together() {
+    let metrics = Metrics::default();
+    // `grouped_total_errors` sorts between the bare and labelled `grouped_total`
+    metrics.increment("grouped_total", &[]);
+    metrics.increment("grouped_total_errors", &[]);
+    metrics.increment("grouped_total", &[("room", "!a:localhost")]);
+    metrics.observe_ms("grouped_latency_ms", &[], 5);
+    metrics.observe_ms("grouped_latency_ms_other", &[], 5);
+    metrics.observe_ms("grouped_latency_ms", &[("kind", "query")], 5);
+
+    let rendered = metrics.render();
+    let lines: Vec<&str> = rendered.lines().collect();
+    for (name, kind) in [
+        ("grouped_total", "counter"),
+        ("grouped_total_errors", "counter"),
+        ("grouped_latency_ms", "histogram"),
+    ] {
+        let typed = format!("# TYPE {} {}", name, kind);
+        let count = lines.iter().filter(|line| **line == typed).count();
+        assert_eq!(count, 1, "{}\n{}", typed, rendered);
+    }
+
+    let at = lines
+        .iter()
+        .position(|line| *line == "# TYPE grouped_total counter")
+        .unwrap();
+    assert_eq!(
+        &lines[at + 1..at + 3],
+        ["grouped_total 1", "grouped_total{room=\"!a:localhost\"} 1"]
+    );
+    // Both histogram series come before the next metric's type line
+    let at = lines
+        .iter()
+        .position(|line| *line == "# TYPE grouped_latency_ms histogram")
+        .unwrap();
+    let next = lines[at + 1..]
+        .iter()
+        .position(|line| line.starts_with("# TYPE"))
+        .map_or(lines.len(), |offset| at + 1 + offset);
+    let counts: Vec<&str> = lines[at..next]
+        .iter()
+        .copied()
+        .filter(|line| line.starts_with("grouped_latency_ms_count"))
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            "grouped_latency_ms_count 1",
+            "grouped_latency_ms_count{kind=\"query\"} 1"
+        ]
+    );
+}
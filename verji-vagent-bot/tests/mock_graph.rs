@@ -0,0 +1,147 @@
+//! The agent responder against the scenario files in `fixtures/mock_graph/`
+//!
+//! `happy_path.json` streams tool steps before answering and `hitl.json`
+//! asks a question before finishing; `timeout.json` is driven from
+//! `agent_timeout.rs`, which shortens the agent timeout for its process.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use verji_vagent_bot::redis_client::RequestKind;
+use verji_vagent_bot::responder::OutgoingMessage;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+fn fixture(name: &str) -> Arc<MockScript> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/mock_graph")
+        .join(name);
+    Arc::new(MockScript::load(&path).expect("fixture"))
+}
+
+fn agent(script: &Arc<MockScript>) -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::clone(script)),
+    )));
+    manager
+}
+
+fn harness(room_id: &str) -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new(room_id).expect("room"))
+        .configure(|config| config.locale = "en".to_string())
+}
+
+/// The one text reply to a dispatched message
+async fn reply(harness: &ResponderTestHarness, manager: &ResponderManager, body: &str) -> String {
+    let messages = harness.dispatch(manager, body).await.expect("dispatch");
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    message_text(&messages[0]).expect("text").to_string()
+}
+
+#[tokio::test]
+async fn progress_steps_are_shown_before_the_answer() {
+    let script = fixture("happy_path.json");
+    let manager = agent(&script);
+    let harness = harness("!happy:localhost");
+
+    let answer = reply(&harness, &manager, "Why was invoice 1234 paid late?").await;
+    assert!(answer.starts_with("_Completed 3 steps in "), "{}", answer);
+    assert!(
+        answer.contains("declined by the bank on the first attempt"),
+        "{}",
+        answer
+    );
+    assert!(
+        answer.ends_with("You asked: _Why was invoice 1234 paid late?_"),
+        "{}",
+        answer
+    );
+
+    // The steps share one message, edited as the graph moves on
+    let sent = harness.mock_room().sent();
+    let steps: Vec<_> = sent
+        .iter()
+        .filter_map(|message| match message {
+            OutgoingMessage::Markdown(body) if body.contains("`search_invoices`") => Some(body),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(steps.len(), 1, "{:?}", sent);
+    let edits = harness.mock_room().edits();
+    let (_, last) = edits.last().expect("the step message was edited");
+    assert!(last.contains("`compose_answer`"), "{}", last);
+
+    let submitted = script.submitted();
+    assert_eq!(submitted.len(), 1);
+    assert_eq!(submitted[0].kind, RequestKind::Query);
+}
+
+#[tokio::test]
+async fn a_graph_error_gets_the_error_reply() {
+    let script = fixture("happy_path.json");
+    let manager = agent(&script);
+    let harness = harness("!broken:localhost");
+
+    let answer = reply(&harness, &manager, "Please break something").await;
+    assert!(
+        answer.contains("The AI assistant couldn't answer this question"),
+        "{}",
+        answer
+    );
+    // The raw progress before the error went out as it came
+    let sent = harness.mock_room().sent();
+    assert!(
+        sent.iter()
+            .any(|message| message_text(message) == Some("🤔 Thinking")),
+        "{:?}",
+        sent
+    );
+}
+
+#[tokio::test]
+async fn a_question_from_the_graph_is_answered_and_resumed() {
+    let script = fixture("hitl.json");
+    let manager = agent(&script);
+    let harness = harness("!hitl:localhost");
+
+    let question = reply(&harness, &manager, "Remind the overdue customers").await;
+    assert!(
+        question.starts_with("I found 3 overdue invoices."),
+        "{}",
+        question
+    );
+    assert!(
+        question.contains("Send the reminders? (yes/no)"),
+        "{}",
+        question
+    );
+
+    // The next message is the answer, not a new query
+    let done = reply(&harness, &manager, "yes").await;
+    assert!(done.ends_with("Done. You answered: yes"), "{}", done);
+
+    let submitted = script.submitted();
+    assert_eq!(submitted.len(), 2, "{:?}", submitted);
+    assert_eq!(submitted[0].kind, RequestKind::Query);
+    assert_eq!(submitted[1].kind, RequestKind::HitlResponse);
+    // The answer resumes the paused request
+    assert_eq!(submitted[1].request_id, submitted[0].request_id);
+    assert_eq!(
+        submitted[1].payload,
+        Some(serde_json::json!({"confirm": "yes"}))
+    );
+
+    // Answered: the next message is a new query, which asks again
+    let again = reply(&harness, &manager, "And the ones from last year?").await;
+    assert!(
+        again.starts_with("I found 3 overdue invoices."),
+        "{}",
+        again
+    );
+    assert_eq!(script.submitted()[2].kind, RequestKind::Query);
+}
@@ -0,0 +1,147 @@
+//! Observers: the order they see a room's messages and outcomes in, and the
+//! stats and feedback they record for agent answers
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use verji_vagent_bot::conversation::ConversationKey;
+use verji_vagent_bot::feedback::ANSWER_SLOT;
+use verji_vagent_bot::observer::{Observer, QueryOutcome};
+use verji_vagent_bot::responder::ResponderContext;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+/// Notes what it saw per room, slowest on the first message it gets
+#[derive(Default)]
+struct Recorder {
+    seen: Mutex<Vec<(String, String)>>,
+}
+
+impl Recorder {
+    fn seen_in(&self, room_id: &str) -> Vec<String> {
+        self.seen
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(room, _)| room == room_id)
+            .map(|(_, seen)| seen.clone())
+            .collect()
+    }
+
+    fn note(&self, context: &ResponderContext, seen: String) {
+        let room_id = context.room.room_id().to_string();
+        self.seen.lock().unwrap().push((room_id, seen));
+    }
+}
+
+#[async_trait]
+impl Observer for Recorder {
+    fn name(&self) -> &str {
+        "Recorder"
+    }
+
+    async fn observe(&self, context: &ResponderContext) -> Result<()> {
+        // Later messages would overtake this one if a room's were run concurrently
+        let delay = match context.message_body.as_ref() {
+            "first" => 50,
+            "second" => 20,
+            _ => 0,
+        };
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        self.note(context, context.message_body.to_string());
+        Ok(())
+    }
+
+    async fn outcome(&self, context: &ResponderContext, outcome: &QueryOutcome) -> Result<()> {
+        self.note(
+            context,
+            format!("{} -> {}", context.message_body, outcome.status),
+        );
+        Ok(())
+    }
+}
+
+fn harness(room_id: &str) -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new(room_id).expect("room"))
+        .configure(|config| config.locale = "en".to_string())
+}
+
+#[tokio::test]
+async fn a_rooms_messages_are_observed_in_order() {
+    let recorder = Arc::new(Recorder::default());
+    let mut manager = ResponderManager::new();
+    manager.add_observer(Arc::clone(&recorder) as Arc<dyn Observer>);
+
+    let bodies = ["first", "second", "third", "fourth", "fifth"];
+    let rooms = [harness("!one:localhost"), harness("!two:localhost")];
+    // Queued without waiting for observers in between
+    for body in bodies {
+        for harness in &rooms {
+            let context = harness.context(body).await.expect("context");
+            manager.dispatch(&context).await.expect("dispatch");
+        }
+    }
+    manager.observers().idle().await;
+
+    for room_id in ["!one:localhost", "!two:localhost"] {
+        assert_eq!(recorder.seen_in(room_id), bodies, "{}", room_id);
+    }
+}
+
+#[tokio::test]
+async fn outcomes_follow_their_messages() {
+    let recorder = Arc::new(Recorder::default());
+    let script = Arc::new(MockScript::from_json(r#"{"fallback_delay_ms": 0}"#).expect("script"));
+    let mut manager = ResponderManager::new();
+    manager.add_observer(Arc::clone(&recorder) as Arc<dyn Observer>);
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(script),
+    )));
+
+    let harness = harness("!agent:localhost");
+    for body in ["first", "second"] {
+        harness.dispatch(&manager, body).await.expect("dispatch");
+    }
+    assert_eq!(
+        recorder.seen_in("!agent:localhost"),
+        ["first", "first -> answered", "second", "second -> answered"]
+    );
+}
+
+#[tokio::test]
+async fn an_answer_is_counted_and_kept_for_feedback() {
+    let script = Arc::new(MockScript::from_json(r#"{"fallback_delay_ms": 0}"#).expect("script"));
+    let agent = Arc::new(VerjiAgentResponder::with_transport(TransportConfig::Mock(
+        Arc::clone(&script),
+    )));
+    let harness = harness("!agent:localhost");
+    harness
+        .respond(agent, "What's on the agenda?")
+        .await
+        .expect("respond");
+
+    let users = harness.stats().room_users("!agent:localhost");
+    assert_eq!(users.len(), 1, "{:?}", users);
+    let (user, counters) = &users[0];
+    assert_eq!(user, "@user:localhost");
+    assert_eq!((counters.queries, counters.errors), (1, 0));
+
+    let history = harness
+        .stats()
+        .history("@user:localhost", Some("!agent:localhost"), 10)
+        .await
+        .expect("history");
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].status, "answered");
+
+    let key = ConversationKey::new("!agent:localhost", "@user:localhost", ANSWER_SLOT);
+    let answer = harness.conversations().get(&key).await.expect("answer");
+    assert_eq!(answer["request_id"], script.submitted()[0].request_id);
+    assert_eq!(answer["question"], "What's on the agenda?");
+}
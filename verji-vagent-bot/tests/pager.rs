@@ -0,0 +1,219 @@
+//! The pager: turning pages, footers, splitting text, and ▶️/◀️ reactions
+//! against the mock room
+
+use matrix_sdk::ruma::EventId;
+use std::time::Duration;
+use verji_vagent_bot::i18n::Catalog;
+use verji_vagent_bot::pager::{self, paginate, PagerState, Turn, NEXT, PREVIOUS};
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+
+fn pages(count: usize) -> Vec<String> {
+    (1..=count).map(|n| format!("Page {} text", n)).collect()
+}
+
+#[test]
+fn pages_turn_within_bounds() {
+    let mut state = PagerState::new(pages(3), "en");
+    assert_eq!((state.current(), state.page_count()), (0, 3));
+
+    // (turn, moved, page shown after)
+    let steps = [
+        (Turn::Previous, false, 0),
+        (Turn::Next, true, 1),
+        (Turn::Next, true, 2),
+        (Turn::Next, false, 2),
+        (Turn::Previous, true, 1),
+        (Turn::Previous, true, 0),
+        (Turn::Previous, false, 0),
+    ];
+    for (turn, moved, current) in steps {
+        assert_eq!(state.turn(turn), moved, "{:?} to {}", turn, current);
+        assert_eq!(state.current(), current);
+    }
+}
+
+#[test]
+fn a_single_page_never_turns() {
+    let catalog = Catalog::embedded();
+    let mut state = PagerState::new(pages(1), "en");
+    assert!(!state.turn(Turn::Next));
+    assert!(!state.turn(Turn::Previous));
+    assert_eq!(state.render(&catalog), "Page 1 text");
+
+    let mut empty = PagerState::new(Vec::new(), "en");
+    assert!(!empty.turn(Turn::Next));
+    assert_eq!(empty.render(&catalog), "");
+}
+
+#[test]
+fn footers_say_where_to_go() {
+    let catalog = Catalog::embedded();
+    let mut state = PagerState::new(pages(3), "en");
+    assert_eq!(
+        state.render(&catalog),
+        "Page 1 text\n\n_page 1/3 — react ▶️ for more_"
+    );
+    state.turn(Turn::Next);
+    assert_eq!(
+        state.render(&catalog),
+        "Page 2 text\n\n_page 2/3 — react ▶️ for more or ◀️ to go back_"
+    );
+    state.turn(Turn::Next);
+    assert_eq!(
+        state.render(&catalog),
+        "Page 3 text\n\n_page 3/3 — react ◀️ to go back_"
+    );
+}
+
+#[test]
+fn the_state_survives_the_store() {
+    let mut state = PagerState::new(pages(4), "nb");
+    state.turn(Turn::Next);
+    let stored = serde_json::to_value(&state).expect("serialize");
+    let loaded: PagerState = serde_json::from_value(stored).expect("deserialize");
+    assert_eq!(loaded, state);
+    assert_eq!(loaded.current(), 1);
+}
+
+#[test]
+fn reactions_map_to_turns() {
+    let cases = [
+        (NEXT, Some(Turn::Next)),
+        (PREVIOUS, Some(Turn::Previous)),
+        // Without the variation selector
+        ("▶", Some(Turn::Next)),
+        ("◀", Some(Turn::Previous)),
+        ("👍", None),
+        ("⏩", None),
+        ("", None),
+    ];
+    for (key, expected) in cases {
+        assert_eq!(Turn::from_reaction(key), expected, "{:?}", key);
+    }
+}
+
+#[test]
+fn text_is_split_between_lines() {
+    let long = "x".repeat(20);
+    let with_long = format!("short\n{}\nend", long);
+    let cases = [
+        ("one\ntwo\nthree", 8, vec!["one\ntwo", "three"]),
+        ("one\ntwo\nthree", 100, vec!["one\ntwo\nthree"]),
+        // A line longer than a page gets one of its own
+        (with_long.as_str(), 10, vec!["short", long.as_str(), "end"]),
+        ("a\n\n", 10, vec!["a"]),
+        ("", 10, vec![""]),
+    ];
+    for (text, max_chars, expected) in cases {
+        assert_eq!(paginate(text, max_chars), expected, "{:?}", text);
+    }
+}
+
+struct Pager {
+    harness: ResponderTestHarness,
+}
+
+impl Pager {
+    fn new(ttl: Duration) -> Self {
+        let harness = ResponderTestHarness::new()
+            .expect("harness")
+            .room(MockRoom::new("!pager:localhost").expect("room"))
+            .configure(|config| config.pager_ttl = ttl);
+        Self { harness }
+    }
+
+    async fn send(&self, pages: Vec<String>) -> matrix_sdk::ruma::OwnedEventId {
+        let context = self.harness.context("!history").await.expect("context");
+        pager::send(&context, self.harness.mock_room(), pages)
+            .await
+            .expect("send")
+    }
+
+    async fn react(&self, sender: &str, target: &EventId, key: &str) -> bool {
+        pager::on_reaction(
+            self.harness.mock_room(),
+            &self.harness.runtime().catalog,
+            self.harness.conversations(),
+            self.harness.sent_events(),
+            sender,
+            target,
+            key,
+        )
+        .await
+        .expect("reaction")
+    }
+
+    /// Text of each edit, in order
+    fn edits(&self) -> Vec<String> {
+        self.harness
+            .mock_room()
+            .edits()
+            .into_iter()
+            .map(|(_, body)| body)
+            .collect()
+    }
+}
+
+const USER: &str = "@user:localhost";
+
+#[tokio::test]
+async fn the_asker_pages_with_reactions() {
+    let pager = Pager::new(Duration::from_secs(3600));
+    let event_id = pager.send(pages(3)).await;
+    let sent = pager.harness.mock_room().sent();
+    assert_eq!(sent.len(), 1);
+    assert!(message_text(&sent[0]).is_some_and(|text| text.starts_with("Page 1 text")));
+
+    assert!(pager.react(USER, &event_id, NEXT).await);
+    assert!(pager.react(USER, &event_id, NEXT).await);
+    // Past the last page: taken, but nothing to show
+    assert!(pager.react(USER, &event_id, NEXT).await);
+    assert!(pager.react(USER, &event_id, PREVIOUS).await);
+
+    let edits = pager.edits();
+    assert_eq!(edits.len(), 3, "{:?}", edits);
+    assert!(edits[0].starts_with("Page 2 text"));
+    assert!(edits[1].starts_with("Page 3 text"));
+    assert!(edits[2].starts_with("Page 2 text"));
+    assert!(pager
+        .harness
+        .mock_room()
+        .edits()
+        .iter()
+        .all(|(edited, _)| *edited == event_id));
+}
+
+#[tokio::test]
+async fn only_the_asker_turns_the_pages() {
+    let pager = Pager::new(Duration::from_secs(3600));
+    let event_id = pager.send(pages(2)).await;
+
+    assert!(!pager.react("@someone:localhost", &event_id, NEXT).await);
+    assert!(!pager.react(USER, &event_id, "👍").await);
+    assert!(pager.edits().is_empty());
+}
+
+#[tokio::test]
+async fn messages_without_pages_are_left_alone() {
+    let pager = Pager::new(Duration::from_secs(3600));
+    // A single page is sent as it is, with nothing to turn
+    let event_id = pager.send(pages(1)).await;
+    let sent = pager.harness.mock_room().sent();
+    assert_eq!(message_text(&sent[0]), Some("Page 1 text"));
+    assert!(!pager.react(USER, &event_id, NEXT).await);
+
+    // Nor does a message the bot didn't send
+    let unknown = EventId::parse("$unknown:localhost").expect("event ID");
+    assert!(!pager.react(USER, &unknown, NEXT).await);
+    assert!(pager.edits().is_empty());
+}
+
+#[tokio::test]
+async fn reactions_after_the_ttl_are_ignored() {
+    let pager = Pager::new(Duration::from_secs(1));
+    let event_id = pager.send(pages(2)).await;
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+
+    assert!(!pager.react(USER, &event_id, NEXT).await);
+    assert!(pager.edits().is_empty());
+}
@@ -0,0 +1,242 @@
+//! Policy room rules: parsing state events, malformed ones included, glob
+//! entities, and the ban list kept current as rules change
+
+use serde_json::{json, Value};
+use verji_vagent_bot::policy::{
+    glob_match, parse_event, PolicyList, PolicyRule, RuleKind, RuleUpdate, ROOM_RULE, USER_RULE,
+};
+
+fn rule(event_type: &str, state_key: &str, content: Value) -> Value {
+    json!({
+        "type": event_type,
+        "state_key": state_key,
+        "sender": "@moderator:localhost",
+        "event_id": "$rule:localhost",
+        "origin_server_ts": 1_700_000_000_000u64,
+        "content": content,
+    })
+}
+
+fn ban(kind: RuleKind, entity: &str, reason: Option<&str>) -> RuleUpdate {
+    RuleUpdate::Ban(PolicyRule {
+        kind,
+        entity: entity.to_string(),
+        reason: reason.map(str::to_string),
+    })
+}
+
+#[test]
+fn ban_rules_parse() {
+    let cases = [
+        (
+            rule(
+                USER_RULE,
+                "rule:spammer",
+                json!({"entity": "@spammer:evil.org", "recommendation": "m.ban", "reason": "spam"}),
+            ),
+            (
+                RuleKind::User,
+                "rule:spammer",
+                ban(RuleKind::User, "@spammer:evil.org", Some("spam")),
+            ),
+        ),
+        (
+            rule(
+                ROOM_RULE,
+                "rule:rooms",
+                json!({"entity": "!*:evil.org", "recommendation": "m.ban"}),
+            ),
+            (
+                RuleKind::Room,
+                "rule:rooms",
+                ban(RuleKind::Room, "!*:evil.org", None),
+            ),
+        ),
+        // The older Mjolnir recommendation, an empty reason and padding
+        (
+            rule(
+                USER_RULE,
+                "legacy",
+                json!({"entity": "  @*:evil.org ", "recommendation": "org.matrix.mjolnir.ban", "reason": ""}),
+            ),
+            (
+                RuleKind::User,
+                "legacy",
+                ban(RuleKind::User, "@*:evil.org", None),
+            ),
+        ),
+    ];
+    for (event, (kind, key, update)) in cases {
+        assert_eq!(
+            parse_event(&event).expect("valid"),
+            Some((kind, key.to_string(), update)),
+            "{}",
+            event
+        );
+    }
+}
+
+#[test]
+fn emptied_rules_and_other_recommendations_remove_the_rule() {
+    let cases = [
+        rule(USER_RULE, "lifted", json!({})),
+        rule(
+            USER_RULE,
+            "watch",
+            json!({"entity": "@someone:localhost", "recommendation": "org.example.watch"}),
+        ),
+    ];
+    for event in cases {
+        let (_, _, update) = parse_event(&event).expect("valid").expect("a rule");
+        assert_eq!(update, RuleUpdate::Remove, "{}", event);
+    }
+}
+
+#[test]
+fn other_event_types_are_not_rules() {
+    let cases = [
+        rule(
+            "m.room.member",
+            "@user:localhost",
+            json!({"membership": "join"}),
+        ),
+        rule(
+            "m.policy.rule.server",
+            "servers",
+            json!({"entity": "evil.org", "recommendation": "m.ban"}),
+        ),
+        json!({"content": {"entity": "@user:localhost"}}),
+    ];
+    for event in cases {
+        assert_eq!(parse_event(&event).expect("ignored"), None, "{}", event);
+    }
+}
+
+#[test]
+fn malformed_rules_are_errors() {
+    let mut no_state_key = rule(
+        USER_RULE,
+        "",
+        json!({"entity": "@a:b", "recommendation": "m.ban"}),
+    );
+    no_state_key.as_object_mut().unwrap().remove("state_key");
+    let cases = [
+        (no_state_key, "has no state key"),
+        (
+            rule(USER_RULE, "r", json!("m.ban")),
+            "content is not an object",
+        ),
+        (
+            rule(USER_RULE, "r", Value::Null),
+            "content is not an object",
+        ),
+        (
+            rule(USER_RULE, "r", json!({"recommendation": "m.ban"})),
+            "has no entity",
+        ),
+        (
+            rule(
+                USER_RULE,
+                "r",
+                json!({"entity": 42, "recommendation": "m.ban"}),
+            ),
+            "invalid entity",
+        ),
+        (
+            rule(
+                USER_RULE,
+                "r",
+                json!({"entity": "   ", "recommendation": "m.ban"}),
+            ),
+            "invalid entity",
+        ),
+        (
+            rule(ROOM_RULE, "r", json!({"entity": "!a:b"})),
+            "has no recommendation",
+        ),
+        (
+            rule(
+                ROOM_RULE,
+                "r",
+                json!({"entity": "!a:b", "recommendation": ["m.ban"]}),
+            ),
+            "has no recommendation",
+        ),
+    ];
+    for (event, error) in cases {
+        let message = format!("{:#}", parse_event(&event).expect_err("malformed"));
+        assert!(message.contains(error), "{}: {}", event, message);
+    }
+}
+
+#[test]
+fn entities_are_globs() {
+    let cases = [
+        ("@spammer:evil.org", "@spammer:evil.org", true),
+        ("@spammer:evil.org", "@spammer:evil.org.uk", false),
+        ("@*:evil.org", "@anyone:evil.org", true),
+        ("@*:evil.org", "@anyone:good.org", false),
+        ("*", "!room:localhost", true),
+        ("@spam?er:evil.org", "@spammer:evil.org", true),
+        ("@spam?er:evil.org", "@spamer:evil.org", false),
+        ("@*bot*:*", "@helperbot2:localhost", true),
+        ("@a*b*c:x", "@aXbYbZc:x", true),
+        ("@a*b*c:x", "@aXbYbZ:x", false),
+        ("", "", true),
+        ("", "@a:b", false),
+        ("@ø*:localhost", "@øyvind:localhost", true),
+    ];
+    for (pattern, text, expected) in cases {
+        assert_eq!(
+            glob_match(pattern, text),
+            expected,
+            "{} against {}",
+            pattern,
+            text
+        );
+    }
+}
+
+#[test]
+fn the_list_follows_state_changes() {
+    let list = PolicyList::new("#policies:localhost");
+    assert!(!list.is_policy_room("!policies:localhost"));
+
+    list.on_state_event(&rule(
+        USER_RULE,
+        "evil",
+        json!({"entity": "@*:evil.org", "recommendation": "m.ban", "reason": "spam"}),
+    ));
+    list.on_state_event(&rule(
+        ROOM_RULE,
+        "room",
+        json!({"entity": "!bad:localhost", "recommendation": "m.ban"}),
+    ));
+    assert_eq!(list.rule_count(), 2);
+
+    let banned = list.ban(RuleKind::User, "@x:evil.org").expect("banned");
+    assert_eq!(banned.reason.as_deref(), Some("spam"));
+    // Rules only apply to their kind
+    assert!(list.ban(RuleKind::Room, "@x:evil.org").is_none());
+    assert!(list.ban(RuleKind::Room, "!bad:localhost").is_some());
+    assert!(list.ban(RuleKind::User, "@x:good.org").is_none());
+
+    // Malformed updates leave the rule alone
+    list.on_state_event(&rule(USER_RULE, "evil", json!({"recommendation": "m.ban"})));
+    assert!(list.ban(RuleKind::User, "@x:evil.org").is_some());
+
+    // A changed rule replaces the one under its state key
+    list.on_state_event(&rule(
+        USER_RULE,
+        "evil",
+        json!({"entity": "@spammer:evil.org", "recommendation": "m.ban"}),
+    ));
+    assert_eq!(list.rule_count(), 2);
+    assert!(list.ban(RuleKind::User, "@x:evil.org").is_none());
+    assert!(list.ban(RuleKind::User, "@spammer:evil.org").is_some());
+
+    // An emptied rule lifts the ban
+    list.on_state_event(&rule(USER_RULE, "evil", json!({})));
+    assert!(list.ban(RuleKind::User, "@spammer:evil.org").is_none());
+    assert_eq!(list.rule_count(), 1);
+}
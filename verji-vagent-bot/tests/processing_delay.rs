@@ -0,0 +1,206 @@
+//! Coalescing, the processing delay and redactions together, against the
+//! mock room: a retracted message is never answered and the bot never shows
+//! typing for it
+//!
+//! Each test has a room of its own, since typing is tracked per room across
+//! the process.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::coalesce::Coalescer;
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::retraction::ProcessingDelay;
+use verji_vagent_bot::testing::{MockRoom, ResponderTestHarness};
+
+const WINDOW: Duration = Duration::from_millis(50);
+const DELAY: Duration = Duration::from_millis(100);
+
+/// Answers every message with its body
+struct Answer;
+
+#[async_trait]
+impl Responder for Answer {
+    fn name(&self) -> &str {
+        "Answer"
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some(format!(
+            "Answer: {}",
+            context.message_body
+        ))))
+    }
+}
+
+fn event(n: usize) -> OwnedEventId {
+    EventId::parse(format!("$m{}:localhost", n)).expect("event ID")
+}
+
+/// The bot's handling of a message up to the responders, as in `bot`
+struct Pipeline {
+    harness: ResponderTestHarness,
+    manager: ResponderManager,
+    coalescer: Coalescer,
+    delay: ProcessingDelay,
+}
+
+impl Pipeline {
+    fn new(room_id: &str, delay: Duration) -> Self {
+        let manager = ResponderManager::new();
+        manager.register(Arc::new(Answer));
+        let harness = ResponderTestHarness::new()
+            .expect("harness")
+            .room(MockRoom::new(room_id).expect("room"))
+            .configure(|config| config.typing_indicator = true);
+        Self {
+            harness,
+            manager,
+            coalescer: Coalescer::new(WINDOW),
+            delay: ProcessingDelay::new(delay),
+        }
+    }
+
+    /// Message `n` arrives after `after`
+    async fn message(&self, after: Duration, n: usize, body: &str, command: bool) {
+        tokio::time::sleep(after).await;
+        let (body, event_ids) = if command {
+            (body.to_string(), vec![event(n)])
+        } else {
+            let key = Coalescer::key("!room:localhost", None, "@user:localhost");
+            match self.coalescer.submit(key, body.to_string(), event(n)).await {
+                Some(burst) => (burst.body, burst.event_ids),
+                None => return,
+            }
+        };
+        if self.delay.applies(command, false, event_ids.len()) && !self.delay.wait(&event_ids).await
+        {
+            return;
+        }
+        self.harness
+            .dispatch(&self.manager, &body)
+            .await
+            .expect("dispatch");
+    }
+
+    /// Message `n` is deleted after `after`
+    async fn redact(&self, after: Duration, n: usize) {
+        tokio::time::sleep(after).await;
+        self.delay.on_redaction(&event(n));
+    }
+
+    fn typing(&self) -> Vec<bool> {
+        self.harness.mock_room().typing_changes()
+    }
+}
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[tokio::test]
+async fn a_burst_is_answered_once_after_the_delay() {
+    let pipeline = Pipeline::new("!burst:localhost", DELAY);
+    tokio::join!(
+        pipeline.message(ms(0), 1, "hey", false),
+        pipeline.message(ms(20), 2, "can you check", false),
+        pipeline.message(ms(40), 3, "invoice 1234?", false),
+    );
+    pipeline
+        .harness
+        .assert_sent_texts(&["Answer: hey\ncan you check\ninvoice 1234?"]);
+    assert_eq!(pipeline.typing(), vec![true, false]);
+}
+
+#[tokio::test]
+async fn deleting_a_message_during_the_delay_drops_the_burst() {
+    let pipeline = Pipeline::new("!retracted:localhost", DELAY);
+    // The window closes at 90ms, the delay ends at 190ms
+    tokio::join!(
+        pipeline.message(ms(0), 1, "hey", false),
+        pipeline.message(ms(40), 2, "what's my password?", false),
+        pipeline.redact(ms(130), 2),
+    );
+    pipeline.harness.assert_sent_texts(&[]);
+    assert!(pipeline.typing().is_empty(), "{:?}", pipeline.typing());
+}
+
+#[tokio::test]
+async fn deleting_a_message_during_the_coalescing_window_counts_too() {
+    let pipeline = Pipeline::new("!early:localhost", DELAY);
+    tokio::join!(
+        pipeline.message(ms(0), 1, "hey", false),
+        pipeline.redact(ms(10), 1),
+        pipeline.message(ms(20), 2, "never mind", false),
+    );
+    pipeline.harness.assert_sent_texts(&[]);
+    assert!(pipeline.typing().is_empty(), "{:?}", pipeline.typing());
+}
+
+#[tokio::test]
+async fn a_redaction_after_the_delay_is_too_late() {
+    let pipeline = Pipeline::new("!late:localhost", DELAY);
+    tokio::join!(
+        pipeline.message(ms(0), 1, "hello", false),
+        pipeline.redact(ms(300), 1),
+    );
+    pipeline.harness.assert_sent_texts(&["Answer: hello"]);
+    assert_eq!(pipeline.typing(), vec![true, false]);
+}
+
+#[tokio::test]
+async fn commands_are_answered_without_waiting() {
+    let pipeline = Pipeline::new("!command:localhost", DELAY);
+    tokio::join!(
+        pipeline.message(ms(0), 1, "!ping", true),
+        pipeline.redact(ms(10), 1),
+    );
+    pipeline.harness.assert_sent_texts(&["Answer: !ping"]);
+    assert_eq!(pipeline.typing(), vec![true, false]);
+}
+
+#[tokio::test]
+async fn without_a_delay_redactions_are_not_waited_for() {
+    let pipeline = Pipeline::new("!nodelay:localhost", Duration::ZERO);
+    tokio::join!(
+        pipeline.message(ms(0), 1, "hello", false),
+        pipeline.redact(ms(10), 1),
+    );
+    pipeline.harness.assert_sent_texts(&["Answer: hello"]);
+}
+
+#[test]
+fn who_waits_out_the_delay() {
+    let delay = ProcessingDelay::new(DELAY);
+    // (command, direct chat, messages) -> waits
+    let cases = [
+        ((false, false, 1), true),
+        ((false, false, 3), true),
+        ((true, false, 1), false),
+        ((true, true, 1), false),
+        // A lone message in a direct chat is clearly meant to be answered
+        ((false, true, 1), false),
+        // ... a burst there may still be retracted
+        ((false, true, 2), true),
+    ];
+    for ((command, direct_chat, messages), waits) in cases {
+        assert_eq!(
+            delay.applies(command, direct_chat, messages),
+            waits,
+            "command {}, direct chat {}, {} messages",
+            command,
+            direct_chat,
+            messages
+        );
+    }
+
+    let disabled = ProcessingDelay::new(Duration::ZERO);
+    assert!(!disabled.applies(false, false, 3));
+}
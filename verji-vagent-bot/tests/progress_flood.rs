@@ -0,0 +1,117 @@
+//! A graph flooding the bot with progress: updates are coalesced while a
+//! send is in flight, so the room sees a bounded number of sends
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::progress::{ProgressFeed, ProgressUpdate};
+use verji_vagent_bot::redis_client::{GraphMessage, GraphMessageType};
+use verji_vagent_bot::responder::OutgoingMessage;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const UPDATES: usize = 500;
+
+fn step(n: usize) -> serde_json::Value {
+    json!({"tool": "search", "step": n, "total": UPDATES, "summary": format!("Step {}", n)})
+}
+
+#[tokio::test]
+async fn a_slow_consumer_only_sees_the_newest_update() {
+    let (feed, mut updates) = ProgressFeed::new();
+
+    let consumer = tokio::spawn(async move {
+        let mut seen = Vec::new();
+        while updates.changed().await.is_ok() {
+            if let Some(update) = updates.borrow_and_update().clone() {
+                seen.push(update);
+            }
+            // A send to the homeserver
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        seen
+    });
+
+    for n in 1..=UPDATES {
+        feed.push(&GraphMessage {
+            request_id: "req-1".to_string(),
+            message_type: GraphMessageType::Progress,
+            content: format!("Step {}", n),
+            metadata: Some(step(n)),
+        });
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(feed.received(), UPDATES);
+    // Coalescing drops pending messages, never steps
+    assert_eq!(feed.step_count(), UPDATES);
+    drop(feed);
+
+    let seen = consumer.await.expect("consumer");
+    assert!(
+        !seen.is_empty() && seen.len() < 50,
+        "{} updates relayed",
+        seen.len()
+    );
+    let Some(ProgressUpdate::Steps(last)) = seen.last() else {
+        panic!("{:?}", seen.last());
+    };
+    assert!(last.contains("✅ … 485 earlier steps"), "{}", last);
+    assert!(
+        last.contains(&format!("⏳ **{0}/{0}** `search` — Step {0}", UPDATES)),
+        "{}",
+        last
+    );
+}
+
+#[tokio::test]
+async fn a_flood_of_progress_costs_a_bounded_number_of_sends() {
+    let mut steps: Vec<_> = (1..=UPDATES)
+        .map(|n| json!({"type": "progress", "content": format!("Step {}", n), "metadata": step(n), "delay_ms": 1}))
+        .collect();
+    steps.push(json!({"type": "final", "content": "All 500 steps done"}));
+    let script = json!({"scenarios": [{"name": "flood", "match": "(?i)flood", "steps": steps}]});
+    let script = MockScript::from_json(&script.to_string()).expect("script");
+
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::new(script)),
+    )));
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .room(
+            MockRoom::new("!test:localhost")
+                .expect("room")
+                .with_send_delay(Duration::from_millis(20)),
+        )
+        .configure(|config| config.locale = "en".to_string());
+
+    let messages = harness
+        .dispatch(&manager, "Flood me with progress")
+        .await
+        .expect("dispatch");
+    assert_eq!(
+        messages.iter().map(message_text).collect::<Vec<_>>(),
+        vec![Some("All 500 steps done")]
+    );
+
+    // One step message, edited in place far less often than steps arrived
+    let sent = harness.mock_room().sent();
+    let progress: Vec<_> = sent
+        .iter()
+        .filter(|message| matches!(message, OutgoingMessage::Markdown(body) if body.contains("`search`")))
+        .collect();
+    assert_eq!(progress.len(), 1, "{:?}", sent);
+    let edits = harness.mock_room().edits();
+    assert!(
+        edits.len() < 100,
+        "{} edits for {} updates",
+        edits.len(),
+        UPDATES
+    );
+    // The last edit still shows the newest step
+    let (_, last) = edits.last().expect("edits");
+    assert!(last.contains(&format!("Step {}", UPDATES)), "{}", last);
+}
@@ -0,0 +1,181 @@
+//! Rendering of graph progress, as snapshots of what the room sees after
+//! each message of a scripted run
+
+use serde_json::{json, Value};
+use std::time::Duration;
+use verji_vagent_bot::i18n::Catalog;
+use verji_vagent_bot::progress::{
+    format_duration, render_steps, ProgressFeed, ProgressStep, ProgressUpdate,
+};
+use verji_vagent_bot::redis_client::{GraphMessage, GraphMessageType};
+
+fn progress(content: &str, metadata: Option<Value>) -> GraphMessage {
+    GraphMessage {
+        request_id: "req-1".to_string(),
+        message_type: GraphMessageType::Progress,
+        content: content.to_string(),
+        metadata,
+    }
+}
+
+fn step(metadata: Value) -> ProgressStep {
+    ProgressStep::from_metadata(Some(&metadata)).expect("a structured step")
+}
+
+const ARGUMENTS: &str = "\n\n<details><summary>Arguments</summary>\n\n\
+                         - `search_docs`: `{\"query\":\"vacation policy\"}`\n\n</details>";
+
+#[test]
+fn a_scripted_run_renders_step_by_step() {
+    let script = [
+        progress("Thinking…", None),
+        progress(
+            "calling tool search_docs",
+            Some(json!({
+                "tool": "search_docs",
+                "step": 1,
+                "total": 3,
+                "summary": "Searching the handbook",
+                "args": {"query": "vacation policy"}
+            })),
+        ),
+        progress(
+            "calling tool read_page",
+            Some(json!({
+                "tool": "read_page",
+                "step": 2,
+                "total": 3,
+                "summary": "Reading 2 pages",
+                "duration_ms": 850
+            })),
+        ),
+        progress(
+            "writing",
+            Some(json!({"step": 3, "summary": "Writing the answer", "duration_ms": 4200})),
+        ),
+        progress("Almost there", None),
+    ];
+    let expected = [
+        ProgressUpdate::Text("Thinking…".to_string()),
+        ProgressUpdate::Steps(format!(
+            "⏳ **1/3** `search_docs` — Searching the handbook{}",
+            ARGUMENTS
+        )),
+        ProgressUpdate::Steps(format!(
+            "✅ **1/3** `search_docs` — Searching the handbook\n\
+             ⏳ **2/3** `read_page` — Reading 2 pages (850ms){}",
+            ARGUMENTS
+        )),
+        ProgressUpdate::Steps(format!(
+            "✅ **1/3** `search_docs` — Searching the handbook\n\
+             ✅ **2/3** `read_page` — Reading 2 pages (850ms)\n\
+             ⏳ **3** Writing the answer (4.2s){}",
+            ARGUMENTS
+        )),
+        ProgressUpdate::Text("Almost there".to_string()),
+    ];
+
+    let (feed, updates) = ProgressFeed::new();
+    for (message, expected) in script.iter().zip(expected) {
+        feed.push(message);
+        assert_eq!(
+            updates.borrow().clone(),
+            Some(expected),
+            "{}",
+            message.content
+        );
+    }
+    assert_eq!(feed.step_count(), 3);
+    assert_eq!(feed.received(), 5);
+}
+
+#[test]
+fn metadata_without_a_summary_is_raw_text() {
+    let (feed, updates) = ProgressFeed::new();
+    feed.push(&progress(
+        "calling tool search_docs with args {\"q\": 1}",
+        Some(json!({"tool": "search_docs", "step": 1})),
+    ));
+    assert_eq!(
+        updates.borrow().clone(),
+        Some(ProgressUpdate::Text(
+            "calling tool search_docs with args {\"q\": 1}".to_string()
+        ))
+    );
+    assert_eq!(feed.step_count(), 0);
+}
+
+#[test]
+fn a_lone_summary_is_a_bare_line() {
+    assert_eq!(
+        render_steps(&[step(json!({"summary": "Looking things up"}))]),
+        "⏳ Looking things up"
+    );
+}
+
+#[test]
+fn long_arguments_are_cut_and_kept_inside_code() {
+    let query = format!("`{}", "x".repeat(400));
+    let rendered = render_steps(&[step(json!({
+        "summary": "Searching",
+        "args": {"query": query}
+    }))]);
+
+    let line = rendered
+        .lines()
+        .find(|line| line.starts_with("- "))
+        .expect("argument line");
+    // Steps without a tool are labelled with their summary
+    let dump = line
+        .strip_prefix("- `Searching`: `")
+        .and_then(|dump| dump.strip_suffix('`'))
+        .expect(line);
+    assert!(!dump.contains('`'), "{}", dump);
+    assert!(dump.starts_with("{\"query\":\"'xxx"), "{}", dump);
+    assert!(dump.ends_with('…'), "{}", dump);
+    assert_eq!(dump.chars().count(), 301);
+}
+
+#[test]
+fn only_the_latest_steps_are_listed() {
+    let steps: Vec<ProgressStep> = (1..=20)
+        .map(|n| step(json!({"step": n, "summary": format!("Step {}", n)})))
+        .collect();
+    let rendered = render_steps(&steps);
+
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert_eq!(lines.len(), 16);
+    assert_eq!(lines[0], "✅ … 5 earlier steps");
+    assert_eq!(lines[1], "✅ **6** Step 6");
+    assert_eq!(lines[15], "⏳ **20** Step 20");
+}
+
+#[test]
+fn durations_are_short() {
+    let cases = [
+        (0, "0ms"),
+        (850, "850ms"),
+        (999, "999ms"),
+        (1_000, "1.0s"),
+        (4_240, "4.2s"),
+        (59_900, "59.9s"),
+        (60_000, "1m 00s"),
+        (125_000, "2m 05s"),
+    ];
+    for (ms, expected) in cases {
+        assert_eq!(format_duration(Duration::from_millis(ms)), expected);
+    }
+}
+
+#[test]
+fn the_summary_line_counts_steps_and_time() {
+    let summary = Catalog::embedded().translate(
+        "en",
+        "progress.summary",
+        &[
+            ("steps", "3"),
+            ("duration", &format_duration(Duration::from_millis(5_000))),
+        ],
+    );
+    assert_eq!(summary, "_Completed 3 steps in 5.0s_");
+}
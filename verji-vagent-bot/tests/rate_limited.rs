@@ -0,0 +1,234 @@
+//! Per-responder quotas on a manual clock, alone and under a cooldown
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use verji_vagent_bot::clock::Clock;
+use verji_vagent_bot::db::TempStore;
+use verji_vagent_bot::decorators::{Cooldown, RateLimited};
+use verji_vagent_bot::quota::{Quota, QuotaScope, QuotaStore};
+use verji_vagent_bot::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::testing::{message_text, ManualClock, MockRoom, ResponderTestHarness};
+
+/// 2023-11-14 22:13:20 UTC
+const START_MS: u64 = 1_700_000_000_000;
+const WINDOW: Duration = Duration::from_secs(3600);
+const EXCEEDED: &str = "⏳ You've reached the usage limit for this feature.";
+
+/// Answers, except for messages saying "pass"
+struct Expensive;
+
+#[async_trait]
+impl Responder for Expensive {
+    fn name(&self) -> &str {
+        "Expensive"
+    }
+
+    fn priority(&self) -> i32 {
+        50
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        if &*context.message_body == "pass" {
+            return Ok(ResponderResult::NotHandled);
+        }
+        Ok(ResponderResult::Handled(Some("Answer".to_string())))
+    }
+}
+
+/// Answers whatever gets past the expensive responder
+struct Fallback;
+
+#[async_trait]
+impl Responder for Fallback {
+    fn name(&self) -> &str {
+        "Fallback"
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some("Fallback".to_string())))
+    }
+}
+
+fn quota(scope: QuotaScope) -> Quota {
+    Quota {
+        limit: 2,
+        window: WINDOW,
+        scope,
+    }
+}
+
+fn manager(responder: Arc<dyn Responder>) -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(responder);
+    manager.register(Arc::new(Fallback));
+    manager
+}
+
+fn limited(
+    scope: QuotaScope,
+    store: &Arc<QuotaStore>,
+    clock: &Arc<ManualClock>,
+) -> RateLimited<Expensive> {
+    RateLimited::with_clock(
+        Expensive,
+        quota(scope),
+        Arc::clone(store),
+        Arc::clone(clock) as Arc<dyn Clock>,
+    )
+}
+
+fn harness() -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string())
+}
+
+async fn answer(harness: &ResponderTestHarness, manager: &ResponderManager, body: &str) -> String {
+    let messages = harness.dispatch(manager, body).await.expect("dispatch");
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    message_text(&messages[0]).expect("text").to_string()
+}
+
+#[tokio::test]
+async fn the_quota_trips_and_resets_with_its_window() {
+    let dir = TempStore::new("rate-limited").expect("store");
+    let store = Arc::new(QuotaStore::open(dir.path()).expect("quota store"));
+    let clock = Arc::new(ManualClock::new(START_MS));
+    let manager = manager(Arc::new(limited(QuotaScope::User, &store, &clock)));
+    let harness = harness();
+
+    assert_eq!(answer(&harness, &manager, "Q1").await, "Answer");
+    clock.advance(Duration::from_secs(600));
+    assert_eq!(answer(&harness, &manager, "Q2").await, "Answer");
+
+    // The window started with the first use, so it resets an hour after it
+    let messages = harness.dispatch(&manager, "Q3").await.expect("dispatch");
+    match &messages[..] {
+        [OutgoingMessage::Mention {
+            body,
+            mentions,
+            notice,
+            ..
+        }] => {
+            assert_eq!(
+                body,
+                "⏳ You've reached the usage limit for this feature. It resets at \
+                 2023-11-14 23:13 UTC."
+            );
+            assert_eq!(mentions.user_ids, vec!["@user:localhost".to_string()]);
+            assert!(notice);
+        }
+        other => panic!("expected the quota notice, got {:?}", other),
+    }
+
+    clock.advance(Duration::from_secs(3000));
+    assert_eq!(answer(&harness, &manager, "Q4").await, "Answer");
+}
+
+#[tokio::test]
+async fn messages_the_responder_passes_on_are_not_counted() {
+    let dir = TempStore::new("rate-limited-pass").expect("store");
+    let store = Arc::new(QuotaStore::open(dir.path()).expect("quota store"));
+    let clock = Arc::new(ManualClock::new(START_MS));
+    let manager = manager(Arc::new(limited(QuotaScope::User, &store, &clock)));
+    let harness = harness();
+
+    for _ in 0..5 {
+        assert_eq!(answer(&harness, &manager, "pass").await, "Fallback");
+    }
+    assert_eq!(answer(&harness, &manager, "Q1").await, "Answer");
+    assert_eq!(answer(&harness, &manager, "Q2").await, "Answer");
+}
+
+#[tokio::test]
+async fn buckets_follow_the_scope() {
+    let cases = [
+        // (scope, another user in the room limited?, the user in another room limited?)
+        (QuotaScope::User, false, true),
+        (QuotaScope::Room, true, false),
+        (QuotaScope::UserRoom, false, false),
+    ];
+    for (scope, same_room, same_user) in cases {
+        let dir = TempStore::new("rate-limited-scope").expect("store");
+        let store = Arc::new(QuotaStore::open(dir.path()).expect("quota store"));
+        let clock = Arc::new(ManualClock::new(START_MS));
+        let manager = manager(Arc::new(limited(scope, &store, &clock)));
+
+        let user = harness();
+        answer(&user, &manager, "Q1").await;
+        answer(&user, &manager, "Q2").await;
+        assert!(answer(&user, &manager, "Q3").await.starts_with(EXCEEDED));
+
+        let other_user = harness().sender("@other:localhost");
+        let reply = answer(&other_user, &manager, "Q1").await;
+        assert_eq!(reply.starts_with(EXCEEDED), same_room, "{:?}", scope);
+
+        let other_room = harness().room(MockRoom::new("!other:localhost").expect("room"));
+        let reply = answer(&other_room, &manager, "Q1").await;
+        assert_eq!(reply.starts_with(EXCEEDED), same_user, "{:?}", scope);
+    }
+}
+
+#[tokio::test]
+async fn used_quota_survives_a_restart() {
+    let dir = TempStore::new("rate-limited-restart").expect("store");
+    // Windows that ended before the restart are dropped, so this one has to
+    // be current
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let clock = Arc::new(ManualClock::new(now_ms));
+    let harness = harness();
+    {
+        let store = Arc::new(QuotaStore::open(dir.path()).expect("quota store"));
+        let manager = manager(Arc::new(limited(QuotaScope::User, &store, &clock)));
+        answer(&harness, &manager, "Q1").await;
+        answer(&harness, &manager, "Q2").await;
+        store.flush().await.expect("flush");
+    }
+
+    let store = Arc::new(QuotaStore::open(dir.path()).expect("quota store"));
+    let manager = manager(Arc::new(limited(QuotaScope::User, &store, &clock)));
+    assert!(answer(&harness, &manager, "Q3").await.starts_with(EXCEEDED));
+}
+
+#[tokio::test]
+async fn a_cooldown_skip_does_not_use_the_quota() {
+    let dir = TempStore::new("rate-limited-cooldown").expect("store");
+    let store = Arc::new(QuotaStore::open(dir.path()).expect("quota store"));
+    let clock = Arc::new(ManualClock::new(START_MS));
+    let cooldown = Duration::from_secs(60);
+    let manager = manager(Arc::new(RateLimited::with_clock(
+        Cooldown::with_clock(Expensive, cooldown, Arc::clone(&clock) as Arc<dyn Clock>),
+        quota(QuotaScope::User),
+        Arc::clone(&store),
+        Arc::clone(&clock) as Arc<dyn Clock>,
+    )));
+    let harness = harness();
+
+    assert_eq!(answer(&harness, &manager, "Q1").await, "Answer");
+    // Cooling down: the fallback answers and the quota is untouched
+    assert_eq!(answer(&harness, &manager, "Q2").await, "Fallback");
+    assert_eq!(answer(&harness, &manager, "Q3").await, "Fallback");
+
+    clock.advance(cooldown);
+    assert_eq!(answer(&harness, &manager, "Q4").await, "Answer");
+    clock.advance(cooldown);
+    assert!(answer(&harness, &manager, "Q5").await.starts_with(EXCEEDED));
+}
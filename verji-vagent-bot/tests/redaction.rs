@@ -0,0 +1,83 @@
+//! Which personal data is masked, and which look-alikes are left alone
+
+use verji_vagent_bot::redact::Redactor;
+
+fn built_in() -> Redactor {
+    Redactor::new(std::iter::empty())
+}
+
+#[test]
+fn personal_data_is_masked() {
+    let redactor = built_in();
+    let cases = [
+        // Email addresses
+        ("Mail ola.nordmann@example.no", "Mail [REDACTED:EMAIL]"),
+        ("<kari+ops@mail.example.com>", "<[REDACTED:EMAIL]>"),
+        // Fødselsnummer with valid check digits, with or without a space
+        ("FNR 15058545640", "FNR [REDACTED:FNR]"),
+        ("born 311299 49980", "born [REDACTED:FNR]"),
+        ("24106711212.", "[REDACTED:FNR]."),
+        // Payment cards passing the Luhn check
+        ("Card 4111 1111 1111 1111", "Card [REDACTED:CARD]"),
+        ("5500-0000-0000-0004 expires", "[REDACTED:CARD] expires"),
+        ("4111111111111111", "[REDACTED:CARD]"),
+        // Norwegian and international phone numbers
+        ("Call 912 34 567", "Call [REDACTED:PHONE]"),
+        ("Call 91234567", "Call [REDACTED:PHONE]"),
+        ("Call +47 912 34 567", "Call [REDACTED:PHONE]"),
+        ("Call +44 20 7946 0958", "Call [REDACTED:PHONE]"),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(redactor.redact(text), expected, "{:?}", text);
+    }
+}
+
+#[test]
+fn look_alikes_are_kept() {
+    let redactor = built_in();
+    let cases = [
+        // Eleven digits failing either fødselsnummer check digit
+        "Invoice 15058545641",
+        "Invoice 15058545650",
+        "Account 12345678901",
+        // Sixteen digits failing the Luhn check
+        "Order 4111111111111112",
+        // Too few or too many digits for a phone number
+        "Ticket 123456",
+        "Call +47 123",
+        // Matrix IDs and bare handles aren't addresses
+        "@user:localhost",
+        "ping @ops",
+        "No personal data here.",
+    ];
+    for text in cases {
+        assert_eq!(redactor.redact(text), text, "{:?}", text);
+    }
+}
+
+#[test]
+fn every_match_in_a_text_is_masked() {
+    let redactor = built_in();
+    assert_eq!(
+        redactor.redact(
+            "Kari (kari@example.no, 912 34 567) has FNR 15058545640 and card 4111111111111111; \
+             Ola has ola@example.no"
+        ),
+        "Kari ([REDACTED:EMAIL], [REDACTED:PHONE]) has FNR [REDACTED:FNR] and card \
+         [REDACTED:CARD]; Ola has [REDACTED:EMAIL]"
+    );
+}
+
+#[test]
+fn custom_patterns_come_after_the_built_in_ones() {
+    let redactor = Redactor::new([
+        ("employee", r"EMP-\d{4}"),
+        // Invalid, so skipped without affecting the others
+        ("broken", r"("),
+        ("project", r"(?i)project\s+falcon"),
+    ]);
+    assert_eq!(
+        redactor.redact("EMP-0042 on Project Falcon, ola@example.no"),
+        "[REDACTED:EMPLOYEE] on [REDACTED:PROJECT], [REDACTED:EMAIL]"
+    );
+}
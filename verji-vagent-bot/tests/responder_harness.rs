@@ -0,0 +1,110 @@
+//! Responders run against a mock room: the ping command and the agent's
+//! reply when vagent-graph can't be reached
+
+use std::sync::Arc;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::{PingPongResponder, VerjiAgentResponder};
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const UNAVAILABLE: &str = "⚠️ The AI assistant can't be reached right now.";
+const CIRCUIT_OPEN: &str = "⚠️ The AI assistant is temporarily unavailable.";
+
+fn harness() -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string())
+}
+
+/// An agent whose graph is never reachable: the client refuses the URL, so
+/// every connection attempt fails at once
+fn offline_agent() -> Arc<VerjiAgentResponder> {
+    Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Redis {
+            url: "not a redis url".to_string(),
+        },
+    ))
+}
+
+#[tokio::test]
+async fn ping_gets_pong() {
+    let harness = harness();
+    for body in ["!ping", "ping", "PING"] {
+        let messages = harness
+            .respond(Arc::new(PingPongResponder::new()), body)
+            .await
+            .expect("dispatch");
+        assert_eq!(messages.len(), 1, "{}: {:?}", body, messages);
+        assert_eq!(message_text(&messages[0]), Some("Pong!"));
+    }
+    harness.assert_sent_texts(&["Pong!", "Pong!", "Pong!"]);
+}
+
+#[tokio::test]
+async fn ping_with_arguments_gets_usage() {
+    let harness = harness();
+    let messages = harness
+        .respond(Arc::new(PingPongResponder::new()), "!ping twice")
+        .await
+        .expect("dispatch");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(message_text(&messages[0]), Some("Usage: `!ping`"));
+}
+
+#[tokio::test]
+async fn sentences_starting_with_ping_reach_the_agent() {
+    let harness = harness();
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(PingPongResponder::new()));
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::new(MockScript::default())),
+    )));
+
+    let messages = harness
+        .dispatch(&manager, "ping me when the report is ready")
+        .await
+        .expect("dispatch");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        message_text(&messages[0]),
+        Some("Echo: ping me when the report is ready")
+    );
+}
+
+#[tokio::test]
+async fn an_unreachable_graph_gets_the_unavailable_reply() {
+    let harness = harness();
+    let agent = offline_agent();
+
+    let messages = harness
+        .respond(agent.clone(), "What's on the agenda?")
+        .await
+        .expect("dispatch");
+    assert_eq!(messages.len(), 1);
+    let reply = message_text(&messages[0]).expect("text reply");
+    assert!(reply.starts_with(UNAVAILABLE), "{}", reply);
+
+    // Right after a failed attempt the bot doesn't try again, and says so
+    let messages = harness
+        .respond(agent, "Anyone there?")
+        .await
+        .expect("dispatch");
+    let reply = message_text(&messages[0]).expect("text reply");
+    assert!(reply.starts_with(CIRCUIT_OPEN), "{}", reply);
+}
+
+#[tokio::test]
+async fn an_unreachable_graph_is_recorded_as_a_failed_query() {
+    let harness = harness();
+    harness
+        .respond(offline_agent(), "What's on the agenda?")
+        .await
+        .expect("dispatch");
+
+    let users = harness.stats().room_users("!test:localhost");
+    assert_eq!(users.len(), 1, "{:?}", users);
+    let (user, counters) = &users[0];
+    assert_eq!(user, "@user:localhost");
+    assert_eq!((counters.queries, counters.errors), (1, 1));
+}
@@ -0,0 +1,177 @@
+//! A responder that errors or panics doesn't take the chain down with it:
+//! with the default policy the next responder still answers
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::routing::Verdict;
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+
+#[derive(Clone, Copy, Debug)]
+enum Fault {
+    /// `handle` returns an error
+    Errors,
+    /// `handle` panics
+    Panics,
+    /// `should_handle` panics
+    PanicsInFilter,
+    /// Defers, then panics in `fallback`
+    PanicsInFallback,
+}
+
+struct Broken(Fault);
+
+#[async_trait]
+impl Responder for Broken {
+    fn name(&self) -> &str {
+        "Broken"
+    }
+
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        if let Fault::PanicsInFilter = self.0 {
+            panic!("filter blew up");
+        }
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        match self.0 {
+            Fault::Errors => bail!("Database is locked"),
+            Fault::Panics => panic!("called `Option::unwrap()` on a `None` value"),
+            Fault::PanicsInFilter => unreachable!("declined by the filter"),
+            Fault::PanicsInFallback => Ok(ResponderResult::Defer),
+        }
+    }
+
+    async fn fallback(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        panic!("fallback blew up");
+    }
+}
+
+/// Answers when asked first, passes when it would have to answer last, so
+/// the broken fallback gets its turn
+struct Steady {
+    passes: bool,
+}
+
+#[async_trait]
+impl Responder for Steady {
+    fn name(&self) -> &str {
+        "Steady"
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        if self.passes {
+            return Ok(ResponderResult::NotHandled);
+        }
+        Ok(ResponderResult::Handled(Some("Steady answer".to_string())))
+    }
+}
+
+fn manager(fault: Fault, steady_passes: bool) -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Broken(fault)));
+    manager.register(Arc::new(Steady {
+        passes: steady_passes,
+    }));
+    manager
+}
+
+async fn texts(harness: &ResponderTestHarness, manager: &ResponderManager) -> Vec<String> {
+    harness
+        .dispatch(manager, "Hello")
+        .await
+        .expect("dispatch")
+        .iter()
+        .filter_map(message_text)
+        .map(str::to_string)
+        .collect()
+}
+
+#[tokio::test]
+async fn the_next_responder_answers_past_errors_and_panics() {
+    for fault in [Fault::Errors, Fault::Panics, Fault::PanicsInFilter] {
+        let harness = ResponderTestHarness::new().expect("harness");
+        let manager = manager(fault, false);
+
+        // Again and again: nothing is poisoned by the first failure
+        for _ in 0..3 {
+            assert_eq!(
+                texts(&harness, &manager).await,
+                vec!["Steady answer"],
+                "{:?}",
+                fault
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_failed_handle_is_traced_as_failed() {
+    for fault in [Fault::Errors, Fault::Panics] {
+        let harness = ResponderTestHarness::new().expect("harness");
+        let manager = manager(fault, true);
+
+        assert!(texts(&harness, &manager).await.is_empty());
+        let traces = manager.traces().unanswered("!test:localhost", 10);
+        let verdicts: Vec<_> = traces[0]
+            .steps
+            .iter()
+            .map(|step| (step.responder.as_str(), step.verdict.clone()))
+            .collect();
+        assert_eq!(
+            verdicts,
+            vec![("Broken", Verdict::Failed), ("Steady", Verdict::NotHandled)],
+            "{:?}",
+            fault
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_panicking_filter_counts_as_a_decline() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let manager = manager(Fault::PanicsInFilter, true);
+
+    assert!(texts(&harness, &manager).await.is_empty());
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(
+        traces[0].steps[0].verdict,
+        Verdict::Declined(Some("should_handle_panicked"))
+    );
+}
+
+#[tokio::test]
+async fn a_panicking_fallback_is_contained() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let manager = manager(Fault::PanicsInFallback, true);
+
+    for _ in 0..2 {
+        assert!(texts(&harness, &manager).await.is_empty());
+    }
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(traces.len(), 2);
+    let verdicts: Vec<_> = traces[0]
+        .steps
+        .iter()
+        .map(|step| step.verdict.clone())
+        .collect();
+    assert_eq!(
+        verdicts,
+        vec![Verdict::Deferred, Verdict::NotHandled, Verdict::Failed]
+    );
+}
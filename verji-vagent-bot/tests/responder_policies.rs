@@ -0,0 +1,210 @@
+//! The manager's timeout and error policies, with a responder that sleeps
+//! past its timeout, one that fails and one that panics ahead of a fallback
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::i18n::CannedReply;
+use verji_vagent_bot::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::{ErrorPolicy, ResponderManager, TimeoutPolicy};
+use verji_vagent_bot::testing::ResponderTestHarness;
+
+const TIMEOUT_REPLY: &str = "⏱️ Sorry, that took too long. Please try again.";
+const ERROR_REPLY: &str = "⚠️ Something went wrong while handling your message.";
+
+#[derive(Clone, Copy)]
+enum Trouble {
+    /// Sleeps far past its timeout
+    Sleeps,
+    /// Returns an error
+    Fails,
+    /// Panics
+    Panics,
+}
+
+/// Claims every message ahead of the fallback and never answers it
+struct Troubled(Trouble);
+
+#[async_trait]
+impl Responder for Troubled {
+    fn name(&self) -> &str {
+        "Troubled"
+    }
+
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_millis(50))
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        match self.0 {
+            Trouble::Sleeps => {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                Ok(ResponderResult::Handled(Some("Too late".to_string())))
+            }
+            Trouble::Fails => bail!("Backend refused the request"),
+            Trouble::Panics => panic!("index out of bounds"),
+        }
+    }
+}
+
+/// Answers whatever reaches it, counting how often it did
+#[derive(Default)]
+struct Fallback {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl Responder for Fallback {
+    fn name(&self) -> &str {
+        "Fallback"
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(ResponderResult::Handled(Some(
+            "Fallback answer".to_string(),
+        )))
+    }
+}
+
+/// Dispatch one message to `trouble` and the fallback under `configure`'s
+/// policies; returns the output and whether the fallback was asked
+async fn dispatch(
+    trouble: Trouble,
+    configure: impl FnOnce(&mut ResponderManager),
+) -> (Vec<OutgoingMessage>, bool) {
+    let fallback = Arc::new(Fallback::default());
+    let mut manager = ResponderManager::new();
+    configure(&mut manager);
+    manager.register(Arc::new(Troubled(trouble)));
+    manager.register(Arc::clone(&fallback) as Arc<dyn Responder>);
+
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .configure(|config| config.locale = "en".to_string());
+    let messages =
+        tokio::time::timeout(Duration::from_secs(5), harness.dispatch(&manager, "Hello"))
+            .await
+            .expect("the sleeping responder wasn't cut off")
+            .expect("dispatch");
+    (messages, fallback.calls.load(Ordering::SeqCst) > 0)
+}
+
+/// Each message as its kind and text
+fn shown(messages: &[OutgoingMessage]) -> Vec<(&'static str, &str)> {
+    messages
+        .iter()
+        .map(|message| match message {
+            OutgoingMessage::Text(body) => ("text", body.as_str()),
+            OutgoingMessage::Notice(body) => ("notice", body.as_str()),
+            other => panic!("unexpected message {:?}", other),
+        })
+        .collect()
+}
+
+const FALLBACK_ANSWER: (&str, &str) = ("text", "Fallback answer");
+
+#[tokio::test]
+async fn a_timeout_continues_with_the_notice_before_the_fallback() {
+    let (messages, fell_back) = dispatch(Trouble::Sleeps, |manager| {
+        manager.set_timeout_policy(TimeoutPolicy::Continue, CannedReply::Default)
+    })
+    .await;
+    assert!(fell_back);
+    assert_eq!(
+        shown(&messages),
+        vec![("notice", TIMEOUT_REPLY), FALLBACK_ANSWER]
+    );
+}
+
+#[tokio::test]
+async fn a_silent_timeout_continues_without_a_notice() {
+    let (messages, fell_back) = dispatch(Trouble::Sleeps, |manager| {
+        manager.set_timeout_policy(TimeoutPolicy::Continue, CannedReply::Silent)
+    })
+    .await;
+    assert!(fell_back);
+    assert_eq!(shown(&messages), vec![FALLBACK_ANSWER]);
+}
+
+#[tokio::test]
+async fn a_timeout_aborts_with_the_custom_reply() {
+    let (messages, fell_back) = dispatch(Trouble::Sleeps, |manager| {
+        manager.set_timeout_policy(
+            TimeoutPolicy::Abort,
+            CannedReply::Custom("Still thinking, ask again later".to_string()),
+        )
+    })
+    .await;
+    assert!(!fell_back);
+    assert_eq!(
+        shown(&messages),
+        vec![("notice", "Still thinking, ask again later")]
+    );
+}
+
+#[tokio::test]
+async fn errors_and_panics_continue_to_the_fallback() {
+    for trouble in [Trouble::Fails, Trouble::Panics] {
+        let (messages, fell_back) = dispatch(trouble, |manager| {
+            manager.set_error_policy(ErrorPolicy::Continue, CannedReply::Default)
+        })
+        .await;
+        assert!(fell_back);
+        assert_eq!(shown(&messages), vec![FALLBACK_ANSWER]);
+    }
+}
+
+#[tokio::test]
+async fn errors_and_panics_get_the_error_reply() {
+    for trouble in [Trouble::Fails, Trouble::Panics] {
+        let (messages, fell_back) = dispatch(trouble, |manager| {
+            manager.set_error_policy(ErrorPolicy::Reply, CannedReply::Default)
+        })
+        .await;
+        assert!(!fell_back);
+        assert_eq!(shown(&messages), vec![("notice", ERROR_REPLY)]);
+    }
+}
+
+#[tokio::test]
+async fn errors_and_panics_abort_silently() {
+    for trouble in [Trouble::Fails, Trouble::Panics] {
+        let (messages, fell_back) = dispatch(trouble, |manager| {
+            manager.set_error_policy(ErrorPolicy::Abort, CannedReply::Default)
+        })
+        .await;
+        assert!(!fell_back);
+        assert!(messages.is_empty(), "{:?}", messages);
+    }
+}
+
+#[tokio::test]
+async fn a_timeout_is_not_an_error() {
+    // The error policy doesn't apply to a responder that only took too long
+    let (messages, fell_back) = dispatch(Trouble::Sleeps, |manager| {
+        manager.set_timeout_policy(TimeoutPolicy::Continue, CannedReply::Silent);
+        manager.set_error_policy(ErrorPolicy::Abort, CannedReply::Default);
+    })
+    .await;
+    assert!(fell_back);
+    assert_eq!(shown(&messages), vec![FALLBACK_ANSWER]);
+}
@@ -0,0 +1,190 @@
+//! Registering, replacing and removing responders at runtime
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+
+/// Answers every message with its own name
+struct Named {
+    name: String,
+    priority: i32,
+}
+
+fn named(name: &str, priority: i32) -> Arc<dyn Responder> {
+    Arc::new(Named {
+        name: name.to_string(),
+        priority,
+    })
+}
+
+#[async_trait]
+impl Responder for Named {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some(self.name.clone())))
+    }
+}
+
+/// Holds the chain at the top until released, then passes the message on
+#[derive(Default)]
+struct Gate {
+    entered: Notify,
+    release: Notify,
+}
+
+#[async_trait]
+impl Responder for Gate {
+    fn name(&self) -> &str {
+        "Gate"
+    }
+
+    fn priority(&self) -> i32 {
+        1000
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        self.entered.notify_one();
+        self.release.notified().await;
+        Ok(ResponderResult::NotHandled)
+    }
+}
+
+fn listed(manager: &ResponderManager) -> Vec<(String, i32)> {
+    manager
+        .list_responders()
+        .into_iter()
+        .map(|info| (info.name, info.priority))
+        .collect()
+}
+
+fn is_sorted(responders: &[(String, i32)]) -> bool {
+    responders.windows(2).all(|pair| pair[0].1 >= pair[1].1)
+}
+
+async fn answer(harness: &ResponderTestHarness, manager: &ResponderManager) -> Option<String> {
+    let messages = harness.dispatch(manager, "Hello").await.expect("dispatch");
+    messages.first().and_then(message_text).map(str::to_string)
+}
+
+#[test]
+fn responders_stay_sorted_by_priority() {
+    let manager = ResponderManager::new();
+    manager.register(named("Middle", 50));
+    manager.register(named("Low", 0));
+    manager.register(named("High", 100));
+    assert_eq!(
+        listed(&manager),
+        vec![
+            ("High".to_string(), 100),
+            ("Middle".to_string(), 50),
+            ("Low".to_string(), 0)
+        ]
+    );
+
+    // A replacement takes the new responder's priority
+    assert!(manager.replace("Low", named("Urgent", 200)));
+    assert_eq!(
+        listed(&manager),
+        vec![
+            ("Urgent".to_string(), 200),
+            ("High".to_string(), 100),
+            ("Middle".to_string(), 50)
+        ]
+    );
+
+    let (removed, _scope) = manager.unregister("High").expect("registered");
+    assert_eq!(removed.name(), "High");
+    assert_eq!(
+        listed(&manager),
+        vec![("Urgent".to_string(), 200), ("Middle".to_string(), 50)]
+    );
+}
+
+#[test]
+fn unknown_names_change_nothing() {
+    let manager = ResponderManager::new();
+    manager.register(named("Only", 10));
+
+    assert!(manager.unregister("Missing").is_none());
+    assert!(!manager.replace("Missing", named("Other", 20)));
+    assert_eq!(listed(&manager), vec![("Only".to_string(), 10)]);
+}
+
+#[tokio::test]
+async fn a_dispatch_in_flight_keeps_its_snapshot() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let gate = Arc::new(Gate::default());
+    let manager = ResponderManager::new();
+    manager.register(Arc::clone(&gate) as Arc<dyn Responder>);
+    manager.register(named("Old", 0));
+
+    let (in_flight, ()) = tokio::join!(answer(&harness, &manager), async {
+        gate.entered.notified().await;
+        manager.unregister("Old");
+        manager.register(named("New", 0));
+        gate.release.notify_one();
+    });
+    assert_eq!(in_flight.as_deref(), Some("Old"));
+
+    // The next message sees the change
+    let next = tokio::join!(answer(&harness, &manager), async {
+        gate.entered.notified().await;
+        gate.release.notify_one();
+    })
+    .0;
+    assert_eq!(next.as_deref(), Some("New"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn registration_races_dispatch_safely() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let manager = Arc::new(ResponderManager::new());
+    manager.register(named("Default", 0));
+
+    let churn: Vec<_> = (0..4)
+        .map(|worker| {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move {
+                for round in 0..50 {
+                    let name = format!("Temp{}-{}", worker, round);
+                    // Negative, so they never answer ahead of the default
+                    manager.register(named(&name, -1 - (round % 7)));
+                    assert!(is_sorted(&listed(&manager)));
+                    if round % 2 == 0 {
+                        assert!(manager.replace(&name, named(&name, -100)));
+                    }
+                    assert!(manager.unregister(&name).is_some());
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    for _ in 0..100 {
+        assert_eq!(answer(&harness, &manager).await.as_deref(), Some("Default"));
+    }
+    for worker in churn {
+        worker.await.expect("churn worker");
+    }
+
+    assert_eq!(listed(&manager), vec![("Default".to_string(), 0)]);
+}
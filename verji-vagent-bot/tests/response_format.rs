@@ -0,0 +1,114 @@
+//! Which format answers go out in: `RESPONSE_FORMAT`, the room's pinned
+//! format and, with `auto`, the question's
+
+use serde_json::json;
+use verji_vagent_bot::responder::OutgoingMessage;
+use verji_vagent_bot::room_config::{ResponseFormat, RoomConfig};
+
+use ResponseFormat::{Auto, Markdown, Plain};
+
+#[test]
+fn every_combination_resolves() {
+    // (configured, room, question formatted) -> answer
+    let cases = [
+        // Auto follows the question
+        ((Auto, None, true), Markdown),
+        ((Auto, None, false), Plain),
+        // Explicit settings override it
+        ((Markdown, None, false), Markdown),
+        ((Markdown, None, true), Markdown),
+        ((Plain, None, true), Plain),
+        ((Plain, None, false), Plain),
+        // A room's format wins over the configured one...
+        ((Auto, Some(Plain), true), Plain),
+        ((Auto, Some(Markdown), false), Markdown),
+        ((Markdown, Some(Plain), true), Plain),
+        ((Plain, Some(Markdown), false), Markdown),
+        // ... including a room set to follow the question
+        ((Plain, Some(Auto), true), Markdown),
+        ((Markdown, Some(Auto), false), Plain),
+        ((Auto, Some(Auto), true), Markdown),
+        ((Auto, Some(Auto), false), Plain),
+    ];
+    for ((configured, room, formatted), expected) in cases {
+        assert_eq!(
+            configured.resolve(room, formatted),
+            expected,
+            "{:?} with room {:?}, formatted question {}",
+            configured,
+            room,
+            formatted
+        );
+    }
+}
+
+#[test]
+fn resolving_never_leaves_auto() {
+    for configured in [Auto, Markdown, Plain] {
+        for room in [None, Some(Auto), Some(Markdown), Some(Plain)] {
+            for formatted in [true, false] {
+                assert_ne!(configured.resolve(room, formatted), Auto);
+            }
+        }
+    }
+}
+
+#[test]
+fn settings_parse() {
+    let cases = [
+        ("markdown", Some(Markdown)),
+        ("md", Some(Markdown)),
+        (" Plain ", Some(Plain)),
+        ("text", Some(Plain)),
+        ("AUTO", Some(Auto)),
+        ("html", None),
+        ("", None),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(ResponseFormat::parse(value), expected, "{:?}", value);
+    }
+    for format in [Auto, Markdown, Plain] {
+        assert_eq!(ResponseFormat::parse(format.as_str()), Some(format));
+    }
+    assert_eq!(ResponseFormat::default(), Markdown);
+}
+
+#[test]
+fn a_room_pins_its_format_in_its_config() {
+    let config: RoomConfig =
+        serde_json::from_value(json!({"response_format": "plain"})).expect("config");
+    assert_eq!(config.response_format, Some(Plain));
+    assert_eq!(
+        serde_json::to_value(&config).expect("serialize"),
+        json!({"response_format": "plain"})
+    );
+
+    // Rooms without one follow `RESPONSE_FORMAT`
+    let config: RoomConfig = serde_json::from_value(json!({})).expect("config");
+    assert_eq!(config.response_format, None);
+    assert!(serde_json::to_value(&config)
+        .expect("serialize")
+        .get("response_format")
+        .is_none());
+}
+
+#[test]
+fn plain_answers_drop_only_the_markdown() {
+    let plain = OutgoingMessage::Markdown("**Paid** on _Monday_".to_string()).into_plain();
+    assert!(
+        matches!(&plain, OutgoingMessage::Text(body) if body == "**Paid** on _Monday_"),
+        "{:?}",
+        plain
+    );
+
+    let unchanged = [
+        OutgoingMessage::Text("hi".to_string()),
+        OutgoingMessage::Notice("note".to_string()),
+        OutgoingMessage::Reaction("👍".to_string()),
+        OutgoingMessage::notice_mentioning("@user:localhost", "Done"),
+    ];
+    for message in unchanged {
+        let before = format!("{:?}", message);
+        assert_eq!(format!("{:?}", message.into_plain()), before);
+    }
+}
@@ -0,0 +1,182 @@
+//! Responders rewriting a message for the rest of the chain, and the cap on
+//! rewrites per message
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::{ResponderManager, MAX_REWRITES};
+use verji_vagent_bot::routing::Verdict;
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+
+/// Rewrites every message it sees by appending `suffix`, counting calls
+struct Appender {
+    suffix: &'static str,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl Responder for Appender {
+    fn name(&self) -> &str {
+        "Appender"
+    }
+
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let body = format!("{}{}", context.message_body, self.suffix);
+        Ok(ResponderResult::Rewritten(Box::new(context.rewrite(
+            body,
+            "appended",
+            self.suffix,
+        ))))
+    }
+}
+
+/// Expands `tl;dr` once, leaving rewritten messages alone
+struct Expander;
+
+#[async_trait]
+impl Responder for Expander {
+    fn name(&self) -> &str {
+        "Expander"
+    }
+
+    fn priority(&self) -> i32 {
+        100
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        context.message_body.contains("tl;dr")
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let body = context.message_body.replace("tl;dr", "summarize briefly:");
+        Ok(ResponderResult::Rewritten(Box::new(
+            context.rewrite(body, "expanded", "tl;dr"),
+        )))
+    }
+}
+
+/// Answers with the body it was given, remembering each one
+#[derive(Default)]
+struct Recorder {
+    seen: Mutex<Vec<(String, Option<String>)>>,
+}
+
+#[async_trait]
+impl Responder for Recorder {
+    fn name(&self) -> &str {
+        "Recorder"
+    }
+
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let annotation = context.annotations.get("expanded").cloned();
+        self.seen
+            .lock()
+            .unwrap()
+            .push((context.message_body.to_string(), annotation));
+        Ok(ResponderResult::Handled(Some(format!(
+            "Got: {}",
+            context.message_body
+        ))))
+    }
+}
+
+async fn texts(manager: &ResponderManager, body: &str) -> Vec<String> {
+    let harness = ResponderTestHarness::new().expect("harness");
+    harness
+        .dispatch(manager, body)
+        .await
+        .expect("dispatch")
+        .iter()
+        .filter_map(message_text)
+        .map(str::to_string)
+        .collect()
+}
+
+#[tokio::test]
+async fn the_next_responder_sees_the_rewritten_body() {
+    let recorder = Arc::new(Recorder::default());
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Expander));
+    manager.register(Arc::clone(&recorder) as Arc<dyn Responder>);
+
+    assert_eq!(
+        texts(&manager, "tl;dr the meeting notes").await,
+        vec!["Got: summarize briefly: the meeting notes"]
+    );
+    // Only the rewritten message reached the recorder, annotated
+    assert_eq!(
+        *recorder.seen.lock().unwrap(),
+        vec![(
+            "summarize briefly: the meeting notes".to_string(),
+            Some("tl;dr".to_string())
+        )]
+    );
+
+    // Messages nobody rewrites pass through as they are
+    assert_eq!(texts(&manager, "Hello").await, vec!["Got: Hello"]);
+}
+
+#[tokio::test]
+async fn rewrites_stop_at_the_limit() {
+    let appender = Arc::new(Appender {
+        suffix: "!",
+        calls: AtomicUsize::new(0),
+    });
+    let recorder = Arc::new(Recorder::default());
+    let manager = ResponderManager::new();
+    manager.register(Arc::clone(&appender) as Arc<dyn Responder>);
+    manager.register(Arc::clone(&recorder) as Arc<dyn Responder>);
+
+    // The rewrite past the limit counts as NotHandled, so the chain moves on
+    // with the body of the last allowed rewrite
+    let expected = format!("Hello{}", "!".repeat(MAX_REWRITES));
+    assert_eq!(
+        texts(&manager, "Hello").await,
+        vec![format!("Got: {}", expected)]
+    );
+    assert_eq!(appender.calls.load(Ordering::SeqCst), MAX_REWRITES + 1);
+    assert_eq!(recorder.seen.lock().unwrap()[0].0, expected);
+}
+
+#[tokio::test]
+async fn a_message_rewritten_forever_goes_unanswered() {
+    let appender = Arc::new(Appender {
+        suffix: "?",
+        calls: AtomicUsize::new(0),
+    });
+    let manager = ResponderManager::new();
+    manager.register(Arc::clone(&appender) as Arc<dyn Responder>);
+
+    assert!(texts(&manager, "Hello").await.is_empty());
+    assert_eq!(appender.calls.load(Ordering::SeqCst), MAX_REWRITES + 1);
+
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(traces.len(), 1);
+    let verdicts: Vec<_> = traces[0]
+        .steps
+        .iter()
+        .map(|step| step.verdict.clone())
+        .collect();
+    let mut expected = vec![Verdict::Rewritten; MAX_REWRITES];
+    expected.push(Verdict::NotHandled);
+    assert_eq!(verdicts, expected);
+}
@@ -0,0 +1,120 @@
+//! Dropping work for a room the bot is kicked from while answering
+//!
+//! The mock graph sends one progress update for "slow" questions and then
+//! never answers, so the kick always lands mid-request.
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use verji_vagent_bot::conversation::{ConversationKey, ConversationStore};
+use verji_vagent_bot::request_id;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const SCRIPT: &str = r#"{"fallback_delay_ms": 0, "scenarios": [{"match": "slow", "steps": [
+    {"type": "progress", "content": "Looking into it"},
+    {"type": "hang"}
+]}]}"#;
+
+fn setup(
+    room_id: &str,
+) -> (
+    Arc<MockScript>,
+    Arc<VerjiAgentResponder>,
+    ResponderTestHarness,
+) {
+    let script = Arc::new(MockScript::from_json(SCRIPT).expect("script"));
+    let responder = Arc::new(VerjiAgentResponder::with_transport(TransportConfig::Mock(
+        Arc::clone(&script),
+    )));
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new(room_id).expect("room"));
+    (script, responder, harness)
+}
+
+/// Wait for `done` to hold, failing the test after a few seconds
+async fn until(what: &str, done: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for {}", what));
+}
+
+#[tokio::test]
+async fn kick_mid_request_drops_the_answer_and_cancels_the_request() {
+    let (script, responder, harness) = setup("!kicked:localhost");
+    let manager = ResponderManager::new();
+    manager.register(responder);
+    let context = harness.context("A slow question").await.expect("context");
+    let request_id = request_id::for_message(&context);
+
+    let kick = async {
+        until("the progress update", || {
+            !harness.mock_room().sent().is_empty()
+        })
+        .await;
+        harness.kick();
+    };
+    let (messages, ()) = tokio::join!(manager.dispatch(&context), kick);
+
+    assert!(messages.expect("dispatch").is_empty());
+    until("the cancellation", || !script.cancelled().is_empty()).await;
+    assert_eq!(script.cancelled(), vec![request_id]);
+    harness.assert_sent_texts(&["Looking into it"]);
+}
+
+#[tokio::test]
+async fn messages_after_a_kick_are_not_answered() {
+    let (script, responder, harness) = setup("!banned:localhost");
+    harness.kick();
+
+    let messages = harness
+        .respond(responder, "Anyone there?")
+        .await
+        .expect("dispatch");
+    assert!(messages.is_empty());
+    assert!(harness.mock_room().sent().is_empty());
+    assert!(script.cancelled().is_empty());
+}
+
+#[tokio::test]
+async fn rejoining_answers_again() {
+    let (_script, responder, harness) = setup("!reinvited:localhost");
+    harness.kick();
+    harness.rejoin();
+
+    let messages = harness
+        .respond(responder, "Hello again")
+        .await
+        .expect("dispatch");
+    assert_eq!(messages.len(), 1, "expected one answer: {:?}", messages);
+    assert_eq!(message_text(&messages[0]), Some("Echo: Hello again"));
+}
+
+#[tokio::test]
+async fn leaving_clears_only_that_rooms_state() {
+    let store = ConversationStore::in_memory(100);
+    let gone = ConversationKey::new("!gone:localhost", "@user:localhost", "hitl.pending");
+    let kept = ConversationKey::new("!kept:localhost", "@user:localhost", "hitl.pending");
+    store
+        .set(gone.clone(), json!({"request_id": "a"}), None)
+        .await;
+    store
+        .set(gone.clone().in_thread("$root"), json!(true), None)
+        .await;
+    store
+        .set(kept.clone(), json!({"request_id": "b"}), None)
+        .await;
+
+    let removed = store.remove_room("!gone:localhost").await;
+    assert_eq!(removed.len(), 2);
+    assert_eq!(store.get(&gone).await, None);
+    assert_eq!(store.get(&kept).await, Some(json!({"request_id": "b"})));
+}
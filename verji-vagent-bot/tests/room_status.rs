@@ -0,0 +1,87 @@
+//! Shape and pagination of the `GET /api/rooms` answer
+
+use serde_json::json;
+use verji_vagent_bot::room_status::{paginate, PageQuery, RoomStatus, RoomsPage, MAX_PAGE_SIZE};
+
+fn room(n: usize) -> RoomStatus {
+    RoomStatus {
+        room_id: format!("!room{:04}:example.org", n),
+        display_name: format!("Room {}", n),
+        ..RoomStatus::default()
+    }
+}
+
+#[test]
+fn page_serializes_to_the_documented_shape() {
+    let page = RoomsPage {
+        rooms: vec![RoomStatus {
+            room_id: "!abc:example.org".to_string(),
+            display_name: "Support".to_string(),
+            last_response: Some(1_760_000_000),
+            last_activity: Some(1_760_000_100),
+            queries: 12,
+            errors: 1,
+            queue_depth: 2,
+        }],
+        total: 1,
+        next_cursor: None,
+    };
+    let expected = json!({
+        "rooms": [{
+            "room_id": "!abc:example.org",
+            "display_name": "Support",
+            "last_response": 1_760_000_000,
+            "last_activity": 1_760_000_100,
+            "queries": 12,
+            "errors": 1,
+            "queue_depth": 2,
+        }],
+        "total": 1,
+        "next_cursor": null,
+    });
+    assert_eq!(serde_json::to_value(&page).unwrap(), expected);
+    assert_eq!(serde_json::from_value::<RoomsPage>(expected).unwrap(), page);
+}
+
+#[test]
+fn rooms_without_answers_have_null_timestamps() {
+    let value = serde_json::to_value(room(1)).unwrap();
+    assert_eq!(value["last_response"], serde_json::Value::Null);
+    assert_eq!(value["last_activity"], serde_json::Value::Null);
+    assert_eq!(value["queue_depth"], 0);
+}
+
+#[test]
+fn cursor_walks_every_room_once() {
+    let rooms: Vec<RoomStatus> = (0..250).rev().map(room).collect();
+    let mut query = PageQuery {
+        limit: Some(100),
+        cursor: None,
+    };
+    let mut seen = Vec::new();
+    loop {
+        let page = paginate(rooms.clone(), &query);
+        assert_eq!(page.total, 250);
+        seen.extend(page.rooms.into_iter().map(|room| room.room_id));
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    let expected: Vec<String> = (0..250).map(|n| room(n).room_id).collect();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn page_size_is_capped() {
+    let rooms: Vec<RoomStatus> = (0..MAX_PAGE_SIZE + 10).map(room).collect();
+    let page = paginate(
+        rooms,
+        &PageQuery {
+            limit: Some(10_000),
+            cursor: None,
+        },
+    );
+    assert_eq!(page.rooms.len(), MAX_PAGE_SIZE);
+    assert!(page.next_cursor.is_some());
+}
@@ -0,0 +1,96 @@
+//! `!roominfo`: the room's settings, the agent its questions go to and its
+//! kill switch
+
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use verji_vagent_bot::archive::{ArchivePolicy, ArchiveWatch};
+use verji_vagent_bot::kill_switch::{KillSwitch, DISABLED_EVENT_TYPE};
+use verji_vagent_bot::responders::RoomInfoResponder;
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+
+fn harness() -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!info:localhost").expect("room"))
+        .configure(|config| {
+            config.locale = "en".to_string();
+            config.agents = BTreeMap::from([
+                ("finance".to_string(), "Invoices and payments".to_string()),
+                ("support".to_string(), "Customer support".to_string()),
+            ]);
+        })
+}
+
+async fn roominfo(harness: &ResponderTestHarness) -> String {
+    let responder = RoomInfoResponder::new(
+        Arc::new(ArchiveWatch::new(ArchivePolicy::from_env())),
+        Arc::new(KillSwitch::new()),
+    );
+    let messages = harness
+        .respond(Arc::new(responder), "!roominfo")
+        .await
+        .expect("respond");
+    message_text(&messages[0]).expect("text").to_string()
+}
+
+#[tokio::test]
+async fn roominfo_shows_the_rooms_agent() {
+    let harness = harness();
+    let text = roominfo(&harness).await;
+    assert!(text.contains("- Room: `!info:localhost`"), "{}", text);
+    assert!(text.contains("- Agent: the default agent"), "{}", text);
+
+    harness
+        .room_configs()
+        .update(harness.mock_room(), |config| {
+            config.agent = Some("finance".to_string())
+        })
+        .await
+        .expect("update");
+    let text = roominfo(&harness).await;
+    assert!(text.contains("- Agent: `finance`"), "{}", text);
+
+    // A selection no longer configured falls back, as queries do
+    harness
+        .room_configs()
+        .update(harness.mock_room(), |config| {
+            config.agent = Some("retired".to_string())
+        })
+        .await
+        .expect("update");
+    let text = roominfo(&harness).await;
+    assert!(text.contains("- Agent: the default agent"), "{}", text);
+}
+
+#[tokio::test]
+async fn the_default_agent_is_named_when_there_is_one() {
+    let harness = harness().configure(|config| config.default_agent = Some("support".to_string()));
+    let text = roominfo(&harness).await;
+    assert!(text.contains("- Agent: `support`"), "{}", text);
+}
+
+#[tokio::test]
+async fn roominfo_shows_the_kill_switch_and_its_reason() {
+    let text = roominfo(&harness()).await;
+    assert!(text.contains("- Bot: enabled"), "{}", text);
+
+    let cases = [
+        (
+            json!({"disabled": true, "reason": "Exam week"}),
+            "- Bot: 🔇 switched off by the room's moderators: Exam week",
+        ),
+        (
+            json!({"disabled": true, "reason": "  "}),
+            "- Bot: 🔇 switched off by the room's moderators\n",
+        ),
+        (json!({"disabled": false}), "- Bot: enabled"),
+    ];
+    for (content, line) in cases {
+        let room = MockRoom::new("!info:localhost")
+            .expect("room")
+            .with_state_event(DISABLED_EVENT_TYPE, "", content.clone());
+        let text = roominfo(&harness().room(room)).await;
+        assert!(text.contains(line), "{}: {}", content, text);
+    }
+}
@@ -0,0 +1,162 @@
+//! Routing traces of messages every responder passed on
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use verji_vagent_bot::responder::{Responder, ResponderContext, ResponderResult, Route};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::PingPongResponder;
+use verji_vagent_bot::routing::{self, Step, Verdict};
+use verji_vagent_bot::testing::ResponderTestHarness;
+
+/// Declines with a reason, like a responder only answering mentions
+struct MentionOnly;
+
+#[async_trait]
+impl Responder for MentionOnly {
+    fn name(&self) -> &str {
+        "MentionOnly"
+    }
+
+    fn priority(&self) -> i32 {
+        50
+    }
+
+    async fn should_handle(&self, context: &ResponderContext) -> bool {
+        context.is_direct_mention
+    }
+
+    async fn route(&self, context: &ResponderContext) -> Route {
+        if context.is_direct_mention {
+            Route::Accept
+        } else {
+            Route::decline("mention_only")
+        }
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::Handled(Some("You called?".to_string())))
+    }
+}
+
+/// Only implements `should_handle`, as responders written before `route` do
+struct Legacy;
+
+#[async_trait]
+impl Responder for Legacy {
+    fn name(&self) -> &str {
+        "Legacy"
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        false
+    }
+
+    async fn handle(&self, _context: &ResponderContext) -> Result<ResponderResult> {
+        Ok(ResponderResult::NotHandled)
+    }
+}
+
+fn manager() -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(PingPongResponder::new()));
+    manager.register(Arc::new(MentionOnly));
+    manager.register(Arc::new(Legacy));
+    manager
+}
+
+fn step(responder: &str, verdict: Verdict) -> Step {
+    Step {
+        responder: responder.to_string(),
+        verdict,
+    }
+}
+
+#[tokio::test]
+async fn a_message_declined_by_three_responders_is_traced() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let manager = manager();
+
+    let messages = harness
+        .dispatch(&manager, "What's the weather like?")
+        .await
+        .expect("dispatch");
+    assert!(messages.is_empty());
+
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(traces.len(), 1);
+    let trace = &traces[0];
+    assert_eq!(trace.sender, "@user:localhost");
+    assert_eq!(trace.dropped_by, None);
+    assert!(!trace.answered);
+    assert_eq!(
+        trace.steps,
+        vec![
+            step(
+                "PingPongResponder",
+                Verdict::Declined(Some("not_a_command"))
+            ),
+            step("MentionOnly", Verdict::Declined(Some("mention_only"))),
+            step("Legacy", Verdict::Declined(None)),
+        ]
+    );
+    assert_eq!(
+        trace.summary(),
+        "PingPongResponder: declined (not_a_command), MentionOnly: declined (mention_only), \
+         Legacy: declined"
+    );
+
+    let rendered = routing::render(&traces);
+    assert!(rendered.contains("1 unanswered message(s)"), "{}", rendered);
+    assert!(rendered.contains(&trace.event_id), "{}", rendered);
+    assert!(
+        rendered.contains("`MentionOnly`: declined (mention_only)"),
+        "{}",
+        rendered
+    );
+}
+
+#[tokio::test]
+async fn answered_messages_are_not_listed() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let manager = manager();
+
+    harness.dispatch(&manager, "!ping").await.expect("dispatch");
+    harness
+        .dispatch(&manager, "Anyone around?")
+        .await
+        .expect("dispatch");
+    let answered = harness
+        .mention(true)
+        .dispatch(&manager, "Hello bot")
+        .await
+        .expect("dispatch");
+    assert_eq!(answered.len(), 1);
+
+    let traces = manager.traces().unanswered("!test:localhost", 10);
+    assert_eq!(traces.len(), 1);
+    assert_eq!(traces[0].steps.len(), 3);
+    assert!(manager
+        .traces()
+        .unanswered("!other:localhost", 10)
+        .is_empty());
+}
+
+#[tokio::test]
+async fn only_the_configured_number_of_traces_is_kept() {
+    let harness = ResponderTestHarness::new().expect("harness");
+    let mut manager = manager();
+    manager.set_trace_max(2);
+    for body in ["one", "two", "three"] {
+        harness.dispatch(&manager, body).await.expect("dispatch");
+    }
+    assert_eq!(manager.traces().unanswered("!test:localhost", 10).len(), 2);
+
+    // 0 turns tracing off
+    manager.set_trace_max(0);
+    harness.dispatch(&manager, "four").await.expect("dispatch");
+    assert!(manager
+        .traces()
+        .unanswered("!test:localhost", 10)
+        .is_empty());
+}
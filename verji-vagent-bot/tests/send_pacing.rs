@@ -0,0 +1,113 @@
+//! The send pace and rate-limit retries, on a paused clock
+
+use matrix_sdk::ruma::api::client::error::{ErrorBody, ErrorKind, RetryAfter};
+use matrix_sdk::ruma::api::client::Error as ClientApiError;
+use matrix_sdk::ruma::api::error::FromHttpResponseError;
+use matrix_sdk::ruma::exports::http::StatusCode;
+use matrix_sdk::{HttpError, RumaApiError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use verji_vagent_bot::send_pacing::SendPacer;
+
+/// Four sends per second
+const INTERVAL: Duration = Duration::from_millis(250);
+const MAX_WAIT: Duration = Duration::from_secs(10);
+
+fn rate_limited(retry_after: Duration) -> matrix_sdk::Error {
+    let error = ClientApiError::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        ErrorBody::Standard {
+            kind: ErrorKind::LimitExceeded {
+                retry_after: Some(RetryAfter::Delay(retry_after)),
+            },
+            message: "Too many requests".to_string(),
+        },
+    );
+    HttpError::from(FromHttpResponseError::Server(RumaApiError::ClientApi(
+        error,
+    )))
+    .into()
+}
+
+/// Send through the pacer, failing with `failures` in order before succeeding;
+/// returns the result and when each attempt was made
+async fn send(
+    pacer: &SendPacer,
+    failures: Vec<Duration>,
+) -> (Result<(), matrix_sdk::Error>, Vec<Instant>) {
+    let attempts = Mutex::new(Vec::new());
+    let failures = Mutex::new(failures.into_iter());
+    let result = pacer
+        .send("message", || {
+            attempts.lock().unwrap().push(Instant::now());
+            let failure = failures.lock().unwrap().next();
+            async move {
+                match failure {
+                    Some(retry_after) => Err(rate_limited(retry_after)),
+                    None => Ok(()),
+                }
+            }
+        })
+        .await;
+    (result, attempts.into_inner().unwrap())
+}
+
+fn gaps(attempts: &[Instant]) -> Vec<Duration> {
+    attempts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+/// Whether `gap` is `wait` plus at most the 10% jitter (and the clock's
+/// millisecond rounding)
+fn waited(gap: Duration, wait: Duration) -> bool {
+    gap >= wait && gap <= wait + wait / 10 + Duration::from_millis(1)
+}
+
+#[tokio::test(start_paused = true)]
+async fn sends_are_spaced_and_rate_limits_are_waited_out() {
+    let pacer = SendPacer::new(4, 3, MAX_WAIT, Arc::default());
+
+    // Back-to-back sends from several tasks go out one interval apart
+    let started = tokio::join!(
+        send(&pacer, vec![]),
+        send(&pacer, vec![]),
+        send(&pacer, vec![]),
+        send(&pacer, vec![])
+    );
+    let mut sent: Vec<Instant> = [started.0, started.1, started.2, started.3]
+        .into_iter()
+        .map(|(result, attempts)| {
+            assert!(result.is_ok());
+            attempts[0]
+        })
+        .collect();
+    sent.sort();
+    for gap in gaps(&sent) {
+        assert!(
+            gap >= INTERVAL && gap <= INTERVAL + Duration::from_millis(1),
+            "{:?}",
+            gap
+        );
+    }
+
+    // A 429 is retried after the server's retry_after_ms
+    let (result, attempts) = send(&pacer, vec![Duration::from_secs(2)]).await;
+    assert!(result.is_ok());
+    assert_eq!(attempts.len(), 2);
+    let gap = gaps(&attempts)[0];
+    assert!(waited(gap, Duration::from_secs(2)), "{:?}", gap);
+
+    // ... but never waits longer than the maximum wait
+    let (result, attempts) = send(&pacer, vec![Duration::from_secs(60)]).await;
+    assert!(result.is_ok());
+    let gap = gaps(&attempts)[0];
+    assert!(waited(gap, MAX_WAIT), "{:?}", gap);
+
+    // A send still rate-limited after the retries fails
+    let (result, attempts) = send(&pacer, vec![Duration::from_millis(500); 10]).await;
+    assert!(result.is_err());
+    assert_eq!(attempts.len(), 4);
+    for gap in gaps(&attempts) {
+        assert!(waited(gap, Duration::from_millis(500)), "{:?}", gap);
+    }
+}
@@ -0,0 +1,129 @@
+//! Bridged senders mapped to one identity: rule order, overlapping patterns,
+//! passthrough, the `SENDER_IDENTITY_RULES` syntax and `!whoami`
+
+use std::sync::Arc;
+use verji_vagent_bot::identity::IdentityRules;
+use verji_vagent_bot::responders::WhoamiResponder;
+use verji_vagent_bot::testing::{message_text, ResponderTestHarness};
+
+const BRIDGES: &str = r"^@whatsapp_(\d+):example\.org$=phone:+${1};
+    ^@signal_(?P<number>\d+):example\.org$=phone:+${number}";
+
+#[test]
+fn bridged_puppets_of_one_person_share_an_identity() {
+    let rules = IdentityRules::parse(BRIDGES);
+    let cases = [
+        ("@whatsapp_4791234567:example.org", "phone:+4791234567"),
+        ("@signal_4791234567:example.org", "phone:+4791234567"),
+        ("@whatsapp_4790000000:example.org", "phone:+4790000000"),
+    ];
+    for (user_id, identity) in cases {
+        assert_eq!(rules.canonical(user_id), identity, "{}", user_id);
+    }
+}
+
+#[test]
+fn senders_no_rule_matches_keep_their_mxid() {
+    let rules = IdentityRules::parse(BRIDGES);
+    let cases = [
+        "@alice:example.org",
+        // Near misses: another server, a non-numeric puppet, a prefix only
+        "@whatsapp_4791234567:evil.org",
+        "@whatsapp_bot:example.org",
+        "@xwhatsapp_123:example.org",
+    ];
+    for user_id in cases {
+        assert_eq!(rules.canonical(user_id), user_id);
+    }
+
+    let none = IdentityRules::default();
+    assert!(none.is_empty());
+    assert_eq!(none.canonical("@alice:example.org"), "@alice:example.org");
+}
+
+#[test]
+fn the_first_of_overlapping_rules_wins() {
+    let specific = r"^@whatsapp_(\d+):.*$";
+    let general = r"^@[a-z]+_(\d+):.*$";
+    let user_id = "@whatsapp_4791234567:example.org";
+
+    let rules = IdentityRules::new([(specific, "phone:+${1}"), (general, "bridged:${1}")]);
+    assert_eq!(rules.canonical(user_id), "phone:+4791234567");
+    // The general rule still covers what the specific one doesn't
+    assert_eq!(
+        rules.canonical("@telegram_4791234567:example.org"),
+        "bridged:4791234567"
+    );
+
+    let rules = IdentityRules::new([(general, "bridged:${1}"), (specific, "phone:+${1}")]);
+    assert_eq!(rules.canonical(user_id), "bridged:4791234567");
+}
+
+#[test]
+fn a_rule_expanding_to_nothing_falls_through() {
+    let rules = IdentityRules::new([
+        // No group 2, so the identity comes out empty
+        (r"^@whatsapp_(\d+):", "${2}"),
+        (r"^@whatsapp_(\d+):", "phone:+${1}"),
+    ]);
+    assert_eq!(
+        rules.canonical("@whatsapp_4791234567:example.org"),
+        "phone:+4791234567"
+    );
+
+    let rules = IdentityRules::new([(r"^@whatsapp_", "")]);
+    assert_eq!(
+        rules.canonical("@whatsapp_4791234567:example.org"),
+        "@whatsapp_4791234567:example.org"
+    );
+}
+
+#[test]
+fn the_rule_list_syntax() {
+    // Entries split at their last `=`, blanks and entries without one skipped
+    let rules = IdentityRules::parse(
+        r" ^@wa_(\d+):x$ = phone:+${1} ;; no-equals-sign ; =orphan ; ^@(?P<a>[a-z]+)=x:x$=${a}",
+    );
+    assert!(!rules.is_empty());
+    assert_eq!(rules.canonical("@wa_123:x"), "phone:+123");
+    assert_eq!(rules.canonical("@bob=x:x"), "bob");
+    assert_eq!(rules.canonical("no-equals-sign"), "no-equals-sign");
+
+    // An invalid pattern is skipped, the rest still apply
+    let rules = IdentityRules::parse(r"^@(unclosed=broken; ^@wa_(\d+):x$=phone:+${1}");
+    assert_eq!(rules.canonical("@wa_123:x"), "phone:+123");
+    assert_eq!(rules.canonical("@(unclosed"), "@(unclosed");
+
+    assert!(IdentityRules::parse("").is_empty());
+    assert!(IdentityRules::parse(" ; ;").is_empty());
+}
+
+#[tokio::test]
+async fn whoami_shows_the_mxid_and_the_identity() {
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .sender("@whatsapp_4791234567:example.org")
+        .configure(|config| config.identities = IdentityRules::parse(BRIDGES));
+    let sent = harness
+        .respond(Arc::new(WhoamiResponder::new()), "!whoami")
+        .await
+        .expect("respond");
+    let text = message_text(&sent[0]).expect("text");
+    assert!(
+        text.contains("`@whatsapp_4791234567:example.org`"),
+        "{}",
+        text
+    );
+    assert!(text.contains("`phone:+4791234567`"), "{}", text);
+    assert!(text.contains("kept under this identity"), "{}", text);
+
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .sender("@alice:example.org");
+    let sent = harness
+        .respond(Arc::new(WhoamiResponder::new()), "!whoami")
+        .await
+        .expect("respond");
+    let text = message_text(&sent[0]).expect("text");
+    assert!(text.contains("the same as your Matrix ID"), "{}", text);
+}
@@ -0,0 +1,187 @@
+//! The sent event registry: eviction by count and age, and final answers
+//! surviving a restart in the bot database
+
+use matrix_sdk::ruma::{EventId, OwnedEventId};
+use std::time::Duration;
+use verji_vagent_bot::db::{self, TempStore};
+use verji_vagent_bot::sent_events::{SentEventRegistry, SentKind};
+
+const ROOM: &str = "!test:localhost";
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn event(name: &str) -> OwnedEventId {
+    EventId::parse(format!("${}:localhost", name)).expect("event ID")
+}
+
+#[tokio::test]
+async fn the_oldest_events_go_beyond_the_count_limit() {
+    let registry = SentEventRegistry::in_memory(3, HOUR);
+    for n in 1..=5 {
+        registry
+            .record(event(&format!("e{}", n)), ROOM, SentKind::Final, None)
+            .await;
+    }
+
+    for n in 1..=2 {
+        assert!(registry.lookup(&event(&format!("e{}", n))).await.is_none());
+    }
+    for n in 3..=5 {
+        let sent = registry
+            .lookup(&event(&format!("e{}", n)))
+            .await
+            .expect("kept");
+        assert_eq!((sent.room_id.as_str(), sent.kind), (ROOM, SentKind::Final));
+    }
+}
+
+#[tokio::test]
+async fn recording_an_event_again_keeps_one_entry() {
+    let registry = SentEventRegistry::in_memory(2, HOUR);
+    registry
+        .record(event("a"), ROOM, SentKind::Progress, Some("req-1"))
+        .await;
+    registry
+        .record(event("a"), ROOM, SentKind::Final, Some("req-1"))
+        .await;
+    registry.record(event("b"), ROOM, SentKind::Ack, None).await;
+
+    // Both fit: the second record of "a" replaced the first
+    let a = registry.lookup(&event("a")).await.expect("a");
+    assert_eq!(a.kind, SentKind::Final);
+    assert!(registry.lookup(&event("b")).await.is_some());
+}
+
+#[tokio::test]
+async fn only_final_answers_are_answered_requests() {
+    let registry = SentEventRegistry::in_memory(10, HOUR);
+    registry
+        .record(event("progress"), ROOM, SentKind::Progress, Some("req-1"))
+        .await;
+    registry
+        .record(event("error"), ROOM, SentKind::Error, Some("req-2"))
+        .await;
+    assert!(!registry.answered("req-1").await);
+    assert!(!registry.answered("req-2").await);
+
+    registry
+        .record(event("final"), ROOM, SentKind::Final, Some("req-1"))
+        .await;
+    assert!(registry.answered("req-1").await);
+}
+
+#[tokio::test]
+async fn requests_are_linked_until_taken() {
+    let registry = SentEventRegistry::in_memory(10, HOUR);
+    registry.link_request(&event("question"), "req-1");
+    registry.link_failure(&event("other"), "req-2");
+
+    assert_eq!(
+        registry.take_request(&event("question")),
+        Some(("req-1".to_string(), SentKind::Final))
+    );
+    assert_eq!(registry.take_request(&event("question")), None);
+    assert_eq!(
+        registry.take_request(&event("other")),
+        Some(("req-2".to_string(), SentKind::Error))
+    );
+}
+
+#[tokio::test]
+async fn final_answers_survive_a_restart() {
+    let store = TempStore::new("sent-events-restart").expect("store");
+    {
+        let registry = SentEventRegistry::open(store.path(), 10, HOUR).expect("open");
+        registry
+            .record(event("answer"), ROOM, SentKind::Final, Some("req-1"))
+            .await;
+        registry
+            .record(event("progress"), ROOM, SentKind::Progress, Some("req-1"))
+            .await;
+        registry
+            .record(event("help"), ROOM, SentKind::Final, None)
+            .await;
+        registry
+            .record(event("ack"), ROOM, SentKind::Ack, None)
+            .await;
+    }
+
+    let registry = SentEventRegistry::open(store.path(), 10, HOUR).expect("reopen");
+    let answer = registry.lookup(&event("answer")).await.expect("answer");
+    assert_eq!(answer.request_id.as_deref(), Some("req-1"));
+    assert_eq!(
+        (answer.room_id.as_str(), answer.kind),
+        (ROOM, SentKind::Final)
+    );
+    assert!(registry.answered("req-1").await);
+
+    // Progress, acks and output without a request live in memory only
+    for name in ["progress", "help", "ack"] {
+        assert!(registry.lookup(&event(name)).await.is_none(), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn answers_evicted_from_memory_are_found_in_the_database() {
+    let store = TempStore::new("sent-events-evicted").expect("store");
+    let registry = SentEventRegistry::open(store.path(), 1, HOUR).expect("open");
+    registry
+        .record(event("first"), ROOM, SentKind::Final, Some("req-1"))
+        .await;
+    registry
+        .record(event("second"), ROOM, SentKind::Final, Some("req-2"))
+        .await;
+
+    let first = registry.lookup(&event("first")).await.expect("first");
+    assert_eq!(first.request_id.as_deref(), Some("req-1"));
+    assert!(registry.answered("req-1").await);
+}
+
+#[tokio::test]
+async fn answers_past_the_age_limit_are_gone() {
+    let store = TempStore::new("sent-events-expired").expect("store");
+    drop(SentEventRegistry::open(store.path(), 10, HOUR).expect("create"));
+
+    // An answer sent two hours ago, before the last restart
+    let conn = db::open(&db::bot_db_path(store.path())).expect("db");
+    conn.execute(
+        "INSERT INTO sent_events (event_id, request_id, room_id, sent_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            "$old:localhost",
+            "req-old",
+            ROOM,
+            (db::now_secs() - 2 * 3600) as i64
+        ],
+    )
+    .expect("insert");
+
+    let registry = SentEventRegistry::open(store.path(), 10, 3 * HOUR).expect("open");
+    assert!(registry.lookup(&event("old")).await.is_some());
+    drop(registry);
+
+    // Reopened with a shorter retention, it is expired and pruned
+    let registry = SentEventRegistry::open(store.path(), 10, HOUR).expect("open");
+    assert!(registry.lookup(&event("old")).await.is_none());
+    assert!(!registry.answered("req-old").await);
+    let rows: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sent_events", [], |row| row.get(0))
+        .expect("count");
+    assert_eq!(rows, 0);
+}
+
+#[tokio::test]
+async fn forgotten_events_are_gone_after_a_restart() {
+    let store = TempStore::new("sent-events-forget").expect("store");
+    let registry = SentEventRegistry::open(store.path(), 10, HOUR).expect("open");
+    registry
+        .record(event("answer"), ROOM, SentKind::Final, Some("req-1"))
+        .await;
+
+    let forgotten = registry.forget(&event("answer")).await.expect("forgotten");
+    assert_eq!(forgotten.request_id.as_deref(), Some("req-1"));
+    assert!(registry.lookup(&event("answer")).await.is_none());
+    drop(registry);
+
+    let registry = SentEventRegistry::open(store.path(), 10, HOUR).expect("reopen");
+    assert!(registry.lookup(&event("answer")).await.is_none());
+    assert!(registry.forget(&event("answer")).await.is_none());
+}
@@ -0,0 +1,125 @@
+//! Store checks against simulated read-only and full stores, and which errors
+//! count as store write failures
+
+use anyhow::anyhow;
+use std::io;
+use verji_vagent_bot::db::TempStore;
+use verji_vagent_bot::store_health::{check, check_writable, is_store_error, StoreHealth};
+
+#[test]
+fn a_writable_store_passes_and_leaves_nothing_behind() {
+    let store = TempStore::new("store-health-ok").expect("store");
+    let status = check(store.path(), 0).expect("check");
+    if cfg!(unix) {
+        assert!(status.free_bytes.is_some_and(|free| free > 0));
+    }
+    let left: Vec<_> = std::fs::read_dir(store.path()).expect("read dir").collect();
+    assert!(left.is_empty(), "{:?}", left);
+}
+
+#[test]
+fn too_little_free_space_fails() {
+    if !cfg!(unix) {
+        return;
+    }
+    let store = TempStore::new("store-health-full").expect("store");
+    let error = check(store.path(), u64::MAX).expect_err("not enough space");
+    let message = format!("{:#}", error);
+    assert!(message.starts_with("Only "), "{}", message);
+    assert!(message.contains("minimum"), "{}", message);
+}
+
+#[test]
+fn a_store_where_the_probe_cant_be_written_fails() {
+    // A directory in the probe's place can't be opened for writing, not even
+    // by root
+    let store = TempStore::new("store-health-blocked").expect("store");
+    std::fs::create_dir(store.path().join(".vagent-write-check")).expect("block probe");
+    let error = check_writable(store.path()).expect_err("not writable");
+    assert!(
+        format!("{:#}", error).contains("is not writable"),
+        "{:#}",
+        error
+    );
+    assert!(check(store.path(), 0).is_err());
+}
+
+#[test]
+fn a_missing_store_directory_fails() {
+    let store = TempStore::new("store-health-missing").expect("store");
+    let error = check(&store.path().join("gone"), 0).expect_err("missing");
+    assert!(
+        format!("{:#}", error).contains("is not writable"),
+        "{:#}",
+        error
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn a_read_only_store_directory_fails() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let store = TempStore::new("store-health-readonly").expect("store");
+    let read_only = std::fs::Permissions::from_mode(0o555);
+    std::fs::set_permissions(store.path(), read_only).expect("chmod");
+
+    let result = check(store.path(), 0);
+    // Permissions don't stop root; the blocked probe above covers that case
+    let root = unsafe { libc::geteuid() } == 0;
+    std::fs::set_permissions(store.path(), std::fs::Permissions::from_mode(0o755)).expect("chmod");
+    if !root {
+        let error = result.expect_err("read-only");
+        assert!(
+            format!("{:#}", error).contains("is not writable"),
+            "{:#}",
+            error
+        );
+    }
+}
+
+#[test]
+fn full_and_read_only_stores_are_recognized_in_errors() {
+    let sqlite = |code| {
+        anyhow::Error::new(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(code),
+            None,
+        ))
+    };
+    let store_errors = [
+        // ENOSPC and EROFS, under context
+        anyhow::Error::new(io::Error::from_raw_os_error(28)).context("Failed to save the session"),
+        anyhow::Error::new(io::Error::from_raw_os_error(30)),
+        sqlite(rusqlite::ffi::SQLITE_FULL),
+        sqlite(rusqlite::ffi::SQLITE_READONLY),
+        sqlite(rusqlite::ffi::SQLITE_IOERR),
+        // SDK errors that only carry the text
+        anyhow!("the crypto store failed: database or disk is full"),
+        anyhow!("Error: Read-only file system (os error 30)"),
+        anyhow!("attempt to write a readonly database").context("Sync failed"),
+    ];
+    for error in &store_errors {
+        assert!(is_store_error(error), "{:#}", error);
+    }
+
+    let other_errors = [
+        anyhow::Error::new(io::Error::from_raw_os_error(2)),
+        anyhow::Error::new(io::Error::new(io::ErrorKind::PermissionDenied, "denied")),
+        sqlite(rusqlite::ffi::SQLITE_BUSY),
+        anyhow!("Connection refused"),
+        anyhow!("M_LIMIT_EXCEEDED: Too many requests"),
+    ];
+    for error in &other_errors {
+        assert!(!is_store_error(error), "{:#}", error);
+    }
+}
+
+#[test]
+fn only_store_errors_are_reported() {
+    // Without the monitor running, reports are logged and the store counts
+    // as healthy
+    let health = StoreHealth::default();
+    assert!(health.report_error("send", &anyhow!("No space left on device")));
+    assert!(!health.report_error("send", &anyhow!("Connection refused")));
+    assert!(health.is_healthy());
+}
@@ -0,0 +1,68 @@
+//! System room detection against synthetic room state
+
+use serde_json::{json, Value};
+use verji_vagent_bot::alias::Aliases;
+use verji_vagent_bot::system_rooms::{self, SystemRoom, SystemRoomKind, SystemRoomPolicy};
+
+const ROOM: &str = "!room:example.org";
+
+/// Detection before any alias is resolved
+fn detect(
+    room_id: &str,
+    tags: Option<&Value>,
+    creator: Option<&str>,
+    policy: &SystemRoomPolicy,
+) -> Option<SystemRoom> {
+    system_rooms::detect(room_id, tags, creator, policy, &Aliases::default())
+}
+
+#[test]
+fn tagged_rooms_are_server_notices() {
+    let tags = json!({ "tags": { "m.server_notice": { "order": 0.5 } } });
+    let detected = detect(
+        ROOM,
+        Some(&tags),
+        Some("@someone:example.org"),
+        &SystemRoomPolicy::default(),
+    )
+    .expect("tagged room is a system room");
+    assert_eq!(detected.kind, SystemRoomKind::ServerNotices);
+}
+
+#[test]
+fn untagged_rooms_are_detected_by_creator() {
+    let policy = SystemRoomPolicy {
+        system_creators: vec!["@monitoring:example.org".to_string()],
+        ..SystemRoomPolicy::default()
+    };
+    let tags = json!({ "tags": { "m.favourite": {} } });
+
+    let notices = detect(ROOM, Some(&tags), Some("@notices:example.org"), &policy);
+    assert_eq!(
+        notices.map(|room| room.kind),
+        Some(SystemRoomKind::ServerNotices)
+    );
+    let system = detect(ROOM, None, Some("@monitoring:example.org"), &policy);
+    assert_eq!(system.map(|room| room.kind), Some(SystemRoomKind::System));
+
+    // Ordinary rooms, with or without tags, and rooms with an unknown creator
+    assert_eq!(
+        detect(ROOM, Some(&tags), Some("@alice:example.org"), &policy),
+        None
+    );
+    assert_eq!(
+        detect(ROOM, None, Some("@monitoring:other.org"), &policy),
+        None
+    );
+    assert_eq!(detect(ROOM, None, None, &policy), None);
+}
+
+#[test]
+fn listed_rooms_are_system_rooms() {
+    let policy = SystemRoomPolicy {
+        system_rooms: vec![ROOM.to_string()],
+        ..SystemRoomPolicy::default()
+    };
+    let detected = detect(ROOM, None, Some("@alice:example.org"), &policy);
+    assert_eq!(detected.map(|room| room.kind), Some(SystemRoomKind::System));
+}
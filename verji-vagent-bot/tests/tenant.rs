@@ -0,0 +1,200 @@
+//! Room tenants read from synthetic state events: present, missing and
+//! malformed, and what the agent does without one
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::responders::VerjiAgentResponder;
+use verji_vagent_bot::tenant::{Tenant, TenantResolver};
+use verji_vagent_bot::testing::{message_text, MockRoom, ResponderTestHarness};
+use verji_vagent_bot::transport::mock::MockScript;
+use verji_vagent_bot::transport::TransportConfig;
+
+const EVENT_TYPE: &str = "no.verji.tenant";
+
+fn resolver(path: &str, required: bool) -> TenantResolver {
+    let mut resolver = TenantResolver::from_env();
+    resolver.event_type = EVENT_TYPE.to_string();
+    resolver.state_key = String::new();
+    resolver.path = path.to_string();
+    resolver.required = required;
+    resolver
+}
+
+fn room(content: Option<Value>) -> MockRoom {
+    let room = MockRoom::new("!tenant:localhost").expect("room");
+    match content {
+        Some(content) => room.with_state_event(EVENT_TYPE, "", content),
+        None => room,
+    }
+}
+
+#[tokio::test]
+async fn a_tenant_event_with_an_id_is_found() {
+    let cases = [
+        ("tenant_id", json!({"tenant_id": "acme"}), "acme"),
+        ("tenant_id", json!({"tenant_id": "  acme \n"}), "acme"),
+        ("tenant_id", json!({"tenant_id": 4711}), "4711"),
+        (
+            "org.id",
+            json!({"org": {"id": "acme", "name": "ACME"}}),
+            "acme",
+        ),
+    ];
+    for (path, content, id) in cases {
+        let tenant = resolver(path, false)
+            .resolve(&room(Some(content.clone())))
+            .await;
+        assert_eq!(tenant, Tenant::Found(id.to_string()), "{}", content);
+        assert_eq!(tenant.id(), Some(id));
+    }
+}
+
+#[tokio::test]
+async fn a_room_without_the_event_has_no_tenant() {
+    let tenant = resolver("tenant_id", false).resolve(&room(None)).await;
+    assert_eq!(tenant, Tenant::Missing);
+    assert_eq!(tenant.id(), None);
+    assert_eq!(tenant.to_string(), "none (no state event)");
+
+    // An event under another state key doesn't count
+    let other_key = MockRoom::new("!tenant:localhost")
+        .expect("room")
+        .with_state_event(EVENT_TYPE, "other", json!({"tenant_id": "acme"}));
+    assert_eq!(
+        resolver("tenant_id", false).resolve(&other_key).await,
+        Tenant::Missing
+    );
+}
+
+#[tokio::test]
+async fn a_malformed_event_has_no_tenant() {
+    let cases = [
+        (json!({}), "no `tenant_id` in the content"),
+        (json!({"tenant": "acme"}), "no `tenant_id` in the content"),
+        (
+            json!({"tenant_id": ""}),
+            "`tenant_id` is not a non-empty string",
+        ),
+        (
+            json!({"tenant_id": "   "}),
+            "`tenant_id` is not a non-empty string",
+        ),
+        (
+            json!({"tenant_id": null}),
+            "`tenant_id` is not a non-empty string",
+        ),
+        (
+            json!({"tenant_id": ["acme"]}),
+            "`tenant_id` is not a non-empty string",
+        ),
+        (
+            json!({"tenant_id": {"id": "acme"}}),
+            "`tenant_id` is not a non-empty string",
+        ),
+        (json!("acme"), "no `tenant_id` in the content"),
+    ];
+    for (content, reason) in cases {
+        let tenant = resolver("tenant_id", false)
+            .resolve(&room(Some(content.clone())))
+            .await;
+        assert_eq!(tenant, Tenant::Malformed(reason.to_string()), "{}", content);
+        assert_eq!(tenant.id(), None);
+    }
+
+    // A path running into a non-object
+    let tenant = resolver("org.id", false)
+        .resolve(&room(Some(json!({"org": "acme"}))))
+        .await;
+    assert_eq!(
+        tenant,
+        Tenant::Malformed("no `org.id` in the content".to_string())
+    );
+}
+
+#[tokio::test]
+async fn resolutions_are_cached_until_the_state_changes() {
+    let resolver = resolver("tenant_id", false);
+    assert!(resolver
+        .resolve(&room(Some(json!({"tenant_id": "acme"}))))
+        .await
+        .id()
+        .is_some());
+    // The same room, now without the event: still cached
+    assert_eq!(
+        resolver.resolve(&room(None)).await,
+        Tenant::Found("acme".to_string())
+    );
+    resolver.invalidate("!tenant:localhost");
+    assert_eq!(resolver.resolve(&room(None)).await, Tenant::Missing);
+}
+
+fn agent(script: &Arc<MockScript>) -> ResponderManager {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(VerjiAgentResponder::with_transport(
+        TransportConfig::Mock(Arc::clone(script)),
+    )));
+    manager
+}
+
+fn harness(content: Option<Value>, required: bool) -> ResponderTestHarness {
+    ResponderTestHarness::new()
+        .expect("harness")
+        .room(room(content))
+        .tenants(resolver("tenant_id", required))
+        .configure(|config| config.locale = "en".to_string())
+}
+
+#[tokio::test]
+async fn without_a_tenant_queries_go_out_untagged() {
+    let cases = [
+        (Some(json!({"tenant_id": "acme"})), Some("acme")),
+        (None, None),
+        (Some(json!({"tenant_id": ""})), None),
+    ];
+    for (content, tenant_id) in cases {
+        let script =
+            Arc::new(MockScript::from_json(r#"{"fallback_delay_ms": 0}"#).expect("script"));
+        let harness = harness(content.clone(), false);
+        let messages = harness
+            .dispatch(&agent(&script), "Who are we?")
+            .await
+            .expect("dispatch");
+        let text = message_text(&messages[0]).expect("text");
+        assert!(text.starts_with("Echo: Who are we?"), "{}", text);
+        let submitted = script.submitted();
+        assert_eq!(
+            submitted[0].metadata.tenant_id.as_deref(),
+            tenant_id,
+            "{:?}",
+            content
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_required_tenant_refuses_rooms_without_one() {
+    let cases = [
+        (Some(json!({"tenant_id": "acme"})), false),
+        (None, true),
+        (Some(json!({"tenant_id": false})), true),
+    ];
+    for (content, refused) in cases {
+        let script =
+            Arc::new(MockScript::from_json(r#"{"fallback_delay_ms": 0}"#).expect("script"));
+        let harness = harness(content.clone(), true);
+        let messages = harness
+            .dispatch(&agent(&script), "Who are we?")
+            .await
+            .expect("dispatch");
+        let text = message_text(&messages[0]).expect("text");
+        assert_eq!(
+            text.contains("This room isn't linked to an organization yet"),
+            refused,
+            "{:?}: {}",
+            content,
+            text
+        );
+        assert_eq!(script.submitted().is_empty(), refused, "{:?}", content);
+    }
+}
@@ -0,0 +1,208 @@
+//! Helpers for tests that run against a real homeserver
+//!
+//! The homeserver comes from `E2E_HOMESERVER` and must allow open
+//! registration (`enable_registration` and
+//! `enable_registration_without_verification` in Synapse). Users get random
+//! names, so runs never collide and nothing has to be torn down on the server.
+
+#![allow(dead_code)]
+
+use anyhow::{Context, Result};
+use matrix_sdk::{
+    config::SyncSettings,
+    deserialized_responses::EncryptionInfo,
+    ruma::{
+        api::client::{
+            account::register::v3::Request as RegistrationRequest,
+            room::create_room::v3::Request as CreateRoomRequest,
+            uiaa,
+        },
+        events::{
+            room::{
+                encryption::RoomEncryptionEventContent,
+                message::{MessageType, OriginalSyncRoomMessageEvent},
+            },
+            InitialStateEvent,
+        },
+        OwnedRoomId, OwnedUserId, UserId,
+    },
+    Client, Room,
+};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use verji_vagent_bot::db::TempStore;
+
+/// Homeserver under test, or `None` when the suite should be skipped
+pub fn homeserver() -> Option<String> {
+    std::env::var("E2E_HOMESERVER")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+}
+
+/// A registered account
+pub struct TestUser {
+    pub username: String,
+    pub password: String,
+}
+
+/// Register a user with a random name
+pub async fn register_user(homeserver: &str, prefix: &str) -> Result<TestUser> {
+    let client = Client::builder()
+        .homeserver_url(homeserver)
+        .build()
+        .await
+        .context("Failed to create registration client")?;
+
+    let username = format!("{}-{}", prefix, uuid::Uuid::new_v4().simple());
+    let password = uuid::Uuid::new_v4().to_string();
+
+    let mut request = RegistrationRequest::new();
+    request.username = Some(username.clone());
+    request.password = Some(password.clone());
+    request.inhibit_login = true;
+    request.auth = Some(uiaa::AuthData::Dummy(uiaa::Dummy::new()));
+    client
+        .matrix_auth()
+        .register(request)
+        .await
+        .with_context(|| format!("Failed to register {} (is open registration enabled?)", username))?;
+
+    Ok(TestUser { username, password })
+}
+
+/// Log a user in with a client of its own, backed by `store`
+pub async fn login(homeserver: &str, user: &TestUser, store: &TempStore) -> Result<Client> {
+    let client = Client::builder()
+        .homeserver_url(homeserver)
+        .sqlite_store(store.path(), None)
+        .build()
+        .await
+        .context("Failed to create client")?;
+    client
+        .matrix_auth()
+        .login_username(&user.username, &user.password)
+        .initial_device_display_name("e2e test user")
+        .await
+        .with_context(|| format!("Failed to log in as {}", user.username))?;
+    Ok(client)
+}
+
+/// Create an encrypted room and invite `invite`
+pub async fn create_encrypted_room(client: &Client, invite: &UserId) -> Result<Room> {
+    let mut request = CreateRoomRequest::new();
+    request.name = Some("vagent e2e".to_string());
+    request.invite = vec![invite.to_owned()];
+    request.initial_state =
+        vec![InitialStateEvent::new(RoomEncryptionEventContent::with_recommended_defaults()).to_raw_any()];
+    client
+        .create_room(request)
+        .await
+        .context("Failed to create room")
+}
+
+/// Accept the invite to `room_id`, syncing until it shows up
+pub async fn join_when_invited(client: &Client, room_id: &OwnedRoomId, timeout: Duration) -> Result<Room> {
+    let poll = async {
+        loop {
+            client.sync_once(SyncSettings::default().timeout(Duration::from_secs(1))).await?;
+            if let Some(room) = client.get_room(room_id) {
+                room.join().await?;
+                return anyhow::Ok(room);
+            }
+        }
+    };
+    tokio::time::timeout(timeout, poll)
+        .await
+        .with_context(|| format!("No invite to {} after {:?}", room_id, timeout))?
+}
+
+/// Poll until `condition` holds, checking every 250 ms
+pub async fn wait_for<F, Fut>(what: &str, timeout: Duration, mut condition: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let poll = async {
+        while !condition().await {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    };
+    tokio::time::timeout(timeout, poll)
+        .await
+        .with_context(|| format!("Timed out after {:?} waiting for {}", timeout, what))
+}
+
+/// A text message as the test user received it
+#[derive(Debug)]
+pub struct ReceivedMessage {
+    pub room_id: OwnedRoomId,
+    pub sender: OwnedUserId,
+    pub body: String,
+    /// `None` when the event arrived unencrypted
+    pub encryption: Option<EncryptionInfo>,
+}
+
+/// Messages seen by a client that keeps syncing in the background
+pub struct MessageFeed {
+    messages: mpsc::UnboundedReceiver<ReceivedMessage>,
+    sync: JoinHandle<()>,
+}
+
+impl MessageFeed {
+    /// Start syncing `client` and collecting the messages it receives
+    pub fn start(client: &Client) -> Self {
+        let (tx, messages) = mpsc::unbounded_channel();
+        client.add_event_handler(
+            move |event: OriginalSyncRoomMessageEvent, room: Room, encryption: Option<EncryptionInfo>| {
+                let tx = tx.clone();
+                async move {
+                    let body = match &event.content.msgtype {
+                        MessageType::Text(text) => text.body.clone(),
+                        MessageType::Notice(notice) => notice.body.clone(),
+                        _ => return,
+                    };
+                    let _ = tx.send(ReceivedMessage {
+                        room_id: room.room_id().to_owned(),
+                        sender: event.sender,
+                        body,
+                        encryption,
+                    });
+                }
+            },
+        );
+
+        let client = client.clone();
+        let sync = tokio::spawn(async move {
+            let _ = client.sync(SyncSettings::default().timeout(Duration::from_secs(1))).await;
+        });
+        Self { messages, sync }
+    }
+
+    /// Next message from `sender` matching `filter`, skipping everything else
+    pub async fn next_from(
+        &mut self,
+        sender: &UserId,
+        timeout: Duration,
+        filter: impl Fn(&ReceivedMessage) -> bool,
+    ) -> Result<ReceivedMessage> {
+        let wait = async {
+            while let Some(message) = self.messages.recv().await {
+                if message.sender == sender && filter(&message) {
+                    return Some(message);
+                }
+            }
+            None
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .with_context(|| format!("No message from {} after {:?}", sender, timeout))?
+            .context("Sync stopped")
+    }
+}
+
+impl Drop for MessageFeed {
+    fn drop(&mut self) {
+        self.sync.abort();
+    }
+}
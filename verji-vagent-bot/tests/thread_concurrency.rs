@@ -0,0 +1,155 @@
+//! Messages are handled in order within a thread and concurrently across
+//! threads, with typing shown while any of them is busy; idle lanes are
+//! removed
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use verji_vagent_bot::responder::{OutgoingMessage, Responder, ResponderContext, ResponderResult};
+use verji_vagent_bot::responder_manager::ResponderManager;
+use verji_vagent_bot::testing::{MockRoom, ResponderTestHarness};
+
+/// Takes a while over every message and records when it starts and ends
+struct Slow {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Responder for Slow {
+    fn name(&self) -> &str {
+        "Slow"
+    }
+
+    async fn should_handle(&self, _context: &ResponderContext) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &ResponderContext) -> Result<ResponderResult> {
+        let body = context.message_body.to_string();
+        self.log.lock().unwrap().push(format!("start {}", body));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        self.log.lock().unwrap().push(format!("end {}", body));
+        Ok(ResponderResult::Handled(Some(format!(
+            "Done with {}",
+            body
+        ))))
+    }
+}
+
+async fn in_thread(harness: &ResponderTestHarness, body: &str, thread: &str) -> ResponderContext {
+    let mut context = harness.context(body).await.expect("context");
+    context.thread_id = Some(thread.to_string());
+    context
+}
+
+async fn dispatch_after(
+    manager: &ResponderManager,
+    context: &ResponderContext,
+    delay_ms: u64,
+) -> Vec<OutgoingMessage> {
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    manager.dispatch(context).await.expect("dispatch")
+}
+
+fn position(log: &[String], entry: &str) -> usize {
+    log.iter()
+        .position(|logged| logged == entry)
+        .unwrap_or_else(|| panic!("{} missing from {:?}", entry, log))
+}
+
+#[tokio::test]
+async fn threads_run_side_by_side_and_each_stays_in_order() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Slow {
+        log: Arc::clone(&log),
+    }));
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!threads:localhost").expect("room"))
+        .configure(|config| config.typing_indicator = true);
+
+    let a1 = in_thread(&harness, "A1", "$thread-a").await;
+    let b1 = in_thread(&harness, "B1", "$thread-b").await;
+    let a2 = in_thread(&harness, "A2", "$thread-a").await;
+
+    let (first, second, third) = tokio::time::timeout(Duration::from_secs(5), async {
+        tokio::join!(
+            dispatch_after(&manager, &a1, 0),
+            dispatch_after(&manager, &b1, 20),
+            dispatch_after(&manager, &a2, 40),
+        )
+    })
+    .await
+    .expect("dispatch hung");
+    assert_eq!(first.len() + second.len() + third.len(), 3);
+
+    let log = log.lock().unwrap().clone();
+    // Thread B didn't wait for thread A
+    assert!(
+        position(&log, "start B1") < position(&log, "end A1"),
+        "{:?}",
+        log
+    );
+    // The follow-up in thread A waited for the first message there
+    assert!(
+        position(&log, "start A2") > position(&log, "end A1"),
+        "{:?}",
+        log
+    );
+    // Typing stayed on until the last message of the room was done
+    assert_eq!(harness.mock_room().typing_changes(), vec![true, false]);
+}
+
+#[tokio::test]
+async fn no_typing_unless_enabled() {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Slow {
+        log: Arc::new(Mutex::new(Vec::new())),
+    }));
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!quiet:localhost").expect("room"));
+
+    let messages = harness.dispatch(&manager, "hello").await.expect("dispatch");
+
+    assert_eq!(messages.len(), 1);
+    assert!(harness.mock_room().typing_changes().is_empty());
+}
+
+#[tokio::test]
+async fn lanes_go_once_their_messages_are_done() {
+    let manager = ResponderManager::new();
+    manager.register(Arc::new(Slow {
+        log: Arc::new(Mutex::new(Vec::new())),
+    }));
+    let harness = ResponderTestHarness::new()
+        .expect("harness")
+        .room(MockRoom::new("!lanes:localhost").expect("room"));
+
+    let a1 = in_thread(&harness, "A1", "$thread-a").await;
+    let a2 = in_thread(&harness, "A2", "$thread-a").await;
+    let b1 = in_thread(&harness, "B1", "$thread-b").await;
+    let main = harness.context("Main").await.expect("context");
+    let lanes = &harness.runtime().processing;
+
+    let (_, _, _, _, busy) = tokio::join!(
+        dispatch_after(&manager, &a1, 0),
+        dispatch_after(&manager, &a2, 0),
+        dispatch_after(&manager, &b1, 0),
+        dispatch_after(&manager, &main, 0),
+        async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            lanes.lane_count("!lanes:localhost")
+        },
+    );
+    // Thread A, thread B and the main timeline
+    assert_eq!(busy, 3);
+    assert_eq!(lanes.lane_count("!lanes:localhost"), 0);
+    assert_eq!(lanes.room_depth("!lanes:localhost"), 0);
+
+    // A new message opens its lane again, and it goes with the message
+    harness.dispatch(&manager, "Again").await.expect("dispatch");
+    assert_eq!(lanes.lane_count("!lanes:localhost"), 0);
+}
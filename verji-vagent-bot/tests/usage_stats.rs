@@ -0,0 +1,47 @@
+//! Usage stats persistence: a flush that fails to write keeps what it drained
+//! for the next one
+
+use std::time::Duration;
+use verji_vagent_bot::db::{self, TempStore};
+use verji_vagent_bot::stats::UsageStats;
+
+const ROOM: &str = "!stats:localhost";
+const USER: &str = "@user:localhost";
+
+fn open(store: &TempStore) -> UsageStats {
+    UsageStats::open(store.path(), Duration::from_secs(3600)).expect("stats")
+}
+
+#[tokio::test]
+async fn a_failed_flush_is_retried_by_the_next() {
+    let store = TempStore::new("stats-flush").expect("store");
+    let stats = open(&store);
+    stats.record_query(ROOM, USER, Duration::from_millis(120), true);
+    stats.record_interaction(ROOM, USER, "What's new?", "answered");
+
+    // Without its table the write fails, rolling back the whole flush
+    let conn = db::open(&db::bot_db_path(store.path())).expect("db");
+    conn.execute_batch("DROP TABLE agent_interactions")
+        .expect("drop");
+    assert!(stats.flush().await.is_err());
+
+    // Reopening recreates the table; nothing was written yet
+    assert!(open(&store).room_users(ROOM).is_empty());
+    stats.record_interaction(ROOM, USER, "And now?", "answered");
+    stats.flush().await.expect("flush");
+
+    let reopened = open(&store);
+    let users = reopened.room_users(ROOM);
+    assert_eq!(users.len(), 1, "{:?}", users);
+    assert_eq!(users[0].1.queries, 1);
+    let history = reopened
+        .history(USER, Some(ROOM), 10)
+        .await
+        .expect("history");
+    let questions: Vec<&str> = history
+        .iter()
+        .map(|entry| entry.question.as_str())
+        .collect();
+    // Newest first, the retried one before the one recorded after it
+    assert_eq!(questions, ["And now?", "What's new?"]);
+}
@@ -35,8 +35,10 @@ class VAgentGraph:
     def __init__(self):
         """Initialize the service."""
         self.redis_url = os.getenv("REDIS_URL", "redis://localhost:6379")
-        self.request_channel = "vagent:requests"
-        self.response_channel = "vagent:responses"
+        # A separate prefix keeps a shadow deployment's traffic apart
+        channel_prefix = os.getenv("GRAPH_CHANNEL_PREFIX") or "vagent"
+        self.request_channel = f"{channel_prefix}:requests"
+        self.response_channel = f"{channel_prefix}:responses"
         self.redis_client: redis.Redis | None = None
         self.pubsub: redis.client.PubSub | None = None
         self.agent: VerjiAgent | None = None